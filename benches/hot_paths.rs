@@ -0,0 +1,221 @@
+use std::{fs::File, hint::black_box, io::Write, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use polars::prelude::*;
+use rayon::prelude::*;
+use rs_gavisunk::{
+    get_kmers::get_kmer_counts_pos,
+    io::{Fasta, ScratchDir},
+    map_kmers::map_sunks_to_seq,
+    merge::concat_dfs,
+    sunk_graph::{
+        get_contig_sunk_graph_components, get_read_largest_sunk_graph_component, GraphParams,
+        DEFAULT_MIN_COMPONENT_SUNKS,
+    },
+    sunk_index::SunkHashIndex,
+    threads::{for_each_bounded, polars_threads},
+};
+
+const KMER_SIZE: usize = 20;
+const SEQ_LEN: usize = 100_000;
+
+/// Deterministic pseudo-random nucleotide sequence.
+fn random_seq(len: usize, seed: u64) -> Vec<u8> {
+    // xorshift64
+    let mut state = seed.max(1);
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            b"ACGT"[(state % 4) as usize]
+        })
+        .collect()
+}
+
+/// Write records to `{name}.fa` in `dir`.
+fn write_fasta(dir: &ScratchDir, name: &str, records: &[(&str, &[u8])]) -> PathBuf {
+    let path = dir.path().join(format!("{name}.fa"));
+    let mut fh = File::create(&path).unwrap();
+    for (rname, seq) in records {
+        writeln!(fh, ">{rname}").unwrap();
+        for line in seq.chunks(60) {
+            fh.write_all(line).unwrap();
+            writeln!(fh).unwrap();
+        }
+    }
+    path
+}
+
+/// SUNKs along a read with contig positions offset from read positions and a few bp of jitter.
+fn read_sunks(n_sunks: usize) -> DataFrame {
+    let offset = 50_000;
+    let rpos: Vec<i64> = (0..n_sunks as i64).map(|i| i * 100 + 1).collect();
+    let cpos: Vec<i64> = rpos
+        .iter()
+        .enumerate()
+        .map(|(i, pos)| offset + pos + (i as i64 % 5))
+        .collect();
+    DataFrame::new(vec![
+        Column::new("cpos".into(), cpos.clone()),
+        Column::new("rpos".into(), rpos),
+        Column::new("id".into(), cpos),
+        Column::new("strand".into(), vec!["+"; n_sunks]),
+    ])
+    .unwrap()
+}
+
+fn bench_kmer_counting(c: &mut Criterion) {
+    let seq = random_seq(SEQ_LEN, 1);
+    let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_bench_kmer_counting").unwrap();
+    let fa = write_fasta(&dir, "kmer_counting", &[("ctg", seq.as_slice())]);
+    let mut fh = Fasta::new(fa).unwrap();
+    c.bench_function("kmer_counting", |b| {
+        b.iter(|| {
+            get_kmer_counts_pos(black_box(&mut fh), "ctg", SEQ_LEN as u64, KMER_SIZE).unwrap()
+        })
+    });
+}
+
+fn bench_sunk_scanning(c: &mut Criterion) {
+    let seq = random_seq(SEQ_LEN, 2);
+    let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_bench_sunk_scanning").unwrap();
+    let fa = write_fasta(&dir, "sunk_scanning", &[("read", seq.as_slice())]);
+    // Half of SUNKs are in the read, half are absent.
+    let absent = random_seq(SEQ_LEN, 3);
+    let kmers: Vec<String> = (0..SEQ_LEN - KMER_SIZE)
+        .step_by(100)
+        .flat_map(|i| {
+            [&seq[i..i + KMER_SIZE], &absent[i..i + KMER_SIZE]]
+                .map(|kmer| String::from_utf8(kmer.to_vec()).unwrap())
+        })
+        .collect();
+    let sunks: Vec<&str> = kmers.iter().map(|kmer| kmer.as_str()).collect();
+    c.bench_function("sunk_scanning", |b| {
+        b.iter(|| map_sunks_to_seq(black_box(&sunks), &fa, "read", 1, SEQ_LEN as u64).unwrap())
+    });
+    let index = SunkHashIndex::new(&sunks).unwrap();
+    c.bench_function("sunk_hash_scanning", |b| {
+        b.iter(|| index.find(black_box(&seq)))
+    });
+}
+
+fn bench_pairwise_consistency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pairwise_consistency");
+    for n_sunks in [50, 200, 500] {
+        let df = read_sunks(n_sunks);
+        group.bench_with_input(BenchmarkId::from_parameter(n_sunks), &df, |b, df| {
+            b.iter(|| {
+                get_read_largest_sunk_graph_component(
+                    black_box(df),
+                    "read",
+                    &GraphParams::default(),
+                )
+                .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_component_extraction(c: &mut Criterion) {
+    // Overlapping reads each carrying 50 consecutive SUNKs.
+    let (mut rnames, mut ids): (Vec<String>, Vec<i64>) = (vec![], vec![]);
+    for read in 0..1_000 {
+        for sunk in 0..50 {
+            rnames.push(format!("read_{read}"));
+            ids.push((read * 10 + sunk) * 100);
+        }
+    }
+    c.bench_function("component_extraction", |b| {
+        b.iter(|| {
+            get_contig_sunk_graph_components(
+                "ctg",
+                black_box(&rnames),
+                &ids,
+                DEFAULT_MIN_COMPONENT_SUNKS,
+                None,
+            )
+            .unwrap()
+        })
+    });
+}
+
+/// Per-contig work mixing a multi-threaded polars query with a rayon parallel iterator, like the graph stage.
+fn contig_work(df: &DataFrame) -> usize {
+    let df_reads = df
+        .clone()
+        .lazy()
+        .group_by([col("read")])
+        .agg([
+            col("cpos").min().alias("st"),
+            col("cpos").max().alias("end"),
+        ])
+        .sort(["st"], Default::default())
+        .collect()
+        .unwrap();
+    let n_components: usize = df
+        .partition_by(["read"], false)
+        .unwrap()
+        .par_iter()
+        .map(|df_read| {
+            get_read_largest_sunk_graph_component(df_read, "read", &GraphParams::default())
+                .unwrap()
+                .map_or(0, |component| component.ids.len())
+        })
+        .sum();
+    df_reads.height() + n_components
+}
+
+fn bench_nested_parallelism(c: &mut Criterion) {
+    let threads = polars_threads();
+    // Contigs each with 100 reads of 50 SUNKs.
+    let dfs_ctg: Vec<DataFrame> = (0..threads.max(2) * 2)
+        .map(|_| {
+            let mut df_read = read_sunks(50);
+            let dfs: Vec<DataFrame> = (0..100)
+                .map(|read| {
+                    df_read
+                        .with_column(Column::new("read".into(), vec![format!("read_{read}"); 50]))
+                        .unwrap()
+                        .clone()
+                })
+                .collect();
+            concat_dfs(dfs, &df_read.schema()).unwrap()
+        })
+        .collect();
+    let mut group = c.benchmark_group("nested_parallelism");
+    group.sample_size(10);
+    group.bench_function("separate_pool", |b| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        b.iter(|| {
+            pool.install(|| {
+                dfs_ctg
+                    .par_iter()
+                    .map(|df| contig_work(black_box(df)))
+                    .sum::<usize>()
+            })
+        })
+    });
+    group.bench_function("polars_pool", |b| {
+        b.iter(|| {
+            for_each_bounded(&dfs_ctg, threads, |df| {
+                black_box(contig_work(black_box(df)));
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_kmer_counting,
+    bench_sunk_scanning,
+    bench_pairwise_consistency,
+    bench_component_extraction,
+    bench_nested_parallelism
+);
+criterion_main!(benches);
@@ -0,0 +1,235 @@
+//! Binary store of the anchors in each read's largest consistent SUNK component by contig.
+//!
+//! Finding each read's largest component checks every pair of its SUNKs and dominates the graph stage. Contig
+//! components only depend on these anchors and `--min-component-sunks`, so rerunning the graph stage with another
+//! threshold, ex. in a sweep, loads them instead.
+//!
+//! Stores are keyed by a hash of the contig, graph parameters affecting anchors, and the read SUNKs the anchors were
+//! built from. Stores with another key are recomputed and overwritten.
+//!
+//! Layout, little-endian: magic, format version `u32`, key `u64`, number of reads `u64`, then per read, name length
+//! `u32`, name, number of anchors `u32`, and each anchor's SUNK group, read position, projected contig position, and
+//! offset deviation as `i64`.
+
+use std::{
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use polars::prelude::*;
+
+use crate::{
+    error::{GavisunkError, Result},
+    naming::sanitize_filename,
+    sunk_graph::GraphParams,
+};
+
+/// Magic bytes at the start of an anchor store.
+const MAGIC: &[u8; 8] = b"GVANCHR\0";
+/// Version of the store layout.
+const FORMAT_VERSION: u32 = 1;
+
+/// Anchors of each read's largest consistent SUNK component on a contig, grouped by read.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReadAnchors {
+    pub rnames: Vec<String>,
+    /// SUNK groups.
+    pub ids: Vec<i64>,
+    pub rpos: Vec<i64>,
+    pub proj_cpos: Vec<i64>,
+    pub offset_devs: Vec<i64>,
+}
+
+/// Directory of anchor stores, one per contig.
+#[derive(Debug, Clone)]
+pub struct AnchorStore {
+    dir: PathBuf,
+}
+
+impl AnchorStore {
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_owned(),
+        })
+    }
+
+    /// Store of a contig. Contigs whose names sanitize to the same filename share a store, but the key includes the
+    /// contig so they never load each other's anchors.
+    pub fn path(&self, ctg: &str) -> PathBuf {
+        self.dir.join(format!("{}.anchors", sanitize_filename(ctg)))
+    }
+
+    /// Key of anchors built from read SUNKs with graph parameters.
+    ///
+    /// # Arguments
+    /// * `ctg`
+    ///     * Contig.
+    /// * `df_sunk_pos`
+    ///     * [`DataFrame`] of filtered read SUNKs the anchors are built from with columns `[read, rpos, cpos, id]`
+    /// * `params`
+    ///     * Graph parameters. `min_component_sunks` doesn't affect anchors and is ignored.
+    /// * `circular_len`
+    ///     * Length of the contig if circular. Anchors of circular contigs continue past the origin.
+    pub fn key(
+        ctg: &str,
+        df_sunk_pos: &DataFrame,
+        params: &GraphParams,
+        circular_len: Option<i64>,
+    ) -> Result<u64> {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        ctg.hash(&mut hasher);
+        circular_len.hash(&mut hasher);
+        format!("{:?}", params.multi_sunk).hash(&mut hasher);
+        params.dst_tolerance.to_bits().hash(&mut hasher);
+        params.max_read_sunks.hash(&mut hasher);
+        params.read_timeout.hash(&mut hasher);
+        for (((read, rpos), cpos), id) in df_sunk_pos
+            .column("read")?
+            .str()?
+            .iter()
+            .zip(df_sunk_pos.column("rpos")?.i64()?.iter())
+            .zip(df_sunk_pos.column("cpos")?.i64()?.iter())
+            .zip(df_sunk_pos.column("id")?.i64()?.iter())
+        {
+            (read, rpos, cpos, id).hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Load the anchors of a contig.
+    ///
+    /// # Returns
+    /// * [`None`] if there's no store or it has another key.
+    /// * [`GavisunkError::InvalidAnchorStore`] if the store is truncated or in another format.
+    pub fn load(&self, ctg: &str, key: u64) -> Result<Option<ReadAnchors>> {
+        let path = self.path(ctg);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let invalid = |reason: String| GavisunkError::InvalidAnchorStore {
+            path: path.clone(),
+            reason,
+        };
+        let mut reader = BufReader::new(File::open(&path)?);
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|err| invalid(err.to_string()))?;
+        if &magic != MAGIC {
+            return Err(invalid("not an anchor store".to_owned()));
+        }
+        let read_u32 = |reader: &mut BufReader<File>| -> Result<u32> {
+            let mut buf = [0; 4];
+            reader
+                .read_exact(&mut buf)
+                .map_err(|err| invalid(err.to_string()))?;
+            Ok(u32::from_le_bytes(buf))
+        };
+        let version = read_u32(&mut reader)?;
+        if version != FORMAT_VERSION {
+            log::info!("Anchor store {path:?} has format version {version}. Recomputing.");
+            return Ok(None);
+        }
+        let read_u64 = |reader: &mut BufReader<File>| -> Result<u64> {
+            let mut buf = [0; 8];
+            reader
+                .read_exact(&mut buf)
+                .map_err(|err| invalid(err.to_string()))?;
+            Ok(u64::from_le_bytes(buf))
+        };
+        if read_u64(&mut reader)? != key {
+            log::debug!(
+                "Anchor store {path:?} was built from other read SUNKs or parameters. Recomputing."
+            );
+            return Ok(None);
+        }
+
+        let mut anchors = ReadAnchors::default();
+        let n_reads = read_u64(&mut reader)?;
+        for _ in 0..n_reads {
+            let mut rname = vec![0; read_u32(&mut reader)? as usize];
+            reader
+                .read_exact(&mut rname)
+                .map_err(|err| invalid(err.to_string()))?;
+            let rname = String::from_utf8(rname).map_err(|err| invalid(err.to_string()))?;
+            let n_anchors = read_u32(&mut reader)? as usize;
+            for _ in 0..n_anchors {
+                anchors.ids.push(read_u64(&mut reader)? as i64);
+                anchors.rpos.push(read_u64(&mut reader)? as i64);
+                anchors.proj_cpos.push(read_u64(&mut reader)? as i64);
+                anchors.offset_devs.push(read_u64(&mut reader)? as i64);
+            }
+            anchors.rnames.extend(std::iter::repeat_n(rname, n_anchors));
+        }
+        Ok(Some(anchors))
+    }
+
+    /// Write the anchors of a contig, replacing any existing store.
+    pub fn write(&self, ctg: &str, key: u64, anchors: &ReadAnchors) -> Result<()> {
+        let path = self.path(ctg);
+        let mut tmp_fname = path.as_os_str().to_owned();
+        tmp_fname.push(format!(".{}.tmp", std::process::id()));
+        let tmp_path = PathBuf::from(tmp_fname);
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            writer.write_all(&key.to_le_bytes())?;
+            let reads = anchors.rnames.chunk_by(|a, b| a == b).collect::<Vec<_>>();
+            writer.write_all(&(reads.len() as u64).to_le_bytes())?;
+            let mut i = 0;
+            for read in reads {
+                let rname = read[0].as_bytes();
+                writer.write_all(&(rname.len() as u32).to_le_bytes())?;
+                writer.write_all(rname)?;
+                writer.write_all(&(read.len() as u32).to_le_bytes())?;
+                for j in i..i + read.len() {
+                    for value in [
+                        anchors.ids[j],
+                        anchors.rpos[j],
+                        anchors.proj_cpos[j],
+                        anchors.offset_devs[j],
+                    ] {
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                i += read.len();
+            }
+            writer.into_inner().map_err(|err| err.into_error())?;
+        }
+        std::fs::rename(&tmp_path, &path).map_err(|source| GavisunkError::Rename {
+            from: tmp_path,
+            to: path,
+            source,
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AnchorStore, ReadAnchors};
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_anchor_store_roundtrip() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_anchors").unwrap();
+        let store = AnchorStore::new(dir.path()).unwrap();
+        let anchors = ReadAnchors {
+            rnames: ["r1", "r1", "r2"].map(str::to_owned).to_vec(),
+            ids: vec![100, 200, 100],
+            rpos: vec![1, 101, 5000],
+            proj_cpos: vec![100, 200, 100],
+            offset_devs: vec![0, -1, 3],
+        };
+        assert_eq!(store.load("chr1/a", 1).unwrap(), None);
+        store.write("chr1/a", 1, &anchors).unwrap();
+        assert_eq!(store.load("chr1/a", 1).unwrap(), Some(anchors));
+        // Another key is recomputed.
+        assert_eq!(store.load("chr1/a", 2).unwrap(), None);
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use polars::prelude::*;
+
+use crate::{
+    error::Result,
+    io::{write_atomic, write_tsv_to},
+    naming::ContigFilenames,
+    writers::{BedWriter, OutputWriter},
+};
+
+/// Name of index manifest stored as the first entry of the archive.
+pub const ARCHIVE_INDEX: &str = "index.tsv";
+
+fn append_bytes<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    data: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Write per-contig graph stage results into a single zstd-compressed tar archive.
+///
+/// Each contig gets `{fname}/sunks.tsv` and `{fname}/supported.bed` entries where `fname` is its sanitized name.
+/// An index manifest, [`ARCHIVE_INDEX`], with columns `[ctg, sunks, bed, sunks_rows, bed_rows]` is written first.
+///
+/// # Arguments
+/// * `path`
+///     * Output archive path. ex. `contigs.tar.zst`
+/// * `results`
+///     * Per-contig `(ctg, df_sunks, df_bed)`.
+/// * `fnames`
+///     * Filesystem-safe contig names used for entry paths.
+pub fn write_archive(
+    path: impl AsRef<Path>,
+    results: &mut [(String, DataFrame, DataFrame)],
+    fnames: &ContigFilenames,
+) -> Result<()> {
+    let (mut ctgs, mut sunks_paths, mut bed_paths, mut sunks_rows, mut bed_rows) =
+        (vec![], vec![], vec![], vec![], vec![]);
+    for (ctg, df_sunks, df_bed) in results.iter() {
+        ctgs.push(ctg.as_str());
+        let fname = fnames.get(ctg);
+        sunks_paths.push(format!("{fname}/sunks.tsv"));
+        bed_paths.push(format!("{fname}/supported.bed"));
+        sunks_rows.push(df_sunks.height() as u64);
+        bed_rows.push(df_bed.height() as u64);
+    }
+    let mut df_index = DataFrame::new(vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("sunks".into(), sunks_paths.clone()),
+        Column::new("bed".into(), bed_paths.clone()),
+        Column::new("sunks_rows".into(), sunks_rows),
+        Column::new("bed_rows".into(), bed_rows),
+    ])?;
+
+    write_atomic(path, |file| {
+        let encoder = zstd::Encoder::new(file, 0)?;
+        let mut builder = tar::Builder::new(encoder);
+
+        let mut buf = vec![];
+        write_tsv_to(&mut df_index, &mut buf)?;
+        append_bytes(&mut builder, ARCHIVE_INDEX, &buf)?;
+
+        for ((_, df_sunks, df_bed), (sunks_path, bed_path)) in results
+            .iter_mut()
+            .zip(sunks_paths.iter().zip(bed_paths.iter()))
+        {
+            buf.clear();
+            write_tsv_to(df_sunks, &mut buf)?;
+            append_bytes(&mut builder, sunks_path, &buf)?;
+
+            buf.clear();
+            BedWriter.write(df_bed, &mut buf, "")?;
+            append_bytes(&mut builder, bed_path, &buf)?;
+        }
+        builder.into_inner()?.finish()?;
+        Ok(())
+    })
+}
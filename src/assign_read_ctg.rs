@@ -1,8 +1,29 @@
 use polars::prelude::*;
 
-const DEFAULT_BANDWIDTH: (f64, f64) = (0.25, 0.75);
+use crate::{
+    error::{GavisunkError, Result},
+    filter_audit::{self, READS, SUNK_HITS},
+    hooks::Stage,
+    orientation::{infer_orientation, Orientation},
+    run_state::RunState,
+};
+
+pub const DEFAULT_BANDWIDTH: (f64, f64) = (0.25, 0.75);
 const DEFAULT_GOOD_SUNK_THR: u64 = 1;
 
+/// Parse a bandwidth of lower and upper percentiles. ex. `0.25:0.75`
+pub fn parse_bandwidth(bandwidth: &str) -> Result<(f64, f64)> {
+    let err = || GavisunkError::InvalidBandwidth(bandwidth.to_owned());
+    let (lower, upper) = bandwidth.split_once(':').ok_or_else(err)?;
+    let (Ok(lower), Ok(upper)) = (lower.trim().parse::<f64>(), upper.trim().parse::<f64>()) else {
+        return Err(err());
+    };
+    if !(0.0..=1.0).contains(&lower) || !(0.0..=1.0).contains(&upper) || lower >= upper {
+        return Err(err());
+    }
+    Ok((lower, upper))
+}
+
 /// Determine which read best matches a given contig based on mapped SUNK position and determine its orientation.
 ///
 /// * From Bioinformatics paper:
@@ -11,6 +32,8 @@ const DEFAULT_GOOD_SUNK_THR: u64 = 1;
 /// > orientation by comparing the locations of read SUNKs to assembly SUNKs
 /// > within a diagonal band centered on the median SUNK location for that read
 ///
+/// Orientation on each contig is inferred from the read's SUNK anchors with [`infer_orientation`].
+///
 /// # Arguments
 /// * `df_read_sunk_pos`
 ///     * [`DataFrame`] of read SUNK positions with columns: `[read, rpos, chrom, cpos]`
@@ -20,6 +43,8 @@ const DEFAULT_GOOD_SUNK_THR: u64 = 1;
 ///     * **A 'good' SUNK is one within this band.**
 /// * `good_sunk_threshold`
 ///     * Number of 'good' SUNKs required to not filter read.
+/// * `state`
+///     * Run the reads and SUNKs kept by each filter are recorded in. See [`filter_audit::record`].
 ///
 /// # Returns
 /// * [`DataFrame`] of reads assigned to contigs and their orientation.
@@ -28,7 +53,8 @@ pub fn assign_read_to_ctg_w_ort(
     df_read_sunk_pos: &DataFrame,
     perc_pos_bounds: Option<(f64, f64)>,
     good_sunk_threshold: Option<u64>,
-) -> eyre::Result<DataFrame> {
+    state: &RunState,
+) -> Result<DataFrame> {
     let (lower_bound, upper_bound) = perc_pos_bounds.unwrap_or(DEFAULT_BANDWIDTH);
     let good_sunk_threshold = good_sunk_threshold.unwrap_or(DEFAULT_GOOD_SUNK_THR);
     let lf_read_sunk_pos = df_read_sunk_pos.clone().lazy();
@@ -36,45 +62,53 @@ pub fn assign_read_to_ctg_w_ort(
     log::info!("Filtering SUNKs with an adjusted position in {lower_bound} percentile and {upper_bound} percentile.");
     log::info!("Requiring a read to have at least {good_sunk_threshold} SUNK(s) within bandwidth.");
 
-    let lf_ort = df_read_sunk_pos
+    let df_anchors = df_read_sunk_pos
         .select(["read", "ctg", "cpos", "rpos"])?
         .lazy()
         // Filter reads with only sunk over read and chrom.
         .filter(col("read").len().over(["read", "ctg"]).gt(lit(1)))
         .group_by(["read", "ctg"])
         .agg([
-            // Calculate a 1D gradient to get direction of sunks in chrom.
-            // ex. [1, 3, 6, 7] => [ 2,  3,  1] => mean is 2 => + (ascending)
-            // ex. [9, 7, 3, 1] => [ -2, -4, -2] => mean is -2.67  => - (descending)
-            //
-            // Similar to np.gradient but without resulting same length vec.
-            // * See here for more https://stackoverflow.com/a/24633888
-            (col("cpos") - col("cpos").shift(lit(1)))
-                .drop_nulls()
-                .mean()
-                .gt(lit(0))
-                .alias("cort"),
-            (col("rpos") - col("rpos").shift(lit(1)))
-                .drop_nulls()
-                .mean()
-                .gt(lit(0))
-                .alias("rort"),
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
         ])
-        .with_column(
-            // Final read ort
-            // Both directions equal, read is + ort. Otherwise, - ort.
-            when(col("rort").and(col("cort")))
-                .then(lit("+"))
-                .otherwise(lit("-"))
-                .alias("ort"),
-        );
+        .collect()?;
+    let (mut reads, mut ctgs, mut orts) = (vec![], vec![], vec![]);
+    for (((read, ctg), rpos), cpos) in df_anchors
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_anchors.column("ctg")?.str()?.iter())
+        .zip(df_anchors.column("rpos")?.list()?.into_iter())
+        .zip(df_anchors.column("cpos")?.list()?.into_iter())
+    {
+        let (Some(read), Some(ctg), Some(rpos), Some(cpos)) = (read, ctg, rpos, cpos) else {
+            continue;
+        };
+        let anchors: Vec<(i64, i64)> = rpos
+            .i64()?
+            .iter()
+            .zip(cpos.i64()?.iter())
+            .flat_map(|(rpos, cpos)| rpos.zip(cpos))
+            .collect();
+        // No direction if all SUNKs share a read or contig position.
+        let ort = infer_orientation(&anchors).map_or(Orientation::Reverse, |(ort, _)| ort);
+        reads.push(read.to_owned());
+        ctgs.push(ctg.to_owned());
+        orts.push(ort.as_str());
+    }
+    let df_ort = DataFrame::new(vec![
+        Column::new("read".into(), reads),
+        Column::new("ctg".into(), ctgs),
+        Column::new("ort".into(), orts),
+    ])?;
 
-    let df = lf_read_sunk_pos
+    let df_banded = lf_read_sunk_pos
         // Filter reads with only sunk over read and chrom.
         .filter(col("read").len().over(["read", "ctg"]).gt(lit(1)))
         // Add orientation.
         .join(
-            lf_ort.lazy(),
+            df_ort.lazy(),
             [col("read"), col("ctg")],
             [col("read"), col("ctg")],
             JoinArgs::new(JoinType::Left),
@@ -107,6 +141,37 @@ pub fn assign_read_to_ctg_w_ort(
                 .over(["read", "ctg"])
                 .alias("sunks_within_bandwidth"),
         )
+        .collect()?;
+    filter_audit::record(
+        Stage::Assign,
+        "multi_sunk",
+        SUNK_HITS,
+        df_read_sunk_pos.height(),
+        df_banded.height(),
+        state,
+    );
+    let n_within_bandwidth = df_banded
+        .clone()
+        .lazy()
+        .group_by([col("read"), col("ctg")])
+        .agg([col("sunks_within_bandwidth").first()])
+        .select([col("sunks_within_bandwidth").cast(DataType::UInt64).sum()])
+        .collect()?
+        .column("sunks_within_bandwidth")?
+        .u64()?
+        .get(0)
+        .unwrap_or_default();
+    filter_audit::record(
+        Stage::Assign,
+        "bandwidth",
+        SUNK_HITS,
+        df_banded.height(),
+        n_within_bandwidth as usize,
+        state,
+    );
+
+    let df_best = df_banded
+        .lazy()
         // Choose based on maximum number of sunks within bandwidth.
         .filter(
             col("sunks_within_bandwidth")
@@ -116,6 +181,10 @@ pub fn assign_read_to_ctg_w_ort(
         .group_by(["read"])
         // Resolve ties by taking just first row.
         .agg([all().first()])
+        .collect()?;
+    let n_best = df_best.height();
+    let df = df_best
+        .lazy()
         // Filter reads with only n good sunks
         .filter(col("sunks_within_bandwidth").gt(good_sunk_threshold))
         .select([
@@ -125,6 +194,14 @@ pub fn assign_read_to_ctg_w_ort(
             col("ort"),
         ])
         .collect()?;
+    filter_audit::record(
+        Stage::Assign,
+        "good_sunk_threshold",
+        READS,
+        n_best,
+        df.height(),
+        state,
+    );
 
     log::info!("Total number of valid reads: {}", df.shape().0);
     Ok(df)
@@ -0,0 +1,52 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::error::Result;
+
+/// Error returned by long-running operations that were stopped via a [`CancelToken`].
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation cancelled.")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Cooperative cancellation flag shared between the caller and running stages.
+///
+/// Cloning the token shares the underlying flag. Stages check it between contigs and reads
+/// and return [`Cancelled`] so partial work can be flushed instead of killing the process mid-write.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. All clones of this token will observe it.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Returns [`Cancelled`] as an error if cancellation was requested.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,452 @@
+//! Support of the origin of circular contigs, ex. mitochondria or plasmids.
+//!
+//! A circular contig is linearized at an arbitrary origin, so reads spanning it have SUNKs near both contig ends. Their
+//! read distances only agree with the distance around the origin, not the contig distance, so the contig's ends would
+//! be falsely unsupported. Contig positions are instead continued past the contig end in read order, see
+//! [`unwrap_origin`], so SUNK graph components can cross the origin. Reads with consistently spaced SUNKs across the
+//! origin also support the junction between the ends on their own.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{
+    error::{GavisunkError, Result},
+    get_kmers::sunk_id,
+    naming::normalize_name,
+    orientation::infer_orientation,
+    ratio,
+};
+
+/// Minimum reads spanning the origin of a circular contig for its junction to be supported.
+pub const MIN_ORIGIN_READS: usize = 2;
+
+/// Load circular contigs from a GFA of the assembly.
+///
+/// A segment with a link from its end to its own start, ex. `L\tctg1\t+\tctg1\t+\t0M`, is circular.
+///
+/// # Arguments
+/// * `path`
+///     * GFA (v1) of the assembly. ex. from hifiasm or Flye
+///
+/// # Returns
+/// * Circular contigs. Names are normalized with [`normalize_name`].
+pub fn load_gfa_circular(path: impl AsRef<Path>) -> Result<HashSet<String>> {
+    let mut circular = HashSet::new();
+    let reader = BufReader::new(File::open(path)?);
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        let Some(fields) = line.strip_prefix("L\t") else {
+            continue;
+        };
+        let [from, from_ort, to, to_ort, ..] = fields.split('\t').collect::<Vec<_>>()[..] else {
+            return Err(GavisunkError::InvalidGfa(format!(
+                "line {}: expected at least 5 tab-separated columns in link",
+                line_num + 1
+            )));
+        };
+        if from == to && from_ort == to_ort {
+            circular.insert(normalize_name(from).to_owned());
+        }
+    }
+    Ok(circular)
+}
+
+/// Continue contig positions of a read's SUNKs past the origin of a circular contig.
+///
+/// SUNKs are walked in read order. Where the contig position jumps backwards by more than half the contig, the read
+/// crossed the origin going forward, and the contig length is added to the following positions. Jumps forwards by
+/// more than half the contig are reads crossing it in reverse. Positions are then shifted by whole contig lengths so
+/// the lowest is on the contig.
+///
+/// # Arguments
+/// * `anchors`
+///     * `(rpos, cpos)` of each SUNK of a read.
+/// * `ctg_len`
+///     * Contig length.
+///
+/// # Returns
+/// * Unwrapped contig position of each anchor in the same order. Positions past `ctg_len` are after the origin.
+pub fn unwrap_origin(anchors: &[(i64, i64)], ctg_len: i64) -> Vec<i64> {
+    let mut order: Vec<usize> = (0..anchors.len()).collect();
+    order.sort_unstable_by_key(|i| anchors[*i]);
+    let half = ctg_len / 2;
+    let mut unwrapped = vec![0; anchors.len()];
+    let (mut offset, mut prev_cpos) = (0, None);
+    for i in order {
+        let cpos = anchors[i].1;
+        match prev_cpos.map(|prev| cpos - prev) {
+            Some(step) if step < -half => offset += ctg_len,
+            Some(step) if step > half => offset -= ctg_len,
+            _ => {}
+        }
+        unwrapped[i] = cpos + offset;
+        prev_cpos = Some(cpos);
+    }
+    if let Some(shift) = unwrapped
+        .iter()
+        .min()
+        .filter(|_| ctg_len > 0)
+        .map(|min_cpos| min_cpos.div_euclid(ctg_len) * ctg_len)
+    {
+        unwrapped.iter_mut().for_each(|cpos| *cpos -= shift);
+    }
+    unwrapped
+}
+
+/// Reads spanning the origin of circular contigs.
+///
+/// A read spans the origin if its good SUNKs continue past the contig end when unwrapped with [`unwrap_origin`] and
+/// the read distance between each pair of consecutive SUNKs is within `dst_tolerance` of their contig distance.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, group, ...]`
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNKs with columns `[id, ...]`. Their hits are ignored.
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `circular`
+///     * Circular contigs.
+/// * `dst_tolerance`
+///     * Allowed relative difference between the read and contig distance. Exclusive. See
+///       [`ratio::within_tolerance`].
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[read, ctg, st, end, sunks, ort]` sorted by contig and read.
+///     * `st` is the position of the read's first SUNK before the origin and `end` of its last after it. The read
+///       supports `[st, len)` and `[0, end)`.
+///     * `sunks` is the number of good SUNKs of the contig in the read.
+pub fn origin_spanning_reads(
+    df_read_sunks: &DataFrame,
+    df_bad_sunks: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    circular: &HashSet<String>,
+    dst_tolerance: f32,
+) -> Result<DataFrame> {
+    let bad: HashSet<&str> = df_bad_sunks.column("id")?.str()?.iter().flatten().collect();
+    let rpos = df_read_sunks.column("rpos")?.cast(&DataType::Int64)?;
+    let cpos = df_read_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let group = df_read_sunks.column("group")?.cast(&DataType::Int64)?;
+    let mut anchors: HashMap<(&str, &str), Vec<(i64, i64)>> = HashMap::new();
+    for ((((read, rpos), ctg), cpos), group) in df_read_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(rpos.i64()?.iter())
+        .zip(df_read_sunks.column("ctg")?.str()?.iter())
+        .zip(cpos.i64()?.iter())
+        .zip(group.i64()?.iter())
+    {
+        let (Some(read), Some(rpos), Some(ctg), Some(cpos), Some(group)) =
+            (read, rpos, ctg, cpos, group)
+        else {
+            continue;
+        };
+        if !circular.contains(ctg) || bad.contains(sunk_id(ctg, group).as_str()) {
+            continue;
+        }
+        anchors.entry((read, ctg)).or_default().push((rpos, cpos));
+    }
+
+    let tolerance_ppm = ratio::to_ppm(f64::from(dst_tolerance));
+    let mut rows = vec![];
+    for ((read, ctg), anchors) in anchors {
+        let Some(ctg_len) = asm_lens.get(ctg).map(|len| *len as i64) else {
+            continue;
+        };
+        let unwrapped = unwrap_origin(&anchors, ctg_len);
+        // Only reads continuing past the contig end cross the origin.
+        if unwrapped.iter().all(|cpos| *cpos < ctg_len) {
+            continue;
+        }
+        let mut anchors: Vec<(i64, i64)> = anchors
+            .iter()
+            .zip(unwrapped)
+            .map(|((rpos, _), cpos)| (*rpos, cpos))
+            .collect();
+        let Some((ort, _)) = infer_orientation(&anchors) else {
+            continue;
+        };
+        anchors.sort_unstable_by_key(|(rpos, cpos)| (*cpos, *rpos));
+        anchors.dedup_by_key(|(_, cpos)| *cpos);
+        // A single misplaced SUNK can't hide between consistent outermost SUNKs.
+        let sign = if ort.is_rev() { -1 } else { 1 };
+        if !anchors.windows(2).all(|pair| {
+            ratio::within_tolerance(
+                sign * (pair[1].0 - pair[0].0),
+                pair[1].1 - pair[0].1,
+                tolerance_ppm,
+            )
+        }) {
+            continue;
+        }
+        let (first_cpos, last_cpos) = (anchors[0].1, anchors[anchors.len() - 1].1);
+        rows.push((
+            ctg,
+            read,
+            first_cpos,
+            (last_cpos - ctg_len).min(ctg_len),
+            anchors.len() as u64,
+            ort,
+        ));
+    }
+    rows.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+    Ok(DataFrame::new(vec![
+        Column::new(
+            "read".into(),
+            rows.iter().map(|row| row.1).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "ctg".into(),
+            rows.iter().map(|row| row.0).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "st".into(),
+            rows.iter().map(|row| row.2).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "end".into(),
+            rows.iter().map(|row| row.3).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "sunks".into(),
+            rows.iter().map(|row| row.4).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "ort".into(),
+            rows.iter().map(|row| row.5.as_str()).collect::<Vec<_>>(),
+        ),
+    ])?)
+}
+
+/// Junctions across the origin of circular contigs covered by at least `min_reads` spanning reads at each position.
+///
+/// # Arguments
+/// * `df_origin_reads`
+///     * [`DataFrame`] of reads spanning the origin of circular contigs. See [`origin_spanning_reads`].
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `min_reads`
+///     * Minimum spanning reads at each position of the junction. ex. [`MIN_ORIGIN_READS`]
+///
+/// # Returns
+/// * Supported junction, `st` and `end` around the origin as in [`origin_spanning_reads`], and the most SUNKs in any
+///   spanning read by contig. Contigs without `min_reads` reads at the origin are omitted.
+pub fn origin_support(
+    df_origin_reads: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    min_reads: usize,
+) -> Result<HashMap<String, (i64, i64, u64)>> {
+    // Start and end of each read past the contig end, as from unwrap_origin, and the most SUNKs in a read.
+    let mut spans: HashMap<&str, (Vec<(i64, i64)>, u64)> = HashMap::new();
+    for (((ctg, st), end), sunks) in df_origin_reads
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_origin_reads.column("st")?.i64()?.iter())
+        .zip(df_origin_reads.column("end")?.i64()?.iter())
+        .zip(df_origin_reads.column("sunks")?.u64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end), Some(sunks)) = (ctg, st, end, sunks) else {
+            continue;
+        };
+        let Some(ctg_len) = asm_lens.get(ctg).map(|len| *len as i64) else {
+            continue;
+        };
+        let (ctg_spans, max_sunks) = spans.entry(ctg).or_default();
+        ctg_spans.push((st, end + ctg_len));
+        *max_sunks = (*max_sunks).max(sunks);
+    }
+
+    let mut support = HashMap::new();
+    for (ctg, (ctg_spans, max_sunks)) in spans {
+        let ctg_len = asm_lens[ctg] as i64;
+        // Depth changes by position. Changes at the same position are applied together.
+        let mut changes: Vec<(i64, i64)> = ctg_spans
+            .iter()
+            .flat_map(|(st, end)| [(*st, 1), (*end, -1)])
+            .collect();
+        changes.sort_unstable();
+        let (mut depth, mut run_st) = (0, None);
+        for (pos, pos_changes) in &changes.iter().chunk_by(|(pos, _)| *pos) {
+            depth += pos_changes.map(|(_, change)| change).sum::<i64>();
+            match run_st {
+                None if depth >= min_reads as i64 => run_st = Some(pos),
+                Some(st) if depth < min_reads as i64 => {
+                    if st < ctg_len && pos > ctg_len {
+                        support.insert(ctg.to_owned(), (st, pos - ctg_len, max_sunks));
+                        break;
+                    }
+                    run_st = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(support)
+}
+
+/// Supported intervals on either side of the origin with columns `[ctg, st, end, sunks]`, as from
+/// [`crate::sunk_graph::create_sunk_graph`].
+pub fn origin_bed(ctg: &str, ctg_len: u64, st: i64, end: i64, sunks: u64) -> Result<DataFrame> {
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), [ctg, ctg]),
+        Column::new("st".into(), [st, 0]),
+        Column::new("end".into(), [ctg_len as i64, end]),
+        Column::new("sunks".into(), [sunks, sunks]),
+    ])?)
+}
+
+/// Split intervals continuing past the end of a circular contig at its origin.
+///
+/// # Arguments
+/// * `df_bed`
+///     * [`DataFrame`] of intervals with columns `[ctg, st, end, sunks]`. See
+///       [`crate::sunk_graph::create_sunk_graph_with_store`].
+/// * `ctg_len`
+///     * Contig length.
+///
+/// # Returns
+/// * Intervals on the contig sorted by start. An interval with `end` past `ctg_len` is split into `[st, ctg_len)` and
+///   `[0, end - ctg_len)`. See [`origin_bed`].
+/// * Whether any interval crossed the origin.
+pub fn split_origin(df_bed: &DataFrame, ctg_len: u64) -> Result<(DataFrame, bool)> {
+    let len = ctg_len as i64;
+    let lf_bed = df_bed.clone().lazy();
+    let df_wrapped = lf_bed
+        .clone()
+        .filter(col("end").gt(lit(len)))
+        .with_columns([lit(0i64).alias("st"), (col("end") - lit(len)).alias("end")])
+        .collect()?;
+    let df_split = concat(
+        [
+            lf_bed.with_column(
+                when(col("end").gt(lit(len)))
+                    .then(lit(len))
+                    .otherwise(col("end"))
+                    .alias("end"),
+            ),
+            df_wrapped.clone().lazy(),
+        ],
+        UnionArgs::default(),
+    )?
+    .sort(["st"], Default::default())
+    .collect()?;
+    Ok((df_split, df_wrapped.height() > 0))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+
+    use polars::prelude::*;
+
+    use super::{
+        origin_spanning_reads, origin_support, split_origin, unwrap_origin, MIN_ORIGIN_READS,
+    };
+
+    #[test]
+    fn test_unwrap_origin() {
+        // Forward and reverse across the origin, and forward across more than half the contig.
+        assert_eq!(
+            unwrap_origin(&[(1_001, 19_001), (1_501, 19_501), (2_501, 501)], 20_000),
+            [19_001, 19_501, 20_501]
+        );
+        assert_eq!(
+            unwrap_origin(&[(5_001, 19_201), (3_901, 301)], 20_000),
+            [19_201, 20_301]
+        );
+        assert_eq!(
+            unwrap_origin(&[(1_001, 5_001), (11_001, 15_001), (16_501, 501)], 20_000),
+            [5_001, 15_001, 20_501]
+        );
+        assert_eq!(
+            unwrap_origin(&[(101, 5_001), (1_101, 6_001)], 20_000),
+            [5_001, 6_001]
+        );
+    }
+
+    #[test]
+    fn test_origin_spanning_reads() {
+        // r1 and r2 span the origin of the 20 kbp contig in either orientation and r6 spans it over more than half the
+        // contig. r3 doesn't reach it, r4's SUNKs are too far apart, r5's contig isn't circular, and r7's middle SUNK
+        // is misplaced even though its outermost SUNKs agree.
+        let df_read_sunks = df!(
+            "read" => [
+                "r1", "r1", "r1", "r2", "r2", "r3", "r3", "r4", "r4", "r5", "r5", "r6", "r6", "r6", "r7", "r7", "r7",
+            ],
+            "rpos" => [
+                1_001i64, 1_501, 2_501, 5_001, 3_901, 101, 1_101, 1_001, 9_001, 1_001, 2_001, 1_001, 11_001, 16_501,
+                1_001, 1_501, 2_501,
+            ],
+            "ctg" => [
+                "mt", "mt", "mt", "mt", "mt", "mt", "mt", "mt", "mt", "ctg1", "ctg1", "mt", "mt", "mt", "mt", "mt",
+                "mt",
+            ],
+            "cpos" => [
+                19_001i64, 19_501, 501, 19_201, 301, 5_001, 6_001, 19_001, 1_001, 19_001, 501, 5_001, 15_001, 501,
+                19_001, 19_900, 501,
+            ],
+            "group" => [1i64, 2, 3, 4, 5, 6, 7, 1, 8, 1, 2, 10, 11, 12, 13, 14, 15],
+        )
+        .unwrap();
+        let df_bad_sunks = df!("id" => ["mt:9"]).unwrap();
+        let asm_lens = HashMap::from([("mt".to_owned(), 20_000), ("ctg1".to_owned(), 20_000)]);
+        let circular = HashSet::from(["mt".to_owned()]);
+        let df = origin_spanning_reads(&df_read_sunks, &df_bad_sunks, &asm_lens, &circular, 0.1)
+            .unwrap();
+        assert_eq!(
+            df,
+            df!(
+                "read" => ["r1", "r2", "r6"],
+                "ctg" => ["mt", "mt", "mt"],
+                "st" => [19_001i64, 19_201, 5_001],
+                "end" => [501i64, 301, 501],
+                "sunks" => [3u64, 2, 3],
+                "ort" => ["+", "-", "+"],
+            )
+            .unwrap()
+        );
+        // Only [19201, 20000) and [0, 301) are covered by all three reads.
+        assert_eq!(
+            origin_support(&df, &asm_lens, MIN_ORIGIN_READS).unwrap(),
+            HashMap::from([("mt".to_owned(), (19_001, 501, 3))])
+        );
+        assert_eq!(
+            origin_support(&df, &asm_lens, 3).unwrap(),
+            HashMap::from([("mt".to_owned(), (19_201, 301, 3))])
+        );
+        assert!(origin_support(&df, &asm_lens, 4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_origin() {
+        let df_bed = df!(
+            "ctg" => ["mt", "mt"],
+            "st" => [1_001i64, 15_001],
+            "end" => [9_001i64, 20_501],
+            "sunks" => [4u64, 5],
+        )
+        .unwrap();
+        let (df_split, wrapped) = split_origin(&df_bed, 20_000).unwrap();
+        assert!(wrapped);
+        assert_eq!(
+            df_split,
+            df!(
+                "ctg" => ["mt", "mt", "mt"],
+                "st" => [0i64, 1_001, 15_001],
+                "end" => [501i64, 9_001, 20_000],
+                "sunks" => [5u64, 4, 5],
+            )
+            .unwrap()
+        );
+    }
+}
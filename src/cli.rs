@@ -0,0 +1,570 @@
+use std::path::{Path, PathBuf};
+
+use clap::{error::ErrorKind, CommandFactory, Parser};
+#[cfg(feature = "graph")]
+use clap::{Args, Subcommand};
+
+#[cfg(feature = "graph")]
+use rs_gavisunk::{
+    assign_read_ctg::parse_bandwidth,
+    curation::DEFAULT_MIN_EVENT_SIZE,
+    intervals::parse_region,
+    read_trims::DEFAULT_MIN_TRIM_SUNKS,
+    remote_map::DEFAULT_LISTEN_ADDR,
+    sunk_graph::{
+        parse_dst_tolerance, MultiSunkStrategy, DEFAULT_DST_TOLERANCE, DEFAULT_MAX_READ_SUNKS,
+        DEFAULT_MIN_COMPONENT_SUNKS, DEFAULT_READ_TIMEOUT,
+    },
+    titration::{parse_fraction, DEFAULT_FRACTIONS},
+};
+use rs_gavisunk::{
+    get_kmers::DEFAULT_KMER_SIZE,
+    hooks::Stage,
+    io::{EmptyIntermediate, DEFAULT_MAX_MALFORMED_RECORDS},
+    joins::DEFAULT_MAX_JOIN_LOSS,
+    memory::parse_mem_size,
+    sunk_context::DEFAULT_GAP_FLANK,
+};
+#[cfg(feature = "mapping")]
+use rs_gavisunk::{
+    inversions::{DEFAULT_BREAKPOINT_SLOP, DEFAULT_MIN_RUN_SUNKS},
+    map_kmers::DedupPolicy,
+    spacing::DEFAULT_SPACING_TOLERANCE,
+    translocations::{
+        DEFAULT_MIN_PHASE_SWITCH_SUNKS, DEFAULT_MIN_TRANSLOCATION_READS,
+        DEFAULT_MIN_TRANSLOCATION_SUNKS,
+    },
+};
+
+/// Validate assembly contigs with SUNKs (singly unique nucleotide k-mers) found in ONT reads.
+#[derive(Parser, Debug)]
+// Subcommands check for inputs they need with `Cli::inputs`.
+#[command(version, about, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[cfg(feature = "graph")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Assembly FASTA. Plain, bgzipped, or gzipped with `--allow-recompress`.
+    #[arg(long, value_name = "FASTA", required = true)]
+    pub assembly: Option<PathBuf>,
+
+    /// ONT reads FASTA or FASTQ. Plain or bgzipped. FASTQ, optionally gzipped, is converted to a bgzipped FASTA,
+    /// `converted_{name}.{hash}.fa.gz` in `--outdir`. Unaligned BAM or CRAM with the `bam` feature is mapped
+    /// straight from its records.
+    #[arg(long, value_name = "FASTX", required = true)]
+    pub reads: Option<PathBuf>,
+
+    /// Kmer size of SUNKs, up to 32. A kmer and its reverse complement are the same SUNK.
+    /// SUNKs over 31 bp can't be packed into the hash index or `--sunk-index`, so each read is searched for every SUNK.
+    #[arg(long, default_value_t = DEFAULT_KMER_SIZE, value_parser = clap::value_parser!(usize).range(1..=32))]
+    pub kmer_size: usize,
+
+    /// Directory of outputs and cached intermediates. Created if missing.
+    /// Relative paths of other options, like outputs, are within it unless they're inputs to the run, ex. `--reads`.
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub outdir: PathBuf,
+
+    /// Also write per-contig `{ctg}_sunks.tsv` and `{ctg}.bed` outputs.
+    /// Contig names are made path-safe. See `contig_filenames.tsv` for the mapping.
+    #[cfg(feature = "graph")]
+    #[arg(long)]
+    pub per_contig: bool,
+
+    /// Write per-contig outputs into a single tar.zst archive with an index manifest.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_name = "TAR_ZST")]
+    pub archive: Option<PathBuf>,
+
+    /// Directory of per-contig stores of each read's anchors in its largest consistent SUNK component. Reruns of the
+    /// graph stage and `sweep` with the same read SUNKs, `--dst-tolerance`, and `--multi-sunk` load them instead of
+    /// recomputing, ex. to try other `--min-component-sunks`.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_name = "DIR")]
+    pub anchor_store: Option<PathBuf>,
+
+    /// Exit after this stage with its outputs finalized. ex. `assign` for the SUNK table and read assignments only.
+    #[arg(long, value_enum)]
+    pub stop_after: Option<Stage>,
+
+    /// TSV of contig `name` and `new_name` applied when loading the assembly and reversed in outputs.
+    /// `name` may be a full FASTA header with a description.
+    #[arg(long, value_name = "TSV")]
+    pub rename_map: Option<PathBuf>,
+
+    /// Also write `manifest.json` listing each output file with its role, schema, row count, and checksum.
+    #[arg(long)]
+    pub manifest: bool,
+
+    /// Regenerate cached intermediates, ex. `asm_sunks.tsv`, even if they exist.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Also start final outputs with `#` comment lines of the date and input checksums after the parameters.
+    #[arg(long)]
+    pub comment_headers: bool,
+
+    /// Comma-separated formats also written for each output as `{output}.{extension}`, ex. `supported.bed.parquet`.
+    /// One of `tsv`, `bed`, `paf`, `json`, `ndjson`, or, with the `parquet` feature, `parquet`. Outputs are otherwise
+    /// written in the format of their extension.
+    #[arg(long, value_delimiter = ',', value_name = "FORMATS")]
+    pub output_formats: Vec<String>,
+
+    /// Warn if a join loses more than this fraction of its rows, ex. from reads missing from the reads FASTA.
+    #[arg(long, default_value_t = DEFAULT_MAX_JOIN_LOSS)]
+    pub max_join_loss: f64,
+
+    /// Write bgzipped copies of plain gzipped FASTA inputs, `recompressed_{name}`, so they can be indexed.
+    /// Otherwise, a plain gzipped assembly is loaded into memory and plain gzipped reads are an error.
+    #[arg(long)]
+    pub allow_recompress: bool,
+
+    /// Memory budget used to decide k-mer counting mode, read batch size, and concurrent contigs. ex. `64G`
+    ///
+    /// If the assembly's k-mers don't fit in half of the budget, they're counted in buckets written to
+    /// `kmer_buckets.*/`, about 17 bytes of disk per assembly base. With more than one read batch, each batch's SUNK
+    /// hits are written to `read_sunks_chunks.*/` until all are mapped. Both are removed when done or on error.
+    /// k-mers are counted in at most 1024 buckets, so counting uses at least about 0.05 bytes per assembly base.
+    #[arg(long, value_parser = parse_mem_size)]
+    pub max_memory: Option<u64>,
+
+    /// Also write `kmer_counts.tsv` with the first position and assembly-wide count of kmers occuring at least this many times.
+    /// Useful to check why a region has no SUNKs. ex. `2` for all repeated kmers.
+    #[arg(long, value_name = "MIN_COUNT")]
+    pub emit_kmer_counts: Option<usize>,
+
+    /// SUNKs within this many bp of an assembly gap (run of N) are flagged as `near_gap`.
+    #[arg(long, default_value_t = DEFAULT_GAP_FLANK)]
+    pub gap_flank: u64,
+
+    /// Skip up to this many malformed FASTA records, ex. truncated reads, before failing.
+    #[arg(long, default_value_t = DEFAULT_MAX_MALFORMED_RECORDS)]
+    pub max_malformed_records: usize,
+
+    /// What to do with cached intermediates that are empty, unreadable, or have fewer rows than were written, ex. from a crashed run.
+    #[arg(long, value_enum, default_value_t = EmptyIntermediate::Redo)]
+    pub on_empty_intermediate: EmptyIntermediate,
+
+    /// Warn if expected coverage (read bases / assembly bases) is below this. SUNK validation needs ~15x+.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = 15.0)]
+    pub min_coverage: f64,
+
+    /// Before mapping, compare k-mer sketches of the assembly and a subset of reads to catch reads from the wrong
+    /// sample. Writes Mash-style distances to `summary.json`.
+    #[cfg(feature = "mapping")]
+    #[arg(long)]
+    pub sketch_check: bool,
+
+    /// Write SVG dot plots of SUNK anchors for these comma-separated reads to `dot_plots/`. One plot per contig hit.
+    #[cfg(feature = "plots")]
+    #[arg(long, value_delimiter = ',', value_name = "READS")]
+    pub dot_plots: Vec<String>,
+
+    /// Write an HTML report to `report/` with a detail page per contig with breakpoints.
+    #[cfg(all(feature = "graph", feature = "plots"))]
+    #[arg(long)]
+    pub report: bool,
+
+    /// How multiple hits of the same SUNK group within a read are resolved.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_enum, default_value_t = DedupPolicy::First)]
+    pub sunk_dedup: DedupPolicy,
+
+    /// Pre-assign each read to at most this many candidate contigs by shared minimizers and only map SUNKs of those contigs.
+    /// Speeds up mapping on whole-genome assemblies at high coverage when SUNKs are too long for the default hash index,
+    /// over 31 bp, which it replaces.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_name = "MAX_CONTIGS", value_parser = clap::value_parser!(usize).range(1..))]
+    pub prebin: Option<usize>,
+
+    /// Look up read k-mers in a memory-mapped SUNK index at this path, shared read-only by processes on the node.
+    /// Written from the SUNKs if missing. `--prebin` has no effect with an index.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_name = "PATH")]
+    pub sunk_index: Option<PathBuf>,
+
+    /// Experimental. Take read SUNK hits from a PAF of reads aligned to the assembly with `cg:Z` or `cs:Z` tags, ex.
+    /// from `minimap2 -c`, instead of mapping SUNKs to reads. A read carries a SUNK within an exactly matching block of
+    /// one of its alignments. Compare to a SUNK-based run to check alignment-based support.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_name = "PAF")]
+    pub anchor_paf: Option<PathBuf>,
+
+    /// Debug check of the map stage. Compare the read sequence at this many sampled read SUNK hits to the SUNK and
+    /// fail on any mismatch.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_name = "HITS")]
+    pub validate_hits: Option<usize>,
+
+    /// Minimum consecutive SUNKs on each side of a strand switch to report an inversion.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = DEFAULT_MIN_RUN_SUNKS)]
+    pub min_inversion_sunks: usize,
+
+    /// Minimum consecutive SUNKs on each side of a contig jump to report a translocation candidate.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = DEFAULT_MIN_TRANSLOCATION_SUNKS)]
+    pub min_translocation_sunks: usize,
+
+    /// Minimum consecutive SUNKs on each side of a jump between haplotypes to report a phase switch candidate.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = DEFAULT_MIN_PHASE_SWITCH_SUNKS)]
+    pub min_phase_switch_sunks: usize,
+
+    /// Distance within which inversion, translocation, and phase switch breakpoints of different reads are clustered.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = DEFAULT_BREAKPOINT_SLOP)]
+    pub breakpoint_slop: i64,
+
+    /// Known bad SUNK groups, ex. in rDNA, masked along with computed bad SUNKs before graph construction.
+    /// Either one SUNK id (`ctg:group`) per line or a BED file of assembly regions.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_name = "IDS_OR_BED")]
+    pub extra_bad_sunks: Option<PathBuf>,
+
+    /// Minimum reads jumping between the same contig positions to report a translocation candidate.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = DEFAULT_MIN_TRANSLOCATION_READS)]
+    pub min_translocation_reads: usize,
+
+    /// Haplotype of each contig of a dual assembly with both haplotypes, as a TSV of contig and haplotype without a
+    /// header. ex. `ctg1\thap1`. Reads jumping between haplotypes are reported as candidate phase switches in
+    /// `phase_switches.bed`. Uses `--min-phase-switch-sunks` and `--min-translocation-reads`.
+    #[cfg(feature = "mapping")]
+    #[arg(long, value_name = "TSV")]
+    pub haplotypes: Option<PathBuf>,
+
+    /// Read group of each read, ex. its flowcell or run id, as a TSV of read and read group without a header.
+    /// ex. `read1\tPAM12345`. Mapping, assignment, and support are reported per read group in `summary.json` under
+    /// `read_groups`.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_name = "TSV")]
+    pub read_groups: Option<PathBuf>,
+
+    /// PAF of the assembly aligned to a reference, ex. from `minimap2 -x asm5 ref.fa asm.fa`. Contigs with at least 90%
+    /// of their aligned bases on the reverse strand are presented reverse complemented in contig outputs of the graph
+    /// stage, with a `flipped` column.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_name = "PAF")]
+    pub ref_paf: Option<PathBuf>,
+
+    /// Circular contigs, ex. mitochondria or plasmids, as comma-separated names. Reads spanning the origin with
+    /// consistently spaced SUNKs support the junction between the contig's ends. Written to `origin_reads.tsv`.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_delimiter = ',', value_name = "CTGS")]
+    pub circular: Vec<String>,
+
+    /// GFA of the assembly, ex. from hifiasm or Flye. Segments linked end to start to themselves are also circular.
+    /// See `--circular`.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_name = "GFA")]
+    pub circular_gfa: Option<PathBuf>,
+
+    /// Allowed deviation of the median read to contig SUNK distance ratio from 1 before a contig is flagged as collapsed or expanded.
+    /// Also the allowed deviation of the read scale of a supported component in `component_scales.bed`.
+    #[cfg(feature = "mapping")]
+    #[arg(long, default_value_t = DEFAULT_SPACING_TOLERANCE)]
+    pub spacing_tolerance: f64,
+
+    /// How SUNK pairs with multiple positions within a read are resolved when building per-read graphs.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_enum, default_value_t = MultiSunkStrategy::KeepFirst)]
+    pub multi_sunk: MultiSunkStrategy,
+
+    /// Allowed relative difference between the read and contig distance of a SUNK pair. Pairs exactly at the tolerance,
+    /// ex. a ratio of 0.9 or 1.1 with 0.1, are inconsistent.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_parser = parse_dst_tolerance, default_value_t = DEFAULT_DST_TOLERANCE)]
+    pub dst_tolerance: f32,
+
+    /// Minimum number of SUNKs in a contig component for it to be supported.
+    #[cfg(feature = "graph")]
+    #[arg(long, default_value_t = DEFAULT_MIN_COMPONENT_SUNKS)]
+    pub min_component_sunks: usize,
+
+    /// Skip reads with more SUNK hits on a contig, ex. from a filtering bug, and list them in `read_status.tsv`.
+    /// `0` for no limit.
+    #[cfg(feature = "graph")]
+    #[arg(long, default_value_t = DEFAULT_MAX_READ_SUNKS)]
+    pub max_read_sunks: usize,
+
+    /// Skip reads taking more seconds to build their SUNK graph on a contig and list them in `read_status.tsv`.
+    /// `0` for no limit.
+    #[cfg(feature = "graph")]
+    #[arg(long, default_value_t = DEFAULT_READ_TIMEOUT.as_secs_f64())]
+    pub read_timeout: f64,
+
+    /// Alignment depth BedGraph, ex. from mosdepth or samtools depth, used to label unsupported intervals.
+    #[cfg(feature = "graph")]
+    #[arg(long)]
+    pub depth: Option<PathBuf>,
+
+    /// Minimum mean alignment depth for an unsupported interval to be covered by reads. Requires `--depth`.
+    #[cfg(feature = "graph")]
+    #[arg(long, default_value_t = 1.0)]
+    pub min_depth: f64,
+
+    /// Place reads with a single SUNK, dropped when building read graphs, where their SUNK is in a supported component.
+    /// Rescued reads are counted separately in `sunk_support.bedgraph` as `rescued_reads` and don't add support.
+    #[cfg(feature = "graph")]
+    #[arg(long)]
+    pub rescue_single_sunk: bool,
+
+    /// Minimum size of unsupported intervals in `curation_targets.bed`, the larger of the interval length and its
+    /// estimated indel size.
+    #[cfg(feature = "graph")]
+    #[arg(long, default_value_t = DEFAULT_MIN_EVENT_SIZE)]
+    pub min_event_size: u64,
+
+    /// Minimum SUNKs on each side of a suggested breakpoint in a chimeric read in `read_trim_suggestions.tsv`.
+    #[cfg(feature = "graph")]
+    #[arg(long, default_value_t = DEFAULT_MIN_TRIM_SUNKS)]
+    pub min_trim_sunks: usize,
+
+    /// BED file of regions to leave out of `curation_targets.bed` along with gaps, ex. centromeres.
+    #[cfg(feature = "graph")]
+    #[arg(long, value_name = "BED")]
+    pub exclude_regions: Option<PathBuf>,
+}
+
+impl Cli {
+    /// Resolve relative paths of inputs from `dir`, ex. the working directory, so cached intermediates record
+    /// absolute input paths.
+    pub fn resolve_inputs(&mut self, dir: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        };
+        let mut inputs = vec![&mut self.assembly, &mut self.reads, &mut self.rename_map];
+        #[cfg(feature = "mapping")]
+        inputs.extend([
+            &mut self.sunk_index,
+            &mut self.anchor_paf,
+            &mut self.extra_bad_sunks,
+            &mut self.haplotypes,
+        ]);
+        #[cfg(feature = "graph")]
+        inputs.extend([
+            &mut self.read_groups,
+            &mut self.ref_paf,
+            &mut self.circular_gfa,
+            &mut self.depth,
+            &mut self.exclude_regions,
+        ]);
+        for path in inputs.into_iter().flatten() {
+            resolve(path);
+        }
+    }
+
+    /// Assembly and reads. Exits with a usage error if either is missing, ex. with a subcommand that needs them.
+    pub fn inputs(&self) -> (&Path, &Path) {
+        match (&self.assembly, &self.reads) {
+            (Some(path_asm), Some(path_reads)) => (path_asm, path_reads),
+            _ => Cli::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--assembly and --reads are required",
+                )
+                .exit(),
+        }
+    }
+
+    /// Path within `--outdir`. Absolute paths are kept as is.
+    pub fn out(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.outdir.join(path)
+    }
+}
+
+#[cfg(feature = "graph")]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Find assembly SUNKs and write `asm_sunks.tsv`.
+    Sunks,
+    /// Map SUNKs in `asm_sunks.tsv` to reads and write `read_sunks.tsv`.
+    Map,
+    /// Assign reads in `read_sunks.tsv` to contigs and write `read_ctg_mapping.tsv`.
+    Assign,
+    /// Find bad SUNKs from read assignments in `read_ctg_mapping.tsv` and write `read_sunks_good.tsv` and
+    /// `read_sunks_bad.tsv`.
+    Filter,
+    /// Build SUNK graphs from the outputs of earlier stages and write the final outputs.
+    Graph,
+    /// Compare the outputs of two runs and report regions and reads that changed.
+    Diff(DiffArgs),
+    /// Reuse mapped read SUNKs and compare supported intervals across a grid of downstream parameters.
+    Sweep(SweepArgs),
+    /// Rerun a single read through mapping, assignment, and per-read graph construction and print each decision.
+    ExplainRead(ExplainReadArgs),
+    /// List the SUNKs in a region and why each read carrying them did or did not support it.
+    ExplainRegion(ExplainRegionArgs),
+    /// Run the map, assign, and filter stages and write the SUNK group read count histogram and bad SUNK cutoffs.
+    BadSunks(BadSunksArgs),
+    /// Report whether kmers are SUNKs, their assembly positions, and which reads from a previous run carry them.
+    Lookup(LookupArgs),
+    /// Downsample assigned reads and report supported bases per contig at each fraction of coverage.
+    Titrate(TitrateArgs),
+    /// Write the assembly sequence of each unsupported interval and its flanks to FASTA.
+    /// With `--per-contig`, writes `{ctg}_unsupported.fa` per contig instead.
+    ExtractUnsupported(ExtractUnsupportedArgs),
+    /// Serve read mapping requests from `submit-map` runs on other machines.
+    ServeMap(ServeMapArgs),
+    /// Run the pipeline with the map stage fanned out to `serve-map` workers.
+    SubmitMap(SubmitMapArgs),
+    /// Run the pipeline with default parameters on a small embedded dataset and check its outputs.
+    SelfTest(SelfTestArgs),
+}
+
+#[cfg(feature = "graph")]
+impl Command {
+    /// Stage run alone by a stage subcommand, ex. `map`. Earlier stages' intermediates must be in `--outdir`.
+    pub fn stage(&self) -> Option<Stage> {
+        match self {
+            Command::Sunks => Some(Stage::Sunks),
+            Command::Map => Some(Stage::Map),
+            Command::Assign => Some(Stage::Assign),
+            Command::Filter => Some(Stage::Filter),
+            Command::Graph => Some(Stage::Graph),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    /// Output directory of the first run.
+    pub run_a: PathBuf,
+
+    /// Output directory of the second run.
+    pub run_b: PathBuf,
+
+    /// Ignore changed regions this length or shorter.
+    #[arg(long, default_value_t = 500)]
+    pub slop: u64,
+
+    /// Output prefix. Writes `{prefix}_intervals.tsv` and `{prefix}_reads.tsv` within `--outdir`.
+    #[arg(long, default_value = "diff")]
+    pub prefix: String,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct SweepArgs {
+    /// Comma-separated lower and upper percentiles used to assign reads to contigs.
+    #[arg(long, value_delimiter = ',', value_parser = parse_bandwidth, default_value = "0.25:0.75")]
+    pub bandwidths: Vec<(f64, f64)>,
+
+    /// Comma-separated allowed relative differences between the read and contig distance of a SUNK pair.
+    #[arg(long, value_delimiter = ',', value_parser = parse_dst_tolerance, default_value = "0.1")]
+    pub dst_tolerances: Vec<f32>,
+
+    /// Comma-separated minimum number of SUNKs in a contig component.
+    #[arg(long, value_delimiter = ',', default_value = "3")]
+    pub min_component_sunks: Vec<usize>,
+
+    /// Output comparison table.
+    #[arg(long, default_value = "sweep.tsv")]
+    pub output: PathBuf,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct BadSunksArgs {
+    /// Output prefix. Writes `{prefix}_histogram.tsv` and `{prefix}_cutoffs.json`.
+    /// With the `plots` feature, also writes `{prefix}_histogram.svg`.
+    #[arg(long, default_value = "bad_sunks")]
+    pub prefix: String,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct ServeMapArgs {
+    /// Address to listen on. Clients aren't authenticated, so only listen on other addresses within a trusted network.
+    #[arg(long, default_value = DEFAULT_LISTEN_ADDR)]
+    pub listen: String,
+
+    /// Reads FASTA on this machine, ex. on a shared filesystem. The same reads as the client's.
+    #[arg(long)]
+    pub reads: PathBuf,
+
+    /// Memory-mapped SUNK index shared by workers on this machine. Written from the first client's SUNKs if missing.
+    #[arg(long, value_name = "PATH")]
+    pub sunk_index: Option<PathBuf>,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct SubmitMapArgs {
+    /// Comma-separated `host:port` of workers started with `serve-map`.
+    #[arg(long, value_delimiter = ',', required = true, value_name = "ADDRS")]
+    pub workers: Vec<String>,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct SelfTestArgs {
+    /// Directory to run in, kept afterwards. Defaults to a temporary directory removed if the test passes.
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct LookupArgs {
+    /// Kmer sequences.
+    pub kmers: Vec<String>,
+
+    /// File of kmer sequences, one per line.
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct TitrateArgs {
+    /// Comma-separated fractions of assigned reads kept.
+    #[arg(long, value_delimiter = ',', value_parser = parse_fraction, default_values_t = DEFAULT_FRACTIONS)]
+    pub fractions: Vec<f64>,
+
+    /// Seed of read sampling. Reads kept at a fraction are also kept at larger fractions.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Output table of supported bases by fraction and contig.
+    #[arg(long, default_value = "titration.tsv")]
+    pub output: PathBuf,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct ExtractUnsupportedArgs {
+    /// Bases added to each side of an interval.
+    #[arg(long, default_value_t = 0)]
+    pub flank: u64,
+
+    /// Unsupported intervals from a previous run. Any BED file of assembly intervals can be given.
+    #[arg(long, default_value = "unsupported.bed")]
+    pub bed: PathBuf,
+
+    /// Output FASTA.
+    #[arg(long, default_value = "unsupported.fa")]
+    pub output: PathBuf,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct ExplainReadArgs {
+    /// Read name.
+    #[arg(long)]
+    pub name: String,
+}
+
+#[cfg(feature = "graph")]
+#[derive(Args, Debug)]
+pub struct ExplainRegionArgs {
+    /// Region as `ctg:start-end` with 1-based, inclusive coordinates. ex. `chr1:1000-2000`
+    #[arg(value_parser = parse_region)]
+    pub region: (String, i64, i64),
+}
@@ -0,0 +1,245 @@
+//! Support of contigs no longer than the reads used to build SUNK graphs.
+//!
+//! A small contig has few SUNKs and reads overlapping it extend well past both ends into neighboring contigs, so reads
+//! are assigned elsewhere and its SUNK graph rarely has a component of [`crate::sunk_graph::GraphParams`]'s
+//! `min_component_sunks`. Instead, reads fully containing the contig with consistently spaced SUNKs support it.
+
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+
+use crate::{error::Result, get_kmers::sunk_id, orientation::infer_orientation, ratio};
+
+/// Contig supported by reads containing it rather than by its SUNK graph.
+pub const CONTAINED_SUPPORT: &str = "contained-support";
+/// Contig supported by components of its SUNK graph.
+pub const SUNK_GRAPH_SUPPORT: &str = "sunk-graph";
+/// Minimum reads containing a small contig for it to be supported.
+pub const MIN_CONTAINED_READS: usize = 2;
+
+/// Reads fully containing small contigs.
+///
+/// A read contains a contig if it has at least two good SUNKs of the contig, the read distance between the outermost
+/// is within `dst_tolerance` of their contig distance, and projecting the contig's ends from them lands within the
+/// read.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, group, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ...]`
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNKs with columns `[id, ...]`. Their hits are ignored.
+/// * `read_lens`
+///     * Read lengths.
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `max_ctg_len`
+///     * Contigs this length or shorter are small. ex. [`crate::sunk_graph::MIN_READ_LEN`]
+/// * `dst_tolerance`
+///     * Allowed relative difference between the read and contig distance. Exclusive. See
+///       [`ratio::within_tolerance`].
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[read, ctg, sunks, ort, assigned]` sorted by contig and read.
+///     * `sunks` is the number of good SUNKs of the contig in the read.
+///     * `assigned` is whether the read is already assigned to the contig.
+pub fn contained_reads(
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    df_bad_sunks: &DataFrame,
+    read_lens: &HashMap<String, u64>,
+    asm_lens: &HashMap<String, u64>,
+    max_ctg_len: u64,
+    dst_tolerance: f32,
+) -> Result<DataFrame> {
+    let bad: HashSet<&str> = df_bad_sunks.column("id")?.str()?.iter().flatten().collect();
+    let rpos = df_read_sunks.column("rpos")?.cast(&DataType::Int64)?;
+    let cpos = df_read_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let group = df_read_sunks.column("group")?.cast(&DataType::Int64)?;
+    let mut anchors: HashMap<(&str, &str), Vec<(i64, i64)>> = HashMap::new();
+    for ((((read, rpos), ctg), cpos), group) in df_read_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(rpos.i64()?.iter())
+        .zip(df_read_sunks.column("ctg")?.str()?.iter())
+        .zip(cpos.i64()?.iter())
+        .zip(group.i64()?.iter())
+    {
+        let (Some(read), Some(rpos), Some(ctg), Some(cpos), Some(group)) =
+            (read, rpos, ctg, cpos, group)
+        else {
+            continue;
+        };
+        if !asm_lens.get(ctg).is_some_and(|len| *len <= max_ctg_len)
+            || bad.contains(sunk_id(ctg, group).as_str())
+        {
+            continue;
+        }
+        anchors.entry((read, ctg)).or_default().push((rpos, cpos));
+    }
+    let assigned: HashSet<(&str, &str)> = df_best_reads_asm
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_best_reads_asm.column("ctg")?.str()?.iter())
+        .flat_map(|(read, ctg)| read.zip(ctg))
+        .collect();
+
+    let tolerance_ppm = ratio::to_ppm(f64::from(dst_tolerance));
+    let mut rows = vec![];
+    for ((read, ctg), mut anchors) in anchors {
+        let (Some(read_len), Some(ctg_len)) = (read_lens.get(read), asm_lens.get(ctg)) else {
+            continue;
+        };
+        let Some((ort, _)) = infer_orientation(&anchors) else {
+            continue;
+        };
+        anchors.sort_unstable_by_key(|(rpos, cpos)| (*cpos, *rpos));
+        let ((first_rpos, first_cpos), (last_rpos, last_cpos)) =
+            (anchors[0], anchors[anchors.len() - 1]);
+        let sign = if ort.is_rev() { -1 } else { 1 };
+        if !ratio::within_tolerance(
+            sign * (last_rpos - first_rpos),
+            last_cpos - first_cpos,
+            tolerance_ppm,
+        ) {
+            continue;
+        }
+        // Read positions of the contig's first and last base. 1-based.
+        let (ctg_st, ctg_end) = (
+            first_rpos - sign * (first_cpos - 1),
+            first_rpos + sign * (*ctg_len as i64 - first_cpos),
+        );
+        let read_range = 1..=*read_len as i64;
+        if !(read_range.contains(&ctg_st) && read_range.contains(&ctg_end)) {
+            continue;
+        }
+        let n_sunks = anchors
+            .iter()
+            .map(|(_, cpos)| cpos)
+            .collect::<HashSet<_>>()
+            .len() as u64;
+        rows.push((ctg, read, n_sunks, ort, assigned.contains(&(read, ctg))));
+    }
+    rows.sort_unstable_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+    Ok(DataFrame::new(vec![
+        Column::new(
+            "read".into(),
+            rows.iter().map(|row| row.1).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "ctg".into(),
+            rows.iter().map(|row| row.0).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "sunks".into(),
+            rows.iter().map(|row| row.2).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "ort".into(),
+            rows.iter().map(|row| row.3.as_str()).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "assigned".into(),
+            rows.iter().map(|row| row.4).collect::<Vec<_>>(),
+        ),
+    ])?)
+}
+
+/// Small contigs with at least `min_reads` reads containing them.
+///
+/// # Arguments
+/// * `df_contained_reads`
+///     * [`DataFrame`] of reads containing small contigs. See [`contained_reads`].
+/// * `min_reads`
+///     * Minimum containing reads. ex. [`MIN_CONTAINED_READS`]
+///
+/// # Returns
+/// * Containing reads and the most SUNKs in any of them by contig.
+pub fn contained_support(
+    df_contained_reads: &DataFrame,
+    min_reads: usize,
+) -> Result<HashMap<String, (u64, u64)>> {
+    let mut support: HashMap<String, (u64, u64)> = HashMap::new();
+    for (ctg, sunks) in df_contained_reads
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_contained_reads.column("sunks")?.u64()?.iter())
+    {
+        let (Some(ctg), Some(sunks)) = (ctg, sunks) else {
+            continue;
+        };
+        let (n_reads, max_sunks) = support.entry(ctg.to_owned()).or_default();
+        *n_reads += 1;
+        *max_sunks = (*max_sunks).max(sunks);
+    }
+    support.retain(|_, (n_reads, _)| *n_reads >= min_reads as u64);
+    Ok(support)
+}
+
+/// Supported interval spanning a whole contig with columns `[ctg, st, end, sunks]`, as from
+/// [`crate::sunk_graph::create_sunk_graph`].
+pub fn contained_bed(ctg: &str, ctg_len: u64, sunks: u64) -> Result<DataFrame> {
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), [ctg]),
+        Column::new("st".into(), [0i64]),
+        Column::new("end".into(), [ctg_len as i64]),
+        Column::new("sunks".into(), [sunks]),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{contained_reads, contained_support, MIN_CONTAINED_READS};
+
+    #[test]
+    fn test_contained_reads() {
+        // r1 and r2 contain the 2 kbp contig in either orientation. r3 ends within it, r4's SUNKs are too far apart,
+        // and the contig of r5 isn't small.
+        let df_read_sunks = df!(
+            "read" => ["r1", "r1", "r2", "r2", "r3", "r3", "r4", "r4", "r5", "r5"],
+            "rpos" => [5_101i64, 6_101, 8_901, 7_901, 101, 1_101, 5_101, 7_101, 5_101, 6_101],
+            "ctg" => ["small", "small", "small", "small", "small", "small", "small", "small", "large", "large"],
+            "cpos" => [101i64, 1_101, 101, 1_101, 501, 1_501, 101, 1_101, 101, 1_101],
+            "group" => [1i64, 2, 1, 2, 3, 4, 1, 2, 1, 2],
+        )
+        .unwrap();
+        let df_best_reads_asm = df!("read" => ["r1", "r2"], "ctg" => ["small", "large"]).unwrap();
+        let df_bad_sunks = df!("id" => ["small:9"]).unwrap();
+        let read_lens =
+            HashMap::from(["r1", "r2", "r3", "r4", "r5"].map(|read| (read.to_owned(), 20_000)));
+        let asm_lens = HashMap::from([("small".to_owned(), 2_000), ("large".to_owned(), 50_000)]);
+        let df = contained_reads(
+            &df_read_sunks,
+            &df_best_reads_asm,
+            &df_bad_sunks,
+            &read_lens,
+            &asm_lens,
+            10_000,
+            0.1,
+        )
+        .unwrap();
+        assert_eq!(
+            df,
+            df!(
+                "read" => ["r1", "r2"],
+                "ctg" => ["small", "small"],
+                "sunks" => [2u64, 2],
+                "ort" => ["+", "-"],
+                "assigned" => [true, false],
+            )
+            .unwrap()
+        );
+        let support = contained_support(&df, MIN_CONTAINED_READS).unwrap();
+        assert_eq!(support, HashMap::from([("small".to_owned(), (2, 2))]));
+        assert!(contained_support(&df, 3).unwrap().is_empty());
+    }
+}
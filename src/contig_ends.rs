@@ -0,0 +1,210 @@
+//! Evidence for extending contigs past their ends from validated reads.
+
+use std::collections::HashMap;
+
+use polars::prelude::*;
+
+use crate::error::Result;
+
+/// Fewest read bases past a contig end for a read to extend it.
+pub const MIN_OVERHANG: i64 = 1_000;
+/// Fewest reads reaching or extending a contig end to call it.
+pub const MIN_END_READS: usize = 2;
+/// Enough reads extend the contig end. It could be extended, ex. by reassembling with those reads.
+pub const EXTENDABLE: &str = "extendable";
+/// Enough reads reach the contig end without extending it, ex. at a telomere or the end of a chromosome arm.
+pub const NOT_EXTENDED: &str = "not_extended";
+/// Too few validated reads reach the contig end to tell.
+pub const READ_LIMITED: &str = "read_limited";
+
+/// Read bases past the start and end of a contig given a read's validated interval on it.
+///
+/// The read is projected collinearly past its validated interval. Negative if the read stops short of the end.
+fn overhangs(
+    (st, end, read_len): (i64, i64, i64),
+    (ctg_st, ctg_end, ctg_len): (i64, i64, i64),
+    is_fwd: bool,
+) -> (i64, i64) {
+    let (before, after) = (st, read_len - end);
+    let (to_start, to_end) = (ctg_st, ctg_len - ctg_end);
+    if is_fwd {
+        (before - to_start, after - to_end)
+    } else {
+        (after - to_start, before - to_end)
+    }
+}
+
+/// Report whether validated reads extend past each contig end.
+///
+/// # Arguments
+/// * `df_read_intervals`
+///     * [`DataFrame`] of read intervals with columns `[read, st, end, ctg, ctg_st, ctg_end, strand, ...]`.
+///       See [`crate::merge::read_intervals`].
+/// * `read_lens`
+///     * Read lengths.
+/// * `ctgs`
+///     * Contigs to report.
+/// * `ctg_lens`
+///     * Contig lengths.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, side, reaching_reads, extending_reads, mean_overhang, max_overhang, status]`
+///     * `side` is `start` or `end`.
+///     * `reaching_reads` reach the end and `extending_reads` extend past it by at least [`MIN_OVERHANG`].
+///     * `mean_overhang` and `max_overhang` are read bases past the end of extending reads. Null without any.
+///     * `status` is [`EXTENDABLE`], [`NOT_EXTENDED`], or [`READ_LIMITED`].
+pub fn contig_end_extensions(
+    df_read_intervals: &DataFrame,
+    read_lens: &HashMap<String, u64>,
+    ctgs: &[String],
+    ctg_lens: &HashMap<String, u64>,
+) -> Result<DataFrame> {
+    // Overhangs past the start and end of each contig.
+    let mut ctg_overhangs: HashMap<&str, [Vec<i64>; 2]> = HashMap::new();
+    let positions = |name: &str| -> Result<Int64Chunked> {
+        Ok(df_read_intervals
+            .column(name)?
+            .cast(&DataType::Int64)?
+            .i64()?
+            .clone())
+    };
+    let (sts, ends, ctg_sts, ctg_ends) = (
+        positions("st")?,
+        positions("end")?,
+        positions("ctg_st")?,
+        positions("ctg_end")?,
+    );
+    for ((((read, st), end), (ctg, ctg_st)), (ctg_end, strand)) in df_read_intervals
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(sts.iter())
+        .zip(ends.iter())
+        .zip(
+            df_read_intervals
+                .column("ctg")?
+                .str()?
+                .iter()
+                .zip(ctg_sts.iter()),
+        )
+        .zip(
+            ctg_ends
+                .iter()
+                .zip(df_read_intervals.column("strand")?.str()?.iter()),
+        )
+    {
+        let (Some(read), Some(st), Some(end), Some(ctg), Some(ctg_st), Some(ctg_end), Some(strand)) =
+            (read, st, end, ctg, ctg_st, ctg_end, strand)
+        else {
+            continue;
+        };
+        let (Some(read_len), Some(ctg_len)) = (read_lens.get(read), ctg_lens.get(ctg)) else {
+            continue;
+        };
+        let (start_overhang, end_overhang) = overhangs(
+            (st, end, *read_len as i64),
+            (ctg_st, ctg_end, *ctg_len as i64),
+            strand != "-",
+        );
+        let [starts, ends] = ctg_overhangs.entry(ctg).or_default();
+        starts.push(start_overhang);
+        ends.push(end_overhang);
+    }
+
+    let (mut out_ctgs, mut sides, mut n_reaching, mut n_extending) =
+        (vec![], vec![], vec![], vec![]);
+    let (mut mean_overhangs, mut max_overhangs, mut statuses) = (vec![], vec![], vec![]);
+    for ctg in ctgs {
+        let ends = ctg_overhangs.get(ctg.as_str());
+        for (i, side) in ["start", "end"].into_iter().enumerate() {
+            let overhangs = ends.map_or(&[][..], |ends| ends[i].as_slice());
+            let reaching = overhangs.iter().filter(|overhang| **overhang >= 0).count();
+            let extending: Vec<i64> = overhangs
+                .iter()
+                .copied()
+                .filter(|overhang| *overhang >= MIN_OVERHANG)
+                .collect();
+            out_ctgs.push(ctg.as_str());
+            sides.push(side);
+            n_reaching.push(reaching as u64);
+            n_extending.push(extending.len() as u64);
+            mean_overhangs.push(
+                (!extending.is_empty())
+                    .then(|| extending.iter().sum::<i64>() as f64 / extending.len() as f64),
+            );
+            max_overhangs.push(extending.iter().max().copied());
+            statuses.push(if extending.len() >= MIN_END_READS {
+                EXTENDABLE
+            } else if reaching >= MIN_END_READS {
+                NOT_EXTENDED
+            } else {
+                READ_LIMITED
+            });
+        }
+    }
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), out_ctgs),
+        Column::new("side".into(), sides),
+        Column::new("reaching_reads".into(), n_reaching),
+        Column::new("extending_reads".into(), n_extending),
+        Column::new("mean_overhang".into(), mean_overhangs),
+        Column::new("max_overhang".into(), max_overhangs),
+        Column::new("status".into(), statuses),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{contig_end_extensions, EXTENDABLE, NOT_EXTENDED};
+
+    #[test]
+    fn test_contig_end_extensions() {
+        // Two forward reads extending 2 kbp past the start and a reverse read reaching the end.
+        let df_read_intervals = df!(
+            "read" => ["r1", "r2", "r3", "r4"],
+            "st" => [3_000i64, 2_500, 1_000, 0],
+            "end" => [10_000i64, 10_000, 10_000, 5_000],
+            "ctg" => ["ctg1", "ctg1", "ctg1", "ctg1"],
+            "ctg_st" => [1_000i64, 500, 90_000, 95_000],
+            "ctg_end" => [8_000i64, 8_000, 99_000, 100_000],
+            "strand" => ["+", "+", "-", "+"],
+        )
+        .unwrap();
+        let read_lens = HashMap::from([
+            ("r1".to_owned(), 10_000),
+            ("r2".to_owned(), 10_000),
+            ("r3".to_owned(), 10_000),
+            ("r4".to_owned(), 5_000),
+        ]);
+        let ctg_lens = HashMap::from([("ctg1".to_owned(), 100_000)]);
+        let df = contig_end_extensions(
+            &df_read_intervals,
+            &read_lens,
+            &["ctg1".to_owned()],
+            &ctg_lens,
+        )
+        .unwrap();
+        assert_eq!(
+            df.select([
+                "side",
+                "reaching_reads",
+                "extending_reads",
+                "max_overhang",
+                "status"
+            ])
+            .unwrap(),
+            df!(
+                "side" => ["start", "end"],
+                "reaching_reads" => [2u64, 2],
+                "extending_reads" => [2u64, 0],
+                "max_overhang" => [Some(2_000i64), None],
+                "status" => [EXTENDABLE, NOT_EXTENDED],
+            )
+            .unwrap()
+        );
+    }
+}
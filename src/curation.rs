@@ -0,0 +1,118 @@
+//! Short list of unsupported intervals for manual curation.
+//!
+//! Most unsupported intervals are small or sit on gaps and regions already known to be unreliable. Curation targets
+//! are the rest, the events large enough to be worth a human's review.
+
+use std::collections::HashMap;
+
+use polars::prelude::*;
+
+use crate::{
+    error::Result,
+    intervals::{intersect, CtgIntervals},
+};
+
+/// Default minimum size of a curation target.
+pub const DEFAULT_MIN_EVENT_SIZE: u64 = 10_000;
+
+/// Filter unsupported intervals to curation targets.
+///
+/// The size of an event is the larger of its interval length and its estimated indel size, as a small unsupported
+/// junction can hide a large insertion.
+///
+/// # Arguments
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end, spanning_reads, indel_size, label, ...]`
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `min_event_size`
+///     * Minimum event size in bp.
+/// * `excluded`
+///     * Sorted, non-overlapping intervals by contig, ex. gaps. Events overlapping them are dropped.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, st, end, event_size, breakpoint, label, spanning_reads, indel_size]`
+///     * `breakpoint` is whether the interval is not at either contig end. See [`crate::junctions::breakpoint_links`].
+pub fn curation_targets(
+    df_unsupported: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    min_event_size: u64,
+    excluded: &CtgIntervals,
+) -> Result<DataFrame> {
+    let mut keep = Vec::with_capacity(df_unsupported.height());
+    let (mut event_sizes, mut breakpoints) = (vec![], vec![]);
+    for (((ctg, st), end), indel_size) in df_unsupported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_unsupported.column("st")?.i64()?.iter())
+        .zip(df_unsupported.column("end")?.i64()?.iter())
+        .zip(df_unsupported.column("indel_size")?.i64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            keep.push(false);
+            continue;
+        };
+        let event_size = (end - st).max(indel_size.unwrap_or_default().abs()) as u64;
+        let is_excluded = excluded
+            .get(ctg)
+            .is_some_and(|intervals| !intersect(&[(st, end)], intervals).is_empty());
+        if event_size < min_event_size || is_excluded {
+            keep.push(false);
+            continue;
+        }
+        let len = asm_lens.get(ctg).copied().unwrap_or_default() as i64;
+        keep.push(true);
+        event_sizes.push(event_size);
+        breakpoints.push(st > 0 && end < len);
+    }
+    let mask: BooleanChunked = keep.into_iter().collect();
+    let mut df = df_unsupported.filter(&mask)?.select([
+        "ctg",
+        "st",
+        "end",
+        "label",
+        "spanning_reads",
+        "indel_size",
+    ])?;
+    df.insert_column(3, Column::new("event_size".into(), event_sizes))?;
+    df.insert_column(4, Column::new("breakpoint".into(), breakpoints))?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::curation_targets;
+
+    #[test]
+    fn test_curation_targets() {
+        let df_unsupported = df!(
+            "ctg" => ["ctg1", "ctg1", "ctg1", "ctg1", "ctg1"],
+            "st" => [0i64, 20_000, 40_000, 60_000, 90_000],
+            "end" => [15_000i64, 20_500, 41_000, 75_000, 100_000],
+            "spanning_reads" => [0u64, 4, 3, 0, 0],
+            "indel_size" => [None, Some(-12_000i64), Some(50), None, None],
+            "label" => ["no_reads", "inconsistent_sunks", "inconsistent_sunks", "no_sunks", "no_reads"],
+        )
+        .unwrap();
+        let asm_lens = HashMap::from([("ctg1".to_owned(), 100_000)]);
+        // The fourth interval is on a gap.
+        let excluded = HashMap::from([("ctg1".to_owned(), vec![(70_000, 71_000)])]);
+        let df = curation_targets(&df_unsupported, &asm_lens, 10_000, &excluded).unwrap();
+        assert_eq!(
+            df.select(["st", "end", "event_size", "breakpoint"])
+                .unwrap(),
+            df!(
+                "st" => [0i64, 20_000, 90_000],
+                "end" => [15_000i64, 20_500, 100_000],
+                "event_size" => [15_000u64, 12_000, 10_000],
+                "breakpoint" => [false, true, false],
+            )
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{
+    error::Result,
+    intervals::{contains, load_bed, CtgIntervals},
+};
+
+/// Region within a supported interval.
+pub const SUPPORTED: &str = "supported";
+/// Region within an unsupported interval only.
+pub const UNSUPPORTED: &str = "unsupported";
+/// Region in neither the supported nor unsupported intervals of a run. ex. Contig not processed.
+pub const ABSENT: &str = "absent";
+
+/// Supported and unsupported intervals of a single run.
+pub struct RunIntervals {
+    supported: CtgIntervals,
+    unsupported: CtgIntervals,
+}
+
+impl RunIntervals {
+    /// Load `supported.bed` and `unsupported.bed` from a run's output directory.
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            supported: load_bed(dir.as_ref().join("supported.bed"))?,
+            unsupported: load_bed(dir.as_ref().join("unsupported.bed"))?,
+        })
+    }
+
+    fn status(&self, ctg: &str, pos: i64) -> &'static str {
+        let contains_pos = |intervals: &CtgIntervals| {
+            intervals
+                .get(ctg)
+                .is_some_and(|intervals| contains(intervals, pos))
+        };
+        if contains_pos(&self.supported) {
+            SUPPORTED
+        } else if contains_pos(&self.unsupported) {
+            UNSUPPORTED
+        } else {
+            ABSENT
+        }
+    }
+
+    fn boundaries<'a>(&'a self, ctg: &str) -> impl Iterator<Item = i64> + 'a {
+        [self.supported.get(ctg), self.unsupported.get(ctg)]
+            .into_iter()
+            .flatten()
+            .flat_map(|itvs| itvs.iter().flat_map(|(st, end)| [*st, *end]))
+    }
+}
+
+/// Get regions whose status differs between two runs.
+///
+/// Both runs are split at every interval boundary and adjacent segments with the same pair of statuses are merged.
+///
+/// # Arguments
+/// * `run_a`
+///     * Intervals of the first run.
+/// * `run_b`
+///     * Intervals of the second run.
+/// * `slop`
+///     * Changed regions this length or shorter are ignored. Absorbs small shifts in interval boundaries.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, st, end, status_a, status_b]`
+pub fn diff_intervals(run_a: &RunIntervals, run_b: &RunIntervals, slop: u64) -> Result<DataFrame> {
+    let ctgs: Vec<&String> = [run_a, run_b]
+        .iter()
+        .flat_map(|run| run.supported.keys().chain(run.unsupported.keys()))
+        .unique()
+        .sorted()
+        .collect();
+
+    let (mut out_ctgs, mut starts, mut ends, mut status_a, mut status_b) =
+        (vec![], vec![], vec![], vec![], vec![]);
+    for ctg in ctgs {
+        let boundaries: Vec<i64> = run_a
+            .boundaries(ctg)
+            .chain(run_b.boundaries(ctg))
+            .sorted_unstable()
+            .dedup()
+            .collect();
+        // Segments with the same pair of statuses merged.
+        let mut segments: Vec<(i64, i64, &str, &str)> = vec![];
+        for (st, end) in boundaries.into_iter().tuple_windows() {
+            let (a, b) = (run_a.status(ctg, st), run_b.status(ctg, st));
+            match segments.last_mut() {
+                Some((_, prev_end, prev_a, prev_b))
+                    if *prev_end == st && *prev_a == a && *prev_b == b =>
+                {
+                    *prev_end = end
+                }
+                _ => segments.push((st, end, a, b)),
+            }
+        }
+        for (st, end, a, b) in segments {
+            if a == b || (end - st) as u64 <= slop {
+                continue;
+            }
+            out_ctgs.push(ctg.as_str());
+            starts.push(st);
+            ends.push(end);
+            status_a.push(a);
+            status_b.push(b);
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), out_ctgs),
+        Column::new("st".into(), starts),
+        Column::new("end".into(), ends),
+        Column::new("status_a".into(), status_a),
+        Column::new("status_b".into(), status_b),
+    ])?)
+}
+
+/// Get reads whose assigned contig or orientation differs between two runs.
+///
+/// # Arguments
+/// * `df_reads_a`
+///     * Read assignments of the first run with columns `[read, ctg, ort, ...]`
+/// * `df_reads_b`
+///     * Read assignments of the second run with columns `[read, ctg, ort, ...]`
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[read, ctg_a, ort_a, ctg_b, ort_b]`. Reads missing from a run have nulls.
+pub fn diff_read_assignments(df_reads_a: &DataFrame, df_reads_b: &DataFrame) -> Result<DataFrame> {
+    let select_run = |df: &DataFrame, run: &str| {
+        df.clone().lazy().select([
+            col("read"),
+            col("ctg").alias(format!("ctg_{run}")),
+            col("ort").alias(format!("ort_{run}")),
+        ])
+    };
+    Ok(select_run(df_reads_a, "a")
+        .join(
+            select_run(df_reads_b, "b"),
+            [col("read")],
+            [col("read")],
+            JoinArgs::new(JoinType::Full).with_coalesce(JoinCoalesce::CoalesceColumns),
+        )
+        .filter(
+            col("ctg_a")
+                .neq_missing(col("ctg_b"))
+                .or(col("ort_a").neq_missing(col("ort_b"))),
+        )
+        .collect()?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{
+        diff_intervals, diff_read_assignments, RunIntervals, ABSENT, SUPPORTED, UNSUPPORTED,
+    };
+    use crate::{
+        io::ScratchDir,
+        writers::{OutputWriters, WriterRegistry},
+    };
+
+    #[test]
+    fn test_load_run_intervals() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_diff_load").unwrap();
+        let writers = OutputWriters::new(WriterRegistry::default(), &[]).unwrap();
+        let header = "# rs-gavisunk version=\"0.1.0\"\n";
+        let mut df_supported = df!(
+            "ctg" => ["c1", "c1", "c2"],
+            "st" => [0i64, 50, 0],
+            "end" => [100i64, 150, 500],
+        )
+        .unwrap();
+        writers
+            .write(&mut df_supported, dir.path().join("supported.bed"), header)
+            .unwrap();
+        let mut df_unsupported = df!("ctg" => ["c1"], "st" => [150i64], "end" => [200i64]).unwrap();
+        writers
+            .write(
+                &mut df_unsupported,
+                dir.path().join("unsupported.bed"),
+                header,
+            )
+            .unwrap();
+
+        // Overlapping intervals are merged.
+        let run = RunIntervals::load(dir.path()).unwrap();
+        assert_eq!(run.supported["c1"], [(0, 150)]);
+        assert_eq!(run.supported["c2"], [(0, 500)]);
+        assert_eq!(run.unsupported["c1"], [(150, 200)]);
+    }
+
+    #[test]
+    fn test_diff_intervals() {
+        // Run b extends support of c1 by 50 bp and didn't process c2.
+        let run_a = RunIntervals {
+            supported: HashMap::from([
+                ("c1".to_owned(), vec![(0, 100)]),
+                ("c2".to_owned(), vec![(0, 500)]),
+            ]),
+            unsupported: HashMap::from([("c1".to_owned(), vec![(100, 200)])]),
+        };
+        let run_b = RunIntervals {
+            supported: HashMap::from([("c1".to_owned(), vec![(0, 150)])]),
+            unsupported: HashMap::from([("c1".to_owned(), vec![(150, 200)])]),
+        };
+        assert_eq!(
+            diff_intervals(&run_a, &run_b, 0).unwrap(),
+            df!(
+                "ctg" => ["c1", "c2"],
+                "st" => [100i64, 0],
+                "end" => [150i64, 500],
+                "status_a" => [UNSUPPORTED, SUPPORTED],
+                "status_b" => [SUPPORTED, ABSENT],
+            )
+            .unwrap()
+        );
+        // Shifts within the slop are ignored.
+        let df = diff_intervals(&run_a, &run_b, 50).unwrap();
+        assert_eq!(df.column("ctg").unwrap().str().unwrap().get(0), Some("c2"));
+        assert_eq!(df.height(), 1);
+    }
+
+    #[test]
+    fn test_diff_read_assignments() {
+        let df_reads_a = df!(
+            "read" => ["r1", "r2", "r3"],
+            "ctg" => ["c1", "c1", "c2"],
+            "ort" => ["+", "+", "-"],
+        )
+        .unwrap();
+        let df_reads_b = df!(
+            "read" => ["r1", "r2", "r4"],
+            "ctg" => ["c1", "c1", "c1"],
+            "ort" => ["+", "-", "+"],
+        )
+        .unwrap();
+        let df = diff_read_assignments(&df_reads_a, &df_reads_b)
+            .unwrap()
+            .sort(["read"], Default::default())
+            .unwrap();
+        // Reads missing from a run have nulls.
+        assert!(df.equals_missing(
+            &df!(
+                "read" => ["r2", "r3", "r4"],
+                "ctg_a" => [Some("c1"), Some("c2"), None],
+                "ort_a" => [Some("+"), Some("-"), None],
+                "ctg_b" => [Some("c1"), None, Some("c1")],
+                "ort_b" => [Some("-"), None, Some("+")],
+            )
+            .unwrap()
+        ));
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use polars::prelude::*;
+
+use crate::{
+    error::Result,
+    intervals::{mean_value, CtgBedGraph},
+};
+
+/// No assembly SUNKs in the region. Unsupported regardless of reads.
+pub const NO_SUNKS: &str = "no_sunks";
+/// SUNKs exist but few or no reads cover the region.
+pub const NO_READS: &str = "no_reads";
+/// Reads cover the region but their SUNKs are inconsistent with the assembly.
+pub const INCONSISTENT_SUNKS: &str = "inconsistent_sunks";
+
+/// Sorted positions of a column by contig.
+fn positions_by_ctg<'a>(df: &'a DataFrame, col_pos: &str) -> Result<HashMap<&'a str, Vec<i64>>> {
+    let pos = df.column(col_pos)?.cast(&DataType::Int64)?;
+    let mut ctg_pos: HashMap<&str, Vec<i64>> = HashMap::new();
+    for (ctg, pos) in df.column("ctg")?.str()?.iter().zip(pos.i64()?.iter()) {
+        let (Some(ctg), Some(pos)) = (ctg, pos) else {
+            continue;
+        };
+        ctg_pos.entry(ctg).or_default().push(pos);
+    }
+    for positions in ctg_pos.values_mut() {
+        positions.sort_unstable();
+    }
+    Ok(ctg_pos)
+}
+
+fn count_within(positions: Option<&Vec<i64>>, st: i64, end: i64) -> u64 {
+    positions.map_or(0, |positions| {
+        (positions.partition_point(|pos| *pos < end) - positions.partition_point(|pos| *pos < st))
+            as u64
+    })
+}
+
+/// Label unsupported regions by the likely cause of missing support.
+///
+/// Regions without assembly SUNKs are [`NO_SUNKS`]. Regions with SUNKs but without reads are [`NO_READS`].
+/// Otherwise, reads are present but their SUNKs are inconsistent, [`INCONSISTENT_SUNKS`].
+///
+/// # Arguments
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end, ...]`
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, ...]`
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[ctg, cpos, ...]`
+/// * `depth`
+///     * Optional alignment depth. If given, regions with a mean depth below `min_depth` have no reads.
+///       Otherwise, regions without read SUNK hits have no reads.
+/// * `min_depth`
+///     * Minimum mean alignment depth for a region to be covered by reads.
+///
+/// # Returns
+/// * `df_unsupported` with additional columns `[asm_sunks, read_sunks, mean_depth, label]`
+///     * `mean_depth` is null without `depth`.
+pub fn label_dropouts(
+    df_unsupported: &DataFrame,
+    df_asm_sunks: &DataFrame,
+    df_read_sunks: &DataFrame,
+    depth: Option<&CtgBedGraph>,
+    min_depth: f64,
+) -> Result<DataFrame> {
+    let asm_sunk_pos = positions_by_ctg(df_asm_sunks, "cpos")?;
+    let read_sunk_pos = positions_by_ctg(df_read_sunks, "cpos")?;
+
+    let (mut asm_sunks, mut read_sunks, mut mean_depths, mut labels) =
+        (vec![], vec![], vec![], vec![]);
+    for ((ctg, st), end) in df_unsupported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_unsupported.column("st")?.i64()?.iter())
+        .zip(df_unsupported.column("end")?.i64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            asm_sunks.push(None);
+            read_sunks.push(None);
+            mean_depths.push(None);
+            labels.push(None);
+            continue;
+        };
+        let n_asm_sunks = count_within(asm_sunk_pos.get(ctg), st, end);
+        let n_read_sunks = count_within(read_sunk_pos.get(ctg), st, end);
+        let mean_depth = depth.map(|depth| {
+            depth
+                .get(ctg)
+                .map_or(0.0, |values| mean_value(values, st, end))
+        });
+        let has_reads = mean_depth.map_or(n_read_sunks > 0, |depth| depth >= min_depth);
+        let label = if n_asm_sunks == 0 {
+            NO_SUNKS
+        } else if !has_reads {
+            NO_READS
+        } else {
+            INCONSISTENT_SUNKS
+        };
+        asm_sunks.push(Some(n_asm_sunks));
+        read_sunks.push(Some(n_read_sunks));
+        mean_depths.push(mean_depth);
+        labels.push(Some(label));
+    }
+
+    let mut df = df_unsupported.clone();
+    df.with_column(Column::new("asm_sunks".into(), asm_sunks))?;
+    df.with_column(Column::new("read_sunks".into(), read_sunks))?;
+    df.with_column(Column::new("mean_depth".into(), mean_depths))?;
+    df.with_column(Column::new("label".into(), labels))?;
+    Ok(df)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{label_dropouts, INCONSISTENT_SUNKS, NO_READS, NO_SUNKS};
+
+    #[test]
+    fn test_label_dropouts() {
+        let df_unsupported = df!(
+            "ctg" => ["c1", "c1", "c1"],
+            "st" => [0i64, 100, 200],
+            "end" => [100i64, 200, 300],
+        )
+        .unwrap();
+        // 0-100 has no SUNKs, 100-200 has SUNKs but no read hits, and 200-300 has both.
+        let df_asm_sunks = df!("ctg" => ["c1", "c1"], "cpos" => [150i64, 250]).unwrap();
+        let df_read_sunks = df!("ctg" => ["c1", "c1"], "cpos" => [250i64, 250]).unwrap();
+        let labels = |df: &DataFrame| -> Vec<Option<String>> {
+            df.column("label")
+                .unwrap()
+                .str()
+                .unwrap()
+                .iter()
+                .map(|label| label.map(str::to_owned))
+                .collect()
+        };
+
+        let df = label_dropouts(&df_unsupported, &df_asm_sunks, &df_read_sunks, None, 5.0).unwrap();
+        assert_eq!(
+            labels(&df),
+            [NO_SUNKS, NO_READS, INCONSISTENT_SUNKS].map(|label| Some(label.to_owned()))
+        );
+        assert_eq!(
+            df.column("read_sunks")
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<u64>>(),
+            [0, 0, 2]
+        );
+
+        // With depth, 200-300 has too few reads despite their SUNK hits.
+        let depth = HashMap::from([("c1".to_owned(), vec![(100, 200, 10.0), (200, 300, 2.0)])]);
+        let df = label_dropouts(
+            &df_unsupported,
+            &df_asm_sunks,
+            &df_read_sunks,
+            Some(&depth),
+            5.0,
+        )
+        .unwrap();
+        assert_eq!(
+            labels(&df),
+            [NO_SUNKS, INCONSISTENT_SUNKS, NO_READS].map(|label| Some(label.to_owned()))
+        );
+    }
+}
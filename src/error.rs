@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use polars::prelude::PolarsError;
+use thiserror::Error;
+
+use crate::cancel::Cancelled;
+
+/// Result type of library functions.
+pub type Result<T> = std::result::Result<T, GavisunkError>;
+
+/// Errors returned by library functions.
+#[derive(Debug, Error)]
+pub enum GavisunkError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// Missing or mistyped columns and other [`DataFrame`](polars::prelude::DataFrame) errors.
+    #[error(transparent)]
+    Schema(#[from] PolarsError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Value out of range: {0}")]
+    OutOfRange(#[from] std::num::TryFromIntError),
+    #[error("Invalid integer: {0}")]
+    ParseInt(#[from] std::num::ParseIntError),
+    #[error("Invalid number: {0}")]
+    ParseFloat(#[from] std::num::ParseFloatError),
+    #[error(transparent)]
+    Cancelled(#[from] Cancelled),
+    #[error("Failed to read index for {path:?}: {source}")]
+    Index {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to move {from:?} to {to:?}: {source}")]
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid parameters file {path:?}: {source}")]
+    InvalidParams {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("{path:?} was produced with different parameters: {diffs}. Remove it or rerun with matching parameters.")]
+    MismatchedParams { path: PathBuf, diffs: String },
+    #[error("{path:?} is in intermediate format {found}, produced by {produced_by}, but this version reads format {expected}. Regenerate it with --force.")]
+    StaleIntermediate {
+        path: PathBuf,
+        found: u32,
+        expected: u32,
+        produced_by: String,
+    },
+    #[error("{path:?} {reason}, ex. from a crashed run. Remove it, rerun with --force, or set --on-empty-intermediate.")]
+    InvalidIntermediate { path: PathBuf, reason: String },
+    #[error("{path:?} needs at least {required} bytes in memory, exceeding the memory budget of {budget} bytes.")]
+    InsufficientMemory {
+        path: PathBuf,
+        required: u64,
+        budget: u64,
+    },
+    #[error("{path:?} from the {stage} stage is missing. Run `rs-gavisunk {stage}` with the same --outdir first.")]
+    MissingStageOutput { path: PathBuf, stage: String },
+    #[error("Invalid memory size: {0:?}")]
+    InvalidMemorySize(String),
+    #[error(
+        "Invalid bandwidth: {0:?}. Expected lower and upper percentiles in [0, 1]. ex. 0.25:0.75"
+    )]
+    InvalidBandwidth(String),
+    #[error("Invalid fraction: {0:?}. Expected a number in (0, 1].")]
+    InvalidFraction(String),
+    #[error("Invalid distance tolerance: {0:?}. Expected a number in (0, 1). ex. 0.1")]
+    InvalidDstTolerance(String),
+    #[error("Invalid kmer size: {0}. Expected 1 to 32.")]
+    InvalidKmerSize(usize),
+    #[error("Invalid region: {0:?}. Expected ctg:start-end. ex. chr1:1000-2000")]
+    InvalidRegion(String),
+    #[error("Invalid haplotype map: {0}")]
+    InvalidHaplotypeMap(String),
+    #[error("Invalid read group map: {0}")]
+    InvalidReadGroupMap(String),
+    #[error("Invalid PAF: {0}")]
+    InvalidPaf(String),
+    #[error("Invalid GFA: {0}")]
+    InvalidGfa(String),
+    #[error("Invalid rename map: {0}")]
+    InvalidRenameMap(String),
+    #[error("Invalid SUNK id: {0:?}. Expected ctg:group. ex. chr1:10301")]
+    InvalidSunkId(String),
+    #[error("{0:?} is gzipped but not bgzipped and cannot be indexed. Recompress it with bgzip, decompress it, or rerun with --allow-recompress.")]
+    PlainGzip(PathBuf),
+    #[error("{0:?} is zstd-compressed without a seek table and cannot be indexed. Recompress it in the seekable format, ex. with t2sz, or with bgzip.")]
+    UnseekableZstd(PathBuf),
+    #[error(
+        "{0:?} is zstd-compressed but zstd support was not built. Rebuild with --features zstd."
+    )]
+    ZstdDisabled(PathBuf),
+    #[error("{0:?} is BAM or CRAM but BAM support was not built. Rebuild with --features bam.")]
+    BamDisabled(PathBuf),
+    #[error("{0:?} is BAM or CRAM, whose records are streamed rather than indexed.")]
+    StreamedReads(PathBuf),
+    #[error("Can't fetch {ctg}:{start}-{stop} from {path:?}: {reason}.")]
+    InvalidFetch {
+        path: PathBuf,
+        ctg: String,
+        start: u64,
+        stop: u64,
+        reason: String,
+    },
+    #[error("{0:?} not found in FASTA.")]
+    MissingSequence(String),
+    #[error("No SUNKs given.")]
+    NoSunks,
+    #[error("Missing columns: {0}")]
+    MissingColumns(String),
+    #[error("Unknown output format {name:?}. Expected one of: {available}")]
+    UnknownOutputFormat { name: String, available: String },
+    #[error("{mismatched} of {checked} sampled read SUNK hits don't match the read sequence. ex. {example}")]
+    MismatchedHits {
+        mismatched: usize,
+        checked: usize,
+        example: String,
+    },
+    #[error("{read} exceeded its SUNK graph budget: {status}")]
+    ReadBudgetExceeded { read: String, status: &'static str },
+    #[error("Invalid SUNK index {path:?}: {reason}")]
+    InvalidSunkIndex { path: PathBuf, reason: String },
+    #[error("Invalid anchor store {path:?}: {reason}")]
+    InvalidAnchorStore { path: PathBuf, reason: String },
+    #[error("Remote mapping failed: {0}")]
+    RemoteMap(String),
+    #[error("Skipped {count} malformed records, more than the maximum of {max}. See warnings for each record or raise --max-malformed-records.")]
+    TooManyMalformedRecords { count: usize, max: usize },
+    #[error("Self-test failed {failed} of {checked} checks. See errors for each check.")]
+    SelfTestFailed { failed: usize, checked: usize },
+}
+
+impl GavisunkError {
+    /// Whether this error is from cancellation via a [`CancelToken`](crate::cancel::CancelToken).
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, GavisunkError::Cancelled(_))
+    }
+}
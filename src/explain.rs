@@ -0,0 +1,582 @@
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+
+use crate::{
+    assign_read_ctg::assign_read_to_ctg_w_ort,
+    cancel::CancelToken,
+    error::{GavisunkError, Result},
+    get_kmers::sunk_id_expr,
+    io::Fasta,
+    map_kmers::{map_sunks_to_reads, read_lengths_df, DedupPolicy},
+    run_state::RunState,
+    sunk_graph::{create_sunk_graph, skipped_reads, GraphParams, MIN_READ_LEN},
+};
+
+/// Rerun a single read through the map, assign, filter, and graph stages and describe each decision.
+///
+/// Per-read graph construction also logs its decisions at the debug level.
+///
+/// # Arguments
+/// * `read`
+///     * Read name.
+/// * `fa`
+///     * Fasta file handle for reads.
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, kmer, group, strand]`
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `df_bad_sunks`
+///     * Bad SUNKs from [`crate::filter_bad_sunks::filter_bad_sunks`]. These depend on all reads so are taken from a previous run.
+///       If [`None`], no SUNKs are masked.
+/// * `bandwidth`
+///     * Percentile band used to assign the read to a contig.
+/// * `dedup`
+///     * How multiple hits of a SUNK group within the read are resolved.
+/// * `params`
+///     * Graph stage parameters.
+/// * `state`
+///     * Run the read's stages are rerun in.
+///
+/// # Returns
+/// * Human-readable trace with one decision per line.
+pub fn explain_read(
+    read: &str,
+    fa: Fasta,
+    df_asm_sunks: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    df_bad_sunks: Option<&DataFrame>,
+    bandwidth: (f64, f64),
+    dedup: DedupPolicy,
+    params: &GraphParams,
+    state: &RunState,
+) -> Result<String> {
+    let cancel = CancelToken::new();
+    let Some(read_len) = fa.lengths().get(read).copied() else {
+        return Err(GavisunkError::MissingSequence(read.to_owned()));
+    };
+    let read_lens = HashMap::from([(read.to_owned(), read_len)]);
+    let mut trace = vec![format!("Read {read} ({read_len} bp)")];
+
+    // Map
+    let df_hits = map_sunks_to_reads(
+        fa,
+        &read_lens,
+        df_asm_sunks,
+        None,
+        None,
+        dedup,
+        None,
+        None,
+        state,
+        &cancel,
+    )?;
+    let n_multi_hits = df_hits
+        .column("hits")?
+        .cast(&DataType::UInt64)?
+        .u64()?
+        .into_iter()
+        .filter(|hits| hits.is_some_and(|hits| hits > 1))
+        .count();
+    trace.push(format!(
+        "[map] {} SUNK hits after {dedup:?} dedup. {n_multi_hits} SUNK groups hit more than once.",
+        df_hits.height()
+    ));
+    let df_ctg_hits = df_hits
+        .clone()
+        .lazy()
+        .group_by([col("ctg")])
+        .agg([
+            col("strand").len().cast(DataType::UInt64).alias("hits"),
+            col("strand")
+                .eq(lit("+"))
+                .sum()
+                .cast(DataType::UInt64)
+                .alias("fwd"),
+            col("cpos").min().alias("st"),
+            col("cpos").max().alias("end"),
+        ])
+        .sort(
+            ["hits"],
+            SortMultipleOptions::default().with_order_descending(true),
+        )
+        .collect()?;
+    for (((ctg, hits), fwd), (st, end)) in df_ctg_hits
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_ctg_hits.column("hits")?.u64()?.iter())
+        .zip(df_ctg_hits.column("fwd")?.u64()?.iter())
+        .zip(
+            df_ctg_hits
+                .column("st")?
+                .i64()?
+                .iter()
+                .zip(df_ctg_hits.column("end")?.i64()?.iter()),
+        )
+    {
+        let (Some(ctg), Some(hits), Some(fwd), Some(st), Some(end)) = (ctg, hits, fwd, st, end)
+        else {
+            continue;
+        };
+        trace.push(format!(
+            "[map] {ctg}:{st}-{end} {hits} hits ({fwd} +, {} -)",
+            hits - fwd
+        ));
+    }
+    if df_hits.height() == 0 {
+        trace.push("[map] No SUNK hits. Read is dropped.".to_owned());
+        return Ok(trace.join("\n"));
+    }
+
+    // Assign
+    let df_assigned = assign_read_to_ctg_w_ort(&df_hits, Some(bandwidth), None, state)?;
+    let (Some(ctg), Some(ort)) = (
+        df_assigned.column("ctg")?.str()?.first(),
+        df_assigned.column("ort")?.str()?.first(),
+    ) else {
+        trace.push(format!(
+            "[assign] No contig with more than one SUNK within the {}-{} percentile band. Read is dropped.",
+            bandwidth.0, bandwidth.1
+        ));
+        return Ok(trace.join("\n"));
+    };
+    let sunks_within_bandwidth = df_assigned
+        .column("sunks_within_bandwidth")?
+        .cast(&DataType::UInt64)?
+        .u64()?
+        .first()
+        .unwrap_or_default();
+    trace.push(format!(
+        "[assign] Assigned to {ctg} ({ort}) with {sunks_within_bandwidth} SUNKs within the {}-{} percentile band.",
+        bandwidth.0, bandwidth.1
+    ));
+
+    // Filter
+    let df_ctg = df_hits
+        .clone()
+        .lazy()
+        .filter(col("ctg").eq(lit(ctg)))
+        .collect()?;
+    trace.push(format!(
+        "[filter] {} of {} SUNK hits are on {ctg}.",
+        df_ctg.height(),
+        df_hits.height()
+    ));
+    let df_bad_sunks = match df_bad_sunks {
+        Some(df_bad_sunks) => df_bad_sunks.select(["id", "count"])?,
+        None => {
+            trace.push("[filter] No bad SUNKs given. No SUNKs masked.".to_owned());
+            DataFrame::new(vec![
+                Column::new_empty("id".into(), &DataType::String),
+                Column::new_empty("count".into(), &DataType::UInt32),
+            ])?
+        }
+    };
+    let n_masked = df_ctg
+        .clone()
+        .lazy()
+        .with_column(col("group").cast(DataType::Int64))
+        .select([sunk_id_expr().alias("id")])
+        .join(
+            df_bad_sunks.clone().lazy(),
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .collect()?
+        .height();
+    trace.push(format!(
+        "[filter] {n_masked} SUNK hits masked as bad SUNKs."
+    ));
+
+    // Graph
+    if read_len <= MIN_READ_LEN {
+        trace.push(format!(
+            "[graph] Read length is not above {MIN_READ_LEN} bp. Read is dropped."
+        ));
+        return Ok(trace.join("\n"));
+    }
+    if df_ctg.height() - n_masked < 2 {
+        trace.push("[graph] Fewer than two unmasked SUNKs. Read is dropped.".to_owned());
+        return Ok(trace.join("\n"));
+    }
+    let (df_sunks, df_bed) = create_sunk_graph(
+        ctg,
+        &df_ctg,
+        &read_lengths_df(&read_lens)?,
+        asm_lens,
+        &df_bad_sunks,
+        params,
+        state,
+        &cancel,
+    )?;
+    if let Some(skipped) = skipped_reads(state)
+        .into_iter()
+        .find(|skipped| skipped.ctg == ctg && skipped.read == read)
+    {
+        trace.push(format!(
+            "[graph] Read exceeded its SUNK graph budget: {}. Read is skipped.",
+            skipped.status
+        ));
+        return Ok(trace.join("\n"));
+    }
+    if df_sunks.height() == 0 {
+        trace.push(format!(
+            "[graph] No SUNK pairs with read and contig distances within {}%. Read is dropped.",
+            params.dst_tolerance * 100.0
+        ));
+        return Ok(trace.join("\n"));
+    }
+    let proj_cpos = df_sunks.column("proj_cpos")?.i64()?;
+    let max_offset_dev = df_sunks
+        .column("offset_dev")?
+        .i64()?
+        .into_iter()
+        .flatten()
+        .map(|dev| dev.abs())
+        .max()
+        .unwrap_or_default();
+    trace.push(format!(
+        "[graph] Largest consistent component has {} of {} unmasked SUNKs projected to {ctg}:{}-{} with a max offset deviation of {max_offset_dev} bp.",
+        df_sunks.height(),
+        df_ctg.height() - n_masked,
+        proj_cpos.min().unwrap_or_default(),
+        proj_cpos.max().unwrap_or_default(),
+    ));
+    if df_bed.height() == 0 {
+        trace.push(format!(
+            "[graph] Component alone is below {} SUNKs. Support depends on overlapping reads.",
+            params.min_component_sunks
+        ));
+    } else {
+        trace.push(format!(
+            "[graph] Component alone supports {ctg}:{}-{}.",
+            df_bed.column("st")?.i64()?.min().unwrap_or_default(),
+            df_bed.column("end")?.i64()?.max().unwrap_or_default(),
+        ));
+    }
+    Ok(trace.join("\n"))
+}
+
+/// Describe the SUNKs in a contig region and why each read carrying them did or did not support it.
+///
+/// The graph stage is rerun for the whole contig as supporting components depend on all its reads.
+///
+/// # Arguments
+/// * `region`
+///     * `(ctg, st, end)` with 1-based, inclusive coordinates. See [`crate::intervals::parse_region`].
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, kmer, group, ...]`
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, group, strand, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ...]`
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNKs with columns `[id, count]`
+/// * `read_lens`
+///     * Read lengths.
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `params`
+///     * Graph stage parameters.
+/// * `state`
+///     * Run the graph stage is rerun in.
+///
+/// # Returns
+/// * Human-readable trace with one SUNK or read per line.
+pub fn explain_region(
+    region: &(String, i64, i64),
+    df_asm_sunks: &DataFrame,
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    df_bad_sunks: &DataFrame,
+    read_lens: &HashMap<String, u64>,
+    asm_lens: &HashMap<String, u64>,
+    params: &GraphParams,
+    state: &RunState,
+) -> Result<String> {
+    let (ctg, st, end) = region;
+    let in_region = || {
+        col("ctg")
+            .eq(lit(ctg.as_str()))
+            .and(col("cpos").gt_eq(lit(*st)))
+            .and(col("cpos").lt_eq(lit(*end)))
+    };
+    let with_pos = |df: &DataFrame| {
+        df.clone().lazy().with_columns([
+            col("cpos").cast(DataType::Int64),
+            col("group").cast(DataType::Int64),
+        ])
+    };
+    let lf_bad_sunks = df_bad_sunks
+        .clone()
+        .lazy()
+        .select([col("id"), col("count").alias("bad_count")]);
+    let mut trace = vec![format!("Region {ctg}:{st}-{end}")];
+
+    // SUNKs in the region with their bad SUNK status and number of reads carrying them.
+    let lf_region_read_sunks = with_pos(df_read_sunks)
+        .filter(in_region())
+        .with_column(sunk_id_expr().alias("id"));
+    let df_sunks = with_pos(df_asm_sunks)
+        .filter(in_region())
+        .group_by([col("ctg"), col("group")])
+        .agg([
+            col("cpos").min().alias("st"),
+            col("cpos").max().alias("end"),
+            col("cpos").len().cast(DataType::UInt64).alias("kmers"),
+        ])
+        .with_column(sunk_id_expr().alias("id"))
+        .join(
+            lf_bad_sunks.clone(),
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .join(
+            lf_region_read_sunks
+                .clone()
+                .group_by([col("id")])
+                .agg([col("read").n_unique().cast(DataType::UInt64).alias("reads")]),
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .with_column(col("bad_count").cast(DataType::Int64))
+        .sort(["st"], Default::default())
+        .collect()?;
+    let mut n_bad_sunks = 0;
+    let mut region_ids = HashSet::new();
+    for ((id, (sunk_st, sunk_end)), ((kmers, bad_count), reads)) in df_sunks
+        .column("id")?
+        .str()?
+        .iter()
+        .zip(
+            df_sunks
+                .column("st")?
+                .i64()?
+                .iter()
+                .zip(df_sunks.column("end")?.i64()?.iter()),
+        )
+        .zip(
+            df_sunks
+                .column("kmers")?
+                .u64()?
+                .iter()
+                .zip(df_sunks.column("bad_count")?.i64()?.iter())
+                .zip(df_sunks.column("reads")?.u64()?.iter()),
+        )
+    {
+        let (Some(id), Some(sunk_st), Some(sunk_end), Some(kmers)) = (id, sunk_st, sunk_end, kmers)
+        else {
+            continue;
+        };
+        region_ids.insert(id);
+        let status = match bad_count {
+            Some(count) => {
+                n_bad_sunks += 1;
+                format!("bad ({count} read hits)")
+            }
+            None => "good".to_owned(),
+        };
+        trace.push(format!(
+            "[sunk] {id} {sunk_st}-{sunk_end} ({kmers} kmers) {status}, carried by {} reads.",
+            reads.unwrap_or_default()
+        ));
+    }
+    trace.push(format!(
+        "[sunk] {} SUNKs in region. {n_bad_sunks} are bad.",
+        region_ids.len()
+    ));
+    if region_ids.is_empty() {
+        return Ok(trace.join("\n"));
+    }
+
+    // Rerun the graph stage for the contig.
+    let df_ctg = df_read_sunks
+        .clone()
+        .lazy()
+        .filter(col("ctg").eq(lit(ctg.as_str())))
+        .collect()?;
+    let (df_graph_sunks, _) = create_sunk_graph(
+        ctg,
+        &df_ctg,
+        &read_lengths_df(read_lens)?,
+        asm_lens,
+        df_bad_sunks,
+        params,
+        state,
+        &CancelToken::new(),
+    )?;
+    // First and last SUNK and number of SUNKs of each component by id.
+    let mut components: HashMap<u64, (i64, i64, u64)> = HashMap::new();
+    let mut component_sunks = HashSet::new();
+    // Components of each read's region SUNKs within its largest consistent component.
+    let mut graph_reads: HashMap<&str, Vec<Option<u64>>> = HashMap::new();
+    for ((read, id), component) in df_graph_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_graph_sunks.column("id")?.str()?.iter())
+        .zip(df_graph_sunks.column("component")?.u64()?.iter())
+    {
+        let (Some(read), Some(id)) = (read, id) else {
+            continue;
+        };
+        let group = id
+            .strip_prefix(ctg.as_str())
+            .and_then(|group| group.strip_prefix(':')?.parse::<i64>().ok());
+        if let (Some(component), Some(group)) = (component, group) {
+            if component_sunks.insert(id) {
+                let (comp_st, comp_end, comp_sunks) =
+                    components.entry(component).or_insert((group, group, 0));
+                *comp_st = (*comp_st).min(group);
+                *comp_end = (*comp_end).max(group);
+                *comp_sunks += 1;
+            }
+        }
+        let read_comps = graph_reads.entry(read).or_default();
+        if region_ids.contains(id) {
+            read_comps.push(component);
+        }
+    }
+    let assignments: HashMap<&str, &str> = df_best_reads_asm
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_best_reads_asm.column("ctg")?.str()?.iter())
+        .flat_map(|(read, ctg)| Some((read?, ctg?)))
+        .collect();
+
+    let df_reads = lf_region_read_sunks
+        .join(
+            lf_bad_sunks,
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .group_by([col("read")])
+        .agg([
+            col("id").len().cast(DataType::UInt64).alias("hits"),
+            col("bad_count")
+                .is_not_null()
+                .sum()
+                .cast(DataType::UInt64)
+                .alias("bad_hits"),
+        ])
+        .sort(["read"], Default::default())
+        .collect()?;
+    let mut n_supporting = 0;
+    for ((read, hits), bad_hits) in df_reads
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_reads.column("hits")?.u64()?.iter())
+        .zip(df_reads.column("bad_hits")?.u64()?.iter())
+    {
+        let (Some(read), Some(hits), Some(bad_hits)) = (read, hits, bad_hits) else {
+            continue;
+        };
+        let assignment = match assignments.get(read) {
+            Some(read_ctg) if read_ctg == ctg => "assigned here".to_owned(),
+            Some(read_ctg) => format!("assigned to {read_ctg}"),
+            None => "unassigned".to_owned(),
+        };
+        let reason = if !matches!(read_lens.get(read), Some(len) if *len > MIN_READ_LEN) {
+            format!("not used: read length is not above {MIN_READ_LEN} bp")
+        } else if bad_hits == hits {
+            "not used: all region SUNKs are bad".to_owned()
+        } else {
+            match graph_reads.get(read) {
+                None => format!("not used: no consistent SUNK pairs on {ctg}"),
+                Some(comps) if comps.is_empty() => {
+                    "not used: region SUNKs not in the read's largest consistent component"
+                        .to_owned()
+                }
+                Some(comps) => match comps.iter().flatten().next() {
+                    Some(comp) => {
+                        n_supporting += 1;
+                        let (comp_st, comp_end, comp_sunks) =
+                            components.get(comp).copied().unwrap_or_default();
+                        format!(
+                            "supports component {comp} ({comp_sunks} SUNKs at {comp_st}-{comp_end}) with {} region SUNKs",
+                            comps.iter().flatten().count()
+                        )
+                    }
+                    None => format!(
+                        "not used: read's component on {ctg} is below {} SUNKs",
+                        params.min_component_sunks
+                    ),
+                },
+            }
+        };
+        trace.push(format!(
+            "[read] {read} {hits} region SUNK hits ({bad_hits} bad), {assignment}. {reason}."
+        ));
+    }
+    trace.push(format!(
+        "[read] {n_supporting} of {} reads support the region.",
+        df_reads.height()
+    ));
+    Ok(trace.join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::explain_read;
+    use crate::{
+        io::{Fasta, ScratchDir},
+        map_kmers::DedupPolicy,
+        run_state::RunState,
+        sunk_graph::GraphParams,
+    };
+
+    #[test]
+    fn test_explain_read() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_explain").unwrap();
+        let path = dir.path().join("reads.fa");
+        std::fs::write(
+            &path,
+            ">read1\nACGTTGCAAGGCTTAACCGG\n>read2\nTTTTTTTTTTTTTTTTTTTT\n",
+        )
+        .unwrap();
+        let df_asm_sunks = df!(
+            "ctg" => ["ctg", "ctg"],
+            "cpos" => [11i64, 31],
+            "kmer" => ["GCAAGGCT", "TTAACCGG"],
+            "strand" => ["+", "+"],
+            "group" => [1i64, 2],
+        )
+        .unwrap();
+        let asm_lens = HashMap::from([("ctg".to_owned(), 100)]);
+        let explain = |read: &str| {
+            explain_read(
+                read,
+                Fasta::new(&path).unwrap(),
+                &df_asm_sunks,
+                &asm_lens,
+                None,
+                (0.25, 0.75),
+                DedupPolicy::First,
+                &GraphParams::default(),
+                &RunState::new(),
+            )
+        };
+
+        // Too short to build a SUNK graph from even if assigned.
+        let trace = explain("read1").unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines[0], "Read read1 (20 bp)");
+        assert!(lines[1].starts_with("[map] 2 SUNK hits after First dedup."));
+        assert!(lines.last().unwrap().ends_with("Read is dropped."));
+
+        let trace = explain("read2").unwrap();
+        assert!(trace.ends_with("[map] No SUNK hits. Read is dropped."));
+        assert!(explain("read3").is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! Export assembly sequence of intervals, ex. unsupported intervals for targeted reassembly or BLAST.
+
+use std::{collections::HashMap, io::Write};
+
+use crate::{error::Result, intervals::CtgIntervals, io::Fasta};
+
+/// Bases per FASTA sequence line.
+const LINE_WIDTH: usize = 80;
+
+/// Write the sequence of each interval extended by flanks as FASTA.
+///
+/// Records are named by their flanked region as `ctg:start-end` with 1-based, inclusive coordinates like `samtools`.
+/// The description is the interval without flanks. ex. `>chr1:9001-21000 interval=chr1:10001-20000`
+///
+/// # Arguments
+/// * `fasta`
+///     * Fasta file handle.
+/// * `intervals`
+///     * 0-based, half-open intervals by FASTA name. See [`crate::intervals::load_bed`].
+/// * `fasta_lens`
+///     * Sequence lengths by FASTA name. Flanks are clamped to the sequence.
+/// * `flank`
+///     * Bases added to each side of an interval.
+/// * `writer`
+///     * Output.
+///
+/// # Returns
+/// * Number of records written. Intervals on sequences not in the FASTA are skipped with a warning.
+pub fn write_flanked_fasta(
+    fasta: &mut Fasta,
+    intervals: &CtgIntervals,
+    fasta_lens: &HashMap<String, u64>,
+    flank: u64,
+    mut writer: impl Write,
+) -> Result<usize> {
+    let mut ctgs: Vec<&String> = intervals.keys().collect();
+    ctgs.sort();
+    let mut n_records = 0;
+    for ctg in ctgs {
+        let Some(ctg_len) = fasta_lens.get(ctg).copied() else {
+            log::warn!("{ctg} not in FASTA. Skipping its intervals.");
+            continue;
+        };
+        for (st, end) in &intervals[ctg] {
+            let (st, end) = (u64::try_from(*st)?, u64::try_from(*end)?.min(ctg_len));
+            if st >= end {
+                continue;
+            }
+            let (flank_st, flank_end) = (st.saturating_sub(flank), (end + flank).min(ctg_len));
+            let bases = fasta.fetch_bytes(ctg, flank_st + 1, flank_end)?;
+            writeln!(
+                writer,
+                ">{ctg}:{}-{flank_end} interval={ctg}:{}-{end}",
+                flank_st + 1,
+                st + 1
+            )?;
+            for line in bases.chunks(LINE_WIDTH) {
+                writer.write_all(line)?;
+                writer.write_all(b"\n")?;
+            }
+            n_records += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(n_records)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::write_flanked_fasta;
+    use crate::io::{Fasta, ScratchDir};
+
+    #[test]
+    fn test_write_flanked_fasta() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_extract").unwrap();
+        let path = dir.path().join("asm.fa");
+        std::fs::write(&path, ">c1\nACGTACGTAC\n").unwrap();
+        let mut fasta = Fasta::new(&path).unwrap();
+        let lens = fasta.lengths();
+        // Flanks and intervals past the end of c1 are clamped. c2 isn't in the FASTA.
+        let intervals = HashMap::from([
+            ("c1".to_owned(), vec![(4, 6), (8, 20)]),
+            ("c2".to_owned(), vec![(0, 2)]),
+        ]);
+        let mut out = vec![];
+        assert_eq!(
+            write_flanked_fasta(&mut fasta, &intervals, &lens, 2, &mut out).unwrap(),
+            2
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ">c1:3-8 interval=c1:5-6\nGTACGT\n>c1:7-10 interval=c1:9-10\nGTAC\n"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Counts of rows kept and dropped by each filter of the pipeline.
+//!
+//! Filters record their counts to the run's [`RunState`] as they run with [`record`]. Counts of a filter run more than
+//! once, ex. once per contig in the graph stage, are summed.
+
+use polars::prelude::*;
+
+use crate::{error::Result, hooks::Stage, run_state::RunState};
+
+/// Read SUNK hits, one per row of read SUNKs.
+pub const SUNK_HITS: &str = "sunk_hits";
+/// Reads. Graph stage filters count a read once per contig it has hits on.
+pub const READS: &str = "reads";
+
+/// Rows kept by a filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterCount {
+    pub stage: Stage,
+    /// Filter name. ex. `bandwidth`
+    pub filter: &'static str,
+    /// What is counted. [`SUNK_HITS`] or [`READS`].
+    pub unit: &'static str,
+    pub input: u64,
+    pub kept: u64,
+}
+
+/// Add the rows passed to and kept by a filter to its counts.
+///
+/// # Arguments
+/// * `stage`
+///     * Stage the filter runs in.
+/// * `filter`
+///     * Filter name.
+/// * `unit`
+///     * What is counted. [`SUNK_HITS`] or [`READS`].
+/// * `input`
+///     * Rows before the filter.
+/// * `kept`
+///     * Rows after the filter.
+/// * `state`
+///     * State of the run the counts are recorded to.
+pub fn record(
+    stage: Stage,
+    filter: &'static str,
+    unit: &'static str,
+    input: usize,
+    kept: usize,
+    state: &RunState,
+) {
+    let Ok(mut counts) = state.filter_counts.lock() else {
+        return;
+    };
+    match counts
+        .iter_mut()
+        .find(|count| count.stage == stage && count.filter == filter && count.unit == unit)
+    {
+        Some(count) => {
+            count.input += input as u64;
+            count.kept += kept as u64;
+        }
+        None => counts.push(FilterCount {
+            stage,
+            filter,
+            unit,
+            input: input as u64,
+            kept: kept as u64,
+        }),
+    }
+}
+
+/// Filter counts recorded to a run so far in the order filters first ran.
+pub fn filter_counts(state: &RunState) -> Vec<FilterCount> {
+    state
+        .filter_counts
+        .lock()
+        .map(|counts| counts.clone())
+        .unwrap_or_default()
+}
+
+/// Rows dropped by each filter recorded to a run so far.
+///
+/// Filters skipped because their stage's intermediate was loaded from a previous run aren't recorded.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[step, stage, filter, unit, input, kept, dropped]`
+///     * `step` is the 1-based order filters first ran in.
+pub fn filter_audit(state: &RunState) -> Result<DataFrame> {
+    let counts = filter_counts(state);
+    Ok(DataFrame::new(vec![
+        Column::new(
+            "step".into(),
+            (1..=counts.len() as u64).collect::<Vec<u64>>(),
+        ),
+        Column::new(
+            "stage".into(),
+            counts
+                .iter()
+                .map(|count| count.stage.to_string())
+                .collect::<Vec<String>>(),
+        ),
+        Column::new(
+            "filter".into(),
+            counts
+                .iter()
+                .map(|count| count.filter)
+                .collect::<Vec<&str>>(),
+        ),
+        Column::new(
+            "unit".into(),
+            counts.iter().map(|count| count.unit).collect::<Vec<&str>>(),
+        ),
+        Column::new(
+            "input".into(),
+            counts.iter().map(|count| count.input).collect::<Vec<u64>>(),
+        ),
+        Column::new(
+            "kept".into(),
+            counts.iter().map(|count| count.kept).collect::<Vec<u64>>(),
+        ),
+        Column::new(
+            "dropped".into(),
+            counts
+                .iter()
+                .map(|count| count.input.saturating_sub(count.kept))
+                .collect::<Vec<u64>>(),
+        ),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{filter_counts, record, FilterCount, READS, SUNK_HITS};
+    use crate::{hooks::Stage, run_state::RunState};
+
+    #[test]
+    fn test_record() {
+        let state = RunState::default();
+        record(Stage::Filter, "bad_sunks", SUNK_HITS, 100, 90, &state);
+        record(Stage::Graph, "read_length", READS, 10, 8, &state);
+        // Another contig.
+        record(Stage::Filter, "bad_sunks", SUNK_HITS, 50, 50, &state);
+        // Runs don't share counts.
+        record(
+            Stage::Filter,
+            "bad_sunks",
+            SUNK_HITS,
+            1,
+            0,
+            &RunState::default(),
+        );
+        assert_eq!(
+            filter_counts(&state),
+            [
+                FilterCount {
+                    stage: Stage::Filter,
+                    filter: "bad_sunks",
+                    unit: SUNK_HITS,
+                    input: 150,
+                    kept: 140,
+                },
+                FilterCount {
+                    stage: Stage::Graph,
+                    filter: "read_length",
+                    unit: READS,
+                    input: 10,
+                    kept: 8,
+                },
+            ]
+        );
+    }
+}
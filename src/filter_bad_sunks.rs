@@ -1,36 +1,346 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
 use polars::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    error::{GavisunkError, Result},
+    get_kmers::{sunk_id, sunk_id_expr},
+    intervals::{contains, load_bed},
+    naming::ContigRenames,
+};
+
+/// Read counts at or below this aren't used to estimate the mode. Low counts are dominated by sequencing errors.
+pub const MIN_MODE_COUNT: u32 = 2;
+/// Deviations above the mode, as the root of the mode, before a SUNK group has abnormal read support.
+const MAX_DEVIATIONS: u64 = 4;
+
+/// SUNK group has abnormal read support and is filtered.
+pub const BAD: &str = "bad";
+/// Count isn't used to estimate the mode.
+pub const IGNORED: &str = "ignored";
+/// SUNK group is kept.
+pub const OK: &str = "ok";
+
+/// Read count cutoffs used to find bad SUNK groups.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BadSunkCutoffs {
+    /// Most frequent read count above `min_mode_count`. [`None`] if no SUNK group has more reads.
+    pub mode: Option<u32>,
+    pub min_mode_count: u32,
+    /// SUNK groups with more reads than this are bad. Only reported. See [`BadSunkCutoffs::status`].
+    pub max_count: Option<f64>,
+}
+
+impl BadSunkCutoffs {
+    /// Estimate cutoffs from a histogram of read counts.
+    ///
+    /// # Arguments
+    /// * `histogram`
+    ///     * `(count, sunk_groups)` pairs. Ties for the mode are broken by the lower count.
+    pub fn new(histogram: &[(u32, u64)]) -> Self {
+        let mode = histogram
+            .iter()
+            .filter(|(count, _)| *count > MIN_MODE_COUNT)
+            .max_by(|(count_a, n_a), (count_b, n_b)| n_a.cmp(n_b).then(count_b.cmp(count_a)))
+            .map(|(count, _)| *count);
+        Self {
+            mode,
+            min_mode_count: MIN_MODE_COUNT,
+            // Root mean square of Poisson counts around the mode.
+            // https://mathworld.wolfram.com/Root-Mean-Square.html
+            max_count: mode.map(|mode| mode as f64 + (mode as f64).sqrt() * MAX_DEVIATIONS as f64),
+        }
+    }
+
+    /// Whether a SUNK group with `count` reads is [`BAD`], [`IGNORED`], or [`OK`].
+    ///
+    /// Groups are bad with more reads than `max_count`, compared exactly in integers as `(count - mode)² > 16 · mode`.
+    /// Groups with exactly `max_count` reads are [`OK`].
+    pub fn status(&self, count: u32) -> &'static str {
+        if count <= self.min_mode_count {
+            IGNORED
+        } else if self.mode.is_some_and(|mode| {
+            count > mode && u64::from(count - mode).pow(2) > MAX_DEVIATIONS.pow(2) * u64::from(mode)
+        }) {
+            BAD
+        } else {
+            OK
+        }
+    }
+}
 
-pub fn filter_bad_sunks(df_sunks: &DataFrame) -> eyre::Result<DataFrame> {
-    let df = df_sunks
+/// Read counts of each SUNK group.
+fn sunk_counts(df_sunks: &DataFrame) -> Result<DataFrame> {
+    Ok(df_sunks
         .select(["ctg", "group"])?
         .lazy()
-        .with_column((col("ctg") + lit(":") + col("group").cast(DataType::String)).alias("id"))
+        .with_column(sunk_id_expr().alias("id"))
         .group_by(["id"])
         // Get count of ctg+group
         .agg([col("ctg").len().alias("count")])
-        // Calculate mode. Based on distribution of kmers. Dependent on sequencing technology.
-        // Histogram of ONT kmer counts.
-        // * x is kmer count
-        // * y is the number of times x kmer count occurs.
-        // * Left skewed due to error rate. We filter
-        // \
-        //  \     *
-        //  |    /-\
-        //  \___/   \_ /
-        // 1 2 3 4 5 6 7
-        .filter(col("count").gt(lit(2)))
-        .with_column(col("count").mode().first().alias("mean_count"));
-
-    // dbg!("{}", df.clone().collect()?);
-
-    // Filter SUNKs with count less than 2 or are 4 root mean square/stdev above the mean.
-    // https://mathworld.wolfram.com/Root-Mean-Square.html
-    Ok(df
+        .collect()?)
+}
+
+/// Histogram of SUNK group read counts and the cutoffs chosen from it.
+///
+/// Distribution of read counts. Dependent on sequencing technology.
+/// * x is the read count of a SUNK group.
+/// * y is the number of SUNK groups with x reads.
+/// * Left skewed due to error rate. Counts at or below [`MIN_MODE_COUNT`] are ignored.
+/// ```text
+/// \
+///  \     *
+///  |    /-\
+///  \___/   \_ /
+/// 1 2 3 4 5 6 7
+/// ```
+///
+/// # Arguments
+/// * `df_sunks`
+///     * [`DataFrame`] of read SUNKs on assigned contigs with columns `[ctg, group, ...]`
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[count, sunk_groups, status]`
+///     * `status` is [`BAD`], [`IGNORED`], or [`OK`].
+/// * [`BadSunkCutoffs`]
+pub fn sunk_count_histogram(df_sunks: &DataFrame) -> Result<(DataFrame, BadSunkCutoffs)> {
+    count_histogram(&sunk_counts(df_sunks)?)
+}
+
+fn count_histogram(df_counts: &DataFrame) -> Result<(DataFrame, BadSunkCutoffs)> {
+    let mut df_hist = df_counts
+        .clone()
+        .lazy()
+        .group_by([col("count")])
+        .agg([col("id").len().cast(DataType::UInt64).alias("sunk_groups")])
+        .sort(["count"], Default::default())
+        .collect()?;
+    let histogram: Vec<(u32, u64)> = df_hist
+        .column("count")?
+        .u32()?
+        .iter()
+        .zip(df_hist.column("sunk_groups")?.u64()?.iter())
+        .flat_map(|(count, n)| Some((count?, n?)))
+        .collect();
+    let cutoffs = BadSunkCutoffs::new(&histogram);
+    let statuses: Vec<&str> = histogram
+        .iter()
+        .map(|(count, _)| cutoffs.status(*count))
+        .collect();
+    df_hist.with_column(Column::new("status".into(), statuses))?;
+    Ok((df_hist, cutoffs))
+}
+
+/// Find SUNK groups with abnormal read support.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[id, count]` where `id` is the [`crate::get_kmers::sunk_id`].
+pub fn filter_bad_sunks(df_sunks: &DataFrame) -> Result<DataFrame> {
+    let df_counts = sunk_counts(df_sunks)?;
+    let (_, cutoffs) = count_histogram(&df_counts)?;
+    let Some(max_count) = cutoffs.max_count else {
+        return Ok(df_counts.clear());
+    };
+    Ok(df_counts
+        .lazy()
         .filter(
             col("count")
-                .gt(col("mean_count") + col("mean_count").sqrt() * lit(4))
-                .or(col("count").lt(2)),
+                .gt(lit(cutoffs.min_mode_count))
+                .and(col("count").cast(DataType::Float64).gt(lit(max_count))),
         )
         .select([col("id"), col("count")])
         .collect()?)
 }
+
+/// Load known bad SUNK groups, ex. in rDNA, to mask in addition to those found by [`filter_bad_sunks`].
+///
+/// The file is either one [`sunk_id`] per line or a BED file of assembly regions. Regions are translated to the
+/// groups of SUNKs starting within them. Contig names are renamed with `renames` to match `df_asm_sunks`.
+///
+/// # Arguments
+/// * `path`
+///     * SUNK IDs or BED file. A file is read as BED if its first record has at least three tab-separated columns.
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, group, ...]`
+/// * `renames`
+///     * Contig renames applied to the assembly.
+///
+/// # Returns
+/// * [`DataFrame`] with column `[id]`
+pub fn load_extra_bad_sunks(
+    path: impl AsRef<Path>,
+    df_asm_sunks: &DataFrame,
+    renames: &ContigRenames,
+) -> Result<DataFrame> {
+    let path = path.as_ref();
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let is_bed = lines
+        .find_map(|line| {
+            line.map(|line| {
+                let line = line.trim_end();
+                let is_header = ["#", "track", "browser"]
+                    .iter()
+                    .any(|prefix| line.starts_with(prefix));
+                (!line.is_empty() && !is_header).then(|| line.split('\t').count() >= 3)
+            })
+            .transpose()
+        })
+        .transpose()?
+        .unwrap_or(false);
+
+    let mut ids: HashSet<String> = HashSet::new();
+    if is_bed {
+        let regions = renames.rename_keys(load_bed(path)?)?;
+        let cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+        let group = df_asm_sunks.column("group")?.cast(&DataType::Int64)?;
+        for ((ctg, cpos), group) in df_asm_sunks
+            .column("ctg")?
+            .str()?
+            .iter()
+            .zip(cpos.i64()?.iter())
+            .zip(group.i64()?.iter())
+        {
+            let (Some(ctg), Some(cpos), Some(group)) = (ctg, cpos, group) else {
+                continue;
+            };
+            // SUNK positions are 1-based.
+            if regions
+                .get(ctg)
+                .is_some_and(|intervals| contains(intervals, cpos - 1))
+            {
+                ids.insert(sunk_id(ctg, group));
+            }
+        }
+    } else {
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let Some(id) = line.split_ascii_whitespace().next() else {
+                continue;
+            };
+            if id.starts_with('#') {
+                continue;
+            }
+            let (ctg, group) = id
+                .rsplit_once(':')
+                .ok_or_else(|| GavisunkError::InvalidSunkId(id.to_owned()))?;
+            let group: i64 = group
+                .parse()
+                .map_err(|_| GavisunkError::InvalidSunkId(id.to_owned()))?;
+            ids.insert(sunk_id(renames.rename_name(ctg), group));
+        }
+    }
+    if ids.is_empty() {
+        log::warn!("No SUNK groups found in {path:?}.");
+    }
+    let mut ids: Vec<String> = ids.into_iter().collect();
+    ids.sort_unstable();
+    Ok(df!("id" => ids)?)
+}
+
+/// Add known bad SUNK groups to those found by [`filter_bad_sunks`].
+///
+/// # Arguments
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNKs with columns `[id, count]`
+/// * `df_sunks`
+///     * [`DataFrame`] of read SUNKs on assigned contigs with columns `[ctg, group, ...]`
+/// * `df_extra_bad_sunks`
+///     * [`DataFrame`] of known bad SUNKs with column `[id]`. See [`load_extra_bad_sunks`].
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[id, count]`
+///     * Known bad SUNK groups without read hits are left out as there is nothing to mask.
+pub fn merge_extra_bad_sunks(
+    df_bad_sunks: &DataFrame,
+    df_sunks: &DataFrame,
+    df_extra_bad_sunks: &DataFrame,
+) -> Result<DataFrame> {
+    let df_extra = sunk_counts(df_sunks)?
+        .lazy()
+        .join(
+            df_extra_bad_sunks.select(["id"])?.lazy(),
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .select([col("id"), col("count")]);
+    // Counts are unsigned when computed but inferred as signed when loaded from an existing file.
+    let df_merged = concat(
+        [
+            df_bad_sunks
+                .clone()
+                .lazy()
+                .select([col("id"), col("count").cast(DataType::UInt32)]),
+            df_extra,
+        ],
+        UnionArgs::default(),
+    )?
+    .unique_stable(Some(vec!["id".into()]), UniqueKeepStrategy::First)
+    .collect()?;
+    log::info!(
+        "Masking {} known bad SUNK groups with read hits in addition to {} computed.",
+        df_merged.height() - df_bad_sunks.height(),
+        df_bad_sunks.height()
+    );
+    Ok(df_merged)
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{load_extra_bad_sunks, BadSunkCutoffs, BAD, IGNORED, OK};
+    use crate::{io::ScratchDir, naming::ContigRenames};
+
+    #[test]
+    fn test_bad_sunk_cutoffs() {
+        let cutoffs = BadSunkCutoffs::new(&[(1, 100), (2, 50), (4, 10), (5, 10), (6, 3), (30, 1)]);
+        assert_eq!(cutoffs.mode, Some(4));
+        assert_eq!(cutoffs.max_count, Some(12.0));
+        assert_eq!(cutoffs.status(1), IGNORED);
+        assert_eq!(cutoffs.status(12), OK);
+        assert_eq!(cutoffs.status(13), BAD);
+        assert_eq!(cutoffs.status(30), BAD);
+        // A mode without an integer root. 5 + 4√5 is about 13.9.
+        let cutoffs = BadSunkCutoffs::new(&[(5, 10)]);
+        assert_eq!(cutoffs.status(13), OK);
+        assert_eq!(cutoffs.status(14), BAD);
+        assert_eq!(BadSunkCutoffs::new(&[(1, 100)]).max_count, None);
+    }
+
+    #[test]
+    fn test_load_extra_bad_sunks() {
+        let df_asm_sunks = df!(
+            "ctg" => ["c1", "c1", "c1", "c2"],
+            "cpos" => [10u64, 11, 500, 10],
+            "group" => [10u64, 10, 500, 10],
+        )
+        .unwrap();
+        let ids = |path: &std::path::Path| -> Vec<String> {
+            load_extra_bad_sunks(path, &df_asm_sunks, &ContigRenames::default())
+                .unwrap()
+                .column("id")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .map(|id| id.to_owned())
+                .collect()
+        };
+        let dir =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_extra_bad_sunks").unwrap();
+        let path_ids = dir.path().join("bad_sunks.txt");
+        std::fs::write(&path_ids, "# rDNA\nc1:500\nc2:10\n").unwrap();
+        assert_eq!(ids(&path_ids), ["c1:500", "c2:10"]);
+
+        let path_bed = dir.path().join("bad_sunks.bed");
+        std::fs::write(&path_bed, "c1\t0\t100\n").unwrap();
+        assert_eq!(ids(&path_bed), ["c1:10"]);
+    }
+}
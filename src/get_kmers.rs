@@ -1,51 +1,195 @@
 use core::str;
-use std::{collections::HashMap, ops::Deref};
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::{BuildHasher, RandomState},
+    io::{BufReader, BufWriter, Read, Write},
+    ops::Deref,
+    path::Path,
+    sync::Mutex,
+};
 
-use super::io::Fasta;
-use kmers::{self, Kmer};
+use super::{
+    cancel::CancelToken,
+    error::{GavisunkError, Result},
+    io::{Fasta, ScratchDir},
+};
 use polars::prelude::*;
 use rayon::prelude::*;
 
+/// Default kmer size of SUNKs.
+pub const DEFAULT_KMER_SIZE: usize = 20;
+/// Largest kmer size that can be packed into a [`u64`].
+pub const MAX_KMER_SIZE: usize = 32;
+/// Bytes of a kmer occurence spilled to a bucket: packed kmer, contig index, 1-based position, and strand.
+const SPILLED_KMER_BYTES: usize = 17;
+/// Kmer occurences buffered per bucket by each contig before they're written.
+const SPILL_BUFFER_KMERS: usize = 4096;
+
+/// Build the stable id of a SUNK group. ex. `chr1:10301`
+///
+/// A SUNK group is a run of overlapping SUNKs and `group` is the 1-based contig position of its first SUNK.
+/// Ids only depend on the assembly and kmer size, so outputs from runs sharing both can be joined on them.
+pub fn sunk_id(ctg: &str, group: i64) -> String {
+    format!("{ctg}:{group}")
+}
+
+/// Expression building [`sunk_id`]s from `ctg` and `group` columns.
+pub fn sunk_id_expr() -> Expr {
+    col("ctg") + lit(":") + col("group").cast(DataType::String)
+}
+
+pub(crate) fn base_code(base: u8) -> Option<u64> {
+    match base {
+        b'A' | b'a' => Some(0),
+        b'C' | b'c' => Some(1),
+        b'G' | b'g' => Some(2),
+        b'T' | b't' => Some(3),
+        _ => None,
+    }
+}
+
+/// Pack a k-mer 2 bits per base with the first base most significant so packed k-mers sort like their sequences.
+///
+/// # Returns
+/// * [`None`] if the k-mer has bases other than `ACGT` (case-insensitive).
+pub fn pack_kmer(kmer: &[u8]) -> Option<u64> {
+    kmer.iter()
+        .try_fold(0u64, |packed, base| Some((packed << 2) | base_code(*base)?))
+}
+
+/// Unpack a k-mer packed with [`pack_kmer`] as uppercase bases.
+pub fn unpack_kmer(packed: u64, kmer_size: usize) -> String {
+    (0..kmer_size)
+        .rev()
+        .map(|i| char::from(b"ACGT"[((packed >> (2 * i)) & 3) as usize]))
+        .collect()
+}
+
+/// Iterate through the canonical k-mers of a sequence, the lesser of each packed k-mer and its reverse complement.
+///
+/// # Arguments
+/// * `seq`
+///     * Sequence. k-mers with bases other than `ACGT` (case-insensitive) are skipped.
+/// * `kmer_size`
+///     * kmer size from 1 to [`MAX_KMER_SIZE`].
+/// * `f`
+///     * Called with the 0-based position, the canonical k-mer packed with [`pack_kmer`], and whether it's the
+///       forward strand. Palindromes are on the forward strand.
+pub fn with_canonical_kmers(seq: &[u8], kmer_size: usize, mut f: impl FnMut(usize, u64, bool)) {
+    let mask = u64::MAX >> (64 - 2 * kmer_size);
+    let rc_shift = 2 * (kmer_size - 1);
+    let (mut fwd, mut rc, mut n_valid) = (0u64, 0u64, 0);
+    for (i, base) in seq.iter().enumerate() {
+        let Some(code) = base_code(*base) else {
+            n_valid = 0;
+            continue;
+        };
+        fwd = ((fwd << 2) | code) & mask;
+        rc = (rc >> 2) | ((3 - code) << rc_shift);
+        n_valid += 1;
+        if n_valid >= kmer_size {
+            f(i + 1 - kmer_size, fwd.min(rc), fwd <= rc);
+        }
+    }
+}
+
 /// Extract all k-mers counts and starting positions from a given sequence.
 /// * See 1.1.1 Counting k-mers in sequencing reads
 ///     * https://www.genome.umd.edu/docs/JellyfishUserGuide.pdf
 ///
 /// # Arguments
 /// * `fasta`
-///     * Fasta file handle
+///     * Fasta file handle.
 /// * `name`
 ///     * Name of sequence.
 /// * `len`
 ///     * Length of sequence.
 /// * `kmer_size`
-///     * kmer size.
+///     * kmer size from 1 to [`MAX_KMER_SIZE`].
 ///
 /// # Returns
-/// * Map of kmers with the their count and first encountered position.
+/// * Map of canonical kmers packed with [`pack_kmer`] with their count on both strands, first encountered position,
+///   and whether that occurence is on the forward strand.
+/// * [`GavisunkError::InvalidKmerSize`] if `kmer_size` is out of range.
 pub fn get_kmer_counts_pos(
-    fasta: &str,
+    fasta: &mut Fasta,
     name: &str,
     len: u64,
     kmer_size: usize,
-) -> eyre::Result<HashMap<Kmer, (usize, usize)>> {
-    let mut fh = Fasta::new(fasta)?;
-    let rec = fh.fetch(name, 1, len.try_into()?)?;
-    let mut indices: HashMap<Kmer, (usize, usize)> = HashMap::new();
-    // Get both fwd and revcomp kmers.
-    // Keep track of count and first occurence.
-    Kmer::with_many_both_pos(kmer_size, rec.sequence(), |pos, x, y| {
-        indices
-            .entry(x.clone())
-            .and_modify(|(cnt, _)| *cnt += 1)
-            .or_insert((1, pos + 1));
+) -> Result<HashMap<u64, (usize, usize, bool)>> {
+    if !(1..=MAX_KMER_SIZE).contains(&kmer_size) {
+        return Err(GavisunkError::InvalidKmerSize(kmer_size));
+    }
+    let bases = fasta.fetch_bytes(name, 1, len)?;
+    let mut indices: HashMap<u64, (usize, usize, bool)> = HashMap::new();
+    // A kmer and its revcomp share one entry so each occurence is counted once.
+    // Keep track of count, first occurence, and strand of first occurence.
+    with_canonical_kmers(&bases, kmer_size, |pos, kmer, is_fwd| {
         indices
-            .entry(y.clone())
-            .and_modify(|(cnt, _)| *cnt += 1)
-            .or_insert((1, pos + 1));
+            .entry(kmer)
+            .and_modify(|(cnt, _, _)| *cnt += 1)
+            .or_insert((1, pos + 1, is_fwd));
     });
     Ok(indices)
 }
 
+/// Kmer counts summed across all sequences, partitioned into shards by hash.
+///
+/// Each shard is merged independently so summing counts scales with cores instead of folding into one map.
+struct KmerCountShards {
+    hasher: RandomState,
+    shards: Vec<HashMap<u64, usize>>,
+}
+
+impl KmerCountShards {
+    /// Only SUNKs are kept unless `keep_repeats` is set.
+    fn new(
+        all_kmer_indices: &HashMap<&str, HashMap<u64, (usize, usize, bool)>>,
+        n_shards: usize,
+        keep_repeats: bool,
+    ) -> Self {
+        let hasher = RandomState::new();
+        let n_shards = n_shards.max(1);
+        // Bucket each sequence's kmers by shard.
+        let buckets: Vec<Vec<Vec<(u64, usize)>>> = all_kmer_indices
+            .par_iter()
+            .map(|(_, kmers)| {
+                let mut buckets = vec![vec![]; n_shards];
+                for (kmer, (cnt, _, _)) in kmers.iter() {
+                    buckets[hasher.hash_one(kmer) as usize % n_shards].push((*kmer, *cnt));
+                }
+                buckets
+            })
+            .collect();
+        // Then merge each shard across sequences.
+        let shards = (0..n_shards)
+            .into_par_iter()
+            .map(|shard| {
+                let mut cnts: HashMap<u64, usize> = HashMap::new();
+                for (kmer, cnt) in buckets.iter().flat_map(|b| b[shard].iter()) {
+                    *cnts.entry(*kmer).or_default() += cnt;
+                }
+                if !keep_repeats {
+                    cnts.retain(|_, cnt| *cnt == 1);
+                }
+                cnts
+            })
+            .collect();
+        Self { hasher, shards }
+    }
+
+    fn count(&self, kmer: u64) -> Option<usize> {
+        self.shards[self.hasher.hash_one(kmer) as usize % self.shards.len()]
+            .get(&kmer)
+            .copied()
+    }
+
+    fn is_sunk(&self, kmer: u64) -> bool {
+        self.count(kmer) == Some(1)
+    }
+}
+
 /// Get singlely unique kmers in the give fasta file of `kmer_size`.
 ///
 /// # Arguments
@@ -53,55 +197,259 @@ pub fn get_kmer_counts_pos(
 ///     * Fasta file handle.
 /// * `kmer_size`
 ///     * kmer size.
+/// * `min_kmer_count`
+///     * Also return all kmers occuring at least this many times in the assembly. Useful to check why a region has no SUNKs.
+/// * `cancel`
+///     * Token checked before counting each sequence.
 /// # Returns
-/// * [`DataFrame`] of SUNK positions with columns `[name, start, kmer, group]`.
+/// * [`DataFrame`] of SUNK positions with columns `[ctg, cpos, kmer, strand, group]`.
+///     * `kmer` is the canonical SUNK. A kmer and its revcomp are the same SUNK and occur once in total.
+///     * `strand` is `+` if the SUNK is found on the forward strand of the contig and `-` otherwise.
+/// * [`DataFrame`] of kmer counts with columns `[ctg, cpos, kmer, strand, count]` if `min_kmer_count` is given.
+///     * `cpos` is the first position of the kmer in the contig and `count` is its total count in the assembly.
 pub fn get_sunk_positions(
     fasta: Fasta,
     fasta_lens: &HashMap<String, u64>,
     kmer_size: usize,
-) -> eyre::Result<DataFrame> {
-    let mut all_kmer_indices: HashMap<&str, HashMap<Kmer, (usize, usize)>> = fasta_lens
+    min_kmer_count: Option<usize>,
+    cancel: &CancelToken,
+) -> Result<(DataFrame, Option<DataFrame>)> {
+    let mut all_kmer_indices: HashMap<&str, HashMap<u64, (usize, usize, bool)>> = fasta_lens
         .into_par_iter()
         .map(|(name, len)| {
-            let kmer_indices =
-                get_kmer_counts_pos(fasta.fname.to_str().unwrap(), name, *len, kmer_size).unwrap();
-            (name.deref(), kmer_indices)
+            cancel.check()?;
+            let mut fh = fasta.try_clone()?;
+            let kmer_indices = get_kmer_counts_pos(&mut fh, name, *len, kmer_size)?;
+            Ok((name.deref(), kmer_indices))
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
     // Sum up kmer counts across all sequences.
-    let mut kmer_cnts: HashMap<Kmer, usize> =
-        all_kmer_indices.values().fold(HashMap::new(), |mut a, b| {
-            for (kmer, (cnt, _)) in b.iter() {
-                *a.entry(kmer.clone()).or_default() += *cnt
-            }
-            a
-        });
-    // Only get SUNKs.
-    kmer_cnts.retain(|_, cnt| *cnt == 1);
+    let kmer_cnts = KmerCountShards::new(
+        &all_kmer_indices,
+        rayon::current_num_threads() * 4,
+        min_kmer_count.is_some(),
+    );
+    let df_kmer_cnts = min_kmer_count
+        .map(|min_count| {
+            get_kmer_count_positions(&all_kmer_indices, &kmer_cnts, kmer_size, min_count)
+        })
+        .transpose()?;
 
     all_kmer_indices.par_iter_mut().for_each(|(_, kmers)| {
         // Get kmers that only occur once.
-        kmers.retain(|k, _| kmer_cnts.contains_key(k));
+        kmers.retain(|k, _| kmer_cnts.is_sunk(*k));
     });
 
     let mut ctgs = vec![];
     let mut kmers = vec![];
     let mut positions = vec![];
+    let mut strands = vec![];
     for (name, kmer_cnts) in all_kmer_indices {
-        for (kmer, (_, pos)) in kmer_cnts {
+        for (kmer, (_, pos, is_fwd)) in kmer_cnts {
             ctgs.push(name);
-            kmers.push(kmer.render(kmer_size));
+            kmers.push(unpack_kmer(kmer, kmer_size));
             positions.push(pos as u64);
+            strands.push(if is_fwd { "+" } else { "-" });
         }
     }
+    let df_sunks_final = group_sunks(ctgs, positions, kmers, strands)?;
+    log::info!("Total number of SUNKs: {}", df_sunks_final.shape().0);
+    Ok((df_sunks_final, df_kmer_cnts))
+}
+
+/// [`get_sunk_positions`] counting kmers in buckets spilled to disk so only one bucket's kmers are in memory at once.
+///
+/// Kmers are hashed into `n_buckets` files in a `kmer_buckets.*` directory in `dir` by a first pass over the contigs.
+/// Each bucket is then loaded, sorted, and counted in turn. Takes about [`SPILLED_KMER_BYTES`] of disk per assembly
+/// base.
+///
+/// # Arguments
+/// * `fasta`
+///     * Fasta file handle.
+/// * `fasta_lens`
+///     * Contig lengths. Contigs must be shorter than 4 Gbp.
+/// * `kmer_size`
+///     * kmer size from 1 to [`MAX_KMER_SIZE`].
+/// * `min_kmer_count`
+///     * See [`get_sunk_positions`].
+/// * `n_buckets`
+///     * Number of buckets. ex. from [`crate::memory::StagePlan`]
+/// * `dir`
+///     * Directory the bucket directory is created in. The bucket directory is removed once counted or on error.
+/// * `cancel`
+///     * Token checked before counting each sequence and bucket.
+///
+/// # Returns
+/// * Same as [`get_sunk_positions`].
+pub fn get_sunk_positions_partitioned(
+    fasta: Fasta,
+    fasta_lens: &HashMap<String, u64>,
+    kmer_size: usize,
+    min_kmer_count: Option<usize>,
+    n_buckets: usize,
+    dir: &Path,
+    cancel: &CancelToken,
+) -> Result<(DataFrame, Option<DataFrame>)> {
+    if !(1..=MAX_KMER_SIZE).contains(&kmer_size) {
+        return Err(GavisunkError::InvalidKmerSize(kmer_size));
+    }
+    let n_buckets = n_buckets.max(1);
+    let mut names: Vec<&str> = fasta_lens.keys().map(|name| name.as_str()).collect();
+    names.sort_unstable();
+    // Removed on return, including on error or cancellation.
+    let bucket_dir = ScratchDir::new_in(dir, "kmer_buckets")?;
+    log::info!(
+        "Counting kmers in {n_buckets} buckets written to {:?}.",
+        bucket_dir.path()
+    );
+
+    let hasher = RandomState::new();
+    let paths: Vec<_> = (0..n_buckets)
+        .map(|bucket| bucket_dir.path().join(format!("{bucket}.bin")))
+        .collect();
+    let writers = paths
+        .iter()
+        .map(|path| Ok(Mutex::new(BufWriter::new(File::create(path)?))))
+        .collect::<Result<Vec<_>>>()?;
+    let spill = |bucket: usize, buffer: &mut Vec<u8>| -> std::io::Result<()> {
+        let mut writer = writers[bucket]
+            .lock()
+            .map_err(|_| std::io::Error::other("bucket writer poisoned"))?;
+        writer.write_all(buffer)?;
+        buffer.clear();
+        Ok(())
+    };
+    names
+        .par_iter()
+        .enumerate()
+        .try_for_each(|(ctg_idx, name)| -> Result<()> {
+            cancel.check()?;
+            let len = fasta_lens[*name];
+            // Positions are spilled as 32-bit.
+            u32::try_from(len)?;
+            let ctg_idx = u32::try_from(ctg_idx)?;
+            let bases = fasta.try_clone()?.fetch_bytes(name, 1, len)?;
+            let mut buffers = vec![Vec::new(); n_buckets];
+            let mut spill_err = None;
+            with_canonical_kmers(&bases, kmer_size, |pos, kmer, is_fwd| {
+                let bucket = hasher.hash_one(kmer) as usize % n_buckets;
+                let buffer = &mut buffers[bucket];
+                buffer.extend(kmer.to_le_bytes());
+                buffer.extend(ctg_idx.to_le_bytes());
+                buffer.extend((pos as u32 + 1).to_le_bytes());
+                buffer.push(u8::from(is_fwd));
+                if buffer.len() >= SPILL_BUFFER_KMERS * SPILLED_KMER_BYTES && spill_err.is_none() {
+                    spill_err = spill(bucket, buffer).err();
+                }
+            });
+            if let Some(err) = spill_err {
+                return Err(err.into());
+            }
+            for (bucket, buffer) in buffers.iter_mut().enumerate() {
+                spill(bucket, buffer)?;
+            }
+            Ok(())
+        })?;
+    for writer in writers {
+        writer
+            .into_inner()
+            .map_err(|_| std::io::Error::other("bucket writer poisoned"))?
+            .flush()?;
+    }
+
+    let (mut ctgs, mut kmers, mut positions, mut strands) = (vec![], vec![], vec![], vec![]);
+    let (mut cnt_ctgs, mut cnt_kmers, mut cnt_positions, mut cnt_strands, mut counts) =
+        (vec![], vec![], vec![], vec![], vec![]);
+    for path in paths.iter() {
+        cancel.check()?;
+        let mut bytes = vec![];
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        let mut occurences: Vec<(u64, u32, u32, bool)> = bytes
+            .par_chunks_exact(SPILLED_KMER_BYTES)
+            .map(|record| {
+                let (kmer, rest) = record.split_at(8);
+                let (ctg_idx, rest) = rest.split_at(4);
+                let (pos, is_fwd) = rest.split_at(4);
+                (
+                    u64::from_le_bytes(kmer.try_into().unwrap_or_default()),
+                    u32::from_le_bytes(ctg_idx.try_into().unwrap_or_default()),
+                    u32::from_le_bytes(pos.try_into().unwrap_or_default()),
+                    is_fwd[0] == 1,
+                )
+            })
+            .collect();
+        drop(bytes);
+        // Occurences of a kmer are adjacent and ordered by contig then position.
+        occurences.par_sort_unstable();
+        for kmer_occurences in occurences.chunk_by(|a, b| a.0 == b.0) {
+            let (kmer, ctg_idx, pos, is_fwd) = kmer_occurences[0];
+            let cnt = kmer_occurences.len();
+            if cnt == 1 {
+                ctgs.push(names[ctg_idx as usize]);
+                kmers.push(unpack_kmer(kmer, kmer_size));
+                positions.push(u64::from(pos));
+                strands.push(if is_fwd { "+" } else { "-" });
+            }
+            if min_kmer_count.is_none_or(|min_count| cnt < min_count) {
+                continue;
+            }
+            // First occurence in each contig.
+            for ctg_occurences in kmer_occurences.chunk_by(|a, b| a.1 == b.1) {
+                let (_, ctg_idx, pos, is_fwd) = ctg_occurences[0];
+                cnt_ctgs.push(names[ctg_idx as usize]);
+                cnt_kmers.push(unpack_kmer(kmer, kmer_size));
+                cnt_positions.push(u64::from(pos));
+                cnt_strands.push(if is_fwd { "+" } else { "-" });
+                counts.push(cnt as u64);
+            }
+        }
+        std::fs::remove_file(path)?;
+    }
+    drop(bucket_dir);
+
+    let df_kmer_cnts = match min_kmer_count {
+        Some(min_count) => {
+            log::info!(
+                "Total number of kmers with count >= {min_count}: {}",
+                cnt_ctgs.len()
+            );
+            Some(
+                DataFrame::new(vec![
+                    Column::new("ctg".into(), cnt_ctgs),
+                    Column::new("cpos".into(), cnt_positions),
+                    Column::new("kmer".into(), cnt_kmers),
+                    Column::new("strand".into(), cnt_strands),
+                    Column::new("count".into(), counts),
+                ])?
+                .sort(["ctg", "cpos"], Default::default())?,
+            )
+        }
+        None => None,
+    };
+    let df_sunks_final = group_sunks(ctgs, positions, kmers, strands)?;
+    log::info!("Total number of SUNKs: {}", df_sunks_final.shape().0);
+    Ok((df_sunks_final, df_kmer_cnts))
+}
+
+/// Sort SUNKs and group runs of overlapping SUNKs.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, cpos, kmer, strand, group]`. See [`get_sunk_positions`].
+fn group_sunks(
+    ctgs: Vec<&str>,
+    positions: Vec<u64>,
+    kmers: Vec<String>,
+    strands: Vec<&str>,
+) -> Result<DataFrame> {
     let df_sunks: DataFrame = DataFrame::new(vec![
         Column::new("ctg".into(), ctgs),
         Column::new("cpos".into(), positions),
         Column::new("kmer".into(), kmers),
+        Column::new("strand".into(), strands),
     ])?;
 
-    let df_sunks_final = df_sunks
+    Ok(df_sunks
         .lazy()
         .sort(["ctg", "cpos"], Default::default())
         .with_column(
@@ -129,8 +477,134 @@ pub fn get_sunk_positions(
         )
         // Set group number to be the first position in adjacent sunks.
         .with_column(col("cpos").first().over(["ctg", "group"]).alias("group"))
-        .collect()?;
+        .collect()?)
+}
 
-    log::info!("Total number of SUNKs: {}", df_sunks_final.shape().0);
-    Ok(df_sunks_final)
+fn get_kmer_count_positions(
+    all_kmer_indices: &HashMap<&str, HashMap<u64, (usize, usize, bool)>>,
+    kmer_cnts: &KmerCountShards,
+    kmer_size: usize,
+    min_count: usize,
+) -> Result<DataFrame> {
+    let mut ctgs = vec![];
+    let mut kmers = vec![];
+    let mut positions = vec![];
+    let mut strands = vec![];
+    let mut counts = vec![];
+    for (name, kmer_pos) in all_kmer_indices.iter() {
+        for (kmer, (_, pos, is_fwd)) in kmer_pos.iter() {
+            let Some(cnt) = kmer_cnts.count(*kmer).filter(|cnt| *cnt >= min_count) else {
+                continue;
+            };
+            ctgs.push(*name);
+            kmers.push(unpack_kmer(*kmer, kmer_size));
+            positions.push(*pos as u64);
+            strands.push(if *is_fwd { "+" } else { "-" });
+            counts.push(cnt as u64);
+        }
+    }
+    log::info!(
+        "Total number of kmers with count >= {min_count}: {}",
+        ctgs.len()
+    );
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("cpos".into(), positions),
+        Column::new("kmer".into(), kmers),
+        Column::new("strand".into(), strands),
+        Column::new("count".into(), counts),
+    ])?
+    .sort(["ctg", "cpos"], Default::default())?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        get_sunk_positions, get_sunk_positions_partitioned, pack_kmer, unpack_kmer,
+        with_canonical_kmers,
+    };
+    use crate::{
+        cancel::CancelToken,
+        io::{Fasta, ScratchDir},
+        run_state::RunState,
+    };
+
+    #[test]
+    fn test_pack_kmer_sorts_like_sequence() {
+        let kmers = ["AACG", "ACGT", "CATG", "TTTA"];
+        let packed: Vec<u64> = kmers
+            .iter()
+            .map(|kmer| pack_kmer(kmer.as_bytes()).unwrap())
+            .collect();
+        assert!(packed.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(unpack_kmer(packed[2], 4), "CATG");
+        assert_eq!(pack_kmer(b"acgt"), pack_kmer(b"ACGT"));
+        assert_eq!(pack_kmer(b"ACNT"), None);
+    }
+
+    #[test]
+    fn test_canonical_kmers() {
+        // AACG and its revcomp CGTT are one kmer. GCGC is a palindrome.
+        let mut counts: HashMap<String, (usize, usize, bool)> = HashMap::new();
+        with_canonical_kmers(b"AACGNCGTTgcgc", 4, |pos, kmer, is_fwd| {
+            counts
+                .entry(unpack_kmer(kmer, 4))
+                .and_modify(|(cnt, _, _)| *cnt += 1)
+                .or_insert((1, pos, is_fwd));
+        });
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("AACG".to_owned(), (2, 0, true)),
+                ("CAAC".to_owned(), (1, 6, false)),
+                ("GCAA".to_owned(), (1, 7, false)),
+                ("CGCA".to_owned(), (1, 8, false)),
+                ("GCGC".to_owned(), (1, 9, true)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_partitioned_kmer_counting() {
+        // ctg2 repeats part of ctg1 reverse complemented.
+        let tmp =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_partitioned_kmers").unwrap();
+        let path = tmp.path().join("asm.fa");
+        std::fs::write(
+            &path,
+            ">ctg1\nACGTTGCAAGGCTTAACGGATCCA\n>ctg2\nTGGATCCGTTAAGCNNACGTAGCTAG\n",
+        )
+        .unwrap();
+        let fasta = Fasta::open(&path, None, false, &RunState::new()).unwrap();
+        let lens = fasta.lengths();
+        let cancel = CancelToken::new();
+        let (df_sunks, df_kmer_cnts) =
+            get_sunk_positions(fasta.try_clone().unwrap(), &lens, 5, Some(2), &cancel).unwrap();
+        let dir = tmp.path().join("buckets");
+        let (df_sunks_partitioned, df_kmer_cnts_partitioned) = get_sunk_positions_partitioned(
+            fasta.try_clone().unwrap(),
+            &lens,
+            5,
+            Some(2),
+            3,
+            &dir,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(df_sunks, df_sunks_partitioned);
+        let df_kmer_cnts = df_kmer_cnts.unwrap();
+        assert!(df_kmer_cnts.height() > 0);
+        assert_eq!(df_kmer_cnts, df_kmer_cnts_partitioned.unwrap());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        // Buckets are removed when cancelled.
+        let cancelled = CancelToken::new();
+        cancelled.cancel();
+        assert!(
+            get_sunk_positions_partitioned(fasta, &lens, 5, Some(2), 3, &dir, &cancelled).is_err()
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
 }
@@ -0,0 +1,144 @@
+use std::fmt::Display;
+
+use polars::prelude::*;
+
+/// Stages of the pipeline in the order they are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
+pub enum Stage {
+    Sunks,
+    Map,
+    Assign,
+    Filter,
+    /// Last stage. Not a valid stage to stop after.
+    #[value(skip)]
+    Graph,
+}
+
+impl Stage {
+    /// Human-readable description of the work done in a stage.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Stage::Sunks => "Getting SUNK positions in assembly.",
+            Stage::Map => "Mapping assembly SUNKs to reads.",
+            Stage::Assign => "Assigning reads to assembly contigs.",
+            Stage::Filter => "Filtering read SUNKs.",
+            Stage::Graph => "Generating SUNK graph by contig.",
+        }
+    }
+
+    /// Intermediates written by a stage and loaded by later stages.
+    pub fn intermediates(&self) -> &'static [&'static str] {
+        match self {
+            Stage::Sunks => &["asm_sunks.tsv"],
+            Stage::Map => &["read_sunks.tsv"],
+            Stage::Assign => &["read_ctg_mapping.tsv"],
+            Stage::Filter => &["read_sunks_good.tsv", "read_sunks_bad.tsv"],
+            Stage::Graph => &[],
+        }
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Stage::Sunks => "sunks",
+            Stage::Map => "map",
+            Stage::Assign => "assign",
+            Stage::Filter => "filter",
+            Stage::Graph => "graph",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Receives events as the pipeline progresses.
+///
+/// All methods have no-op defaults so implementors only override what they need.
+/// Observers are shared across worker threads during the graph stage and must be [`Send`] + [`Sync`].
+///
+/// # Examples
+/// Streaming per-contig results elsewhere.
+/// ```ignore
+/// struct DbSink;
+///
+/// impl StageObserver for DbSink {
+///     fn on_contig(&self, ctg: &str, df_sunks: &DataFrame, df_bed: &DataFrame) {
+///         // Insert rows into database.
+///     }
+/// }
+/// ```
+pub trait StageObserver: Send + Sync {
+    /// Called before a stage begins.
+    fn on_stage_start(&self, _stage: Stage) {}
+
+    /// Called with each intermediate [`DataFrame`] produced or loaded by a stage.
+    ///
+    /// # Arguments
+    /// * `stage`
+    ///     * Stage producing the frame.
+    /// * `name`
+    ///     * Name of the frame. ex. `read_sunks`
+    /// * `df`
+    ///     * Intermediate [`DataFrame`].
+    fn on_frame(&self, _stage: Stage, _name: &str, _df: &DataFrame) {}
+
+    /// Called after a stage finishes.
+    fn on_stage_end(&self, _stage: Stage) {}
+
+    /// Called with the results of the graph stage for a single contig.
+    ///
+    /// # Arguments
+    /// * `ctg`
+    ///     * Contig name.
+    /// * `df_sunks`
+    ///     * [`DataFrame`] of read SUNKs in the largest component per read.
+    /// * `df_bed`
+    ///     * [`DataFrame`] of supported intervals.
+    fn on_contig(&self, _ctg: &str, _df_sunks: &DataFrame, _df_bed: &DataFrame) {}
+}
+
+/// Logs stage progress.
+pub struct LogObserver;
+
+impl StageObserver for LogObserver {
+    fn on_stage_start(&self, stage: Stage) {
+        log::info!("{}", stage.description());
+    }
+
+    fn on_frame(&self, stage: Stage, name: &str, df: &DataFrame) {
+        log::debug!("Stage {stage} produced {name} with {} rows.", df.height());
+    }
+
+    fn on_contig(&self, ctg: &str, _df_sunks: &DataFrame, df_bed: &DataFrame) {
+        log::debug!("Found {} supported intervals for {ctg}.", df_bed.height());
+    }
+}
+
+/// Registered [`StageObserver`]s. Events are dispatched in registration order.
+#[derive(Default)]
+pub struct Observers(Vec<Box<dyn StageObserver>>);
+
+impl Observers {
+    pub fn register(&mut self, observer: impl StageObserver + 'static) -> &mut Self {
+        self.0.push(Box::new(observer));
+        self
+    }
+
+    pub fn stage_start(&self, stage: Stage) {
+        self.0.iter().for_each(|obs| obs.on_stage_start(stage));
+    }
+
+    pub fn frame(&self, stage: Stage, name: &str, df: &DataFrame) {
+        self.0.iter().for_each(|obs| obs.on_frame(stage, name, df));
+    }
+
+    pub fn stage_end(&self, stage: Stage) {
+        self.0.iter().for_each(|obs| obs.on_stage_end(stage));
+    }
+
+    pub fn contig(&self, ctg: &str, df_sunks: &DataFrame, df_bed: &DataFrame) {
+        self.0
+            .iter()
+            .for_each(|obs| obs.on_contig(ctg, df_sunks, df_bed));
+    }
+}
@@ -0,0 +1,316 @@
+//! Interval utilities for BED files and per-contig intervals.
+//!
+//! Intervals are half-open, 0-based `(st, end)` pairs as in BED files.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use coitrees::{COITree, Interval, IntervalTree};
+use itertools::Itertools;
+
+use crate::{
+    error::{GavisunkError, Result},
+    naming::normalize_name,
+};
+
+pub type RegionIntervals<T> = HashMap<String, Vec<Interval<T>>>;
+pub type RegionIntervalTrees<T> = HashMap<String, COITree<T, usize>>;
+/// Sorted, non-overlapping intervals by contig.
+pub type CtgIntervals = HashMap<String, Vec<(i64, i64)>>;
+/// Sorted `(st, end, value)` bedGraph intervals by contig.
+pub type CtgBedGraph = HashMap<String, Vec<(i64, i64, f64)>>;
+
+/// Whether a BED line is empty or a comment, `track`, or `browser` line.
+fn is_bed_comment(line: &str) -> bool {
+    line.is_empty()
+        || line.starts_with('#')
+        || line.starts_with("track")
+        || line.starts_with("browser")
+}
+
+/// Lines of a BED file without comments. See [`is_bed_comment`].
+fn bed_lines(bed: impl AsRef<Path>) -> Result<impl Iterator<Item = std::io::Result<String>>> {
+    Ok(BufReader::new(File::open(bed)?)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !is_bed_comment(line))))
+}
+
+/// Split a BED line into `(name, start, stop, other_cols)`.
+///
+/// `name` is normalized with [`normalize_name`] to match FASTA record names.
+/// `line_num` counts lines from [`bed_lines`] so a header line is skipped if it is the first line after comments,
+/// ex. in outputs with a comment header.
+fn parse_bed_line(line: &str, line_num: usize) -> Result<Option<(&str, i64, i64, &str)>> {
+    let (name, start, stop, other_cols) =
+        if let Some((name, start, stop, other_cols)) = line.splitn(4, '\t').collect_tuple() {
+            (name, start, stop, other_cols)
+        } else if let Some((name, start, stop)) = line.splitn(3, '\t').collect_tuple() {
+            (name, start, stop, "")
+        } else {
+            log::error!("Invalid line: {line}");
+            return Ok(None);
+        };
+    match (start.parse::<i64>(), stop.parse::<i64>()) {
+        (Ok(start), Ok(stop)) => Ok(Some((normalize_name(name), start, stop, other_cols))),
+        // ex. ctg st end
+        _ if line_num == 0 => Ok(None),
+        (Err(err), _) | (_, Err(err)) => Err(err.into()),
+    }
+}
+
+/// Read an input bedfile and convert it to a [`COITree`].
+///
+/// # Arguments
+/// * `bed`: Bedfile path.
+/// * `intervals_fn`: Function applied to `(start, stop, other_cols)` to convert into an [`Interval`].
+///
+/// # Examples
+/// BED3 record.
+/// ```ignore
+/// let records = read_bed(
+///     "test.bed",
+///     |start: i32, stop: i32, other_cols: &str| Interval::new(start, stop, None)
+/// )
+/// ```
+/// BED4 record
+/// ```ignore
+/// let records = read_bed(
+///     "test.bed",
+///     |start: i32, stop: i32, other_cols: &str| Interval::new(start, stop, Some(other_cols.to_owned()))
+/// )
+/// ```
+pub fn read_bed<T: Clone>(
+    bed: Option<impl AsRef<Path>>,
+    intervals_fn: impl Fn(i32, i32, &str) -> Interval<T>,
+) -> Result<Option<RegionIntervalTrees<T>>> {
+    let mut intervals: RegionIntervals<T> = HashMap::new();
+    let mut trees: RegionIntervalTrees<T> = HashMap::new();
+
+    let Some(bed) = bed else {
+        return Ok(None);
+    };
+    for (line_num, line) in bed_lines(bed)?.enumerate() {
+        let line = line?;
+        let Some((name, start, stop, other_cols)) = parse_bed_line(&line, line_num)? else {
+            continue;
+        };
+        let (first, last) = (i32::try_from(start)?, i32::try_from(stop)?);
+
+        intervals
+            .entry(name.to_owned())
+            .and_modify(|intervals| intervals.push(intervals_fn(first, last, other_cols)))
+            .or_insert_with(|| vec![intervals_fn(first, last, other_cols)]);
+    }
+    for (roi, intervals) in intervals.into_iter() {
+        trees.entry(roi).or_insert(COITree::new(&intervals));
+    }
+    Ok(Some(trees))
+}
+
+/// Load a BED file as merged intervals by contig. Columns after the third are ignored.
+pub fn load_bed(bed: impl AsRef<Path>) -> Result<CtgIntervals> {
+    let mut intervals: CtgIntervals = HashMap::new();
+    for (line_num, line) in bed_lines(bed)?.enumerate() {
+        let line = line?;
+        let Some((name, start, stop, _)) = parse_bed_line(&line, line_num)? else {
+            continue;
+        };
+        intervals
+            .entry(name.to_owned())
+            .or_default()
+            .push((start, stop));
+    }
+    Ok(intervals
+        .into_iter()
+        .map(|(ctg, itvs)| (ctg, merge(itvs, 0)))
+        .collect())
+}
+
+/// Load a bedGraph file. ex. from `mosdepth` or `samtools depth`
+///
+/// The value is taken from the fourth column.
+pub fn load_bedgraph(bedgraph: impl AsRef<Path>) -> Result<CtgBedGraph> {
+    let mut values: CtgBedGraph = HashMap::new();
+    for (line_num, line) in bed_lines(bedgraph)?.enumerate() {
+        let line = line?;
+        let Some((name, start, stop, other_cols)) = parse_bed_line(&line, line_num)? else {
+            continue;
+        };
+        let value = other_cols
+            .split('\t')
+            .next()
+            .unwrap_or_default()
+            .parse::<f64>()?;
+        values
+            .entry(name.to_owned())
+            .or_default()
+            .push((start, stop, value));
+    }
+    for ctg_values in values.values_mut() {
+        ctg_values.sort_unstable_by_key(|(st, end, _)| (*st, *end));
+    }
+    Ok(values)
+}
+
+/// Mean value of sorted, non-overlapping bedGraph intervals over `[st, end)`. Bases not covered count as zero.
+pub fn mean_value(values: &[(i64, i64, f64)], st: i64, end: i64) -> f64 {
+    if end <= st {
+        return 0.0;
+    }
+    let first = values.partition_point(|(_, v_end, _)| *v_end <= st);
+    let total: f64 = values[first..]
+        .iter()
+        .take_while(|(v_st, _, _)| *v_st < end)
+        .map(|(v_st, v_end, value)| (end.min(*v_end) - st.max(*v_st)) as f64 * value)
+        .sum();
+    total / (end - st) as f64
+}
+
+/// Merge overlapping intervals and those separated by at most `slop` bases.
+///
+/// # Returns
+/// * Sorted, non-overlapping intervals.
+pub fn merge(mut intervals: Vec<(i64, i64)>, slop: i64) -> Vec<(i64, i64)> {
+    intervals.sort_unstable();
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+    for (st, end) in intervals {
+        match merged.last_mut() {
+            Some((_, prev_end)) if st <= *prev_end + slop => *prev_end = (*prev_end).max(end),
+            _ => merged.push((st, end)),
+        }
+    }
+    merged
+}
+
+/// Intersect two sets of sorted, non-overlapping intervals.
+pub fn intersect(a: &[(i64, i64)], b: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let (mut i, mut j) = (0, 0);
+    let mut overlaps = vec![];
+    while i < a.len() && j < b.len() {
+        let (st, end) = (a[i].0.max(b[j].0), a[i].1.min(b[j].1));
+        if st < end {
+            overlaps.push((st, end));
+        }
+        // Advance whichever ends first.
+        if a[i].1 < b[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    overlaps
+}
+
+/// Subtract sorted, non-overlapping intervals `b` from `a`.
+pub fn subtract(a: &[(i64, i64)], b: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut remaining = vec![];
+    let mut j = 0;
+    for (st, end) in a.iter().copied() {
+        let mut st = st;
+        // Skip intervals ending before this one.
+        while j < b.len() && b[j].1 <= st {
+            j += 1;
+        }
+        let mut k = j;
+        while k < b.len() && b[k].0 < end {
+            if b[k].0 > st {
+                remaining.push((st, b[k].0));
+            }
+            st = st.max(b[k].1);
+            k += 1;
+        }
+        if st < end {
+            remaining.push((st, end));
+        }
+    }
+    remaining
+}
+
+/// Whether `pos` is within any of the sorted, non-overlapping intervals.
+pub fn contains(intervals: &[(i64, i64)], pos: i64) -> bool {
+    let idx = intervals.partition_point(|(st, _)| *st <= pos);
+    idx > 0 && intervals[idx - 1].1 > pos
+}
+
+/// Parse a region as `ctg:start-end` with 1-based, inclusive coordinates like `samtools`. ex. `chr1:1,000-2,000`
+///
+/// Contig names may contain `:`.
+pub fn parse_region(region: &str) -> Result<(String, i64, i64)> {
+    let err = || GavisunkError::InvalidRegion(region.to_owned());
+    let (ctg, coords) = region.rsplit_once(':').ok_or_else(err)?;
+    let (st, end) = coords.split_once('-').ok_or_else(err)?;
+    let (Ok(st), Ok(end)) = (
+        st.replace(',', "").parse::<i64>(),
+        end.replace(',', "").parse::<i64>(),
+    ) else {
+        return Err(err());
+    };
+    if ctg.is_empty() || st < 1 || st > end {
+        return Err(err());
+    }
+    Ok((ctg.to_owned(), st, end))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{intersect, load_bed, merge, parse_region, subtract};
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_merge_slop() {
+        let itvs = vec![(10, 20), (0, 5), (22, 30), (50, 60)];
+        assert_eq!(
+            merge(itvs.clone(), 0),
+            [(0, 5), (10, 20), (22, 30), (50, 60)]
+        );
+        assert_eq!(merge(itvs, 5), [(0, 30), (50, 60)]);
+    }
+
+    #[test]
+    fn test_intersect_subtract() {
+        let a = [(0, 100), (200, 300)];
+        let b = [(50, 60), (90, 210), (250, 260)];
+        assert_eq!(
+            intersect(&a, &b),
+            [(50, 60), (90, 100), (200, 210), (250, 260)]
+        );
+        assert_eq!(
+            subtract(&a, &b),
+            [(0, 50), (60, 90), (210, 250), (260, 300)]
+        );
+    }
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            parse_region("chr1:1,000-2,000").unwrap(),
+            ("chr1".to_owned(), 1000, 2000)
+        );
+        assert_eq!(
+            parse_region("HLA:A:1-10").unwrap(),
+            ("HLA:A".to_owned(), 1, 10)
+        );
+        assert!(parse_region("chr1").is_err());
+        assert!(parse_region("chr1:20-10").is_err());
+    }
+
+    #[test]
+    fn test_load_bed_with_comment_header() {
+        let dir =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_comment_header").unwrap();
+        let path = dir.path().join("regions.bed");
+        std::fs::write(
+            &path,
+            "# rs-gavisunk version=\"0.1.0\"\n# date=2024-01-01T00:00:00Z\nctg\tst\tend\nctg_1\t10\t20\n",
+        )
+        .unwrap();
+        assert_eq!(load_bed(&path).unwrap()["ctg_1"], [(10, 20)]);
+
+        std::fs::write(&path, "ctg_1\t10\t20\nctg\tst\tend\n").unwrap();
+        assert!(load_bed(&path).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::error::Result;
+
+/// Default minimum number of consecutive SUNKs on one strand for a run to be considered.
+pub const DEFAULT_MIN_RUN_SUNKS: usize = 3;
+/// Default distance within which breakpoints from different reads are clustered.
+pub const DEFAULT_BREAKPOINT_SLOP: i64 = 1000;
+
+/// Run of consecutive SUNKs within a read hitting the contig on the same strand.
+#[derive(Debug, Clone, Copy)]
+struct StrandRun {
+    is_fwd: bool,
+    sunks: usize,
+    first_cpos: i64,
+    last_cpos: i64,
+}
+
+/// Get contig intervals where a read switches strand between runs of at least `min_run_sunks` SUNKs.
+///
+/// # Arguments
+/// * `anchors`
+///     * `(cpos, is_fwd)` of a read's SUNKs ordered by read position.
+fn read_strand_switches(anchors: &[(i64, bool)], min_run_sunks: usize) -> Vec<(i64, i64)> {
+    let runs = anchors
+        .iter()
+        .chunk_by(|(_, is_fwd)| *is_fwd)
+        .into_iter()
+        .map(|(is_fwd, run)| {
+            let run: Vec<i64> = run.map(|(cpos, _)| *cpos).collect();
+            StrandRun {
+                is_fwd,
+                sunks: run.len(),
+                first_cpos: run[0],
+                last_cpos: run[run.len() - 1],
+            }
+        })
+        // Drop short runs from spurious hits and then join runs on the same strand.
+        .filter(|run| run.sunks >= min_run_sunks)
+        .coalesce(|a, b| {
+            if a.is_fwd == b.is_fwd {
+                Ok(StrandRun {
+                    sunks: a.sunks + b.sunks,
+                    last_cpos: b.last_cpos,
+                    ..a
+                })
+            } else {
+                Err((a, b))
+            }
+        })
+        .collect_vec();
+    runs.iter()
+        .tuple_windows()
+        .map(|(a, b)| {
+            let (st, end) = if a.last_cpos <= b.first_cpos {
+                (a.last_cpos, b.first_cpos)
+            } else {
+                (b.first_cpos, a.last_cpos)
+            };
+            (st, end + 1)
+        })
+        .collect()
+}
+
+/// Detect candidate inversion breakpoints from reads whose SUNK hits switch strand along the read.
+///
+/// Within an inversion, SUNKs hit the read on the opposite strand and `cpos` runs against `rpos`.
+/// Breakpoints from different reads within `slop` of each other are clustered.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, strand, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ...]`. Only SUNKs on the assigned contig are used.
+/// * `min_run_sunks`
+///     * Minimum consecutive SUNKs on one strand for a run to be considered.
+/// * `slop`
+///     * Distance within which breakpoints are clustered.
+///
+/// # Returns
+/// * [`DataFrame`] of breakpoints with columns `[ctg, st, end, reads]`
+///     * `reads` is the number of reads supporting the breakpoint.
+pub fn detect_inversions(
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    min_run_sunks: usize,
+    slop: i64,
+) -> Result<DataFrame> {
+    let df = df_read_sunks
+        .select(["read", "rpos", "ctg", "cpos", "strand"])?
+        .inner_join(
+            &df_best_reads_asm.select(["read", "ctg"])?,
+            ["read", "ctg"],
+            ["read", "ctg"],
+        )?
+        .lazy()
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
+        ])
+        .sort(["read", "rpos"], Default::default())
+        .collect()?;
+
+    let mut breakpoints: HashMap<&str, Vec<(i64, i64, &str)>> = HashMap::new();
+    let rows = df
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df.column("ctg")?.str()?.iter())
+        .zip(df.column("cpos")?.i64()?.iter())
+        .zip(df.column("strand")?.str()?.iter())
+        .flat_map(|(((read, ctg), cpos), strand)| Some((read?, ctg?, cpos?, strand? == "+")));
+    for ((read, ctg), anchors) in &rows.chunk_by(|(read, ctg, _, _)| (*read, *ctg)) {
+        let anchors: Vec<(i64, bool)> =
+            anchors.map(|(_, _, cpos, is_fwd)| (cpos, is_fwd)).collect();
+        for (st, end) in read_strand_switches(&anchors, min_run_sunks) {
+            breakpoints.entry(ctg).or_default().push((st, end, read));
+        }
+    }
+
+    let (mut ctgs, mut starts, mut ends, mut n_reads) = (vec![], vec![], vec![], vec![]);
+    for (ctg, mut ctg_breakpoints) in breakpoints.into_iter().sorted_by_key(|(ctg, _)| *ctg) {
+        ctg_breakpoints.sort_unstable();
+        let mut clusters: Vec<(i64, i64, HashSet<&str>)> = vec![];
+        for (st, end, read) in ctg_breakpoints {
+            match clusters.last_mut() {
+                Some((_, cl_end, reads)) if st <= *cl_end + slop => {
+                    *cl_end = (*cl_end).max(end);
+                    reads.insert(read);
+                }
+                _ => clusters.push((st, end, HashSet::from([read]))),
+            }
+        }
+        for (st, end, reads) in clusters {
+            ctgs.push(ctg);
+            starts.push(st);
+            ends.push(end);
+            n_reads.push(reads.len() as u64);
+        }
+    }
+    log::info!("Found {} candidate inversion breakpoints.", ctgs.len());
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("st".into(), starts),
+        Column::new("end".into(), ends),
+        Column::new("reads".into(), n_reads),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::detect_inversions;
+
+    #[test]
+    fn test_detect_inversions() {
+        // r1 and r2 switch strand across c1:300-5400, the second run of SUNKs running against the read.
+        // r3 has a single spurious reverse hit and r4 is assigned to c2.
+        let (mut reads, mut rpos, mut cpos, mut strands) = (vec![], vec![], vec![], vec![]);
+        let inverted = [
+            (100, "+"),
+            (200, "+"),
+            (300, "+"),
+            (5300, "-"),
+            (5200, "-"),
+            (5100, "-"),
+            (5400, "+"),
+            (5500, "+"),
+            (5600, "+"),
+        ];
+        for (read, shift) in [("r1", 0), ("r2", 10), ("r4", 0)] {
+            for (i, (pos, strand)) in inverted.iter().enumerate() {
+                reads.push(read);
+                rpos.push(i as i64 * 100 + 1);
+                cpos.push(pos + shift);
+                strands.push(*strand);
+            }
+        }
+        for (i, strand) in ["+", "+", "+", "-", "+", "+", "+"].into_iter().enumerate() {
+            reads.push("r3");
+            rpos.push(i as i64 * 100 + 1);
+            cpos.push(i as i64 * 100 + 100);
+            strands.push(strand);
+        }
+        let df_read_sunks = df!(
+            "read" => reads,
+            "rpos" => rpos,
+            "ctg" => vec!["c1"; cpos.len()],
+            "cpos" => cpos,
+            "strand" => strands,
+        )
+        .unwrap();
+        let df_best_reads_asm = df!(
+            "read" => ["r1", "r2", "r3", "r4"],
+            "ctg" => ["c1", "c1", "c1", "c2"],
+        )
+        .unwrap();
+        let df = detect_inversions(&df_read_sunks, &df_best_reads_asm, 3, 100).unwrap();
+        assert_eq!(
+            df,
+            df!(
+                "ctg" => ["c1"],
+                "st" => [300i64],
+                "end" => [5411i64],
+                "reads" => [2u64],
+            )
+            .unwrap()
+        );
+    }
+}
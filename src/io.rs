@@ -1,120 +1,818 @@
 use core::str;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, Read, Write},
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
 };
 
-use coitrees::{COITree, Interval, IntervalTree};
-use eyre::Context;
-use itertools::Itertools;
+use flate2::read::MultiGzDecoder;
+
+#[cfg(feature = "bam")]
+use noodles::{bam, cram};
 use noodles::{
     bgzf::{self, IndexedReader},
     fasta::{self},
+    fastq,
 };
 use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "zstd")]
+use crate::seekable_zstd::{self, SeekableZstd};
+use crate::{
+    error::{GavisunkError, Result},
+    naming::normalize_name,
+    run_state::RunState,
+};
+
+/// gzip magic bytes.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// zstd frame magic bytes.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// BAM magic bytes after BGZF decompression.
+const BAM_MAGIC: [u8; 4] = *b"BAM\x01";
+/// CRAM file definition magic bytes.
+const CRAM_MAGIC: [u8; 4] = *b"CRAM";
+
+/// Default number of malformed records skipped before failing.
+pub const DEFAULT_MAX_MALFORMED_RECORDS: usize = 100;
+
+/// CRC-32 of a file as lowercase hex.
+pub fn file_crc32(path: impl AsRef<Path>) -> Result<String> {
+    let mut reader = File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:08x}", hasher.finalize()))
+}
 
-pub type RegionIntervals<T> = HashMap<String, Vec<Interval<T>>>;
-pub type RegionIntervalTrees<T> = HashMap<String, COITree<T, usize>>;
+/// What to do with an existing intermediate that's empty, unreadable, or has fewer rows than were written, ex. from a
+/// crashed run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum EmptyIntermediate {
+    /// Warn and recompute it.
+    #[default]
+    Redo,
+    /// Fail with [`GavisunkError::InvalidIntermediate`].
+    Error,
+    /// Warn and use it as is. Recomputed if it's empty or unreadable.
+    Keep,
+}
+
+/// Malformed records skipped so far in a run, as `{path}:{name}`.
+pub fn malformed_records(state: &RunState) -> Vec<String> {
+    state
+        .malformed_records
+        .lock()
+        .map(|records| records.clone())
+        .unwrap_or_default()
+}
+
+/// Intermediates loaded or written so far in a run. See [`load_or_redo_df`].
+pub fn intermediates(state: &RunState) -> Vec<PathBuf> {
+    state
+        .intermediates
+        .lock()
+        .map(|paths| paths.clone())
+        .unwrap_or_default()
+}
+
+/// Record an intermediate loaded or written by a run.
+#[doc(hidden)]
+pub fn record_intermediate(path: &Path, state: &RunState) {
+    if let Ok(mut paths) = state.intermediates.lock() {
+        if !paths.iter().any(|recorded| recorded == path) {
+            paths.push(path.to_owned());
+        }
+    }
+}
 
-/// Read an input bedfile and convert it to a [`COITree`].
+/// Warn and skip a malformed record, ex. a truncated read.
 ///
 /// # Arguments
-/// * `bed`: Bedfile path.
-/// * `intervals_fn`: Function applied to `(start, stop, other_cols)` to convert into an [`Interval`].
+/// * `path`
+///     * FASTA file.
+/// * `name`
+///     * Record name or position if the name couldn't be read.
+/// * `err`
+///     * Why the record is malformed.
+/// * `state`
+///     * Run the record is skipped in.
 ///
-/// # Examples
-/// BED3 record.
-/// ```
-/// let records = read_bed(
-///     "test.bed",
-///     |start: i32, stop: i32, other_cols: &str| Interval::new(start, stop, None)
-/// )
-/// ```
-/// BED4 record
-/// ```
-/// let records = read_bed(
-///     "test.bed",
-///     |start: i32, stop: i32, other_cols: &str| Interval::new(start, stop, Some(other_cols.to_owned()))
-/// )
-/// ```
-pub fn read_bed<T: Clone>(
-    bed: Option<impl AsRef<Path>>,
-    intervals_fn: impl Fn(i32, i32, &str) -> Interval<T>,
-) -> eyre::Result<Option<RegionIntervalTrees<T>>> {
-    let mut intervals: RegionIntervals<T> = HashMap::new();
-    let mut trees: RegionIntervalTrees<T> = HashMap::new();
-
-    let Some(bed) = bed else {
-        return Ok(None);
+/// # Returns
+/// * [`GavisunkError::TooManyMalformedRecords`] once more than [`RunState::max_malformed_records`] are skipped.
+pub fn skip_malformed_record(
+    path: &Path,
+    name: &str,
+    err: impl Display,
+    state: &RunState,
+) -> Result<()> {
+    log::warn!("Skipping malformed record {name:?} in {path:?}: {err}");
+    let count = {
+        let mut records = state
+            .malformed_records
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        records.push(format!("{}:{name}", path.display()));
+        records.len()
     };
-    let bed_fh = File::open(bed)?;
-    let bed_reader = BufReader::new(bed_fh);
+    let max = state.max_malformed_records;
+    if count > max {
+        return Err(GavisunkError::TooManyMalformedRecords { count, max });
+    }
+    Ok(())
+}
 
-    for line in bed_reader.lines() {
-        let line = line?;
-        let (name, start, stop, other_cols) =
-            if let Some((name, start, stop, other_cols)) = line.splitn(4, '\t').collect_tuple() {
-                (name, start, stop, other_cols)
-            } else if let Some((name, start, stop)) = line.splitn(3, '\t').collect_tuple() {
-                (name, start, stop, "")
-            } else {
-                log::error!("Invalid line: {line}");
-                continue;
-            };
-        let (first, last) = (start.parse::<i32>()?, stop.parse::<i32>()?);
+/// Compression of a FASTA file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// Plain gzip. Cannot be range-queried.
+    Gzip,
+    /// Blocked gzip from `bgzip`. Can be range-queried with a `.gzi` index.
+    Bgzip,
+    /// zstd. Can be range-queried if in the seekable format with a seek table.
+    Zstd,
+}
 
-        intervals
-            .entry(name.to_owned())
-            .and_modify(|intervals| intervals.push(intervals_fn(first, last, other_cols)))
-            .or_insert_with(|| vec![intervals_fn(first, last, other_cols)]);
+impl Compression {
+    /// Detect compression from magic bytes. BGZF blocks are gzip members with a `BC` extra subfield.
+    pub fn detect(infile: impl AsRef<Path>) -> Result<Self> {
+        let mut header = Vec::with_capacity(16);
+        File::open(infile)?.take(16).read_to_end(&mut header)?;
+        Ok(Self::from_header(&header))
     }
-    for (roi, intervals) in intervals.into_iter() {
-        trees.entry(roi).or_insert(COITree::new(&intervals));
+
+    fn from_header(header: &[u8]) -> Self {
+        if header.starts_with(&ZSTD_MAGIC) {
+            Self::Zstd
+        } else if !header.starts_with(&GZIP_MAGIC) {
+            Self::None
+        } else if header.len() >= 14 && header[3] & 0x04 != 0 && &header[12..14] == b"BC" {
+            Self::Bgzip
+        } else {
+            Self::Gzip
+        }
+    }
+}
+
+/// Format of a sequence file after decompression.
+///
+/// FASTQ cannot be indexed and is converted to bgzipped FASTA by [`Fasta::indexable_path`]. BAM and CRAM records are
+/// streamed instead. See [`Fasta::for_each_read`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqFormat {
+    Fasta,
+    /// FASTQ, ex. from a basecaller.
+    Fastq,
+    /// BAM, ex. unaligned BAM from `dorado`. Requires the `bam` feature.
+    Bam,
+    /// CRAM. Requires the `bam` feature.
+    Cram,
+}
+
+impl SeqFormat {
+    /// Detect format from the magic bytes or first byte of the decompressed file. FASTQ records start with `@`.
+    pub fn detect(infile: impl AsRef<Path>) -> Result<Self> {
+        let mut handle = Fasta::decompressed_reader(infile.as_ref())?;
+        Ok(Self::from_header(handle.fill_buf()?))
+    }
+
+    /// Whether a BAM or CRAM, which is streamed rather than converted.
+    pub fn is_alignment(self) -> bool {
+        matches!(self, Self::Bam | Self::Cram)
+    }
+
+    fn from_header(header: &[u8]) -> Self {
+        if header.starts_with(&BAM_MAGIC) {
+            Self::Bam
+        } else if header.starts_with(&CRAM_MAGIC) {
+            Self::Cram
+        } else if header.starts_with(b"@") {
+            Self::Fastq
+        } else {
+            Self::Fasta
+        }
     }
-    Ok(Some(trees))
 }
 
 pub enum FastaReader {
     Bgzip(fasta::io::Reader<IndexedReader<File>>),
     Standard(fasta::io::Reader<BufReader<File>>),
+    /// Seekable zstd. Only frames overlapping a queried region are decompressed.
+    #[cfg(feature = "zstd")]
+    Zstd(fasta::io::Reader<BufReader<SeekableZstd<File>>>),
+    /// Sequences by name loaded into memory. Shared between clones.
+    InMemory(Arc<HashMap<String, Vec<u8>>>),
+}
+
+/// Sequence fetched from a [`Fasta`]. Borrowed from in-memory sequences and owned by the queried record otherwise.
+pub enum FetchedSeq<'a> {
+    Borrowed(&'a [u8]),
+    Record(fasta::Record),
+}
+
+impl FetchedSeq<'_> {
+    /// Overlapping k-mers with their 1-based positions.
+    pub fn kmers(&self, kmer_size: usize) -> impl Iterator<Item = (usize, &[u8])> {
+        // `windows` panics on a size of 0.
+        let n_kmers = if kmer_size == 0 {
+            0
+        } else {
+            (self.len() + 1).saturating_sub(kmer_size)
+        };
+        self.windows(kmer_size.max(1))
+            .take(n_kmers)
+            .enumerate()
+            .map(|(i, kmer)| (i + 1, kmer))
+    }
+}
+
+impl std::ops::Deref for FetchedSeq<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FetchedSeq::Borrowed(seq) => seq,
+            FetchedSeq::Record(rec) => rec.sequence().as_ref(),
+        }
+    }
 }
 
+impl AsRef<[u8]> for FetchedSeq<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// FASTA file or stream queryable by record name.
+///
+/// Record names are normalized with [`normalize_name`] so `lengths`, `fetch`, and [`DataFrame`] keys built from them
+/// agree regardless of header descriptions or whitespace in the FASTA or its index.
 pub struct Fasta {
     pub fname: PathBuf,
     reader: FastaReader,
     index: fasta::fai::Index,
+    /// Normalized names to names in `index`.
+    names: HashMap<String, String>,
+    /// Normalized names to record lengths in `index`.
+    lens: HashMap<String, u64>,
 }
 
 impl Fasta {
-    pub fn new(infile: impl AsRef<Path>) -> eyre::Result<Self> {
+    pub fn new(infile: impl AsRef<Path>) -> Result<Self> {
         let fname = infile.as_ref().to_owned();
-        let (index, gzi) = Self::get_faidx(&infile)?;
-        let fh = Self::read_fa(&infile, gzi.as_ref())?;
+        let (index, fh) = match Compression::detect(&fname)? {
+            Compression::Gzip => return Err(GavisunkError::PlainGzip(fname)),
+            Compression::Zstd => Self::read_zstd(&fname)?,
+            Compression::None | Compression::Bgzip => {
+                let (index, gzi) = Self::get_faidx(&infile)?;
+                (index, Self::read_fa(&infile, gzi.as_ref())?)
+            }
+        };
+        let names = Self::normalize_names(
+            &fname,
+            index
+                .as_ref()
+                .iter()
+                .map(|rec| String::from_utf8_lossy(rec.name()).into_owned()),
+        );
+        let index_lens: HashMap<String, u64> = index
+            .as_ref()
+            .iter()
+            .map(|rec| {
+                (
+                    String::from_utf8_lossy(rec.name()).into_owned(),
+                    rec.length(),
+                )
+            })
+            .collect();
+        let lens = names
+            .iter()
+            .filter_map(|(key, name)| Some((key.clone(), *index_lens.get(name)?)))
+            .collect();
         Ok(Self {
             fname,
             reader: fh,
             index,
+            names,
+            lens,
         })
     }
 
+    /// Map normalized names to original names, warning on names that only differ after normalization.
+    fn normalize_names(
+        fname: &Path,
+        names: impl IntoIterator<Item = String>,
+    ) -> HashMap<String, String> {
+        let mut normalized = HashMap::new();
+        for name in names {
+            let key = normalize_name(&name).to_owned();
+            if let Some(existing) = normalized.get(&key) {
+                log::warn!(
+                    "Records {existing:?} and {name:?} in {fname:?} have the same name, {key:?}. Only the first is used."
+                );
+                continue;
+            }
+            normalized.insert(key, name);
+        }
+        normalized
+    }
+
+    /// Open a FASTA or FASTQ file, loading it into memory if it cannot be indexed.
+    ///
+    /// # Arguments
+    /// * `infile`
+    ///     * FASTA, FASTQ, BAM, or CRAM file. `-` reads from stdin. FASTQ files are converted with
+    ///       [`Fasta::indexable_path`]. BAM and CRAM files are loaded into memory.
+    /// * `max_bytes`
+    ///     * Memory budget for loading a non-indexable file into memory. If [`None`], unlimited.
+    /// * `allow_recompress`
+    ///     * Index a bgzipped copy of a plain gzipped FASTA rather than loading it into memory.
+    ///       See [`Fasta::indexable_path`].
+    /// * `state`
+    ///     * Run malformed records are skipped in. See [`skip_malformed_record`].
+    pub fn open(
+        infile: impl AsRef<Path>,
+        max_bytes: Option<u64>,
+        allow_recompress: bool,
+        state: &RunState,
+    ) -> Result<Self> {
+        if Self::is_streamed(&infile)? {
+            log::info!(
+                "{:?} is BAM or CRAM and cannot be indexed. Loading into memory.",
+                infile.as_ref()
+            );
+            Self::new_in_memory(infile, max_bytes, state)
+        } else if Self::is_indexable(&infile) && SeqFormat::detect(&infile)? == SeqFormat::Fastq {
+            Self::new(Self::indexable_path(infile, allow_recompress, state)?)
+        } else if Self::is_indexable(&infile)
+            && !allow_recompress
+            && Compression::detect(&infile)? == Compression::Gzip
+        {
+            log::info!(
+                "{:?} is gzipped but not bgzipped and cannot be indexed. Loading into memory. Use --allow-recompress to index a bgzipped copy instead.",
+                infile.as_ref()
+            );
+            Self::new_in_memory(infile, max_bytes, state)
+        } else if Self::is_indexable(&infile)
+            && Compression::detect(&infile)? == Compression::Zstd
+            && !Self::is_seekable_zstd(&infile)?
+        {
+            log::info!(
+                "{:?} is zstd-compressed without a seek table and cannot be indexed. Loading into memory.",
+                infile.as_ref()
+            );
+            Self::new_in_memory(infile, max_bytes, state)
+        } else if Self::is_indexable(&infile) {
+            Self::new(Self::indexable_path(infile, allow_recompress, state)?)
+        } else {
+            log::info!(
+                "{:?} cannot be indexed. Loading into memory.",
+                infile.as_ref()
+            );
+            Self::new_in_memory(infile, max_bytes, state)
+        }
+    }
+
+    /// Whether `infile` is a BAM or CRAM file, whose records are streamed with [`Fasta::for_each_read`] rather than
+    /// converted to an indexable copy.
+    pub fn is_streamed(infile: impl AsRef<Path>) -> Result<bool> {
+        Ok(Self::is_indexable(&infile) && SeqFormat::detect(infile)?.is_alignment())
+    }
+
+    /// Record lengths of reads by normalized name.
+    ///
+    /// # Arguments
+    /// * `infile`
+    ///     * Reads file. BAM and CRAM records are streamed. Otherwise, lengths are from the index of
+    ///       [`Fasta::indexable_path`].
+    /// * `allow_recompress`
+    ///     * See [`Fasta::indexable_path`].
+    /// * `state`
+    ///     * Run malformed records are skipped in. See [`skip_malformed_record`].
+    pub fn read_lengths(
+        infile: impl AsRef<Path>,
+        allow_recompress: bool,
+        state: &RunState,
+    ) -> Result<HashMap<String, u64>> {
+        let infile = infile.as_ref();
+        if !Self::is_streamed(infile)? {
+            return Ok(
+                Self::new(Self::indexable_path(infile, allow_recompress, state)?)?.lengths(),
+            );
+        }
+        let mut lens = HashMap::new();
+        Self::for_each_read(infile, state, |name, seq| {
+            lens.entry(name.to_owned()).or_insert(seq.len() as u64);
+            Ok(())
+        })?;
+        Ok(lens)
+    }
+
+    /// Whether `infile` can be range-queried with an index. Stdin (`-`), pipes, and other streams cannot.
+    pub fn is_indexable(infile: impl AsRef<Path>) -> bool {
+        let infile = infile.as_ref();
+        infile != Path::new("-") && std::fs::metadata(infile).is_ok_and(|meta| meta.is_file())
+    }
+
+    #[cfg(feature = "zstd")]
+    fn is_seekable_zstd(infile: impl AsRef<Path>) -> Result<bool> {
+        seekable_zstd::is_seekable(infile)
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn is_seekable_zstd(_infile: impl AsRef<Path>) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Path of an indexable copy of `infile`.
+    ///
+    /// # Arguments
+    /// * `infile`
+    ///     * FASTA, FASTQ, BAM, or CRAM file.
+    /// * `allow_recompress`
+    ///     * Recompress a plain gzipped FASTA with bgzip to `recompressed_{name}` in [`RunState::copy_dir`].
+    ///       An existing copy newer than `infile` is reused.
+    /// * `state`
+    ///     * Run malformed records of a FASTQ are skipped in and copies are written to the directory of. See
+    ///       [`Fasta::convert_to_fasta`].
+    ///
+    /// # Returns
+    /// * `infile` if an uncompressed, bgzipped, or zstd-compressed FASTA. Otherwise, the recompressed copy.
+    /// * The converted copy if a FASTQ. See [`Fasta::convert_to_fasta`].
+    /// * Error if `infile` is a plain gzipped FASTA and `allow_recompress` is `false`.
+    /// * Error if `infile` is a BAM or CRAM. Its records are streamed with [`Fasta::for_each_read`] instead.
+    pub fn indexable_path(
+        infile: impl AsRef<Path>,
+        allow_recompress: bool,
+        state: &RunState,
+    ) -> Result<PathBuf> {
+        let infile = infile.as_ref();
+        match SeqFormat::detect(infile)? {
+            SeqFormat::Fasta => {}
+            SeqFormat::Fastq => return Self::convert_to_fasta(infile, state),
+            SeqFormat::Bam | SeqFormat::Cram => {
+                return Err(GavisunkError::StreamedReads(infile.to_owned()))
+            }
+        }
+        if Compression::detect(infile)? != Compression::Gzip {
+            return Ok(infile.to_owned());
+        }
+        if !allow_recompress {
+            return Err(GavisunkError::PlainGzip(infile.to_owned()));
+        }
+        let mut fname = infile
+            .file_name()
+            .map(|fname| fname.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // Indexes are only looked up for bgzipped files ending in `.gz`.
+        if !fname.ends_with(".gz") {
+            fname.push_str(".gz");
+        }
+        let outfile = state.copy_dir.join(format!("recompressed_{fname}"));
+        let modified = |path: &Path| path.metadata().and_then(|meta| meta.modified());
+        if outfile.exists() && modified(&outfile)? >= modified(infile)? {
+            log::info!("Reusing bgzipped copy of {infile:?}, {outfile:?}.");
+            return Ok(outfile);
+        }
+        log::info!("Recompressing {infile:?} with bgzip to {outfile:?}.");
+        write_atomic(&outfile, |file| {
+            let mut reader = MultiGzDecoder::new(BufReader::new(File::open(infile)?));
+            let mut writer = bgzf::Writer::new(file);
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.finish()?;
+            Ok(())
+        })?;
+        let gzi = outfile.with_extension("gz.gzi");
+        write_atomic(&gzi, |file| write_gzi(&outfile, file))?;
+        // A stale index of a previous copy no longer matches.
+        let fai = outfile.with_extension("gz.fai");
+        if fai.exists() {
+            std::fs::remove_file(fai)?;
+        }
+        Ok(outfile)
+    }
+
+    /// Convert a FASTQ to a bgzipped FASTA, `converted_{name}.{hash}.fa.gz`, in [`RunState::copy_dir`] with a `.gzi`
+    /// index. `hash` is the CRC-32 of the canonical path of `infile`, so inputs with the same name in different
+    /// directories get their own copy. Qualities and header descriptions are dropped. An existing copy newer than
+    /// `infile` is reused.
+    ///
+    /// Malformed records, ex. truncated reads, are skipped with [`skip_malformed_record`].
+    ///
+    /// # Arguments
+    /// * `infile`
+    ///     * FASTQ file. gzipped, bgzipped, and zstd-compressed input is decompressed.
+    /// * `state`
+    ///     * Run malformed records are skipped in and the copy is written to the directory of.
+    ///
+    /// # Returns
+    /// * Path of the converted copy.
+    pub fn convert_to_fasta(infile: impl AsRef<Path>, state: &RunState) -> Result<PathBuf> {
+        let infile = infile.as_ref();
+        let mut fname = infile
+            .file_name()
+            .map(|fname| fname.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for ext in [".gz", ".zst", ".fastq", ".fq"] {
+            if let Some(stem) = fname.strip_suffix(ext) {
+                fname = stem.to_owned();
+            }
+        }
+        let path_hash = crc32fast::hash(infile.canonicalize()?.to_string_lossy().as_bytes());
+        let outfile = state
+            .copy_dir
+            .join(format!("converted_{fname}.{path_hash:08x}.fa.gz"));
+        let modified = |path: &Path| path.metadata().and_then(|meta| meta.modified());
+        if outfile.exists() && modified(&outfile)? >= modified(infile)? {
+            log::info!("Reusing FASTA copy of {infile:?}, {outfile:?}.");
+            return Ok(outfile);
+        }
+        log::info!("Converting {infile:?} to bgzipped FASTA {outfile:?}.");
+        write_atomic(&outfile, |file| {
+            let mut writer = bgzf::Writer::new(file);
+            Self::for_each_record(
+                infile,
+                Self::decompressed_reader(infile)?,
+                state,
+                |name, seq| {
+                    writer.write_all(b">")?;
+                    writer.write_all(name.as_bytes())?;
+                    writer.write_all(b"\n")?;
+                    writer.write_all(seq)?;
+                    writer.write_all(b"\n")?;
+                    Ok(())
+                },
+            )?;
+            writer.finish()?;
+            Ok(())
+        })?;
+        let gzi = outfile.with_extension("gz.gzi");
+        write_atomic(&gzi, |file| write_gzi(&outfile, file))?;
+        // A stale index of a previous copy no longer matches.
+        let fai = outfile.with_extension("gz.fai");
+        if fai.exists() {
+            std::fs::remove_file(fai)?;
+        }
+        Ok(outfile)
+    }
+
+    /// Open a file or stdin (`-`), decompressing gzipped and zstd-compressed input.
+    fn decompressed_reader(fname: &Path) -> Result<Box<dyn BufRead>> {
+        let mut handle: Box<dyn BufRead> = if fname == Path::new("-") {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(fname)?))
+        };
+        if handle.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            handle = Box::new(BufReader::new(MultiGzDecoder::new(handle)));
+        } else if handle.fill_buf()?.starts_with(&ZSTD_MAGIC) {
+            handle = Self::zstd_decoder(fname, handle)?;
+        }
+        Ok(handle)
+    }
+
+    /// Call `f` with the normalized name and sequence of each record of a file or stdin (`-`) in any [`SeqFormat`]
+    /// without indexing it, ex. to map BAM or CRAM reads in batches.
+    ///
+    /// Malformed records, ex. without sequence, are skipped with [`skip_malformed_record`].
+    pub fn for_each_read(
+        infile: impl AsRef<Path>,
+        state: &RunState,
+        mut f: impl FnMut(&str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let infile = infile.as_ref();
+        Self::for_each_record(
+            infile,
+            Self::decompressed_reader(infile)?,
+            state,
+            |name, seq| f(normalize_name(name), seq),
+        )
+    }
+
+    /// Call `f` with the name and sequence of each record of a decompressed stream in any [`SeqFormat`].
+    ///
+    /// Malformed records, ex. without sequence, are skipped with [`skip_malformed_record`].
+    fn for_each_record(
+        fname: &Path,
+        mut handle: Box<dyn BufRead>,
+        state: &RunState,
+        mut f: impl FnMut(&str, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let mut visit = |i: usize, rec: std::io::Result<(String, Vec<u8>)>| -> Result<bool> {
+            match rec {
+                Ok((name, seq)) if seq.is_empty() => {
+                    skip_malformed_record(fname, &name, "no sequence", state)?;
+                }
+                Ok((name, seq)) => f(&name, &seq)?,
+                // Nothing more can be read from a truncated file.
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    skip_malformed_record(fname, &format!("record {}", i + 1), err, state)?;
+                    return Ok(false);
+                }
+                Err(err) => {
+                    skip_malformed_record(fname, &format!("record {}", i + 1), err, state)?;
+                }
+            }
+            Ok(true)
+        };
+        match SeqFormat::from_header(handle.fill_buf()?) {
+            #[cfg(feature = "bam")]
+            format @ (SeqFormat::Bam | SeqFormat::Cram) => {
+                Self::for_each_alignment_record(format, handle, &mut visit)?;
+            }
+            #[cfg(not(feature = "bam"))]
+            SeqFormat::Bam | SeqFormat::Cram => {
+                return Err(GavisunkError::BamDisabled(fname.to_owned()));
+            }
+            SeqFormat::Fastq => {
+                let mut reader = fastq::io::Reader::new(handle);
+                for (i, rec) in reader.records().enumerate() {
+                    let rec = rec.map(|rec| {
+                        (
+                            String::from_utf8_lossy(rec.name()).into_owned(),
+                            rec.sequence().to_vec(),
+                        )
+                    });
+                    if !visit(i, rec)? {
+                        break;
+                    }
+                }
+            }
+            SeqFormat::Fasta => {
+                let mut reader = fasta::io::Reader::new(handle);
+                for (i, rec) in reader.records().enumerate() {
+                    let rec = rec.map(|rec| {
+                        (
+                            String::from_utf8_lossy(rec.name()).into_owned(),
+                            rec.sequence().as_ref().to_vec(),
+                        )
+                    });
+                    if !visit(i, rec)? {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Visit the primary records of a BAM or CRAM stream. See [`Fasta::for_each_record`].
+    #[cfg(feature = "bam")]
+    fn for_each_alignment_record(
+        format: SeqFormat,
+        handle: Box<dyn BufRead>,
+        visit: &mut impl FnMut(usize, std::io::Result<(String, Vec<u8>)>) -> Result<bool>,
+    ) -> Result<()> {
+        use noodles::sam::alignment::{record::Sequence, Record};
+
+        /// Name and sequence of a record. [`None`] if a secondary or supplementary alignment.
+        fn primary_read(rec: &impl Record) -> std::io::Result<Option<(String, Vec<u8>)>> {
+            let flags = rec.flags()?;
+            if flags.is_secondary() || flags.is_supplementary() {
+                return Ok(None);
+            }
+            // Skipped as malformed as its hits couldn't be told apart from other reads'.
+            let Some(name) = rec.name() else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "record has no name",
+                ));
+            };
+            Ok(Some((
+                String::from_utf8_lossy(name).into_owned(),
+                rec.sequence().iter().collect(),
+            )))
+        }
+
+        if format == SeqFormat::Cram {
+            let mut reader = cram::io::Reader::new(handle);
+            let header = reader.read_header()?;
+            for (i, rec) in reader.records(&header).enumerate() {
+                let Some(rec) = rec.and_then(|rec| primary_read(&rec)).transpose() else {
+                    continue;
+                };
+                if !visit(i, rec)? {
+                    break;
+                }
+            }
+        } else {
+            // The stream is already decompressed.
+            let mut reader = bam::io::Reader::from(handle);
+            reader.read_header()?;
+            for (i, rec) in reader.records().enumerate() {
+                let Some(rec) = rec.and_then(|rec| primary_read(&rec)).transpose() else {
+                    continue;
+                };
+                if !visit(i, rec)? {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load all records of a FASTA, FASTQ, BAM, or CRAM file or stream into memory.
+    ///
+    /// Malformed records, ex. without sequence, are skipped with [`skip_malformed_record`].
+    ///
+    /// # Arguments
+    /// * `infile`
+    ///     * FASTA, FASTQ, BAM, or CRAM file. `-` reads from stdin. gzipped and zstd-compressed input is decompressed.
+    /// * `max_bytes`
+    ///     * Error if sequences exceed this many bytes. If [`None`], unlimited.
+    /// * `state`
+    ///     * Run malformed records are skipped in.
+    pub fn new_in_memory(
+        infile: impl AsRef<Path>,
+        max_bytes: Option<u64>,
+        state: &RunState,
+    ) -> Result<Self> {
+        let fname = infile.as_ref().to_owned();
+        let handle = Self::decompressed_reader(&fname)?;
+        let mut seqs = HashMap::new();
+        let mut total_bytes = 0;
+        Self::for_each_record(&fname, handle, state, |name, seq| {
+            total_bytes += seq.len() as u64;
+            if let Some(budget) = max_bytes.filter(|budget| total_bytes > *budget) {
+                return Err(GavisunkError::InsufficientMemory {
+                    path: fname.clone(),
+                    required: total_bytes,
+                    budget,
+                });
+            }
+            let key = normalize_name(name);
+            if seqs.contains_key(key) {
+                log::warn!(
+                    "Records in {fname:?} have the same name, {key:?}. Only the first is used."
+                );
+                return Ok(());
+            }
+            seqs.insert(key.to_owned(), seq.to_vec());
+            Ok(())
+        })?;
+        log::info!("Loaded {total_bytes} bases from {fname:?} into memory.");
+        Ok(Self::from_seqs(fname, seqs))
+    }
+
+    /// In-memory sequences, ex. a batch of records from [`Fasta::for_each_read`].
+    ///
+    /// # Arguments
+    /// * `fname`
+    ///     * File the sequences were read from. Used in errors.
+    /// * `seqs`
+    ///     * Sequences by normalized name.
+    pub fn from_seqs(fname: impl AsRef<Path>, seqs: HashMap<String, Vec<u8>>) -> Self {
+        Self {
+            fname: fname.as_ref().to_owned(),
+            reader: FastaReader::InMemory(Arc::new(seqs)),
+            index: fasta::fai::Index::default(),
+            names: HashMap::new(),
+            lens: HashMap::new(),
+        }
+    }
+
+    /// Get a new handle to the same FASTA. Indexed files are reopened and in-memory sequences are shared.
+    pub fn try_clone(&self) -> Result<Self> {
+        match &self.reader {
+            FastaReader::InMemory(seqs) => Ok(Self {
+                fname: self.fname.clone(),
+                reader: FastaReader::InMemory(seqs.clone()),
+                index: fasta::fai::Index::default(),
+                names: HashMap::new(),
+                lens: HashMap::new(),
+            }),
+            FastaReader::Bgzip(_) | FastaReader::Standard(_) => Self::new(&self.fname),
+            #[cfg(feature = "zstd")]
+            FastaReader::Zstd(_) => Self::new(&self.fname),
+        }
+    }
+
+    /// Record lengths by normalized name.
     pub fn lengths(&self) -> HashMap<String, u64> {
-        self.index
-            .as_ref()
-            .iter()
-            .map(|rec| {
-                (
-                    String::from_utf8(rec.name().to_vec()).unwrap(),
-                    rec.length(),
-                )
-            })
-            .collect()
+        if let FastaReader::InMemory(seqs) = &self.reader {
+            return seqs
+                .iter()
+                .map(|(name, seq)| (name.clone(), seq.len() as u64))
+                .collect();
+        }
+        self.lens.clone()
     }
 
-    fn get_faidx(
-        fa: &impl AsRef<Path>,
-    ) -> eyre::Result<(fasta::fai::Index, Option<bgzf::gzi::Index>)> {
+    fn get_faidx(fa: &impl AsRef<Path>) -> Result<(fasta::fai::Index, Option<bgzf::gzi::Index>)> {
         // https://www.ginkgobioworks.com/2023/03/17/even-more-rapid-retrieval-from-very-large-files-with-rust/
         let fa_path = fa.as_ref().canonicalize()?;
         let is_bgzipped = fa_path.extension().and_then(|e| e.to_str()) == Some("gz");
@@ -123,7 +821,10 @@ impl Fasta {
         if is_bgzipped {
             let index_reader = bgzf::indexed_reader::Builder::default()
                 .build_from_path(fa)
-                .with_context(|| format!("Failed to read gzi for {fa_path:?}"))?;
+                .map_err(|source| GavisunkError::Index {
+                    path: fa_path.clone(),
+                    source,
+                })?;
             let gzi = index_reader.index().clone();
 
             if let Ok(fai) = fai {
@@ -147,20 +848,138 @@ impl Fasta {
         }
     }
 
-    pub fn fetch(&mut self, ctg_name: &str, start: u32, stop: u32) -> eyre::Result<fasta::Record> {
-        let start_pos = noodles::core::Position::new(start.clamp(1, u32::MAX) as usize).unwrap();
-        let stop_pos = noodles::core::Position::new(stop.clamp(1, u32::MAX) as usize).unwrap();
-        let region = noodles::core::Region::new(ctg_name, start_pos..=stop_pos);
+    /// Length of a record. `ctg_name` is normalized with [`normalize_name`].
+    pub fn length(&self, ctg_name: &str) -> Option<u64> {
+        let ctg_name = normalize_name(ctg_name);
+        if let FastaReader::InMemory(seqs) = &self.reader {
+            return seqs.get(ctg_name).map(|seq| seq.len() as u64);
+        }
+        self.lens.get(ctg_name).copied()
+    }
+
+    /// Fetch a 1-based, inclusive region of a record. `ctg_name` is normalized with [`normalize_name`].
+    ///
+    /// Copies in-memory sequences into the record. Use [`Fasta::fetch_bytes`] if only the sequence is needed.
+    ///
+    /// # Returns
+    /// * An empty record if `stop` is `start - 1`.
+    /// * [`GavisunkError::InvalidFetch`] if `start` is `0`, after `stop + 1`, or `stop` is past the end of the record.
+    pub fn fetch(&mut self, ctg_name: &str, start: u64, stop: u64) -> Result<fasta::Record> {
+        let ctg_name = normalize_name(ctg_name);
+        match self.fetch_bytes(ctg_name, start, stop)? {
+            FetchedSeq::Record(rec) => Ok(rec),
+            FetchedSeq::Borrowed(seq) => Ok(fasta::Record::new(
+                fasta::record::Definition::new(format!("{ctg_name}:{start}-{stop}"), None),
+                fasta::record::Sequence::from(seq.to_vec()),
+            )),
+        }
+    }
+
+    /// Fetch the sequence of a 1-based, inclusive region of a record without copying in-memory sequences.
+    ///
+    /// See [`Fasta::fetch`] for arguments and errors.
+    pub fn fetch_bytes(&mut self, ctg_name: &str, start: u64, stop: u64) -> Result<FetchedSeq<'_>> {
+        let ctg_name = normalize_name(ctg_name);
+        let invalid = |reason: String| GavisunkError::InvalidFetch {
+            path: self.fname.clone(),
+            ctg: ctg_name.to_owned(),
+            start,
+            stop,
+            reason,
+        };
+        if start == 0 {
+            return Err(invalid("positions are 1-based".to_owned()));
+        }
+        if start > stop.saturating_add(1) {
+            return Err(invalid("start is after stop".to_owned()));
+        }
+        // Unknown records are left to the reader to report.
+        if let Some(len) = self.length(ctg_name).filter(|len| stop > *len) {
+            return Err(invalid(format!("record is {len} bp")));
+        }
+        if start > stop {
+            return Ok(FetchedSeq::Borrowed(&[]));
+        }
+        let to_position = |pos: u64| {
+            usize::try_from(pos)
+                .ok()
+                .and_then(noodles::core::Position::new)
+                .ok_or_else(|| invalid(format!("{pos} exceeds the addressable size")))
+        };
+        let (start_pos, stop_pos) = (to_position(start)?, to_position(stop)?);
+        let index_name = self
+            .names
+            .get(ctg_name)
+            .map_or(ctg_name, |name| name.as_str());
+        let region = noodles::core::Region::new(index_name, start_pos..=stop_pos);
         match &mut self.reader {
-            FastaReader::Bgzip(reader) => Ok(reader.query(&self.index, &region)?),
-            FastaReader::Standard(reader) => Ok(reader.query(&self.index, &region)?),
+            FastaReader::Bgzip(reader) => {
+                Ok(FetchedSeq::Record(reader.query(&self.index, &region)?))
+            }
+            FastaReader::Standard(reader) => {
+                Ok(FetchedSeq::Record(reader.query(&self.index, &region)?))
+            }
+            #[cfg(feature = "zstd")]
+            FastaReader::Zstd(reader) => {
+                Ok(FetchedSeq::Record(reader.query(&self.index, &region)?))
+            }
+            FastaReader::InMemory(seqs) => {
+                let Some(seq) = seqs.get(ctg_name) else {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("invalid reference sequence name: {ctg_name}"),
+                    )
+                    .into());
+                };
+                Ok(FetchedSeq::Borrowed(
+                    &seq[usize::from(start_pos) - 1..usize::from(stop_pos)],
+                ))
+            }
         }
     }
 
-    fn read_fa(
-        fa: &impl AsRef<Path>,
-        fa_gzi: Option<&bgzf::gzi::Index>,
-    ) -> eyre::Result<FastaReader> {
+    /// Open a seekable zstd FASTA and its `{name}.fai` index, indexing the decompressed records if there is none.
+    #[cfg(feature = "zstd")]
+    fn read_zstd(fa: &Path) -> Result<(fasta::fai::Index, FastaReader)> {
+        let mut fai_fname = fa.as_os_str().to_owned();
+        fai_fname.push(".fai");
+        let index = match fasta::fai::read(PathBuf::from(fai_fname)) {
+            Ok(fai) => {
+                log::debug!("Existing fai index found for {fa:?}");
+                fai
+            }
+            Err(_) => {
+                log::debug!("No existing faidx for {fa:?}. Generating...");
+                let mut records = Vec::new();
+                let mut indexer = fasta::io::Indexer::new(BufReader::new(SeekableZstd::open(fa)?));
+                while let Some(record) = indexer.index_record()? {
+                    records.push(record);
+                }
+                fasta::fai::Index::from(records)
+            }
+        };
+        let reader = fasta::io::Reader::new(BufReader::new(SeekableZstd::open(fa)?));
+        Ok((index, FastaReader::Zstd(reader)))
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn read_zstd(fa: &Path) -> Result<(fasta::fai::Index, FastaReader)> {
+        Err(GavisunkError::ZstdDisabled(fa.to_owned()))
+    }
+
+    #[cfg(feature = "zstd")]
+    fn zstd_decoder(_fa: &Path, handle: Box<dyn BufRead>) -> Result<Box<dyn BufRead>> {
+        Ok(Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(handle)?,
+        )))
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn zstd_decoder(fa: &Path, _handle: Box<dyn BufRead>) -> Result<Box<dyn BufRead>> {
+        Err(GavisunkError::ZstdDisabled(fa.to_owned()))
+    }
+
+    fn read_fa(fa: &impl AsRef<Path>, fa_gzi: Option<&bgzf::gzi::Index>) -> Result<FastaReader> {
         let fa_file = std::fs::File::open(fa);
         if let Some(fa_gzi) = fa_gzi {
             Ok(FastaReader::Bgzip(
@@ -178,47 +997,575 @@ impl Fasta {
     }
 }
 
-pub fn write_tsv(df: &mut DataFrame, path: impl AsRef<Path>) -> eyre::Result<()> {
-    let mut file = File::create(path)?;
-    CsvWriter::new(&mut file)
+/// Write a GZI index of a BGZF file.
+///
+/// The index is the number of blocks after the first and the `(compressed, uncompressed)` offsets of each,
+/// as little-endian [`u64`]s. Offsets are read from the `BSIZE` and `ISIZE` fields of each block.
+fn write_gzi(bgzf_path: &Path, writer: &mut impl Write) -> Result<()> {
+    let mut reader = BufReader::new(File::open(bgzf_path)?);
+    let mut offsets: Vec<(u64, u64)> = vec![];
+    let (mut compressed, mut uncompressed) = (0, 0);
+    let mut header = [0; 18];
+    loop {
+        match reader.read_exact(&mut header) {
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            res => res?,
+        }
+        let block_size = u64::from(u16::from_le_bytes([header[16], header[17]])) + 1;
+        let mut rest = vec![0; block_size as usize - header.len()];
+        reader.read_exact(&mut rest)?;
+        let block_len = rest
+            .last_chunk::<4>()
+            .map_or(0, |block_len| u32::from_le_bytes(*block_len));
+        if compressed > 0 && block_len > 0 {
+            offsets.push((compressed, uncompressed));
+        }
+        compressed += block_size;
+        uncompressed += u64::from(block_len);
+    }
+    writer.write_all(&(offsets.len() as u64).to_le_bytes())?;
+    for (compressed, uncompressed) in offsets {
+        writer.write_all(&compressed.to_le_bytes())?;
+        writer.write_all(&uncompressed.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Write to a file atomically.
+///
+/// Output is written to `{path}.tmp`, synced to disk, and then renamed to `path`.
+/// If the process dies mid-write, only the temporary file is left behind.
+///
+/// # Arguments
+/// * `path`
+///     * Final output path.
+/// * `write_fn`
+///     * Function writing contents to the given file handle.
+pub fn write_atomic(
+    path: impl AsRef<Path>,
+    write_fn: impl FnOnce(&mut File) -> Result<()>,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut tmp_fname = path.as_os_str().to_owned();
+    tmp_fname.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_fname);
+
+    let mut file = File::create(&tmp_path)?;
+    if let Err(err) = write_fn(&mut file).and_then(|_| Ok(file.sync_all()?)) {
+        std::fs::remove_file(&tmp_path)?;
+        return Err(err);
+    }
+    std::fs::rename(&tmp_path, path).map_err(|source| GavisunkError::Rename {
+        from: tmp_path.clone(),
+        to: path.to_owned(),
+        source,
+    })?;
+    Ok(())
+}
+
+/// Directory removed with its contents when dropped, ex. on success, error, or cancellation.
+#[derive(Debug)]
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    /// Create a directory in `parent` named `{prefix}.{pid}.{n}`, unique to this process and call.
+    ///
+    /// # Arguments
+    /// * `parent`
+    ///     * Directory to create it in. Created if missing.
+    /// * `prefix`
+    ///     * Name prefix, ex. the stage using it.
+    pub fn new_in(parent: impl AsRef<Path>, prefix: &str) -> Result<Self> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let parent = parent.as_ref();
+        std::fs::create_dir_all(parent)?;
+        loop {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let path = parent.join(format!("{prefix}.{}.{id}", std::process::id()));
+            match std::fs::create_dir(&path) {
+                Ok(()) => return Ok(Self { path }),
+                // Left by a killed run with the same pid.
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.path) {
+            log::warn!("Failed to remove {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// Write a [`DataFrame`] to a TSV file with header atomically.
+///
+/// Rows are sorted by all columns, in column order, so output is deterministic regardless of thread scheduling.
+pub fn write_tsv(df: &mut DataFrame, path: impl AsRef<Path>) -> Result<()> {
+    write_atomic(path, |file| write_tsv_to(df, file))
+}
+
+/// Write a [`DataFrame`] to a TSV file atomically after `#` comment lines. See [`crate::parameters::RunMetadata::comment_header`].
+pub fn write_tsv_with_comments(
+    df: &mut DataFrame,
+    path: impl AsRef<Path>,
+    comments: &str,
+) -> Result<()> {
+    write_atomic(path, |file| {
+        file.write_all(comments.as_bytes())?;
+        write_tsv_to(df, file)
+    })
+}
+
+/// Copy of `df` with rows sorted by all columns, in column order, so output is deterministic regardless of thread
+/// scheduling. `df` is left in its order.
+pub fn sort_rows(df: &DataFrame) -> Result<DataFrame> {
+    Ok(df.sort(
+        df.get_column_names_owned(),
+        SortMultipleOptions::default().with_maintain_order(true),
+    )?)
+}
+
+/// Write a [`DataFrame`] as TSV with header to any writer. Rows are sorted as in [`write_tsv`].
+pub fn write_tsv_to(df: &mut DataFrame, writer: impl Write) -> Result<()> {
+    CsvWriter::new(writer)
         .include_header(true)
         .with_separator(b'\t')
-        .finish(df)?;
+        .finish(&mut sort_rows(df)?)?;
     Ok(())
 }
 
-pub fn load_tsv(path: impl AsRef<Path>) -> eyre::Result<DataFrame> {
+/// Write a [`DataFrame`] as TSV without header to any writer. Rows are sorted as in [`write_tsv`].
+pub fn write_tsv_rows_to(df: &mut DataFrame, writer: impl Write) -> Result<()> {
+    CsvWriter::new(writer)
+        .include_header(false)
+        .with_separator(b'\t')
+        .finish(&mut sort_rows(df)?)?;
+    Ok(())
+}
+
+/// Columns of `asm_sunks.tsv`. See [`crate::get_kmers::get_sunk_positions`].
+pub const ASM_SUNKS_COLUMNS: &[(&str, DataType)] = &[
+    ("ctg", DataType::String),
+    ("cpos", DataType::Int64),
+    ("kmer", DataType::String),
+    ("strand", DataType::String),
+    ("group", DataType::Int64),
+];
+/// Columns of `read_sunks.tsv`.
+pub const READ_SUNKS_COLUMNS: &[(&str, DataType)] = &[
+    ("read", DataType::String),
+    ("rpos", DataType::Int64),
+    ("ctg", DataType::String),
+    ("cpos", DataType::Int64),
+    ("group", DataType::Int64),
+    ("strand", DataType::String),
+    ("hits", DataType::Int64),
+];
+/// Columns of `read_ctg_mapping.tsv`.
+pub const READ_CTG_MAPPING_COLUMNS: &[(&str, DataType)] = &[
+    ("read", DataType::String),
+    ("ctg", DataType::String),
+    ("sunks_within_bandwidth", DataType::Int64),
+    ("ort", DataType::String),
+];
+/// Columns of `read_sunks_good.tsv`.
+pub const READ_SUNKS_GOOD_COLUMNS: &[(&str, DataType)] = &[
+    ("read", DataType::String),
+    ("rpos", DataType::Int64),
+    ("ctg", DataType::String),
+    ("cpos", DataType::Int64),
+    ("group", DataType::Int64),
+];
+/// Columns of `read_sunks_bad.tsv`.
+pub const READ_SUNKS_BAD_COLUMNS: &[(&str, DataType)] =
+    &[("id", DataType::String), ("count", DataType::Int64)];
+
+/// Column names of a TSV file with header, skipping leading `#` comment lines.
+fn tsv_header(path: &Path) -> Result<Vec<String>> {
+    let reader = BufReader::new(File::open(path)?);
+    for line in reader.lines() {
+        let line = line?;
+        if !line.starts_with('#') {
+            return Ok(line.split('\t').map(str::to_owned).collect());
+        }
+    }
+    Ok(vec![])
+}
+
+/// Load a TSV file with header and known columns. Leading `#` comment lines are skipped.
+///
+/// Types aren't inferred so, ex. numeric contig names stay strings, and only projected columns are kept in memory.
+///
+/// # Arguments
+/// * `path`
+///     * TSV file.
+/// * `columns`
+///     * Names and types of all columns in order. ex. [`READ_SUNKS_COLUMNS`]. If the header differs, ex. from an
+///       older version, types are inferred with [`load_tsv`] instead.
+/// * `projection`
+///     * Columns to load. All if empty.
+pub fn load_tsv_with_schema(
+    path: impl AsRef<Path>,
+    columns: &[(&str, DataType)],
+    projection: &[&str],
+) -> Result<DataFrame> {
+    let path = path.as_ref();
+    let header = tsv_header(path)?;
+    let lf = if header
+        .iter()
+        .map(String::as_str)
+        .eq(columns.iter().map(|(name, _)| *name))
+    {
+        let schema = Schema::from_iter(
+            columns
+                .iter()
+                .map(|(name, dtype)| Field::new((*name).into(), dtype.clone())),
+        );
+        LazyCsvReader::new(path)
+            .with_has_header(true)
+            .with_separator(b'\t')
+            .with_comment_prefix(Some("#".into()))
+            .with_schema(Some(Arc::new(schema)))
+            .finish()?
+    } else {
+        log::warn!("Columns of {path:?}, {header:?}, differ from those expected. Inferring types.");
+        load_tsv(path)?.lazy()
+    };
+    let lf = if projection.is_empty() {
+        lf
+    } else {
+        lf.select(
+            projection
+                .iter()
+                .map(|name| col(*name))
+                .collect::<Vec<Expr>>(),
+        )
+    };
+    Ok(lf.collect()?)
+}
+
+/// Load a TSV file with header. Leading `#` comment lines are skipped.
+pub fn load_tsv(path: impl AsRef<Path>) -> Result<DataFrame> {
     Ok(CsvReadOptions::default()
         .with_has_header(true)
-        .with_parse_options(CsvParseOptions::default().with_separator(b'\t'))
+        .with_parse_options(
+            CsvParseOptions::default()
+                .with_separator(b'\t')
+                .with_comment_prefix(Some("#")),
+        )
         .try_into_reader_with_file_path(Some(PathBuf::from(path.as_ref())))?
         .finish()?)
 }
 
+/// Version of the format of intermediate files. Bumped when the columns or types of an intermediate change so caches
+/// from older versions fail with a clear error rather than a schema error when loaded.
+pub const INTERMEDIATE_FORMAT_VERSION: u32 = 3;
+
+/// Parameters an intermediate file was produced with.
+///
+/// Stored as a JSON sidecar, `{path}.params.json`, next to each intermediate so cached files
+/// produced with different parameters aren't silently mixed. The sidecar also records the
+/// [`INTERMEDIATE_FORMAT_VERSION`] and version of `rs-gavisunk` that produced the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntermediateParams {
+    pub kmer_size: usize,
+    pub assembly: PathBuf,
+    pub reads: PathBuf,
+    /// Contig renames applied to the assembly. See [`crate::naming::ContigRenames`].
+    #[serde(default)]
+    pub rename_map: Option<PathBuf>,
+    /// CRC-32 of the rename map so edits to it in place are caught. See [`file_crc32`].
+    #[serde(default)]
+    pub rename_map_crc32: Option<String>,
+    /// Read alignments used instead of mapping SUNKs to reads. See `--anchor-paf`.
+    #[serde(default)]
+    pub anchor_paf: Option<PathBuf>,
+    /// Flags of the intermediate's stage and the stages before it that change its contents, as `{flag: value}`.
+    /// ex. `{"sunk_dedup": "First"}` for `read_sunks.tsv`. See [`IntermediateParams::with_flag`].
+    #[serde(default)]
+    pub flags: BTreeMap<String, String>,
+}
+
+impl IntermediateParams {
+    /// Add a flag changing the intermediates of a stage. Intermediates of later stages should keep the flags of
+    /// earlier ones as they're derived from them.
+    ///
+    /// # Arguments
+    /// * `flag`
+    ///     * Name of the flag. ex. `sunk_dedup`
+    /// * `value`
+    ///     * Value of the flag, recorded with its [`std::fmt::Debug`] representation.
+    pub fn with_flag(mut self, flag: &str, value: impl std::fmt::Debug) -> Self {
+        self.flags.insert(flag.to_owned(), format!("{value:?}"));
+        self
+    }
+
+    fn sidecar(path: &Path) -> PathBuf {
+        let mut fname = path.as_os_str().to_owned();
+        fname.push(".params.json");
+        PathBuf::from(fname)
+    }
+
+    /// Write parameters to the sidecar of `path` stamped with the current format version and its number of rows.
+    pub fn write(&self, path: impl AsRef<Path>, rows: usize) -> Result<()> {
+        let sidecar = IntermediateSidecar {
+            format_version: INTERMEDIATE_FORMAT_VERSION,
+            version: env!("CARGO_PKG_VERSION"),
+            rows,
+            params: self,
+        };
+        write_atomic(Self::sidecar(path.as_ref()), |file| {
+            Ok(serde_json::to_writer_pretty(file, &sidecar)?)
+        })
+    }
+
+    /// Number of rows recorded in the sidecar of `path`, if any. Sidecars from before rows were recorded have none.
+    pub fn recorded_rows(path: impl AsRef<Path>) -> Result<Option<usize>> {
+        let sidecar = Self::sidecar(path.as_ref());
+        if !sidecar.exists() {
+            return Ok(None);
+        }
+        let value: serde_json::Value =
+            serde_json::from_reader(BufReader::new(File::open(&sidecar)?)).map_err(|source| {
+                GavisunkError::InvalidParams {
+                    path: sidecar.clone(),
+                    source,
+                }
+            })?;
+        Ok(value
+            .get("rows")
+            .and_then(serde_json::Value::as_u64)
+            .map(|rows| rows as usize))
+    }
+
+    /// Check that the sidecar of `path` matches these parameters.
+    ///
+    /// # Returns
+    /// * [`GavisunkError::StaleIntermediate`] if `path` is in another format version.
+    /// * Error describing differing parameters if they don't match.
+    pub fn check(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let sidecar = Self::sidecar(path);
+        if !sidecar.exists() {
+            log::warn!("No parameters recorded for {path:?}. Cannot verify it is consistent with current parameters.");
+            return Ok(());
+        }
+        let invalid = |source| GavisunkError::InvalidParams {
+            path: sidecar.clone(),
+            source,
+        };
+        // Check the version first as parameters of other versions may not deserialize.
+        let value: serde_json::Value =
+            serde_json::from_reader(BufReader::new(File::open(&sidecar)?)).map_err(invalid)?;
+        let format_version = value
+            .get("format_version")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0) as u32;
+        if format_version != INTERMEDIATE_FORMAT_VERSION {
+            let produced_by = value
+                .get("version")
+                .and_then(serde_json::Value::as_str)
+                .map_or_else(
+                    || "an unversioned rs-gavisunk".to_owned(),
+                    |version| format!("rs-gavisunk v{version}"),
+                );
+            return Err(GavisunkError::StaleIntermediate {
+                path: path.to_owned(),
+                found: format_version,
+                expected: INTERMEDIATE_FORMAT_VERSION,
+                produced_by,
+            });
+        }
+        let existing: IntermediateParams = serde_json::from_value(value).map_err(invalid)?;
+        let mut diffs = vec![];
+        if existing.kmer_size != self.kmer_size {
+            diffs.push(format!(
+                "kmer_size (existing: {}, current: {})",
+                existing.kmer_size, self.kmer_size
+            ));
+        }
+        if existing.assembly != self.assembly {
+            diffs.push(format!(
+                "assembly (existing: {:?}, current: {:?})",
+                existing.assembly, self.assembly
+            ));
+        }
+        if existing.reads != self.reads {
+            diffs.push(format!(
+                "reads (existing: {:?}, current: {:?})",
+                existing.reads, self.reads
+            ));
+        }
+        if existing.rename_map != self.rename_map {
+            diffs.push(format!(
+                "rename_map (existing: {:?}, current: {:?})",
+                existing.rename_map, self.rename_map
+            ));
+        }
+        if existing.rename_map_crc32 != self.rename_map_crc32 {
+            diffs.push(format!(
+                "rename_map contents (existing crc32: {:?}, current crc32: {:?})",
+                existing.rename_map_crc32, self.rename_map_crc32
+            ));
+        }
+        if existing.anchor_paf != self.anchor_paf {
+            diffs.push(format!(
+                "anchor_paf (existing: {:?}, current: {:?})",
+                existing.anchor_paf, self.anchor_paf
+            ));
+        }
+        let flags: BTreeSet<&String> = existing.flags.keys().chain(self.flags.keys()).collect();
+        for flag in flags {
+            let (existing_value, current_value) = (existing.flags.get(flag), self.flags.get(flag));
+            if existing_value != current_value {
+                diffs.push(format!(
+                    "{flag} (existing: {}, current: {})",
+                    existing_value.map_or("unset", String::as_str),
+                    current_value.map_or("unset", String::as_str)
+                ));
+            }
+        }
+        if !diffs.is_empty() {
+            return Err(GavisunkError::MismatchedParams {
+                path: path.to_owned(),
+                diffs: diffs.join(", "),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Contents of the sidecar of an intermediate.
+#[derive(Serialize)]
+struct IntermediateSidecar<'a> {
+    format_version: u32,
+    /// Version of `rs-gavisunk`.
+    version: &'a str,
+    /// Rows written, to detect truncated files.
+    rows: usize,
+    #[serde(flatten)]
+    params: &'a IntermediateParams,
+}
+
+/// Load an existing intermediate, checking that it isn't empty, unreadable, or truncated, ex. from a crashed run.
+///
+/// Intermediates without rows are only accepted if their sidecar records that none were written.
+///
+/// # Arguments
+/// * `path`
+///     * Intermediate TSV.
+/// * `state`
+///     * Run whose [`RunState::empty_intermediate`] policy handles empty, unreadable, or truncated files.
+/// * `load`
+///     * Function loading `path`.
+///
+/// # Returns
+/// * [`None`] if it's empty, unreadable, or truncated and should be recomputed.
+pub fn load_intermediate(
+    path: &Path,
+    state: &RunState,
+    load: impl FnOnce() -> Result<DataFrame>,
+) -> Result<Option<DataFrame>> {
+    let (df, reason) = if std::fs::metadata(path)?.len() == 0 {
+        (None, "is empty".to_owned())
+    } else {
+        match load() {
+            // ex. A partially written last line.
+            Err(GavisunkError::Schema(err)) => (None, format!("can't be read: {err}")),
+            Err(err) => return Err(err),
+            Ok(df) => {
+                let reason = match IntermediateParams::recorded_rows(path)? {
+                    Some(rows) if rows != df.height() => {
+                        format!("has {} of {rows} rows written", df.height())
+                    }
+                    None if df.height() == 0 => "has no rows".to_owned(),
+                    _ => return Ok(Some(df)),
+                };
+                (Some(df), reason)
+            }
+        }
+    };
+    match (state.empty_intermediate, df) {
+        (EmptyIntermediate::Error, _) => Err(GavisunkError::InvalidIntermediate {
+            path: path.to_owned(),
+            reason,
+        }),
+        (EmptyIntermediate::Keep, Some(df)) => {
+            log::warn!("{path:?} {reason}. Using it anyway.");
+            Ok(Some(df))
+        }
+        (EmptyIntermediate::Keep, None) => {
+            log::warn!("{path:?} {reason} so can't be used as is. Recomputing it.");
+            Ok(None)
+        }
+        (EmptyIntermediate::Redo, _) => {
+            log::warn!("{path:?} {reason}. Recomputing it.");
+            Ok(None)
+        }
+    }
+}
+
 /// Loads the given file if it exists. If not, then redoes function call.
 ///
 /// # Arguments
 /// * `path`
 ///     * File path to TSV with header.
+/// * `params`
+///     * [`IntermediateParams`] recorded alongside `path` and checked when loading an existing file.
+/// * `state`
+///     * [`RunState`] of the run. Existing files that are empty or truncated are handled with its policy.
 /// * `fn_call`
 ///     * Expression that generates a [`DataFrame`].
 ///     * This will be written to `path`.
 /// * `force`
 ///     * Optional argument to force redoing work even if path exists.
+/// * `columns`, `projection`
+///     * Optional known columns and columns to load of an existing file. See [`load_tsv_with_schema`].
 /// # Returns
 /// * [`DataFrame`]
+///     * Existing files that are empty or truncated are handled with [`load_intermediate`].
+#[macro_export]
 macro_rules! load_or_redo_df {
-    ($path:ident, $fn_call:expr) => {
-        load_or_redo_df!($path, $fn_call, false)
-    };
-    ($path:ident, $fn_call:expr, $force:ident) => {
-        if !$path.exists() || $force {
-            let mut df = $fn_call;
-            write_tsv(&mut df, $path)?;
-            df
+    (@load $path:ident, $params:expr, $state:expr, $fn_call:expr, $force:expr, $load:expr) => {{
+        let existing = if !$path.exists() || $force {
+            None
         } else {
             log::info!("Loading existing file: {:?}", $path);
-            load_tsv($path)?
-        }
+            $params.check($path)?;
+            $crate::io::load_intermediate($path, $state, $load)?
+        };
+        let df = match existing {
+            Some(df) => df,
+            None => {
+                // Sorted as written so computed and loaded intermediates have the same row order.
+                let mut df = $crate::io::sort_rows(&$fn_call)?;
+                write_tsv(&mut df, $path)?;
+                $params.write($path, df.height())?;
+                df
+            }
+        };
+        $crate::io::record_intermediate($path, $state);
+        df
+    }};
+    ($path:ident, $params:expr, $state:expr, $fn_call:expr) => {
+        $crate::load_or_redo_df!($path, $params, $state, $fn_call, false)
+    };
+    ($path:ident, $params:expr, $state:expr, $fn_call:expr, $force:expr, $columns:expr, $projection:expr) => {
+        $crate::load_or_redo_df!(@load $path, $params, $state, $fn_call, $force, || {
+            load_tsv_with_schema($path, $columns, $projection)
+        })
+    };
+    ($path:ident, $params:expr, $state:expr, $fn_call:expr, $force:expr) => {
+        $crate::load_or_redo_df!(@load $path, $params, $state, $fn_call, $force, || load_tsv($path))
     };
 }
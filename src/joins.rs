@@ -0,0 +1,105 @@
+//! Row accounting for joins and lookups that can silently drop rows.
+//!
+//! Joins on names or kmers drop rows without error when inputs disagree, ex. reads missing from the reads FASTA or
+//! intermediates left over from another run. Rows lost are checked against the run's fraction,
+//! [`RunState::max_join_loss`], and logged as warnings naming the join and its likely cause.
+
+use polars::prelude::*;
+
+use crate::{error::Result, run_state::RunState};
+
+/// Default fraction of rows a join may lose before warning.
+pub const DEFAULT_MAX_JOIN_LOSS: f64 = 0.01;
+
+/// Warn if a join lost more than [`RunState::max_join_loss`] of its rows.
+///
+/// # Arguments
+/// * `join`
+///     * Name of the join. ex. `read SUNKs x read lengths`
+/// * `cause`
+///     * Likely cause of lost rows.
+/// * `total`
+///     * Rows before the join.
+/// * `lost`
+///     * Rows without a match.
+/// * `state`
+///     * State of the run.
+///
+/// # Returns
+/// * Fraction of rows lost. `0.0` if there were no rows.
+pub fn check_join_loss(
+    join: &str,
+    cause: &str,
+    total: usize,
+    lost: usize,
+    state: &RunState,
+) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    let frac = lost as f64 / total as f64;
+    if lost > 0 && frac > state.max_join_loss {
+        log::warn!(
+            "Join {join} lost {lost} of {total} rows ({:.2}%). Likely cause: {cause}.",
+            frac * 100.0
+        );
+    } else if lost > 0 {
+        log::debug!("Join {join} lost {lost} of {total} rows.");
+    }
+    frac
+}
+
+/// Count rows of `df` whose `on` columns have no match in `df_other`.
+pub fn count_unmatched(df: &DataFrame, df_other: &DataFrame, on: &[&str]) -> Result<usize> {
+    let on_cols: Vec<Expr> = on.iter().map(|c| col(*c)).collect();
+    let lf_keys = df_other
+        .select(on.iter().copied())?
+        .lazy()
+        .unique(None, UniqueKeepStrategy::First)
+        .with_column(lit(true).alias("matched"));
+    Ok(df
+        .select(on.iter().copied())?
+        .lazy()
+        .join(
+            lf_keys,
+            on_cols.clone(),
+            on_cols,
+            JoinArgs::new(JoinType::Left),
+        )
+        .filter(col("matched").is_null())
+        .collect()?
+        .height())
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{check_join_loss, count_unmatched};
+    use crate::run_state::RunState;
+
+    #[test]
+    fn test_count_unmatched() {
+        let df_read_sunks = df!(
+            "read" => ["r1", "r1", "r2", "r3"],
+            "ctg" => ["c1", "c1", "c1", "c2"],
+        )
+        .unwrap();
+        let df_best_reads_asm = df!(
+            "read" => ["r1", "r2", "r4"],
+            "ctg" => ["c1", "c2", "c1"],
+        )
+        .unwrap();
+        assert_eq!(
+            count_unmatched(&df_best_reads_asm, &df_read_sunks, &["read", "ctg"]).unwrap(),
+            2
+        );
+        assert_eq!(
+            count_unmatched(&df_read_sunks, &df_best_reads_asm, &["read"]).unwrap(),
+            1
+        );
+        let state = RunState::default();
+        assert_eq!(check_join_loss("test", "test", 4, 1, &state), 0.25);
+        assert_eq!(check_join_loss("test", "test", 0, 0, &state), 0.0);
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{error::Result, ratio};
+
+/// Estimate indel sizes at unsupported intervals from reads with SUNKs on both sides.
+///
+/// For each read spanning an interval, the discrepancy is the read distance minus the contig distance between
+/// the closest flanking SUNKs. A consistent non-zero median suggests a size error in the assembly rather than a misjoin.
+/// Reads agree on the median if their read distance is within `dst_tolerance` of the contig distance plus the
+/// median.
+///
+/// # Arguments
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end]`
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ...]`. Only SUNKs on the assigned contig are used.
+/// * `dst_tolerance`
+///     * Allowed relative difference between a read's distance and the one expected from the median.
+///
+/// # Returns
+/// * `df_unsupported` with additional columns `[spanning_reads, indel_size]`
+///     * `indel_size` is the median discrepancy, the mean of the middle two rounded for an even number of reads.
+///       Positive values are insertions in reads relative to the assembly. Null if no reads span the interval or any
+///       disagree on the median.
+pub fn estimate_junction_indels(
+    df_unsupported: &DataFrame,
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    dst_tolerance: f32,
+) -> Result<DataFrame> {
+    // Junction intervals by contig with their row in df_unsupported.
+    let mut junctions: HashMap<&str, Vec<(i64, i64, usize)>> = HashMap::new();
+    for (row, ((ctg, st), end)) in df_unsupported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_unsupported.column("st")?.i64()?.iter())
+        .zip(df_unsupported.column("end")?.i64()?.iter())
+        .enumerate()
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            continue;
+        };
+        junctions.entry(ctg).or_default().push((st, end, row));
+    }
+    for ctg_junctions in junctions.values_mut() {
+        ctg_junctions.sort_unstable();
+    }
+
+    let df_anchors = df_read_sunks
+        .select(["read", "rpos", "ctg", "cpos"])?
+        .inner_join(
+            &df_best_reads_asm.select(["read", "ctg"])?,
+            ["read", "ctg"],
+            ["read", "ctg"],
+        )?
+        .lazy()
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
+        ])
+        .sort(["read", "cpos"], Default::default())
+        .collect()?;
+
+    // Read and contig distances of reads spanning each junction.
+    let mut distances: Vec<Vec<(i64, i64)>> = vec![vec![]; df_unsupported.height()];
+    let rows = df_anchors
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_anchors.column("ctg")?.str()?.iter())
+        .zip(df_anchors.column("rpos")?.i64()?.iter())
+        .zip(df_anchors.column("cpos")?.i64()?.iter())
+        .flat_map(|(((read, ctg), rpos), cpos)| Some((read?, ctg?, rpos?, cpos?)));
+    for ((_, ctg), anchors) in &rows.chunk_by(|(read, ctg, _, _)| (*read, *ctg)) {
+        let Some(ctg_junctions) = junctions.get(ctg) else {
+            continue;
+        };
+        let anchors: Vec<(i64, i64)> = anchors.map(|(_, _, rpos, cpos)| (cpos, rpos)).collect();
+        let (Some((min_cpos, _)), Some((max_cpos, _))) = (anchors.first(), anchors.last()) else {
+            continue;
+        };
+        // Only junctions within the read's SUNKs.
+        let first = ctg_junctions.partition_point(|(st, _, _)| st < min_cpos);
+        for (st, end, row) in ctg_junctions[first..]
+            .iter()
+            .take_while(|(_, end, _)| end <= max_cpos)
+        {
+            let left = anchors.partition_point(|(cpos, _)| cpos <= st);
+            let right = anchors.partition_point(|(cpos, _)| cpos < end);
+            let (Some(left), Some(right)) =
+                (left.checked_sub(1).map(|i| anchors[i]), anchors.get(right))
+            else {
+                continue;
+            };
+            let read_dst = (right.1 - left.1).abs();
+            let ctg_dst = right.0 - left.0;
+            distances[*row].push((read_dst, ctg_dst));
+        }
+    }
+
+    let tolerance_ppm = ratio::to_ppm(f64::from(dst_tolerance));
+    let (spanning_reads, indel_sizes): (Vec<u64>, Vec<Option<i64>>) = distances
+        .into_iter()
+        .map(|dsts| {
+            let n = dsts.len() as u64;
+            let indel_size = median_discrepancy(&dsts).filter(|indel_size| {
+                dsts.iter().all(|(read_dst, ctg_dst)| {
+                    ratio::within_tolerance(*read_dst, ctg_dst + indel_size, tolerance_ppm)
+                })
+            });
+            (n, indel_size)
+        })
+        .unzip();
+
+    let mut df = df_unsupported.clone();
+    df.with_column(Column::new("spanning_reads".into(), spanning_reads))?;
+    df.with_column(Column::new("indel_size".into(), indel_sizes))?;
+    Ok(df)
+}
+
+/// Median of read minus contig distances. The mean of the middle two, rounded, if there are an even number.
+///
+/// # Returns
+/// * `None` if there are no distances.
+fn median_discrepancy(dsts: &[(i64, i64)]) -> Option<i64> {
+    let discrepancies = dsts
+        .iter()
+        .map(|(read_dst, ctg_dst)| read_dst - ctg_dst)
+        .sorted_unstable()
+        .collect_vec();
+    // The same value if there are an odd number.
+    let lower = discrepancies.get(discrepancies.len().checked_sub(1)? / 2)?;
+    let upper = discrepancies[discrepancies.len() / 2];
+    Some(((lower + upper) as f64 / 2.0).round() as i64)
+}
+
+/// Supported component flanking a breakpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Flank {
+    st: i64,
+    end: i64,
+    component: u64,
+    reads: u64,
+}
+
+/// Majority orientation of reads assigned to each contig component.
+///
+/// # Returns
+/// * `+` or `-` by `(ctg, component)`. `.` if reads are split evenly.
+fn component_orientations(
+    df_components: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+) -> Result<HashMap<(String, u64), &'static str>> {
+    let df_orts = df_components
+        .select(["ctg", "read", "component"])?
+        .unique_stable(None, UniqueKeepStrategy::First, None)?
+        .inner_join(
+            &df_best_reads_asm.select(["read", "ctg", "ort"])?,
+            ["read", "ctg"],
+            ["read", "ctg"],
+        )?;
+    let mut counts: HashMap<(String, u64), (u64, u64)> = HashMap::new();
+    for ((ctg, component), ort) in df_orts
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_orts.column("component")?.u64()?.iter())
+        .zip(df_orts.column("ort")?.str()?.iter())
+    {
+        let (Some(ctg), Some(component), Some(ort)) = (ctg, component, ort) else {
+            continue;
+        };
+        let (fwd, rev) = counts.entry((ctg.to_owned(), component)).or_default();
+        if ort == "+" {
+            *fwd += 1;
+        } else {
+            *rev += 1;
+        }
+    }
+    Ok(counts
+        .into_iter()
+        .map(|(key, (fwd, rev))| {
+            let ort = match fwd.cmp(&rev) {
+                std::cmp::Ordering::Greater => "+",
+                std::cmp::Ordering::Less => "-",
+                std::cmp::Ordering::Equal => ".",
+            };
+            (key, ort)
+        })
+        .collect())
+}
+
+/// Link the supported components flanking each breakpoint as BEDPE for curation tools. ex. HiGlass
+///
+/// Breakpoints are unsupported intervals not at either contig end. The flanking components are the closest supported
+/// components ending before and starting after the breakpoint.
+///
+/// # Arguments
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end, spanning_reads, label, ...]`
+/// * `df_scales`
+///     * [`DataFrame`] of supported components with columns `[ctg, st, end, component, reads, ...]`.
+///       See [`crate::spacing::component_scale_stats`].
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in components with columns `[ctg, read, component, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ort, ...]`
+/// * `asm_lens`
+///     * Contig lengths.
+///
+/// # Returns
+/// * [`DataFrame`] with BEDPE columns `[ctg_a, st_a, end_a, ctg_b, st_b, end_b, name, score, strand_a, strand_b]`
+///   and `[component_a, component_b, reads_a, reads_b, spanning_reads, label]`.
+///     * `a` is the component before the breakpoint and `b` the one after. Breakpoints without both are skipped.
+///     * `name` is the breakpoint, `{ctg}:{st}-{end}`, and `score` the fewest reads of either component.
+///     * `strand_a` and `strand_b` are the majority orientation of each component's reads, `.` if split evenly.
+pub fn breakpoint_links(
+    df_unsupported: &DataFrame,
+    df_scales: &DataFrame,
+    df_components: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+) -> Result<DataFrame> {
+    let mut flanks: HashMap<&str, Vec<Flank>> = HashMap::new();
+    for ((((ctg, st), end), component), reads) in df_scales
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_scales.column("st")?.i64()?.iter())
+        .zip(df_scales.column("end")?.i64()?.iter())
+        .zip(df_scales.column("component")?.u64()?.iter())
+        .zip(df_scales.column("reads")?.u64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end), Some(component), Some(reads)) =
+            (ctg, st, end, component, reads)
+        else {
+            continue;
+        };
+        flanks.entry(ctg).or_default().push(Flank {
+            st,
+            end,
+            component,
+            reads,
+        });
+    }
+    let orts = component_orientations(df_components, df_best_reads_asm)?;
+    let ort = |ctg: &str, flank: &Flank| {
+        orts.get(&(ctg.to_owned(), flank.component))
+            .copied()
+            .unwrap_or(".")
+    };
+
+    let (mut ctgs, mut sts_a, mut ends_a, mut sts_b, mut ends_b) =
+        (vec![], vec![], vec![], vec![], vec![]);
+    let (mut names, mut scores, mut strands_a, mut strands_b) = (vec![], vec![], vec![], vec![]);
+    let (mut components_a, mut components_b, mut reads_a, mut reads_b) =
+        (vec![], vec![], vec![], vec![]);
+    let (mut spanning_reads, mut labels) = (vec![], vec![]);
+    for ((((ctg, st), end), n_spanning), label) in df_unsupported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_unsupported.column("st")?.i64()?.iter())
+        .zip(df_unsupported.column("end")?.i64()?.iter())
+        .zip(df_unsupported.column("spanning_reads")?.u64()?.iter())
+        .zip(df_unsupported.column("label")?.str()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            continue;
+        };
+        let len = asm_lens.get(ctg).copied().unwrap_or_default() as i64;
+        if st <= 0 || end >= len {
+            continue;
+        }
+        let ctg_flanks = flanks.get(ctg).map_or(&[][..], Vec::as_slice);
+        let before = ctg_flanks
+            .iter()
+            .filter(|flank| flank.end <= st)
+            .max_by_key(|flank| (flank.end, flank.reads));
+        let after = ctg_flanks
+            .iter()
+            .filter(|flank| flank.st >= end)
+            .min_by_key(|flank| (flank.st, std::cmp::Reverse(flank.reads)));
+        let (Some(before), Some(after)) = (before, after) else {
+            continue;
+        };
+        ctgs.push(ctg);
+        sts_a.push(before.st);
+        ends_a.push(before.end);
+        sts_b.push(after.st);
+        ends_b.push(after.end);
+        names.push(format!("{ctg}:{st}-{end}"));
+        scores.push(before.reads.min(after.reads));
+        strands_a.push(ort(ctg, before));
+        strands_b.push(ort(ctg, after));
+        components_a.push(before.component);
+        components_b.push(after.component);
+        reads_a.push(before.reads);
+        reads_b.push(after.reads);
+        spanning_reads.push(n_spanning);
+        labels.push(label);
+    }
+    Ok(DataFrame::new(vec![
+        Column::new("ctg_a".into(), ctgs.clone()),
+        Column::new("st_a".into(), sts_a),
+        Column::new("end_a".into(), ends_a),
+        Column::new("ctg_b".into(), ctgs),
+        Column::new("st_b".into(), sts_b),
+        Column::new("end_b".into(), ends_b),
+        Column::new("name".into(), names),
+        Column::new("score".into(), scores),
+        Column::new("strand_a".into(), strands_a),
+        Column::new("strand_b".into(), strands_b),
+        Column::new("component_a".into(), components_a),
+        Column::new("component_b".into(), components_b),
+        Column::new("reads_a".into(), reads_a),
+        Column::new("reads_b".into(), reads_b),
+        Column::new("spanning_reads".into(), spanning_reads),
+        Column::new("label".into(), labels),
+    ])?
+    .sort(["ctg_a", "st_a"], Default::default())?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{breakpoint_links, estimate_junction_indels, median_discrepancy};
+
+    #[test]
+    fn test_estimate_junction_indels() {
+        // r1 and r2 span 50 more bases than c1 across 1000-1100. No read spans 5000-5100.
+        let df_unsupported = df!(
+            "ctg" => ["c1", "c1"],
+            "st" => [1000i64, 5000],
+            "end" => [1100i64, 5100],
+        )
+        .unwrap();
+        let df_read_sunks = df!(
+            "read" => ["r1", "r1", "r1", "r1", "r2", "r2", "r2"],
+            "rpos" => [1i64, 401, 751, 1051, 11, 311, 661],
+            "ctg" => ["c1"; 7],
+            "cpos" => [500i64, 900, 1200, 1500, 600, 900, 1200],
+        )
+        .unwrap();
+        let df_best_reads_asm = df!("read" => ["r1", "r2"], "ctg" => ["c1", "c1"]).unwrap();
+        let df = estimate_junction_indels(&df_unsupported, &df_read_sunks, &df_best_reads_asm, 0.1)
+            .unwrap();
+        assert!(df
+            .select(["spanning_reads", "indel_size"])
+            .unwrap()
+            .equals_missing(
+                &df!(
+                    "spanning_reads" => [2u64, 0],
+                    "indel_size" => [Some(50i64), None],
+                )
+                .unwrap()
+            ));
+    }
+
+    #[test]
+    fn test_estimate_junction_indels_agreement() {
+        let df_unsupported = df!("ctg" => ["c1"], "st" => [1000i64], "end" => [1100i64]).unwrap();
+        // Each read has SUNKs at 900 and 1200 spanning 300 bases plus its discrepancy.
+        let estimate = |discrepancies: &[i64]| {
+            let reads = (0..discrepancies.len())
+                .map(|i| format!("r{i}"))
+                .collect::<Vec<_>>();
+            let df_read_sunks = df!(
+                "read" => reads.iter().flat_map(|read| [read.clone(), read.clone()]).collect::<Vec<_>>(),
+                "rpos" => discrepancies.iter().flat_map(|dst| [1i64, 301 + dst]).collect::<Vec<_>>(),
+                "ctg" => vec!["c1"; reads.len() * 2],
+                "cpos" => discrepancies.iter().flat_map(|_| [900i64, 1200]).collect::<Vec<_>>(),
+            )
+            .unwrap();
+            let df_best_reads_asm =
+                df!("read" => &reads, "ctg" => vec!["c1"; reads.len()]).unwrap();
+            let df =
+                estimate_junction_indels(&df_unsupported, &df_read_sunks, &df_best_reads_asm, 0.1)
+                    .unwrap();
+            df.column("indel_size").unwrap().i64().unwrap().get(0)
+        };
+        // Mean of the middle two.
+        assert_eq!(estimate(&[50, 60]), Some(55));
+        // 450 bases is more than 10% off the 350 expected from the median.
+        assert_eq!(estimate(&[50, 50, 150]), None);
+        assert_eq!(estimate(&[50, 55, 60]), Some(55));
+    }
+
+    #[test]
+    fn test_median_discrepancy() {
+        assert_eq!(median_discrepancy(&[]), None);
+        assert_eq!(median_discrepancy(&[(350, 300)]), Some(50));
+        assert_eq!(median_discrepancy(&[(0, 100), (400, 300)]), Some(0));
+        assert_eq!(median_discrepancy(&[(0, 100), (405, 300)]), Some(3));
+    }
+
+    #[test]
+    fn test_breakpoint_links() {
+        let df_unsupported = df!(
+            "ctg" => ["ctg1", "ctg1", "ctg1"],
+            "st" => [0i64, 400, 900],
+            "end" => [100i64, 500, 1000],
+            "spanning_reads" => [0u64, 1, 0],
+            "label" => ["no_reads", "inconsistent_sunks", "no_reads"],
+        )
+        .unwrap();
+        let df_scales = df!(
+            "ctg" => ["ctg1", "ctg1"],
+            "st" => [100i64, 500],
+            "end" => [400i64, 900],
+            "component" => [0u64, 1],
+            "reads" => [3u64, 2],
+        )
+        .unwrap();
+        let df_components = df!(
+            "ctg" => ["ctg1", "ctg1", "ctg1", "ctg1"],
+            "read" => ["r1", "r1", "r2", "r3"],
+            "component" => [0u64, 0, 0, 1],
+        )
+        .unwrap();
+        let df_best_reads_asm = df!(
+            "read" => ["r1", "r2", "r3"],
+            "ctg" => ["ctg1", "ctg1", "ctg1"],
+            "ort" => ["+", "+", "-"],
+        )
+        .unwrap();
+        let asm_lens = HashMap::from([("ctg1".to_owned(), 1_000)]);
+        let df = breakpoint_links(
+            &df_unsupported,
+            &df_scales,
+            &df_components,
+            &df_best_reads_asm,
+            &asm_lens,
+        )
+        .unwrap();
+        assert_eq!(
+            df.select(["st_a", "end_a", "st_b", "end_b"]).unwrap(),
+            df!(
+                "st_a" => [100i64],
+                "end_a" => [400i64],
+                "st_b" => [500i64],
+                "end_b" => [900i64],
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            df.select(["name", "strand_a", "strand_b", "label"])
+                .unwrap(),
+            df!(
+                "name" => ["ctg1:400-500"],
+                "strand_a" => ["+"],
+                "strand_b" => ["-"],
+                "label" => ["inconsistent_sunks"],
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            df.select(["score", "reads_a", "reads_b", "spanning_reads"])
+                .unwrap(),
+            df!(
+                "score" => [2u64],
+                "reads_a" => [3u64],
+                "reads_b" => [2u64],
+                "spanning_reads" => [1u64],
+            )
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Validate assembly contigs with SUNKs (singly unique nucleotide k-mers) found in ONT reads.
+//!
+//! # Features
+//! * `mapping` - Map SUNKs to reads, assign reads to contigs, and filter bad SUNKs.
+//! * `graph` - Build per-contig SUNK graphs and merge outputs. Requires `mapping`.
+//! * `plots` - Diagnostic SVG dot plots of read SUNK anchors and an HTML report. Requires `mapping`.
+//!
+//! With no features, only SUNK discovery and [`io`] are built.
+//!
+//! Run the pipeline from Rust with [`pipeline::Pipeline`] or call each stage's functions directly.
+
+#[cfg(feature = "graph")]
+pub mod anchor_store;
+#[cfg(feature = "graph")]
+pub mod archive;
+#[cfg(feature = "mapping")]
+pub mod assign_read_ctg;
+pub mod cancel;
+#[cfg(feature = "graph")]
+pub mod circular;
+#[cfg(feature = "graph")]
+pub mod contained;
+#[cfg(feature = "graph")]
+pub mod contig_ends;
+#[cfg(feature = "graph")]
+pub mod curation;
+#[cfg(feature = "graph")]
+pub mod diff;
+#[cfg(feature = "graph")]
+pub mod dropouts;
+pub mod error;
+#[cfg(feature = "graph")]
+pub mod explain;
+pub mod extract;
+pub mod filter_audit;
+#[cfg(feature = "mapping")]
+pub mod filter_bad_sunks;
+pub mod get_kmers;
+pub mod hooks;
+pub mod intervals;
+#[cfg(feature = "mapping")]
+pub mod inversions;
+pub mod joins;
+#[cfg(feature = "graph")]
+pub mod junctions;
+#[macro_use]
+pub mod io;
+pub mod lookup;
+pub mod manifest;
+#[cfg(feature = "mapping")]
+pub mod map_kmers;
+pub mod memory;
+#[cfg(feature = "graph")]
+pub mod merge;
+pub mod minhash;
+pub mod minimizers;
+pub mod naming;
+pub mod orientation;
+#[cfg(feature = "mapping")]
+pub mod paf_anchors;
+pub mod pairwise;
+pub mod parameters;
+#[cfg(feature = "graph")]
+pub mod pipeline;
+#[cfg(feature = "plots")]
+pub mod plots;
+#[cfg(feature = "graph")]
+pub mod polish;
+pub mod ratio;
+#[cfg(feature = "graph")]
+pub mod read_trims;
+#[cfg(feature = "graph")]
+pub mod ref_orientation;
+#[cfg(feature = "mapping")]
+pub mod remote_map;
+#[cfg(feature = "plots")]
+pub mod report;
+pub mod run_state;
+#[cfg(feature = "zstd")]
+pub mod seekable_zstd;
+#[cfg(feature = "graph")]
+pub mod self_test;
+#[cfg(feature = "graph")]
+pub mod sensitivity;
+#[cfg(feature = "mapping")]
+pub mod spacing;
+pub mod summary;
+pub mod sunk_context;
+#[cfg(feature = "graph")]
+pub mod sunk_graph;
+#[cfg(feature = "mapping")]
+pub mod sunk_index;
+#[cfg(feature = "graph")]
+pub mod sweep;
+pub mod threads;
+#[cfg(feature = "graph")]
+pub mod titration;
+#[cfg(feature = "mapping")]
+pub mod translocations;
+pub mod writers;
@@ -0,0 +1,207 @@
+//! Look up kmers in the SUNK index for interactive debugging.
+
+use polars::prelude::*;
+
+use crate::{error::Result, get_kmers::sunk_id};
+
+/// Reads listed per SUNK. Others are only counted.
+const MAX_READS_LISTED: usize = 20;
+
+/// Describe whether each kmer is a SUNK, where it is in the assembly, and which reads carry it.
+///
+/// # Arguments
+/// * `kmers`
+///     * Kmer sequences. Case is ignored.
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, kmer, strand, group, ...]`
+/// * `df_kmer_counts`
+///     * Optional [`DataFrame`] of repeated kmers with columns `[ctg, cpos, kmer, strand, count]`. See `--emit-kmer-counts`.
+/// * `df_read_sunks`
+///     * Optional [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, group, strand, ...]`.
+///     * Reads are matched by SUNK group as read hits are deduplicated by group.
+///
+/// # Returns
+/// * Human-readable report with one block per kmer.
+pub fn lookup_kmers(
+    kmers: &[String],
+    df_asm_sunks: &DataFrame,
+    df_kmer_counts: Option<&DataFrame>,
+    df_read_sunks: Option<&DataFrame>,
+) -> Result<String> {
+    let kmer_size = df_asm_sunks
+        .column("kmer")?
+        .str()?
+        .first()
+        .map(|kmer| kmer.len());
+    let mut report = vec![];
+    for kmer in kmers {
+        let kmer = kmer.to_ascii_uppercase();
+        if let Some(kmer_size) = kmer_size.filter(|kmer_size| *kmer_size != kmer.len()) {
+            report.push(format!(
+                "{kmer}: length {} differs from the SUNK kmer size, {kmer_size}.",
+                kmer.len()
+            ));
+            continue;
+        }
+        let df_hits = df_asm_sunks
+            .clone()
+            .lazy()
+            .filter(col("kmer").eq(lit(kmer.as_str())))
+            .select([
+                col("ctg"),
+                col("cpos").cast(DataType::Int64),
+                col("strand"),
+                col("group").cast(DataType::Int64),
+            ])
+            .collect()?;
+        let hit = df_hits
+            .column("ctg")?
+            .str()?
+            .iter()
+            .zip(df_hits.column("cpos")?.i64()?.iter())
+            .zip(df_hits.column("strand")?.str()?.iter())
+            .zip(df_hits.column("group")?.i64()?.iter())
+            .find_map(|(((ctg, cpos), strand), group)| Some((ctg?, cpos?, strand?, group?)));
+
+        let Some((ctg, cpos, strand, group)) = hit else {
+            report.push(format!(
+                "{kmer}: not a SUNK. {}",
+                repeat_count(&kmer, df_kmer_counts)?
+            ));
+            continue;
+        };
+        let id = sunk_id(ctg, group);
+        report.push(format!(
+            "{kmer}: SUNK at {ctg}:{cpos} ({strand}) in SUNK group {id}"
+        ));
+        let Some(df_read_sunks) = df_read_sunks else {
+            report.push("  No read_sunks.tsv from a previous run. Reads not checked.".to_owned());
+            continue;
+        };
+        let df_reads = df_read_sunks
+            .clone()
+            .lazy()
+            .filter(
+                col("ctg")
+                    .eq(lit(ctg))
+                    .and(col("group").cast(DataType::Int64).eq(lit(group))),
+            )
+            .select([
+                col("read"),
+                col("rpos").cast(DataType::Int64),
+                col("strand"),
+            ])
+            .sort(["read", "rpos"], Default::default())
+            .collect()?;
+        report.push(format!("  {} read hits to {id}", df_reads.height()));
+        for ((read, rpos), strand) in df_reads
+            .column("read")?
+            .str()?
+            .iter()
+            .zip(df_reads.column("rpos")?.i64()?.iter())
+            .zip(df_reads.column("strand")?.str()?.iter())
+            .take(MAX_READS_LISTED)
+        {
+            let (Some(read), Some(rpos), Some(strand)) = (read, rpos, strand) else {
+                continue;
+            };
+            report.push(format!("  {read}:{rpos} ({strand})"));
+        }
+        if df_reads.height() > MAX_READS_LISTED {
+            report.push(format!(
+                "  ... and {} more",
+                df_reads.height() - MAX_READS_LISTED
+            ));
+        }
+    }
+    Ok(report.join("\n"))
+}
+
+/// Describe the assembly count of a kmer that isn't a SUNK.
+fn repeat_count(kmer: &str, df_kmer_counts: Option<&DataFrame>) -> Result<String> {
+    let Some(df_kmer_counts) = df_kmer_counts else {
+        return Ok("Rerun with --emit-kmer-counts to check if it is repeated.".to_owned());
+    };
+    let df_kmer = df_kmer_counts
+        .clone()
+        .lazy()
+        .filter(col("kmer").eq(lit(kmer)))
+        .select([
+            col("ctg"),
+            col("cpos").cast(DataType::Int64),
+            col("count").cast(DataType::Int64),
+        ])
+        .collect()?;
+    let first = df_kmer
+        .column("ctg")?
+        .str()?
+        .first()
+        .zip(df_kmer.column("cpos")?.i64()?.first())
+        .zip(df_kmer.column("count")?.i64()?.first());
+    Ok(match first {
+        Some(((ctg, cpos), count)) => {
+            format!("Occurs {count} times in the assembly. First at {ctg}:{cpos}.")
+        }
+        None => "Not in the assembly or below the --emit-kmer-counts minimum count.".to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::lookup_kmers;
+
+    #[test]
+    fn test_lookup_kmers() {
+        let df_asm_sunks = df!(
+            "ctg" => ["c1"],
+            "cpos" => [100i64],
+            "kmer" => ["ACGTA"],
+            "strand" => ["+"],
+            "group" => [100i64],
+        )
+        .unwrap();
+        let df_kmer_counts = df!(
+            "ctg" => ["c2"],
+            "cpos" => [5i64],
+            "kmer" => ["GGGGG"],
+            "strand" => ["+"],
+            "count" => [3i64],
+        )
+        .unwrap();
+        let df_read_sunks = df!(
+            "read" => ["r2", "r1", "r3"],
+            "rpos" => [20i64, 10, 30],
+            "ctg" => ["c1", "c1", "c1"],
+            "cpos" => [100i64, 100, 500],
+            "group" => [100i64, 100, 500],
+            "strand" => ["-", "+", "+"],
+        )
+        .unwrap();
+        let kmers = ["acgta", "GGGGG", "ACG"].map(str::to_owned);
+        let report = lookup_kmers(
+            &kmers,
+            &df_asm_sunks,
+            Some(&df_kmer_counts),
+            Some(&df_read_sunks),
+        )
+        .unwrap();
+        assert_eq!(
+            report.lines().collect::<Vec<&str>>(),
+            [
+                "ACGTA: SUNK at c1:100 (+) in SUNK group c1:100",
+                "  2 read hits to c1:100",
+                "  r1:10 (+)",
+                "  r2:20 (-)",
+                "GGGGG: not a SUNK. Occurs 3 times in the assembly. First at c2:5.",
+                "ACG: length 3 differs from the SUNK kmer size, 5.",
+            ]
+        );
+
+        // Without counts or read SUNKs from a previous run.
+        let report = lookup_kmers(&kmers[..2], &df_asm_sunks, None, None).unwrap();
+        assert!(report.contains("No read_sunks.tsv from a previous run."));
+        assert!(report.contains("Rerun with --emit-kmer-counts"));
+    }
+}
@@ -1,91 +1,2104 @@
-use std::path::Path;
-
-use assign_read_ctg::assign_read_to_ctg_w_ort;
-use filter_bad_sunks::filter_bad_sunks;
-use get_kmers::get_sunk_positions;
-use io::{load_tsv, write_tsv, Fasta};
-use map_kmers::{get_good_read_sunks, map_sunks_to_reads};
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use sunk_graph::create_sunk_graph;
-
-mod assign_read_ctg;
-mod get_kmers;
-#[macro_use]
-mod io;
-mod filter_bad_sunks;
-mod map_kmers;
-mod sunk_graph;
+#[cfg(feature = "mapping")]
+use std::collections::HashMap;
+#[cfg(feature = "mapping")]
+use std::sync::Arc;
+#[cfg(feature = "graph")]
+use std::{collections::HashSet, ffi::OsStr, net::TcpListener, sync::Mutex, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use clap::Parser;
+use cli::Cli;
+#[cfg(feature = "graph")]
+use cli::{
+    BadSunksArgs, Command, DiffArgs, ExplainReadArgs, ExplainRegionArgs, ExtractUnsupportedArgs,
+    LookupArgs, SelfTestArgs, SweepArgs, TitrateArgs,
+};
+#[cfg(feature = "mapping")]
+use polars::prelude::*;
+#[cfg(feature = "plots")]
+use rs_gavisunk::plots::{histogram_svg, write_read_dot_plots};
+#[cfg(all(feature = "graph", feature = "plots"))]
+use rs_gavisunk::report::write_report;
+#[cfg(feature = "graph")]
+use rs_gavisunk::{
+    anchor_store::AnchorStore,
+    archive::write_archive,
+    assign_read_ctg::DEFAULT_BANDWIDTH,
+    cancel::Cancelled,
+    circular::{
+        load_gfa_circular, origin_bed, origin_spanning_reads, origin_support, split_origin,
+        MIN_ORIGIN_READS,
+    },
+    contained::{contained_bed, contained_reads, contained_support, MIN_CONTAINED_READS},
+    contig_ends::{contig_end_extensions, EXTENDABLE},
+    curation::curation_targets,
+    diff::{diff_intervals, diff_read_assignments, RunIntervals},
+    dropouts::label_dropouts,
+    explain::{explain_read, explain_region},
+    extract::write_flanked_fasta,
+    filter_bad_sunks::sunk_count_histogram,
+    intervals::{load_bed, load_bedgraph, merge, CtgIntervals},
+    io::load_tsv,
+    junctions::{breakpoint_links, estimate_junction_indels},
+    lookup::lookup_kmers,
+    merge::{concat_dfs, contig_summary, get_unsupported_intervals, read_intervals, sunk_support},
+    naming::{normalize_name, ContigFilenames},
+    polish::polish_targets,
+    read_trims::read_trim_suggestions,
+    ref_orientation::{load_paf_strands, ContigOrientations, MIN_REVERSE_FRACTION},
+    remote_map::serve_map,
+    self_test::{check_outputs, write_inputs},
+    sensitivity::{
+        contig_coverage, contig_false_support, good_sunk_positions, region_sensitivity,
+        ReadLengthDist,
+    },
+    summary::{load_read_groups, read_group_stats, read_length_stats},
+    sunk_context::count_context_sunks,
+    sunk_graph::{
+        create_sunk_graph_with_store, read_status, rescue_single_sunk_reads, supported_bp,
+        GraphParams, MIN_READ_LEN,
+    },
+    sweep::{sweep, sweep_grid},
+    threads::for_each_bounded,
+    titration::{titrate, TitrationParams},
+};
+#[cfg(feature = "mapping")]
+use rs_gavisunk::{
+    assign_read_ctg::assign_read_to_ctg_w_ort,
+    filter_bad_sunks::{filter_bad_sunks, load_extra_bad_sunks, merge_extra_bad_sunks},
+    inversions::detect_inversions,
+    map_kmers::{
+        get_good_read_sunks, map_sunks_to_read_records, map_sunks_to_reads, read_lengths_df,
+        validate_read_hits, Prebin,
+    },
+    minhash::{sketch_reads_vs_asm, MIN_READ_CONTAINMENT},
+    minimizers::{ContigSketches, DEFAULT_SKETCH_KMER_SIZE, DEFAULT_SKETCH_WINDOW},
+    naming::warn_unmatched_contigs,
+    paf_anchors::read_sunks_from_paf,
+    remote_map::map_sunks_to_reads_remote,
+    spacing::{component_scale_stats, contig_spacing_stats, COLLAPSED, EXPANDED},
+    summary::estimate_coverage,
+    sunk_context::covariate_strata,
+    sunk_index::{SunkIndex, MAX_INDEX_KMER_SIZE},
+    translocations::{detect_phase_switches, detect_translocations, load_haplotypes},
+};
+use rs_gavisunk::{
+    cancel::CancelToken,
+    error::GavisunkError,
+    filter_audit::filter_audit,
+    get_kmers::{get_sunk_positions, get_sunk_positions_partitioned},
+    hooks::{LogObserver, Observers, Stage},
+    io::{
+        self, load_tsv_with_schema, write_atomic, Fasta, IntermediateParams, ASM_SUNKS_COLUMNS,
+        READ_CTG_MAPPING_COLUMNS, READ_SUNKS_BAD_COLUMNS, READ_SUNKS_COLUMNS,
+        READ_SUNKS_GOOD_COLUMNS,
+    },
+    load_or_redo_df,
+    memory::{KmerCounting, StagePlan},
+    naming::ContigRenames,
+    parameters::{Parameters, RunMetadata},
+    run_state::RunState,
+    summary::{RunStatus, RunSummary},
+    sunk_context::{annotate_sunk_context, sequence_context, sunk_context_stats},
+    writers::{OutputWriters, WriterRegistry},
+};
+
+mod cli;
 
 fn main() -> eyre::Result<()> {
+    let mut cli = Cli::parse();
+    cli.resolve_inputs(&std::env::current_dir()?);
     simple_logger::SimpleLogger::new()
         .with_level(log::LevelFilter::Info)
         .init()?;
+    let state = RunState::new()
+        .with_max_join_loss(cli.max_join_loss)
+        .with_max_malformed_records(cli.max_malformed_records)
+        .with_empty_intermediate(cli.on_empty_intermediate)
+        .with_copy_dir(cli.outdir.clone());
+    let writers = OutputWriters::new(WriterRegistry::default(), &cli.output_formats)?;
+
+    #[cfg(feature = "graph")]
+    if let Some(Command::Diff(args)) = &cli.command {
+        return diff_runs(&cli, args, &writers);
+    }
+    // Runs until stopped, so before handling Ctrl-C.
+    #[cfg(feature = "graph")]
+    if let Some(Command::ServeMap(args)) = &cli.command {
+        return Ok(serve_map(
+            TcpListener::bind(&args.listen)?,
+            &args.reads,
+            args.sunk_index.as_deref(),
+            &state,
+        )?);
+    }
+
+    let mut observers = Observers::default();
+    observers.register(LogObserver);
+
+    // First Ctrl-C stops scheduling new contigs and lets in-flight ones finish.
+    // Second Ctrl-C aborts in-flight contigs.
+    let interrupt = CancelToken::new();
+    let abort = CancelToken::new();
+    {
+        let (interrupt, abort) = (interrupt.clone(), abort.clone());
+        ctrlc::set_handler(move || {
+            if interrupt.is_cancelled() {
+                log::warn!("Interrupted again. Aborting in-flight contigs.");
+                abort.cancel();
+            } else {
+                log::warn!(
+                    "Interrupted. Finishing in-flight contigs. Press Ctrl-C again to abort."
+                );
+                interrupt.cancel();
+            }
+        })?;
+    }
+
+    #[cfg(feature = "graph")]
+    if let Some(Command::SelfTest(args)) = &cli.command {
+        return self_test(args, &observers, &interrupt, &abort);
+    }
+
+    // Outputs, cached intermediates, and relative paths of subcommands are within the output directory.
+    std::fs::create_dir_all(&cli.outdir)?;
+    let mut summary = RunSummary::default();
+    let started = SystemTime::now();
+    // Runs stopped by a failed contig still summarize the contigs completed before returning the error.
+    let failed = match run(
+        &cli,
+        &observers,
+        &interrupt,
+        &abort,
+        &state,
+        &writers,
+        &mut summary,
+    ) {
+        Err(err) if is_cancelled(&err) => None,
+        Err(err) if summary.status == RunStatus::Partial => Some(err),
+        Err(err) => return Err(err),
+        Ok(()) => None,
+    };
+    if interrupt.is_cancelled() {
+        summary.status = RunStatus::Partial;
+    }
+    summary.malformed_records = io::malformed_records(&state);
+    // Subcommands like sweep rerun filters, so only counts of pipeline and stage runs are a funnel.
+    let mut df_audit = filter_audit(&state)?;
+    if is_pipeline_run(&cli) && df_audit.height() > 0 {
+        writers.write(
+            &mut df_audit,
+            cli.out("filter_audit.tsv"),
+            &summary.comment_header,
+        )?;
+    }
+    summary.write(cli.out("summary.json"))?;
+    write_manifest(&cli, &state, &summary, started)?;
+    if let Some(err) = failed {
+        log::error!("Run failed. Outputs are partial.");
+        return Err(err);
+    }
 
-    let kmer_size = 20;
-    let asm_fh = Fasta::new("test/input/all.fa")?;
-    let asm_lens = asm_fh.lengths();
+    if summary.status == RunStatus::Partial {
+        log::warn!("Run cancelled. Outputs are partial.");
+    } else {
+        log::info!("Done.");
+    }
+    Ok(())
+}
+
+/// Compare the supported intervals and read assignments of two runs.
+#[cfg(feature = "graph")]
+fn diff_runs(cli: &Cli, args: &DiffArgs, writers: &OutputWriters) -> eyre::Result<()> {
+    std::fs::create_dir_all(&cli.outdir)?;
+    let header = format!(
+        "# rs-gavisunk version={:?} run_a={:?} run_b={:?} slop={}\n",
+        env!("CARGO_PKG_VERSION"),
+        args.run_a,
+        args.run_b,
+        args.slop
+    );
+    let run_a = RunIntervals::load(&args.run_a)?;
+    let run_b = RunIntervals::load(&args.run_b)?;
+    let mut df_intervals = diff_intervals(&run_a, &run_b, args.slop)?;
+    let changed_bp: i64 = (df_intervals.column("end")?.i64()?
+        - df_intervals.column("st")?.i64()?)
+    .sum()
+    .unwrap_or_default();
+    log::info!(
+        "{} regions ({changed_bp} bp) changed status.",
+        df_intervals.height()
+    );
+    writers.write(
+        &mut df_intervals,
+        cli.out(format!("{}_intervals.tsv", args.prefix)),
+        &header,
+    )?;
+
+    let path_reads = "read_ctg_mapping.tsv";
+    let mut df_reads = diff_read_assignments(
+        &load_tsv_with_schema(args.run_a.join(path_reads), READ_CTG_MAPPING_COLUMNS, &[])?,
+        &load_tsv_with_schema(args.run_b.join(path_reads), READ_CTG_MAPPING_COLUMNS, &[])?,
+    )?;
+    log::info!("{} reads changed assignment.", df_reads.height());
+    writers.write(
+        &mut df_reads,
+        cli.out(format!("{}_reads.tsv", args.prefix)),
+        &header,
+    )?;
+    Ok(())
+}
+
+/// Run the pipeline on the embedded dataset and check its outputs against expectations.
+#[cfg(feature = "graph")]
+fn self_test(
+    args: &SelfTestArgs,
+    observers: &Observers,
+    interrupt: &CancelToken,
+    abort: &CancelToken,
+) -> eyre::Result<()> {
+    let dir = args.dir.clone().unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("rs_gavisunk_self_test_{}", std::process::id()))
+    });
+    std::fs::create_dir_all(&dir)?;
+    let dir = dir.canonicalize()?;
+    log::info!("Running self-test in {dir:?}.");
+    let (path_asm, path_reads) = (dir.join("input/all.fa"), dir.join("input/all_ONT.fa"));
+    write_inputs(&path_asm, &path_reads)?;
+    // Expectations only hold for default parameters, so other options are ignored.
+    let test_cli = Cli::parse_from([
+        OsStr::new(env!("CARGO_BIN_NAME")),
+        OsStr::new("--assembly"),
+        path_asm.as_os_str(),
+        OsStr::new("--reads"),
+        path_reads.as_os_str(),
+        OsStr::new("--outdir"),
+        dir.as_os_str(),
+    ]);
+    let mut summary = RunSummary::default();
+    let result = run(
+        &test_cli,
+        observers,
+        interrupt,
+        abort,
+        &RunState::new(),
+        &OutputWriters::default(),
+        &mut summary,
+    )
+    .and_then(|_| Ok(check_outputs(&dir)?));
+
+    let (checked, failures) = result?;
+    if !failures.is_empty() {
+        for failure in &failures {
+            log::error!("{failure}");
+        }
+        log::error!("Outputs kept in {dir:?}.");
+        return Err(GavisunkError::SelfTestFailed {
+            failed: failures.len(),
+            checked,
+        }
+        .into());
+    }
+    log::info!("Self-test passed {checked} checks.");
+    if args.dir.is_none() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+/// Workers to map reads on with `submit-map`, if any.
+#[cfg(feature = "mapping")]
+fn map_workers(cli: &Cli) -> Option<&[String]> {
+    #[cfg(feature = "graph")]
+    if let Some(Command::SubmitMap(args)) = &cli.command {
+        return Some(&args.workers);
+    }
+    #[cfg(not(feature = "graph"))]
+    let _ = cli;
+    None
+}
+
+/// Write `manifest.json` describing the files produced by this run, if requested.
+///
+/// Outputs are listed if written since `started` and intermediates if loaded or written by the run, so files left in
+/// the output directory by earlier runs aren't listed.
+fn write_manifest(
+    cli: &Cli,
+    state: &RunState,
+    summary: &RunSummary,
+    started: SystemTime,
+) -> eyre::Result<()> {
+    if !cli.manifest {
+        return Ok(());
+    }
+    let intermediates = io::intermediates(state);
+    let mut files: Vec<(PathBuf, &str)> = vec![];
+    for (path, role) in OUTPUTS {
+        if role != "intermediate" {
+            files.push((PathBuf::from(path), role));
+        } else if intermediates.contains(&cli.out(path)) {
+            files.push((PathBuf::from(path), role));
+            files.push((
+                PathBuf::from(format!("{path}.params.json")),
+                "intermediate_params",
+            ));
+        }
+    }
+    #[cfg(feature = "graph")]
+    {
+        if let Some(path_archive) = &cli.archive {
+            files.push((path_archive.clone(), "per_contig_archive"));
+        }
+        let path_fnames = cli.out("contig_filenames.tsv");
+        if cli.per_contig && path_fnames.exists() {
+            let df_fnames = load_tsv(&path_fnames)?;
+            for fname in df_fnames
+                .column("filename")?
+                .cast(&DataType::String)?
+                .str()?
+                .into_iter()
+                .flatten()
+            {
+                files.push((PathBuf::from(format!("{fname}_sunks.tsv")), "per_contig"));
+                files.push((PathBuf::from(format!("{fname}.bed")), "per_contig"));
+            }
+        }
+    }
+    #[cfg(feature = "plots")]
+    if let Ok(entries) = std::fs::read_dir(cli.out("dot_plots")) {
+        let mut plots: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| Path::new("dot_plots").join(entry.file_name()))
+            .collect();
+        plots.sort();
+        files.extend(plots.into_iter().map(|path| (path, "dot_plot")));
+    }
+    #[cfg(all(feature = "graph", feature = "plots"))]
+    if let Ok(entries) = std::fs::read_dir(cli.out("report")) {
+        let mut pages: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| Path::new("report").join(entry.file_name()))
+            .collect();
+        pages.sort();
+        files.extend(pages.into_iter().map(|path| (path, "report")));
+    }
+    files.retain(|(path, role)| {
+        role.starts_with("intermediate")
+            || std::fs::metadata(cli.out(path))
+                .and_then(|meta| meta.modified())
+                .is_ok_and(|modified| modified >= started)
+    });
+    let manifest = Manifest::new(&cli.outdir, summary, files)?;
+    manifest.write(cli.out("manifest.json"))?;
+    log::info!("Wrote manifest of {} files.", manifest.files.len());
+    Ok(())
+}
+
+/// Parameters of the run from the command line.
+fn run_parameters(cli: &Cli, params: &IntermediateParams) -> Parameters {
+    #[cfg(feature = "mapping")]
+    use clap::ValueEnum;
+    #[cfg_attr(not(feature = "mapping"), allow(unused_mut))]
+    let mut parameters = Parameters {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        kmer_size: params.kmer_size,
+        assembly: params.assembly.clone(),
+        reads: params.reads.clone(),
+        rename_map: params.rename_map.clone(),
+        gap_flank: cli.gap_flank,
+        max_join_loss: cli.max_join_loss,
+        sunk_dedup: None,
+        prebin: None,
+        bandwidth: None,
+        min_mode_count: None,
+        extra_bad_sunks: None,
+        spacing_tolerance: None,
+        min_inversion_sunks: None,
+        min_translocation_sunks: None,
+        min_translocation_reads: None,
+        min_phase_switch_sunks: None,
+        breakpoint_slop: None,
+        haplotypes: None,
+        multi_sunk: None,
+        dst_tolerance: None,
+        min_component_sunks: None,
+        max_read_sunks: None,
+        read_timeout: None,
+        min_depth: None,
+        rescue_single_sunk: None,
+        min_event_size: None,
+        min_trim_sunks: None,
+        exclude_regions: None,
+    };
+    #[cfg(feature = "mapping")]
+    {
+        parameters.sunk_dedup = cli
+            .sunk_dedup
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned());
+        parameters.prebin = cli.prebin;
+        parameters.bandwidth = Some(rs_gavisunk::assign_read_ctg::DEFAULT_BANDWIDTH);
+        parameters.min_mode_count = Some(rs_gavisunk::filter_bad_sunks::MIN_MODE_COUNT);
+        parameters.extra_bad_sunks = cli.extra_bad_sunks.clone();
+        parameters.spacing_tolerance = Some(cli.spacing_tolerance);
+        parameters.min_inversion_sunks = Some(cli.min_inversion_sunks);
+        parameters.min_translocation_sunks = Some(cli.min_translocation_sunks);
+        parameters.min_translocation_reads = Some(cli.min_translocation_reads);
+        parameters.min_phase_switch_sunks = Some(cli.min_phase_switch_sunks);
+        parameters.breakpoint_slop = Some(cli.breakpoint_slop);
+        parameters.haplotypes = cli.haplotypes.clone();
+    }
+    #[cfg(feature = "graph")]
+    {
+        parameters.multi_sunk = cli
+            .multi_sunk
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned());
+        parameters.dst_tolerance = Some(cli.dst_tolerance);
+        parameters.min_component_sunks = Some(cli.min_component_sunks);
+        parameters.max_read_sunks = Some(cli.max_read_sunks);
+        parameters.read_timeout = Some(cli.read_timeout);
+        parameters.min_depth = Some(cli.min_depth);
+        parameters.rescue_single_sunk = Some(cli.rescue_single_sunk);
+        parameters.min_event_size = Some(cli.min_event_size);
+        parameters.min_trim_sunks = Some(cli.min_trim_sunks);
+        parameters.exclude_regions = cli.exclude_regions.clone();
+    }
+    parameters
+}
+
+/// Whether to exit after `stage` as requested with `--stop-after`.
+fn stops_after(cli: &Cli, stage: Stage) -> bool {
+    let stop = cli.stop_after == Some(stage) || stage_command(cli) == Some(stage);
+    if stop {
+        log::info!("Stopping after the {stage} stage.");
+    }
+    stop
+}
+
+/// Stage run alone by a stage subcommand, ex. `map`.
+fn stage_command(cli: &Cli) -> Option<Stage> {
+    #[cfg(feature = "graph")]
+    if let Some(command) = &cli.command {
+        return command.stage();
+    }
+    #[cfg(not(feature = "graph"))]
+    let _ = cli;
+    None
+}
+
+/// Whether the run is the pipeline or one of its stages, not a subcommand like `sweep`.
+fn is_pipeline_run(cli: &Cli) -> bool {
+    #[cfg(feature = "graph")]
+    if let Some(command) = &cli.command {
+        return command.stage().is_some() || matches!(command, Command::SubmitMap(_));
+    }
+    #[cfg(not(feature = "graph"))]
+    let _ = cli;
+    true
+}
+
+/// Whether a stage may recompute its intermediates. Stage subcommands load those of other stages.
+fn runs_stage(cli: &Cli, stage: Stage) -> bool {
+    stage_command(cli).is_none_or(|command| command == stage)
+}
+
+/// Whether to recompute a stage's intermediates with `--force`.
+fn redo_stage(cli: &Cli, stage: Stage) -> bool {
+    cli.force && runs_stage(cli, stage)
+}
+
+/// Check that the intermediates of stages before a stage subcommand's stage exist so they're loaded, not recomputed.
+fn check_stage_inputs(cli: &Cli) -> Result<(), GavisunkError> {
+    let Some(command) = stage_command(cli) else {
+        return Ok(());
+    };
+    for stage in [Stage::Sunks, Stage::Map, Stage::Assign, Stage::Filter] {
+        if stage >= command {
+            break;
+        }
+        for path in stage.intermediates() {
+            let path = cli.out(path);
+            if !path.exists() {
+                return Err(GavisunkError::MissingStageOutput {
+                    path,
+                    stage: stage.to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the run stopped because it was cancelled.
+fn is_cancelled(err: &eyre::Report) -> bool {
+    err.downcast_ref::<GavisunkError>()
+        .is_some_and(GavisunkError::is_cancelled)
+}
+
+fn run(
+    cli: &Cli,
+    observers: &Observers,
+    interrupt: &CancelToken,
+    abort: &CancelToken,
+    state: &RunState,
+    writers: &OutputWriters,
+    summary: &mut RunSummary,
+) -> eyre::Result<()> {
+    check_stage_inputs(cli)?;
+    let kmer_size = cli.kmer_size;
+    let (path_asm, path_reads) = cli.inputs();
+    let params = IntermediateParams {
+        kmer_size,
+        assembly: path_asm.to_owned(),
+        reads: path_reads.to_owned(),
+        rename_map: cli.rename_map.clone(),
+        rename_map_crc32: cli.rename_map.as_ref().map(io::file_crc32).transpose()?,
+        #[cfg(feature = "mapping")]
+        anchor_paf: cli.anchor_paf.clone(),
+        #[cfg(not(feature = "mapping"))]
+        anchor_paf: None,
+        flags: Default::default(),
+    };
+    let parameters = run_parameters(cli, &params);
+    log::info!("Parameters: {parameters}");
+    let header = if cli.comment_headers {
+        let mut inputs = vec![("assembly", path_asm), ("reads", path_reads)];
+        if let Some(rename_map) = &cli.rename_map {
+            inputs.push(("rename_map", rename_map.as_path()));
+        }
+        log::info!("Checksumming inputs for output comment headers.");
+        RunMetadata::new(parameters.clone(), &inputs)?.comment_header()
+    } else {
+        parameters.comment_header()
+    };
+    summary.comment_header.clone_from(&header);
+    summary.parameters = Some(parameters);
+    let renames = cli
+        .rename_map
+        .as_ref()
+        .map(ContigRenames::load)
+        .transpose()?
+        .unwrap_or_default();
+    let asm_fh = Fasta::open(path_asm, cli.max_memory, cli.allow_recompress, state)?;
+    // Sequences are fetched by FASTA name. All later stages use renamed contigs.
+    let fa_lens = asm_fh.lengths();
+    let asm_lens = renames.rename_keys(fa_lens.clone())?;
+    #[cfg(feature = "graph")]
+    if let Some(Command::ExtractUnsupported(args)) = &cli.command {
+        return extract_unsupported(cli, args, asm_fh, &fa_lens, &renames);
+    }
     log::info!(
         "Reading {} contigs from {:?}.",
         asm_lens.len(),
         asm_fh.fname
     );
 
-    let ont_fh = Fasta::new("test/input/all_ONT.fa")?;
-    let ont_lens = ont_fh.lengths();
-    log::info!("Reading {} reads from {:?}.", ont_lens.len(), ont_fh.fname);
+    let plan = StagePlan::new(cli.max_memory, &asm_lens, rayon::current_num_threads());
+    log::info!("Stage plan: {plan}");
 
-    log::info!("Getting SUNK positions in assembly.");
-    let path_sunks_asm = Path::new("asm_sunks.tsv");
+    observers.stage_start(Stage::Sunks);
+    let path_sunks_asm = &cli.out("asm_sunks.tsv");
+    let path_kmer_cnts = &cli.out("kmer_counts.tsv");
+    // Kmer counts are only available while counting SUNKs.
+    let redo_sunks = runs_stage(cli, Stage::Sunks)
+        && (cli.force || (cli.emit_kmer_counts.is_some() && !path_kmer_cnts.exists()));
+    let ctx_fh = asm_fh.try_clone()?;
     let df_asm_sunks = load_or_redo_df!(
         path_sunks_asm,
-        get_sunk_positions(asm_fh, &asm_lens, kmer_size)?
+        params,
+        state,
+        {
+            let (df_sunks, df_kmer_cnts) = match plan.kmer_counting {
+                KmerCounting::InMemory => get_sunk_positions(
+                    asm_fh,
+                    &fa_lens,
+                    kmer_size,
+                    cli.emit_kmer_counts,
+                    interrupt,
+                )?,
+                KmerCounting::Partitioned(n_buckets) => get_sunk_positions_partitioned(
+                    asm_fh,
+                    &fa_lens,
+                    kmer_size,
+                    cli.emit_kmer_counts,
+                    n_buckets,
+                    &cli.outdir,
+                    interrupt,
+                )?,
+            };
+            if let Some(df_kmer_cnts) = df_kmer_cnts {
+                writers.write(
+                    &mut renames.restore(&renames.rename(&df_kmer_cnts)?)?,
+                    path_kmer_cnts,
+                    &header,
+                )?;
+            }
+            renames.rename(&df_sunks)?
+        },
+        redo_sunks,
+        ASM_SUNKS_COLUMNS,
+        &[]
+    );
+    // Flag SUNKs in softmasked or gap-adjacent sequence and annotate their composition to help interpret unstable
+    // support.
+    let mut sunk_starts: HashMap<String, Vec<i64>> = HashMap::new();
+    let sunk_cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    for (ctg, cpos) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(sunk_cpos.i64()?.iter())
+    {
+        if let (Some(ctg), Some(cpos)) = (ctg, cpos) {
+            sunk_starts
+                .entry(renames.restore_name(ctg).to_owned())
+                .or_default()
+                .push(cpos - 1);
+        }
+    }
+    let seq_context = renames.rename_keys(sequence_context(
+        &ctx_fh,
+        &fa_lens,
+        &sunk_starts,
+        kmer_size,
+        interrupt,
+    )?)?;
+    drop(sunk_starts);
+    let df_asm_sunks =
+        annotate_sunk_context(&df_asm_sunks, &seq_context, kmer_size, cli.gap_flank)?;
+    let sunk_context = sunk_context_stats(&df_asm_sunks, None)?;
+    log::info!(
+        "{} of {} SUNK groups are softmasked and {} are within {} bp of a gap.",
+        sunk_context.softmasked,
+        sunk_context.groups,
+        sunk_context.near_gap,
+        cli.gap_flank
     );
+    summary.sunk_context = Some(sunk_context);
+    observers.frame(Stage::Sunks, "asm_sunks", &df_asm_sunks);
+    observers.stage_end(Stage::Sunks);
+    summary.stages_completed.push(Stage::Sunks.to_string());
+    if stops_after(cli, Stage::Sunks) {
+        return Ok(());
+    }
+
+    #[cfg(feature = "graph")]
+    if let Some(Command::Lookup(args)) = &cli.command {
+        return run_lookup(cli, args, &renames, &df_asm_sunks);
+    }
+    #[cfg(feature = "graph")]
+    if let Some(Command::ExplainRead(args)) = &cli.command {
+        return run_explain_read(cli, args, path_reads, &asm_lens, &df_asm_sunks, state);
+    }
+
+    #[cfg(feature = "mapping")]
+    {
+        let ctx = RunContext {
+            cli,
+            observers,
+            interrupt,
+            abort,
+            params: &params,
+            renames: &renames,
+            header: &header,
+            state,
+            writers,
+        };
+        let Some(mapped) = map_reads(&ctx, summary, path_reads, &asm_lens, &plan, &df_asm_sunks)?
+        else {
+            return Ok(());
+        };
+        #[cfg(feature = "graph")]
+        if let Some(Command::Sweep(args)) = &cli.command {
+            return sweep_params(&ctx, args, &asm_lens, mapped);
+        }
+        #[cfg(feature = "graph")]
+        if let Some(Command::ExplainRegion(args)) = &cli.command {
+            return run_explain_region(&ctx, args, &asm_lens, &df_asm_sunks, &mapped);
+        }
+        #[cfg(feature = "graph")]
+        if let Some(Command::Titrate(args)) = &cli.command {
+            return run_titration(&ctx, args, &asm_lens, &mapped);
+        }
+        #[cfg(feature = "graph")]
+        if let Some(Command::BadSunks(args)) = &cli.command {
+            return run_bad_sunks(&ctx, args, &mapped);
+        }
+        #[cfg(feature = "graph")]
+        {
+            let gaps: CtgIntervals = seq_context
+                .iter()
+                .map(|(ctg, context)| (ctg.clone(), context.gaps.clone()))
+                .collect();
+            graph_contigs(
+                &ctx,
+                summary,
+                &asm_lens,
+                &plan,
+                &df_asm_sunks,
+                &gaps,
+                mapped,
+            )?;
+        }
+        #[cfg(not(feature = "graph"))]
+        drop(mapped);
+    }
+    #[cfg(not(feature = "mapping"))]
+    let _ = abort;
+    Ok(())
+}
+
+/// Pipeline state shared by stages after SUNK discovery.
+#[cfg(feature = "mapping")]
+#[cfg_attr(not(feature = "graph"), allow(dead_code))]
+struct RunContext<'a> {
+    cli: &'a Cli,
+    observers: &'a Observers,
+    interrupt: &'a CancelToken,
+    abort: &'a CancelToken,
+    params: &'a IntermediateParams,
+    renames: &'a ContigRenames,
+    /// Comment header of final outputs. See [`RunMetadata::comment_header`].
+    header: &'a str,
+    state: &'a RunState,
+    writers: &'a OutputWriters,
+}
 
-    log::info!("Mapping assembly SUNKs to reads.");
-    let path_sunks_reads = Path::new("read_sunks.tsv");
+/// Outputs of the map, assign, and filter stages consumed by the graph stage.
+#[cfg(feature = "mapping")]
+#[cfg_attr(not(feature = "graph"), allow(dead_code))]
+struct MappedReads {
+    ont_lens: HashMap<String, u64>,
+    /// Read lengths as a [`DataFrame`], built once and shared by all contigs.
+    df_read_lens: Arc<DataFrame>,
+    df_read_sunks: DataFrame,
+    df_best_reads_asm: DataFrame,
+    /// Computed bad SUNKs merged with `--extra-bad-sunks`.
+    df_bad_sunks: DataFrame,
+    df_extra_bad_sunks: Option<DataFrame>,
+}
+
+/// Map assembly SUNKs to reads, assign reads to contigs, and filter bad SUNKs.
+///
+/// Returns [`None`] if stopped early with `--stop-after`.
+#[cfg(feature = "mapping")]
+fn map_reads(
+    ctx: &RunContext,
+    summary: &mut RunSummary,
+    path_reads: &Path,
+    asm_lens: &HashMap<String, u64>,
+    plan: &StagePlan,
+    df_asm_sunks: &DataFrame,
+) -> eyre::Result<Option<MappedReads>> {
+    let RunContext {
+        cli,
+        observers,
+        interrupt,
+        params,
+        renames,
+        header,
+        state,
+        writers,
+        ..
+    } = *ctx;
+    // BAM and CRAM reads are mapped straight from their records rather than an indexed copy.
+    let ont_fh = if Fasta::is_streamed(path_reads)? {
+        None
+    } else {
+        Some(Fasta::new(Fasta::indexable_path(
+            path_reads,
+            cli.allow_recompress,
+            state,
+        )?)?)
+    };
+    let ont_lens = match &ont_fh {
+        Some(fh) => fh.lengths(),
+        None => Fasta::read_lengths(path_reads, cli.allow_recompress, state)?,
+    };
+    log::info!("Reading {} reads from {path_reads:?}.", ont_lens.len());
+    // Checks that fetch reads by name load streamed reads into memory.
+    let open_reads = || match &ont_fh {
+        Some(fh) => fh.try_clone(),
+        None => Fasta::new_in_memory(path_reads, cli.max_memory, state),
+    };
+
+    summary.expected_coverage = estimate_coverage(&ont_lens, asm_lens);
+    if let Some(coverage) = summary.expected_coverage {
+        log::info!("Expected coverage: {coverage:.2}x");
+        if coverage < cli.min_coverage {
+            log::warn!(
+                "Expected coverage ({coverage:.2}x) is below {}x. Many regions may lack SUNK support.",
+                cli.min_coverage
+            );
+        }
+    }
+    if cli.sketch_check {
+        let asm_fh = Fasta::open(
+            &params.assembly,
+            cli.max_memory,
+            cli.allow_recompress,
+            state,
+        )?;
+        let sketch = sketch_reads_vs_asm(
+            &asm_fh,
+            &asm_fh.lengths(),
+            |ctg| renames.rename_name(ctg).to_owned(),
+            &open_reads()?,
+            &ont_lens,
+            interrupt,
+        )?;
+        if let (Some(containment), Some(distance)) = (sketch.read_containment, sketch.read_distance)
+        {
+            log::info!(
+                "{:.3} of sketched read k-mers are in the assembly. Mash distance: {distance:.4}",
+                containment
+            );
+            if containment < MIN_READ_CONTAINMENT {
+                log::warn!(
+                    "Less than {MIN_READ_CONTAINMENT} of read k-mers are in the assembly. Reads may be from another sample or contaminated."
+                );
+            }
+        }
+        summary.sketch = Some(sketch);
+    }
+
+    observers.stage_start(Stage::Map);
+    let path_sunks_reads = &cli.out("read_sunks.tsv");
+    let params_map = params
+        .clone()
+        .with_flag("sunk_dedup", cli.sunk_dedup)
+        .with_flag("prebin", cli.prebin);
     let df_read_sunks = load_or_redo_df!(
         path_sunks_reads,
-        map_sunks_to_reads(ont_fh, &ont_lens, &df_asm_sunks)?
+        params_map,
+        state,
+        if let Some(path_paf) = &cli.anchor_paf {
+            read_sunks_from_paf(
+                path_paf,
+                df_asm_sunks,
+                &ont_lens,
+                params.kmer_size,
+                |ctg| renames.rename_name(ctg).to_owned(),
+                cli.sunk_dedup,
+                state,
+            )?
+        } else if let Some(workers) = map_workers(cli) {
+            if cli.prebin.is_some() {
+                log::warn!("--prebin isn't supported with submit-map. All SUNKs are searched.");
+            }
+            map_sunks_to_reads_remote(
+                workers,
+                path_reads,
+                &ont_lens,
+                df_asm_sunks,
+                cli.sunk_dedup,
+                state,
+                interrupt,
+            )?
+        } else {
+            if cli.sunk_index.is_some() && params.kmer_size > MAX_INDEX_KMER_SIZE {
+                log::warn!(
+                    "--sunk-index only packs SUNKs up to {MAX_INDEX_KMER_SIZE} bp. Not used."
+                );
+            }
+            let sunk_index = cli
+                .sunk_index
+                .as_ref()
+                .filter(|_| params.kmer_size <= MAX_INDEX_KMER_SIZE)
+                .map(|path| -> Result<SunkIndex, GavisunkError> {
+                    let sunks: Vec<&str> = df_asm_sunks
+                        .column("kmer")?
+                        .str()?
+                        .into_iter()
+                        .flatten()
+                        .collect();
+                    SunkIndex::open_or_create(path, &sunks)
+                })
+                .transpose()?;
+            let sketches = cli
+                .prebin
+                .filter(|_| sunk_index.is_none())
+                .map(|_| {
+                    let asm_fh = Fasta::open(
+                        &params.assembly,
+                        cli.max_memory,
+                        cli.allow_recompress,
+                        state,
+                    )?;
+                    ContigSketches::from_fasta(
+                        &asm_fh,
+                        &asm_fh.lengths(),
+                        |ctg| renames.rename_name(ctg).to_owned(),
+                        DEFAULT_SKETCH_KMER_SIZE,
+                        DEFAULT_SKETCH_WINDOW,
+                        interrupt,
+                    )
+                })
+                .transpose()?;
+            let prebin = sketches
+                .as_ref()
+                .zip(cli.prebin)
+                .map(|(sketches, max_candidates)| Prebin {
+                    sketches,
+                    max_candidates,
+                });
+            match &ont_fh {
+                Some(fh) => map_sunks_to_reads(
+                    fh.try_clone()?,
+                    &ont_lens,
+                    df_asm_sunks,
+                    plan.read_batch_bases,
+                    Some(&cli.outdir),
+                    cli.sunk_dedup,
+                    prebin,
+                    sunk_index.as_ref(),
+                    state,
+                    interrupt,
+                )?,
+                None => map_sunks_to_read_records(
+                    path_reads,
+                    df_asm_sunks,
+                    plan.read_batch_bases,
+                    Some(&cli.outdir),
+                    cli.sunk_dedup,
+                    prebin,
+                    sunk_index.as_ref(),
+                    state,
+                    interrupt,
+                )?,
+            }
+        },
+        redo_stage(cli, Stage::Map),
+        READ_SUNKS_COLUMNS,
+        // Hit counts are only used to explain reads, which remaps them.
+        &["read", "rpos", "ctg", "cpos", "group", "strand"]
     );
+    observers.frame(Stage::Map, "read_sunks", &df_read_sunks);
+    if let Some(n_hits) = cli.validate_hits {
+        let n_checked = validate_read_hits(&open_reads()?, &df_read_sunks, df_asm_sunks, n_hits)?;
+        log::info!("{n_checked} sampled read SUNK hits match the read sequence.");
+    }
+    #[cfg(feature = "plots")]
+    if !cli.dot_plots.is_empty() {
+        let n_plots = write_read_dot_plots(&df_read_sunks, &cli.dot_plots, cli.out("dot_plots"))?;
+        log::info!("Wrote {n_plots} dot plots to dot_plots/.");
+    }
+    observers.stage_end(Stage::Map);
+    summary.stages_completed.push(Stage::Map.to_string());
+    if stops_after(cli, Stage::Map) {
+        return Ok(None);
+    }
 
-    log::info!("Assigning reads to assembly contigs.");
-    let path_best_reads_asm = Path::new("read_ctg_mapping.tsv");
+    observers.stage_start(Stage::Assign);
+    let path_best_reads_asm = &cli.out("read_ctg_mapping.tsv");
+    let params_assign = params_map.with_flag("bandwidth", DEFAULT_BANDWIDTH);
     let df_best_reads_asm = load_or_redo_df!(
         path_best_reads_asm,
-        assign_read_to_ctg_w_ort(&df_read_sunks, None, None)?
+        params_assign,
+        state,
+        assign_read_to_ctg_w_ort(&df_read_sunks, None, None, state)?,
+        redo_stage(cli, Stage::Assign),
+        READ_CTG_MAPPING_COLUMNS,
+        &[]
     );
+    observers.frame(Stage::Assign, "read_ctg_mapping", &df_best_reads_asm);
+    let df_inversions = detect_inversions(
+        &df_read_sunks,
+        &df_best_reads_asm,
+        cli.min_inversion_sunks,
+        cli.breakpoint_slop,
+    )?;
+    writers.write(
+        &mut renames.restore(&df_inversions)?,
+        cli.out("inversions.bed"),
+        header,
+    )?;
+    let df_translocations = detect_translocations(
+        &df_read_sunks,
+        cli.min_translocation_sunks,
+        cli.min_translocation_reads,
+        cli.breakpoint_slop,
+    )?;
+    writers.write(
+        &mut renames.restore(&df_translocations)?,
+        cli.out("translocation_candidates.tsv"),
+        header,
+    )?;
+    if let Some(path_haplotypes) = &cli.haplotypes {
+        let haplotypes = renames.rename_keys(load_haplotypes(path_haplotypes)?)?;
+        warn_unmatched_contigs(
+            "--haplotypes",
+            haplotypes.keys().map(String::as_str),
+            asm_lens,
+        );
+        let df_phase_switches = detect_phase_switches(
+            &df_read_sunks,
+            &haplotypes,
+            cli.min_phase_switch_sunks,
+            cli.min_translocation_reads,
+            cli.breakpoint_slop,
+        )?;
+        writers.write(
+            &mut renames.restore(&df_phase_switches)?,
+            cli.out("phase_switches.bed"),
+            header,
+        )?;
+    }
+    let df_spacing = contig_spacing_stats(
+        df_asm_sunks,
+        &df_read_sunks,
+        &df_best_reads_asm,
+        cli.spacing_tolerance,
+    )?;
+    writers.write(
+        &mut renames.restore(&df_spacing)?,
+        cli.out("contig_spacing_stats.tsv"),
+        header,
+    )?;
+    summary.contigs_spacing_flagged = df_spacing
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_spacing.column("status")?.str()?.iter())
+        .filter_map(|(ctg, status)| match (ctg, status) {
+            (Some(ctg), Some(COLLAPSED | EXPANDED)) => Some(renames.restore_name(ctg).to_owned()),
+            _ => None,
+        })
+        .collect();
+    observers.stage_end(Stage::Assign);
+    summary.stages_completed.push(Stage::Assign.to_string());
+    if stops_after(cli, Stage::Assign) {
+        return Ok(None);
+    }
+    interrupt.check()?;
 
-    log::info!("Filtering read SUNKs.");
-    let path_bad_sunks_reads = Path::new("read_sunks_bad.tsv");
-    let path_good_sunks_reads = Path::new("read_sunks_good.tsv");
+    observers.stage_start(Stage::Filter);
+    let path_bad_sunks_reads = &cli.out("read_sunks_bad.tsv");
+    let path_good_sunks_reads = &cli.out("read_sunks_good.tsv");
+    let params_filter = params_assign.with_flag(
+        "min_mode_count",
+        rs_gavisunk::filter_bad_sunks::MIN_MODE_COUNT,
+    );
     let df_good_sunks_reads = load_or_redo_df!(
         path_good_sunks_reads,
-        get_good_read_sunks(&df_read_sunks, &df_best_reads_asm)?
+        params_filter,
+        state,
+        get_good_read_sunks(&df_read_sunks, &df_best_reads_asm, state)?,
+        redo_stage(cli, Stage::Filter),
+        READ_SUNKS_GOOD_COLUMNS,
+        &[]
     );
     let df_bad_sunks = load_or_redo_df!(
         path_bad_sunks_reads,
-        filter_bad_sunks(&df_good_sunks_reads)?
+        params_filter,
+        state,
+        filter_bad_sunks(&df_good_sunks_reads)?,
+        redo_stage(cli, Stage::Filter),
+        READ_SUNKS_BAD_COLUMNS,
+        &[]
+    );
+    let df_extra_bad_sunks = cli
+        .extra_bad_sunks
+        .as_ref()
+        .map(|path| load_extra_bad_sunks(path, df_asm_sunks, renames))
+        .transpose()?;
+    let df_bad_sunks = match &df_extra_bad_sunks {
+        Some(df_extra) => merge_extra_bad_sunks(&df_bad_sunks, &df_good_sunks_reads, df_extra)?,
+        None => df_bad_sunks,
+    };
+    let strata = covariate_strata(df_asm_sunks, &df_good_sunks_reads, &df_bad_sunks)?;
+    for stratum in strata.iter().filter(|stratum| stratum.groups > 0) {
+        log::info!(
+            "SUNK groups with {} {}: {} without reads and {} bad of {}.",
+            stratum.covariate,
+            stratum.stratum,
+            stratum.unobserved,
+            stratum.bad,
+            stratum.groups
+        );
+    }
+    summary.sunk_covariates = Some(strata);
+    observers.frame(Stage::Filter, "read_sunks_good", &df_good_sunks_reads);
+    observers.frame(Stage::Filter, "read_sunks_bad", &df_bad_sunks);
+    observers.stage_end(Stage::Filter);
+    summary.stages_completed.push(Stage::Filter.to_string());
+    if stops_after(cli, Stage::Filter) {
+        return Ok(None);
+    }
+    interrupt.check()?;
+
+    Ok(Some(MappedReads {
+        df_read_lens: Arc::new(read_lengths_df(&ont_lens)?),
+        ont_lens,
+        df_read_sunks,
+        df_best_reads_asm,
+        df_bad_sunks,
+        df_extra_bad_sunks,
+    }))
+}
+
+/// Graph stage parameters from the command line.
+#[cfg(feature = "graph")]
+fn graph_params(cli: &Cli) -> GraphParams {
+    GraphParams {
+        multi_sunk: cli.multi_sunk,
+        dst_tolerance: cli.dst_tolerance,
+        min_component_sunks: cli.min_component_sunks,
+        max_read_sunks: (cli.max_read_sunks > 0).then_some(cli.max_read_sunks),
+        read_timeout: (cli.read_timeout > 0.0).then(|| Duration::from_secs_f64(cli.read_timeout)),
+    }
+}
+
+/// Write the assembly sequence of unsupported intervals from a previous run with flanks.
+#[cfg(feature = "graph")]
+fn extract_unsupported(
+    cli: &Cli,
+    args: &ExtractUnsupportedArgs,
+    mut asm_fh: Fasta,
+    fa_lens: &HashMap<String, u64>,
+    renames: &ContigRenames,
+) -> eyre::Result<()> {
+    // Outputs use FASTA names so intervals are fetched as is.
+    let path_bed = cli.out(&args.bed);
+    let intervals = load_bed(&path_bed)?;
+    warn_unmatched_contigs(
+        &path_bed.to_string_lossy(),
+        intervals.keys().map(String::as_str),
+        fa_lens,
+    );
+    let n_records = if cli.per_contig {
+        let fnames = ContigFilenames::new(fa_lens.keys().map(|ctg| renames.rename_name(ctg)));
+        let mut n_records = 0;
+        for (ctg, ctg_intervals) in &intervals {
+            let fname = fnames.get(renames.rename_name(ctg));
+            let ctg_intervals = HashMap::from([(ctg.clone(), ctg_intervals.clone())]);
+            write_atomic(cli.out(format!("{fname}_unsupported.fa")), |file| {
+                n_records += write_flanked_fasta(
+                    &mut asm_fh,
+                    &ctg_intervals,
+                    fa_lens,
+                    args.flank,
+                    std::io::BufWriter::new(file),
+                )?;
+                Ok(())
+            })?;
+        }
+        n_records
+    } else {
+        let mut n_records = 0;
+        write_atomic(cli.out(&args.output), |file| {
+            n_records = write_flanked_fasta(
+                &mut asm_fh,
+                &intervals,
+                fa_lens,
+                args.flank,
+                std::io::BufWriter::new(file),
+            )?;
+            Ok(())
+        })?;
+        n_records
+    };
+    log::info!(
+        "Wrote {n_records} intervals with {} bp flanks from {:?}.",
+        args.flank,
+        path_bed
     );
+    Ok(())
+}
+
+/// Print whether kmers are SUNKs, their assembly positions, and the reads carrying them.
+#[cfg(feature = "graph")]
+fn run_lookup(
+    cli: &Cli,
+    args: &LookupArgs,
+    renames: &ContigRenames,
+    df_asm_sunks: &DataFrame,
+) -> eyre::Result<()> {
+    let mut kmers = args.kmers.clone();
+    if let Some(path) = &args.file {
+        kmers.extend(
+            std::fs::read_to_string(cli.out(path))?
+                .lines()
+                .map(str::trim)
+                .filter(|kmer| !kmer.is_empty() && !kmer.starts_with('#'))
+                .map(str::to_owned),
+        );
+    }
+    // Repeated kmers and read hits are only available from a previous run.
+    let path_kmer_cnts = cli.out("kmer_counts.tsv");
+    let df_kmer_cnts = path_kmer_cnts
+        .exists()
+        .then(|| renames.rename(&load_tsv(&path_kmer_cnts)?))
+        .transpose()?;
+    let path_sunks_reads = cli.out("read_sunks.tsv");
+    let df_read_sunks = path_sunks_reads
+        .exists()
+        .then(|| {
+            load_tsv_with_schema(
+                &path_sunks_reads,
+                READ_SUNKS_COLUMNS,
+                &["read", "rpos", "ctg", "group", "strand"],
+            )
+        })
+        .transpose()?;
+    let report = lookup_kmers(
+        &kmers,
+        df_asm_sunks,
+        df_kmer_cnts.as_ref(),
+        df_read_sunks.as_ref(),
+    )?;
+    println!("{report}");
+    Ok(())
+}
+
+/// Print a trace of a single read through the map, assign, and graph stages.
+#[cfg(feature = "graph")]
+fn run_explain_read(
+    cli: &Cli,
+    args: &ExplainReadArgs,
+    path_reads: &Path,
+    asm_lens: &HashMap<String, u64>,
+    df_asm_sunks: &DataFrame,
+    state: &RunState,
+) -> eyre::Result<()> {
+    // Show per-read graph decisions.
+    log::set_max_level(log::LevelFilter::Debug);
+    // Bad SUNKs depend on all reads so can only be reused from a previous run.
+    let path_bad_sunks = cli.out("read_sunks_bad.tsv");
+    let df_bad_sunks = if path_bad_sunks.exists() {
+        Some(load_tsv_with_schema(
+            &path_bad_sunks,
+            READ_SUNKS_BAD_COLUMNS,
+            &[],
+        )?)
+    } else {
+        log::warn!("No {path_bad_sunks:?} from a previous run. Bad SUNKs are not masked.");
+        None
+    };
+    let trace = explain_read(
+        &args.name,
+        if Fasta::is_streamed(path_reads)? {
+            Fasta::new_in_memory(path_reads, cli.max_memory, state)?
+        } else {
+            Fasta::new(Fasta::indexable_path(
+                path_reads,
+                cli.allow_recompress,
+                state,
+            )?)?
+        },
+        df_asm_sunks,
+        asm_lens,
+        df_bad_sunks.as_ref(),
+        DEFAULT_BANDWIDTH,
+        cli.sunk_dedup,
+        &graph_params(cli),
+        state,
+    )?;
+    println!("{trace}");
+    Ok(())
+}
+
+/// Print the SUNKs in a region and why each read carrying them did or did not support it.
+#[cfg(feature = "graph")]
+fn run_explain_region(
+    ctx: &RunContext,
+    args: &ExplainRegionArgs,
+    asm_lens: &HashMap<String, u64>,
+    df_asm_sunks: &DataFrame,
+    mapped: &MappedReads,
+) -> eyre::Result<()> {
+    let (ctg, st, end) = &args.region;
+    let trace = explain_region(
+        &(ctx.renames.rename_name(ctg).to_owned(), *st, *end),
+        df_asm_sunks,
+        &mapped.df_read_sunks,
+        &mapped.df_best_reads_asm,
+        &mapped.df_bad_sunks,
+        &mapped.ont_lens,
+        asm_lens,
+        &graph_params(ctx.cli),
+        ctx.state,
+    )?;
+    println!("{trace}");
+    Ok(())
+}
+
+/// Downsample assigned reads and write supported bases per contig at each fraction of coverage.
+#[cfg(feature = "graph")]
+fn run_titration(
+    ctx: &RunContext,
+    args: &TitrateArgs,
+    asm_lens: &HashMap<String, u64>,
+    mapped: &MappedReads,
+) -> eyre::Result<()> {
+    let params = TitrationParams {
+        fractions: args.fractions.clone(),
+        seed: args.seed,
+        graph: graph_params(ctx.cli),
+    };
+    let df_titration = titrate(
+        &mapped.df_read_sunks,
+        &mapped.df_best_reads_asm,
+        &mapped.df_read_lens,
+        asm_lens,
+        mapped.df_extra_bad_sunks.as_ref(),
+        &params,
+        ctx.state,
+        ctx.interrupt,
+    )?;
+    ctx.writers.write(
+        &mut ctx.renames.restore(&df_titration)?,
+        ctx.cli.out(&args.output),
+        ctx.header,
+    )?;
+    Ok(())
+}
+
+/// Write the SUNK group read count histogram and the cutoffs used to filter bad SUNKs.
+#[cfg(feature = "graph")]
+fn run_bad_sunks(ctx: &RunContext, args: &BadSunksArgs, mapped: &MappedReads) -> eyre::Result<()> {
+    let df_good_sunks_reads =
+        get_good_read_sunks(&mapped.df_read_sunks, &mapped.df_best_reads_asm, ctx.state)?;
+    let (mut df_hist, cutoffs) = sunk_count_histogram(&df_good_sunks_reads)?;
+    match (cutoffs.mode, cutoffs.max_count) {
+        (Some(mode), Some(max_count)) => log::info!(
+            "SUNK group read count mode is {mode}. Groups with more than {max_count:.1} reads are bad."
+        ),
+        _ => log::warn!(
+            "No SUNK group has more than {} reads. No SUNKs are filtered.",
+            cutoffs.min_mode_count
+        ),
+    }
+    ctx.writers.write(
+        &mut df_hist,
+        ctx.cli.out(format!("{}_histogram.tsv", args.prefix)),
+        ctx.header,
+    )?;
+    write_atomic(
+        ctx.cli.out(format!("{}_cutoffs.json", args.prefix)),
+        |file| Ok(serde_json::to_writer_pretty(file, &cutoffs)?),
+    )?;
+
+    #[cfg(feature = "plots")]
+    {
+        let bars: Vec<(u64, u64, bool)> = df_hist
+            .column("count")?
+            .u32()?
+            .iter()
+            .zip(df_hist.column("sunk_groups")?.u64()?.iter())
+            .zip(df_hist.column("status")?.str()?.iter())
+            .flat_map(|((count, n), status)| {
+                Some((
+                    count? as u64,
+                    n?,
+                    status? == rs_gavisunk::filter_bad_sunks::BAD,
+                ))
+            })
+            .collect();
+        let cutoff_lines: Vec<f64> = [Some(cutoffs.min_mode_count as f64), cutoffs.max_count]
+            .into_iter()
+            .flatten()
+            .collect();
+        let svg = histogram_svg(
+            "SUNK group read counts",
+            "Reads",
+            "SUNK groups",
+            &bars,
+            &cutoff_lines,
+        );
+        write_atomic(
+            ctx.cli.out(format!("{}_histogram.svg", args.prefix)),
+            |file| Ok(std::io::Write::write_all(file, svg.as_bytes())?),
+        )?;
+    }
+    Ok(())
+}
+
+/// Build per-contig SUNK graphs and merge per-contig outputs into genome-wide files.
+#[cfg(feature = "graph")]
+fn graph_contigs(
+    ctx: &RunContext,
+    summary: &mut RunSummary,
+    asm_lens: &HashMap<String, u64>,
+    plan: &StagePlan,
+    df_asm_sunks: &DataFrame,
+    gaps: &CtgIntervals,
+    mapped: MappedReads,
+) -> eyre::Result<()> {
+    let RunContext {
+        cli,
+        observers,
+        interrupt,
+        abort,
+        renames,
+        header,
+        state,
+        writers,
+        ..
+    } = *ctx;
+    let MappedReads {
+        ont_lens,
+        df_read_lens,
+        df_read_sunks,
+        df_best_reads_asm,
+        df_bad_sunks,
+        ..
+    } = mapped;
+
+    let params = graph_params(cli);
 
     // TODO: Process by contig
-    log::info!("Generating SUNK graph by contig.");
-    df_read_sunks
-        .partition_by(["ctg"], true)?
-        .par_iter()
-        .for_each(|df_ctg| {
-            let ctg = df_ctg
-                .column("ctg")
-                .unwrap()
-                .str()
-                .unwrap()
-                .first()
-                .map(|ctg| ctg.to_owned())
-                .unwrap();
-            let (mut df_sunks, mut df_bed) =
-                create_sunk_graph(&ctg, &df_ctg, &ont_lens, &df_bad_sunks).unwrap();
-            write_tsv(&mut df_sunks, format!("{ctg}_sunks.tsv")).unwrap();
-            write_tsv(&mut df_bed, format!("{ctg}.bed")).unwrap();
-        });
-    log::info!("Done.");
+    observers.stage_start(Stage::Graph);
+    let dfs_ctg = df_read_sunks.partition_by(["ctg"], true)?;
+    // Contig names may contain characters that aren't valid in paths. ex. `/`, `:`, or `|`
+    let fnames = ContigFilenames::new(asm_lens.keys().map(|ctg| ctg.as_str()));
+    if cli.per_contig {
+        writers.write(
+            &mut renames.restore(&fnames.to_df()?)?,
+            cli.out("contig_filenames.tsv"),
+            header,
+        )?;
+    }
+    let ctg_results = Mutex::new(Vec::with_capacity(dfs_ctg.len()));
+    summary.contigs_total = dfs_ctg.len();
+    // SUNK graphs of circular contigs continue across their origin.
+    let mut circular: HashSet<String> = cli
+        .circular
+        .iter()
+        .map(|ctg| normalize_name(ctg).to_owned())
+        .collect();
+    if let Some(path) = &cli.circular_gfa {
+        circular.extend(load_gfa_circular(path)?);
+    }
+    let circular: HashSet<String> = circular
+        .iter()
+        .map(|ctg| renames.rename_name(ctg).to_owned())
+        .collect();
+    warn_unmatched_contigs("--circular", circular.iter().map(String::as_str), asm_lens);
+    // Supported intervals on the contig. Intervals of circular contigs crossing the origin are split at it.
+    let contig_bed = |ctg: &str, df_bed: &DataFrame| -> Result<(DataFrame, bool), GavisunkError> {
+        match asm_lens.get(ctg).filter(|_| circular.contains(ctg)) {
+            Some(ctg_len) => split_origin(df_bed, *ctg_len),
+            None => Ok((df_bed.clone(), false)),
+        }
+    };
+    let anchor_store = cli
+        .anchor_store
+        .as_ref()
+        .map(|dir| AnchorStore::new(cli.out(dir)))
+        .transpose()?;
+    // Largest contigs first so they don't finish last on one thread.
+    let mut dfs_ctg_by_size: Vec<&DataFrame> = dfs_ctg.iter().collect();
+    dfs_ctg_by_size.sort_by_key(|df_ctg| std::cmp::Reverse(df_ctg.height()));
+    let graph_contig = |df_ctg: &DataFrame| -> Result<(), GavisunkError> {
+        let Some(ctg) = df_ctg.column("ctg")?.str()?.first().map(str::to_owned) else {
+            return Ok(());
+        };
+        let res = create_sunk_graph_with_store(
+            &ctg,
+            df_ctg,
+            &df_read_lens,
+            asm_lens,
+            &df_bad_sunks,
+            &params,
+            circular.contains(&ctg),
+            anchor_store.as_ref(),
+            state,
+            abort,
+        );
+        if res.as_ref().is_err_and(|err| err.is_cancelled()) {
+            log::warn!("Cancelled graph stage for {ctg}. No output written.");
+            return Ok(());
+        }
+        let (mut df_sunks, df_bed) = res?;
+        observers.contig(&ctg, &df_sunks, &df_bed);
+        if cli.per_contig {
+            let fname = fnames.get(&ctg);
+            writers.write(&mut df_sunks, cli.out(format!("{fname}_sunks.tsv")), header)?;
+            let (df_ctg_bed, _) = contig_bed(&ctg, &df_bed)?;
+            writers.write(
+                &mut renames.restore(&df_ctg_bed)?,
+                cli.out(format!("{fname}.bed")),
+                header,
+            )?;
+        }
+        ctg_results.lock().unwrap().push((ctg, df_sunks, df_bed));
+        Ok(())
+    };
+    // First error of a contig. Stops new contigs like an interrupt and is returned once outputs of the completed
+    // contigs are written.
+    let ctg_error: Mutex<Option<GavisunkError>> = Mutex::new(None);
+    // Share polars' pool with the contigs' queries instead of oversubscribing CPUs with a separate pool.
+    for_each_bounded(&dfs_ctg_by_size, plan.graph_concurrency, |df_ctg| {
+        // Don't start new contigs once interrupted or failed. Finished contigs are already written.
+        if interrupt.is_cancelled() || !ctg_error.lock().is_ok_and(|err| err.is_none()) {
+            return;
+        }
+        if let Err(err) = graph_contig(df_ctg) {
+            log::error!("Graph stage failed: {err}. Finishing in-flight contigs.");
+            if let Ok(mut ctg_error) = ctg_error.lock() {
+                ctg_error.get_or_insert(err);
+            }
+        }
+    });
+    observers.stage_end(Stage::Graph);
+    let mut df_read_status = read_status(state)?;
+    summary.skipped_reads = df_read_status.height();
+    if summary.skipped_reads > 0 {
+        log::warn!(
+            "Skipped {} reads exceeding their SUNK graph budget. See read_status.tsv.",
+            summary.skipped_reads
+        );
+    }
+    writers.write(
+        &mut renames.restore(&df_read_status)?,
+        cli.out("read_status.tsv"),
+        header,
+    )?;
+
+    let mut ctg_results = ctg_results.into_inner()?;
+    ctg_results.sort_by(|a, b| a.0.cmp(&b.0));
+    let ctgs_completed: Vec<String> = ctg_results.iter().map(|(ctg, _, _)| ctg.clone()).collect();
+    for df_ctg in dfs_ctg.iter() {
+        let Some(ctg) = df_ctg.column("ctg")?.str()?.first() else {
+            continue;
+        };
+        if ctgs_completed
+            .binary_search_by(|c| c.as_str().cmp(ctg))
+            .is_ok()
+        {
+            continue;
+        }
+        // Remove outputs left over from previous runs so they aren't mistaken for this run's results.
+        let fname = fnames.get(ctg);
+        for path in [format!("{fname}_sunks.tsv"), format!("{fname}.bed")] {
+            let path = cli.out(path);
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        summary.contigs_incomplete.push(ctg.to_owned());
+    }
+    let ctg_error = ctg_error.into_inner()?;
+
+    if let Some(path_archive) = &cli.archive {
+        let mut archive_results = ctg_results
+            .iter()
+            .map(|(ctg, df_sunks, df_bed)| {
+                Ok((ctg.clone(), df_sunks.clone(), contig_bed(ctg, df_bed)?.0))
+            })
+            .collect::<Result<Vec<_>, GavisunkError>>()?;
+        write_archive(cli.out(path_archive), &mut archive_results, &fnames)?;
+    }
+
+    // Small contigs are supported by reads containing them if their SUNK graphs don't support them.
+    let df_contained_reads = contained_reads(
+        &df_read_sunks,
+        &df_best_reads_asm,
+        &df_bad_sunks,
+        &ont_lens,
+        asm_lens,
+        MIN_READ_LEN,
+        cli.dst_tolerance,
+    )?;
+    let contained = contained_support(&df_contained_reads, MIN_CONTAINED_READS)?;
+
+    // Reads spanning the origin of circular contigs that aren't in SUNK graph components still support it.
+    let df_origin_reads = origin_spanning_reads(
+        &df_read_sunks,
+        &df_bad_sunks,
+        asm_lens,
+        &circular,
+        cli.dst_tolerance,
+    )?;
+    let origins = origin_support(&df_origin_reads, asm_lens, MIN_ORIGIN_READS)?;
+
+    // Merge per-contig outputs into genome-wide files.
+    let (mut dfs_components, mut dfs_supported, mut dfs_scales) = (vec![], vec![], vec![]);
+    let (mut ctgs_contained, mut ctgs_origin) = (HashSet::new(), HashSet::new());
+    for (ctg, mut df_sunks, mut df_bed) in ctg_results {
+        let ctg_len = asm_lens.get(&ctg).copied().unwrap_or_default();
+        let (mut df_ctg_bed, crosses_origin) = contig_bed(&ctg, &df_bed)?;
+        if let Some((_, n_sunks)) = contained.get(&ctg) {
+            if supported_bp(&df_ctg_bed)? < ctg_len {
+                df_bed = contained_bed(&ctg, ctg_len, *n_sunks)?;
+                df_ctg_bed = df_bed.clone();
+                ctgs_contained.insert(ctg.clone());
+            }
+        }
+        // Scales use the unsplit intervals as they're indexed by component.
+        dfs_scales.push(component_scale_stats(
+            &ctg,
+            &df_sunks,
+            &df_bed,
+            cli.spacing_tolerance,
+        )?);
+        let mut df_bed = df_ctg_bed;
+        if !ctgs_contained.contains(&ctg) {
+            if crosses_origin {
+                ctgs_origin.insert(ctg.clone());
+            } else if let Some((st, end, n_sunks)) = origins.get(&ctg) {
+                // Added after component scales as intervals across the origin from spanning reads aren't components.
+                df_bed = df_bed
+                    .vstack(&origin_bed(&ctg, ctg_len, *st, *end, *n_sunks)?)?
+                    .sort(["st"], Default::default())?;
+                ctgs_origin.insert(ctg.clone());
+            }
+        }
+        summary.supported_bp += supported_bp(&df_bed)?;
+        summary.assembly_bp += ctg_len;
+        let col_ctg = Column::new("ctg".into(), vec![ctg.as_str(); df_sunks.height()]);
+        df_sunks.insert_column(0, col_ctg)?;
+        dfs_components.push(df_sunks);
+        dfs_supported.push(df_bed);
+    }
+    log::info!(
+        "Supported {} of {} bp ({:.2}%) of completed contigs.",
+        summary.supported_bp,
+        summary.assembly_bp,
+        summary.supported_bp as f64 / summary.assembly_bp.max(1) as f64 * 100.0
+    );
+    summary.contigs_contained_support = ctgs_contained
+        .iter()
+        .map(|ctg| renames.restore_name(ctg).to_owned())
+        .collect();
+    summary.contigs_contained_support.sort_unstable();
+    if !ctgs_contained.is_empty() {
+        log::info!(
+            "{} small contigs supported by reads containing them.",
+            ctgs_contained.len()
+        );
+    }
+    summary.contigs_origin_support = ctgs_origin
+        .iter()
+        .map(|ctg| renames.restore_name(ctg).to_owned())
+        .collect();
+    summary.contigs_origin_support.sort_unstable();
+    if !circular.is_empty() {
+        log::info!(
+            "{} of {} circular contigs supported across their origin.",
+            ctgs_origin.len(),
+            circular.len()
+        );
+    }
+    let df_supported = concat_dfs(
+        dfs_supported,
+        &Schema::from_iter([
+            Field::new("ctg".into(), DataType::String),
+            Field::new("st".into(), DataType::Int64),
+            Field::new("end".into(), DataType::Int64),
+            Field::new("sunks".into(), DataType::UInt64),
+        ]),
+    )?;
+    let df_scales = concat_dfs(
+        dfs_scales,
+        &Schema::from_iter([
+            Field::new("ctg".into(), DataType::String),
+            Field::new("st".into(), DataType::Int64),
+            Field::new("end".into(), DataType::Int64),
+            Field::new("component".into(), DataType::UInt64),
+            Field::new("reads".into(), DataType::UInt64),
+            Field::new("offset".into(), DataType::Float64),
+            Field::new("scale".into(), DataType::Float64),
+            Field::new("status".into(), DataType::String),
+            Field::new("size_delta".into(), DataType::Int64),
+        ]),
+    )?;
+    let n_scale_flagged = df_scales
+        .column("status")?
+        .str()?
+        .iter()
+        .filter(|status| matches!(status, Some(COLLAPSED | EXPANDED)))
+        .count();
+    log::info!(
+        "{n_scale_flagged} supported components with read scale deviating from the assembly."
+    );
+    // Present contigs reverse complemented relative to a reference in its orientation.
+    let orientations = cli
+        .ref_paf
+        .as_ref()
+        .map(|path| -> eyre::Result<_> {
+            let strands = renames.rename_keys(load_paf_strands(path)?)?;
+            warn_unmatched_contigs("--ref-paf", strands.keys().map(String::as_str), asm_lens);
+            Ok(ContigOrientations::new(
+                &strands,
+                asm_lens,
+                MIN_REVERSE_FRACTION,
+            ))
+        })
+        .transpose()?;
+    if let Some(orientations) = &orientations {
+        summary.contigs_flipped = orientations
+            .flipped()
+            .into_iter()
+            .map(|ctg| renames.restore_name(ctg).to_owned())
+            .collect();
+        log::info!(
+            "{} contigs reverse complemented relative to the reference.",
+            summary.contigs_flipped.len()
+        );
+    }
+    let present = |df: &DataFrame, intervals: &[(&str, &str)]| -> eyre::Result<DataFrame> {
+        let df = match &orientations {
+            Some(orientations) => orientations.apply(df, intervals)?,
+            None => df.clone(),
+        };
+        Ok(renames.restore(&df)?)
+    };
+    writers.write(
+        &mut present(&df_scales, &[("st", "end")])?,
+        cli.out("component_scales.bed"),
+        header,
+    )?;
+    let df_unsupported = get_unsupported_intervals(&df_supported, asm_lens, &ctgs_completed)?;
+    // Distinguish assembly size errors from misjoins at unsupported junctions.
+    let df_unsupported = estimate_junction_indels(
+        &df_unsupported,
+        &df_read_sunks,
+        &df_best_reads_asm,
+        cli.dst_tolerance,
+    )?;
+    // Label why intervals are unsupported as each calls for a different fix.
+    let depth = cli
+        .depth
+        .as_ref()
+        .map(|path| renames.rename_keys(load_bedgraph(path)?))
+        .transpose()?;
+    if let Some(depth) = &depth {
+        warn_unmatched_contigs("--depth", depth.keys().map(String::as_str), asm_lens);
+    }
+    let df_unsupported = label_dropouts(
+        &df_unsupported,
+        df_asm_sunks,
+        &df_read_sunks,
+        depth.as_ref(),
+        cli.min_depth,
+    )?;
+    let df_unsupported = count_context_sunks(&df_unsupported, df_asm_sunks)?;
+    let df_components = concat_dfs(
+        dfs_components,
+        &Schema::from_iter([
+            Field::new("ctg".into(), DataType::String),
+            Field::new("read".into(), DataType::String),
+            Field::new("id".into(), DataType::String),
+            Field::new("rpos".into(), DataType::Int64),
+            Field::new("proj_cpos".into(), DataType::Int64),
+            Field::new("offset_dev".into(), DataType::Int64),
+            Field::new("component".into(), DataType::UInt64),
+        ]),
+    )?;
+    let read_lengths = read_length_stats(&ont_lens, &df_components, state)?;
+    log::info!("Read N50: {} bp", read_lengths.n50);
+    for bin in read_lengths.bins.iter() {
+        log::info!(
+            "Reads {}: {} of {} reads support {} bp.",
+            bin.bin,
+            bin.supporting_reads,
+            bin.reads,
+            bin.supported_bp
+        );
+    }
+    summary.read_lengths = Some(read_lengths);
+    if let Some(path_read_groups) = &cli.read_groups {
+        let read_groups = load_read_groups(path_read_groups)?;
+        let n_unmatched = read_groups
+            .keys()
+            .filter(|read| !ont_lens.contains_key(*read))
+            .count();
+        if n_unmatched > 0 {
+            log::warn!(
+                "{n_unmatched} reads in --read-groups are not in the reads and will be ignored."
+            );
+        }
+        let groups = read_group_stats(
+            &read_groups,
+            &ont_lens,
+            &df_read_sunks,
+            &df_best_reads_asm,
+            &df_components,
+            asm_lens.values().sum(),
+            state,
+        )?;
+        for group in groups.iter() {
+            log::info!(
+                "Read group {}: {} of {} reads mapped, {} assigned, and {} support {} bp.",
+                group.group,
+                group.mapped_reads,
+                group.reads,
+                group.assigned_reads,
+                group.supporting_reads,
+                group.supported_bp
+            );
+        }
+        summary.read_groups = Some(groups);
+    }
+    summary.sunk_context = Some(sunk_context_stats(df_asm_sunks, Some(&df_components))?);
+    writers.write(
+        &mut present(&df_supported, &[("st", "end")])?,
+        cli.out("supported.bed"),
+        header,
+    )?;
+    // Bound the misassembly size supported intervals could still hide.
+    let sunk_positions = good_sunk_positions(df_asm_sunks, &df_bad_sunks)?;
+    let coverage = contig_coverage(&df_best_reads_asm, &ont_lens, asm_lens)?;
+    let read_dist = ReadLengthDist::new(ont_lens.values().copied());
+    let tolerance = f64::from(cli.dst_tolerance);
+    let df_sensitivity = region_sensitivity(
+        &df_supported,
+        &sunk_positions,
+        &coverage,
+        tolerance,
+        &read_dist,
+    )?;
+    writers.write(
+        &mut present(&df_sensitivity, &[("st", "end")])?,
+        cli.out("sensitivity.bed"),
+        header,
+    )?;
+    let df_false_support = contig_false_support(
+        &ctgs_completed,
+        asm_lens,
+        &sunk_positions,
+        &coverage,
+        tolerance,
+        &read_dist,
+    )?;
+    writers.write(
+        &mut present(&df_false_support, &[])?,
+        cli.out("false_support.tsv"),
+        header,
+    )?;
+    let mut ctg_reads: HashMap<String, u64> = HashMap::new();
+    for ctg in df_best_reads_asm.column("ctg")?.str()?.iter().flatten() {
+        *ctg_reads.entry(ctg.to_owned()).or_default() += 1;
+    }
+    // Reads containing a small contig are also assigned to it.
+    for (ctg, assigned) in df_contained_reads
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_contained_reads.column("assigned")?.bool()?.iter())
+    {
+        if let (Some(ctg), Some(false)) = (ctg, assigned) {
+            *ctg_reads.entry(ctg.to_owned()).or_default() += 1;
+        }
+    }
+    writers.write(
+        &mut present(&df_contained_reads, &[])?,
+        cli.out("contained_reads.tsv"),
+        header,
+    )?;
+    if !circular.is_empty() {
+        writers.write(
+            &mut present(&df_origin_reads, &[])?,
+            cli.out("origin_reads.tsv"),
+            header,
+        )?;
+    }
+    let df_contig_summary = contig_summary(
+        &ctgs_completed,
+        asm_lens,
+        &df_unsupported,
+        &sunk_positions,
+        &ctg_reads,
+        &coverage,
+        &ctgs_contained,
+    )?;
+    writers.write(
+        &mut present(&df_contig_summary, &[])?,
+        cli.out("contig_summary.tsv"),
+        header,
+    )?;
+    writers.write(
+        &mut present(&df_unsupported, &[("st", "end")])?,
+        cli.out("unsupported.bed"),
+        header,
+    )?;
+    // Leave out events on gaps or known problem regions from the short list for review.
+    let mut excluded = gaps.clone();
+    if let Some(path) = &cli.exclude_regions {
+        let regions = renames.rename_keys(load_bed(path)?)?;
+        warn_unmatched_contigs(
+            "--exclude-regions",
+            regions.keys().map(String::as_str),
+            asm_lens,
+        );
+        for (ctg, intervals) in regions {
+            excluded.entry(ctg).or_default().extend(intervals);
+        }
+    }
+    let excluded: CtgIntervals = excluded
+        .into_iter()
+        .map(|(ctg, intervals)| (ctg, merge(intervals, 0)))
+        .collect();
+    let df_curation_targets =
+        curation_targets(&df_unsupported, asm_lens, cli.min_event_size, &excluded)?;
+    log::info!(
+        "{} of {} unsupported intervals are curation targets of at least {} bp.",
+        df_curation_targets.height(),
+        df_unsupported.height(),
+        cli.min_event_size
+    );
+    writers.write(
+        &mut present(&df_curation_targets, &[("st", "end")])?,
+        cli.out("curation_targets.bed"),
+        header,
+    )?;
+    let df_breakpoints = breakpoint_links(
+        &df_unsupported,
+        &df_scales,
+        &df_components,
+        &df_best_reads_asm,
+        asm_lens,
+    )?;
+    writers.write(
+        &mut present(&df_breakpoints, &[])?,
+        cli.out("breakpoints.bedpe"),
+        header,
+    )?;
+    let df_polish_targets = polish_targets(
+        &df_unsupported,
+        &df_scales,
+        df_asm_sunks,
+        &df_bad_sunks,
+        asm_lens,
+    )?;
+    log::info!("{} polish targets.", df_polish_targets.height());
+    writers.write(
+        &mut present(&df_polish_targets, &[("st", "end")])?,
+        cli.out("polish_targets.bed"),
+        header,
+    )?;
+    writers.write(
+        &mut present(&df_components, &[])?,
+        cli.out("read_components.tsv"),
+        header,
+    )?;
+    let df_rescued = cli
+        .rescue_single_sunk
+        .then(|| {
+            rescue_single_sunk_reads(&df_read_sunks, &df_read_lens, &df_bad_sunks, &df_components)
+        })
+        .transpose()?;
+    if let Some(df_rescued) = &df_rescued {
+        log::info!(
+            "Rescued {} single-SUNK reads within supported components.",
+            df_rescued.height()
+        );
+        summary.rescued_reads = Some(df_rescued.height());
+    }
+    let df_sunk_support = sunk_support(
+        &df_components,
+        df_asm_sunks,
+        &ctgs_completed,
+        df_rescued.as_ref(),
+    )?;
+    writers.write(
+        &mut present(&df_sunk_support, &[("st", "end")])?,
+        cli.out("sunk_support.bedgraph"),
+        header,
+    )?;
+    let df_read_intervals = read_intervals(&df_components, ctx.params.kmer_size)?;
+    let df_read_trims = read_trim_suggestions(
+        &df_read_intervals,
+        &df_read_sunks,
+        &ont_lens,
+        ctx.params.kmer_size,
+        cli.min_trim_sunks,
+    )?;
+    log::info!(
+        "Suggested {} breakpoints in possibly chimeric reads.",
+        df_read_trims.height()
+    );
+    writers.write(
+        &mut present(&df_read_intervals, &[("ctg_st", "ctg_end")])?,
+        cli.out("read_intervals.bed"),
+        header,
+    )?;
+    writers.write(
+        &mut present(&df_read_trims, &[])?,
+        cli.out("read_trim_suggestions.tsv"),
+        header,
+    )?;
+    let df_contig_ends =
+        contig_end_extensions(&df_read_intervals, &ont_lens, &ctgs_completed, asm_lens)?;
+    let n_extendable = df_contig_ends
+        .column("status")?
+        .str()?
+        .iter()
+        .filter(|status| *status == Some(EXTENDABLE))
+        .count();
+    log::info!("Found {n_extendable} contig ends extended by validated reads.");
+    writers.write(
+        &mut present(&df_contig_ends, &[])?,
+        cli.out("contig_ends.tsv"),
+        header,
+    )?;
+    #[cfg(feature = "plots")]
+    if cli.report {
+        let ctg_lens: HashMap<String, u64> = ctgs_completed
+            .iter()
+            .flat_map(|ctg| Some((renames.restore_name(ctg).to_owned(), *asm_lens.get(ctg)?)))
+            .collect();
+        let n_pages = write_report(
+            cli.out("report"),
+            &ctg_lens,
+            &renames.restore(&df_supported)?,
+            &renames.restore(&df_unsupported)?,
+            &renames.restore(&df_read_intervals)?,
+            &renames.restore(df_asm_sunks)?,
+        )?;
+        log::info!("Wrote report with {n_pages} contig pages to report/.");
+    }
+
+    summary.contigs_completed = ctgs_completed;
+    if let Some(err) = ctg_error {
+        summary.status = RunStatus::Partial;
+        return Err(err.into());
+    }
+    if interrupt.is_cancelled() {
+        return Err(GavisunkError::from(Cancelled).into());
+    }
+    summary.stages_completed.push(Stage::Graph.to_string());
+    Ok(())
+}
+
+/// Rerun the assign, filter, and graph stages across a grid of parameters and write a comparison table.
+#[cfg(feature = "graph")]
+fn sweep_params(
+    ctx: &RunContext,
+    args: &SweepArgs,
+    asm_lens: &HashMap<String, u64>,
+    mapped: MappedReads,
+) -> eyre::Result<()> {
+    let grid = sweep_grid(
+        &args.bandwidths,
+        &args.dst_tolerances,
+        &args.min_component_sunks,
+        graph_params(ctx.cli),
+    );
+    log::info!("Sweeping {} parameter sets.", grid.len());
+    let anchor_store = ctx
+        .cli
+        .anchor_store
+        .as_ref()
+        .map(|dir| AnchorStore::new(ctx.cli.out(dir)))
+        .transpose()?;
+    let mut df_sweep = sweep(
+        &mapped.df_read_sunks,
+        &mapped.df_read_lens,
+        asm_lens,
+        mapped.df_extra_bad_sunks.as_ref(),
+        &grid,
+        anchor_store.as_ref(),
+        ctx.state,
+        ctx.interrupt,
+    )?;
+    ctx.writers
+        .write(&mut df_sweep, ctx.cli.out(&args.output), ctx.header)?;
     Ok(())
 }
@@ -0,0 +1,200 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use polars::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    io::write_atomic,
+    summary::{RunStatus, RunSummary},
+};
+
+/// Outputs at fixed paths and their roles. Files not produced by a run are left out of its manifest.
+/// Intermediates also have a `{path}.params.json` sidecar. See [`crate::io::IntermediateParams`].
+pub const OUTPUTS: [(&str, &str); 30] = [
+    ("summary.json", "summary"),
+    ("asm_sunks.tsv", "intermediate"),
+    ("kmer_counts.tsv", "diagnostic"),
+    ("read_sunks.tsv", "intermediate"),
+    ("read_ctg_mapping.tsv", "intermediate"),
+    ("read_sunks_good.tsv", "intermediate"),
+    ("read_sunks_bad.tsv", "intermediate"),
+    ("inversions.bed", "output"),
+    ("translocation_candidates.tsv", "output"),
+    ("phase_switches.bed", "output"),
+    ("contig_spacing_stats.tsv", "output"),
+    ("contig_filenames.tsv", "per_contig_index"),
+    ("supported.bed", "output"),
+    ("sensitivity.bed", "output"),
+    ("false_support.tsv", "output"),
+    ("contained_reads.tsv", "output"),
+    ("origin_reads.tsv", "output"),
+    ("contig_summary.tsv", "output"),
+    ("unsupported.bed", "output"),
+    ("curation_targets.bed", "output"),
+    ("breakpoints.bedpe", "output"),
+    ("polish_targets.bed", "output"),
+    ("component_scales.bed", "output"),
+    ("read_components.tsv", "output"),
+    ("sunk_support.bedgraph", "output"),
+    ("read_intervals.bed", "output"),
+    ("read_trim_suggestions.tsv", "output"),
+    ("contig_ends.tsv", "output"),
+    ("filter_audit.tsv", "diagnostic"),
+    ("read_status.tsv", "diagnostic"),
+];
+
+/// Rows used to infer column types of tabular outputs.
+const SCHEMA_INFER_ROWS: usize = 100;
+
+/// Column of a tabular output.
+#[derive(Debug, Serialize)]
+pub struct ManifestColumn {
+    pub name: String,
+    pub dtype: String,
+}
+
+/// Output file produced by a run.
+#[derive(Debug, Serialize)]
+pub struct ManifestFile {
+    /// Path relative to the output directory, or absolute if outside it.
+    pub path: PathBuf,
+    /// ex. `output`, `intermediate`, or `per_contig`
+    pub role: String,
+    pub bytes: u64,
+    /// CRC-32 of the file contents as hex.
+    pub crc32: String,
+    /// Columns and their inferred types. Only set for TSV and BED outputs.
+    pub schema: Option<Vec<ManifestColumn>>,
+    /// Rows excluding the header and comments. Only set for TSV and BED outputs.
+    pub rows: Option<usize>,
+}
+
+impl ManifestFile {
+    /// Describe `path`, relative to `dir`.
+    pub fn new(dir: &Path, path: impl AsRef<Path>, role: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        let is_tabular = matches!(ext, Some("tsv" | "bed"));
+        // Column names of BED outputs are the last comment line. See [`crate::writers::BedWriter`].
+        let is_bed = ext == Some("bed");
+        let mut reader = BufReader::new(File::open(dir.join(path))?);
+        let mut hasher = crc32fast::Hasher::new();
+        let (mut bytes, mut lines, mut comments) = (0, 0, 0);
+        let mut at_line_start = true;
+        let mut buf = vec![0; 1 << 16];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            bytes += n as u64;
+            for b in &buf[..n] {
+                comments += (at_line_start && *b == b'#') as usize;
+                lines += (*b == b'\n') as usize;
+                at_line_start = *b == b'\n';
+            }
+        }
+        let bed_names: Option<Vec<String>> = if is_bed {
+            BufReader::new(File::open(dir.join(path))?)
+                .lines()
+                .map_while(|line| line.ok())
+                .take_while(|line| line.starts_with('#'))
+                .last()
+                .map(|line| line[1..].split('\t').map(str::to_owned).collect())
+        } else {
+            None
+        };
+        let n_rows = lines.saturating_sub(comments + usize::from(!is_bed));
+        let schema = if is_bed && n_rows == 0 {
+            bed_names.map(|names| {
+                names
+                    .into_iter()
+                    .map(|name| ManifestColumn {
+                        name,
+                        dtype: DataType::Null.to_string(),
+                    })
+                    .collect()
+            })
+        } else if is_tabular && bytes > 0 {
+            let mut df = CsvReadOptions::default()
+                .with_has_header(!is_bed)
+                .with_n_rows(Some(SCHEMA_INFER_ROWS))
+                .with_parse_options(
+                    CsvParseOptions::default()
+                        .with_separator(b'\t')
+                        .with_comment_prefix(Some("#")),
+                )
+                .try_into_reader_with_file_path(Some(dir.join(path)))?
+                .finish()?;
+            if let Some(names) = bed_names.filter(|names| names.len() == df.width()) {
+                df.set_column_names(names)?;
+            }
+            Some(
+                df.schema()
+                    .iter()
+                    .map(|(name, dtype)| ManifestColumn {
+                        name: name.to_string(),
+                        dtype: dtype.to_string(),
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            role: role.to_owned(),
+            bytes,
+            crc32: format!("{:08x}", hasher.finalize()),
+            rows: schema.as_ref().map(|_| n_rows),
+            schema,
+        })
+    }
+}
+
+/// Files produced by a run, written as `manifest.json` so downstream pipelines can check outputs before use.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    /// Status from [`RunSummary`]. Outputs of a partial run are only valid for its completed contigs.
+    pub status: RunStatus,
+    pub stages_completed: Vec<String>,
+    pub files: Vec<ManifestFile>,
+}
+
+impl Manifest {
+    /// Describe existing files produced by a run.
+    ///
+    /// # Arguments
+    /// * `dir`
+    ///     * Output directory of the run.
+    /// * `summary`
+    ///     * Summary of the run.
+    /// * `files`
+    ///     * `(path, role)` of each candidate output relative to `dir`. Paths that don't exist are skipped.
+    pub fn new<'a>(
+        dir: &Path,
+        summary: &RunSummary,
+        files: impl IntoIterator<Item = (PathBuf, &'a str)>,
+    ) -> Result<Self> {
+        let files = files
+            .into_iter()
+            .filter(|(path, _)| dir.join(path).is_file())
+            .map(|(path, role)| ManifestFile::new(dir, path, role))
+            .collect::<Result<Vec<ManifestFile>>>()?;
+        Ok(Self {
+            status: summary.status,
+            stages_completed: summary.stages_completed.clone(),
+            files,
+        })
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomic(path, |file| Ok(serde_json::to_writer_pretty(file, self)?))
+    }
+}
@@ -1,111 +1,908 @@
-use eyre::bail;
 use kmers::{self, Kmer, SimplePosIndex};
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
 
-use crate::io::Fasta;
+use crate::{
+    cancel::CancelToken,
+    error::{GavisunkError, Result},
+    io::{skip_malformed_record, write_tsv_to, Fasta, ScratchDir},
+    joins::{check_join_loss, count_unmatched},
+    minimizers::ContigSketches,
+    run_state::RunState,
+    sunk_index::{SunkHashIndex, SunkIndex, MAX_INDEX_KMER_SIZE},
+};
 use polars::prelude::*;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-fn map_sunks_to_seq<'a, 'b>(
+/// Find all positions of `sunks` in both orientations within a region of a sequence.
+///
+/// # Returns
+/// * `(ctg, sunk, pos, is_fwd)` for each hit with 1-based `pos` on the forward strand of the sequence.
+///     * `is_fwd` is `false` if the reverse complement of the SUNK was found.
+pub fn map_sunks_to_seq<'a, 'b>(
     sunks: &[&'a str],
     fname: &PathBuf,
     ctg: &'b str,
-    start: u32,
-    end: u32,
-) -> eyre::Result<Vec<(&'b str, &'a str, usize)>> {
+    start: u64,
+    end: u64,
+) -> Result<Vec<(&'b str, &'a str, usize, bool)>> {
     let mut fasta = Fasta::new(fname)?;
-    let rec = fasta.fetch(ctg, start, end)?;
+    let bases = fasta.fetch_bytes(ctg, start, end)?;
+    find_sunks(sunks, &bases, ctg)
+}
 
+/// Find all positions of `sunks` in both orientations within a sequence.
+///
+/// # Returns
+/// * `(ctg, sunk, pos, is_fwd)` for each hit with 1-based `pos`. See [`map_sunks_to_seq`].
+pub(crate) fn find_sunks<'a, 'b>(
+    sunks: &[&'a str],
+    seq: &[u8],
+    ctg: &'b str,
+) -> Result<Vec<(&'b str, &'a str, usize, bool)>> {
     let Some(kmer_size) = sunks.first().map(|k| k.len()) else {
-        bail!("No SUNKs given.")
+        return Err(GavisunkError::NoSunks);
     };
 
-    // Use kmer's simple positional index to generate all kmer position indices first.
-    // Add both fwd and reverse comp kmers.
+    // Use kmer's simple positional index to generate all forward kmer position indices first.
     let mut idx = SimplePosIndex::new(kmer_size);
-    idx.add_seq_both(rec.sequence());
+    idx.add_seq(seq);
 
-    // Then iterate thru all sunks and get their 1-based positions within the index.
-    Ok(sunks
-        .iter()
-        .flat_map(|sunk| {
-            idx.find(&Kmer::make(sunk).unwrap())
-                .iter()
-                .map(|pos| (ctg, *sunk, *pos + 1))
-        })
-        .collect())
+    // Then iterate thru all sunks and their reverse complements and get their 1-based positions within the index.
+    let mut hits = vec![];
+    for sunk in sunks.iter() {
+        let kmer = Kmer::make(sunk).unwrap();
+        let kmer_rc = kmer.rev_comp(kmer_size);
+        for (kmer, is_fwd) in [(kmer, true), (kmer_rc, false)] {
+            hits.extend(
+                idx.find(&kmer)
+                    .iter()
+                    .map(|pos| (ctg, *sunk, *pos + 1, is_fwd)),
+            );
+        }
+    }
+    Ok(hits)
+}
+
+/// Pre-assignment of reads to candidate contigs before mapping SUNKs.
+#[derive(Debug, Clone, Copy)]
+pub struct Prebin<'a> {
+    pub sketches: &'a ContigSketches,
+    /// Maximum candidate contigs per read.
+    pub max_candidates: usize,
+}
+
+/// How multiple hits of the same SUNK group within a read are resolved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DedupPolicy {
+    /// Keep the hit with the lowest assembly position.
+    #[default]
+    First,
+    /// Keep all hits.
+    All,
+    /// Keep the hit closest to the read's median offset band on the contig.
+    BestByBand,
+}
+
+/// Fewest bases mapped per task. Smaller tasks cost more in FASTA handles than they gain in balance.
+pub const MIN_TASK_BASES: u64 = 1_000_000;
+/// Tasks per thread so work-stealing can even out uneven tasks.
+const TASKS_PER_THREAD: u64 = 8;
+
+/// 1-based, inclusive read segments mapped together as a single task.
+pub(crate) type MapTask<'a> = Vec<(&'a str, u64, u64)>;
+
+/// Group reads into tasks of similar total bases, largest first.
+///
+/// Reads longer than a task are split into segments overlapping by `kmer_size - 1` so each k-mer is in exactly one
+/// segment. A single ultralong read then can't stall a worker while others idle.
+///
+/// # Arguments
+/// * `reads`
+///     * Read names and lengths.
+/// * `kmer_size`
+///     * kmer size.
+/// * `threads`
+///     * Number of worker threads.
+pub(crate) fn map_tasks<'a>(
+    reads: &[(&'a String, &u64)],
+    kmer_size: u64,
+    threads: usize,
+) -> Vec<MapTask<'a>> {
+    let total_bases: u64 = reads.iter().map(|(_, len)| **len).sum();
+    let task_bases = (total_bases / (threads.max(1) as u64 * TASKS_PER_THREAD)).max(MIN_TASK_BASES);
+
+    let mut segments: Vec<(&str, u64, u64)> = vec![];
+    for (read, len) in reads {
+        let mut st = 1;
+        loop {
+            let end = (st + task_bases - 1 + kmer_size - 1).min(**len);
+            segments.push((read.as_str(), st, end));
+            if end >= **len {
+                break;
+            }
+            st += task_bases;
+        }
+    }
+    // Pack the longest segments first so the smallest fill in at the end.
+    segments.sort_unstable_by(|a, b| (b.2 - b.1).cmp(&(a.2 - a.1)).then_with(|| a.cmp(b)));
+    let mut tasks: Vec<MapTask> = vec![];
+    let mut task_len = 0;
+    for segment in segments {
+        let segment_len = segment.2 - segment.1 + 1;
+        if tasks.is_empty() || task_len + segment_len > task_bases {
+            tasks.push(vec![]);
+            task_len = 0;
+        }
+        task_len += segment_len;
+        tasks.last_mut().unwrap().push(segment);
+    }
+    tasks
 }
 
 /// Map sunks from an assembly to reads.
 ///
+/// Reads that can't be fetched, ex. truncated records, are skipped with [`skip_malformed_record`].
+///
 /// # Arguments
 /// * `fa`
 ///     * Fasta file handle for reads.
 /// * `df_sunks`
 ///     * [`DataFrame`] with columns `[name, kmer, start, group]`
+/// * `batch_bases`
+///     * Maximum total bases of reads indexed concurrently. If [`None`], all reads are mapped at once.
+///     * Reads in a batch are mapped in tasks of similar size. See [`MIN_TASK_BASES`].
+///     * Hits are deduplicated by batch, as a read is never split across batches, so only one batch's raw hits are
+///       held at once.
+/// * `chunk_dir`
+///     * Directory a `read_sunks_chunks.*` directory is created in. Each batch's hits are written to it as
+///       `{batch}.tsv` and scanned from at the end, so memory is bound by the final [`DataFrame`] rather than also
+///       every batch's. It's removed once mapped or on error.
+///     * If [`None`] or there's a single batch, hits of each batch are kept in memory.
+/// * `dedup`
+///     * How multiple hits per `(read, ctg, group)` are resolved.
+/// * `prebin`
+///     * Only search each read segment for SUNKs of its candidate contigs. If [`None`], all SUNKs are searched.
+/// * `index`
+///     * Look up read k-mers in a memory-mapped index of `df_sunks` instead of a [`SunkHashIndex`] built for this call.
+///       `prebin` is ignored as lookups don't scale with the number of SUNKs.
+///     * Without either, read k-mers are looked up in a [`SunkHashIndex`]. If SUNKs can't be packed, ex. longer than
+///       [`crate::sunk_index::MAX_INDEX_KMER_SIZE`], each read segment is indexed and searched for every SUNK instead.
+/// * `state`
+///     * Run malformed reads are skipped and join losses are checked in.
+/// * `cancel`
+///     * Token checked before mapping each read segment.
 ///
 /// # Returns
 /// * [`DataFrame`] of SUNKs within reads from the assembly.
-///     * With columns `[seq, pos, name, start, group]`
+///     * With columns `[read, rpos, ctg, cpos, group, strand, hits]`
+///     * `strand` is `+` if the SUNK hit is in the same orientation in the read and contig and `-` otherwise.
+///     * `hits` is the number of hits of the SUNK group in the read before deduplication.
 pub fn map_sunks_to_reads(
     fa: Fasta,
     fa_lens: &HashMap<String, u64>,
     df_sunks: &DataFrame,
-) -> eyre::Result<DataFrame> {
-    let col_sunks = df_sunks.column("kmer")?;
-    let sunks: Vec<&str> = col_sunks.str()?.into_iter().flatten().collect();
-
-    let mapped_sunks: Vec<(&str, &str, usize)> = fa_lens
-        .par_iter()
-        .map(|(seq, len)| map_sunks_to_seq(&sunks, &fa.fname, seq, 1, *len as u32).unwrap())
-        .reduce(Vec::new, |mut a, b| {
-            a.extend(b);
-            a
+    batch_bases: Option<u64>,
+    chunk_dir: Option<&Path>,
+    dedup: DedupPolicy,
+    prebin: Option<Prebin>,
+    index: Option<&SunkIndex>,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<DataFrame> {
+    let search = SunkSearch::new(df_sunks, dedup, prebin, index, state)?;
+
+    // Group reads into batches so only a bounded number of read bases are indexed at once.
+    let mut batches: Vec<Vec<(&String, &u64)>> = vec![vec![]];
+    let mut batch_len = 0;
+    for (seq, len) in fa_lens.iter() {
+        if batch_bases.is_some_and(|max_bases| batch_len + len > max_bases) {
+            batches.push(vec![]);
+            batch_len = 0;
+        }
+        batch_len += len;
+        batches.last_mut().unwrap().push((seq, len));
+    }
+
+    let mut batch_hits = BatchHits::new(chunk_dir.filter(|_| batches.len() > 1))?;
+    if let Some(dir) = batch_hits.chunk_dir.as_ref().map(ScratchDir::path) {
+        log::info!(
+            "Mapping reads in {} batches with hits written to {dir:?}.",
+            batches.len()
+        );
+    }
+    for batch in batches.iter() {
+        batch_hits.push(search.map_batch(&fa, batch, cancel)?)?;
+    }
+    let df_final = batch_hits.finish()?;
+    log::info!("Total SUNKs mapped: {}", df_final.height());
+    Ok(df_final)
+}
+
+/// Map sunks from an assembly to reads streamed from a file that can't be indexed, ex. unaligned BAM or CRAM.
+///
+/// Records are read in batches of up to `batch_bases` held in memory, each mapped as in [`map_sunks_to_reads`].
+/// Malformed records are skipped with [`skip_malformed_record`].
+///
+/// # Arguments
+/// * `path_reads`
+///     * Reads file. Any format read by [`Fasta::for_each_read`].
+/// * `batch_bases`
+///     * Maximum total bases of reads held and indexed at once. If [`None`], all reads are held at once.
+/// * `chunk_dir`
+///     * Directory a directory of each batch's hits is created in. See [`map_sunks_to_reads`]. Only used with `batch_bases`.
+/// * Other arguments are as in [`map_sunks_to_reads`].
+///
+/// # Returns
+/// * [`DataFrame`] of SUNKs within reads from the assembly. See [`map_sunks_to_reads`].
+pub fn map_sunks_to_read_records(
+    path_reads: &Path,
+    df_sunks: &DataFrame,
+    batch_bases: Option<u64>,
+    chunk_dir: Option<&Path>,
+    dedup: DedupPolicy,
+    prebin: Option<Prebin>,
+    index: Option<&SunkIndex>,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<DataFrame> {
+    let search = SunkSearch::new(df_sunks, dedup, prebin, index, state)?;
+    let mut batch_hits = BatchHits::new(chunk_dir.filter(|_| batch_bases.is_some()))?;
+    let map_seqs = |batch_hits: &mut BatchHits, seqs: HashMap<String, Vec<u8>>| -> Result<()> {
+        let fa = Fasta::from_seqs(path_reads, seqs);
+        let lens = fa.lengths();
+        let batch: Vec<(&String, &u64)> = lens.iter().collect();
+        batch_hits.push(search.map_batch(&fa, &batch, cancel)?)
+    };
+
+    log::info!("Mapping reads streamed from {path_reads:?}.");
+    let (mut seqs, mut n_bases) = (HashMap::new(), 0);
+    Fasta::for_each_read(path_reads, state, |name, seq| {
+        cancel.check()?;
+        if batch_bases.is_some_and(|max_bases| n_bases + seq.len() as u64 > max_bases)
+            && !seqs.is_empty()
+        {
+            map_seqs(&mut batch_hits, std::mem::take(&mut seqs))?;
+            n_bases = 0;
+        }
+        if seqs.contains_key(name) {
+            log::warn!(
+                "Records in {path_reads:?} have the same name, {name:?}. Only the first is used."
+            );
+            return Ok(());
+        }
+        n_bases += seq.len() as u64;
+        seqs.insert(name.to_owned(), seq.to_vec());
+        Ok(())
+    })?;
+    // An empty batch still gives the columns of the hits.
+    if !seqs.is_empty() || batch_hits.is_empty() {
+        map_seqs(&mut batch_hits, seqs)?;
+    }
+    let df_final = batch_hits.finish()?;
+    log::info!("Total SUNKs mapped: {}", df_final.height());
+    Ok(df_final)
+}
+
+/// SUNKs searched in every batch of reads and how read k-mers are looked up.
+struct SunkSearch<'a> {
+    df_sunks: &'a DataFrame,
+    sunks: Vec<&'a str>,
+    /// SUNKs by contig. Only with `prebin`.
+    ctg_sunks: HashMap<&'a str, Vec<&'a str>>,
+    kmer_size: u64,
+    dedup: DedupPolicy,
+    prebin: Option<Prebin<'a>>,
+    index: Option<&'a SunkIndex>,
+    hash_index: Option<SunkHashIndex>,
+    state: &'a RunState,
+}
+
+impl<'a> SunkSearch<'a> {
+    fn new(
+        df_sunks: &'a DataFrame,
+        dedup: DedupPolicy,
+        prebin: Option<Prebin<'a>>,
+        index: Option<&'a SunkIndex>,
+        state: &'a RunState,
+    ) -> Result<Self> {
+        let col_sunks = df_sunks.column("kmer")?;
+        let sunks: Vec<&str> = col_sunks.str()?.into_iter().flatten().collect();
+        let mut ctg_sunks: HashMap<&str, Vec<&str>> = HashMap::new();
+        if prebin.is_some() {
+            for (ctg, sunk) in df_sunks
+                .column("ctg")?
+                .str()?
+                .iter()
+                .zip(col_sunks.str()?.iter())
+            {
+                if let (Some(ctg), Some(sunk)) = (ctg, sunk) {
+                    ctg_sunks.entry(ctg).or_default().push(sunk);
+                }
+            }
+        }
+        let Some(kmer_size) = sunks.first().map(|sunk| sunk.len() as u64) else {
+            return Err(GavisunkError::NoSunks);
+        };
+        // Scan each read once for all SUNKs rather than searching each read for every SUNK.
+        let hash_index = match (index, prebin) {
+            (None, None) => {
+                let hash_index = SunkHashIndex::new(&sunks);
+                if hash_index.is_none() && kmer_size > MAX_INDEX_KMER_SIZE as u64 {
+                    log::warn!(
+                        "{kmer_size} bp SUNKs are too long to pack. Searching each read for every SUNK instead."
+                    );
+                } else if hash_index.is_none() {
+                    log::warn!("SUNKs can't be packed into a hash index. Searching each read for every SUNK instead.");
+                }
+                hash_index
+            }
+            _ => None,
+        };
+        Ok(Self {
+            df_sunks,
+            sunks,
+            ctg_sunks,
+            kmer_size,
+            dedup,
+            prebin,
+            index,
+            hash_index,
+            state,
         })
-        .into_iter()
-        .collect();
+    }
+
+    /// Map SUNKs to a batch of reads in tasks of similar size. See [`map_tasks`].
+    ///
+    /// # Returns
+    /// * [`DataFrame`] of deduplicated hits. See [`map_sunks_to_reads`].
+    fn map_batch(
+        &self,
+        fa: &Fasta,
+        batch: &[(&String, &u64)],
+        cancel: &CancelToken,
+    ) -> Result<DataFrame> {
+        // SUNKs found with `index` are unpacked so they aren't borrowed from `df_sunks`.
+        let batch_mapped_sunks = map_tasks(batch, self.kmer_size, rayon::current_num_threads())
+            .par_iter()
+            .map(|task| {
+                let mut fasta = fa.try_clone()?;
+                let mut task_hits = vec![];
+                for (seq, st, end) in task {
+                    cancel.check()?;
+                    let bases = match fasta.fetch_bytes(seq, *st, *end) {
+                        Ok(bases) => bases,
+                        // ex. a truncated read. The handle may be left mid-record so reopen it.
+                        Err(GavisunkError::Io(err)) => {
+                            skip_malformed_record(&fa.fname, seq, err, self.state)?;
+                            fasta = fa.try_clone()?;
+                            continue;
+                        }
+                        Err(err) => return Err(err),
+                    };
+                    if let Some(index) = self.index {
+                        task_hits.extend(index.find(&bases).into_iter().map(
+                            |(pos, idx, is_fwd)| {
+                                let sunk = Cow::Owned(index.kmer(idx));
+                                (*seq, sunk, pos + *st as usize - 1, is_fwd)
+                            },
+                        ));
+                        continue;
+                    }
+                    if let Some(hash_index) = &self.hash_index {
+                        task_hits.extend(hash_index.find(&bases).into_iter().map(
+                            |(pos, idx, is_fwd)| {
+                                (
+                                    *seq,
+                                    Cow::Borrowed(self.sunks[idx]),
+                                    pos + *st as usize - 1,
+                                    is_fwd,
+                                )
+                            },
+                        ));
+                        continue;
+                    }
+                    let candidate_sunks: Vec<&str>;
+                    let seq_sunks = match self.prebin {
+                        Some(prebin) => {
+                            candidate_sunks = prebin
+                                .sketches
+                                .candidates(&bases, prebin.max_candidates)
+                                .into_iter()
+                                .flat_map(|ctg| {
+                                    self.ctg_sunks.get(ctg).into_iter().flatten().copied()
+                                })
+                                .collect();
+                            &candidate_sunks
+                        }
+                        None => &self.sunks,
+                    };
+                    if seq_sunks.is_empty() {
+                        continue;
+                    }
+                    // Hit positions are relative to the segment.
+                    task_hits.extend(find_sunks(seq_sunks, &bases, seq)?.into_iter().map(
+                        |(seq, sunk, pos, is_fwd)| {
+                            (seq, Cow::Borrowed(sunk), pos + *st as usize - 1, is_fwd)
+                        },
+                    ));
+                }
+                Ok(task_hits)
+            })
+            .try_reduce(Vec::new, |mut a, b| {
+                a.extend(b);
+                Ok(a)
+            })?;
+        let mapped_sunks = batch_mapped_sunks
+            .iter()
+            .map(|(read, sunk, pos, is_fwd)| (*read, sunk.as_ref(), *pos, *is_fwd))
+            .collect();
+        sunk_hits_df(mapped_sunks, self.df_sunks, self.dedup, self.state)
+    }
+}
+
+/// Hits of each batch of reads, kept in memory or written to a chunk each.
+struct BatchHits {
+    /// Directory of chunks, removed when dropped. If [`None`], hits are kept in memory.
+    chunk_dir: Option<ScratchDir>,
+    batch_dfs: Vec<LazyFrame>,
+    chunks: Vec<(PathBuf, Schema)>,
+}
+
+impl BatchHits {
+    /// Hits written to chunks in a new directory in `parent_dir`, or kept in memory if [`None`].
+    fn new(parent_dir: Option<&Path>) -> Result<Self> {
+        Ok(Self {
+            chunk_dir: parent_dir
+                .map(|dir| ScratchDir::new_in(dir, "read_sunks_chunks"))
+                .transpose()?,
+            batch_dfs: vec![],
+            chunks: vec![],
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.batch_dfs.is_empty() && self.chunks.is_empty()
+    }
 
+    fn push(&mut self, mut df_batch: DataFrame) -> Result<()> {
+        match &self.chunk_dir {
+            Some(dir) => {
+                let path = dir.path().join(format!("{}.tsv", self.chunks.len()));
+                write_tsv_to(&mut df_batch, BufWriter::new(File::create(&path)?))?;
+                self.chunks.push((path, df_batch.schema()));
+            }
+            None => self.batch_dfs.push(df_batch.lazy()),
+        }
+        Ok(())
+    }
+
+    /// All hits sorted by read and position.
+    fn finish(self) -> Result<DataFrame> {
+        let mut batch_dfs = self.batch_dfs;
+        for (path, schema) in self.chunks {
+            batch_dfs.push(
+                LazyCsvReader::new(path)
+                    .with_has_header(true)
+                    .with_separator(b'\t')
+                    .with_schema(Some(Arc::new(schema)))
+                    .finish()?,
+            );
+        }
+        let df_final = concat(batch_dfs, UnionArgs::default())?
+            .sort(["read", "rpos"], Default::default())
+            .collect()?;
+        drop(self.chunk_dir);
+        Ok(df_final)
+    }
+}
+
+/// Join SUNK hits in reads to assembly SUNKs and deduplicate them.
+///
+/// # Arguments
+/// * `mapped_sunks`
+///     * `(read, sunk, pos, is_fwd)` of each hit. See [`map_sunks_to_seq`].
+/// * `df_sunks`
+///     * [`DataFrame`] with columns `[ctg, cpos, kmer, strand, group]`
+/// * `dedup`
+///     * How multiple hits per `(read, ctg, group)` are resolved.
+/// * `state`
+///     * Run the join loss is checked in.
+///
+/// # Returns
+/// * [`DataFrame`] of SUNKs within reads. See [`map_sunks_to_reads`].
+pub(crate) fn sunk_hits_df(
+    mapped_sunks: Vec<(&str, &str, usize, bool)>,
+    df_sunks: &DataFrame,
+    dedup: DedupPolicy,
+    state: &RunState,
+) -> Result<DataFrame> {
     let mut reads = Vec::with_capacity(mapped_sunks.len());
     let mut kmers = Vec::with_capacity(mapped_sunks.len());
     let mut positions = Vec::with_capacity(mapped_sunks.len());
-    for (read, kmer, pos) in mapped_sunks.into_iter() {
+    let mut read_strands = Vec::with_capacity(mapped_sunks.len());
+    for (read, kmer, pos, is_fwd) in mapped_sunks.into_iter() {
         reads.push(read);
         kmers.push(kmer);
         positions.push(pos as u64);
+        read_strands.push(if is_fwd { "+" } else { "-" });
     }
 
-    let df_final = DataFrame::new(vec![
+    let df_hits = DataFrame::new(vec![
         Column::new("read".into(), reads),
         Column::new("kmer".into(), kmers),
         Column::new("rpos".into(), positions),
+        Column::new("rstrand".into(), read_strands),
     ])?
-    .join(df_sunks, ["kmer"], ["kmer"], JoinArgs::new(JoinType::Left))?
-    .lazy()
-    .group_by([col("read"), col("ctg"), col("group")])
-    .agg([
-        col("cpos").first(),
-        col("rpos").sort_by(["cpos"], Default::default()).first(),
-    ])
-    .select([
-        col("read"),
-        col("rpos"),
-        col("ctg"),
-        col("cpos"),
-        col("group"),
-    ])
-    .sort(["read", "rpos"], Default::default())
-    .collect()?;
-
-    log::info!("Total SUNKs mapped: {}", df_final.shape().0);
+    .join(df_sunks, ["kmer"], ["kmer"], JoinArgs::new(JoinType::Left))?;
+    check_join_loss(
+        "read SUNK hits x assembly SUNKs",
+        "SUNK kmers differ from those searched, ex. by case",
+        df_hits.height(),
+        df_hits.column("ctg")?.null_count(),
+        state,
+    );
+    let lf_hits = df_hits.lazy().with_columns([
+        col("rpos").cast(DataType::Int64),
+        col("cpos").cast(DataType::Int64),
+        // Hit is in the same orientation if the SUNK is on the same strand in the read and contig.
+        when(col("rstrand").eq(col("strand")))
+            .then(lit("+"))
+            .otherwise(lit("-"))
+            .alias("strand"),
+    ]);
+    dedup_hits(lf_hits, dedup)
+}
 
+/// Count and deduplicate hits of each SUNK group within a read.
+///
+/// # Arguments
+/// * `lf_hits`
+///     * [`LazyFrame`] of hits with columns `[read, rpos, ctg, cpos, group, strand, ...]`. Positions are [`i64`].
+/// * `dedup`
+///     * How multiple hits per `(read, ctg, group)` are resolved.
+///
+/// # Returns
+/// * [`DataFrame`] of SUNKs within reads. See [`map_sunks_to_reads`].
+pub(crate) fn dedup_hits(lf_hits: LazyFrame, dedup: DedupPolicy) -> Result<DataFrame> {
+    let lf_hits = lf_hits
+        // Number of hits of a SUNK group within a read.
+        .with_column(
+            col("rpos")
+                .len()
+                .over(["read", "ctg", "group"])
+                .cast(DataType::UInt64)
+                .alias("hits"),
+        );
+
+    let by_hit = [col("read"), col("ctg"), col("group")];
+    let first_by_cpos = || {
+        [
+            col("cpos").sort_by(["cpos"], Default::default()).first(),
+            col("rpos").sort_by(["cpos"], Default::default()).first(),
+            col("strand").sort_by(["cpos"], Default::default()).first(),
+            col("hits").first(),
+        ]
+    };
+    let lf_dedup = match dedup {
+        DedupPolicy::First => lf_hits.group_by(by_hit).agg(first_by_cpos()),
+        DedupPolicy::All => lf_hits,
+        DedupPolicy::BestByBand => lf_hits
+            // Distance from the diagonal (fwd) or anti-diagonal (rev) band centered on the median offset.
+            .with_column(
+                min_horizontal([
+                    ((col("cpos") - col("rpos"))
+                        - (col("cpos") - col("rpos")).median().over(["read", "ctg"]))
+                    .abs(),
+                    ((col("cpos") + col("rpos"))
+                        - (col("cpos") + col("rpos")).median().over(["read", "ctg"]))
+                    .abs(),
+                ])?
+                .alias("band_dist"),
+            )
+            .filter(
+                col("band_dist")
+                    .eq(col("band_dist").min())
+                    .over(["read", "ctg", "group"]),
+            )
+            .group_by(by_hit)
+            .agg(first_by_cpos()),
+    };
+
+    let df_final = lf_dedup
+        .select([
+            col("read"),
+            col("rpos"),
+            col("ctg"),
+            col("cpos"),
+            col("group"),
+            col("strand"),
+            col("hits"),
+        ])
+        .sort(["read", "rpos"], Default::default())
+        .collect()?;
     Ok(df_final)
 }
 
+/// Read lengths as a [`DataFrame`] with columns `[read, read_length]`.
+///
+/// Built once per run and joined lazily by contig rather than rebuilt from the map for each contig.
+pub fn read_lengths_df(read_lens: &HashMap<String, u64>) -> Result<DataFrame> {
+    let (reads, lens): (Vec<&str>, Vec<u64>) = read_lens
+        .iter()
+        .map(|(read, len)| (read.as_str(), *len))
+        .unzip();
+    Ok(DataFrame::new(vec![
+        Column::new("read".into(), reads),
+        Column::new("read_length".into(), lens),
+    ])?)
+}
+
 pub fn get_good_read_sunks(
     df_read_sunks: &DataFrame,
     df_best_reads_asm: &DataFrame,
-) -> eyre::Result<DataFrame> {
+    state: &RunState,
+) -> Result<DataFrame> {
+    check_join_loss(
+        "read assignments x read SUNKs",
+        "read SUNKs and assignments are from different runs",
+        df_best_reads_asm.height(),
+        count_unmatched(df_best_reads_asm, df_read_sunks, &["read", "ctg"])?,
+        state,
+    );
     Ok(df_read_sunks
         .inner_join(df_best_reads_asm, ["read", "ctg"], ["read", "ctg"])?
         .select(["read", "rpos", "ctg", "cpos", "group"])?)
 }
+
+/// Reverse complement of a sequence. Bases other than `ACGT` are kept as is.
+fn revcomp(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|nt| match nt.to_ascii_uppercase() {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            nt => nt,
+        })
+        .collect()
+}
+
+/// Check a sample of read SUNK hits by comparing the read sequence at each hit to the SUNK.
+///
+/// A debugging cross-check of the mapping stage for off-by-one or strand errors. The read subsequence at `rpos` must
+/// equal the SUNK, or its reverse complement if the hit is on the opposite strand of the read.
+///
+/// # Arguments
+/// * `fa`
+///     * Reads FASTA.
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNK hits with columns `[read, rpos, ctg, cpos, strand, ...]`. See [`map_sunks_to_reads`].
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, kmer, strand, ...]`
+/// * `n_hits`
+///     * Number of hits checked. Hits are sampled by hash so the same hits are checked across runs.
+///
+/// # Returns
+/// * Number of hits checked or [`GavisunkError::MismatchedHits`] if any don't match.
+pub fn validate_read_hits(
+    fa: &Fasta,
+    df_read_sunks: &DataFrame,
+    df_asm_sunks: &DataFrame,
+    n_hits: usize,
+) -> Result<usize> {
+    let read_rpos = df_read_sunks.column("rpos")?.cast(&DataType::Int64)?;
+    let read_cpos = df_read_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let mut hits: Vec<(u64, &str, i64, &str, i64, bool)> = df_read_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(read_rpos.i64()?.iter())
+        .zip(df_read_sunks.column("ctg")?.str()?.iter())
+        .zip(read_cpos.i64()?.iter())
+        .zip(df_read_sunks.column("strand")?.str()?.iter())
+        .flat_map(|((((read, rpos), ctg), cpos), strand)| {
+            let (read, rpos, ctg, cpos) = (read?, rpos?, ctg?, cpos?);
+            let mut hasher = DefaultHasher::new();
+            (read, rpos, ctg, cpos).hash(&mut hasher);
+            Some((hasher.finish(), read, rpos, ctg, cpos, strand? == "+"))
+        })
+        .collect();
+    hits.sort_unstable();
+    hits.truncate(n_hits);
+
+    // SUNK and its contig strand of each sampled hit.
+    let keys: HashSet<(&str, i64)> = hits.iter().map(|hit| (hit.3, hit.4)).collect();
+    let asm_cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let asm_sunks: HashMap<(&str, i64), (&str, bool)> = df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(asm_cpos.i64()?.iter())
+        .zip(df_asm_sunks.column("kmer")?.str()?.iter())
+        .zip(df_asm_sunks.column("strand")?.str()?.iter())
+        .flat_map(|(((ctg, cpos), kmer), strand)| Some(((ctg?, cpos?), (kmer?, strand? == "+"))))
+        .filter(|(key, _)| keys.contains(key))
+        .collect();
+
+    let mut fasta = fa.try_clone()?;
+    let (mut mismatched, mut example) = (0, String::new());
+    for (_, read, rpos, ctg, cpos, is_same_strand) in &hits {
+        let Some((kmer, is_ctg_fwd)) = asm_sunks.get(&(*ctg, *cpos)) else {
+            continue;
+        };
+        let end = *rpos + kmer.len() as i64 - 1;
+        let read_seq = fasta
+            .fetch_bytes(read, u64::try_from(*rpos)?, u64::try_from(end)?)?
+            .to_ascii_uppercase();
+        // The SUNK was found as is in the read if it's on the same strand of the read as the contig.
+        let expected = if is_same_strand == is_ctg_fwd {
+            kmer.as_bytes().to_ascii_uppercase()
+        } else {
+            revcomp(kmer.as_bytes())
+        };
+        if read_seq != expected {
+            if mismatched == 0 {
+                example = format!(
+                    "{read}:{rpos}-{end} is {} but SUNK {ctg}:{cpos} is {}",
+                    String::from_utf8_lossy(&read_seq),
+                    String::from_utf8_lossy(&expected)
+                );
+            }
+            mismatched += 1;
+        }
+    }
+    if mismatched > 0 {
+        return Err(GavisunkError::MismatchedHits {
+            mismatched,
+            checked: hits.len(),
+            example,
+        });
+    }
+    Ok(hits.len())
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use polars::prelude::*;
+
+    use super::{
+        map_sunks_to_reads, map_tasks, revcomp, validate_read_hits, DedupPolicy, MIN_TASK_BASES,
+    };
+    use crate::{
+        cancel::CancelToken,
+        error::GavisunkError,
+        io::{Fasta, ScratchDir},
+        run_state::RunState,
+    };
+
+    #[test]
+    fn test_map_tasks() {
+        let names: Vec<String> = (0..4).map(|i| format!("read_{i}")).collect();
+        let lens = [2 * MIN_TASK_BASES + 10, 100, 200, 300];
+        let reads: Vec<(&String, &u64)> = names.iter().zip(lens.iter()).collect();
+        let tasks = map_tasks(&reads, 20, 4);
+
+        // Ultralong read is split into overlapping segments, each its own task.
+        let segments: Vec<(u64, u64)> = tasks
+            .iter()
+            .flatten()
+            .filter(|(read, _, _)| *read == "read_0")
+            .map(|(_, st, end)| (*st, *end))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (1, MIN_TASK_BASES + 19),
+                (MIN_TASK_BASES + 1, 2 * MIN_TASK_BASES + 19),
+                (2 * MIN_TASK_BASES + 1, 2 * MIN_TASK_BASES + 10)
+            ]
+        );
+        // Short reads are packed with the last segment.
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[2].len(), 4);
+    }
+
+    #[test]
+    fn test_map_sunks_to_reads_chunked() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_map_chunked").unwrap();
+        let path = dir.path().join("reads.fa");
+        std::fs::write(
+            &path,
+            ">read1\nACGTTGCAAGGCTTAACCGG\n>read2\nCCGGTTAAGCCTTGCAACGT\n>read3\nTTTTTTTTTT\n",
+        )
+        .unwrap();
+        let fa = Fasta::new(&path).unwrap();
+        let lens = fa.lengths();
+        let df_sunks = df!(
+            "ctg" => ["ctg", "ctg"],
+            "cpos" => [11i64, 31],
+            "kmer" => ["GCAAGGCT", "TTAACCGG"],
+            "strand" => ["+", "+"],
+            "group" => [1i64, 2],
+        )
+        .unwrap();
+        let cancel = CancelToken::new();
+        let map = |chunk_dir: Option<&std::path::Path>| {
+            map_sunks_to_reads(
+                Fasta::new(&path).unwrap(),
+                &lens,
+                &df_sunks,
+                // A batch per read.
+                Some(20),
+                chunk_dir,
+                DedupPolicy::First,
+                None,
+                None,
+                &RunState::new(),
+                &cancel,
+            )
+            .unwrap()
+        };
+        let chunk_dir = dir.path().join("chunks");
+        let df_chunked = map(Some(&chunk_dir));
+        let df_in_memory = map(None);
+        assert_eq!(df_chunked.height(), 4);
+        assert!(df_chunked.equals(&df_in_memory));
+        assert_eq!(std::fs::read_dir(&chunk_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_validate_read_hits() {
+        let read = b"ACGTTGCAAGGCTTAACCGGTATCGATCGGATCCATGCAAGTTC";
+        let dir =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_validate_hits").unwrap();
+        let path = dir.path().join("read.fa");
+        let mut fh = std::fs::File::create(&path).unwrap();
+        writeln!(fh, ">read").unwrap();
+        fh.write_all(read).unwrap();
+        writeln!(fh).unwrap();
+        let fa = Fasta::new(&path).unwrap();
+
+        // A SUNK on the forward strand of the contig found as is, and one on the reverse found as its revcomp.
+        let sunk_2 = String::from_utf8(revcomp(&read[20..30])).unwrap();
+        let df_asm_sunks = df!(
+            "ctg" => ["ctg", "ctg"],
+            "cpos" => [101i64, 201],
+            "kmer" => [std::str::from_utf8(&read[5..15]).unwrap(), &sunk_2],
+            "strand" => ["+", "-"],
+        )
+        .unwrap();
+        let df_read_sunks = df!(
+            "read" => ["read", "read"],
+            "rpos" => [6i64, 21],
+            "ctg" => ["ctg", "ctg"],
+            "cpos" => [101i64, 201],
+            "strand" => ["+", "+"],
+        )
+        .unwrap();
+        assert_eq!(
+            validate_read_hits(&fa, &df_read_sunks, &df_asm_sunks, 10).unwrap(),
+            2
+        );
+
+        // Off-by-one read position.
+        let df_read_sunks = df_read_sunks
+            .lazy()
+            .with_column(col("rpos") + lit(1))
+            .collect()
+            .unwrap();
+        assert!(matches!(
+            validate_read_hits(&fa, &df_read_sunks, &df_asm_sunks, 10),
+            Err(GavisunkError::MismatchedHits {
+                mismatched: 2,
+                checked: 2,
+                ..
+            })
+        ));
+    }
+}
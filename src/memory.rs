@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::error::{GavisunkError, Result};
+
+/// Approximate bytes per k-mer entry in the k-mer count map. Only the canonical k-mer is stored.
+const KMER_COUNT_BYTES_PER_BASE: u64 = 48;
+/// Approximate bytes per read base in the per-read positional k-mer index. Both orientations are stored.
+const READ_INDEX_BYTES_PER_BASE: u64 = 2 * 40;
+/// Approximate peak bytes used by the graph stage for a single contig.
+const GRAPH_BYTES_PER_CONTIG: u64 = 2 * 1024 * 1024 * 1024;
+/// Most buckets k-mers are counted in. Each is an open file and a buffer per contig thread while spilling.
+pub const MAX_KMER_BUCKETS: usize = 1024;
+
+/// Parse a human-readable memory size. ex. `64G`, `512M`, `1024`
+///
+/// Suffixes `K`, `M`, `G`, and `T` are powers of 1024 and are case-insensitive. An optional trailing `B` is ignored.
+/// Sizes must be at least one byte.
+pub fn parse_mem_size(size: &str) -> Result<u64> {
+    let size = size.trim().to_uppercase();
+    let size = size.strip_suffix('B').unwrap_or(&size);
+    let (num, mult): (&str, u64) = match size.chars().last() {
+        Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        Some('T') => (&size[..size.len() - 1], 1 << 40),
+        Some(_) => (size, 1),
+        None => return Err(GavisunkError::InvalidMemorySize(size.to_owned())),
+    };
+    match num.trim().parse::<f64>().map(|num| num * mult as f64) {
+        Ok(bytes) if bytes.is_finite() && bytes >= 1.0 => Ok(bytes as u64),
+        _ => Err(GavisunkError::InvalidMemorySize(size.to_owned())),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmerCounting {
+    InMemory,
+    /// Spill k-mers into this many buckets and count each separately.
+    Partitioned(usize),
+}
+
+/// Staging decisions derived from a single memory budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StagePlan {
+    pub kmer_counting: KmerCounting,
+    /// Maximum total read bases mapped concurrently. [`None`] if unbounded.
+    pub read_batch_bases: Option<u64>,
+    /// Number of contigs processed concurrently in the graph stage.
+    pub graph_concurrency: usize,
+}
+
+impl StagePlan {
+    /// Decide how to run each stage given a memory budget.
+    ///
+    /// Half of the budget is reserved for k-mer counting, a quarter for concurrently indexed reads,
+    /// and the full budget is divided among contigs in the graph stage since stages don't overlap.
+    ///
+    /// k-mers are counted in at most [`MAX_KMER_BUCKETS`] buckets, so counting takes at least `1 / MAX_KMER_BUCKETS`
+    /// of its in-memory size, about 0.05 bytes per assembly base, however small the budget.
+    ///
+    /// # Arguments
+    /// * `max_memory`
+    ///     * Memory budget in bytes. If [`None`], everything is in-memory and uses all threads.
+    /// * `asm_lens`
+    ///     * Assembly contig lengths.
+    /// * `threads`
+    ///     * Number of available threads.
+    pub fn new(max_memory: Option<u64>, asm_lens: &HashMap<String, u64>, threads: usize) -> Self {
+        let Some(max_memory) = max_memory else {
+            return Self {
+                kmer_counting: KmerCounting::InMemory,
+                read_batch_bases: None,
+                graph_concurrency: threads,
+            };
+        };
+        let asm_bases: u64 = asm_lens.values().sum();
+        let kmer_budget = std::cmp::max(max_memory / 2, 1);
+        let kmer_mem = asm_bases * KMER_COUNT_BYTES_PER_BASE;
+        let kmer_counting = if kmer_mem <= kmer_budget {
+            KmerCounting::InMemory
+        } else {
+            let n_buckets = kmer_mem.div_ceil(kmer_budget).min(MAX_KMER_BUCKETS as u64);
+            KmerCounting::Partitioned(n_buckets as usize)
+        };
+        let read_batch_bases = std::cmp::max(max_memory / 4 / READ_INDEX_BYTES_PER_BASE, 1);
+        let graph_concurrency =
+            ((max_memory / GRAPH_BYTES_PER_CONTIG) as usize).clamp(1, std::cmp::max(threads, 1));
+        Self {
+            kmer_counting,
+            read_batch_bases: Some(read_batch_bases),
+            graph_concurrency,
+        }
+    }
+}
+
+impl Display for StagePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kmer_counting = match self.kmer_counting {
+            KmerCounting::InMemory => "in-memory".to_owned(),
+            KmerCounting::Partitioned(n) => format!("partitioned into {n} buckets"),
+        };
+        let read_batch = self
+            .read_batch_bases
+            .map(|bases| format!("{bases} bases"))
+            .unwrap_or_else(|| "unbounded".to_owned());
+        write!(
+            f,
+            "k-mer counting: {kmer_counting}, read batch: {read_batch}, concurrent contigs: {}",
+            self.graph_concurrency
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{parse_mem_size, KmerCounting, StagePlan, MAX_KMER_BUCKETS};
+
+    #[test]
+    fn test_parse_mem_size() {
+        assert_eq!(parse_mem_size("64G").unwrap(), 64 << 30);
+        assert_eq!(parse_mem_size("512mb").unwrap(), 512 << 20);
+        assert_eq!(parse_mem_size("1024").unwrap(), 1024);
+        assert_eq!(parse_mem_size("1.5K").unwrap(), 1536);
+        for size in ["", "0", "0G", "-1G", "0.5", "NaN", "inf", "G", "abc"] {
+            assert!(parse_mem_size(size).is_err(), "{size}");
+        }
+    }
+
+    #[test]
+    fn test_stage_plan_caps_buckets() {
+        let asm_lens = HashMap::from([("ctg1".to_owned(), 3_000_000_000)]);
+        let plan = StagePlan::new(Some(1 << 20), &asm_lens, 8);
+        assert_eq!(
+            plan.kmer_counting,
+            KmerCounting::Partitioned(MAX_KMER_BUCKETS)
+        );
+        assert_eq!(plan.graph_concurrency, 1);
+        let plan = StagePlan::new(Some(1 << 40), &asm_lens, 8);
+        assert_eq!(plan.kmer_counting, KmerCounting::InMemory);
+    }
+}
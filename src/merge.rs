@@ -0,0 +1,445 @@
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+
+use crate::{
+    contained::{CONTAINED_SUPPORT, SUNK_GRAPH_SUPPORT},
+    error::Result,
+    intervals::{merge, subtract},
+};
+
+/// Vertically concatenate per-contig [`DataFrame`]s.
+///
+/// # Arguments
+/// * `dfs`
+///     * Per-contig [`DataFrame`]s with identical schemas.
+/// * `schema`
+///     * Schema of output if no [`DataFrame`]s given.
+pub fn concat_dfs(dfs: Vec<DataFrame>, schema: &Schema) -> Result<DataFrame> {
+    let mut dfs = dfs.into_iter();
+    let Some(mut df_all) = dfs.next() else {
+        return Ok(DataFrame::empty_with_schema(schema));
+    };
+    for df in dfs {
+        df_all.vstack_mut(&df)?;
+    }
+    df_all.align_chunks();
+    Ok(df_all)
+}
+
+/// Get the interval of each read covered by its largest consistent SUNK component.
+///
+/// Segments of a read outside its interval aren't validated by SUNKs, so chimeric reads can be trimmed or split to
+/// the interval before reassembly.
+///
+/// # Arguments
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in supported components with columns `[ctg, read, rpos, proj_cpos, offset_dev, ...]`
+/// * `kmer_size`
+///     * kmer size. Intervals end at the last base of the last SUNK.
+///
+/// # Returns
+/// * [`DataFrame`] of read intervals with columns `[read, st, end, ctg, ctg_st, ctg_end, strand, sunks]`
+///     * `st` and `end` are 0-based, half-open read positions. `ctg_st` and `ctg_end` are the same for the contig.
+///     * `strand` is `-` if contig positions descend as read positions ascend.
+pub fn read_intervals(df_components: &DataFrame, kmer_size: usize) -> Result<DataFrame> {
+    let kmer_size = i64::try_from(kmer_size)?;
+    // SUNK positions are 1-based.
+    Ok(df_components
+        .clone()
+        .lazy()
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            (col("proj_cpos") + col("offset_dev"))
+                .cast(DataType::Int64)
+                .alias("cpos"),
+        ])
+        .group_by([col("read"), col("ctg")])
+        .agg([
+            (col("rpos").min() - lit(1)).alias("st"),
+            (col("rpos").max() - lit(1) + lit(kmer_size)).alias("end"),
+            (col("cpos").min() - lit(1)).alias("ctg_st"),
+            (col("cpos").max() - lit(1) + lit(kmer_size)).alias("ctg_end"),
+            when(
+                col("cpos")
+                    .sort_by(["rpos"], Default::default())
+                    .first()
+                    .gt(col("cpos").sort_by(["rpos"], Default::default()).last()),
+            )
+            .then(lit("-"))
+            .otherwise(lit("+"))
+            .alias("strand"),
+            col("rpos").len().cast(DataType::UInt64).alias("sunks"),
+        ])
+        .select([
+            col("read"),
+            col("st"),
+            col("end"),
+            col("ctg"),
+            col("ctg_st"),
+            col("ctg_end"),
+            col("strand"),
+            col("sunks"),
+        ])
+        .collect()?)
+}
+
+/// Get intervals of contigs not covered by any supported interval.
+///
+/// # Arguments
+/// * `df_supported`
+///     * [`DataFrame`] of supported intervals with columns `[ctg, st, end, ...]`
+/// * `ctg_lens`
+///     * Contig lengths.
+/// * `ctgs`
+///     * Contigs to report. Contigs without any supported interval are wholly unsupported.
+///
+/// # Returns
+/// * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end]`
+pub fn get_unsupported_intervals(
+    df_supported: &DataFrame,
+    ctg_lens: &HashMap<String, u64>,
+    ctgs: &[String],
+) -> Result<DataFrame> {
+    let mut supported: HashMap<&str, Vec<(i64, i64)>> = HashMap::new();
+    for ((ctg, st), end) in df_supported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_supported.column("st")?.i64()?.iter())
+        .zip(df_supported.column("end")?.i64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            continue;
+        };
+        supported.entry(ctg).or_default().push((st, end));
+    }
+
+    let (mut out_ctgs, mut starts, mut ends) = (vec![], vec![], vec![]);
+    for ctg in ctgs {
+        let Some(ctg_len) = ctg_lens.get(ctg).map(|len| *len as i64) else {
+            log::warn!("No length for {ctg}. Skipping unsupported intervals.");
+            continue;
+        };
+        let intervals = merge(supported.get(ctg.as_str()).cloned().unwrap_or_default(), 0);
+        for (st, end) in subtract(&[(0, ctg_len)], &intervals) {
+            out_ctgs.push(ctg.as_str());
+            starts.push(st);
+            ends.push(end);
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), out_ctgs),
+        Column::new("st".into(), starts),
+        Column::new("end".into(), ends),
+    ])?)
+}
+
+/// Count the validated reads containing each assembly SUNK.
+///
+/// Gives near base-resolution support, finer than supported intervals. SUNKs of `ctgs` without any validated read
+/// have zero support.
+///
+/// # Arguments
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in supported components with columns `[ctg, read, rpos, proj_cpos, offset_dev, ...]`
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, ...]`
+/// * `ctgs`
+///     * Contigs to report.
+/// * `df_rescued`
+///     * Optional [`DataFrame`] of rescued single-SUNK reads with columns `[ctg, read, cpos, ...]`.
+///     * See [`crate::sunk_graph::rescue_single_sunk_reads`].
+///
+/// # Returns
+/// * [`DataFrame`] bedgraph of the first base of each SUNK with columns `[ctg, st, end, reads]`
+///     * With `df_rescued`, an additional column `rescued_reads` counted separately from `reads`.
+pub fn sunk_support(
+    df_components: &DataFrame,
+    df_asm_sunks: &DataFrame,
+    ctgs: &[String],
+    df_rescued: Option<&DataFrame>,
+) -> Result<DataFrame> {
+    let lf_ctgs = DataFrame::new(vec![Column::new("ctg".into(), ctgs)])?.lazy();
+    let lf_reads = df_components
+        .clone()
+        .lazy()
+        .with_column(
+            (col("proj_cpos") + col("offset_dev"))
+                .cast(DataType::Int64)
+                .alias("cpos"),
+        )
+        .group_by([col("ctg"), col("cpos")])
+        .agg([col("read").n_unique().cast(DataType::UInt64).alias("reads")]);
+    // SUNK positions are 1-based.
+    let mut lf_support = df_asm_sunks
+        .clone()
+        .lazy()
+        .select([col("ctg"), col("cpos").cast(DataType::Int64)])
+        .join(
+            lf_ctgs,
+            [col("ctg")],
+            [col("ctg")],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .unique(None, UniqueKeepStrategy::First)
+        .join(
+            lf_reads,
+            [col("ctg"), col("cpos")],
+            [col("ctg"), col("cpos")],
+            JoinArgs::new(JoinType::Left),
+        );
+    let mut cols = vec![
+        col("ctg"),
+        (col("cpos") - lit(1)).alias("st"),
+        col("cpos").alias("end"),
+        col("reads").fill_null(lit(0u64)),
+    ];
+    if let Some(df_rescued) = df_rescued {
+        let lf_rescued = df_rescued
+            .clone()
+            .lazy()
+            .with_column(col("cpos").cast(DataType::Int64))
+            .group_by([col("ctg"), col("cpos")])
+            .agg([col("read")
+                .n_unique()
+                .cast(DataType::UInt64)
+                .alias("rescued_reads")]);
+        lf_support = lf_support.join(
+            lf_rescued,
+            [col("ctg"), col("cpos")],
+            [col("ctg"), col("cpos")],
+            JoinArgs::new(JoinType::Left),
+        );
+        cols.push(col("rescued_reads").fill_null(lit(0u64)));
+    }
+    Ok(lf_support
+        .sort(["ctg", "cpos"], Default::default())
+        .select(cols)
+        .collect()?)
+}
+
+/// Rank contigs by the fraction of their length supported.
+///
+/// # Arguments
+/// * `ctgs`
+///     * Contigs to report.
+/// * `ctg_lens`
+///     * Contig lengths.
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end, ...]`. See [`get_unsupported_intervals`].
+/// * `sunk_positions`
+///     * Positions of good SUNKs by contig.
+/// * `ctg_reads`
+///     * Number of reads assigned to each contig.
+/// * `coverage`
+///     * Coverage of each contig by its assigned reads.
+/// * `contained`
+///     * Small contigs supported by reads containing them. See [`crate::contained::contained_support`].
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[rank, ctg, length, supported_bp, supported_fraction, breakpoints, sunks_per_kbp, reads, coverage, support]`
+///     * Sorted by `supported_fraction` then `length`, descending.
+///     * `breakpoints` counts unsupported intervals not at either contig end.
+///     * `support` is [`CONTAINED_SUPPORT`] for `contained` contigs and [`SUNK_GRAPH_SUPPORT`] otherwise.
+pub fn contig_summary(
+    ctgs: &[String],
+    ctg_lens: &HashMap<String, u64>,
+    df_unsupported: &DataFrame,
+    sunk_positions: &HashMap<String, Vec<i64>>,
+    ctg_reads: &HashMap<String, u64>,
+    coverage: &HashMap<String, f64>,
+    contained: &HashSet<String>,
+) -> Result<DataFrame> {
+    let mut unsupported: HashMap<&str, Vec<(i64, i64)>> = HashMap::new();
+    for ((ctg, st), end) in df_unsupported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_unsupported.column("st")?.i64()?.iter())
+        .zip(df_unsupported.column("end")?.i64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            continue;
+        };
+        unsupported.entry(ctg).or_default().push((st, end));
+    }
+
+    let mut rows = Vec::with_capacity(ctgs.len());
+    for ctg in ctgs {
+        let len = ctg_lens.get(ctg).copied().unwrap_or_default();
+        let intervals = merge(unsupported.remove(ctg.as_str()).unwrap_or_default(), 0);
+        let unsupported_bp: i64 = intervals.iter().map(|(st, end)| end - st).sum();
+        let supported_bp = (len as i64 - unsupported_bp).max(0) as u64;
+        let breakpoints = intervals
+            .iter()
+            .filter(|(st, end)| *st > 0 && *end < len as i64)
+            .count() as u64;
+        let n_sunks = sunk_positions.get(ctg).map_or(0, Vec::len);
+        rows.push((
+            ctg.as_str(),
+            len,
+            supported_bp,
+            supported_bp as f64 / len.max(1) as f64,
+            breakpoints,
+            n_sunks as f64 / len.max(1) as f64 * 1_000.0,
+            ctg_reads.get(ctg).copied().unwrap_or_default(),
+            coverage.get(ctg).copied().unwrap_or_default(),
+            if contained.contains(ctg) {
+                CONTAINED_SUPPORT
+            } else {
+                SUNK_GRAPH_SUPPORT
+            },
+        ));
+    }
+    rows.sort_by(|a, b| b.3.total_cmp(&a.3).then(b.1.cmp(&a.1)).then(a.0.cmp(b.0)));
+
+    Ok(DataFrame::new(vec![
+        Column::new("rank".into(), (1..=rows.len() as u64).collect::<Vec<u64>>()),
+        Column::new(
+            "ctg".into(),
+            rows.iter().map(|row| row.0).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "length".into(),
+            rows.iter().map(|row| row.1).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "supported_bp".into(),
+            rows.iter().map(|row| row.2).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "supported_fraction".into(),
+            rows.iter().map(|row| row.3).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "breakpoints".into(),
+            rows.iter().map(|row| row.4).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "sunks_per_kbp".into(),
+            rows.iter().map(|row| row.5).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "reads".into(),
+            rows.iter().map(|row| row.6).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "coverage".into(),
+            rows.iter().map(|row| row.7).collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "support".into(),
+            rows.iter().map(|row| row.8).collect::<Vec<_>>(),
+        ),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use std::collections::{HashMap, HashSet};
+
+    use super::{contig_summary, sunk_support};
+
+    #[test]
+    fn test_sunk_support() {
+        let df_components = df!(
+            "ctg" => ["c1", "c1", "c1", "c1"],
+            "read" => ["r1", "r2", "r1", "r2"],
+            "rpos" => [1i64, 5, 11, 15],
+            "proj_cpos" => [100i64, 100, 100, 100],
+            "offset_dev" => [1i64, 1, 11, 12],
+        )
+        .unwrap();
+        let df_asm_sunks = df!(
+            "ctg" => ["c1", "c1", "c1", "c2"],
+            "cpos" => [101i64, 111, 150, 10],
+        )
+        .unwrap();
+        let df = sunk_support(&df_components, &df_asm_sunks, &["c1".to_owned()], None).unwrap();
+        assert_eq!(
+            df,
+            df!(
+                "ctg" => ["c1", "c1", "c1"],
+                "st" => [100i64, 110, 149],
+                "end" => [101i64, 111, 150],
+                "reads" => [2u64, 1, 0],
+            )
+            .unwrap()
+        );
+        // Rescued reads are counted separately.
+        let df_rescued = df!(
+            "ctg" => ["c1"],
+            "read" => ["r3"],
+            "cpos" => [111i64],
+        )
+        .unwrap();
+        let df = sunk_support(
+            &df_components,
+            &df_asm_sunks,
+            &["c1".to_owned()],
+            Some(&df_rescued),
+        )
+        .unwrap();
+        assert_eq!(
+            df.column("rescued_reads").unwrap().u64().unwrap().to_vec(),
+            [Some(0), Some(1), Some(0)]
+        );
+        assert_eq!(
+            df.column("reads").unwrap().u64().unwrap().to_vec(),
+            [Some(2), Some(1), Some(0)]
+        );
+    }
+
+    #[test]
+    fn test_contig_summary() {
+        let ctgs = ["c1".to_owned(), "c2".to_owned()];
+        let ctg_lens = HashMap::from([("c1".to_owned(), 1_000), ("c2".to_owned(), 2_000)]);
+        // c1 is missing support at its start and in the middle.
+        let df_unsupported = df!(
+            "ctg" => ["c1", "c1", "c2"],
+            "st" => [0i64, 400, 1_900],
+            "end" => [100i64, 500, 2_000],
+        )
+        .unwrap();
+        let sunk_positions = HashMap::from([("c1".to_owned(), vec![150, 600])]);
+        let ctg_reads = HashMap::from([("c1".to_owned(), 3)]);
+        let df = contig_summary(
+            &ctgs,
+            &ctg_lens,
+            &df_unsupported,
+            &sunk_positions,
+            &ctg_reads,
+            &HashMap::new(),
+            &HashSet::from(["c2".to_owned()]),
+        )
+        .unwrap();
+        assert_eq!(
+            df.select([
+                "rank",
+                "ctg",
+                "supported_bp",
+                "breakpoints",
+                "reads",
+                "support"
+            ])
+            .unwrap(),
+            df!(
+                "rank" => [1u64, 2],
+                "ctg" => ["c2", "c1"],
+                "supported_bp" => [1_900u64, 800],
+                "breakpoints" => [0u64, 1],
+                "reads" => [0u64, 3],
+                "support" => ["contained-support", "sunk-graph"],
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            df.column("sunks_per_kbp").unwrap().f64().unwrap().get(1),
+            Some(2.0)
+        );
+    }
+}
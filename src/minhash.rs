@@ -0,0 +1,254 @@
+//! Sanity check that reads come from the assembled sample with FracMinHash sketches.
+//!
+//! Sketches keep every canonical k-mer hash below `max_hash / scale`, so the containment of the sketched reads in the
+//! assembly is estimated directly from shared hashes regardless of how much of the assembly the reads cover. Reads
+//! from another species or heavily contaminated reads share few k-mers with the assembly and are caught before
+//! mapping.
+
+use std::collections::{HashMap, HashSet};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{cancel::CancelToken, error::Result, io::Fasta, minimizers::for_each_kmer_hash};
+
+/// k-mer size of sketches.
+pub const SKETCH_KMER_SIZE: usize = 21;
+/// One in this many k-mer hashes is kept.
+pub const SKETCH_SCALE: u64 = 1000;
+/// Read bases sketched. Reads are taken in name order until this is reached.
+pub const MAX_SKETCH_READ_BASES: u64 = 200_000_000;
+/// Warn if less than this fraction of sketched read k-mers are in the assembly.
+///
+/// Sequencing errors alone lower containment, ex. ~0.35 for reads with a 5% error rate.
+pub const MIN_READ_CONTAINMENT: f64 = 0.05;
+
+/// FracMinHash sketch of one or more sequences.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FracMinHash {
+    kmer_size: usize,
+    max_hash: u64,
+    hashes: HashSet<u64>,
+}
+
+impl FracMinHash {
+    pub fn new(kmer_size: usize, scale: u64) -> Self {
+        let kmer_size = kmer_size.clamp(1, 31);
+        Self {
+            kmer_size,
+            max_hash: ((1u64 << (2 * kmer_size)) - 1) / scale.max(1),
+            hashes: HashSet::new(),
+        }
+    }
+
+    /// Add the k-mers of a sequence.
+    pub fn add_seq(&mut self, seq: &[u8]) {
+        let max_hash = self.max_hash;
+        for_each_kmer_hash(seq, self.kmer_size, |_, hash| {
+            if hash <= max_hash {
+                self.hashes.insert(hash);
+            }
+        });
+    }
+
+    /// Add the hashes of another sketch with the same parameters.
+    pub fn merge(&mut self, other: &FracMinHash) {
+        self.hashes.extend(other.hashes.iter());
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Number of hashes shared with another sketch.
+    pub fn shared(&self, other: &FracMinHash) -> usize {
+        let (small, large) = if self.len() <= other.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        small
+            .hashes
+            .iter()
+            .filter(|hash| large.hashes.contains(hash))
+            .count()
+    }
+
+    /// Fraction of k-mers of this sketch in another. [`None`] if this sketch is empty.
+    pub fn containment(&self, other: &FracMinHash) -> Option<f64> {
+        (!self.is_empty()).then(|| self.shared(other) as f64 / self.len() as f64)
+    }
+}
+
+/// Mash-style distance from the containment of one sketch in another. `1.0` if nothing is shared.
+///
+/// Approximates the per-base divergence of the contained sequence as `1 - C^(1/k)`.
+pub fn mash_distance(containment: f64, kmer_size: usize) -> f64 {
+    if containment <= 0.0 {
+        return 1.0;
+    }
+    1.0 - containment.min(1.0).powf(1.0 / kmer_size as f64)
+}
+
+/// Read k-mers shared with a contig.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContigSketchStats {
+    pub ctg: String,
+    /// Hashes in the contig sketch.
+    pub hashes: usize,
+    /// Fraction of sketched read hashes found in the contig.
+    pub read_fraction: f64,
+    /// `read_fraction` over the contig's fraction of assembly hashes. ex. near `0` if the reads miss the contig and
+    /// above `1` for collapsed repeats.
+    pub enrichment: Option<f64>,
+}
+
+/// Similarity of the sketched reads to the assembly.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SketchStats {
+    pub kmer_size: usize,
+    pub scale: u64,
+    pub reads_sketched: usize,
+    pub read_bases_sketched: u64,
+    /// Fraction of sketched read k-mers in the assembly.
+    pub read_containment: Option<f64>,
+    /// Mash-style distance of the reads to the assembly from `read_containment`. See [`mash_distance`].
+    /// Includes sequencing errors.
+    pub read_distance: Option<f64>,
+    pub contigs: Vec<ContigSketchStats>,
+}
+
+/// Sketch fetched sequences in parallel.
+fn sketch_seqs<'a>(
+    fasta: &Fasta,
+    lens: impl IntoParallelIterator<Item = (&'a String, &'a u64)>,
+    cancel: &CancelToken,
+) -> Result<Vec<(&'a String, FracMinHash)>> {
+    lens.into_par_iter()
+        .map(|(name, len)| {
+            cancel.check()?;
+            let mut fh = fasta.try_clone()?;
+            let bases = fh.fetch_bytes(name, 1, *len)?;
+            let mut sketch = FracMinHash::new(SKETCH_KMER_SIZE, SKETCH_SCALE);
+            sketch.add_seq(&bases);
+            Ok((name, sketch))
+        })
+        .collect()
+}
+
+/// Sketch the assembly and a subset of reads and compare them.
+///
+/// # Arguments
+/// * `asm`
+///     * Assembly FASTA.
+/// * `asm_lens`
+///     * Contig lengths by FASTA name.
+/// * `names`
+///     * Name of each contig in the output, ex. from [`crate::naming::ContigRenames::rename_name`].
+/// * `reads`
+///     * Reads FASTA.
+/// * `read_lens`
+///     * Read lengths. Reads are sketched in name order up to [`MAX_SKETCH_READ_BASES`].
+/// * `cancel`
+///     * Token checked before sketching each sequence.
+///
+/// # Returns
+/// * [`SketchStats`] with contigs ordered by name.
+pub fn sketch_reads_vs_asm(
+    asm: &Fasta,
+    asm_lens: &HashMap<String, u64>,
+    names: impl Fn(&str) -> String,
+    reads: &Fasta,
+    read_lens: &HashMap<String, u64>,
+    cancel: &CancelToken,
+) -> Result<SketchStats> {
+    let mut read_names: Vec<(&String, &u64)> = read_lens.iter().collect();
+    read_names.sort_unstable();
+    let mut read_bases = 0;
+    let read_names: Vec<(&String, &u64)> = read_names
+        .into_iter()
+        .take_while(|(_, len)| {
+            let keep = read_bases < MAX_SKETCH_READ_BASES;
+            read_bases += **len;
+            keep
+        })
+        .collect();
+    let read_bases_sketched = read_names.iter().map(|(_, len)| **len).sum();
+
+    let mut read_sketch = FracMinHash::new(SKETCH_KMER_SIZE, SKETCH_SCALE);
+    for (_, sketch) in sketch_seqs(reads, read_names.clone(), cancel)? {
+        read_sketch.merge(&sketch);
+    }
+    let ctg_sketches = sketch_seqs(asm, asm_lens.par_iter(), cancel)?;
+    let mut asm_sketch = FracMinHash::new(SKETCH_KMER_SIZE, SKETCH_SCALE);
+    for (_, sketch) in &ctg_sketches {
+        asm_sketch.merge(sketch);
+    }
+
+    let read_containment = read_sketch.containment(&asm_sketch);
+    let mut contigs: Vec<ContigSketchStats> = ctg_sketches
+        .iter()
+        .map(|(ctg, sketch)| {
+            let read_fraction = read_sketch.containment(sketch).unwrap_or(0.0);
+            let asm_fraction = sketch.len() as f64 / asm_sketch.len().max(1) as f64;
+            ContigSketchStats {
+                ctg: names(ctg),
+                hashes: sketch.len(),
+                read_fraction,
+                enrichment: (asm_fraction > 0.0 && !read_sketch.is_empty())
+                    .then(|| read_fraction / asm_fraction),
+            }
+        })
+        .collect();
+    contigs.sort_by(|a, b| a.ctg.cmp(&b.ctg));
+
+    Ok(SketchStats {
+        kmer_size: SKETCH_KMER_SIZE,
+        scale: SKETCH_SCALE,
+        reads_sketched: read_names.len(),
+        read_bases_sketched,
+        read_containment,
+        read_distance: read_containment
+            .map(|containment| mash_distance(containment, SKETCH_KMER_SIZE)),
+        contigs,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mash_distance, FracMinHash};
+
+    fn random_seq(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_containment() {
+        let asm = random_seq(500_000, 1);
+        let mut asm_sketch = FracMinHash::new(21, 100);
+        asm_sketch.add_seq(&asm);
+
+        let mut same = FracMinHash::new(21, 100);
+        same.add_seq(&asm[100_000..300_000]);
+        assert_eq!(same.containment(&asm_sketch), Some(1.0));
+        assert_eq!(mash_distance(1.0, 21), 0.0);
+
+        let mut other = FracMinHash::new(21, 100);
+        other.add_seq(&random_seq(200_000, 2));
+        assert!(other.containment(&asm_sketch).unwrap() < 0.01);
+        assert_eq!(mash_distance(0.0, 21), 1.0);
+        assert_eq!(FracMinHash::new(21, 100).containment(&asm_sketch), None);
+    }
+}
@@ -0,0 +1,283 @@
+//! Coarse pre-assignment of reads to candidate contigs by shared minimizers.
+//!
+//! Each contig is sketched by its `(w, k)` minimizers. A read is sketched the same way and only contigs sharing the
+//! most minimizers with it are kept as candidates so only their SUNKs are searched for in the read. This bounds the
+//! SUNKs searched per read and the size of the SUNK join on whole-genome assemblies at high coverage.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rayon::prelude::*;
+
+use crate::{cancel::CancelToken, error::Result, io::Fasta};
+
+/// Default k-mer size of minimizers.
+pub const DEFAULT_SKETCH_KMER_SIZE: usize = 19;
+/// Default number of consecutive k-mers a minimizer is chosen from.
+pub const DEFAULT_SKETCH_WINDOW: usize = 50;
+/// Minimum minimizers a read shares with a contig for it to be a candidate.
+pub const MIN_SHARED_MINIMIZERS: usize = 3;
+
+/// Invertible integer hash so minimizers aren't biased towards low complexity k-mers. From `minimap2`.
+fn hash64(key: u64, mask: u64) -> u64 {
+    let mut key = (!key).wrapping_add(key << 21) & mask;
+    key ^= key >> 24;
+    key = (key.wrapping_add(key << 3)).wrapping_add(key << 8) & mask;
+    key ^= key >> 14;
+    key = (key.wrapping_add(key << 2)).wrapping_add(key << 4) & mask;
+    key ^= key >> 28;
+    key.wrapping_add(key << 31) & mask
+}
+
+/// Call `f` with the position and hash of each canonical k-mer of a sequence.
+///
+/// k-mers with bases other than `ACGT` (case-insensitive) are skipped. Hashes are less than `4^kmer_size`.
+///
+/// # Arguments
+/// * `seq`
+///     * Sequence.
+/// * `kmer_size`
+///     * k-mer size. At most 31.
+/// * `f`
+///     * Called with the 0-based position of the last base of each k-mer and its hash.
+pub fn for_each_kmer_hash(seq: &[u8], kmer_size: usize, mut f: impl FnMut(usize, u64)) {
+    let kmer_size = kmer_size.clamp(1, 31);
+    let mask = (1u64 << (2 * kmer_size)) - 1;
+    let shift = 2 * (kmer_size as u64 - 1);
+    let (mut fwd, mut rev, mut n_valid) = (0u64, 0u64, 0usize);
+    for (i, base) in seq.iter().enumerate() {
+        let code = match base {
+            b'A' | b'a' => 0,
+            b'C' | b'c' => 1,
+            b'G' | b'g' => 2,
+            b'T' | b't' => 3,
+            _ => {
+                n_valid = 0;
+                continue;
+            }
+        };
+        fwd = ((fwd << 2) | code) & mask;
+        rev = (rev >> 2) | ((3 - code) << shift);
+        n_valid += 1;
+        if n_valid >= kmer_size {
+            f(i, hash64(fwd.min(rev), mask));
+        }
+    }
+}
+
+/// Hashes of the canonical `(w, k)` minimizers of a sequence.
+///
+/// # Arguments
+/// * `seq`
+///     * Sequence.
+/// * `kmer_size`
+///     * k-mer size. See [`for_each_kmer_hash`].
+/// * `window`
+///     * Number of consecutive k-mers a minimizer is chosen from.
+///
+/// # Returns
+/// * Distinct minimizer hashes.
+pub fn minimizers(seq: &[u8], kmer_size: usize, window: usize) -> HashSet<u64> {
+    let window = window.max(1);
+    // (position, hash) of k-mers in the window with ascending hashes.
+    let mut queue: VecDeque<(usize, u64)> = VecDeque::new();
+    let mut hashes = HashSet::new();
+    // Consecutive k-mers since the last skipped base.
+    let mut n_kmers = 0;
+    for_each_kmer_hash(seq, kmer_size, |i, hash| {
+        if queue.back().is_some_and(|(pos, _)| *pos + 1 != i) {
+            queue.clear();
+            n_kmers = 0;
+        }
+        n_kmers += 1;
+        while queue.back().is_some_and(|(_, back)| *back >= hash) {
+            queue.pop_back();
+        }
+        queue.push_back((i, hash));
+        while queue.front().is_some_and(|(pos, _)| *pos + window <= i) {
+            queue.pop_front();
+        }
+        if n_kmers >= window {
+            if let Some((_, hash)) = queue.front() {
+                hashes.insert(*hash);
+            }
+        }
+    });
+    hashes
+}
+
+/// Minimizer sketches of assembly contigs.
+#[derive(Debug, Default)]
+pub struct ContigSketches {
+    kmer_size: usize,
+    window: usize,
+    ctgs: Vec<String>,
+    /// Indices of contigs with each minimizer.
+    index: HashMap<u64, Vec<u32>>,
+}
+
+impl ContigSketches {
+    /// Sketch contigs.
+    ///
+    /// # Arguments
+    /// * `seqs`
+    ///     * Contig names and sequences.
+    /// * `kmer_size`, `window`
+    ///     * Minimizer parameters. See [`minimizers`].
+    pub fn new<'a>(
+        seqs: impl IntoIterator<Item = (String, &'a [u8])>,
+        kmer_size: usize,
+        window: usize,
+    ) -> Self {
+        let mut sketches = Self {
+            kmer_size,
+            window,
+            ..Default::default()
+        };
+        for (ctg, seq) in seqs {
+            let hashes = minimizers(seq, kmer_size, window);
+            sketches.add(ctg, hashes);
+        }
+        sketches
+    }
+
+    /// Sketch contigs of an assembly.
+    ///
+    /// # Arguments
+    /// * `fasta`
+    ///     * Assembly FASTA.
+    /// * `fasta_lens`
+    ///     * Lengths of contigs to sketch by FASTA name.
+    /// * `names`
+    ///     * Name of each contig in the sketches, ex. from [`crate::naming::ContigRenames::rename_name`].
+    /// * `kmer_size`, `window`
+    ///     * Minimizer parameters. See [`minimizers`].
+    /// * `cancel`
+    ///     * Token checked before sketching each contig.
+    pub fn from_fasta(
+        fasta: &Fasta,
+        fasta_lens: &HashMap<String, u64>,
+        names: impl Fn(&str) -> String + Sync,
+        kmer_size: usize,
+        window: usize,
+        cancel: &CancelToken,
+    ) -> Result<Self> {
+        let ctg_hashes = fasta_lens
+            .par_iter()
+            .map(|(ctg, len)| {
+                cancel.check()?;
+                let mut fh = fasta.try_clone()?;
+                let bases = fh.fetch_bytes(ctg, 1, *len)?;
+                Ok((names(ctg), minimizers(&bases, kmer_size, window)))
+            })
+            .collect::<Result<Vec<(String, HashSet<u64>)>>>()?;
+        let mut sketches = Self {
+            kmer_size,
+            window,
+            ..Default::default()
+        };
+        for (ctg, hashes) in ctg_hashes {
+            sketches.add(ctg, hashes);
+        }
+        log::info!(
+            "Sketched {} contigs with {} distinct minimizers.",
+            sketches.ctgs.len(),
+            sketches.index.len()
+        );
+        Ok(sketches)
+    }
+
+    fn add(&mut self, ctg: String, hashes: HashSet<u64>) {
+        let idx = self.ctgs.len() as u32;
+        self.ctgs.push(ctg);
+        for hash in hashes {
+            self.index.entry(hash).or_default().push(idx);
+        }
+    }
+
+    /// Contigs sharing the most minimizers with a sequence.
+    ///
+    /// # Arguments
+    /// * `seq`
+    ///     * Read sequence in either orientation.
+    /// * `max_candidates`
+    ///     * Maximum contigs returned.
+    ///
+    /// # Returns
+    /// * Up to `max_candidates` contigs sharing at least [`MIN_SHARED_MINIMIZERS`] with `seq` by descending count.
+    pub fn candidates(&self, seq: &[u8], max_candidates: usize) -> Vec<&str> {
+        let mut counts: HashMap<u32, usize> = HashMap::new();
+        for hash in minimizers(seq, self.kmer_size, self.window) {
+            for idx in self.index.get(&hash).into_iter().flatten() {
+                *counts.entry(*idx).or_default() += 1;
+            }
+        }
+        let mut counts: Vec<(u32, usize)> = counts
+            .into_iter()
+            .filter(|(_, count)| *count >= MIN_SHARED_MINIMIZERS)
+            .collect();
+        // Ties are ordered by contig for reproducibility.
+        counts.sort_unstable_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| self.ctgs[a.0 as usize].cmp(&self.ctgs[b.0 as usize]))
+        });
+        counts
+            .into_iter()
+            .take(max_candidates)
+            .map(|(idx, _)| self.ctgs[idx as usize].as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{minimizers, ContigSketches};
+
+    fn random_seq(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                b"ACGT"[(state >> 62) as usize]
+            })
+            .collect()
+    }
+
+    fn revcomp(seq: &[u8]) -> Vec<u8> {
+        seq.iter()
+            .rev()
+            .map(|nt| match nt {
+                b'A' => b'T',
+                b'C' => b'G',
+                b'G' => b'C',
+                _ => b'A',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_minimizers_are_canonical() {
+        let seq = random_seq(5000, 1);
+        let fwd = minimizers(&seq, 15, 10);
+        assert!(!fwd.is_empty());
+        assert_eq!(fwd, minimizers(&revcomp(&seq), 15, 10));
+        assert!(minimizers(b"ACGTNNNN", 15, 10).is_empty());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let (ctg_1, ctg_2) = (random_seq(50_000, 2), random_seq(50_000, 3));
+        let sketches = ContigSketches::new(
+            [
+                ("ctg_1".to_owned(), ctg_1.as_slice()),
+                ("ctg_2".to_owned(), ctg_2.as_slice()),
+            ],
+            15,
+            10,
+        );
+        let read = revcomp(&ctg_2[10_000..20_000]);
+        assert_eq!(sketches.candidates(&read, 2), ["ctg_2"]);
+        assert!(sketches.candidates(&random_seq(10_000, 4), 2).is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use polars::prelude::*;
+
+use crate::error::{GavisunkError, Result};
+
+/// Columns of contig names restored in outputs.
+const CTG_COLUMNS: [&str; 5] = ["ctg", "ctg_a", "ctg_b", "left_ctg", "right_ctg"];
+
+/// Device names reserved on Windows regardless of extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Normalize a sequence name to the first whitespace-delimited token of its FASTA header.
+///
+/// FASTA indexes only keep this token, so names from any other source must be normalized before they're compared.
+/// Leading `>` and whitespace, including `\t` and `\r`, are stripped. ex. `>ctg1 length=100\r` -> `ctg1`
+pub fn normalize_name(name: &str) -> &str {
+    name.trim_start_matches('>')
+        .split_ascii_whitespace()
+        .next()
+        .unwrap_or_default()
+}
+
+/// Warn if names from an input match no assembly contigs. Joins on them would silently be empty.
+///
+/// # Arguments
+/// * `source`
+///     * Input the names came from, for the warning.
+/// * `names`
+///     * Normalized contig names. See [`normalize_name`].
+/// * `asm_lens`
+///     * Assembly contig lengths.
+///
+/// # Returns
+/// * Number of unmatched names.
+pub fn warn_unmatched_contigs<'a>(
+    source: &str,
+    names: impl IntoIterator<Item = &'a str>,
+    asm_lens: &HashMap<String, u64>,
+) -> usize {
+    let mut unmatched: Vec<&str> = names
+        .into_iter()
+        .filter(|name| !asm_lens.contains_key(*name))
+        .collect();
+    unmatched.sort_unstable();
+    unmatched.dedup();
+    if !unmatched.is_empty() {
+        log::warn!(
+            "{} contigs in {source} are not in the assembly and will be ignored. ex. {:?}",
+            unmatched.len(),
+            &unmatched[..unmatched.len().min(5)]
+        );
+    }
+    unmatched.len()
+}
+
+/// Convert a sequence name to a name safe to use as a filename on any platform.
+///
+/// Characters other than ASCII alphanumerics, `.`, `-`, and `_` are replaced with `_`.
+/// ex. `haplotype1-0000033#1#chr1` -> `haplotype1-0000033_1_chr1`
+pub fn sanitize_filename(name: &str) -> String {
+    let mut fname: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stem = fname.split('.').next().unwrap_or_default();
+    if fname.is_empty()
+        || fname.chars().all(|c| c == '.')
+        || RESERVED_NAMES.contains(&stem.to_ascii_uppercase().as_str())
+    {
+        fname.insert(0, '_');
+    }
+    fname
+}
+
+/// Unique, filesystem-safe filenames for contigs.
+///
+/// Contigs whose sanitized names collide get a numeric suffix in sorted contig order. ex. `ctg:1` -> `ctg_1_2`
+#[derive(Debug, Default, Clone)]
+pub struct ContigFilenames(HashMap<String, String>);
+
+impl ContigFilenames {
+    pub fn new<'a>(ctgs: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut ctgs: Vec<&str> = ctgs.into_iter().collect();
+        ctgs.sort_unstable();
+        ctgs.dedup();
+
+        let mut used = HashSet::new();
+        let mut fnames = HashMap::with_capacity(ctgs.len());
+        for ctg in ctgs {
+            let base = sanitize_filename(ctg);
+            let mut fname = base.clone();
+            let mut n = 1;
+            while !used.insert(fname.clone()) {
+                n += 1;
+                fname = format!("{base}_{n}");
+            }
+            fnames.insert(ctg.to_owned(), fname);
+        }
+        Self(fnames)
+    }
+
+    /// Filename for `ctg`. Contigs not given on construction are sanitized but may not be unique.
+    pub fn get(&self, ctg: &str) -> String {
+        self.0
+            .get(ctg)
+            .cloned()
+            .unwrap_or_else(|| sanitize_filename(ctg))
+    }
+
+    /// Lookup table of contig names to filenames.
+    ///
+    /// # Returns
+    /// * [`DataFrame`] with columns `[ctg, filename]`
+    pub fn to_df(&self) -> Result<DataFrame> {
+        let (ctgs, fnames): (Vec<&str>, Vec<&str>) = self
+            .0
+            .iter()
+            .map(|(ctg, fname)| (ctg.as_str(), fname.as_str()))
+            .unzip();
+        Ok(DataFrame::new(vec![
+            Column::new("ctg".into(), ctgs),
+            Column::new("filename".into(), fnames),
+        ])?)
+    }
+}
+
+/// Contig renames applied when loading the assembly and reversed when writing outputs.
+///
+/// Loaded from a TSV of `name` and `new_name` without a header. `name` may be a full FASTA header with a description.
+/// As FASTA indexes only keep the first whitespace-delimited token of headers, `name` is matched by both its full
+/// text and its first token. Outputs restore the full `name`. ex. `ctg1 len=100` -> `ctg1_hap1` -> `ctg1 len=100`
+#[derive(Debug, Default, Clone)]
+pub struct ContigRenames {
+    renamed: HashMap<String, String>,
+    restored: HashMap<String, String>,
+}
+
+impl ContigRenames {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let mut renames = Self::default();
+        let reader = BufReader::new(File::open(path)?);
+        for (line_num, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |reason: &str| {
+                GavisunkError::InvalidRenameMap(format!("line {}: {reason}", line_num + 1))
+            };
+            let (name, new_name) = line
+                .split_once('\t')
+                .ok_or_else(|| err("expected a name and new name separated by a tab"))?;
+            let (name, new_name) = (name.trim(), new_name.trim());
+            let token = normalize_name(name);
+            if token.is_empty() || new_name.is_empty() {
+                return Err(err("empty name"));
+            }
+            if renames.renamed.contains_key(name) || renames.renamed.contains_key(token) {
+                return Err(err(&format!("{token:?} is renamed more than once")));
+            }
+            if renames.restored.contains_key(new_name) {
+                return Err(err(&format!("{new_name:?} is used more than once")));
+            }
+            for key in [name, token] {
+                renames.renamed.insert(key.to_owned(), new_name.to_owned());
+            }
+            renames
+                .restored
+                .insert(new_name.to_owned(), name.to_owned());
+        }
+        Ok(renames)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.renamed.is_empty()
+    }
+
+    /// Name used within the pipeline. Names not in the map are unchanged.
+    pub fn rename_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.renamed.get(name).map_or(name, |name| name.as_str())
+    }
+
+    /// Original name for a name used within the pipeline. Names not in the map are unchanged.
+    pub fn restore_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.restored.get(name).map_or(name, |name| name.as_str())
+    }
+
+    /// Rename the keys of a map by contig.
+    ///
+    /// # Returns
+    /// * Error if a new name collides with another contig.
+    pub fn rename_keys<V>(&self, values: HashMap<String, V>) -> Result<HashMap<String, V>> {
+        let n_ctgs = values.len();
+        let renamed: HashMap<String, V> = values
+            .into_iter()
+            .map(|(ctg, value)| (self.rename_name(&ctg).to_owned(), value))
+            .collect();
+        if renamed.len() != n_ctgs {
+            return Err(GavisunkError::InvalidRenameMap(
+                "new names collide with other contigs".to_owned(),
+            ));
+        }
+        Ok(renamed)
+    }
+
+    /// Rename contig columns, `ctg`, `ctg_a`, and `ctg_b`, to names used within the pipeline.
+    pub fn rename(&self, df: &DataFrame) -> Result<DataFrame> {
+        Self::map_columns(df, &self.renamed)
+    }
+
+    /// Restore original names in contig columns, `ctg`, `ctg_a`, and `ctg_b`.
+    pub fn restore(&self, df: &DataFrame) -> Result<DataFrame> {
+        Self::map_columns(df, &self.restored)
+    }
+
+    fn map_columns(df: &DataFrame, names: &HashMap<String, String>) -> Result<DataFrame> {
+        let mut df = df.clone();
+        if names.is_empty() {
+            return Ok(df);
+        }
+        for col_name in CTG_COLUMNS {
+            let Ok(column) = df.column(col_name) else {
+                continue;
+            };
+            let mapped: Vec<Option<&str>> = column
+                .str()?
+                .iter()
+                .map(|ctg| ctg.map(|ctg| names.get(ctg).map_or(ctg, |name| name.as_str())))
+                .collect();
+            let mapped = Column::new(col_name.into(), mapped);
+            df.with_column(mapped)?;
+        }
+        Ok(df)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        normalize_name, sanitize_filename, warn_unmatched_contigs, ContigFilenames, ContigRenames,
+    };
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(normalize_name("ctg1"), "ctg1");
+        assert_eq!(normalize_name(">ctg1 length=100"), "ctg1");
+        assert_eq!(normalize_name("ctg1\tlength=100"), "ctg1");
+        assert_eq!(normalize_name("ctg1\r"), "ctg1");
+        assert_eq!(normalize_name("  ctg1  "), "ctg1");
+        assert_eq!(normalize_name("h1#1#chr1:1-100 x"), "h1#1#chr1:1-100");
+        assert_eq!(normalize_name(""), "");
+    }
+
+    #[test]
+    fn test_warn_unmatched_contigs() {
+        let asm_lens = HashMap::from([("ctg1".to_owned(), 100)]);
+        assert_eq!(
+            warn_unmatched_contigs("test", ["ctg1", "ctg2", "ctg2", "ctg3"], &asm_lens),
+            2
+        );
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(
+            sanitize_filename("haplotype1-0000033#1#chr1"),
+            "haplotype1-0000033_1_chr1"
+        );
+        assert_eq!(sanitize_filename("chr1:1-100|a/b"), "chr1_1-100_a_b");
+        assert_eq!(sanitize_filename("con.fa"), "_con.fa");
+        assert_eq!(sanitize_filename(".."), "_..");
+    }
+
+    #[test]
+    fn test_contig_filenames_unique() {
+        let fnames = ContigFilenames::new(["ctg:1", "ctg/1", "ctg_1"]);
+        assert_eq!(fnames.get("ctg/1"), "ctg_1");
+        assert_eq!(fnames.get("ctg:1"), "ctg_1_2");
+        assert_eq!(fnames.get("ctg_1"), "ctg_1_3");
+    }
+
+    #[test]
+    fn test_contig_renames() {
+        let dir =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_contig_renames").unwrap();
+        let path = dir.path().join("renames.tsv");
+        std::fs::write(&path, "ctg1 len=100\tctg1_hap1\nctg2\tctg2_hap2\n").unwrap();
+        let renames = ContigRenames::load(&path).unwrap();
+        assert_eq!(renames.rename_name("ctg1"), "ctg1_hap1");
+        assert_eq!(renames.rename_name("ctg1 len=100"), "ctg1_hap1");
+        assert_eq!(renames.restore_name("ctg1_hap1"), "ctg1 len=100");
+        assert_eq!(renames.rename_name("ctg3"), "ctg3");
+    }
+}
@@ -0,0 +1,117 @@
+//! Orientation of a read relative to a contig from its SUNK anchors.
+
+use std::fmt::Display;
+
+/// Orientation of a read relative to a contig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// Contig positions ascend with read positions.
+    Forward,
+    /// Contig positions descend with read positions.
+    Reverse,
+}
+
+impl Orientation {
+    pub fn is_rev(&self) -> bool {
+        *self == Orientation::Reverse
+    }
+
+    /// Strand as in read assignments and SUNK hits. ex. `+`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Orientation::Forward => "+",
+            Orientation::Reverse => "-",
+        }
+    }
+}
+
+impl Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Infer the orientation of a read from the gradient of contig positions along the read.
+///
+/// Anchors are ordered by read position and each step between consecutive anchors votes for the direction contig
+/// positions move in. Ties are broken by the net change in contig position from the first to the last anchor, then
+/// towards [`Orientation::Forward`].
+///
+/// # Arguments
+/// * `anchors`
+///     * `(rpos, cpos)` of SUNKs hitting both the read and contig, in any order.
+///
+/// # Returns
+/// * Orientation and its confidence, the fraction of steps agreeing with it in `[0.5, 1.0]`.
+/// * [`None`] if there are fewer than two anchors with distinct read and contig positions.
+///
+/// # Examples
+/// ```
+/// use rs_gavisunk::orientation::{infer_orientation, Orientation};
+///
+/// let (ort, confidence) = infer_orientation(&[(1, 900), (50, 850), (100, 860), (150, 700)]).unwrap();
+/// assert_eq!(ort, Orientation::Reverse);
+/// assert!((confidence - 2.0 / 3.0).abs() < 1e-9);
+/// ```
+pub fn infer_orientation(anchors: &[(i64, i64)]) -> Option<(Orientation, f64)> {
+    let mut anchors = anchors.to_vec();
+    anchors.sort_unstable();
+    anchors.dedup_by_key(|(rpos, _)| *rpos);
+
+    let (mut n_asc, mut n_desc) = (0usize, 0usize);
+    for (a, b) in anchors.iter().zip(anchors.iter().skip(1)) {
+        match b.1.cmp(&a.1) {
+            std::cmp::Ordering::Greater => n_asc += 1,
+            std::cmp::Ordering::Less => n_desc += 1,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    let n_steps = n_asc + n_desc;
+    if n_steps == 0 {
+        return None;
+    }
+    let ort = match n_asc.cmp(&n_desc) {
+        std::cmp::Ordering::Greater => Orientation::Forward,
+        std::cmp::Ordering::Less => Orientation::Reverse,
+        std::cmp::Ordering::Equal => {
+            let (first, last) = (anchors.first()?.1, anchors.last()?.1);
+            if last < first {
+                Orientation::Reverse
+            } else {
+                Orientation::Forward
+            }
+        }
+    };
+    Some((ort, n_asc.max(n_desc) as f64 / n_steps as f64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{infer_orientation, Orientation};
+
+    #[test]
+    fn test_infer_orientation() {
+        let fwd = [(1, 100), (20, 130), (40, 125), (60, 170), (80, 190)];
+        let (ort, confidence) = infer_orientation(&fwd).unwrap();
+        assert_eq!(ort, Orientation::Forward);
+        assert_eq!(confidence, 0.75);
+
+        // Reversing the read flips its orientation regardless of anchor order.
+        let rev: Vec<(i64, i64)> = fwd
+            .iter()
+            .rev()
+            .map(|(rpos, cpos)| (-rpos, *cpos))
+            .collect();
+        assert_eq!(infer_orientation(&rev).unwrap().0, Orientation::Reverse);
+
+        // Ties are broken by net change.
+        let tie = [(1, 100), (10, 90), (20, 150), (30, 140), (40, 200)];
+        assert_eq!(
+            infer_orientation(&tie).unwrap(),
+            (Orientation::Forward, 0.5)
+        );
+
+        assert_eq!(infer_orientation(&[(1, 100)]), None);
+        assert_eq!(infer_orientation(&[(1, 100), (1, 200)]), None);
+    }
+}
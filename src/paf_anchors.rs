@@ -0,0 +1,313 @@
+//! Experimental read SUNK hits from alignments of reads to the assembly instead of exact k-mer matches.
+//!
+//! Each alignment's exactly matching blocks are projected onto the assembly SUNKs they contain, so downstream stages
+//! run unchanged and SUNK-based and alignment-based support can be compared on the same reads.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use polars::prelude::*;
+
+use crate::{
+    error::{GavisunkError, Result},
+    joins::check_join_loss,
+    map_kmers::{dedup_hits, DedupPolicy},
+    naming::normalize_name,
+    run_state::RunState,
+};
+
+/// Operation of an alignment walked along the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AlnOp {
+    /// Aligned bases. Exact matches unless from a `cg:Z` `M`.
+    Match(u64),
+    Mismatch(u64),
+    Ins(u64),
+    Del(u64),
+}
+
+/// Parse a `cg:Z` CIGAR. `M` is assumed to be a match as mismatches aren't distinguished.
+fn parse_cigar(cigar: &str) -> std::result::Result<Vec<AlnOp>, String> {
+    let mut ops = vec![];
+    let mut len = String::new();
+    for c in cigar.chars() {
+        if c.is_ascii_digit() {
+            len.push(c);
+            continue;
+        }
+        let n: u64 = len
+            .parse()
+            .map_err(|_| format!("invalid CIGAR {cigar:?}"))?;
+        len.clear();
+        let op = match c {
+            'M' | '=' => AlnOp::Match(n),
+            'X' => AlnOp::Mismatch(n),
+            'I' | 'S' => AlnOp::Ins(n),
+            'D' | 'N' => AlnOp::Del(n),
+            'H' | 'P' => continue,
+            _ => return Err(format!("invalid CIGAR operation {c:?} in {cigar:?}")),
+        };
+        ops.push(op);
+    }
+    Ok(ops)
+}
+
+/// Parse a `cs:Z` difference string in short or long form.
+fn parse_cs(cs: &str) -> std::result::Result<Vec<AlnOp>, String> {
+    let invalid = || format!("invalid cs {cs:?}");
+    let mut ops = vec![];
+    let bytes = cs.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let op = bytes[i];
+        let st = i + 1;
+        let mut end = st;
+        while end < bytes.len() && !matches!(bytes[end], b':' | b'=' | b'*' | b'+' | b'-' | b'~') {
+            end += 1;
+        }
+        let field = &cs[st..end];
+        ops.push(match op {
+            b':' => AlnOp::Match(field.parse().map_err(|_| invalid())?),
+            b'=' => AlnOp::Match(field.len() as u64),
+            b'*' if field.len() == 2 => AlnOp::Mismatch(1),
+            b'+' => AlnOp::Ins(field.len() as u64),
+            b'-' => AlnOp::Del(field.len() as u64),
+            // Intron. ex. `~gt123ag`
+            b'~' if field.len() > 4 => {
+                AlnOp::Del(field[2..field.len() - 2].parse().map_err(|_| invalid())?)
+            }
+            _ => return Err(invalid()),
+        });
+        i = end;
+    }
+    Ok(ops)
+}
+
+/// Exactly matching blocks of an alignment as `(target start, query offset, length)`, 0-based.
+///
+/// Query offsets are from the start of the aligned query on the alignment strand.
+fn match_blocks(ops: &[AlnOp], tst: u64) -> Vec<(u64, u64, u64)> {
+    let mut blocks: Vec<(u64, u64, u64)> = vec![];
+    let (mut t, mut q) = (tst, 0);
+    for op in ops {
+        match *op {
+            AlnOp::Match(n) => {
+                // Merge adjacent matches. ex. `:10=ACGT`
+                match blocks.last_mut() {
+                    Some((bt, bq, len)) if *bt + *len == t && *bq + *len == q => *len += n,
+                    _ => blocks.push((t, q, n)),
+                }
+                t += n;
+                q += n;
+            }
+            AlnOp::Mismatch(n) => {
+                t += n;
+                q += n;
+            }
+            AlnOp::Ins(n) => q += n,
+            AlnOp::Del(n) => t += n,
+        }
+    }
+    blocks
+}
+
+/// Read SUNK hits from a PAF of reads aligned to the assembly, ex. from `minimap2 -c` or `--cs`.
+///
+/// A read carries a SUNK if the SUNK is entirely within an exactly matching block of one of its alignments. Mismatches
+/// are only known from `cs:Z`, which is preferred over `cg:Z`. Secondary alignments, `tp:A:S`, are skipped.
+///
+/// # Arguments
+/// * `path`
+///     * PAF of reads, the query, aligned to the assembly with `cg:Z` or `cs:Z` tags.
+/// * `df_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, group, ...]`
+/// * `read_lens`
+///     * Read lengths. Alignments of other reads are skipped.
+/// * `kmer_size`
+///     * kmer size.
+/// * `rename`
+///     * Name used within the pipeline of an assembly contig in the PAF.
+/// * `dedup`
+///     * How multiple hits per `(read, ctg, group)` are resolved.
+/// * `state`
+///     * Run the join of alignments to reads is checked in.
+///
+/// # Returns
+/// * [`DataFrame`] of SUNKs within reads. See [`crate::map_kmers::map_sunks_to_reads`].
+pub fn read_sunks_from_paf(
+    path: impl AsRef<Path>,
+    df_sunks: &DataFrame,
+    read_lens: &HashMap<String, u64>,
+    kmer_size: usize,
+    rename: impl Fn(&str) -> String,
+    dedup: DedupPolicy,
+    state: &RunState,
+) -> Result<DataFrame> {
+    // 0-based start and group of each SUNK sorted by contig position.
+    let mut ctg_sunks: HashMap<&str, Vec<(u64, i64)>> = HashMap::new();
+    for ((ctg, cpos), group) in df_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(
+            df_sunks
+                .column("cpos")?
+                .cast(&DataType::UInt64)?
+                .u64()?
+                .iter(),
+        )
+        .zip(
+            df_sunks
+                .column("group")?
+                .cast(&DataType::Int64)?
+                .i64()?
+                .iter(),
+        )
+    {
+        let (Some(ctg), Some(cpos), Some(group)) = (ctg, cpos, group) else {
+            continue;
+        };
+        ctg_sunks.entry(ctg).or_default().push((cpos - 1, group));
+    }
+    for sunks in ctg_sunks.values_mut() {
+        sunks.sort_unstable();
+    }
+
+    let k = kmer_size as u64;
+    let (mut reads, mut rpos, mut ctgs, mut cpos, mut groups, mut strands) =
+        (vec![], vec![], vec![], vec![], vec![], vec![]);
+    let (mut n_alns, mut n_unknown_reads) = (0, 0);
+    let reader = BufReader::new(File::open(path)?);
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err =
+            |reason: &str| GavisunkError::InvalidPaf(format!("line {}: {reason}", line_num + 1));
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [qname, _, qst, qend, strand, tname, _, tst, _, _, _, _, ..] = fields[..] else {
+            return Err(err("expected at least 12 tab-separated columns"));
+        };
+        let tags = fields.get(12..).unwrap_or_default();
+        if tags.contains(&"tp:A:S") {
+            continue;
+        }
+        n_alns += 1;
+        let read = normalize_name(qname);
+        if !read_lens.contains_key(read) {
+            n_unknown_reads += 1;
+            continue;
+        }
+        let ctg = rename(normalize_name(tname));
+        let Some(sunks) = ctg_sunks.get(ctg.as_str()) else {
+            continue;
+        };
+        let parse = |value: &str, name: &str| -> Result<u64> {
+            value.parse().map_err(|_| err(&format!("invalid {name}")))
+        };
+        let (qst, qend, tst) = (
+            parse(qst, "query start")?,
+            parse(qend, "query end")?,
+            parse(tst, "target start")?,
+        );
+        let is_fwd = match strand {
+            "+" => true,
+            "-" => false,
+            _ => return Err(err(&format!("invalid strand {strand:?}"))),
+        };
+        let ops = if let Some(cs) = tags.iter().find_map(|tag| tag.strip_prefix("cs:Z:")) {
+            parse_cs(cs)
+        } else if let Some(cg) = tags.iter().find_map(|tag| tag.strip_prefix("cg:Z:")) {
+            parse_cigar(cg)
+        } else {
+            return Err(err("no cg:Z or cs:Z tag. Align with minimap2 -c or --cs."));
+        }
+        .map_err(|reason| err(&reason))?;
+
+        for (bt, bq, len) in match_blocks(&ops, tst) {
+            let first = sunks.partition_point(|(pos, _)| *pos < bt);
+            for (pos, group) in sunks[first..]
+                .iter()
+                .take_while(|(pos, _)| pos + k <= bt + len)
+            {
+                let q = bq + (pos - bt);
+                // Query offsets of reverse alignments are along the reverse complement of the read.
+                let read_pos = if is_fwd {
+                    qst + q + 1
+                } else {
+                    qend - q - k + 1
+                };
+                reads.push(read.to_owned());
+                rpos.push(read_pos as i64);
+                ctgs.push(ctg.clone());
+                cpos.push(*pos as i64 + 1);
+                groups.push(*group);
+                strands.push(if is_fwd { "+" } else { "-" });
+            }
+        }
+    }
+    check_join_loss(
+        "PAF alignments x reads",
+        "reads missing from the reads FASTA, ex. from a different reads file or mismatched names",
+        n_alns,
+        n_unknown_reads,
+        state,
+    );
+    let lf_hits = DataFrame::new(vec![
+        Column::new("read".into(), reads),
+        Column::new("rpos".into(), rpos),
+        Column::new("ctg".into(), ctgs),
+        Column::new("cpos".into(), cpos),
+        Column::new("group".into(), groups),
+        Column::new("strand".into(), strands),
+    ])?
+    .lazy();
+    let df_read_sunks = dedup_hits(lf_hits, dedup)?;
+    log::info!("Total SUNKs in read alignments: {}", df_read_sunks.height());
+    Ok(df_read_sunks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{match_blocks, parse_cigar, parse_cs, AlnOp};
+
+    #[test]
+    fn test_parse_alignment_ops() {
+        assert_eq!(
+            parse_cigar("10M2I3D5=1X").unwrap(),
+            [
+                AlnOp::Match(10),
+                AlnOp::Ins(2),
+                AlnOp::Del(3),
+                AlnOp::Match(5),
+                AlnOp::Mismatch(1)
+            ]
+        );
+        assert_eq!(
+            parse_cs(":10*ag=ACG+tt-c").unwrap(),
+            [
+                AlnOp::Match(10),
+                AlnOp::Mismatch(1),
+                AlnOp::Match(3),
+                AlnOp::Ins(2),
+                AlnOp::Del(1)
+            ]
+        );
+        assert!(parse_cs(":10*a").is_err());
+    }
+
+    #[test]
+    fn test_match_blocks() {
+        // Adjacent matches merge. Mismatches and indels split blocks.
+        let ops = parse_cs(":10=AC*ag:5+tt:4-c:3").unwrap();
+        assert_eq!(
+            match_blocks(&ops, 100),
+            [(100, 0, 12), (113, 13, 5), (118, 20, 4), (123, 24, 3)]
+        );
+    }
+}
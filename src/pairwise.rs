@@ -0,0 +1,61 @@
+/// Lazily computed values between all pairs of elements in a slice.
+///
+/// Pairs `(i, j)` with `i < j` are yielded in condensed order, `(0, 1), (0, 2), ..., (1, 2), ...`,
+/// matching [`itertools::Itertools::combinations`]. Nothing is allocated so dense inputs don't require
+/// a full matrix.
+///
+/// # Examples
+/// ```
+/// use rs_gavisunk::pairwise::Pairwise;
+///
+/// let dsts: Vec<(usize, usize, i64)> = Pairwise::new(&[1, 4, 6], |a: &i64, b: &i64| (a - b).abs()).collect();
+/// assert_eq!(dsts, [(0, 1, 3), (0, 2, 5), (1, 2, 2)]);
+/// ```
+pub struct Pairwise<'a, T, F> {
+    values: &'a [T],
+    i: usize,
+    j: usize,
+    pair_fn: F,
+}
+
+impl<'a, T, F, D> Pairwise<'a, T, F>
+where
+    F: Fn(&T, &T) -> D,
+{
+    pub fn new(values: &'a [T], pair_fn: F) -> Self {
+        Self {
+            values,
+            i: 0,
+            j: 1,
+            pair_fn,
+        }
+    }
+}
+
+impl<T, F, D> Iterator for Pairwise<'_, T, F>
+where
+    F: Fn(&T, &T) -> D,
+{
+    type Item = (usize, usize, D);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.j >= self.values.len() {
+            self.i += 1;
+            self.j = self.i + 1;
+            if self.j >= self.values.len() {
+                return None;
+            }
+        }
+        let (i, j) = (self.i, self.j);
+        self.j += 1;
+        Some((i, j, (self.pair_fn)(&self.values[i], &self.values[j])))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.values.len();
+        // Remaining pairs in the current row and all following rows.
+        let rows_after = n.saturating_sub(self.i + 1);
+        let remaining = n.saturating_sub(self.j) + rows_after * rows_after.saturating_sub(1) / 2;
+        (remaining, Some(remaining))
+    }
+}
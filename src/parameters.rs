@@ -0,0 +1,227 @@
+//! Parameters of a run embedded in outputs so each result file describes how it was produced.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{error::Result, io::file_crc32};
+
+/// Parameters of a run. Written to `summary.json` and as a comment header of each final output.
+///
+/// Parameters of stages not built with the current features are [`None`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Parameters {
+    /// Version of `rs-gavisunk`.
+    pub version: String,
+    pub kmer_size: usize,
+    pub assembly: PathBuf,
+    pub reads: PathBuf,
+    pub rename_map: Option<PathBuf>,
+    /// Distance from a gap within which SUNKs are flagged as `near_gap`.
+    pub gap_flank: u64,
+    /// Fraction of rows a join may lose before warning.
+    pub max_join_loss: f64,
+    /// How multiple hits of the same SUNK group within a read are resolved.
+    pub sunk_dedup: Option<String>,
+    /// Maximum candidate contigs per read from minimizer pre-binning.
+    pub prebin: Option<usize>,
+    /// Percentile band used to assign reads to contigs.
+    pub bandwidth: Option<(f64, f64)>,
+    /// SUNK group read counts at or below this aren't used to find bad SUNKs.
+    pub min_mode_count: Option<u32>,
+    pub extra_bad_sunks: Option<PathBuf>,
+    pub spacing_tolerance: Option<f64>,
+    pub min_inversion_sunks: Option<usize>,
+    pub min_translocation_sunks: Option<usize>,
+    pub min_translocation_reads: Option<usize>,
+    pub min_phase_switch_sunks: Option<usize>,
+    /// Distance within which breakpoints of different reads are clustered.
+    pub breakpoint_slop: Option<i64>,
+    pub haplotypes: Option<PathBuf>,
+    pub multi_sunk: Option<String>,
+    pub dst_tolerance: Option<f32>,
+    pub min_component_sunks: Option<usize>,
+    /// Reads with more SUNK hits on a contig are skipped. `0` if unlimited.
+    pub max_read_sunks: Option<usize>,
+    /// Seconds to build a read's SUNK graph on a contig before it's skipped. `0` if unlimited.
+    pub read_timeout: Option<f64>,
+    pub min_depth: Option<f64>,
+    pub rescue_single_sunk: Option<bool>,
+    /// Minimum size of curation targets.
+    pub min_event_size: Option<u64>,
+    pub min_trim_sunks: Option<usize>,
+    pub exclude_regions: Option<PathBuf>,
+}
+
+impl Parameters {
+    /// Parameters as `#` comment lines prepended to text outputs.
+    pub fn comment_header(&self) -> String {
+        format!("# rs-gavisunk {self}\n")
+    }
+}
+
+/// Inputs larger than this are identified by size and modification time instead of being read in full.
+pub const MAX_CHECKSUM_BYTES: u64 = 64 << 20;
+
+/// Checksum of an input file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InputChecksum {
+    /// Role of the input. ex. `assembly`
+    pub name: String,
+    pub path: PathBuf,
+    /// Size in bytes.
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// CRC-32 as lowercase hex. [`None`] if larger than [`MAX_CHECKSUM_BYTES`].
+    pub crc32: Option<String>,
+}
+
+impl InputChecksum {
+    /// Checksum an input. Only inputs up to [`MAX_CHECKSUM_BYTES`] are read, so reads and assemblies of tens of
+    /// gigabases aren't read an extra time.
+    pub fn new(name: &str, path: &Path) -> Result<Self> {
+        let metadata = path.metadata()?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        let crc32 = (metadata.len() <= MAX_CHECKSUM_BYTES)
+            .then(|| file_crc32(path))
+            .transpose()?;
+        Ok(Self {
+            name: name.to_owned(),
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            mtime,
+            crc32,
+        })
+    }
+}
+
+/// Metadata of a run written as `#` comment lines at the top of each text output so files stay self-describing when
+/// copied away from the run's `summary.json`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunMetadata {
+    pub parameters: Parameters,
+    /// Start of the run in UTC. ex. `2024-01-01T00:00:00Z`
+    pub date: String,
+    pub inputs: Vec<InputChecksum>,
+}
+
+impl RunMetadata {
+    /// Checksum inputs and timestamp a run.
+    ///
+    /// # Arguments
+    /// * `parameters`
+    ///     * Parameters of the run.
+    /// * `inputs`
+    ///     * Role and path of each input. Paths that aren't files, ex. pipes, are skipped. See [`InputChecksum::new`].
+    pub fn new(parameters: Parameters, inputs: &[(&str, &Path)]) -> Result<Self> {
+        let inputs = inputs
+            .iter()
+            .filter(|(_, path)| path.is_file())
+            .map(|(name, path)| InputChecksum::new(name, path))
+            .collect::<Result<Vec<InputChecksum>>>()?;
+        Ok(Self {
+            parameters,
+            date: utc_timestamp(SystemTime::now()),
+            inputs,
+        })
+    }
+
+    /// Metadata as `#` comment lines. The tool version and parameters, the date, then one line per input.
+    pub fn comment_header(&self) -> String {
+        let mut header = self.parameters.comment_header();
+        header.push_str(&format!("# date={}\n", self.date));
+        for input in &self.inputs {
+            header.push_str(&format!(
+                "# input {}={:?} size={} mtime={}",
+                input.name, input.path, input.size, input.mtime
+            ));
+            if let Some(crc32) = &input.crc32 {
+                header.push_str(&format!(" crc32={crc32}"));
+            }
+            header.push('\n');
+        }
+        header
+    }
+}
+
+/// Format a time as an ISO 8601 UTC timestamp to the second. ex. `2024-01-01T00:00:00Z`
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs()) as i64;
+    let (days, secs) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    // Civil date from days since the epoch. See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+impl Display for Parameters {
+    /// Space-separated `key=value` pairs with values as JSON. Unset parameters are omitted.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(self) else {
+            return Err(std::fmt::Error);
+        };
+        let pairs = fields
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| format!("{key}={value}"))
+            .join(" ");
+        write!(f, "{pairs}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::{utc_timestamp, InputChecksum};
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            "2023-11-14T22:13:20Z"
+        );
+        // Leap day.
+        assert_eq!(
+            utc_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_input_checksum() {
+        let dir =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_input_checksum").unwrap();
+        let path = dir.path().join("asm.fa");
+        std::fs::write(&path, ">ctg\nACGT\n").unwrap();
+        let checksum = InputChecksum::new("assembly", &path).unwrap();
+        assert_eq!(checksum.size, 10);
+        assert!(checksum.mtime > 0);
+        assert_eq!(checksum.crc32, Some(crate::io::file_crc32(&path).unwrap()));
+    }
+}
@@ -0,0 +1,388 @@
+//! Run the pipeline from Rust without the binary.
+//!
+//! Stages run in memory. Unlike the binary, intermediates aren't cached, contigs aren't renamed, and no outputs are
+//! written.
+//!
+//! # Examples
+//! ```no_run
+//! use rs_gavisunk::pipeline::{Config, Pipeline};
+//!
+//! let config = Config::new("asm.fa", "ont.fa").with_kmer_size(20);
+//! let output = Pipeline::new(config)?.run()?;
+//! println!("{}", output.df_supported);
+//! # Ok::<(), rs_gavisunk::error::GavisunkError>(())
+//! ```
+
+use std::path::PathBuf;
+
+use polars::prelude::*;
+
+use crate::{
+    assign_read_ctg::{assign_read_to_ctg_w_ort, DEFAULT_BANDWIDTH},
+    cancel::CancelToken,
+    error::{GavisunkError, Result},
+    filter_bad_sunks::filter_bad_sunks,
+    get_kmers::{get_sunk_positions, DEFAULT_KMER_SIZE, MAX_KMER_SIZE},
+    hooks::{Observers, Stage},
+    io::Fasta,
+    map_kmers::{
+        get_good_read_sunks, map_sunks_to_read_records, map_sunks_to_reads, read_lengths_df,
+        DedupPolicy,
+    },
+    merge::concat_dfs,
+    run_state::RunState,
+    sunk_graph::{create_sunk_graph, GraphParams},
+};
+
+/// Inputs and parameters of a [`Pipeline`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Assembly FASTA.
+    pub assembly: PathBuf,
+    /// Reads FASTA.
+    pub reads: PathBuf,
+    /// Kmer size of SUNKs.
+    pub kmer_size: usize,
+    /// Lower and upper percentiles used to assign reads to contigs. See [`assign_read_to_ctg_w_ort`].
+    pub bandwidth: (f64, f64),
+    /// How multiple hits of a SUNK group within a read are resolved.
+    pub dedup: DedupPolicy,
+    /// Graph stage parameters.
+    pub graph: GraphParams,
+    /// Maximum total bases of reads mapped at once. If [`None`], all reads are mapped at once.
+    pub read_batch_bases: Option<u64>,
+    /// Memory budget in bytes for loading FASTA files. See [`Fasta::open`].
+    pub max_memory: Option<u64>,
+    /// Decompress gzipped FASTA files that aren't bgzipped to a temporary file.
+    pub allow_recompress: bool,
+}
+
+impl Config {
+    /// Config with default parameters.
+    pub fn new(assembly: impl Into<PathBuf>, reads: impl Into<PathBuf>) -> Self {
+        Self {
+            assembly: assembly.into(),
+            reads: reads.into(),
+            kmer_size: DEFAULT_KMER_SIZE,
+            bandwidth: DEFAULT_BANDWIDTH,
+            dedup: DedupPolicy::default(),
+            graph: GraphParams::default(),
+            read_batch_bases: None,
+            max_memory: None,
+            allow_recompress: false,
+        }
+    }
+
+    /// Set the kmer size of SUNKs.
+    pub fn with_kmer_size(mut self, kmer_size: usize) -> Self {
+        self.kmer_size = kmer_size;
+        self
+    }
+
+    /// Set the lower and upper percentiles used to assign reads to contigs. ex. `(0.25, 0.75)`
+    pub fn with_bandwidth(mut self, bandwidth: (f64, f64)) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// Set how multiple hits of a SUNK group within a read are resolved.
+    pub fn with_dedup(mut self, dedup: DedupPolicy) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Set the graph stage parameters.
+    pub fn with_graph_params(mut self, graph: GraphParams) -> Self {
+        self.graph = graph;
+        self
+    }
+
+    /// Set the maximum total bases of reads mapped at once. [`None`] maps all reads at once.
+    pub fn with_read_batch_bases(mut self, read_batch_bases: Option<u64>) -> Self {
+        self.read_batch_bases = read_batch_bases;
+        self
+    }
+
+    /// Set the memory budget in bytes for loading FASTA files. [`None`] for unlimited.
+    pub fn with_max_memory(mut self, max_memory: Option<u64>) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    /// Set whether gzipped FASTA files that aren't bgzipped are decompressed to a temporary file.
+    pub fn with_allow_recompress(mut self, allow_recompress: bool) -> Self {
+        self.allow_recompress = allow_recompress;
+        self
+    }
+}
+
+/// Outputs of [`Pipeline::graph`].
+#[derive(Debug, Clone)]
+pub struct PipelineOutput {
+    /// SUNKs in the largest component of each read with columns `[ctg, read, id, rpos, proj_cpos, offset_dev, component]`.
+    /// See [`create_sunk_graph`].
+    pub df_read_components: DataFrame,
+    /// Supported intervals with columns `[ctg, st, end, sunks]`.
+    pub df_supported: DataFrame,
+}
+
+/// Stages of the pipeline run on a [`Config`].
+///
+/// Each stage takes the outputs of earlier stages, so stages can be run one at a time, ex. to inspect or modify
+/// their outputs, or all at once with [`Pipeline::run`].
+pub struct Pipeline {
+    config: Config,
+    observers: Observers,
+    cancel: CancelToken,
+    state: RunState,
+}
+
+impl Pipeline {
+    /// Check the parameters of a [`Config`].
+    pub fn new(config: Config) -> Result<Self> {
+        if !(1..=MAX_KMER_SIZE).contains(&config.kmer_size) {
+            return Err(GavisunkError::InvalidKmerSize(config.kmer_size));
+        }
+        let (lower, upper) = config.bandwidth;
+        if !(0.0..=1.0).contains(&lower) || !(0.0..=1.0).contains(&upper) || lower >= upper {
+            return Err(GavisunkError::InvalidBandwidth(format!("{lower}:{upper}")));
+        }
+        Ok(Self {
+            config,
+            observers: Observers::default(),
+            cancel: CancelToken::new(),
+            state: RunState::new(),
+        })
+    }
+
+    /// Observers notified as stages start and end and contigs finish.
+    pub fn with_observers(mut self, observers: Observers) -> Self {
+        self.observers = observers;
+        self
+    }
+
+    /// Token checked by long-running stages. Cancelled stages return [`GavisunkError::Cancelled`].
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Settings of the run, ex. how many malformed records are skipped before failing. Records of the run, ex. filter
+    /// counts, are kept in it. See [`crate::filter_audit::filter_audit`].
+    pub fn with_state(mut self, state: RunState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Settings and records of the run. See [`Pipeline::with_state`].
+    pub fn state(&self) -> &RunState {
+        &self.state
+    }
+
+    /// Inputs and parameters of the pipeline.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Find assembly SUNKs.
+    ///
+    /// # Returns
+    /// * [`DataFrame`] with columns `[ctg, cpos, kmer, strand, group]`. See [`get_sunk_positions`].
+    pub fn sunks(&self) -> Result<DataFrame> {
+        self.observers.stage_start(Stage::Sunks);
+        let fasta = Fasta::open(
+            &self.config.assembly,
+            self.config.max_memory,
+            self.config.allow_recompress,
+            &self.state,
+        )?;
+        let lengths = fasta.lengths();
+        let (df_asm_sunks, _) =
+            get_sunk_positions(fasta, &lengths, self.config.kmer_size, None, &self.cancel)?;
+        self.observers
+            .frame(Stage::Sunks, "asm_sunks", &df_asm_sunks);
+        self.observers.stage_end(Stage::Sunks);
+        Ok(df_asm_sunks)
+    }
+
+    /// Map assembly SUNKs to reads.
+    ///
+    /// # Arguments
+    /// * `df_asm_sunks`
+    ///     * [`DataFrame`] of assembly SUNKs. See [`Pipeline::sunks`].
+    ///
+    /// # Returns
+    /// * [`DataFrame`] with columns `[read, rpos, ctg, cpos, group, strand, hits]`. See [`map_sunks_to_reads`].
+    pub fn map(&self, df_asm_sunks: &DataFrame) -> Result<DataFrame> {
+        self.observers.stage_start(Stage::Map);
+        let df_read_sunks = if Fasta::is_streamed(&self.config.reads)? {
+            map_sunks_to_read_records(
+                &self.config.reads,
+                df_asm_sunks,
+                self.config.read_batch_bases,
+                None,
+                self.config.dedup,
+                None,
+                None,
+                &self.state,
+                &self.cancel,
+            )?
+        } else {
+            let path_reads = Fasta::indexable_path(
+                &self.config.reads,
+                self.config.allow_recompress,
+                &self.state,
+            )?;
+            let fasta = Fasta::new(path_reads)?;
+            let lengths = fasta.lengths();
+            map_sunks_to_reads(
+                fasta,
+                &lengths,
+                df_asm_sunks,
+                self.config.read_batch_bases,
+                None,
+                self.config.dedup,
+                None,
+                None,
+                &self.state,
+                &self.cancel,
+            )?
+        };
+        self.observers
+            .frame(Stage::Map, "read_sunks", &df_read_sunks);
+        self.observers.stage_end(Stage::Map);
+        Ok(df_read_sunks)
+    }
+
+    /// Assign reads to contigs.
+    ///
+    /// # Arguments
+    /// * `df_read_sunks`
+    ///     * [`DataFrame`] of read SUNKs. See [`Pipeline::map`].
+    ///
+    /// # Returns
+    /// * [`DataFrame`] with columns `[read, ctg, sunks_within_bandwidth, ort]`. See [`assign_read_to_ctg_w_ort`].
+    pub fn assign(&self, df_read_sunks: &DataFrame) -> Result<DataFrame> {
+        self.observers.stage_start(Stage::Assign);
+        let df_best_reads_asm = assign_read_to_ctg_w_ort(
+            df_read_sunks,
+            Some(self.config.bandwidth),
+            None,
+            &self.state,
+        )?;
+        self.observers
+            .frame(Stage::Assign, "read_ctg_mapping", &df_best_reads_asm);
+        self.observers.stage_end(Stage::Assign);
+        Ok(df_best_reads_asm)
+    }
+
+    /// Find SUNK groups with abnormal read support among reads on their assigned contig.
+    ///
+    /// # Arguments
+    /// * `df_read_sunks`
+    ///     * [`DataFrame`] of read SUNKs. See [`Pipeline::map`].
+    /// * `df_best_reads_asm`
+    ///     * [`DataFrame`] of read assignments. See [`Pipeline::assign`].
+    ///
+    /// # Returns
+    /// * [`DataFrame`] of bad SUNK groups with columns `[id, count]`. See [`filter_bad_sunks`].
+    pub fn filter(
+        &self,
+        df_read_sunks: &DataFrame,
+        df_best_reads_asm: &DataFrame,
+    ) -> Result<DataFrame> {
+        self.observers.stage_start(Stage::Filter);
+        let df_good_sunks_reads =
+            get_good_read_sunks(df_read_sunks, df_best_reads_asm, &self.state)?;
+        let df_bad_sunks = filter_bad_sunks(&df_good_sunks_reads)?;
+        self.observers
+            .frame(Stage::Filter, "read_sunks_good", &df_good_sunks_reads);
+        self.observers
+            .frame(Stage::Filter, "read_sunks_bad", &df_bad_sunks);
+        self.observers.stage_end(Stage::Filter);
+        Ok(df_bad_sunks)
+    }
+
+    /// Build SUNK graphs of each contig with read SUNK hits.
+    ///
+    /// # Arguments
+    /// * `df_read_sunks`
+    ///     * [`DataFrame`] of read SUNKs. See [`Pipeline::map`].
+    /// * `df_bad_sunks`
+    ///     * [`DataFrame`] of bad SUNK groups to mask. See [`Pipeline::filter`].
+    pub fn graph(
+        &self,
+        df_read_sunks: &DataFrame,
+        df_bad_sunks: &DataFrame,
+    ) -> Result<PipelineOutput> {
+        self.observers.stage_start(Stage::Graph);
+        let asm_lens = Fasta::open(
+            &self.config.assembly,
+            self.config.max_memory,
+            self.config.allow_recompress,
+            &self.state,
+        )?
+        .lengths();
+        let df_read_lens = read_lengths_df(&Fasta::read_lengths(
+            &self.config.reads,
+            self.config.allow_recompress,
+            &self.state,
+        )?)?;
+
+        let (mut dfs_components, mut dfs_supported) = (vec![], vec![]);
+        for df_ctg in df_read_sunks.partition_by_stable(["ctg"], true)? {
+            self.cancel.check()?;
+            let Some(ctg) = df_ctg.column("ctg")?.str()?.first().map(str::to_owned) else {
+                continue;
+            };
+            let (mut df_sunks, df_bed) = create_sunk_graph(
+                &ctg,
+                &df_ctg,
+                &df_read_lens,
+                &asm_lens,
+                df_bad_sunks,
+                &self.config.graph,
+                &self.state,
+                &self.cancel,
+            )?;
+            self.observers.contig(&ctg, &df_sunks, &df_bed);
+            let col_ctg = Column::new("ctg".into(), vec![ctg.as_str(); df_sunks.height()]);
+            df_sunks.insert_column(0, col_ctg)?;
+            dfs_components.push(df_sunks);
+            dfs_supported.push(df_bed);
+        }
+        self.observers.stage_end(Stage::Graph);
+        Ok(PipelineOutput {
+            df_read_components: concat_dfs(
+                dfs_components,
+                &Schema::from_iter([
+                    Field::new("ctg".into(), DataType::String),
+                    Field::new("read".into(), DataType::String),
+                    Field::new("id".into(), DataType::String),
+                    Field::new("rpos".into(), DataType::Int64),
+                    Field::new("proj_cpos".into(), DataType::Int64),
+                    Field::new("offset_dev".into(), DataType::Int64),
+                    Field::new("component".into(), DataType::UInt64),
+                ]),
+            )?,
+            df_supported: concat_dfs(
+                dfs_supported,
+                &Schema::from_iter([
+                    Field::new("ctg".into(), DataType::String),
+                    Field::new("st".into(), DataType::Int64),
+                    Field::new("end".into(), DataType::Int64),
+                    Field::new("sunks".into(), DataType::UInt64),
+                ]),
+            )?,
+        })
+    }
+
+    /// Run all stages.
+    pub fn run(&self) -> Result<PipelineOutput> {
+        let df_asm_sunks = self.sunks()?;
+        let df_read_sunks = self.map(&df_asm_sunks)?;
+        let df_best_reads_asm = self.assign(&df_read_sunks)?;
+        let df_bad_sunks = self.filter(&df_read_sunks, &df_best_reads_asm)?;
+        self.graph(&df_read_sunks, &df_bad_sunks)
+    }
+}
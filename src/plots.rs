@@ -0,0 +1,467 @@
+use std::{collections::HashSet, fmt::Write as _, io::Write, path::Path};
+
+use polars::prelude::*;
+
+use crate::{error::Result, io::write_atomic, naming::ContigFilenames};
+
+const WIDTH: f64 = 600.0;
+const HEIGHT: f64 = 600.0;
+const MARGIN: f64 = 60.0;
+const FWD_COLOR: &str = "#1f77b4";
+const REV_COLOR: &str = "#d62728";
+const SUPPORTED_COLOR: &str = "#2ca02c";
+const PILEUP_WIDTH: f64 = 1000.0;
+/// Maximum rows of reads in a pileup.
+pub const MAX_PILEUP_ROWS: usize = 200;
+
+/// Render a dot plot of SUNK anchors as SVG, like a minidot.
+///
+/// Colinear anchors form a diagonal. Inversions flip its direction and translocations appear as offset diagonals.
+///
+/// # Arguments
+/// * `title`
+///     * Plot title.
+/// * `xlabel`, `ylabel`
+///     * Axis labels.
+/// * `anchors`
+///     * `(cpos, rpos, is_fwd)` of each anchor. Anchors on the reverse strand are colored red.
+///
+/// # Returns
+/// * SVG document.
+pub fn dot_plot_svg(
+    title: &str,
+    xlabel: &str,
+    ylabel: &str,
+    anchors: &[(i64, i64, bool)],
+) -> String {
+    let (x_min, x_max) = anchors
+        .iter()
+        .fold((i64::MAX, i64::MIN), |(lo, hi), (x, _, _)| {
+            (lo.min(*x), hi.max(*x))
+        });
+    let (y_min, y_max) = anchors
+        .iter()
+        .fold((i64::MAX, i64::MIN), |(lo, hi), (_, y, _)| {
+            (lo.min(*y), hi.max(*y))
+        });
+    let (x_span, y_span) = (
+        x_max.saturating_sub(x_min).max(1) as f64,
+        y_max.saturating_sub(y_min).max(1) as f64,
+    );
+    let (plot_w, plot_h) = (WIDTH - 2.0 * MARGIN, HEIGHT - 2.0 * MARGIN);
+
+    let mut svg = String::new();
+    // Writing to a String is infallible.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle" font-size="14">{}</text>"#,
+        WIDTH / 2.0,
+        MARGIN / 2.0,
+        escape(title)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{MARGIN}" y="{MARGIN}" width="{plot_w}" height="{plot_h}" fill="none" stroke="black"/>"#
+    );
+    // Axis bounds and labels.
+    if !anchors.is_empty() {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{MARGIN}" y="{}" text-anchor="start">{x_min}</text><text x="{}" y="{}" text-anchor="end">{x_max}</text>"#,
+            HEIGHT - MARGIN + 15.0,
+            WIDTH - MARGIN,
+            HEIGHT - MARGIN + 15.0,
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end">{y_min}</text><text x="{}" y="{}" text-anchor="end">{y_max}</text>"#,
+            MARGIN - 5.0,
+            HEIGHT - MARGIN,
+            MARGIN - 5.0,
+            MARGIN + 10.0,
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+        WIDTH / 2.0,
+        HEIGHT - MARGIN / 4.0,
+        escape(xlabel)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle" transform="rotate(-90 {} {})">{}</text>"#,
+        MARGIN / 3.0,
+        HEIGHT / 2.0,
+        MARGIN / 3.0,
+        HEIGHT / 2.0,
+        escape(ylabel)
+    );
+    for (x, y, is_fwd) in anchors {
+        let cx = MARGIN + (x - x_min) as f64 / x_span * plot_w;
+        // SVG y increases downwards.
+        let cy = HEIGHT - MARGIN - (y - y_min) as f64 / y_span * plot_h;
+        let _ = writeln!(
+            svg,
+            r#"<circle cx="{cx:.1}" cy="{cy:.1}" r="1.5" fill="{}"/>"#,
+            if *is_fwd { FWD_COLOR } else { REV_COLOR }
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render a histogram as SVG with vertical lines at cutoffs.
+///
+/// # Arguments
+/// * `title`
+///     * Plot title.
+/// * `xlabel`, `ylabel`
+///     * Axis labels.
+/// * `bars`
+///     * `(x, height, is_highlighted)` of each bar. Highlighted bars are colored red.
+/// * `cutoffs`
+///     * x positions of dashed vertical lines.
+///
+/// # Returns
+/// * SVG document.
+pub fn histogram_svg(
+    title: &str,
+    xlabel: &str,
+    ylabel: &str,
+    bars: &[(u64, u64, bool)],
+    cutoffs: &[f64],
+) -> String {
+    let x_max = bars
+        .iter()
+        .map(|(x, _, _)| *x as f64 + 1.0)
+        .chain(cutoffs.iter().map(|cutoff| cutoff + 1.0))
+        .fold(1.0, f64::max);
+    let y_max = bars.iter().map(|(_, y, _)| *y).max().unwrap_or(1).max(1);
+    let (plot_w, plot_h) = (WIDTH - 2.0 * MARGIN, HEIGHT - 2.0 * MARGIN);
+    let bar_w = (plot_w / x_max).max(1.0);
+
+    let mut svg = String::new();
+    // Writing to a String is infallible.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle" font-size="14">{}</text>"#,
+        WIDTH / 2.0,
+        MARGIN / 2.0,
+        escape(title)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{MARGIN}" y="{MARGIN}" width="{plot_w}" height="{plot_h}" fill="none" stroke="black"/>"#
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{MARGIN}" y="{}" text-anchor="start">0</text><text x="{}" y="{}" text-anchor="end">{}</text>"#,
+        HEIGHT - MARGIN + 15.0,
+        WIDTH - MARGIN,
+        HEIGHT - MARGIN + 15.0,
+        x_max as u64,
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="end">0</text><text x="{}" y="{}" text-anchor="end">{y_max}</text>"#,
+        MARGIN - 5.0,
+        HEIGHT - MARGIN,
+        MARGIN - 5.0,
+        MARGIN + 10.0,
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+        WIDTH / 2.0,
+        HEIGHT - MARGIN / 4.0,
+        escape(xlabel)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle" transform="rotate(-90 {} {})">{}</text>"#,
+        MARGIN / 3.0,
+        HEIGHT / 2.0,
+        MARGIN / 3.0,
+        HEIGHT / 2.0,
+        escape(ylabel)
+    );
+    for (x, y, is_highlighted) in bars {
+        let h = *y as f64 / y_max as f64 * plot_h;
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{bar_w:.1}" height="{h:.1}" fill="{}"/>"#,
+            MARGIN + *x as f64 / x_max * plot_w,
+            HEIGHT - MARGIN - h,
+            if *is_highlighted {
+                REV_COLOR
+            } else {
+                FWD_COLOR
+            }
+        );
+    }
+    for cutoff in cutoffs {
+        let cx = MARGIN + (cutoff + 0.5) / x_max * plot_w;
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{cx:.1}" y1="{MARGIN}" x2="{cx:.1}" y2="{}" stroke="black" stroke-dasharray="4"/>"#,
+            HEIGHT - MARGIN
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Render read spans stacked along a contig as SVG, like a genome browser pileup.
+///
+/// # Arguments
+/// * `title`
+///     * Plot title.
+/// * `ctg_len`
+///     * Contig length.
+/// * `supported`, `unsupported`
+///     * `(st, end)` of supported and unsupported intervals drawn as a track above the reads.
+/// * `reads`
+///     * `(st, end, is_fwd)` contig span of each read. Reads on the reverse strand are colored red.
+///       Reads beyond [`MAX_PILEUP_ROWS`] rows are left out.
+///
+/// # Returns
+/// * SVG document.
+pub fn pileup_svg(
+    title: &str,
+    ctg_len: i64,
+    supported: &[(i64, i64)],
+    unsupported: &[(i64, i64)],
+    reads: &[(i64, i64, bool)],
+) -> String {
+    // Greedily stack reads into the first row they don't overlap.
+    let mut reads = reads.to_vec();
+    reads.sort_unstable();
+    let mut row_ends: Vec<i64> = vec![];
+    let mut rows: Vec<(usize, i64, i64, bool)> = vec![];
+    for (st, end, is_fwd) in reads {
+        let row = match row_ends.iter().position(|row_end| *row_end < st) {
+            Some(row) => row,
+            None if row_ends.len() < MAX_PILEUP_ROWS => {
+                row_ends.push(i64::MIN);
+                row_ends.len() - 1
+            }
+            None => continue,
+        };
+        row_ends[row] = end;
+        rows.push((row, st, end, is_fwd));
+    }
+    let (plot_w, row_h, track_h) = (PILEUP_WIDTH - 2.0 * MARGIN, 4.0, 10.0);
+    let height = 2.0 * MARGIN + track_h + 5.0 + row_ends.len() as f64 * row_h;
+    let x = |pos: i64| MARGIN + pos as f64 / ctg_len.max(1) as f64 * plot_w;
+
+    let mut svg = String::new();
+    // Writing to a String is infallible.
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{PILEUP_WIDTH}" height="{height}" font-family="sans-serif" font-size="12">"#
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle" font-size="14">{}</text>"#,
+        PILEUP_WIDTH / 2.0,
+        MARGIN / 2.0,
+        escape(title)
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="{MARGIN}" y="{}" text-anchor="start">0</text><text x="{}" y="{}" text-anchor="end">{ctg_len}</text>"#,
+        height - MARGIN / 2.0,
+        PILEUP_WIDTH - MARGIN,
+        height - MARGIN / 2.0,
+    );
+    for (intervals, color) in [(supported, SUPPORTED_COLOR), (unsupported, REV_COLOR)] {
+        for (st, end) in intervals {
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{:.1}" y="{MARGIN}" width="{:.1}" height="{track_h}" fill="{color}"/>"#,
+                x(*st),
+                (x(*end) - x(*st)).max(0.5),
+            );
+        }
+    }
+    for (row, st, end, is_fwd) in rows {
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"/>"#,
+            x(st),
+            MARGIN + track_h + 5.0 + row as f64 * row_h,
+            (x(end) - x(st)).max(0.5),
+            row_h - 1.0,
+            if is_fwd { FWD_COLOR } else { REV_COLOR }
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Write a dot plot of SUNK anchors for each contig a read has hits on.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, strand, ...]`
+/// * `reads`
+///     * Reads to plot.
+/// * `outdir`
+///     * Output directory. Plots are written to `{read}__{ctg}.svg` with path-safe names, suffixed if they collide.
+///       See [`ContigFilenames`].
+///
+/// # Returns
+/// * Number of plots written.
+pub fn write_read_dot_plots(
+    df_read_sunks: &DataFrame,
+    reads: &[String],
+    outdir: impl AsRef<Path>,
+) -> Result<usize> {
+    let outdir = outdir.as_ref();
+    std::fs::create_dir_all(outdir)?;
+    let reads: HashSet<&str> = reads.iter().map(|read| read.as_str()).collect();
+    let mask: BooleanChunked = df_read_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .map(|read| read.is_some_and(|read| reads.contains(read)))
+        .collect();
+    let df_reads = df_read_sunks
+        .filter(&mask)?
+        .lazy()
+        .select([
+            col("read"),
+            col("ctg"),
+            col("cpos").cast(DataType::Int64),
+            col("rpos").cast(DataType::Int64),
+            col("strand"),
+        ])
+        .collect()?;
+
+    let mut groups = vec![];
+    for df_grp in df_reads.partition_by(["read", "ctg"], true)? {
+        let (Some(read), Some(ctg)) = (
+            df_grp.column("read")?.str()?.first().map(str::to_owned),
+            df_grp.column("ctg")?.str()?.first().map(str::to_owned),
+        ) else {
+            continue;
+        };
+        groups.push((format!("{read}__{ctg}"), read, ctg, df_grp));
+    }
+    // Sanitized read and contig names can collide.
+    let fnames = ContigFilenames::new(groups.iter().map(|(name, _, _, _)| name.as_str()));
+
+    let mut n_plots = 0;
+    let mut plotted = HashSet::new();
+    for (name, read, ctg, df_grp) in &groups {
+        let anchors: Vec<(i64, i64, bool)> = df_grp
+            .column("cpos")?
+            .i64()?
+            .iter()
+            .zip(df_grp.column("rpos")?.i64()?.iter())
+            .zip(df_grp.column("strand")?.str()?.iter())
+            .flat_map(|((cpos, rpos), strand)| Some((cpos?, rpos?, strand? == "+")))
+            .collect();
+        let svg = dot_plot_svg(
+            &format!("{read} vs {ctg} ({} SUNKs)", anchors.len()),
+            &format!("{ctg} position"),
+            &format!("{read} position"),
+            &anchors,
+        );
+        let path = outdir.join(format!("{}.svg", fnames.get(name)));
+        write_atomic(&path, |file| Ok(file.write_all(svg.as_bytes())?))?;
+        plotted.insert(read.as_str());
+        n_plots += 1;
+    }
+    for read in reads.into_iter().filter(|read| !plotted.contains(*read)) {
+        log::warn!("No SUNK hits for {read}. No dot plot written.");
+    }
+    Ok(n_plots)
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{dot_plot_svg, write_read_dot_plots, FWD_COLOR, REV_COLOR};
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_dot_plot_svg() {
+        let svg = dot_plot_svg(
+            "<r1> vs c1",
+            "c1 position",
+            "r1 position",
+            &[(100, 1, true), (200, 101, true), (300, 201, false)],
+        );
+        assert!(svg.contains("&lt;r1&gt; vs c1"));
+        assert_eq!(svg.matches("<circle").count(), 3);
+        assert_eq!(svg.matches(REV_COLOR).count(), 1);
+        assert_eq!(svg.matches(FWD_COLOR).count(), 2);
+    }
+
+    #[test]
+    fn test_write_read_dot_plots() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_dot_plots").unwrap();
+        let df_read_sunks = df!(
+            "read" => ["r1", "r1", "r1", "r2"],
+            "rpos" => [1i64, 101, 51, 1],
+            "ctg" => ["c1", "c1", "c2", "c1"],
+            "cpos" => [100i64, 200, 500, 100],
+            "strand" => ["+", "+", "-", "+"],
+        )
+        .unwrap();
+        let reads = ["r1", "r3"].map(str::to_owned);
+        let outdir = dir.path().join("dot_plots");
+        // One per contig of r1. r2 isn't requested and r3 has no hits.
+        assert_eq!(
+            write_read_dot_plots(&df_read_sunks, &reads, &outdir).unwrap(),
+            2
+        );
+        let svg = std::fs::read_to_string(outdir.join("r1__c1.svg")).unwrap();
+        assert_eq!(svg.matches("<circle").count(), 2);
+        assert!(outdir.join("r1__c2.svg").exists());
+        assert_eq!(std::fs::read_dir(&outdir).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_write_read_dot_plots_collisions() {
+        let dir =
+            ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_dot_plot_names").unwrap();
+        // Both reads sanitize to r_1.
+        let df_read_sunks = df!(
+            "read" => ["r/1", "r:1"],
+            "rpos" => [1i64, 1],
+            "ctg" => ["c1", "c1"],
+            "cpos" => [100i64, 100],
+            "strand" => ["+", "+"],
+        )
+        .unwrap();
+        let reads = ["r/1", "r:1"].map(str::to_owned);
+        let outdir = dir.path().join("dot_plots");
+        assert_eq!(
+            write_read_dot_plots(&df_read_sunks, &reads, &outdir).unwrap(),
+            2
+        );
+        assert!(outdir.join("r_1__c1.svg").exists());
+        assert!(outdir.join("r_1__c1_2.svg").exists());
+    }
+}
@@ -0,0 +1,274 @@
+//! Regions to revisit when polishing the assembly, each with a suggested action.
+//!
+//! Combines unsupported intervals, components whose reads suggest a collapsed repeat, and windows dense with bad SUNKs
+//! into one BED for downstream polishing workflows.
+
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+
+use crate::{
+    dropouts::INCONSISTENT_SUNKS, error::Result, get_kmers::sunk_id, ratio, spacing::COLLAPSED,
+};
+
+/// Look at the region by hand. ex. no SUNKs or reads to say what's wrong.
+pub const INSPECT: &str = "inspect";
+/// Reads span the region with a consistent size difference that polishing can fix.
+pub const REPOLISH: &str = "re-polish";
+/// No read spans the region between supported sequence on both sides. Likely a misjoin.
+pub const BREAK: &str = "break";
+
+/// Unsupported interval. See [`crate::merge::get_unsupported_intervals`].
+pub const UNSUPPORTED: &str = "unsupported";
+/// Supported component with reads spanning more bases than the contig.
+/// See [`crate::spacing::component_scale_stats`].
+pub const COLLAPSED_REPEAT: &str = "collapsed_repeat";
+/// Window where many SUNKs have abnormal read support. See [`crate::filter_bad_sunks`].
+pub const BAD_SUNK_DENSITY: &str = "bad_sunk_density";
+
+/// Size of windows bad SUNK density is measured in.
+pub const BAD_SUNK_WINDOW: i64 = 10_000;
+/// Minimum SUNKs in a window to measure its bad SUNK density.
+const MIN_WINDOW_SUNKS: u64 = 10;
+/// Minimum fraction of bad SUNKs in a window for it to be a target. Inclusive.
+pub const MIN_BAD_SUNK_FRACTION: f64 = 0.5;
+
+/// Suggested action for an unsupported interval.
+///
+/// * [`BREAK`] if its SUNKs are inconsistent and no read spans it away from the contig ends.
+/// * [`REPOLISH`] if its SUNKs are inconsistent but reads span it.
+/// * [`INSPECT`] otherwise.
+fn unsupported_action(label: &str, spanning_reads: u64, is_breakpoint: bool) -> &'static str {
+    match (label == INCONSISTENT_SUNKS, spanning_reads > 0) {
+        (true, true) => REPOLISH,
+        (true, false) if is_breakpoint => BREAK,
+        _ => INSPECT,
+    }
+}
+
+/// Merged windows of [`BAD_SUNK_WINDOW`] bp with at least [`MIN_BAD_SUNK_FRACTION`] bad SUNKs.
+///
+/// # Returns
+/// * `(ctg, st, end, bad_sunks, sunks)` sorted by contig and start.
+fn bad_sunk_windows(
+    df_asm_sunks: &DataFrame,
+    df_bad_sunks: &DataFrame,
+) -> Result<Vec<(String, i64, i64, u64, u64)>> {
+    let bad: HashSet<&str> = df_bad_sunks.column("id")?.str()?.iter().flatten().collect();
+    let cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let group = df_asm_sunks.column("group")?.cast(&DataType::Int64)?;
+    // Bad and total SUNKs by window index.
+    let mut counts: HashMap<(&str, i64), (u64, u64)> = HashMap::new();
+    for ((ctg, cpos), group) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(cpos.i64()?.iter())
+        .zip(group.i64()?.iter())
+    {
+        let (Some(ctg), Some(cpos), Some(group)) = (ctg, cpos, group) else {
+            continue;
+        };
+        // cpos is 1-based.
+        let (n_bad, n_sunks) = counts
+            .entry((ctg, (cpos - 1) / BAD_SUNK_WINDOW))
+            .or_default();
+        *n_bad += u64::from(bad.contains(sunk_id(ctg, group).as_str()));
+        *n_sunks += 1;
+    }
+    let min_ppm = ratio::to_ppm(MIN_BAD_SUNK_FRACTION);
+    let mut dense: Vec<(&str, i64, u64, u64)> = counts
+        .into_iter()
+        .filter(|(_, (n_bad, n_sunks))| {
+            *n_sunks >= MIN_WINDOW_SUNKS && ratio::at_least(*n_bad, *n_sunks, min_ppm)
+        })
+        .map(|((ctg, idx), (n_bad, n_sunks))| (ctg, idx, n_bad, n_sunks))
+        .collect();
+    dense.sort_unstable();
+
+    // Merge adjacent windows.
+    let mut windows: Vec<(String, i64, i64, u64, u64)> = vec![];
+    for (ctg, idx, n_bad, n_sunks) in dense {
+        let (st, end) = (idx * BAD_SUNK_WINDOW, (idx + 1) * BAD_SUNK_WINDOW);
+        match windows.last_mut() {
+            Some((prev_ctg, _, prev_end, prev_bad, prev_sunks))
+                if prev_ctg == ctg && *prev_end == st =>
+            {
+                *prev_end = end;
+                *prev_bad += n_bad;
+                *prev_sunks += n_sunks;
+            }
+            _ => windows.push((ctg.to_owned(), st, end, n_bad, n_sunks)),
+        }
+    }
+    Ok(windows)
+}
+
+/// Regions to revisit when polishing with a suggested action.
+///
+/// # Arguments
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end, spanning_reads, label, ...]`.
+///       See [`crate::dropouts::label_dropouts`].
+/// * `df_scales`
+///     * [`DataFrame`] of supported components with columns `[ctg, st, end, scale, status, ...]`.
+///       See [`crate::spacing::component_scale_stats`].
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, group, ...]`
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNKs with column `[id, ...]`. See [`sunk_id`].
+/// * `asm_lens`
+///     * Contig lengths. Bad SUNK windows are clipped to them.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, st, end, action, source, evidence]`
+///     * `action` is [`INSPECT`], [`REPOLISH`], or [`BREAK`].
+///     * `source` is [`UNSUPPORTED`], [`COLLAPSED_REPEAT`], or [`BAD_SUNK_DENSITY`].
+///     * `evidence` is the unsupported interval's label, the component's scale, or the bad and total SUNKs of the
+///       window. ex. `bad_sunks=12/20`
+///     * Targets from different sources may overlap.
+pub fn polish_targets(
+    df_unsupported: &DataFrame,
+    df_scales: &DataFrame,
+    df_asm_sunks: &DataFrame,
+    df_bad_sunks: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+) -> Result<DataFrame> {
+    let (mut ctgs, mut starts, mut ends) = (vec![], vec![], vec![]);
+    let (mut actions, mut sources, mut evidence) = (vec![], vec![], vec![]);
+
+    for ((((ctg, st), end), n_spanning), label) in df_unsupported
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_unsupported.column("st")?.i64()?.iter())
+        .zip(df_unsupported.column("end")?.i64()?.iter())
+        .zip(df_unsupported.column("spanning_reads")?.u64()?.iter())
+        .zip(df_unsupported.column("label")?.str()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end), Some(label)) = (ctg, st, end, label) else {
+            continue;
+        };
+        let len = asm_lens.get(ctg).copied().unwrap_or_default() as i64;
+        ctgs.push(ctg.to_owned());
+        starts.push(st);
+        ends.push(end);
+        actions.push(unsupported_action(
+            label,
+            n_spanning.unwrap_or_default(),
+            st > 0 && end < len,
+        ));
+        sources.push(UNSUPPORTED);
+        evidence.push(label.to_owned());
+    }
+
+    for (((ctg, st), end), (scale, status)) in df_scales
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_scales.column("st")?.i64()?.iter())
+        .zip(df_scales.column("end")?.i64()?.iter())
+        .zip(
+            df_scales
+                .column("scale")?
+                .f64()?
+                .iter()
+                .zip(df_scales.column("status")?.str()?.iter()),
+        )
+    {
+        let (Some(ctg), Some(st), Some(end), Some(scale), Some(COLLAPSED)) =
+            (ctg, st, end, scale, status)
+        else {
+            continue;
+        };
+        ctgs.push(ctg.to_owned());
+        starts.push(st);
+        ends.push(end);
+        // Polishing can't add back missing copies of a repeat.
+        actions.push(INSPECT);
+        sources.push(COLLAPSED_REPEAT);
+        evidence.push(format!("scale={scale:.3}"));
+    }
+
+    for (ctg, st, end, n_bad, n_sunks) in bad_sunk_windows(df_asm_sunks, df_bad_sunks)? {
+        let len = asm_lens.get(&ctg).copied().unwrap_or(end as u64) as i64;
+        ctgs.push(ctg);
+        starts.push(st);
+        ends.push(end.min(len));
+        actions.push(INSPECT);
+        sources.push(BAD_SUNK_DENSITY);
+        evidence.push(format!("bad_sunks={n_bad}/{n_sunks}"));
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("st".into(), starts),
+        Column::new("end".into(), ends),
+        Column::new("action".into(), actions),
+        Column::new("source".into(), sources),
+        Column::new("evidence".into(), evidence),
+    ])?
+    .sort(["ctg", "st"], Default::default())?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{polish_targets, BREAK, INSPECT, REPOLISH};
+
+    #[test]
+    fn test_polish_targets() {
+        let df_unsupported = df!(
+            "ctg" => ["ctg1", "ctg1", "ctg1"],
+            "st" => [0i64, 40_000, 60_000],
+            "end" => [1_000i64, 41_000, 61_000],
+            "spanning_reads" => [0u64, 3, 0],
+            "label" => ["no_reads", "inconsistent_sunks", "inconsistent_sunks"],
+        )
+        .unwrap();
+        let df_scales = df!(
+            "ctg" => ["ctg1", "ctg1"],
+            "st" => [1_000i64, 41_000],
+            "end" => [40_000i64, 60_000],
+            "scale" => [Some(0.8), None],
+            "status" => [Some("collapsed"), None],
+        )
+        .unwrap();
+        // Half of the SUNKs in the second window are bad. The first window has too few SUNKs.
+        let cpos: Vec<i64> = (0..5).chain((0..20).map(|i| 10_001 + i * 100)).collect();
+        let groups: Vec<i64> = (0..cpos.len() as i64).collect();
+        let df_asm_sunks = df!(
+            "ctg" => vec!["ctg1"; cpos.len()],
+            "cpos" => cpos,
+            "group" => groups,
+        )
+        .unwrap();
+        let bad: Vec<String> = (0..15).map(|group| format!("ctg1:{group}")).collect();
+        let df_bad_sunks = df!("id" => bad).unwrap();
+        let asm_lens = HashMap::from([("ctg1".to_owned(), 100_000)]);
+
+        let df = polish_targets(
+            &df_unsupported,
+            &df_scales,
+            &df_asm_sunks,
+            &df_bad_sunks,
+            &asm_lens,
+        )
+        .unwrap();
+        assert_eq!(
+            df,
+            df!(
+                "ctg" => ["ctg1"; 5],
+                "st" => [0i64, 1_000, 10_000, 40_000, 60_000],
+                "end" => [1_000i64, 40_000, 20_000, 41_000, 61_000],
+                "action" => [INSPECT, INSPECT, INSPECT, REPOLISH, BREAK],
+                "source" => ["unsupported", "collapsed_repeat", "bad_sunk_density", "unsupported", "unsupported"],
+                "evidence" => ["no_reads", "scale=0.800", "bad_sunks=10/20", "inconsistent_sunks", "inconsistent_sunks"],
+            )
+            .unwrap()
+        );
+    }
+}
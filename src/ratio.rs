@@ -0,0 +1,81 @@
+//! Exact comparisons of ratios against fractional thresholds.
+//!
+//! A ratio computed in floating point, ex. `9.0 / 10.0` against `1.0 - 0.1`, can land on either side of a threshold
+//! depending on precision and evaluation order. Thresholds are instead rounded to parts per million and compared with
+//! integer arithmetic, so boundaries behave the same on every platform and version.
+
+/// Parts per million in a whole.
+pub const PPM: i128 = 1_000_000;
+
+/// A fraction rounded to the nearest part per million. ex. `0.9` is `900_000`
+pub fn to_ppm(fraction: f64) -> i128 {
+    (fraction * PPM as f64).round() as i128
+}
+
+/// Whether `num / den` is at least `min_ppm` parts per million. Inclusive.
+///
+/// # Returns
+/// * `false` if `den` is 0.
+pub fn at_least(num: u64, den: u64, min_ppm: i128) -> bool {
+    den > 0 && i128::from(num) * PPM >= i128::from(den) * min_ppm
+}
+
+/// Whether `num / |den|` is strictly within `tolerance_ppm` parts per million of 1, the open interval
+/// `(1 - tolerance, 1 + tolerance)`. Ratios exactly at the tolerance are outside.
+///
+/// # Returns
+/// * `false` if `den` is 0.
+pub fn within_tolerance(num: i64, den: i64, tolerance_ppm: i128) -> bool {
+    let den = i128::from(den).abs();
+    den != 0 && (i128::from(num) - den).abs() * PPM < den * tolerance_ppm
+}
+
+/// Deviation of a floating point ratio from 1 rounded to the nearest part per million.
+///
+/// Compared against a tolerance from [`to_ppm`], ratios within rounding error of the tolerance fall on the boundary.
+pub fn deviation_ppm(ratio: f64) -> i128 {
+    to_ppm(ratio - 1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{at_least, deviation_ppm, to_ppm, within_tolerance};
+
+    #[test]
+    fn test_to_ppm() {
+        assert_eq!(to_ppm(0.9), 900_000);
+        // f32 tolerances widened to f64 aren't exact.
+        assert_eq!(to_ppm(f64::from(0.1f32)), 100_000);
+        assert_eq!(to_ppm(f64::from(0.02f32)), 20_000);
+    }
+
+    #[test]
+    fn test_at_least() {
+        let min_ppm = to_ppm(0.9);
+        assert!(at_least(9, 10, min_ppm));
+        assert!(at_least(900_000_000_000, 1_000_000_000_000, min_ppm));
+        assert!(!at_least(899_999, 1_000_000, min_ppm));
+        assert!(!at_least(0, 0, min_ppm));
+    }
+
+    #[test]
+    fn test_within_tolerance() {
+        let tolerance_ppm = to_ppm(f64::from(0.1f32));
+        // Exactly at 0.9 and 1.1 is outside.
+        assert!(!within_tolerance(9, 10, tolerance_ppm));
+        assert!(!within_tolerance(11, 10, tolerance_ppm));
+        assert!(within_tolerance(91, 100, tolerance_ppm));
+        assert!(within_tolerance(109, 100, tolerance_ppm));
+        // Direction of the contig distance doesn't matter.
+        assert!(within_tolerance(95, -100, tolerance_ppm));
+        assert!(!within_tolerance(0, 0, tolerance_ppm));
+    }
+
+    #[test]
+    fn test_deviation_ppm() {
+        // 1.1 - 1.0 isn't exactly 0.1 in floating point.
+        assert_eq!(deviation_ppm(1.1), to_ppm(0.1));
+        assert_eq!(deviation_ppm(0.9), -to_ppm(0.1));
+        assert_eq!(deviation_ppm(1.0), 0);
+    }
+}
@@ -0,0 +1,238 @@
+//! Trim and split suggestions for chimeric reads.
+//!
+//! A read is likely chimeric if SUNK evidence outside its validated segment disagrees with it, ex. a second segment
+//! on another contig or a run of SUNKs beyond its end inconsistent with its component. Suggestions are breakpoint
+//! intervals within reads that can be fed to trimming tools in a reassembly loop.
+
+use std::collections::HashMap;
+
+use polars::prelude::*;
+
+use crate::error::Result;
+
+/// Read is split within the breakpoint between two validated segments.
+pub const SPLIT: &str = "split";
+/// Read is trimmed at the breakpoint between a validated segment and inconsistent SUNKs towards the read end.
+pub const TRIM: &str = "trim";
+/// Default minimum SUNKs on each side of a breakpoint.
+pub const DEFAULT_MIN_TRIM_SUNKS: usize = 3;
+
+/// Validated segment of a read.
+#[derive(Debug, Clone, Copy)]
+struct Segment<'a> {
+    st: i64,
+    end: i64,
+    ctg: &'a str,
+    sunks: u64,
+}
+
+/// Suggest breakpoints within reads where SUNK evidence indicates chimerism.
+///
+/// # Arguments
+/// * `df_read_intervals`
+///     * [`DataFrame`] of validated read segments with columns `[read, st, end, ctg, sunks, ...]`.
+///     * See [`crate::merge::read_intervals`].
+/// * `df_read_sunks`
+///     * [`DataFrame`] of all read SUNK hits with columns `[read, rpos, ...]`
+/// * `read_lens`
+///     * Read lengths.
+/// * `kmer_size`
+///     * kmer size.
+/// * `min_sunks`
+///     * Minimum SUNKs on each side of a breakpoint.
+///
+/// # Returns
+/// * [`DataFrame`] with one row per breakpoint and columns `[read, read_length, st, end, action, left_ctg, right_ctg, sunks]`
+///     * `st` and `end` are the 0-based, half-open read interval containing the breakpoint.
+///     * `action` is [`SPLIT`] or [`TRIM`].
+///     * `left_ctg` and `right_ctg` are the contigs of the validated segments on each side, if any.
+///     * `sunks` is the number of SUNKs on the side of the breakpoint with less evidence.
+pub fn read_trim_suggestions(
+    df_read_intervals: &DataFrame,
+    df_read_sunks: &DataFrame,
+    read_lens: &HashMap<String, u64>,
+    kmer_size: usize,
+    min_sunks: usize,
+) -> Result<DataFrame> {
+    let (kmer_size, min_sunks) = (i64::try_from(kmer_size)?, min_sunks as u64);
+    let mut segments: HashMap<&str, Vec<Segment>> = HashMap::new();
+    let sunks = df_read_intervals.column("sunks")?.cast(&DataType::UInt64)?;
+    for ((((read, st), end), ctg), sunks) in df_read_intervals
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_read_intervals.column("st")?.i64()?.iter())
+        .zip(df_read_intervals.column("end")?.i64()?.iter())
+        .zip(df_read_intervals.column("ctg")?.str()?.iter())
+        .zip(sunks.u64()?.iter())
+    {
+        let (Some(read), Some(st), Some(end), Some(ctg), Some(sunks)) = (read, st, end, ctg, sunks)
+        else {
+            continue;
+        };
+        segments.entry(read).or_default().push(Segment {
+            st,
+            end,
+            ctg,
+            sunks,
+        });
+    }
+
+    // SUNK hits of reads with a validated segment.
+    let rpos = df_read_sunks.column("rpos")?.cast(&DataType::Int64)?;
+    let mut hits: HashMap<&str, Vec<i64>> = HashMap::new();
+    for (read, rpos) in df_read_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(rpos.i64()?.iter())
+    {
+        let (Some(read), Some(rpos)) = (read, rpos) else {
+            continue;
+        };
+        if segments.contains_key(read) {
+            // SUNK positions are 1-based.
+            hits.entry(read).or_default().push(rpos - 1);
+        }
+    }
+
+    let (mut reads, mut read_lengths, mut sts, mut ends) = (vec![], vec![], vec![], vec![]);
+    let (mut actions, mut left_ctgs, mut right_ctgs, mut n_sunks) =
+        (vec![], vec![], vec![], vec![]);
+    let mut add = |read: &str, (st, end): (i64, i64), action, left, right, sunks: u64| {
+        reads.push(read.to_owned());
+        read_lengths.push(read_lens.get(read).copied());
+        sts.push(st);
+        ends.push(end);
+        actions.push(action);
+        left_ctgs.push(left);
+        right_ctgs.push(right);
+        n_sunks.push(sunks);
+    };
+
+    let mut read_names: Vec<&&str> = segments.keys().collect();
+    read_names.sort();
+    for read in read_names {
+        let mut read_segments: Vec<Segment> = segments[*read]
+            .iter()
+            .filter(|segment| segment.sunks >= min_sunks)
+            .copied()
+            .collect();
+        read_segments.sort_by_key(|segment| segment.st);
+        let (Some(first), Some(last)) = (read_segments.first(), read_segments.last()) else {
+            continue;
+        };
+        // Split between non-overlapping segments.
+        for (left, right) in read_segments.iter().zip(read_segments.iter().skip(1)) {
+            if right.st >= left.end {
+                add(
+                    read,
+                    (left.end, right.st),
+                    SPLIT,
+                    Some(left.ctg.to_owned()),
+                    Some(right.ctg.to_owned()),
+                    left.sunks.min(right.sunks),
+                );
+            }
+        }
+        // Trim ends with SUNKs outside of all segments.
+        let read_hits = hits.get(*read).map_or(&[][..], |hits| hits.as_slice());
+        let left_hits: Vec<i64> = read_hits
+            .iter()
+            .filter(|pos| **pos + kmer_size <= first.st)
+            .copied()
+            .collect();
+        if left_hits.len() as u64 >= min_sunks {
+            let bp_st = left_hits.iter().max().map_or(0, |pos| pos + kmer_size);
+            add(
+                read,
+                (bp_st, first.st),
+                TRIM,
+                None,
+                Some(first.ctg.to_owned()),
+                left_hits.len() as u64,
+            );
+        }
+        let right_hits: Vec<i64> = read_hits
+            .iter()
+            .filter(|pos| **pos >= last.end)
+            .copied()
+            .collect();
+        if right_hits.len() as u64 >= min_sunks {
+            let bp_end = right_hits.iter().min().copied().unwrap_or(last.end);
+            add(
+                read,
+                (last.end, bp_end),
+                TRIM,
+                Some(last.ctg.to_owned()),
+                None,
+                right_hits.len() as u64,
+            );
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("read".into(), reads),
+        Column::new("read_length".into(), read_lengths),
+        Column::new("st".into(), sts),
+        Column::new("end".into(), ends),
+        Column::new("action".into(), actions),
+        Column::new("left_ctg".into(), left_ctgs),
+        Column::new("right_ctg".into(), right_ctgs),
+        Column::new("sunks".into(), n_sunks),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{read_trim_suggestions, SPLIT, TRIM};
+
+    #[test]
+    fn test_read_trim_suggestions() {
+        let df_read_intervals = df!(
+            "read" => ["r1", "r1", "r2"],
+            "st" => [0i64, 5000, 100],
+            "end" => [3000i64, 9000, 2000],
+            "ctg" => ["c1", "c2", "c1"],
+            "sunks" => [10u64, 8, 10],
+        )
+        .unwrap();
+        let df_read_sunks = df!(
+            "read" => ["r2", "r2", "r2", "r2"],
+            "rpos" => [50i64, 2501, 2601, 2701],
+        )
+        .unwrap();
+        let read_lens = HashMap::from([("r1".to_owned(), 9000), ("r2".to_owned(), 3000)]);
+        let df =
+            read_trim_suggestions(&df_read_intervals, &df_read_sunks, &read_lens, 20, 3).unwrap();
+        assert_eq!(df.height(), 2);
+        let actions: Vec<&str> = df
+            .column("action")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(actions, [SPLIT, TRIM]);
+        let sts: Vec<i64> = df
+            .column("st")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        let ends: Vec<i64> = df
+            .column("end")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(sts, [3000, 2000]);
+        assert_eq!(ends, [5000, 2500]);
+    }
+}
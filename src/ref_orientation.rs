@@ -0,0 +1,209 @@
+//! Orientation of contigs relative to a reference from a PAF of the assembly aligned to it.
+//!
+//! Contigs assembled as the reverse complement of the reference are flipped in outputs so coordinates can be
+//! compared across assemblies of the same genome.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use polars::prelude::*;
+
+use crate::{
+    error::{GavisunkError, Result},
+    naming::normalize_name,
+    ratio,
+};
+
+/// Minimum fraction of a contig's aligned bases on the reverse strand of the reference for it to be flipped. Inclusive.
+pub const MIN_REVERSE_FRACTION: f64 = 0.9;
+
+/// Sum aligned query bases on each strand of the reference by query.
+///
+/// # Arguments
+/// * `path`
+///     * PAF of the assembly, the query, aligned to a reference. ex. from `minimap2 -x asm5 ref.fa asm.fa`
+///
+/// # Returns
+/// * Forward and reverse aligned bases by contig. Contig names are normalized with [`normalize_name`].
+pub fn load_paf_strands(path: impl AsRef<Path>) -> Result<HashMap<String, (u64, u64)>> {
+    let mut strands: HashMap<String, (u64, u64)> = HashMap::new();
+    let reader = BufReader::new(File::open(path)?);
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err =
+            |reason: &str| GavisunkError::InvalidPaf(format!("line {}: {reason}", line_num + 1));
+        let [qname, _qlen, qst, qend, strand, ..] = line.split('\t').collect::<Vec<_>>()[..] else {
+            return Err(err("expected at least 5 tab-separated columns"));
+        };
+        let (qst, qend): (u64, u64) = (
+            qst.parse().map_err(|_| err("invalid query start"))?,
+            qend.parse().map_err(|_| err("invalid query end"))?,
+        );
+        let aln_bp = qend.saturating_sub(qst);
+        let (fwd, rev) = strands.entry(normalize_name(qname).to_owned()).or_default();
+        match strand {
+            "+" => *fwd += aln_bp,
+            "-" => *rev += aln_bp,
+            _ => return Err(err(&format!("invalid strand {strand:?}"))),
+        }
+    }
+    Ok(strands)
+}
+
+/// Contigs reverse complemented relative to a reference and their lengths.
+#[derive(Debug, Default, Clone)]
+pub struct ContigOrientations {
+    flipped: HashMap<String, u64>,
+}
+
+impl ContigOrientations {
+    /// Flip contigs with at least `min_reverse_fraction` of their aligned bases on the reverse strand.
+    ///
+    /// The fraction is compared exactly to the nearest part per million with [`ratio::at_least`].
+    ///
+    /// # Arguments
+    /// * `strands`
+    ///     * Forward and reverse aligned bases by contig. See [`load_paf_strands`].
+    /// * `asm_lens`
+    ///     * Contig lengths. Contigs not in the assembly are ignored.
+    /// * `min_reverse_fraction`
+    ///     * See [`MIN_REVERSE_FRACTION`].
+    pub fn new(
+        strands: &HashMap<String, (u64, u64)>,
+        asm_lens: &HashMap<String, u64>,
+        min_reverse_fraction: f64,
+    ) -> Self {
+        let min_ppm = ratio::to_ppm(min_reverse_fraction);
+        let flipped = strands
+            .iter()
+            .filter(|(_, (fwd, rev))| *rev > 0 && ratio::at_least(*rev, fwd + rev, min_ppm))
+            .flat_map(|(ctg, _)| Some((ctg.clone(), *asm_lens.get(ctg)?)))
+            .collect();
+        Self { flipped }
+    }
+
+    pub fn is_flipped(&self, ctg: &str) -> bool {
+        self.flipped.contains_key(ctg)
+    }
+
+    /// Flipped contigs sorted by name.
+    pub fn flipped(&self) -> Vec<&str> {
+        let mut ctgs: Vec<&str> = self.flipped.keys().map(String::as_str).collect();
+        ctgs.sort_unstable();
+        ctgs
+    }
+
+    /// Present contig coordinates in reference orientation and add a `flipped` column.
+    ///
+    /// # Arguments
+    /// * `df`
+    ///     * [`DataFrame`] with a `ctg` column. Returned unchanged without one.
+    /// * `intervals`
+    ///     * 0-based, half-open contig interval columns. ex. `[("st", "end")]`
+    ///     * Intervals of flipped contigs become `[len - end, len - st)`.
+    ///
+    /// # Returns
+    /// * [`DataFrame`] with a `flipped` column. If `ctg` is the first column and there are intervals, rows are sorted
+    ///   by `ctg` and the first interval so BED outputs stay sorted.
+    pub fn apply(&self, df: &DataFrame, intervals: &[(&str, &str)]) -> Result<DataFrame> {
+        let Ok(ctgs) = df.column("ctg") else {
+            return Ok(df.clone());
+        };
+        let lens: Vec<Option<i64>> = ctgs
+            .str()?
+            .iter()
+            .map(|ctg| {
+                ctg.and_then(|ctg| self.flipped.get(ctg))
+                    .map(|len| *len as i64)
+            })
+            .collect();
+        let ctg_first = df
+            .get_column_names()
+            .first()
+            .is_some_and(|name| name.as_str() == "ctg");
+        let mut df = df.clone();
+        df.with_column(Column::new("flipped_len".into(), lens))?;
+
+        let is_flipped = col("flipped_len").is_not_null();
+        let mut exprs = vec![];
+        for (st, end) in intervals {
+            let (st_dtype, end_dtype) = (df.column(st)?.dtype(), df.column(end)?.dtype());
+            exprs.push(
+                when(is_flipped.clone())
+                    .then(col("flipped_len") - col(*end).cast(DataType::Int64))
+                    .otherwise(col(*st).cast(DataType::Int64))
+                    .cast(st_dtype.clone())
+                    .alias(*st),
+            );
+            exprs.push(
+                when(is_flipped.clone())
+                    .then(col("flipped_len") - col(*st).cast(DataType::Int64))
+                    .otherwise(col(*end).cast(DataType::Int64))
+                    .cast(end_dtype.clone())
+                    .alias(*end),
+            );
+        }
+        let mut lf = df
+            .lazy()
+            .with_columns(exprs)
+            .with_column(is_flipped.alias("flipped"))
+            .drop(["flipped_len"]);
+        if let (true, Some((st, _))) = (ctg_first, intervals.first()) {
+            lf = lf.sort(["ctg", st], Default::default());
+        }
+        Ok(lf.collect()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{ContigOrientations, MIN_REVERSE_FRACTION};
+
+    #[test]
+    fn test_flip_contigs() {
+        let strands = HashMap::from([
+            ("ctg1".to_owned(), (0, 900)),
+            ("ctg2".to_owned(), (900, 100)),
+        ]);
+        let asm_lens = HashMap::from([("ctg1".to_owned(), 1_000), ("ctg2".to_owned(), 1_000)]);
+        let orientations = ContigOrientations::new(&strands, &asm_lens, MIN_REVERSE_FRACTION);
+        assert_eq!(orientations.flipped(), ["ctg1"]);
+
+        // Exactly the minimum fraction is flipped.
+        let strands = HashMap::from([
+            ("ctg1".to_owned(), (100, 900)),
+            ("ctg2".to_owned(), (100_001, 899_999)),
+        ]);
+        let boundary = ContigOrientations::new(&strands, &asm_lens, MIN_REVERSE_FRACTION);
+        assert_eq!(boundary.flipped(), ["ctg1"]);
+
+        let df = df!(
+            "ctg" => ["ctg1", "ctg1", "ctg2"],
+            "st" => [0i64, 600, 100],
+            "end" => [100i64, 1000, 200],
+        )
+        .unwrap();
+        let df = orientations.apply(&df, &[("st", "end")]).unwrap();
+        assert_eq!(
+            df,
+            df!(
+                "ctg" => ["ctg1", "ctg1", "ctg2"],
+                "st" => [0i64, 900, 100],
+                "end" => [400i64, 1000, 200],
+                "flipped" => [true, true, false],
+            )
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,663 @@
+//! Fan out mapping SUNKs to reads across worker processes on other machines over TCP.
+//!
+//! Mapping is independent per read segment and dominates run time on large read sets. A worker started with
+//! `serve-map` receives the SUNKs once per connection, then maps batches of read segments fetched from its own copy of
+//! the reads FASTA, ex. on a shared filesystem, and streams back their hits. The client hands out batches as workers
+//! finish them and requeues the batch of a worker that disconnects.
+//!
+//! Messages are newline-delimited JSON of at most [`MAX_MESSAGE_BYTES`]. See [`Request`] and [`Response`]. Workers
+//! don't authenticate clients, so they listen on localhost unless given another address.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::Path,
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+use polars::prelude::*;
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    cancel::CancelToken,
+    error::{GavisunkError, Result},
+    io::{skip_malformed_record, Fasta},
+    map_kmers::{find_sunks, map_tasks, sunk_hits_df, DedupPolicy, MapTask},
+    run_state::RunState,
+    sunk_index::{SunkHashIndex, SunkIndex},
+};
+
+/// Default address workers listen on.
+pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:7687";
+/// Version of the message format. Workers refuse clients of other versions.
+const PROTOCOL_VERSION: u32 = 2;
+/// Largest message sent or received, including the newline.
+const MAX_MESSAGE_BYTES: u64 = 1 << 30;
+/// SUNKs sent per message so that each stays well below [`MAX_MESSAGE_BYTES`].
+const SUNKS_PER_MESSAGE: usize = 1 << 20;
+/// How often workers waiting on batches in flight check for cancellation.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Message from the client to a worker.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    /// Sent once after connecting, followed by `n_sunks` SUNKs in [`Request::Sunks`] messages.
+    Index { protocol: u32, n_sunks: usize },
+    /// Sorted SUNKs searched in every read segment. In the same order as in a [`SunkIndex`] of them.
+    Sunks { sunks: Vec<String> },
+    /// 1-based, inclusive read segments to map.
+    Map { segments: Vec<(String, u64, u64)> },
+}
+
+/// Message from a worker to the client.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    /// The SUNKs were received. Read segments are mapped on `threads` threads.
+    Ready {
+        threads: usize,
+    },
+    /// `(read, sunk, pos, is_fwd)` of each hit of a batch with `sunk` as an index into the SUNKs sent and `pos` 1-based
+    /// on the read. `skipped` has the name and error of each read that couldn't be fetched.
+    Hits {
+        hits: Vec<(String, u32, u64, bool)>,
+        skipped: Vec<(String, String)>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+fn send(writer: &mut impl Write, msg: &impl Serialize) -> Result<()> {
+    serde_json::to_writer(&mut *writer, msg)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Receive a message. [`None`] if the connection was closed.
+///
+/// Messages longer than [`MAX_MESSAGE_BYTES`] are an error rather than buffered.
+fn recv<T: DeserializeOwned>(reader: &mut impl BufRead) -> Result<Option<T>> {
+    let mut line = String::new();
+    if reader
+        .by_ref()
+        .take(MAX_MESSAGE_BYTES)
+        .read_line(&mut line)?
+        == 0
+    {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && line.len() as u64 == MAX_MESSAGE_BYTES {
+        return Err(GavisunkError::RemoteMap(format!(
+            "message longer than {MAX_MESSAGE_BYTES} bytes"
+        )));
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+/// Serve mapping requests until the process is stopped. Each client is served on its own thread.
+///
+/// # Arguments
+/// * `listener`
+///     * Bound listener. ex. on [`DEFAULT_LISTEN_ADDR`]
+/// * `reads`
+///     * Reads FASTA on this machine. Clients can't choose which file is read.
+/// * `sunk_index`
+///     * Memory-mapped SUNK index shared with other workers on this machine. Written from the first client's SUNKs
+///       if it doesn't exist. See [`SunkIndex::open_or_create`].
+/// * `state`
+///     * Run malformed records of `reads` are skipped in when it's opened.
+pub fn serve_map(
+    listener: TcpListener,
+    reads: &Path,
+    sunk_index: Option<&Path>,
+    state: &RunState,
+) -> Result<()> {
+    // A worker's own reads may be FASTQ, or BAM or CRAM loaded into memory. Indexed once so clients don't race to
+    // write the index.
+    let fasta = Fasta::open(reads, None, false, state)?;
+    log::info!(
+        "Serving map requests of reads in {:?} on {}.",
+        fasta.fname,
+        listener.local_addr()?
+    );
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let fasta = &fasta;
+            scope.spawn(move || {
+                let peer = stream.peer_addr().ok();
+                log::info!("Client {peer:?} connected.");
+                match serve_client(stream, fasta, sunk_index) {
+                    Ok(()) => log::info!("Client {peer:?} disconnected."),
+                    Err(err) => log::error!("Client {peer:?} failed: {err}"),
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Serve a single client. Errors are also sent to the client.
+fn serve_client(stream: TcpStream, fasta: &Fasta, sunk_index: Option<&Path>) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let res = map_requests(&mut reader, &mut writer, fasta, sunk_index);
+    if let Err(err) = &res {
+        let _ = send(
+            &mut writer,
+            &Response::Error {
+                message: err.to_string(),
+            },
+        );
+    }
+    res
+}
+
+fn map_requests(
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    fasta: &Fasta,
+    sunk_index: Option<&Path>,
+) -> Result<()> {
+    let Some(Request::Index { protocol, n_sunks }) = recv(reader)? else {
+        return Err(GavisunkError::RemoteMap(
+            "expected SUNKs before read segments".to_owned(),
+        ));
+    };
+    if protocol != PROTOCOL_VERSION {
+        return Err(GavisunkError::RemoteMap(format!(
+            "client protocol {protocol} doesn't match worker protocol {PROTOCOL_VERSION}"
+        )));
+    }
+    let mut sunks: Vec<String> = vec![];
+    while sunks.len() < n_sunks {
+        let Some(Request::Sunks { sunks: chunk }) = recv(reader)? else {
+            return Err(GavisunkError::RemoteMap(format!(
+                "expected {n_sunks} SUNKs before read segments, got {}",
+                sunks.len()
+            )));
+        };
+        if sunks.len() + chunk.len() > n_sunks {
+            return Err(GavisunkError::RemoteMap(format!(
+                "received more than the {n_sunks} SUNKs announced"
+            )));
+        }
+        sunks.extend(chunk);
+    }
+    let sunks: Vec<&str> = sunks.iter().map(String::as_str).collect();
+    let index = sunk_index
+        .map(|path| SunkIndex::open_or_create(path, &sunks))
+        .transpose()?;
+    // Positions in the hash index are those of the SUNKs sent.
+    let hash_index = match &index {
+        Some(_) => None,
+        None => SunkHashIndex::new(&sunks),
+    };
+    // Positions in the index are the positions of the sorted SUNKs.
+    let sunk_idx: HashMap<&str, u32> = if index.is_some() || hash_index.is_some() {
+        HashMap::new()
+    } else {
+        sunks
+            .iter()
+            .enumerate()
+            .map(|(idx, sunk)| (*sunk, idx as u32))
+            .collect()
+    };
+    log::info!(
+        "Received {} SUNKs. Mapping reads in {:?}.",
+        sunks.len(),
+        fasta.fname
+    );
+    send(
+        writer,
+        &Response::Ready {
+            threads: rayon::current_num_threads(),
+        },
+    )?;
+
+    while let Some(request) = recv(reader)? {
+        let Request::Map { segments } = request else {
+            return Err(GavisunkError::RemoteMap(
+                "expected read segments after SUNKs".to_owned(),
+            ));
+        };
+        let results = segments
+            .par_iter()
+            .map(|(read, st, end)| {
+                let mut fh = fasta.try_clone()?;
+                let bases = match fh.fetch_bytes(read, *st, *end) {
+                    Ok(bases) => bases,
+                    // ex. a truncated read. Reported to the client, which counts it against its maximum.
+                    Err(GavisunkError::Io(err)) => return Ok(Err((read.clone(), err.to_string()))),
+                    Err(err) => return Err(err),
+                };
+                // Hit positions are relative to the segment.
+                if let Some(index) = &index {
+                    return Ok(Ok(index
+                        .find(&bases)
+                        .into_iter()
+                        .map(|(pos, idx, is_fwd)| {
+                            (read.clone(), idx as u32, pos as u64 + *st - 1, is_fwd)
+                        })
+                        .collect()));
+                }
+                if let Some(hash_index) = &hash_index {
+                    return Ok(Ok(hash_index
+                        .find(&bases)
+                        .into_iter()
+                        .map(|(pos, idx, is_fwd)| {
+                            (read.clone(), idx as u32, pos as u64 + *st - 1, is_fwd)
+                        })
+                        .collect()));
+                }
+                Ok(Ok(find_sunks(&sunks, &bases, read)?
+                    .into_iter()
+                    .map(|(read, sunk, pos, is_fwd)| {
+                        (
+                            read.to_owned(),
+                            sunk_idx[sunk],
+                            pos as u64 + *st - 1,
+                            is_fwd,
+                        )
+                    })
+                    .collect::<Vec<(String, u32, u64, bool)>>()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (mut hits, mut skipped) = (vec![], vec![]);
+        for res in results {
+            match res {
+                Ok(segment_hits) => hits.extend(segment_hits),
+                Err(read) => skipped.push(read),
+            }
+        }
+        send(writer, &Response::Hits { hits, skipped })?;
+    }
+    Ok(())
+}
+
+/// Connection to a worker ready to map read segments.
+struct Worker {
+    addr: String,
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    threads: usize,
+}
+
+impl Worker {
+    /// Connect to a worker and send it the SUNKs.
+    fn connect(addr: &str, sunks: &[&str]) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        send(
+            &mut writer,
+            &Request::Index {
+                protocol: PROTOCOL_VERSION,
+                n_sunks: sunks.len(),
+            },
+        )?;
+        for chunk in sunks.chunks(SUNKS_PER_MESSAGE) {
+            send(
+                &mut writer,
+                &Request::Sunks {
+                    sunks: chunk.iter().map(|sunk| (*sunk).to_owned()).collect(),
+                },
+            )?;
+        }
+        let threads = match recv(&mut reader)? {
+            Some(Response::Ready { threads }) => threads.max(1),
+            Some(Response::Error { message }) => {
+                return Err(GavisunkError::RemoteMap(format!("{addr}: {message}")))
+            }
+            _ => {
+                return Err(GavisunkError::RemoteMap(format!(
+                    "{addr}: unexpected response to SUNKs"
+                )))
+            }
+        };
+        Ok(Self {
+            addr: addr.to_owned(),
+            reader,
+            writer,
+            threads,
+        })
+    }
+
+    /// Map read segments.
+    ///
+    /// # Returns
+    /// * Hits and skipped reads. See [`Response::Hits`].
+    /// * [`GavisunkError::Io`] if the connection was lost.
+    fn map(&mut self, segments: Vec<(String, u64, u64)>) -> Result<Response> {
+        send(&mut self.writer, &Request::Map { segments })?;
+        recv(&mut self.reader)?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "worker closed the connection",
+            )
+            .into()
+        })
+    }
+}
+
+/// Tasks shared by the threads sending them to workers.
+struct MapQueue<'a> {
+    /// Tasks not yet sent, smallest first.
+    tasks: Vec<MapTask<'a>>,
+    /// Batches sent to a worker and not yet answered. Requeued if their worker is lost.
+    in_flight: usize,
+    /// A worker failed, so the others stop taking batches.
+    failed: bool,
+}
+
+/// Take the next batch of up to `n` tasks.
+///
+/// Waits while the queue is empty but batches are in flight, as they may still be requeued.
+///
+/// # Returns
+/// * [`None`] once every task has been mapped or another worker failed.
+fn queue_next_batch<'a>(
+    queue: &Mutex<MapQueue<'a>>,
+    queue_changed: &Condvar,
+    n: usize,
+    cancel: &CancelToken,
+) -> Result<Option<Vec<MapTask<'a>>>> {
+    let mut state = queue.lock().unwrap_or_else(|err| err.into_inner());
+    loop {
+        cancel.check()?;
+        if state.failed || (state.tasks.is_empty() && state.in_flight == 0) {
+            return Ok(None);
+        }
+        if !state.tasks.is_empty() {
+            break;
+        }
+        state = queue_changed
+            .wait_timeout(state, WAIT_INTERVAL)
+            .unwrap_or_else(|err| err.into_inner())
+            .0;
+    }
+    let n_left = state.tasks.len().saturating_sub(n);
+    state.in_flight += 1;
+    Ok(Some(state.tasks.split_off(n_left)))
+}
+
+/// Map a batch of tasks on a worker and collect its hits.
+///
+/// # Returns
+/// * [`GavisunkError::Io`] if the connection to the worker was lost.
+fn map_batch(
+    worker: &mut Worker,
+    batch: &[MapTask],
+    path_reads: &Path,
+    hits: &Mutex<Vec<(String, u32, u64, bool)>>,
+    state: &RunState,
+) -> Result<()> {
+    let segments = batch
+        .iter()
+        .flatten()
+        .map(|(read, st, end)| ((*read).to_owned(), *st, *end))
+        .collect();
+    match worker.map(segments)? {
+        Response::Hits {
+            hits: batch_hits,
+            skipped,
+        } => {
+            for (read, err) in skipped {
+                skip_malformed_record(path_reads, &read, err, state)?;
+            }
+            hits.lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .extend(batch_hits);
+            Ok(())
+        }
+        Response::Error { message } => Err(GavisunkError::RemoteMap(format!(
+            "{}: {message}",
+            worker.addr
+        ))),
+        Response::Ready { .. } => Err(GavisunkError::RemoteMap(format!(
+            "{}: unexpected response to read segments",
+            worker.addr
+        ))),
+    }
+}
+
+/// Map SUNKs from an assembly to reads on remote workers.
+///
+/// Reads are split into tasks as in [`crate::map_kmers::map_sunks_to_reads`] and each worker is sent one task per
+/// thread at a time. Tasks of workers that disconnect are requeued for the others, so idle workers wait until every
+/// batch in flight has finished. Reads that workers can't fetch are skipped with [`skip_malformed_record`].
+///
+/// # Arguments
+/// * `workers`
+///     * `host:port` of each worker started with [`serve_map`]. Unreachable workers are skipped.
+/// * `path_reads`
+///     * Reads file. Workers read their own copy of the same reads. Used in warnings of skipped reads.
+/// * `fa_lens`
+///     * Read lengths.
+/// * `df_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, kmer, strand, group]`
+/// * `dedup`
+///     * How multiple hits per `(read, ctg, group)` are resolved.
+/// * `state`
+///     * Run skipped reads and join losses are recorded in.
+/// * `cancel`
+///     * Token checked before sending each task.
+///
+/// # Returns
+/// * [`DataFrame`] of SUNKs within reads. See [`crate::map_kmers::map_sunks_to_reads`].
+pub fn map_sunks_to_reads_remote(
+    workers: &[String],
+    path_reads: &Path,
+    fa_lens: &HashMap<String, u64>,
+    df_sunks: &DataFrame,
+    dedup: DedupPolicy,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<DataFrame> {
+    let mut sunks: Vec<&str> = df_sunks
+        .column("kmer")?
+        .str()?
+        .into_iter()
+        .flatten()
+        .collect();
+    sunks.sort_unstable();
+    sunks.dedup();
+    let Some(kmer_size) = sunks.first().map(|sunk| sunk.len() as u64) else {
+        return Err(GavisunkError::NoSunks);
+    };
+    let workers: Vec<Worker> = workers
+        .iter()
+        .filter_map(|addr| match Worker::connect(addr, &sunks) {
+            Ok(worker) => Some(worker),
+            Err(err) => {
+                log::warn!("Skipping worker {addr}: {err}");
+                None
+            }
+        })
+        .collect();
+    if workers.is_empty() {
+        return Err(GavisunkError::RemoteMap("no workers available".to_owned()));
+    }
+    let threads: usize = workers.iter().map(|worker| worker.threads).sum();
+    log::info!(
+        "Mapping reads on {} workers with {threads} threads.",
+        workers.len()
+    );
+
+    let reads: Vec<(&String, &u64)> = fa_lens.iter().collect();
+    let mut tasks = map_tasks(&reads, kmer_size, threads);
+    // Largest first.
+    tasks.reverse();
+    let queue = Mutex::new(MapQueue {
+        tasks,
+        in_flight: 0,
+        failed: false,
+    });
+    let queue_changed = Condvar::new();
+    let hits: Mutex<Vec<(String, u32, u64, bool)>> = Mutex::new(vec![]);
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = workers
+            .into_iter()
+            .map(|mut worker| {
+                let (queue, queue_changed, hits) = (&queue, &queue_changed, &hits);
+                scope.spawn(move || -> Result<()> {
+                    loop {
+                        let Some(batch) =
+                            queue_next_batch(queue, queue_changed, worker.threads, cancel)?
+                        else {
+                            return Ok(());
+                        };
+                        let res = map_batch(&mut worker, &batch, path_reads, hits, state);
+                        let mut state = queue.lock().unwrap_or_else(|err| err.into_inner());
+                        state.in_flight -= 1;
+                        queue_changed.notify_all();
+                        match res {
+                            Ok(()) => {}
+                            Err(GavisunkError::Io(err)) => {
+                                log::warn!(
+                                    "Lost worker {}: {err}. Requeuing its {} tasks.",
+                                    worker.addr,
+                                    batch.len()
+                                );
+                                state.tasks.extend(batch);
+                                return Ok(());
+                            }
+                            Err(err) => {
+                                state.failed = true;
+                                return Err(err);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().try_for_each(|handle| {
+            handle.join().unwrap_or_else(|_| {
+                Err(GavisunkError::RemoteMap(
+                    "worker thread panicked".to_owned(),
+                ))
+            })
+        })
+    })?;
+
+    let n_left = queue
+        .into_inner()
+        .unwrap_or_else(|err| err.into_inner())
+        .tasks
+        .len();
+    if n_left > 0 {
+        return Err(GavisunkError::RemoteMap(format!(
+            "all workers were lost with {n_left} tasks left"
+        )));
+    }
+    let hits = hits.into_inner().unwrap_or_else(|err| err.into_inner());
+    let mapped_sunks = hits
+        .iter()
+        .map(|(read, sunk, pos, is_fwd)| {
+            let sunk = sunks
+                .get(*sunk as usize)
+                .ok_or_else(|| GavisunkError::RemoteMap(format!("hit of unknown SUNK {sunk}")))?;
+            Ok((read.as_str(), *sunk, *pos as usize, *is_fwd))
+        })
+        .collect::<Result<Vec<(&str, &str, usize, bool)>>>()?;
+    let df_read_sunks = sunk_hits_df(mapped_sunks, df_sunks, dedup, state)?;
+    log::info!("Total SUNKs mapped: {}", df_read_sunks.height());
+    Ok(df_read_sunks)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, net::TcpListener};
+
+    use polars::prelude::*;
+
+    use super::{map_sunks_to_reads_remote, serve_map};
+    use crate::{
+        cancel::CancelToken,
+        io::{Fasta, ScratchDir},
+        map_kmers::{map_sunks_to_reads, DedupPolicy},
+        run_state::RunState,
+    };
+
+    #[test]
+    fn test_remote_map_matches_local() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_remote_map").unwrap();
+        let path = dir.path().join("reads.fa");
+        std::fs::write(
+            &path,
+            ">read1\nAACCAGTCGGGGTGGCATCA\n>read2\nACGTTGATGCCAAACCAGTC\n>read3\nTTTTTTTTTTTTTTTTTTTT\n",
+        )
+        .unwrap();
+        let df_sunks = df!(
+            "ctg" => ["ctg1", "ctg1"],
+            "cpos" => [1u64, 9],
+            "kmer" => ["AACCAGTC", "TGGCATCA"],
+            "strand" => ["+", "+"],
+            "group" => [1u64, 9],
+        )
+        .unwrap();
+
+        // Indexed before the worker opens the reads.
+        let fa = Fasta::new(&path).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let path_worker = path.clone();
+        std::thread::spawn(move || serve_map(listener, &path_worker, None, &RunState::new()));
+
+        let lens: HashMap<String, u64> = fa.lengths();
+        let cancel = CancelToken::new();
+        let state = RunState::new();
+        let df_remote = map_sunks_to_reads_remote(
+            &["127.0.0.1:1".to_owned(), addr],
+            &path,
+            &lens,
+            &df_sunks,
+            DedupPolicy::First,
+            &state,
+            &cancel,
+        )
+        .unwrap();
+        let df_local = map_sunks_to_reads(
+            Fasta::new(&path).unwrap(),
+            &lens,
+            &df_sunks,
+            None,
+            None,
+            DedupPolicy::First,
+            None,
+            None,
+            &state,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(df_remote.height(), 4);
+        assert!(df_remote.equals(&df_local));
+
+        // Worker looking up SUNKs in a shared index.
+        let path_index = dir.path().join("sunks.idx");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let path_worker = path.clone();
+        std::thread::spawn(move || {
+            serve_map(listener, &path_worker, Some(&path_index), &RunState::new())
+        });
+        let df_indexed = map_sunks_to_reads_remote(
+            &[addr],
+            &path,
+            &lens,
+            &df_sunks,
+            DedupPolicy::First,
+            &state,
+            &cancel,
+        )
+        .unwrap();
+        assert!(df_indexed.equals(&df_local));
+    }
+}
@@ -0,0 +1,287 @@
+//! Self-contained HTML report with a detail page per contig with breakpoints.
+//!
+//! Pages embed their SVGs and tables inline so they can be shared as single files.
+
+use std::{collections::HashMap, fmt::Write as _, io::Write, path::Path};
+
+use polars::prelude::*;
+
+use crate::{
+    error::Result,
+    io::write_atomic,
+    naming::ContigFilenames,
+    plots::{escape, histogram_svg, pileup_svg, MAX_PILEUP_ROWS},
+};
+
+/// Bins of the SUNK density plot.
+const DENSITY_BINS: i64 = 200;
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:2em}table{border-collapse:collapse;font-size:12px}\
+td,th{border:1px solid #ccc;padding:2px 6px;text-align:left}th{background:#eee}";
+
+/// Split a [`DataFrame`] with a `ctg` column by contig.
+fn by_ctg(df: &DataFrame) -> Result<HashMap<String, DataFrame>> {
+    let mut dfs = HashMap::new();
+    for df_ctg in df.partition_by(["ctg"], true)? {
+        if let Some(ctg) = df_ctg.column("ctg")?.str()?.first() {
+            dfs.insert(ctg.to_owned(), df_ctg.clone());
+        }
+    }
+    Ok(dfs)
+}
+
+/// `(st, end)` intervals of a [`DataFrame`] with columns `[st, end]`.
+fn intervals(df: Option<&DataFrame>, st: &str, end: &str) -> Result<Vec<(i64, i64)>> {
+    let Some(df) = df else {
+        return Ok(vec![]);
+    };
+    let (sts, ends) = (
+        df.column(st)?.cast(&DataType::Int64)?,
+        df.column(end)?.cast(&DataType::Int64)?,
+    );
+    Ok(sts
+        .i64()?
+        .iter()
+        .zip(ends.i64()?.iter())
+        .flat_map(|(st, end)| st.zip(end))
+        .collect())
+}
+
+/// Render a [`DataFrame`] as an HTML table.
+fn html_table(df: &DataFrame) -> Result<String> {
+    let mut html = String::from("<table>\n<tr>");
+    for name in df.get_column_names() {
+        let _ = write!(html, "<th>{}</th>", escape(name));
+    }
+    html.push_str("</tr>\n");
+    let cols = df
+        .get_columns()
+        .iter()
+        .map(|col| col.cast(&DataType::String))
+        .collect::<PolarsResult<Vec<Column>>>()?;
+    let cols = cols
+        .iter()
+        .map(|col| col.str())
+        .collect::<PolarsResult<Vec<&StringChunked>>>()?;
+    for row in 0..df.height() {
+        html.push_str("<tr>");
+        for col in &cols {
+            let _ = write!(html, "<td>{}</td>", escape(col.get(row).unwrap_or("")));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    Ok(html)
+}
+
+/// Write an HTML report of supported intervals with a detail page per contig with breakpoints.
+///
+/// A contig has breakpoints if it has any unsupported interval.
+///
+/// # Arguments
+/// * `outdir`
+///     * Output directory. Writes `index.html` and `{ctg}.html` with path-safe names.
+/// * `ctg_lens`
+///     * Lengths of contigs to report.
+/// * `df_supported`
+///     * [`DataFrame`] of supported intervals with columns `[ctg, st, end, ...]`
+/// * `df_unsupported`
+///     * [`DataFrame`] of unsupported intervals with columns `[ctg, st, end, ...]`. Listed as the breakpoint table.
+/// * `df_read_intervals`
+///     * [`DataFrame`] of read segments with columns `[read, ctg, ctg_st, ctg_end, strand, ...]`. See [`crate::merge::read_intervals`].
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, ...]`
+///
+/// # Returns
+/// * Number of contig pages written.
+pub fn write_report(
+    outdir: impl AsRef<Path>,
+    ctg_lens: &HashMap<String, u64>,
+    df_supported: &DataFrame,
+    df_unsupported: &DataFrame,
+    df_read_intervals: &DataFrame,
+    df_asm_sunks: &DataFrame,
+) -> Result<usize> {
+    let outdir = outdir.as_ref();
+    std::fs::create_dir_all(outdir)?;
+    let supported = by_ctg(df_supported)?;
+    let unsupported = by_ctg(df_unsupported)?;
+    let read_intervals = by_ctg(df_read_intervals)?;
+    let asm_sunks = by_ctg(&df_asm_sunks.select(["ctg", "cpos"])?)?;
+    let fnames = ContigFilenames::new(ctg_lens.keys().map(String::as_str));
+
+    let mut ctgs: Vec<&String> = ctg_lens.keys().collect();
+    ctgs.sort();
+    let mut index = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>rs-gavisunk report</title><style>{STYLE}</style></head><body>\n\
+        <h1>rs-gavisunk report</h1>\n<table>\n<tr><th>ctg</th><th>length</th><th>supported_bp</th><th>supported</th><th>unsupported_intervals</th></tr>\n"
+    );
+    let mut n_pages = 0;
+    for ctg in ctgs {
+        let ctg_len = ctg_lens[ctg] as i64;
+        let ctg_supported = intervals(supported.get(ctg), "st", "end")?;
+        let ctg_unsupported = intervals(unsupported.get(ctg), "st", "end")?;
+        let supported_bp: i64 = ctg_supported.iter().map(|(st, end)| end - st).sum();
+        let supported_perc = supported_bp as f64 / ctg_len.max(1) as f64 * 100.0;
+        let name = if ctg_unsupported.is_empty() {
+            escape(ctg)
+        } else {
+            let fname = format!("{}.html", fnames.get(ctg));
+            let page = contig_page(
+                ctg,
+                ctg_len,
+                &ctg_supported,
+                &ctg_unsupported,
+                unsupported.get(ctg),
+                read_intervals.get(ctg),
+                asm_sunks.get(ctg),
+            )?;
+            write_atomic(outdir.join(&fname), |file| {
+                Ok(file.write_all(page.as_bytes())?)
+            })?;
+            n_pages += 1;
+            format!("<a href=\"{}\">{}</a>", escape(&fname), escape(ctg))
+        };
+        let _ = writeln!(
+            index,
+            "<tr><td>{name}</td><td>{ctg_len}</td><td>{supported_bp}</td><td>{supported_perc:.2}%</td><td>{}</td></tr>",
+            ctg_unsupported.len()
+        );
+    }
+    index.push_str("</table>\n</body></html>\n");
+    write_atomic(outdir.join("index.html"), |file| {
+        Ok(file.write_all(index.as_bytes())?)
+    })?;
+    Ok(n_pages)
+}
+
+/// Detail page of a contig with its pileup, breakpoint table, SUNK density, and read list.
+fn contig_page(
+    ctg: &str,
+    ctg_len: i64,
+    supported: &[(i64, i64)],
+    unsupported: &[(i64, i64)],
+    df_unsupported: Option<&DataFrame>,
+    df_read_intervals: Option<&DataFrame>,
+    df_asm_sunks: Option<&DataFrame>,
+) -> Result<String> {
+    let reads: Vec<(i64, i64, bool)> = match df_read_intervals {
+        Some(df) => intervals(Some(df), "ctg_st", "ctg_end")?
+            .into_iter()
+            .zip(df.column("strand")?.str()?.iter())
+            .map(|((st, end), strand)| (st, end, strand != Some("-")))
+            .collect(),
+        None => vec![],
+    };
+    let pileup = pileup_svg(
+        &format!(
+            "{ctg} ({} reads, up to {MAX_PILEUP_ROWS} rows)",
+            reads.len()
+        ),
+        ctg_len,
+        supported,
+        unsupported,
+        &reads,
+    );
+
+    // SUNKs per bin. Bins overlapping unsupported intervals are highlighted.
+    let bin_len = (ctg_len / DENSITY_BINS).max(1);
+    let mut counts = vec![0u64; (ctg_len / bin_len + 1) as usize];
+    if let Some(df) = df_asm_sunks {
+        for cpos in df
+            .column("cpos")?
+            .cast(&DataType::Int64)?
+            .i64()?
+            .iter()
+            .flatten()
+        {
+            if let Some(count) = counts.get_mut(((cpos - 1).max(0) / bin_len) as usize) {
+                *count += 1;
+            }
+        }
+    }
+    let bars: Vec<(u64, u64, bool)> = counts
+        .iter()
+        .enumerate()
+        .map(|(bin, count)| {
+            let (st, end) = (bin as i64 * bin_len, (bin as i64 + 1) * bin_len);
+            let is_unsupported = unsupported
+                .iter()
+                .any(|(u_st, u_end)| *u_st < end && st < *u_end);
+            (bin as u64, *count, is_unsupported)
+        })
+        .collect();
+    let density = histogram_svg(
+        &format!("{ctg} SUNK density"),
+        &format!("Bin ({bin_len} bp)"),
+        "SUNKs",
+        &bars,
+        &[],
+    );
+
+    let breakpoints = match df_unsupported {
+        Some(df) => html_table(df)?,
+        None => String::new(),
+    };
+    let read_list = match df_read_intervals {
+        Some(df) => html_table(&df.sort(["ctg_st"], Default::default())?)?,
+        None => "<p>No reads.</p>".to_owned(),
+    };
+    let ctg = escape(ctg);
+    Ok(format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{ctg}</title><style>{STYLE}</style></head><body>\n\
+        <p><a href=\"index.html\">Back to report</a></p>\n<h1>{ctg}</h1>\n\
+        <h2>Pileup</h2>\n{pileup}\n<h2>Breakpoints</h2>\n{breakpoints}\n\
+        <h2>SUNK density</h2>\n{density}\n<h2>Reads</h2>\n{read_list}</body></html>\n"
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::write_report;
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_write_report() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_report").unwrap();
+        let outdir = dir.path().join("report");
+        let ctg_lens = HashMap::from([("c1".to_owned(), 10000), ("c2".to_owned(), 5000)]);
+        let df_supported = df!(
+            "ctg" => ["c1", "c1", "c2"],
+            "st" => [0i64, 6000, 0],
+            "end" => [4000i64, 10000, 5000],
+        )
+        .unwrap();
+        let df_unsupported = df!("ctg" => ["c1"], "st" => [4000i64], "end" => [6000i64]).unwrap();
+        let df_read_intervals = df!(
+            "read" => ["r1", "r2"],
+            "ctg" => ["c1", "c1"],
+            "ctg_st" => [0i64, 5500],
+            "ctg_end" => [4500i64, 10000],
+            "strand" => ["+", "-"],
+        )
+        .unwrap();
+        let df_asm_sunks = df!("ctg" => ["c1", "c2"], "cpos" => [101i64, 201]).unwrap();
+        let n_pages = write_report(
+            &outdir,
+            &ctg_lens,
+            &df_supported,
+            &df_unsupported,
+            &df_read_intervals,
+            &df_asm_sunks,
+        )
+        .unwrap();
+        assert_eq!(n_pages, 1);
+        let index = std::fs::read_to_string(outdir.join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"c1.html\">c1</a>"));
+        assert!(!outdir.join("c2.html").exists());
+        let page = std::fs::read_to_string(outdir.join("c1.html")).unwrap();
+        assert_eq!(page.matches("<svg").count(), 2);
+        assert!(page.contains("<td>r2</td>"));
+    }
+}
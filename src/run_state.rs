@@ -0,0 +1,86 @@
+//! Settings and records of a single run shared by its stages.
+//!
+//! Each run, ex. of the binary or a [`crate::pipeline::Pipeline`], owns a [`RunState`] and passes it to the stages
+//! that read its settings or record to it, so runs in the same process don't share settings or mix their records.
+
+use std::{path::PathBuf, sync::Mutex};
+
+use crate::{
+    filter_audit::FilterCount,
+    io::{EmptyIntermediate, DEFAULT_MAX_MALFORMED_RECORDS},
+    joins::DEFAULT_MAX_JOIN_LOSS,
+};
+
+#[cfg(feature = "graph")]
+use crate::sunk_graph::SkippedRead;
+
+/// Settings and records of a run.
+#[derive(Debug)]
+pub struct RunState {
+    /// Fraction of rows a join may lose before warning. See [`crate::joins::check_join_loss`].
+    pub max_join_loss: f64,
+    /// Malformed records skipped across all inputs before failing. `0` fails on the first. See
+    /// [`crate::io::skip_malformed_record`].
+    pub max_malformed_records: usize,
+    /// What to do with empty or truncated intermediates. See [`crate::io::load_intermediate`].
+    pub empty_intermediate: EmptyIntermediate,
+    /// Directory converted and recompressed copies of inputs are written to. Empty for the working directory. See
+    /// [`crate::io::Fasta::indexable_path`].
+    pub copy_dir: PathBuf,
+    /// Malformed records skipped so far, as `{path}:{name}`.
+    pub(crate) malformed_records: Mutex<Vec<String>>,
+    /// Intermediates loaded or written so far. See [`crate::io::intermediates`].
+    pub(crate) intermediates: Mutex<Vec<PathBuf>>,
+    /// Filter counts in the order filters first ran. See [`crate::filter_audit::record`].
+    pub(crate) filter_counts: Mutex<Vec<FilterCount>>,
+    /// Reads skipped when building SUNK graphs. See [`crate::sunk_graph::skipped_reads`].
+    #[cfg(feature = "graph")]
+    pub(crate) skipped_reads: Mutex<Vec<SkippedRead>>,
+}
+
+impl Default for RunState {
+    fn default() -> Self {
+        Self {
+            max_join_loss: DEFAULT_MAX_JOIN_LOSS,
+            max_malformed_records: DEFAULT_MAX_MALFORMED_RECORDS,
+            empty_intermediate: EmptyIntermediate::default(),
+            copy_dir: PathBuf::new(),
+            malformed_records: Mutex::default(),
+            intermediates: Mutex::default(),
+            filter_counts: Mutex::default(),
+            #[cfg(feature = "graph")]
+            skipped_reads: Mutex::default(),
+        }
+    }
+}
+
+impl RunState {
+    /// State of a run with default settings and nothing recorded.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the fraction of rows a join may lose before warning. Clamped to `[0, 1]`. `0.0` warns on any loss.
+    pub fn with_max_join_loss(mut self, frac: f64) -> Self {
+        self.max_join_loss = frac.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the number of malformed records skipped across all inputs before failing. `0` fails on the first.
+    pub fn with_max_malformed_records(mut self, max: usize) -> Self {
+        self.max_malformed_records = max;
+        self
+    }
+
+    /// Set what to do with empty or truncated intermediates.
+    pub fn with_empty_intermediate(mut self, policy: EmptyIntermediate) -> Self {
+        self.empty_intermediate = policy;
+        self
+    }
+
+    /// Set the directory converted and recompressed copies of inputs are written to.
+    pub fn with_copy_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.copy_dir = dir.into();
+        self
+    }
+}
@@ -0,0 +1,293 @@
+//! Random access into files in the zstd seekable format.
+//!
+//! Seekable zstd files are independently compressed frames followed by a skippable frame with a seek table of the
+//! compressed and decompressed size of each frame. Reads only decompress the frames they overlap so region queries
+//! don't decompress the whole file. See
+//! <https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md>
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::error::{GavisunkError, Result};
+
+/// Magic number of the skippable frame with the seek table.
+const SKIPPABLE_MAGIC: u32 = 0x184D2A5E;
+/// Magic number at the end of the seek table.
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+/// Bytes of the skippable frame header. Magic number and frame size.
+const SKIPPABLE_HEADER_LEN: u64 = 8;
+/// Bytes of the seek table footer. Number of frames, descriptor, and magic number.
+const FOOTER_LEN: u64 = 9;
+/// Set in the seek table descriptor if entries have a checksum.
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Offsets and sizes of a compressed frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    compressed_offset: u64,
+    decompressed_offset: u64,
+    compressed_size: u32,
+    decompressed_size: u32,
+}
+
+impl Frame {
+    fn decompressed_end(&self) -> u64 {
+        self.decompressed_offset + u64::from(self.decompressed_size)
+    }
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Read the seek table at the end of a seekable zstd file.
+///
+/// # Returns
+/// * Frames ordered by offset.
+/// * [`None`] if there is no seek table.
+fn read_seek_table(reader: &mut (impl Read + Seek)) -> std::io::Result<Option<Vec<Frame>>> {
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    if file_len < SKIPPABLE_HEADER_LEN + FOOTER_LEN {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::End(-(FOOTER_LEN as i64)))?;
+    let n_frames = read_u32(reader)?;
+    let mut descriptor = [0; 1];
+    reader.read_exact(&mut descriptor)?;
+    if read_u32(reader)? != SEEKABLE_MAGIC {
+        return Ok(None);
+    }
+    let entry_len: u64 = if descriptor[0] & CHECKSUM_FLAG != 0 {
+        12
+    } else {
+        8
+    };
+    let table_len = u64::from(n_frames) * entry_len + FOOTER_LEN;
+    let Some(data_len) = file_len.checked_sub(table_len + SKIPPABLE_HEADER_LEN) else {
+        return Err(invalid_data("seek table is larger than the file"));
+    };
+    reader.seek(SeekFrom::Start(data_len))?;
+    if read_u32(reader)? != SKIPPABLE_MAGIC || u64::from(read_u32(reader)?) != table_len {
+        return Err(invalid_data("seek table is not in a skippable frame"));
+    }
+
+    let mut entries = vec![0; (table_len - FOOTER_LEN) as usize];
+    reader.read_exact(&mut entries)?;
+    let mut frames = Vec::with_capacity(n_frames as usize);
+    let (mut compressed_offset, mut decompressed_offset) = (0, 0);
+    // Checksums, if any, are ignored. zstd checks frames with their own checksum.
+    for entry in entries.chunks_exact(entry_len as usize) {
+        let compressed_size = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+        let decompressed_size = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+        frames.push(Frame {
+            compressed_offset,
+            decompressed_offset,
+            compressed_size,
+            decompressed_size,
+        });
+        compressed_offset += u64::from(compressed_size);
+        decompressed_offset += u64::from(decompressed_size);
+    }
+    if compressed_offset != data_len {
+        return Err(invalid_data(
+            "seek table frame sizes don't match the file size",
+        ));
+    }
+    Ok(Some(frames))
+}
+
+/// Whether a file is zstd-compressed in the seekable format.
+pub fn is_seekable(infile: impl AsRef<Path>) -> Result<bool> {
+    Ok(read_seek_table(&mut File::open(infile)?)?.is_some())
+}
+
+/// Reader of the decompressed contents of a seekable zstd file.
+///
+/// Seeking is free. Reading decompresses the frame at the current position, which is kept until a read moves past it.
+pub struct SeekableZstd<R> {
+    inner: R,
+    frames: Vec<Frame>,
+    /// Decompressed position.
+    pos: u64,
+    /// Index and contents of the last decompressed frame.
+    frame: Option<(usize, Vec<u8>)>,
+}
+
+impl SeekableZstd<File> {
+    /// Open a seekable zstd file.
+    ///
+    /// # Returns
+    /// * [`GavisunkError::UnseekableZstd`] if the file has no seek table.
+    pub fn open(infile: impl AsRef<Path>) -> Result<Self> {
+        let infile = infile.as_ref();
+        Self::new(File::open(infile)?)?
+            .ok_or_else(|| GavisunkError::UnseekableZstd(infile.to_owned()))
+    }
+}
+
+impl<R: Read + Seek> SeekableZstd<R> {
+    /// Read the seek table of a seekable zstd stream.
+    ///
+    /// # Returns
+    /// * [`None`] if `inner` has no seek table.
+    pub fn new(mut inner: R) -> Result<Option<Self>> {
+        let Some(frames) = read_seek_table(&mut inner)? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            inner,
+            frames,
+            pos: 0,
+            frame: None,
+        }))
+    }
+
+    /// Decompressed length.
+    pub fn len(&self) -> u64 {
+        self.frames.last().map_or(0, Frame::decompressed_end)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of compressed frames.
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Decompress a frame unless it was the last one read.
+    fn load_frame(&mut self, idx: usize) -> std::io::Result<&[u8]> {
+        if self.frame.as_ref().map(|(loaded, _)| *loaded) != Some(idx) {
+            let frame = self.frames[idx];
+            self.inner.seek(SeekFrom::Start(frame.compressed_offset))?;
+            let mut compressed = vec![0; frame.compressed_size as usize];
+            self.inner.read_exact(&mut compressed)?;
+            let data = zstd::bulk::decompress(&compressed, frame.decompressed_size as usize)?;
+            if data.len() != frame.decompressed_size as usize {
+                return Err(invalid_data("frame size doesn't match the seek table"));
+            }
+            self.frame = Some((idx, data));
+        }
+        Ok(self.frame.as_ref().map_or(&[], |(_, data)| data.as_slice()))
+    }
+}
+
+impl<R: Read + Seek> Read for SeekableZstd<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // First frame ending after the position. Skips empty frames.
+        let idx = self
+            .frames
+            .partition_point(|frame| frame.decompressed_end() <= self.pos);
+        let Some(frame) = self.frames.get(idx).copied() else {
+            return Ok(0);
+        };
+        let start = (self.pos - frame.decompressed_offset) as usize;
+        let data = &self.load_frame(idx)?[start..];
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for SeekableZstd<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = new_pos.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use super::{SeekableZstd, SEEKABLE_MAGIC, SKIPPABLE_MAGIC};
+    use crate::io::{Compression, Fasta, ScratchDir};
+
+    /// Compress `data` as seekable zstd with frames of `frame_len` decompressed bytes.
+    fn seekable_zstd(data: &[u8], frame_len: usize) -> Vec<u8> {
+        let mut out = vec![];
+        let mut table = vec![];
+        for chunk in data.chunks(frame_len) {
+            let compressed = zstd::bulk::compress(chunk, 3).unwrap();
+            out.extend_from_slice(&compressed);
+            table.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            table.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        }
+        let n_frames = (table.len() / 8) as u32;
+        table.extend_from_slice(&n_frames.to_le_bytes());
+        table.push(0);
+        table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        out.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+        out.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        out.extend_from_slice(&table);
+        out
+    }
+
+    #[test]
+    fn test_seekable_zstd_reader() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut reader = SeekableZstd::new(Cursor::new(seekable_zstd(&data, 1000)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(reader.num_frames(), 10);
+        assert_eq!(reader.len(), 10_000);
+
+        // Across a frame boundary.
+        reader.seek(SeekFrom::Start(2990)).unwrap();
+        let mut buf = vec![0; 20];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[2990..3010]);
+
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = vec![];
+        reader.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+
+        let plain = zstd::bulk::compress(&data, 3).unwrap();
+        assert!(SeekableZstd::new(Cursor::new(plain)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_fetch_seekable_zstd_fasta() {
+        let fasta = format!(
+            ">ctg1 desc\n{}\n>ctg2\n{}\n",
+            "ACGT".repeat(500),
+            "GGCC".repeat(500)
+        );
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_seekable").unwrap();
+        let path = dir.path().join("asm.fa.zst");
+        std::fs::File::create(&path)
+            .unwrap()
+            .write_all(&seekable_zstd(fasta.as_bytes(), 256))
+            .unwrap();
+        assert_eq!(Compression::detect(&path).unwrap(), Compression::Zstd);
+
+        let mut fa = Fasta::new(&path).unwrap();
+        assert_eq!(fa.lengths()["ctg2"], 2000);
+        let rec = fa.try_clone().unwrap().fetch("ctg2", 1, 4).unwrap();
+        assert_eq!(rec.sequence().as_ref(), b"GGCC");
+        let rec = fa.fetch("ctg1", 1997, 2000).unwrap();
+        assert_eq!(rec.sequence().as_ref(), b"ACGT");
+    }
+}
@@ -0,0 +1,161 @@
+//! Embedded dataset and expected outputs of `self-test` to check an installation.
+//!
+//! Two contigs built from one repeat unit with sparse substitutions, so SUNKs are spaced like in a segmental
+//! duplication. `ctg_ok` is assembled correctly and `ctg_misjoin` is missing 5 kbp of its source sequence at 20 kbp.
+//! See `test/self_test/generate.py` to regenerate it.
+
+use std::{collections::HashMap, io::Read, path::Path};
+
+use flate2::read::GzDecoder;
+
+use crate::{
+    error::{GavisunkError, Result},
+    intervals::load_bed,
+};
+
+/// Gzipped assembly FASTA.
+const ASSEMBLY: &[u8] = include_bytes!("../test/self_test/asm.fa.gz");
+/// Gzipped reads FASTA.
+const READS: &[u8] = include_bytes!("../test/self_test/reads.fa.gz");
+/// Expected outputs with columns `[ctg, min_supported_fraction, unsupported_at]`. `.` if a contig has no misjoin.
+const EXPECTED: &str = include_str!("../test/self_test/expected.tsv");
+/// Allowed distance between an expected misjoin and an unsupported interval.
+pub const MISJOIN_SLOP: i64 = 1_000;
+
+/// Expected outputs of a contig.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expectation {
+    pub ctg: String,
+    /// Minimum fraction of the contig in `supported.bed`.
+    pub min_supported_fraction: f64,
+    /// Position of a misjoin that must be in `unsupported.bed`.
+    /// Without one, no unsupported interval longer than [`MISJOIN_SLOP`] may be between contig ends.
+    pub unsupported_at: Option<i64>,
+}
+
+/// Parse the embedded expectations.
+pub fn expectations() -> Result<Vec<Expectation>> {
+    EXPECTED
+        .lines()
+        .skip(1)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let [ctg, min_supported_fraction, unsupported_at] =
+                line.split('\t').collect::<Vec<_>>()[..]
+            else {
+                return Err(GavisunkError::MissingColumns(line.to_owned()));
+            };
+            Ok(Expectation {
+                ctg: ctg.to_owned(),
+                min_supported_fraction: min_supported_fraction.parse()?,
+                unsupported_at: (unsupported_at != ".")
+                    .then(|| unsupported_at.parse())
+                    .transpose()?,
+            })
+        })
+        .collect()
+}
+
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut decompressed = vec![];
+    GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Write the embedded assembly and reads as plain FASTA.
+pub fn write_inputs(path_asm: impl AsRef<Path>, path_reads: impl AsRef<Path>) -> Result<()> {
+    for (path, bytes) in [(path_asm.as_ref(), ASSEMBLY), (path_reads.as_ref(), READS)] {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, gunzip(bytes)?)?;
+    }
+    Ok(())
+}
+
+/// Lengths of the embedded contigs.
+pub fn contig_lengths() -> Result<HashMap<String, u64>> {
+    let mut lens: HashMap<String, u64> = HashMap::new();
+    let mut ctg = None;
+    for line in String::from_utf8_lossy(&gunzip(ASSEMBLY)?).lines() {
+        if let Some(name) = line.strip_prefix('>') {
+            ctg = Some(name.to_owned());
+        } else if let Some(ctg) = &ctg {
+            *lens.entry(ctg.clone()).or_default() += line.trim_end().len() as u64;
+        }
+    }
+    Ok(lens)
+}
+
+/// Check the outputs of a run on the embedded dataset against expectations.
+///
+/// # Arguments
+/// * `dir`
+///     * Output directory of the run.
+///
+/// # Returns
+/// * Number of checks and a description of each failed check.
+pub fn check_outputs(dir: impl AsRef<Path>) -> Result<(usize, Vec<String>)> {
+    let dir = dir.as_ref();
+    let supported = load_bed(dir.join("supported.bed"))?;
+    let unsupported = load_bed(dir.join("unsupported.bed"))?;
+    let lens = contig_lengths()?;
+    let (mut checked, mut failures) = (0, vec![]);
+    for expected in expectations()? {
+        let ctg = expected.ctg.as_str();
+        let len = lens.get(ctg).copied().unwrap_or_default() as i64;
+        let supported_bp: i64 = supported
+            .get(ctg)
+            .map_or(0, |itvs| itvs.iter().map(|(st, end)| end - st).sum());
+        let fraction = supported_bp as f64 / len.max(1) as f64;
+        checked += 1;
+        if fraction < expected.min_supported_fraction {
+            failures.push(format!(
+                "{ctg} is {:.1}% supported, less than the expected {:.1}%.",
+                fraction * 100.0,
+                expected.min_supported_fraction * 100.0
+            ));
+        }
+
+        let ctg_unsupported = unsupported.get(ctg).map_or(&[][..], Vec::as_slice);
+        checked += 1;
+        match expected.unsupported_at {
+            Some(pos) => {
+                let (near_st, near_end) = (pos - MISJOIN_SLOP, pos + MISJOIN_SLOP);
+                if !ctg_unsupported
+                    .iter()
+                    .any(|(st, end)| *st < near_end && *end > near_st)
+                {
+                    failures.push(format!(
+                        "{ctg} has no unsupported interval within {MISJOIN_SLOP} bp of its misjoin at {pos}."
+                    ));
+                }
+            }
+            None => {
+                if let Some((st, end)) = ctg_unsupported
+                    .iter()
+                    .find(|(st, end)| *st > 0 && *end < len && end - st > MISJOIN_SLOP)
+                {
+                    failures.push(format!(
+                        "{ctg} has an unexpected unsupported interval at {st}-{end}."
+                    ));
+                }
+            }
+        }
+    }
+    Ok((checked, failures))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{contig_lengths, expectations};
+
+    #[test]
+    fn test_embedded_dataset() {
+        let lens = contig_lengths().unwrap();
+        for expected in expectations().unwrap() {
+            assert!(lens.contains_key(&expected.ctg), "{}", expected.ctg);
+        }
+        assert_eq!(lens.get("ctg_misjoin"), Some(&40_000));
+    }
+}
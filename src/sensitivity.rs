@@ -0,0 +1,342 @@
+//! Chance a misassembly would still appear supported given SUNK density, read lengths, and the distance tolerance.
+//!
+//! An insertion or deletion of `S` bp is only caught by a read carrying a pair of SUNKs on either side of it whose read
+//! and contig distance differ by more than the tolerance, so the pair must be closer than `S / tolerance`. With SUNKs
+//! placed uniformly at density `λ`, the gap between the SUNKs flanking a point is `Gamma(2, λ)`. A gap of `g` bp is
+//! spanned by `coverage * E[(L - g)+] / E[L]` reads on average for read lengths `L`. A misassembly appears supported
+//! if its flanking gap is too wide or no read spans it:
+//!
+//! `P(S) = P(G > S / tol) + ∫_0^(S / tol) f_G(g) exp(-coverage * E[(L - g)+] / E[L]) dg`
+//!
+//! Every read spanning a SUNK pair is assumed to carry both SUNKs, so estimates are a lower bound.
+
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+
+use crate::{error::Result, get_kmers::sunk_id};
+
+/// Largest false-support probability for a misassembly size to be detectable.
+pub const MAX_FALSE_SUPPORT: f64 = 0.05;
+/// Largest misassembly size searched for the minimum detectable size.
+pub const MAX_MISASSEMBLY_SIZE: u64 = 1_000_000;
+/// Misassembly sizes with a reported false-support probability per contig, and their column names.
+pub const FALSE_SUPPORT_SIZES: [(u64, &str); 3] =
+    [(1_000, "p_1kb"), (10_000, "p_10kb"), (100_000, "p_100kb")];
+/// Steps integrating over the flanking SUNK gap.
+const INTEGRATION_STEPS: usize = 256;
+/// Flanking gaps are integrated up to this many mean SUNK distances. The remaining density is negligible.
+const MAX_GAP_MEANS: f64 = 40.0;
+
+/// Read length distribution.
+#[derive(Debug, Clone, Default)]
+pub struct ReadLengthDist {
+    /// Sorted read lengths.
+    lens: Vec<u64>,
+    /// Sum of lengths from each index to the end.
+    suffix_sums: Vec<u64>,
+}
+
+impl ReadLengthDist {
+    pub fn new(lens: impl IntoIterator<Item = u64>) -> Self {
+        let mut lens: Vec<u64> = lens.into_iter().collect();
+        lens.sort_unstable();
+        let mut suffix_sums = vec![0; lens.len() + 1];
+        for (i, len) in lens.iter().enumerate().rev() {
+            suffix_sums[i] = suffix_sums[i + 1] + len;
+        }
+        Self { lens, suffix_sums }
+    }
+
+    /// Expected reads spanning `span` bp per 1x of coverage. `E[(L - span)+] / E[L]`
+    pub fn spanning_fraction(&self, span: f64) -> f64 {
+        let total = self.suffix_sums[0];
+        if total == 0 {
+            return 0.0;
+        }
+        let idx = self.lens.partition_point(|len| (*len as f64) <= span);
+        let n_longer = (self.lens.len() - idx) as f64;
+        ((self.suffix_sums[idx] as f64 - span * n_longer) / total as f64).max(0.0)
+    }
+}
+
+/// Probability that a misassembly of `size` bp appears supported.
+///
+/// # Arguments
+/// * `size`
+///     * Size of the inserted or deleted sequence.
+/// * `density`
+///     * SUNKs per bp.
+/// * `coverage`
+///     * Read coverage.
+/// * `tolerance`
+///     * Allowed relative difference between the read and contig distance of a SUNK pair.
+/// * `reads`
+///     * Read length distribution.
+pub fn false_support_probability(
+    size: f64,
+    density: f64,
+    coverage: f64,
+    tolerance: f64,
+    reads: &ReadLengthDist,
+) -> f64 {
+    if density <= 0.0 || coverage <= 0.0 || tolerance <= 0.0 {
+        return 1.0;
+    }
+    let max_gap = size / tolerance;
+    let x = density * max_gap;
+    let p_wide_gap = (-x).exp() * (1.0 + x);
+    // Gaps too narrow to catch the misassembly that no read spans. Midpoint rule over the Gamma(2, λ) density.
+    let end = max_gap.min(MAX_GAP_MEANS / density);
+    let step = end / INTEGRATION_STEPS as f64;
+    let p_unspanned: f64 = (0..INTEGRATION_STEPS)
+        .map(|i| {
+            let gap = (i as f64 + 0.5) * step;
+            let pdf = density.powi(2) * gap * (-density * gap).exp();
+            pdf * (-coverage * reads.spanning_fraction(gap)).exp() * step
+        })
+        .sum();
+    (p_wide_gap + p_unspanned).clamp(0.0, 1.0)
+}
+
+/// Smallest misassembly size with a false-support probability of at most [`MAX_FALSE_SUPPORT`].
+///
+/// See [`false_support_probability`] for arguments.
+///
+/// # Returns
+/// * [`None`] if misassemblies up to [`MAX_MISASSEMBLY_SIZE`] can't be detected.
+pub fn min_detectable_size(
+    density: f64,
+    coverage: f64,
+    tolerance: f64,
+    reads: &ReadLengthDist,
+) -> Option<u64> {
+    let prob =
+        |size: u64| false_support_probability(size as f64, density, coverage, tolerance, reads);
+    if prob(MAX_MISASSEMBLY_SIZE) > MAX_FALSE_SUPPORT {
+        return None;
+    }
+    // The probability falls with size as wider flanking gaps catch it.
+    let (mut lo, mut hi) = (1, MAX_MISASSEMBLY_SIZE);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if prob(mid) <= MAX_FALSE_SUPPORT {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Some(lo)
+}
+
+/// Sorted positions of SUNKs by contig, excluding bad SUNKs.
+///
+/// # Arguments
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, group, ...]`
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNKs with column `[id, ...]`. See [`sunk_id`].
+pub fn good_sunk_positions(
+    df_asm_sunks: &DataFrame,
+    df_bad_sunks: &DataFrame,
+) -> Result<HashMap<String, Vec<i64>>> {
+    let bad: HashSet<&str> = df_bad_sunks.column("id")?.str()?.iter().flatten().collect();
+    let cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let group = df_asm_sunks.column("group")?.cast(&DataType::Int64)?;
+    let mut positions: HashMap<String, Vec<i64>> = HashMap::new();
+    for ((ctg, cpos), group) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(cpos.i64()?.iter())
+        .zip(group.i64()?.iter())
+    {
+        let (Some(ctg), Some(cpos), Some(group)) = (ctg, cpos, group) else {
+            continue;
+        };
+        if bad.contains(sunk_id(ctg, group).as_str()) {
+            continue;
+        }
+        positions.entry(ctg.to_owned()).or_default().push(cpos);
+    }
+    for ctg_positions in positions.values_mut() {
+        ctg_positions.sort_unstable();
+    }
+    Ok(positions)
+}
+
+/// Coverage of each contig by the bases of reads assigned to it.
+///
+/// # Arguments
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ...]`
+/// * `read_lens`
+///     * Read lengths.
+/// * `asm_lens`
+///     * Contig lengths.
+pub fn contig_coverage(
+    df_best_reads_asm: &DataFrame,
+    read_lens: &HashMap<String, u64>,
+    asm_lens: &HashMap<String, u64>,
+) -> Result<HashMap<String, f64>> {
+    let mut bases: HashMap<&str, u64> = HashMap::new();
+    for (read, ctg) in df_best_reads_asm
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_best_reads_asm.column("ctg")?.str()?.iter())
+    {
+        let (Some(read), Some(ctg)) = (read, ctg) else {
+            continue;
+        };
+        *bases.entry(ctg).or_default() += read_lens.get(read).copied().unwrap_or_default();
+    }
+    Ok(bases
+        .into_iter()
+        .filter_map(|(ctg, bases)| {
+            let len = *asm_lens.get(ctg).filter(|len| **len > 0)?;
+            Some((ctg.to_owned(), bases as f64 / len as f64))
+        })
+        .collect())
+}
+
+/// Minimum detectable misassembly size of each region.
+///
+/// # Arguments
+/// * `df_regions`
+///     * [`DataFrame`] of regions with columns `[ctg, st, end, ...]`. ex. supported intervals.
+/// * `sunk_positions`
+///     * Sorted SUNK positions by contig. See [`good_sunk_positions`].
+/// * `coverage`
+///     * Coverage by contig. See [`contig_coverage`].
+/// * `tolerance`, `reads`
+///     * See [`false_support_probability`].
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, st, end, sunks_per_kbp, coverage, min_detectable_size]`
+///     * `min_detectable_size` is null if no misassembly up to [`MAX_MISASSEMBLY_SIZE`] is detectable.
+pub fn region_sensitivity(
+    df_regions: &DataFrame,
+    sunk_positions: &HashMap<String, Vec<i64>>,
+    coverage: &HashMap<String, f64>,
+    tolerance: f64,
+    reads: &ReadLengthDist,
+) -> Result<DataFrame> {
+    let st = df_regions.column("st")?.cast(&DataType::Int64)?;
+    let end = df_regions.column("end")?.cast(&DataType::Int64)?;
+    let (mut ctgs, mut sts, mut ends, mut densities, mut coverages, mut min_sizes) =
+        (vec![], vec![], vec![], vec![], vec![], vec![]);
+    for ((ctg, st), end) in df_regions
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(st.i64()?.iter())
+        .zip(end.i64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            continue;
+        };
+        let positions = sunk_positions.get(ctg).map_or(&[][..], Vec::as_slice);
+        // SUNK positions are 1-based.
+        let n_sunks = positions.partition_point(|pos| *pos <= end)
+            - positions.partition_point(|pos| *pos <= st);
+        let density = n_sunks as f64 / (end - st).max(1) as f64;
+        let ctg_coverage = coverage.get(ctg).copied().unwrap_or_default();
+        ctgs.push(ctg.to_owned());
+        sts.push(st);
+        ends.push(end);
+        densities.push(density * 1_000.0);
+        coverages.push(ctg_coverage);
+        min_sizes.push(min_detectable_size(density, ctg_coverage, tolerance, reads));
+    }
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("st".into(), sts),
+        Column::new("end".into(), ends),
+        Column::new("sunks_per_kbp".into(), densities),
+        Column::new("coverage".into(), coverages),
+        Column::new("min_detectable_size".into(), min_sizes),
+    ])?)
+}
+
+/// False-support probabilities of misassemblies of [`FALSE_SUPPORT_SIZES`] in each contig.
+///
+/// # Arguments
+/// * `ctgs`
+///     * Contigs to report.
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `sunk_positions`, `coverage`, `tolerance`, `reads`
+///     * See [`region_sensitivity`].
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, length, sunks_per_kbp, coverage, min_detectable_size, p_1kb, p_10kb, p_100kb]`
+pub fn contig_false_support(
+    ctgs: &[String],
+    asm_lens: &HashMap<String, u64>,
+    sunk_positions: &HashMap<String, Vec<i64>>,
+    coverage: &HashMap<String, f64>,
+    tolerance: f64,
+    reads: &ReadLengthDist,
+) -> Result<DataFrame> {
+    let mut lens = vec![];
+    let (mut densities, mut coverages, mut min_sizes) = (vec![], vec![], vec![]);
+    let mut probs: Vec<Vec<f64>> = vec![vec![]; FALSE_SUPPORT_SIZES.len()];
+    for ctg in ctgs {
+        let len = asm_lens.get(ctg).copied().unwrap_or_default();
+        let n_sunks = sunk_positions.get(ctg).map_or(0, Vec::len);
+        let density = n_sunks as f64 / len.max(1) as f64;
+        let ctg_coverage = coverage.get(ctg).copied().unwrap_or_default();
+        lens.push(len);
+        densities.push(density * 1_000.0);
+        coverages.push(ctg_coverage);
+        min_sizes.push(min_detectable_size(density, ctg_coverage, tolerance, reads));
+        for ((size, _), size_probs) in FALSE_SUPPORT_SIZES.iter().zip(probs.iter_mut()) {
+            size_probs.push(false_support_probability(
+                *size as f64,
+                density,
+                ctg_coverage,
+                tolerance,
+                reads,
+            ));
+        }
+    }
+    let mut cols = vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("length".into(), lens),
+        Column::new("sunks_per_kbp".into(), densities),
+        Column::new("coverage".into(), coverages),
+        Column::new("min_detectable_size".into(), min_sizes),
+    ];
+    for ((_, name), size_probs) in FALSE_SUPPORT_SIZES.iter().zip(probs) {
+        cols.push(Column::new((*name).into(), size_probs));
+    }
+    Ok(DataFrame::new(cols)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{false_support_probability, min_detectable_size, ReadLengthDist};
+
+    #[test]
+    fn test_false_support_probability() {
+        let reads = ReadLengthDist::new([10_000, 20_000, 50_000]);
+        assert_eq!(reads.spanning_fraction(0.0), 1.0);
+        assert_eq!(reads.spanning_fraction(50_000.0), 0.0);
+
+        // One SUNK per kbp at 30x.
+        let p = |size: f64| false_support_probability(size, 1e-3, 30.0, 0.1, &reads);
+        assert!(p(100.0) > p(1_000.0) && p(1_000.0) > p(10_000.0));
+        assert!(p(10_000.0) < 1e-6);
+        assert_eq!(
+            false_support_probability(1_000.0, 0.0, 30.0, 0.1, &reads),
+            1.0
+        );
+
+        let min_size = min_detectable_size(1e-3, 30.0, 0.1, &reads).unwrap();
+        assert!(p(min_size as f64) <= 0.05 && p((min_size - 1) as f64) > 0.05);
+        // Sparser SUNKs need larger misassemblies.
+        assert!(min_detectable_size(1e-4, 30.0, 0.1, &reads).unwrap() > min_size);
+        assert_eq!(min_detectable_size(1e-3, 0.0, 0.1, &reads), None);
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{error::Result, ratio};
+
+/// Default allowed deviation of the median read to contig distance ratio from 1 before a contig is flagged.
+pub const DEFAULT_SPACING_TOLERANCE: f64 = 0.02;
+/// Minimum number of read SUNK pairs on a contig for it to be flagged.
+const MIN_SPACING_PAIRS: usize = 20;
+/// Read to contig distance ratios outside this range are spurious hits and ignored.
+const SPACING_RATIO_BOUNDS: (f64, f64) = (0.5, 2.0);
+/// Minimum SUNKs of a read in a component to fit its scale.
+const MIN_SCALE_ANCHORS: usize = 3;
+/// Minimum reads with a fitted scale for a component to be flagged.
+const MIN_SCALE_READS: usize = 2;
+
+/// Reads span more bases than the contig between SUNKs. Sequence is missing from the contig.
+pub const COLLAPSED: &str = "collapsed";
+/// Reads span fewer bases than the contig between SUNKs. Sequence is duplicated in the contig.
+pub const EXPANDED: &str = "expanded";
+/// Read and contig spacing agree.
+pub const CONSISTENT: &str = "consistent";
+
+/// Status of a ratio, `above` or `below` if it deviates from 1 by more than `tolerance` and [`CONSISTENT`] otherwise.
+///
+/// Deviations exactly at the tolerance, to the nearest part per million, are [`CONSISTENT`].
+/// See [`ratio::deviation_ppm`].
+fn deviation_status(
+    value: f64,
+    tolerance: f64,
+    above: &'static str,
+    below: &'static str,
+) -> &'static str {
+    let (deviation, tolerance) = (ratio::deviation_ppm(value), ratio::to_ppm(tolerance));
+    if deviation > tolerance {
+        above
+    } else if deviation < -tolerance {
+        below
+    } else {
+        CONSISTENT
+    }
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_unstable_by(f64::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Least squares slope of `y` on `x`. [`None`] if `x` is constant.
+fn slope(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let (mean_x, mean_y) = points
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+    let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), (x, y)| {
+        (
+            cov + (x - mean_x) * (y - mean_y),
+            var + (x - mean_x).powi(2),
+        )
+    });
+    (var > 0.0).then(|| cov / var)
+}
+
+/// Compare the expected spacing of SUNKs in each contig with their observed spacing in reads.
+///
+/// Expected spacing is the distance between adjacent SUNK groups in the assembly. Observed spacing is the ratio of read
+/// to contig distance between adjacent SUNKs of reads assigned to the contig.
+///
+/// # Arguments
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, group, ...]`
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, group, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of read assignments with columns `[read, ctg, ...]`. Only SUNKs on the assigned contig are used.
+/// * `tolerance`
+///     * Allowed deviation of the median ratio from 1.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, sunks, mean_dst, median_dst, read_pairs, median_ratio, status]`
+///     * `mean_dst` and `median_dst` are the expected distances between adjacent SUNK groups.
+///       With uniformly placed SUNKs, distances are exponential with mean `mean_dst`.
+///     * `median_ratio` is the median read to contig distance ratio of adjacent read SUNKs.
+///     * `status` is [`COLLAPSED`], [`EXPANDED`], or [`CONSISTENT`]. Null with too few read SUNK pairs.
+pub fn contig_spacing_stats(
+    df_asm_sunks: &DataFrame,
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    tolerance: f64,
+) -> Result<DataFrame> {
+    // Expected spacing from adjacent SUNK groups.
+    let df_groups = df_asm_sunks
+        .clone()
+        .lazy()
+        .select([col("ctg"), col("group").cast(DataType::Int64)])
+        .unique(None, UniqueKeepStrategy::First)
+        .sort(["ctg", "group"], Default::default())
+        .collect()?;
+    let mut expected: HashMap<&str, (usize, Vec<f64>)> = HashMap::new();
+    for (ctg, groups) in &df_groups
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_groups.column("group")?.i64()?.iter())
+        .flat_map(|(ctg, group)| Some((ctg?, group?)))
+        .chunk_by(|(ctg, _)| *ctg)
+    {
+        let groups = groups.map(|(_, group)| group).collect_vec();
+        let dsts = groups
+            .iter()
+            .tuple_windows()
+            .map(|(a, b)| (b - a) as f64)
+            .collect();
+        expected.insert(ctg, (groups.len(), dsts));
+    }
+
+    // Observed spacing from adjacent SUNKs of assigned reads.
+    let df_anchors = df_read_sunks
+        .select(["read", "rpos", "ctg", "cpos", "group"])?
+        .inner_join(
+            &df_best_reads_asm.select(["read", "ctg"])?,
+            ["read", "ctg"],
+            ["read", "ctg"],
+        )?
+        .lazy()
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
+            col("group").cast(DataType::Int64),
+        ])
+        .sort(["read", "cpos"], Default::default())
+        .collect()?;
+    let mut observed: HashMap<&str, Vec<f64>> = HashMap::new();
+    let rows = df_anchors
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_anchors.column("ctg")?.str()?.iter())
+        .zip(df_anchors.column("rpos")?.i64()?.iter())
+        .zip(df_anchors.column("cpos")?.i64()?.iter())
+        .zip(df_anchors.column("group")?.i64()?.iter())
+        .flat_map(|((((read, ctg), rpos), cpos), group)| Some((read?, ctg?, rpos?, cpos?, group?)));
+    for ((_, ctg), anchors) in &rows.chunk_by(|(read, ctg, _, _, _)| (*read, *ctg)) {
+        let ratios = observed.entry(ctg).or_default();
+        for ((_, _, rpos_1, cpos_1, group_1), (_, _, rpos_2, cpos_2, group_2)) in
+            anchors.tuple_windows()
+        {
+            // Overlapping kmers of the same group aren't independent.
+            if group_1 == group_2 || cpos_1 == cpos_2 {
+                continue;
+            }
+            let ratio = (rpos_2 - rpos_1).abs() as f64 / (cpos_2 - cpos_1) as f64;
+            if ratio > SPACING_RATIO_BOUNDS.0 && ratio < SPACING_RATIO_BOUNDS.1 {
+                ratios.push(ratio);
+            }
+        }
+    }
+
+    let (mut ctgs, mut n_sunks, mut mean_dsts, mut median_dsts) = (vec![], vec![], vec![], vec![]);
+    let (mut read_pairs, mut median_ratios, mut statuses) = (vec![], vec![], vec![]);
+    for (ctg, (sunks, mut dsts)) in expected.into_iter().sorted_by_key(|(ctg, _)| *ctg) {
+        let mut ratios = observed.remove(ctg).unwrap_or_default();
+        let median_ratio = median(&mut ratios);
+        let status = median_ratio
+            .filter(|_| ratios.len() >= MIN_SPACING_PAIRS)
+            .map(|median_ratio| deviation_status(median_ratio, tolerance, COLLAPSED, EXPANDED));
+        ctgs.push(ctg);
+        n_sunks.push(sunks as u64);
+        mean_dsts.push((!dsts.is_empty()).then(|| dsts.iter().sum::<f64>() / dsts.len() as f64));
+        median_dsts.push(median(&mut dsts));
+        read_pairs.push(ratios.len() as u64);
+        median_ratios.push(median_ratio);
+        statuses.push(status);
+    }
+    let n_flagged = statuses
+        .iter()
+        .filter(|status| status.is_some_and(|status| status != CONSISTENT))
+        .count();
+    log::info!("{n_flagged} contigs with read SUNK spacing deviating from the assembly.");
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), ctgs),
+        Column::new("sunks".into(), n_sunks),
+        Column::new("mean_dst".into(), mean_dsts),
+        Column::new("median_dst".into(), median_dsts),
+        Column::new("read_pairs".into(), read_pairs),
+        Column::new("median_ratio".into(), median_ratios),
+        Column::new("status".into(), statuses),
+    ])?)
+}
+
+/// Estimate the consensus offset and scale of reads in each supported component of a contig.
+///
+/// Each read's contig positions are regressed on its read positions within the component. The absolute slope is the
+/// read's local scale, contig bases per read base. Components whose median scale deviates from 1 are candidate
+/// expansions or collapses of the assembly.
+///
+/// # Arguments
+/// * `ctg`
+///     * Contig.
+/// * `df_sunks`
+///     * [`DataFrame`] of read SUNKs in components with columns `[read, rpos, proj_cpos, offset_dev, component, ...]`.
+///       See [`crate::sunk_graph::create_sunk_graph`].
+/// * `df_bed`
+///     * [`DataFrame`] of the contig's supported intervals with columns `[ctg, st, end, ...]`, one per component.
+/// * `tolerance`
+///     * Allowed deviation of the median scale from 1.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, st, end, component, reads, offset, scale, status, size_delta]`
+///     * `offset` is the median `offset_dev` of the component's SUNKs, its shift from the projection of its reads.
+///     * `scale` is the median scale of reads with at least [`MIN_SCALE_ANCHORS`] SUNKs in the component.
+///     * `status` is [`COLLAPSED`], [`EXPANDED`], or [`CONSISTENT`]. Null with too few reads.
+///     * `size_delta` is the estimated assembly minus read bases of the interval. Positive if expanded.
+pub fn component_scale_stats(
+    ctg: &str,
+    df_sunks: &DataFrame,
+    df_bed: &DataFrame,
+    tolerance: f64,
+) -> Result<DataFrame> {
+    let df_sunks = df_sunks
+        .clone()
+        .lazy()
+        .select([
+            col("read"),
+            col("component").cast(DataType::UInt64),
+            col("rpos").cast(DataType::Int64),
+            col("offset_dev").cast(DataType::Int64),
+            (col("proj_cpos") + col("offset_dev"))
+                .cast(DataType::Int64)
+                .alias("cpos"),
+        ])
+        .collect()?;
+    let mut anchors: HashMap<(u64, &str), Vec<(f64, f64)>> = HashMap::new();
+    let mut offsets: HashMap<u64, Vec<f64>> = HashMap::new();
+    for ((((read, comp), rpos), offset_dev), cpos) in df_sunks
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df_sunks.column("component")?.u64()?.iter())
+        .zip(df_sunks.column("rpos")?.i64()?.iter())
+        .zip(df_sunks.column("offset_dev")?.i64()?.iter())
+        .zip(df_sunks.column("cpos")?.i64()?.iter())
+    {
+        let (Some(read), Some(comp), Some(rpos), Some(offset_dev), Some(cpos)) =
+            (read, comp, rpos, offset_dev, cpos)
+        else {
+            continue;
+        };
+        anchors
+            .entry((comp, read))
+            .or_default()
+            .push((rpos as f64, cpos as f64));
+        offsets.entry(comp).or_default().push(offset_dev as f64);
+    }
+    let mut scales: HashMap<u64, Vec<f64>> = HashMap::new();
+    for ((comp, _), points) in anchors {
+        if points.len() < MIN_SCALE_ANCHORS {
+            continue;
+        }
+        if let Some(scale) = slope(&points)
+            .map(f64::abs)
+            .filter(|scale| *scale > SPACING_RATIO_BOUNDS.0 && *scale < SPACING_RATIO_BOUNDS.1)
+        {
+            scales.entry(comp).or_default().push(scale);
+        }
+    }
+
+    let (mut starts, mut ends, mut comps, mut n_reads) = (vec![], vec![], vec![], vec![]);
+    let (mut comp_offsets, mut comp_scales, mut statuses, mut size_deltas) =
+        (vec![], vec![], vec![], vec![]);
+    for (comp, (st, end)) in df_bed
+        .column("st")?
+        .i64()?
+        .iter()
+        .zip(df_bed.column("end")?.i64()?.iter())
+        .enumerate()
+    {
+        let (Some(st), Some(end)) = (st, end) else {
+            continue;
+        };
+        let comp = comp as u64;
+        let mut comp_scale = scales.remove(&comp).unwrap_or_default();
+        let scale = median(&mut comp_scale);
+        let status = scale
+            .filter(|_| comp_scale.len() >= MIN_SCALE_READS)
+            .map(|scale| deviation_status(scale, tolerance, EXPANDED, COLLAPSED));
+        starts.push(st);
+        ends.push(end);
+        comps.push(comp);
+        n_reads.push(comp_scale.len() as u64);
+        let mut comp_offset = offsets.remove(&comp).unwrap_or_default();
+        comp_offsets.push(median(&mut comp_offset));
+        comp_scales.push(scale);
+        statuses.push(status);
+        size_deltas
+            .push(scale.map(|scale| ((end - st) as f64 * (1.0 - 1.0 / scale)).round() as i64));
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), vec![ctg; starts.len()]),
+        Column::new("st".into(), starts),
+        Column::new("end".into(), ends),
+        Column::new("component".into(), comps),
+        Column::new("reads".into(), n_reads),
+        Column::new("offset".into(), comp_offsets),
+        Column::new("scale".into(), comp_scales),
+        Column::new("status".into(), statuses),
+        Column::new("size_delta".into(), size_deltas),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{
+        component_scale_stats, contig_spacing_stats, deviation_status, COLLAPSED, CONSISTENT,
+        EXPANDED,
+    };
+
+    #[test]
+    fn test_contig_spacing_stats() {
+        // SUNKs every 100 bp. Reads span 10% more bases than c1 between SUNKs and as many as c2. c3 has no reads.
+        let (mut asm_ctgs, mut groups) = (vec![], vec![]);
+        let (mut reads, mut rpos, mut read_ctgs, mut cpos) = (vec![], vec![], vec![], vec![]);
+        for (ctg, read, read_dst) in [("c1", "r1", 110), ("c2", "r2", 100), ("c3", "r3", 0)] {
+            for i in 0..30i64 {
+                asm_ctgs.push(ctg);
+                groups.push(i * 100);
+                if read_dst > 0 {
+                    reads.push(read);
+                    rpos.push(i * read_dst + 1);
+                    read_ctgs.push(ctg);
+                    cpos.push(i * 100);
+                }
+            }
+        }
+        let df_asm_sunks = df!(
+            "ctg" => asm_ctgs,
+            "cpos" => groups.clone(),
+            "group" => groups,
+        )
+        .unwrap();
+        let df_read_sunks = df!(
+            "read" => reads,
+            "rpos" => rpos,
+            "ctg" => read_ctgs,
+            "cpos" => cpos.clone(),
+            "group" => cpos,
+        )
+        .unwrap();
+        let df_best_reads_asm = df!("read" => ["r1", "r2"], "ctg" => ["c1", "c2"]).unwrap();
+        let df =
+            contig_spacing_stats(&df_asm_sunks, &df_read_sunks, &df_best_reads_asm, 0.02).unwrap();
+        assert!(df
+            .select(["ctg", "sunks", "median_dst", "read_pairs", "status"])
+            .unwrap()
+            .equals_missing(
+                &df!(
+                    "ctg" => ["c1", "c2", "c3"],
+                    "sunks" => [30u64, 30, 30],
+                    "median_dst" => [100.0, 100.0, 100.0],
+                    "read_pairs" => [29u64, 29, 0],
+                    "status" => [Some(COLLAPSED), Some(CONSISTENT), None],
+                )
+                .unwrap()
+            ));
+    }
+
+    #[test]
+    fn test_deviation_status() {
+        // Deviations exactly at the tolerance are consistent despite 1.1 - 1.0 > 0.1 in floating point.
+        assert_eq!(deviation_status(1.1, 0.1, COLLAPSED, EXPANDED), CONSISTENT);
+        assert_eq!(deviation_status(0.9, 0.1, COLLAPSED, EXPANDED), CONSISTENT);
+        assert_eq!(
+            deviation_status(1.100001, 0.1, COLLAPSED, EXPANDED),
+            COLLAPSED
+        );
+        assert_eq!(
+            deviation_status(0.899999, 0.1, COLLAPSED, EXPANDED),
+            EXPANDED
+        );
+    }
+
+    #[test]
+    fn test_component_scale_stats() {
+        // Reads r1 and r2 span 10% fewer bases than component 0 and agree with component 1.
+        let (mut reads, mut rpos, mut proj_cpos, mut comps) = (vec![], vec![], vec![], vec![]);
+        for (read, st) in [("r1", 0), ("r2", 50)] {
+            for i in 0..5 {
+                reads.push(read);
+                rpos.push(st + i * 100);
+                proj_cpos.push(1000 + st + i * 110);
+                comps.push(Some(0u64));
+            }
+            for i in 0..5 {
+                reads.push(read);
+                rpos.push(st + 1000 + i * 100);
+                proj_cpos.push(5000 + st + i * 100);
+                comps.push(Some(1u64));
+            }
+        }
+        let df_sunks = df!(
+            "read" => reads,
+            "rpos" => rpos,
+            "proj_cpos" => proj_cpos,
+            "offset_dev" => vec![0i64; 20],
+            "component" => comps,
+        )
+        .unwrap();
+        let df_bed = df!(
+            "ctg" => ["c1", "c1"],
+            "st" => [1000i64, 5000],
+            "end" => [2100i64, 6000],
+        )
+        .unwrap();
+        let df = component_scale_stats("c1", &df_sunks, &df_bed, 0.02).unwrap();
+        let scales: Vec<f64> = df
+            .column("scale")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert!((scales[0] - 1.1).abs() < 1e-9);
+        assert!((scales[1] - 1.0).abs() < 1e-9);
+        let statuses: Vec<Option<&str>> =
+            df.column("status").unwrap().str().unwrap().iter().collect();
+        assert_eq!(statuses, [Some(EXPANDED), Some(CONSISTENT)]);
+        assert_eq!(
+            df.column("size_delta").unwrap().i64().unwrap().get(0),
+            Some(100)
+        );
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use polars::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    error::{GavisunkError, Result},
+    intervals::merge,
+    io::write_atomic,
+    joins::check_join_loss,
+    minhash::SketchStats,
+    naming::normalize_name,
+    parameters::Parameters,
+    run_state::RunState,
+    sunk_context::{CovariateStratum, SunkContextStats},
+};
+
+/// Upper bounds of read length bins. Reads this length or longer fall in the last, ultralong, bin.
+pub const READ_LENGTH_BINS: [u64; 2] = [30_000, 100_000];
+/// Read group of reads missing from `--read-groups`.
+pub const UNGROUPED: &str = "ungrouped";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    #[default]
+    Complete,
+    /// Run was interrupted or a contig failed in the graph stage. Only outputs for `contigs_completed` are valid.
+    Partial,
+}
+
+/// Summary of a pipeline run written as JSON alongside outputs.
+#[derive(Debug, Default, Serialize)]
+pub struct RunSummary {
+    pub status: RunStatus,
+    /// Parameters of the run. Also written as a comment header of each final output.
+    pub parameters: Option<Parameters>,
+    /// Comment header of final outputs. See [`crate::parameters::RunMetadata::comment_header`].
+    #[serde(skip)]
+    pub comment_header: String,
+    /// Total read bases divided by total assembly bases.
+    pub expected_coverage: Option<f64>,
+    /// Malformed FASTA records skipped, as `{path}:{name}`.
+    pub malformed_records: Vec<String>,
+    /// Similarity of a subset of reads to the assembly. Only set with `--sketch-check`.
+    pub sketch: Option<SketchStats>,
+    /// Stages that finished and whose intermediates are safe to reuse.
+    pub stages_completed: Vec<String>,
+    pub contigs_total: usize,
+    pub contigs_completed: Vec<String>,
+    /// Contigs not processed or interrupted mid-way. Stale outputs for these are removed.
+    pub contigs_incomplete: Vec<String>,
+    /// Total length of completed contigs.
+    pub assembly_bp: u64,
+    /// Bases of completed contigs within supported intervals.
+    pub supported_bp: u64,
+    /// Contigs whose read SUNK spacing deviates from the assembly. See `contig_spacing_stats.tsv`.
+    pub contigs_spacing_flagged: Vec<String>,
+    /// Contigs reverse complemented relative to the reference in `--ref-paf` and flipped in outputs.
+    pub contigs_flipped: Vec<String>,
+    /// Contigs no longer than the reads used to build SUNK graphs, supported by reads fully containing them rather
+    /// than by their SUNK graph. Labeled `contained-support` in `contig_summary.tsv`.
+    pub contigs_contained_support: Vec<String>,
+    /// Circular contigs from `--circular` or `--circular-gfa` with reads spanning their origin. The intervals on either
+    /// side of the origin are supported. See `origin_reads.tsv`.
+    pub contigs_origin_support: Vec<String>,
+    /// Single-SUNK reads placed within supported components. Only set with `--rescue-single-sunk`.
+    pub rescued_reads: Option<usize>,
+    /// Reads skipped in the graph stage for exceeding `--max-read-sunks` or `--read-timeout` on a contig.
+    pub skipped_reads: usize,
+    /// Support stratified by read length. Only set once the graph stage finishes.
+    pub read_lengths: Option<ReadLengthStats>,
+    /// Mapping, assignment, and support stratified by read group. Only set with `--read-groups` once the graph stage
+    /// finishes.
+    pub read_groups: Option<Vec<ReadGroupStats>>,
+    /// SUNK groups in softmasked or gap-adjacent sequence.
+    pub sunk_context: Option<SunkContextStats>,
+    /// SUNK groups without reads and with abnormal read support stratified by GC content and max homopolymer length.
+    /// Only set once the filter stage finishes.
+    pub sunk_covariates: Option<Vec<CovariateStratum>>,
+}
+
+/// Read length distribution and the support contributed by reads of each length.
+#[derive(Debug, Default, Serialize)]
+pub struct ReadLengthStats {
+    pub n50: u64,
+    pub bins: Vec<ReadLengthBinStats>,
+}
+
+/// Support contributed by reads within a length bin.
+#[derive(Debug, Default, Serialize)]
+pub struct ReadLengthBinStats {
+    /// Bin label. ex. `30-100kb`
+    pub bin: String,
+    pub reads: usize,
+    pub bases: u64,
+    /// Reads with SUNKs in a supported contig component.
+    pub supporting_reads: usize,
+    /// SUNKs in supported contig components from reads in this bin.
+    pub supporting_sunks: u64,
+    /// Contig bases spanned by the supporting SUNKs of reads in this bin.
+    pub supported_bp: u64,
+}
+
+/// Mapping, assignment, and support of reads within a read group, ex. a flowcell or run.
+#[derive(Debug, Default, Serialize)]
+pub struct ReadGroupStats {
+    pub group: String,
+    pub reads: usize,
+    pub bases: u64,
+    /// Reads with any SUNK hit.
+    pub mapped_reads: usize,
+    /// Reads assigned to a contig.
+    pub assigned_reads: usize,
+    /// Reads with SUNKs in a supported contig component.
+    pub supporting_reads: usize,
+    /// SUNKs in supported contig components from reads in this group.
+    pub supporting_sunks: u64,
+    /// Contig bases spanned by the supporting SUNKs of reads in this group.
+    pub supported_bp: u64,
+    /// Fraction of the assembly in `supported_bp`.
+    pub supported_fraction: Option<f64>,
+}
+
+impl RunSummary {
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomic(path, |file| Ok(serde_json::to_writer_pretty(file, self)?))
+    }
+}
+
+/// Estimate expected coverage from read and assembly lengths alone.
+///
+/// # Returns
+/// * Total read bases / total assembly bases. [`None`] if the assembly is empty.
+pub fn estimate_coverage(
+    read_lens: &HashMap<String, u64>,
+    asm_lens: &HashMap<String, u64>,
+) -> Option<f64> {
+    let asm_bases: u64 = asm_lens.values().sum();
+    let read_bases: u64 = read_lens.values().sum();
+    (asm_bases > 0).then(|| read_bases as f64 / asm_bases as f64)
+}
+
+/// Read N50. Half of all read bases are in reads this length or longer.
+pub fn n50(read_lens: &HashMap<String, u64>) -> u64 {
+    let mut lens: Vec<u64> = read_lens.values().copied().collect();
+    lens.sort_unstable_by(|a, b| b.cmp(a));
+    let half = lens.iter().sum::<u64>().div_ceil(2);
+    let mut total = 0;
+    for len in lens {
+        total += len;
+        if total >= half {
+            return len;
+        }
+    }
+    0
+}
+
+fn read_length_bin_label(idx: usize) -> String {
+    match (
+        idx.checked_sub(1).map(|i| READ_LENGTH_BINS[i]),
+        READ_LENGTH_BINS.get(idx),
+    ) {
+        (None, Some(upper)) => format!("<{}kb", upper / 1000),
+        (Some(lower), Some(upper)) => format!("{}-{}kb", lower / 1000, upper / 1000),
+        (Some(lower), None) => format!(">={}kb", lower / 1000),
+        (None, None) => "all".to_owned(),
+    }
+}
+
+/// Stratify support by read length using [`READ_LENGTH_BINS`].
+///
+/// # Arguments
+/// * `read_lens`
+///     * Read lengths.
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in the largest component of each read with columns `[ctg, read, proj_cpos, offset_dev, component, ...]`
+///     * See [`crate::sunk_graph::create_sunk_graph`].
+/// * `state`
+///     * Run the join of component SUNKs to read lengths is checked in.
+///
+/// # Returns
+/// * N50 and per bin read counts, bases, and support. Supported bases are spans of each read's SUNKs within a
+///   contig component, so bins can overlap.
+pub fn read_length_stats(
+    read_lens: &HashMap<String, u64>,
+    df_components: &DataFrame,
+    state: &RunState,
+) -> Result<ReadLengthStats> {
+    let bin_idx = |len: u64| {
+        READ_LENGTH_BINS
+            .iter()
+            .position(|upper| len < *upper)
+            .unwrap_or(READ_LENGTH_BINS.len())
+    };
+    let mut bins: Vec<ReadLengthBinStats> = (0..=READ_LENGTH_BINS.len())
+        .map(|idx| ReadLengthBinStats {
+            bin: read_length_bin_label(idx),
+            ..Default::default()
+        })
+        .collect();
+    for len in read_lens.values() {
+        let bin = &mut bins[bin_idx(*len)];
+        bin.reads += 1;
+        bin.bases += len;
+    }
+
+    let support = stratified_support(read_lens, df_components, bins.len(), state, |_, len| {
+        Some(bin_idx(len))
+    })?;
+    for (bin, (supporting_reads, supporting_sunks, supported_bp)) in bins.iter_mut().zip(support) {
+        bin.supporting_reads = supporting_reads;
+        bin.supporting_sunks = supporting_sunks;
+        bin.supported_bp = supported_bp;
+    }
+    Ok(ReadLengthStats {
+        n50: n50(read_lens),
+        bins,
+    })
+}
+
+/// Support of reads in each of `n` strata, ex. read length bins.
+///
+/// # Arguments
+/// * `read_lens`
+///     * Read lengths.
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in the largest component of each read with columns `[ctg, read, proj_cpos, offset_dev, component, ...]`
+/// * `n`
+///     * Number of strata.
+/// * `state`
+///     * Run the join of component SUNKs to read lengths is checked in.
+/// * `stratum`
+///     * Stratum of a read given its name and length. Reads without one are skipped.
+///
+/// # Returns
+/// * Per stratum supporting reads, supporting SUNKs, and bases spanned by each read's SUNKs within a contig
+///   component.
+fn stratified_support(
+    read_lens: &HashMap<String, u64>,
+    df_components: &DataFrame,
+    n: usize,
+    state: &RunState,
+    stratum: impl Fn(&str, u64) -> Option<usize>,
+) -> Result<Vec<(usize, u64, u64)>> {
+    // Span of each read's SUNKs within a contig component.
+    let mut spans: HashMap<(&str, &str, u64), (i64, i64)> = HashMap::new();
+    let mut stratum_sunks = vec![0; n];
+    let mut missing_reads = 0;
+    for (((ctg, read), (proj_cpos, offset_dev)), component) in df_components
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_components.column("read")?.str()?.iter())
+        .zip(
+            df_components
+                .column("proj_cpos")?
+                .i64()?
+                .iter()
+                .zip(df_components.column("offset_dev")?.i64()?.iter()),
+        )
+        .zip(df_components.column("component")?.u64()?.iter())
+    {
+        let (Some(ctg), Some(read), Some(proj_cpos), Some(offset_dev), Some(component)) =
+            (ctg, read, proj_cpos, offset_dev, component)
+        else {
+            continue;
+        };
+        let Some(len) = read_lens.get(read) else {
+            missing_reads += 1;
+            continue;
+        };
+        let Some(idx) = stratum(read, *len) else {
+            continue;
+        };
+        stratum_sunks[idx] += 1;
+        let cpos = proj_cpos + offset_dev;
+        spans
+            .entry((ctg, read, component))
+            .and_modify(|(st, end)| {
+                *st = (*st).min(cpos);
+                *end = (*end).max(cpos);
+            })
+            .or_insert((cpos, cpos));
+    }
+    check_join_loss(
+        "component SUNKs x read lengths",
+        "reads missing from the reads FASTA",
+        df_components.height(),
+        missing_reads,
+        state,
+    );
+    let mut stratum_reads: Vec<HashSet<&str>> = vec![HashSet::new(); n];
+    let mut stratum_spans: Vec<HashMap<&str, Vec<(i64, i64)>>> = vec![HashMap::new(); n];
+    for ((ctg, read, _), span) in spans {
+        let Some(idx) = stratum(read, read_lens[read]) else {
+            continue;
+        };
+        stratum_reads[idx].insert(read);
+        stratum_spans[idx].entry(ctg).or_default().push(span);
+    }
+    Ok(stratum_reads
+        .into_iter()
+        .zip(stratum_sunks)
+        .zip(stratum_spans)
+        .map(|((reads, sunks), ctg_spans)| {
+            let supported_bp = ctg_spans
+                .into_values()
+                .flat_map(|spans| merge(spans, 0))
+                .map(|(st, end)| (end - st) as u64)
+                .sum();
+            (reads.len(), sunks, supported_bp)
+        })
+        .collect())
+}
+
+/// Load the read group of each read, ex. its flowcell or run id.
+///
+/// Loaded from a TSV of `read` and `group` without a header. Read names are normalized with [`normalize_name`].
+pub fn load_read_groups(path: impl AsRef<Path>) -> Result<HashMap<String, String>> {
+    let mut groups = HashMap::new();
+    let reader = BufReader::new(File::open(path)?);
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |reason: &str| {
+            GavisunkError::InvalidReadGroupMap(format!("line {}: {reason}", line_num + 1))
+        };
+        let (read, group) = line
+            .split_once('\t')
+            .ok_or_else(|| err("expected a read and read group separated by a tab"))?;
+        let (read, group) = (normalize_name(read.trim()), group.trim());
+        if read.is_empty() || group.is_empty() {
+            return Err(err("empty read or read group"));
+        }
+        if groups
+            .insert(read.to_owned(), group.to_owned())
+            .is_some_and(|prev| prev != group)
+        {
+            return Err(err(&format!("{read:?} has more than one read group")));
+        }
+    }
+    Ok(groups)
+}
+
+/// Stratify mapping, assignment, and support by read group to find runs or flowcells dragging down support.
+///
+/// # Arguments
+/// * `read_groups`
+///     * Read group of each read. See [`load_read_groups`]. Reads without one are in [`UNGROUPED`].
+/// * `read_lens`
+///     * Read lengths.
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, ...]`
+/// * `df_best_reads_asm`
+///     * [`DataFrame`] of reads assigned to contigs with columns `[read, ...]`
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in the largest component of each read with columns `[ctg, read, proj_cpos, offset_dev, component, ...]`
+/// * `assembly_bp`
+///     * Total assembly length.
+/// * `state`
+///     * Run the join of component SUNKs to read lengths is checked in.
+///
+/// # Returns
+/// * Per read group stats sorted by group. [`UNGROUPED`] is only included if it has reads.
+pub fn read_group_stats(
+    read_groups: &HashMap<String, String>,
+    read_lens: &HashMap<String, u64>,
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    df_components: &DataFrame,
+    assembly_bp: u64,
+    state: &RunState,
+) -> Result<Vec<ReadGroupStats>> {
+    let mut names: Vec<&str> = read_groups
+        .values()
+        .map(String::as_str)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    names.sort_unstable();
+    names.push(UNGROUPED);
+    let idx: HashMap<&str, usize> = names.iter().enumerate().map(|(i, g)| (*g, i)).collect();
+    let group_idx = |read: &str| {
+        read_groups
+            .get(read)
+            .map_or(names.len() - 1, |group| idx[group.as_str()])
+    };
+
+    let mut groups: Vec<ReadGroupStats> = names
+        .iter()
+        .map(|group| ReadGroupStats {
+            group: (*group).to_owned(),
+            ..Default::default()
+        })
+        .collect();
+    for (read, len) in read_lens {
+        let group = &mut groups[group_idx(read)];
+        group.reads += 1;
+        group.bases += len;
+    }
+    // Reads are repeated per SUNK and per contig so count each once.
+    let unique_reads = |df: &DataFrame| -> Result<HashSet<String>> {
+        Ok(df
+            .column("read")?
+            .str()?
+            .into_no_null_iter()
+            .map(str::to_owned)
+            .collect())
+    };
+    for read in unique_reads(df_read_sunks)? {
+        groups[group_idx(&read)].mapped_reads += 1;
+    }
+    for read in unique_reads(df_best_reads_asm)? {
+        groups[group_idx(&read)].assigned_reads += 1;
+    }
+    let support = stratified_support(read_lens, df_components, groups.len(), state, |read, _| {
+        Some(group_idx(read))
+    })?;
+    for (group, (supporting_reads, supporting_sunks, supported_bp)) in
+        groups.iter_mut().zip(support)
+    {
+        group.supporting_reads = supporting_reads;
+        group.supporting_sunks = supporting_sunks;
+        group.supported_bp = supported_bp;
+        group.supported_fraction =
+            (assembly_bp > 0).then(|| supported_bp as f64 / assembly_bp as f64);
+    }
+    if groups.last().is_some_and(|group| group.reads == 0) {
+        groups.pop();
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::{read_group_stats, UNGROUPED};
+    use crate::run_state::RunState;
+
+    #[test]
+    fn test_read_group_stats() {
+        let read_groups = HashMap::from([
+            ("r1".to_owned(), "fc1".to_owned()),
+            ("r2".to_owned(), "fc2".to_owned()),
+        ]);
+        let read_lens = HashMap::from([
+            ("r1".to_owned(), 1_000),
+            ("r2".to_owned(), 2_000),
+            ("r3".to_owned(), 3_000),
+        ]);
+        let df_read_sunks = df!("read" => ["r1", "r1", "r2", "r3"]).unwrap();
+        let df_best_reads_asm = df!("read" => ["r1", "r2"]).unwrap();
+        let df_components = df!(
+            "ctg" => ["ctg1", "ctg1"],
+            "read" => ["r1", "r1"],
+            "proj_cpos" => [100i64, 500],
+            "offset_dev" => [0i64, 0],
+            "component" => [0u64, 0],
+        )
+        .unwrap();
+        let groups = read_group_stats(
+            &read_groups,
+            &read_lens,
+            &df_read_sunks,
+            &df_best_reads_asm,
+            &df_components,
+            1_000,
+            &RunState::new(),
+        )
+        .unwrap();
+        let summary: Vec<_> = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.group.as_str(),
+                    g.reads,
+                    g.mapped_reads,
+                    g.assigned_reads,
+                    g.supporting_reads,
+                    g.supported_bp,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("fc1", 1, 1, 1, 1, 400),
+                ("fc2", 1, 1, 1, 0, 0),
+                (UNGROUPED, 1, 1, 0, 0, 0),
+            ]
+        );
+        assert_eq!(groups[0].supported_fraction, Some(0.4));
+    }
+}
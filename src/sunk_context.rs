@@ -0,0 +1,551 @@
+//! Assembly sequence context of SUNKs.
+//!
+//! SUNKs in softmasked repeats or next to gaps are more likely to be assembly artifacts or missing from reads, so
+//! support built from them is less stable. ONT reads also drop out in GC-extreme sequence and around long
+//! homopolymers, so SUNKs are annotated with the composition of their flanking sequence.
+
+use std::collections::{HashMap, HashSet};
+
+use polars::prelude::*;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{cancel::CancelToken, error::Result, get_kmers::sunk_id, io::Fasta};
+
+/// Default distance from a gap within which SUNKs are flagged.
+pub const DEFAULT_GAP_FLANK: u64 = 1_000;
+/// Bases on either side of a SUNK included in its GC content and max homopolymer length.
+pub const COMPOSITION_FLANK: usize = 50;
+/// Upper bounds of GC content strata of SUNK groups. The last stratum has no upper bound.
+const GC_STRATA: [f64; 5] = [0.3, 0.4, 0.5, 0.6, 0.7];
+/// Max homopolymer lengths of SUNK groups at or above this are stratified together.
+const MAX_HOMOPOLYMER_STRATUM: u32 = 8;
+
+/// Softmasked and gap intervals of a contig as sorted, 0-based, half-open intervals.
+#[derive(Debug, Default, Clone)]
+pub struct SeqContext {
+    /// Runs of lowercase bases.
+    pub softmasked: Vec<(i64, i64)>,
+    /// Runs of `N` or `n`.
+    pub gaps: Vec<(i64, i64)>,
+    /// Composition of the flanked sequence of SUNKs as `(0-based start, GC content, max homopolymer length)` sorted
+    /// by start. GC content is [`None`] without any `ACGT` bases.
+    pub composition: Vec<(i64, Option<f64>, u32)>,
+}
+
+impl SeqContext {
+    pub fn new(seq: &[u8]) -> Self {
+        Self {
+            softmasked: runs(seq, |base| base.is_ascii_lowercase() && base != b'n'),
+            gaps: runs(seq, |base| base.eq_ignore_ascii_case(&b'N')),
+            composition: vec![],
+        }
+    }
+
+    /// Add the composition of SUNKs starting at `starts` flanked by [`COMPOSITION_FLANK`] bases.
+    ///
+    /// # Arguments
+    /// * `seq`
+    ///     * Sequence of the contig.
+    /// * `starts`
+    ///     * 0-based SUNK starts.
+    /// * `kmer_size`
+    ///     * kmer size.
+    pub fn with_composition(mut self, seq: &[u8], starts: &[i64], kmer_size: usize) -> Self {
+        self.composition = starts
+            .iter()
+            .map(|st| {
+                let st = usize::try_from(*st).unwrap_or_default();
+                let window = &seq[st.saturating_sub(COMPOSITION_FLANK).min(seq.len())
+                    ..(st + kmer_size + COMPOSITION_FLANK).min(seq.len())];
+                let (gc, max_homopolymer) = composition(window);
+                (st as i64, gc, max_homopolymer)
+            })
+            .collect();
+        self.composition.sort_unstable_by_key(|(st, _, _)| *st);
+        self
+    }
+}
+
+/// GC content, ignoring non-`ACGT` bases, and max homopolymer length of a sequence.
+fn composition(seq: &[u8]) -> (Option<f64>, u32) {
+    let (mut n_gc, mut n_acgt) = (0, 0);
+    let (mut max_homopolymer, mut homopolymer, mut prev) = (0, 0, None);
+    for base in seq.iter().map(u8::to_ascii_uppercase) {
+        match base {
+            b'G' | b'C' => {
+                n_gc += 1;
+                n_acgt += 1;
+            }
+            b'A' | b'T' => n_acgt += 1,
+            _ => (),
+        }
+        homopolymer = if prev == Some(base) && base != b'N' {
+            homopolymer + 1
+        } else {
+            1
+        };
+        max_homopolymer = max_homopolymer.max(homopolymer);
+        prev = Some(base);
+    }
+    (
+        (n_acgt > 0).then(|| n_gc as f64 / n_acgt as f64),
+        max_homopolymer,
+    )
+}
+
+/// Runs of bases matching `pred`.
+fn runs(seq: &[u8], pred: impl Fn(u8) -> bool) -> Vec<(i64, i64)> {
+    let mut runs = vec![];
+    let mut st = None;
+    for (pos, base) in seq.iter().enumerate() {
+        match (pred(*base), st) {
+            (true, None) => st = Some(pos as i64),
+            (false, Some(run_st)) => {
+                runs.push((run_st, pos as i64));
+                st = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(run_st) = st {
+        runs.push((run_st, seq.len() as i64));
+    }
+    runs
+}
+
+fn overlaps(intervals: &[(i64, i64)], st: i64, end: i64) -> bool {
+    let idx = intervals.partition_point(|(_, iv_end)| *iv_end <= st);
+    intervals.get(idx).is_some_and(|(iv_st, _)| *iv_st < end)
+}
+
+/// Get the softmasked and gap intervals of each sequence and the composition around its SUNKs.
+///
+/// # Arguments
+/// * `fasta`
+///     * Fasta file handle.
+/// * `fasta_lens`
+///     * Sequence lengths by FASTA name.
+/// * `sunk_starts`
+///     * 0-based SUNK starts by FASTA name.
+/// * `kmer_size`
+///     * kmer size.
+/// * `cancel`
+///     * Token checked before reading each sequence.
+///
+/// # Returns
+/// * [`SeqContext`] by FASTA name.
+pub fn sequence_context(
+    fasta: &Fasta,
+    fasta_lens: &HashMap<String, u64>,
+    sunk_starts: &HashMap<String, Vec<i64>>,
+    kmer_size: usize,
+    cancel: &CancelToken,
+) -> Result<HashMap<String, SeqContext>> {
+    fasta_lens
+        .par_iter()
+        .map(|(name, len)| {
+            cancel.check()?;
+            let mut fh = fasta.try_clone()?;
+            let bases = fh.fetch_bytes(name, 1, *len)?;
+            let starts = sunk_starts.get(name).map_or(&[][..], Vec::as_slice);
+            Ok((
+                name.clone(),
+                SeqContext::new(&bases).with_composition(&bases, starts, kmer_size),
+            ))
+        })
+        .collect()
+}
+
+/// Flag assembly SUNKs by their sequence context.
+///
+/// # Arguments
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, ...]`
+/// * `context`
+///     * [`SeqContext`] by contig. Contigs without context are unflagged.
+/// * `kmer_size`
+///     * kmer size.
+/// * `gap_flank`
+///     * SUNKs within this many bp of a gap are flagged.
+///
+/// # Returns
+/// * `df_asm_sunks` with additional columns `[softmasked, near_gap, gc, max_homopolymer]`
+///     * `softmasked` if any base of the SUNK is softmasked.
+///     * `gc` and `max_homopolymer` of the SUNK flanked by [`COMPOSITION_FLANK`] bases. Null without composition.
+pub fn annotate_sunk_context(
+    df_asm_sunks: &DataFrame,
+    context: &HashMap<String, SeqContext>,
+    kmer_size: usize,
+    gap_flank: u64,
+) -> Result<DataFrame> {
+    let cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let (gap_flank, kmer_size) = (i64::try_from(gap_flank)?, i64::try_from(kmer_size)?);
+    let (mut softmasked, mut near_gap) = (vec![], vec![]);
+    let (mut gc, mut max_homopolymer) = (vec![], vec![]);
+    for (ctg, cpos) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(cpos.i64()?.iter())
+    {
+        let (Some(ctg_context), Some(cpos)) = (ctg.and_then(|ctg| context.get(ctg)), cpos) else {
+            softmasked.push(false);
+            near_gap.push(false);
+            gc.push(None);
+            max_homopolymer.push(None);
+            continue;
+        };
+        let (st, end) = (cpos - 1, cpos - 1 + kmer_size);
+        softmasked.push(overlaps(&ctg_context.softmasked, st, end));
+        near_gap.push(overlaps(&ctg_context.gaps, st - gap_flank, end + gap_flank));
+        let composition = ctg_context
+            .composition
+            .binary_search_by_key(&st, |(st, _, _)| *st)
+            .ok()
+            .map(|idx| ctg_context.composition[idx]);
+        gc.push(composition.and_then(|(_, gc, _)| gc));
+        max_homopolymer.push(composition.map(|(_, _, max_homopolymer)| max_homopolymer));
+    }
+    let mut df = df_asm_sunks.clone();
+    df.with_column(Column::new("softmasked".into(), softmasked))?;
+    df.with_column(Column::new("near_gap".into(), near_gap))?;
+    df.with_column(Column::new("gc".into(), gc))?;
+    df.with_column(Column::new("max_homopolymer".into(), max_homopolymer))?;
+    Ok(df)
+}
+
+/// Count flagged SUNKs within regions.
+///
+/// # Arguments
+/// * `df_regions`
+///     * [`DataFrame`] of regions with columns `[ctg, st, end, ...]`
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, cpos, softmasked, near_gap, ...]`. See [`annotate_sunk_context`].
+///
+/// # Returns
+/// * `df_regions` with additional columns `[softmasked_sunks, near_gap_sunks]`
+pub fn count_context_sunks(df_regions: &DataFrame, df_asm_sunks: &DataFrame) -> Result<DataFrame> {
+    let cpos = df_asm_sunks.column("cpos")?.cast(&DataType::Int64)?;
+    let mut flagged: HashMap<&str, (Vec<i64>, Vec<i64>)> = HashMap::new();
+    for (((ctg, cpos), softmasked), near_gap) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(cpos.i64()?.iter())
+        .zip(df_asm_sunks.column("softmasked")?.bool()?.iter())
+        .zip(df_asm_sunks.column("near_gap")?.bool()?.iter())
+    {
+        let (Some(ctg), Some(cpos)) = (ctg, cpos) else {
+            continue;
+        };
+        let (ctg_softmasked, ctg_near_gap) = flagged.entry(ctg).or_default();
+        if softmasked == Some(true) {
+            ctg_softmasked.push(cpos);
+        }
+        if near_gap == Some(true) {
+            ctg_near_gap.push(cpos);
+        }
+    }
+    for (ctg_softmasked, ctg_near_gap) in flagged.values_mut() {
+        ctg_softmasked.sort_unstable();
+        ctg_near_gap.sort_unstable();
+    }
+    // SUNK positions are 1-based.
+    let count_within = |positions: &[i64], st: i64, end: i64| {
+        (positions.partition_point(|pos| *pos <= end) - positions.partition_point(|pos| *pos <= st))
+            as u64
+    };
+
+    let (mut softmasked_sunks, mut near_gap_sunks) = (vec![], vec![]);
+    for ((ctg, st), end) in df_regions
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(df_regions.column("st")?.i64()?.iter())
+        .zip(df_regions.column("end")?.i64()?.iter())
+    {
+        let (Some(ctg), Some(st), Some(end)) = (ctg, st, end) else {
+            softmasked_sunks.push(None);
+            near_gap_sunks.push(None);
+            continue;
+        };
+        let (n_softmasked, n_near_gap) = flagged.get(ctg).map_or((0, 0), |(sm, ng)| {
+            (count_within(sm, st, end), count_within(ng, st, end))
+        });
+        softmasked_sunks.push(Some(n_softmasked));
+        near_gap_sunks.push(Some(n_near_gap));
+    }
+    let mut df = df_regions.clone();
+    df.with_column(Column::new("softmasked_sunks".into(), softmasked_sunks))?;
+    df.with_column(Column::new("near_gap_sunks".into(), near_gap_sunks))?;
+    Ok(df)
+}
+
+/// Counts of SUNK groups by sequence context. A group is flagged if any of its SUNKs is.
+#[derive(Debug, Default, Serialize)]
+pub struct SunkContextStats {
+    pub groups: usize,
+    pub softmasked: usize,
+    pub near_gap: usize,
+    /// Groups in supported read components. Only set once the graph stage finishes.
+    pub supporting: Option<SupportingContextStats>,
+}
+
+/// Counts of SUNK groups in supported read components by sequence context.
+#[derive(Debug, Default, Serialize)]
+pub struct SupportingContextStats {
+    pub groups: usize,
+    pub softmasked: usize,
+    pub near_gap: usize,
+}
+
+/// Count of `(softmasked, near_gap)` flags as `(total, softmasked, near_gap)`.
+fn count_flags<'a>(flags: impl Iterator<Item = &'a (bool, bool)>) -> (usize, usize, usize) {
+    flags.fold((0, 0, 0), |(n, sm, ng), (softmasked, near_gap)| {
+        (n + 1, sm + *softmasked as usize, ng + *near_gap as usize)
+    })
+}
+
+/// Summarize SUNK groups by sequence context.
+///
+/// # Arguments
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, group, softmasked, near_gap, ...]`. See [`annotate_sunk_context`].
+/// * `df_components`
+///     * Optional [`DataFrame`] of read SUNKs in supported components with columns `[id, ...]`.
+///     * See [`crate::sunk_graph::create_sunk_graph`].
+pub fn sunk_context_stats(
+    df_asm_sunks: &DataFrame,
+    df_components: Option<&DataFrame>,
+) -> Result<SunkContextStats> {
+    let group = df_asm_sunks.column("group")?.cast(&DataType::Int64)?;
+    let mut groups: HashMap<String, (bool, bool)> = HashMap::new();
+    for (((ctg, group), softmasked), near_gap) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(group.i64()?.iter())
+        .zip(df_asm_sunks.column("softmasked")?.bool()?.iter())
+        .zip(df_asm_sunks.column("near_gap")?.bool()?.iter())
+    {
+        let (Some(ctg), Some(group)) = (ctg, group) else {
+            continue;
+        };
+        let flags = groups.entry(sunk_id(ctg, group)).or_default();
+        flags.0 |= softmasked == Some(true);
+        flags.1 |= near_gap == Some(true);
+    }
+    let (n_groups, n_softmasked, n_near_gap) = count_flags(groups.values());
+    let supporting = df_components
+        .map(|df| -> Result<SupportingContextStats> {
+            let ids: HashSet<&str> = df.column("id")?.str()?.into_iter().flatten().collect();
+            let (groups, softmasked, near_gap) =
+                count_flags(ids.into_iter().filter_map(|id| groups.get(id)));
+            Ok(SupportingContextStats {
+                groups,
+                softmasked,
+                near_gap,
+            })
+        })
+        .transpose()?;
+    Ok(SunkContextStats {
+        groups: n_groups,
+        softmasked: n_softmasked,
+        near_gap: n_near_gap,
+        supporting,
+    })
+}
+
+/// Read support of SUNK groups within a stratum of a sequence covariate.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct CovariateStratum {
+    /// `gc` or `max_homopolymer`.
+    pub covariate: &'static str,
+    /// ex. `[0.3, 0.4)` or `8+`
+    pub stratum: String,
+    pub groups: usize,
+    /// Groups without reads.
+    pub unobserved: usize,
+    /// Groups with abnormal read support. See [`crate::filter_bad_sunks::filter_bad_sunks`].
+    pub bad: usize,
+    pub unobserved_fraction: Option<f64>,
+    pub bad_fraction: Option<f64>,
+}
+
+fn gc_stratum(gc: f64) -> usize {
+    GC_STRATA.partition_point(|upper| *upper <= gc)
+}
+
+fn gc_stratum_label(idx: usize) -> String {
+    match (idx.checked_sub(1).map(|i| GC_STRATA[i]), GC_STRATA.get(idx)) {
+        (None, Some(upper)) => format!("[0, {upper})"),
+        (Some(lower), Some(upper)) => format!("[{lower}, {upper})"),
+        (Some(lower), None) => format!("[{lower}, 1]"),
+        (None, None) => unreachable!(),
+    }
+}
+
+/// Stratify SUNK group dropout and bad-SUNK rates by GC content and max homopolymer length.
+///
+/// A group's GC content is the mean of its SUNKs and its max homopolymer length is the max of its SUNKs.
+///
+/// # Arguments
+/// * `df_asm_sunks`
+///     * [`DataFrame`] of assembly SUNKs with columns `[ctg, group, gc, max_homopolymer, ...]`. See [`annotate_sunk_context`].
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[ctg, group, ...]`. Groups without any are unobserved.
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNK groups with columns `[id, ...]`.
+///
+/// # Returns
+/// * [`CovariateStratum`]s of GC content and then max homopolymer length. Groups without composition are skipped.
+pub fn covariate_strata(
+    df_asm_sunks: &DataFrame,
+    df_read_sunks: &DataFrame,
+    df_bad_sunks: &DataFrame,
+) -> Result<Vec<CovariateStratum>> {
+    let group = df_asm_sunks.column("group")?.cast(&DataType::Int64)?;
+    let max_homopolymer = df_asm_sunks
+        .column("max_homopolymer")?
+        .cast(&DataType::UInt32)?;
+    // Sum and number of GC contents and max homopolymer length by group.
+    let mut groups: HashMap<String, (f64, usize, u32)> = HashMap::new();
+    for (((ctg, group), gc), max_homopolymer) in df_asm_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(group.i64()?.iter())
+        .zip(df_asm_sunks.column("gc")?.f64()?.iter())
+        .zip(max_homopolymer.u32()?.iter())
+    {
+        let (Some(ctg), Some(group), Some(max_homopolymer)) = (ctg, group, max_homopolymer) else {
+            continue;
+        };
+        let (gc_sum, n_gc, group_max_homopolymer) = groups.entry(sunk_id(ctg, group)).or_default();
+        if let Some(gc) = gc {
+            *gc_sum += gc;
+            *n_gc += 1;
+        }
+        *group_max_homopolymer = (*group_max_homopolymer).max(max_homopolymer);
+    }
+    let read_group = df_read_sunks.column("group")?.cast(&DataType::Int64)?;
+    let observed: HashSet<String> = df_read_sunks
+        .column("ctg")?
+        .str()?
+        .iter()
+        .zip(read_group.i64()?.iter())
+        .filter_map(|(ctg, group)| Some(sunk_id(ctg?, group?)))
+        .collect();
+    let bad: HashSet<&str> = df_bad_sunks
+        .column("id")?
+        .str()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // Groups, unobserved, and bad by stratum.
+    let mut gc_counts = vec![(0, 0, 0); GC_STRATA.len() + 1];
+    let mut homopolymer_counts = vec![(0, 0, 0); MAX_HOMOPOLYMER_STRATUM as usize + 1];
+    for (id, (gc_sum, n_gc, max_homopolymer)) in &groups {
+        let (is_unobserved, is_bad) = (
+            !observed.contains(id) as usize,
+            bad.contains(id.as_str()) as usize,
+        );
+        let mut strata =
+            vec![&mut homopolymer_counts[(*max_homopolymer).min(MAX_HOMOPOLYMER_STRATUM) as usize]];
+        if *n_gc > 0 {
+            strata.push(&mut gc_counts[gc_stratum(gc_sum / *n_gc as f64)]);
+        }
+        for (n, n_unobserved, n_bad) in strata {
+            *n += 1;
+            *n_unobserved += is_unobserved;
+            *n_bad += is_bad;
+        }
+    }
+
+    let to_stratum = |covariate, stratum, (groups, unobserved, bad): (usize, usize, usize)| {
+        let fraction = |n: usize| (groups > 0).then(|| n as f64 / groups as f64);
+        CovariateStratum {
+            covariate,
+            stratum,
+            groups,
+            unobserved,
+            bad,
+            unobserved_fraction: fraction(unobserved),
+            bad_fraction: fraction(bad),
+        }
+    };
+    let gc_strata = gc_counts
+        .into_iter()
+        .enumerate()
+        .map(|(idx, counts)| to_stratum("gc", gc_stratum_label(idx), counts));
+    let homopolymer_strata = homopolymer_counts
+        .into_iter()
+        .enumerate()
+        // Windows always have a homopolymer of at least 1.
+        .skip(1)
+        .map(|(len, counts)| {
+            let label = if len as u32 == MAX_HOMOPOLYMER_STRATUM {
+                format!("{len}+")
+            } else {
+                len.to_string()
+            };
+            to_stratum("max_homopolymer", label, counts)
+        });
+    Ok(gc_strata.chain(homopolymer_strata).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{composition, covariate_strata, overlaps, SeqContext};
+
+    #[test]
+    fn test_seq_context() {
+        let context = SeqContext::new(b"ACgtaNNNNACGTnnACacg");
+        assert_eq!(context.softmasked, vec![(2, 5), (17, 20)]);
+        assert_eq!(context.gaps, vec![(5, 9), (13, 15)]);
+        assert!(overlaps(&context.gaps, 0, 6));
+        assert!(!overlaps(&context.gaps, 9, 13));
+        assert!(overlaps(&context.softmasked, 19, 25));
+    }
+
+    #[test]
+    fn test_composition() {
+        assert_eq!(composition(b"GGCCAAAAAT"), (Some(0.4), 5));
+        assert_eq!(composition(b"NNNNacgt"), (Some(0.5), 1));
+        assert_eq!(composition(b"NNNN"), (None, 1));
+        let context = SeqContext::new(b"AAAAAAGCGC").with_composition(b"AAAAAAGCGC", &[6, 0], 2);
+        assert_eq!(context.composition[0].0, 0);
+        assert_eq!(context.composition[1].0, 6);
+    }
+
+    #[test]
+    fn test_covariate_strata() {
+        let df_asm_sunks = df!(
+            "ctg" => ["ctg1", "ctg1", "ctg1", "ctg1"],
+            "group" => [1i64, 1, 2, 3],
+            "gc" => [Some(0.42), Some(0.48), Some(0.65), None],
+            "max_homopolymer" => [Some(2u32), Some(9), Some(2), None],
+        )
+        .unwrap();
+        let df_read_sunks = df!("ctg" => ["ctg1"], "group" => [1i64]).unwrap();
+        let df_bad_sunks = df!("id" => ["ctg1:1"]).unwrap();
+        let strata = covariate_strata(&df_asm_sunks, &df_read_sunks, &df_bad_sunks).unwrap();
+        let counts = |covariate: &str, stratum: &str| {
+            strata
+                .iter()
+                .find(|s| s.covariate == covariate && s.stratum == stratum)
+                .map(|s| (s.groups, s.unobserved, s.bad))
+                .unwrap()
+        };
+        // Group 1 has a mean GC of 0.45.
+        assert_eq!(counts("gc", "[0.4, 0.5)"), (1, 0, 1));
+        assert_eq!(counts("gc", "[0.6, 0.7)"), (1, 1, 0));
+        assert_eq!(counts("gc", "[0.7, 1]"), (0, 0, 0));
+        assert_eq!(counts("max_homopolymer", "8+"), (1, 0, 1));
+        assert_eq!(counts("max_homopolymer", "2"), (1, 1, 0));
+    }
+}
@@ -1,20 +1,182 @@
-use distmat::DistMatrix;
-use eyre::bail;
 use itertools::Itertools;
 use petgraph::graph::NodeIndex;
 use petgraph::{algo::kosaraju_scc, Graph};
 use polars::prelude::*;
-use std::collections::{HashMap, HashSet};
-use std::i64;
-use std::ops::Not;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
-const MIN_READ_LEN: u64 = 10000;
+use crate::{
+    anchor_store::{AnchorStore, ReadAnchors},
+    cancel::CancelToken,
+    circular::{split_origin, unwrap_origin},
+    error::{GavisunkError, Result},
+    filter_audit::{self, READS, SUNK_HITS},
+    get_kmers::{sunk_id, sunk_id_expr},
+    hooks::Stage,
+    intervals::merge,
+    joins::check_join_loss,
+    orientation::infer_orientation,
+    pairwise::Pairwise,
+    ratio,
+    run_state::RunState,
+};
 
-fn get_contig_sunk_graph_components(
+/// Reads this length or shorter are not used to build SUNK graphs.
+pub const MIN_READ_LEN: u64 = 10000;
+/// Default allowed relative difference between the read and contig distance of a SUNK pair.
+pub const DEFAULT_DST_TOLERANCE: f32 = 0.1;
+/// Default minimum number of SUNKs in a contig component for it to be supported.
+pub const DEFAULT_MIN_COMPONENT_SUNKS: usize = 3;
+/// Default maximum SUNK hits of a read on a contig before it's skipped.
+pub const DEFAULT_MAX_READ_SUNKS: usize = 20_000;
+/// Default maximum time to build the SUNK graph of a read on a contig before it's skipped.
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// Read skipped for having more SUNK hits than [`GraphParams::max_read_sunks`].
+pub const TOO_MANY_SUNKS: &str = "too_many_sunks";
+/// Read skipped for taking longer than [`GraphParams::read_timeout`].
+pub const TIMED_OUT: &str = "timed_out";
+/// SUNK pairs compared between checks of [`GraphParams::read_timeout`].
+const TIMEOUT_CHECK_PAIRS: usize = 1 << 16;
+
+/// How SUNK id pairs with multiple read positions within a read are resolved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MultiSunkStrategy {
+    /// Keep the first pair of read positions.
+    #[default]
+    KeepFirst,
+    /// Keep the pair of read positions whose distance is closest to the expected contig distance.
+    KeepClosest,
+    /// Drop all pairs of read positions.
+    DropAll,
+}
+
+/// Parameters of the graph stage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphParams {
+    /// How SUNK id pairs with multiple read positions are resolved.
+    pub multi_sunk: MultiSunkStrategy,
+    /// Allowed relative difference between the read and contig distance of a SUNK pair. Exclusive and compared to the
+    /// nearest part per million. See [`ratio::within_tolerance`].
+    pub dst_tolerance: f32,
+    /// Minimum number of SUNKs in a contig component for it to be supported.
+    pub min_component_sunks: usize,
+    /// Reads with more SUNK hits on a contig are skipped. If [`None`], reads aren't skipped for their size.
+    pub max_read_sunks: Option<usize>,
+    /// Reads taking longer to build their SUNK graph on a contig are skipped. If [`None`], reads aren't timed out.
+    pub read_timeout: Option<Duration>,
+}
+
+impl Default for GraphParams {
+    fn default() -> Self {
+        Self {
+            multi_sunk: MultiSunkStrategy::default(),
+            dst_tolerance: DEFAULT_DST_TOLERANCE,
+            min_component_sunks: DEFAULT_MIN_COMPONENT_SUNKS,
+            max_read_sunks: Some(DEFAULT_MAX_READ_SUNKS),
+            read_timeout: Some(DEFAULT_READ_TIMEOUT),
+        }
+    }
+}
+
+/// Parse an allowed relative difference between the read and contig distance of a SUNK pair in `(0, 1)`. ex. `0.1`
+pub fn parse_dst_tolerance(tolerance: &str) -> Result<f32> {
+    match tolerance.trim().parse::<f32>() {
+        Ok(value) if value > 0.0 && value < 1.0 => Ok(value),
+        _ => Err(GavisunkError::InvalidDstTolerance(tolerance.to_owned())),
+    }
+}
+
+/// Read skipped when building SUNK graphs for exceeding its budget. See [`GraphParams`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRead {
+    pub ctg: String,
+    pub read: String,
+    /// SUNK hits of the read on the contig.
+    pub sunks: usize,
+    /// [`TOO_MANY_SUNKS`] or [`TIMED_OUT`].
+    pub status: &'static str,
+}
+
+/// Warn and record a read skipped for exceeding its budget in a run.
+fn skip_read(skipped: SkippedRead, state: &RunState) {
+    log::warn!(
+        "Skipped {} with {} SUNK hits on {}: {}.",
+        skipped.read,
+        skipped.sunks,
+        skipped.ctg,
+        skipped.status
+    );
+    if let Ok(mut skipped_reads) = state.skipped_reads.lock() {
+        skipped_reads.push(skipped);
+    }
+}
+
+/// Reads skipped so far in a run for exceeding their budget.
+pub fn skipped_reads(state: &RunState) -> Vec<SkippedRead> {
+    state
+        .skipped_reads
+        .lock()
+        .map(|skipped_reads| skipped_reads.clone())
+        .unwrap_or_default()
+}
+
+/// Reads skipped so far in a run for exceeding their budget.
+///
+/// # Returns
+/// * [`DataFrame`] with columns `[ctg, read, sunks, status]`
+///     * `status` is [`TOO_MANY_SUNKS`] or [`TIMED_OUT`].
+pub fn read_status(state: &RunState) -> Result<DataFrame> {
+    let skipped = skipped_reads(state);
+    Ok(DataFrame::new(vec![
+        Column::new(
+            "ctg".into(),
+            skipped.iter().map(|read| read.ctg.as_str()).collect_vec(),
+        ),
+        Column::new(
+            "read".into(),
+            skipped.iter().map(|read| read.read.as_str()).collect_vec(),
+        ),
+        Column::new(
+            "sunks".into(),
+            skipped.iter().map(|read| read.sunks as u64).collect_vec(),
+        ),
+        Column::new(
+            "status".into(),
+            skipped.iter().map(|read| read.status).collect_vec(),
+        ),
+    ])?)
+}
+
+/// Get connected components of SUNKs shared by reads along a contig.
+///
+/// # Arguments
+/// * `ctg`
+///     * Contig name.
+/// * `rnames`
+///     * Read names, grouped, for each SUNK id in `ids`.
+/// * `ids`
+///     * SUNK ids in the largest component of each read.
+/// * `min_sunks`
+///     * Minimum number of SUNKs in a component.
+/// * `circular_len`
+///     * Length of the contig if circular. A component spans the shortest arc covering its SUNKs, which may cross the
+///       origin.
+///
+/// # Returns
+/// * [`DataFrame`] of components with at least `min_sunks` SUNKs.
+///     * With columns `[ctg, st, end, sunks]`
+///     * Components crossing the origin of a circular contig end past its length.
+/// * Index of the component each SUNK id belongs to.
+///     * Components are indexed in order of `[st, end]`.
+pub fn get_contig_sunk_graph_components(
     ctg: &str,
     rnames: &[String],
     ids: &[i64],
-) -> eyre::Result<DataFrame> {
+    min_sunks: usize,
+    circular_len: Option<i64>,
+) -> Result<(DataFrame, HashMap<i64, u64>)> {
     let mut reads = vec![];
     let mut ids_1 = vec![];
     let mut ids_2 = vec![];
@@ -39,121 +201,211 @@ fn get_contig_sunk_graph_components(
     }
     let components = kosaraju_scc(&graph);
 
-    let (mut starts, mut ends, mut sunks) = (vec![], vec![], vec![]);
-    for comp in components.into_iter().filter(|nodes| nodes.len() > 2) {
-        let mut min_st = i64::MAX;
-        let mut max_end = 0;
-        for pos in comp.iter().flat_map(|n| graph.node_weight(*n)) {
-            min_st = std::cmp::min(min_st, *pos);
-            max_end = std::cmp::max(max_end, *pos);
+    let mut comp_bounds = vec![];
+    for comp in components
+        .into_iter()
+        .filter(|nodes| nodes.len() >= min_sunks)
+    {
+        let positions: Vec<i64> = comp
+            .iter()
+            .flat_map(|n| graph.node_weight(*n))
+            .copied()
+            .sorted_unstable()
+            .collect();
+        let (Some(min_st), Some(max_end)) = (positions.first(), positions.last()) else {
+            continue;
+        };
+        let (mut st, mut end) = (*min_st, *max_end);
+        // On a circular contig, leave out the largest gap between SUNKs rather than the gap across the origin.
+        if let Some(ctg_len) = circular_len {
+            let mut max_gap = min_st + ctg_len - max_end;
+            for pair in positions.windows(2) {
+                if pair[1] - pair[0] > max_gap {
+                    max_gap = pair[1] - pair[0];
+                    (st, end) = (pair[1], pair[0] + ctg_len);
+                }
+            }
         }
-        starts.push(min_st);
-        ends.push(max_end);
+        comp_bounds.push((st, end, comp));
+    }
+    comp_bounds.sort_by_key(|(st, end, _)| (*st, *end));
+
+    let (mut starts, mut ends, mut sunks) = (vec![], vec![], vec![]);
+    let mut id_comps = HashMap::new();
+    for (idx, (st, end, comp)) in comp_bounds.into_iter().enumerate() {
+        starts.push(st);
+        ends.push(end);
         sunks.push(TryInto::<u64>::try_into(comp.len())?);
+        for id in comp.iter().flat_map(|n| graph.node_weight(*n)) {
+            id_comps.insert(*id, idx as u64);
+        }
     }
 
-    Ok(DataFrame::new(vec![
+    let df_bed = DataFrame::new(vec![
         Column::new("ctg".into(), vec![ctg; starts.len()]),
         Column::new("st".into(), starts),
         Column::new("end".into(), ends),
         Column::new("sunks".into(), sunks),
-    ])?)
+    ])?;
+    Ok((df_bed, id_comps))
+}
+
+/// SUNKs in the largest consistent component of a read.
+#[derive(Debug, Default)]
+pub struct ReadComponent {
+    /// Read is reversed relative to the contig.
+    pub is_rev: bool,
+    /// SUNK ids.
+    pub ids: Vec<i64>,
+    /// Read positions of SUNKs.
+    pub rpos: Vec<i64>,
+    /// Contig positions of SUNKs.
+    pub cpos: Vec<i64>,
+}
+
+impl ReadComponent {
+    /// Project read positions onto the contig using the median offset of the component.
+    ///
+    /// # Returns
+    /// * Projected contig position of each SUNK.
+    pub fn projected_cpos(&self) -> Vec<i64> {
+        // Read positions ascend with contig positions on the forward strand and descend on the reverse strand.
+        let sign = if self.is_rev { -1 } else { 1 };
+        let mut offsets: Vec<i64> = self
+            .rpos
+            .iter()
+            .zip(self.cpos.iter())
+            .map(|(rpos, cpos)| cpos - sign * rpos)
+            .collect();
+        offsets.sort_unstable();
+        let Some(offset) = offsets.get(offsets.len() / 2) else {
+            return vec![];
+        };
+        self.rpos.iter().map(|rpos| offset + sign * rpos).collect()
+    }
 }
 
-fn get_read_largest_sunk_graph_component(
+/// Get the largest component of SUNKs within a read whose pairwise read distances agree with their contig distances.
+///
+/// # Arguments
+/// * `df_grp`
+///     * [`DataFrame`] of a single read's SUNKs ordered by `cpos` with [`i64`] columns `[cpos, rpos, id]` and hit orientation, `strand`.
+/// * `rname`
+///     * Read name.
+/// * `params`
+///     * Graph stage parameters.
+///
+/// # Returns
+/// * SUNKs in the largest component or [`None`] if no SUNKs are consistent.
+/// * [`GavisunkError::ReadBudgetExceeded`] if the read has more SUNKs than [`GraphParams::max_read_sunks`] or takes
+///   longer than [`GraphParams::read_timeout`].
+pub fn get_read_largest_sunk_graph_component(
     df_grp: &DataFrame,
     rname: &str,
-) -> eyre::Result<Option<Vec<i64>>> {
+    params: &GraphParams,
+) -> Result<Option<ReadComponent>> {
     let cpos_col = df_grp.column("cpos")?;
     let rpos_col = df_grp.column("rpos")?;
     let id_col = df_grp.column("id")?;
 
-    // Calculate pairwise distance between both self contig and read sunk positions
-    let cpos_dst_arr = Series::new(
-        "cpos_dst".into(),
-        DistMatrix::from_pw_distances(cpos_col.i64()?.cont_slice()?)
-            .into_inner()
-            .1,
-    );
-    let rpos_dst_arr = Series::new(
-        "rpos_dst".into(),
-        DistMatrix::from_pw_distances(rpos_col.i64()?.cont_slice()?)
-            .into_inner()
-            .1,
-    );
-    // Only take half of entire pairwise mtx.
-    /*
-        - 1 2 3
-        1 0 0 0
-        2 1 0 0
-        3 1 1 0
-    */
-    let rpos_sign_arr = Series::new(
-        "rpos_sign".into(),
-        DistMatrix::from_pw_distances_with(rpos_col.i64()?.cont_slice()?, |a, b| a > b)
-            .into_inner()
-            .1,
-    );
+    let cpos = cpos_col.i64()?.cont_slice()?;
+    let rpos = rpos_col.i64()?.cont_slice()?;
+    let ids = id_col.i64()?.cont_slice()?;
 
-    // Keep track of id to position correspondence.
-    let id_comb = id_col
-        .i64()?
-        .iter()
-        .flatten()
-        .combinations(2)
-        .flat_map(|c| c.into_iter().collect_tuple::<(i64, i64)>())
-        .collect_vec();
-    let rpos_comb = rpos_col
-        .i64()?
-        .iter()
-        .flatten()
-        .combinations(2)
-        .flat_map(|c| c.into_iter().collect_tuple::<(i64, i64)>())
-        .collect_vec();
+    let exceeded = |status| GavisunkError::ReadBudgetExceeded {
+        read: rname.to_owned(),
+        status,
+    };
+    if params
+        .max_read_sunks
+        .is_some_and(|max_sunks| df_grp.height() > max_sunks)
+    {
+        return Err(exceeded(TOO_MANY_SUNKS));
+    }
+    let deadline = params.read_timeout.map(|timeout| Instant::now() + timeout);
+    let check_deadline = || {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Err(exceeded(TIMED_OUT))
+        } else {
+            Ok(())
+        }
+    };
 
     /*
-    For each read, a matrix of all pairwise inter-SUNK distances within the read is generated using NumPy
-    and compared to expected distances from the assembly,
-    allowing ±2% variation in length for a given distance by default
+    For each read, all pairwise inter-SUNK distances within the read are
+    compared to expected distances from the assembly,
+    allowing ±`dst_tolerance` (10% by default) variation in length for a given distance.
+    Pairs exactly at the tolerance are inconsistent.
     */
-    let pos_diff =
-        (rpos_dst_arr.cast(&DataType::Float32)? / cpos_dst_arr.cast(&DataType::Float32)?)?;
-    let mask = pos_diff.lt(1.1)? & pos_diff.gt(0.9)?;
+    let tolerance_ppm = ratio::to_ppm(f64::from(params.dst_tolerance));
+    // Only keep pairs within variation along with whether the first read position is greater than the second.
+    let mut consistent_pairs = vec![];
+    for (n, (i, j, rpos_dst)) in Pairwise::new(rpos, |a: &i64, b: &i64| (a - b).abs()).enumerate() {
+        if n % TIMEOUT_CHECK_PAIRS == 0 {
+            check_deadline()?;
+        }
+        if ratio::within_tolerance(rpos_dst, cpos[i] - cpos[j], tolerance_ppm) {
+            consistent_pairs.push((i, j, rpos[i] > rpos[j]));
+        }
+    }
 
-    if mask.sum() < Some(1) {
-        log::debug!("SUNKs not within 2% variation in length for {rname}");
+    if consistent_pairs.is_empty() {
+        log::debug!(
+            "SUNKs not within {}% variation in length for {rname}",
+            params.dst_tolerance * 100.0
+        );
         return Ok(None);
     }
-    let Some(true_orient) = ({
-        // Only calculate on masked version
-        let df_max_sign = rpos_sign_arr
-            .filter(&mask)?
-            .value_counts(false, false, "count".into(), false)?
-            .lazy()
-            .filter(col("count").eq(col("count").max()))
-            .select([col("rpos_sign")])
-            .first()
-            .collect()?;
-        df_max_sign.column("rpos_sign")?.bool()?.first()
-    }) else {
-        bail!("Cannot determine true orient for {rname}.");
-    };
-
-    // Generate new mask that checks if is true orientation
-    // TODO: Double check.
-    let mask_true_orient = if true_orient {
-        rpos_sign_arr.bool()? & &mask
+    // Read is reversed relative to the contig if most of its SUNK hits are on the opposite strand.
+    // As SUNKs are ordered by contig position, read positions of consistent pairs then descend.
+    let col_strand = df_grp.column("strand")?.str()?;
+    let n_fwd = col_strand
+        .iter()
+        .filter(|strand| *strand == Some("+"))
+        .count();
+    let n_rev = col_strand
+        .iter()
+        .filter(|strand| *strand == Some("-"))
+        .count();
+    let true_orient = if n_fwd != n_rev {
+        n_rev > n_fwd
     } else {
-        rpos_sign_arr.bool()?.not() & mask.clone()
+        // Fall back to the gradient of SUNKs in consistent pairs.
+        let anchors: Vec<(i64, i64)> = consistent_pairs
+            .iter()
+            .flat_map(|(i, j, _)| [*i, *j])
+            .unique()
+            .map(|i| (rpos[i], cpos[i]))
+            .collect();
+        infer_orientation(&anchors).is_some_and(|(ort, _)| ort.is_rev())
     };
+    log::debug!(
+        "{rname}: {} consistent SUNK pairs. {n_fwd} + and {n_rev} - hits. Orientation: {}",
+        consistent_pairs.len(),
+        if true_orient { "-" } else { "+" }
+    );
 
     // Get SUNK and read position with correct orientation.
-    let (ids_1, ids_2): (Vec<i64>, Vec<i64>) = id_comb.into_iter().unzip();
-    let (pos_1, pos_2): (Vec<i64>, Vec<i64>) = rpos_comb.into_iter().unzip();
-    let col_id_1 = Column::new("id_1".into(), ids_1).filter(&mask_true_orient)?;
-    let col_id_2 = Column::new("id_2".into(), ids_2).filter(&mask_true_orient)?;
-    let col_pos_1 = Column::new("pos_1".into(), pos_1).filter(&mask_true_orient)?;
-    let col_pos_2 = Column::new("pos_2".into(), pos_2).filter(&mask_true_orient)?;
+    let (mut ids_1, mut ids_2, mut pos_1, mut pos_2) = (vec![], vec![], vec![], vec![]);
+    let (mut rows_1, mut rows_2) = (vec![], vec![]);
+    for (i, j, _) in consistent_pairs
+        .into_iter()
+        .filter(|(_, _, sign)| *sign == true_orient)
+    {
+        ids_1.push(ids[i]);
+        ids_2.push(ids[j]);
+        pos_1.push(rpos[i]);
+        pos_2.push(rpos[j]);
+        rows_1.push(i as u64);
+        rows_2.push(j as u64);
+    }
+    let col_id_1 = Column::new("id_1".into(), ids_1);
+    let col_id_2 = Column::new("id_2".into(), ids_2);
+    let col_pos_1 = Column::new("pos_1".into(), pos_1);
+    let col_pos_2 = Column::new("pos_2".into(), pos_2);
+    // Keep track of rows in df_grp.
+    let col_row_1 = Column::new("row_1".into(), rows_1);
+    let col_row_2 = Column::new("row_2".into(), rows_2);
 
     // Find id pair groups with multiple identical sunks.
     // We do this here instead of in polars as would require cloning df twice to perform agg + uniq operation.
@@ -170,7 +422,7 @@ fn get_read_largest_sunk_graph_component(
                 .zip(col_pos_2.i64()?.iter().flatten()),
         )
         // Sort and group by pair
-        .sorted_by(|(id_pair_1, _), (id_pair_2, _)| id_pair_1.cmp(&id_pair_2))
+        .sorted_by(|(id_pair_1, _), (id_pair_2, _)| id_pair_1.cmp(id_pair_2))
         .chunk_by(|(id_pair, _)| *id_pair)
         .into_iter()
         // Count number of unique SUNK positions per group.
@@ -190,6 +442,13 @@ fn get_read_largest_sunk_graph_component(
         })
         .collect();
 
+    log::debug!(
+        "{rname}: {} SUNK pairs with multiple read positions resolved with {:?}.",
+        multi_sunk_grps.len(),
+        params.multi_sunk
+    );
+
+    check_deadline()?;
     let is_multi_sunk = Column::new(
         "is_multi_sunk".into(),
         col_id_1
@@ -201,26 +460,59 @@ fn get_read_largest_sunk_graph_component(
             .collect_vec(),
     );
 
-    let cols_subset_id_pos_comb = DataFrame::new(vec![
+    let lf_id_pos_comb = DataFrame::new(vec![
         col_id_1,
         col_id_2,
         col_pos_1,
         col_pos_2,
+        col_row_1,
+        col_row_2,
         is_multi_sunk,
     ])?
-    .lazy()
-    // Drop other rows that have dupe sunks.
-    .unique(
-        Some(vec!["id_1".into(), "id_2".into(), "is_multi_sunk".into()]),
-        UniqueKeepStrategy::First,
-    )
-    .drop([col("is_multi_sunk")])
-    .collect()?
-    .take_columns();
+    .lazy();
+    let lf_id_pos_comb = match params.multi_sunk {
+        MultiSunkStrategy::KeepFirst => lf_id_pos_comb,
+        // Order rows so the pair whose read distance best matches the contig distance comes first.
+        MultiSunkStrategy::KeepClosest => lf_id_pos_comb.sort_by_exprs(
+            [(((col("id_2") - col("id_1")).abs() - (col("pos_2") - col("pos_1")).abs()).abs())],
+            SortMultipleOptions::default().with_maintain_order(true),
+        ),
+        MultiSunkStrategy::DropAll => lf_id_pos_comb.filter(col("is_multi_sunk").not()),
+    };
+    let cols_subset_id_pos_comb = lf_id_pos_comb
+        // Drop other rows that have dupe sunks.
+        .unique(
+            Some(vec!["id_1".into(), "id_2".into(), "is_multi_sunk".into()]),
+            UniqueKeepStrategy::First,
+        )
+        .drop([col("is_multi_sunk")])
+        .collect()?
+        .take_columns();
 
-    let [col_id_1, col_id_2, col_pos_1, col_pos_2] = &cols_subset_id_pos_comb[..] else {
-        bail!("Insufficient num of columns.")
+    let [col_id_1, col_id_2, col_pos_1, col_pos_2, col_row_1, col_row_2] =
+        &cols_subset_id_pos_comb[..]
+    else {
+        return Err(GavisunkError::MissingColumns(
+            "id_1, id_2, pos_1, pos_2, row_1, row_2".to_owned(),
+        ));
     };
+    // Row of each SUNK id used in the graph.
+    let mut id_rows: HashMap<i64, usize> = HashMap::new();
+    for (id, row) in col_id_1
+        .i64()?
+        .iter()
+        .flatten()
+        .zip(col_row_1.u64()?.iter().flatten())
+        .chain(
+            col_id_2
+                .i64()?
+                .iter()
+                .flatten()
+                .zip(col_row_2.u64()?.iter().flatten()),
+        )
+    {
+        id_rows.entry(id).or_insert(row as usize);
+    }
 
     let mut graph: Graph<i64, i64, petgraph::Undirected> = Graph::new_undirected();
     // Add and store nodes
@@ -260,25 +552,253 @@ fn get_read_largest_sunk_graph_component(
         return Ok(None);
     };
 
-    Ok(Some(
-        largest_component
-            .iter()
-            .flat_map(|node| graph.node_weight(*node))
-            .cloned()
-            .collect(),
-    ))
+    log::debug!(
+        "{rname}: {} components. Largest has {} SUNKs.",
+        components.len(),
+        largest_component.len()
+    );
+
+    let mut read_component = ReadComponent {
+        is_rev: true_orient,
+        ..Default::default()
+    };
+    for id in largest_component
+        .iter()
+        .flat_map(|node| graph.node_weight(*node))
+    {
+        let row = id_rows[id];
+        read_component.ids.push(*id);
+        read_component.rpos.push(rpos[row]);
+        read_component.cpos.push(cpos[row]);
+    }
+    Ok(Some(read_component))
+}
+
+/// Total bases covered by a contig's intervals with columns `[st, end]`. Overlapping intervals are counted once.
+pub fn supported_bp(df_bed: &DataFrame) -> Result<u64> {
+    let intervals = df_bed
+        .column("st")?
+        .i64()?
+        .iter()
+        .zip(df_bed.column("end")?.i64()?.iter())
+        .flat_map(|(st, end)| st.zip(end))
+        .collect();
+    Ok(merge(intervals, 0)
+        .into_iter()
+        .map(|(st, end)| (end - st) as u64)
+        .sum())
+}
+
+/// Clamp intervals with columns `[st, end]` to `[0, ctg_len]`.
+fn clamp_intervals(mut df_bed: DataFrame, ctg_len: u64) -> Result<DataFrame> {
+    let ctg_len = i64::try_from(ctg_len)?;
+    for name in ["st", "end"] {
+        let clamped = df_bed
+            .column(name)?
+            .i64()?
+            .apply_values(|pos| pos.clamp(0, ctg_len))
+            .into_column();
+        df_bed.with_column(clamped)?;
+    }
+    Ok(df_bed)
+}
+
+/// SUNKs of a read on a circular contig with contig positions and ids continued past the origin.
+///
+/// SUNK ids are contig positions, so both are shifted by the same whole contig lengths. See [`unwrap_origin`].
+///
+/// # Arguments
+/// * `df_grp`
+///     * [`DataFrame`] of a read's SUNKs with columns `[rpos, cpos, id, ...]`
+/// * `ctg_len`
+///     * Contig length.
+///
+/// # Returns
+/// * [`DataFrame`] of the read's SUNKs sorted by unwrapped contig position.
+fn unwrap_read_origin(df_grp: &DataFrame, ctg_len: i64) -> Result<DataFrame> {
+    let rpos = df_grp.column("rpos")?.i64()?.cont_slice()?;
+    let cpos = df_grp.column("cpos")?.i64()?.cont_slice()?;
+    let ids = df_grp.column("id")?.i64()?.cont_slice()?;
+    let anchors: Vec<(i64, i64)> = rpos.iter().copied().zip(cpos.iter().copied()).collect();
+    let shifts: Vec<i64> = unwrap_origin(&anchors, ctg_len)
+        .into_iter()
+        .zip(cpos)
+        .map(|(unwrapped, cpos)| unwrapped - cpos)
+        .collect();
+    let mut df_unwrapped = df_grp.clone();
+    df_unwrapped.with_column(Column::new(
+        "cpos".into(),
+        cpos.iter()
+            .zip(&shifts)
+            .map(|(cpos, shift)| cpos + shift)
+            .collect_vec(),
+    ))?;
+    df_unwrapped.with_column(Column::new(
+        "id".into(),
+        ids.iter()
+            .zip(&shifts)
+            .map(|(id, shift)| id + shift)
+            .collect_vec(),
+    ))?;
+    let mut df_unwrapped = df_unwrapped.sort(["cpos", "rpos"], Default::default())?;
+    df_unwrapped.as_single_chunk();
+    Ok(df_unwrapped)
+}
+
+/// Anchors of each read's largest consistent SUNK component.
+///
+/// Reads exceeding their budget are skipped and recorded. See [`skipped_reads`].
+///
+/// # Arguments
+/// * `ctg`
+///     * Contig.
+/// * `df_sunk_pos`
+///     * [`DataFrame`] of filtered read SUNKs with columns `[read, rpos, cpos, id, ...]`
+/// * `params`
+///     * Graph parameters.
+/// * `circular_len`
+///     * Length of the contig if circular. See [`unwrap_read_origin`].
+/// * `state`
+///     * Run skipped reads are recorded in.
+/// * `cancel`
+///     * Token checked before each read.
+fn read_anchors(
+    ctg: &str,
+    df_sunk_pos: &DataFrame,
+    params: &GraphParams,
+    circular_len: Option<i64>,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<ReadAnchors> {
+    let mut anchors = ReadAnchors::default();
+    for df_grp in df_sunk_pos.partition_by(["read"], true)?.iter() {
+        cancel.check()?;
+        let Some(rname) = df_grp.column("read")?.str()?.first() else {
+            continue;
+        };
+        let df_unwrapped;
+        let df_grp = match circular_len {
+            Some(ctg_len) => {
+                df_unwrapped = unwrap_read_origin(df_grp, ctg_len)?;
+                &df_unwrapped
+            }
+            None => df_grp,
+        };
+        let read_comp = match get_read_largest_sunk_graph_component(df_grp, rname, params) {
+            Err(GavisunkError::ReadBudgetExceeded { status, .. }) => {
+                skip_read(
+                    SkippedRead {
+                        ctg: ctg.to_owned(),
+                        read: rname.to_owned(),
+                        sunks: df_grp.height(),
+                        status,
+                    },
+                    state,
+                );
+                continue;
+            }
+            res => res?,
+        };
+        if let Some(mut read_comp) = read_comp {
+            let read_proj_cpos = read_comp.projected_cpos();
+            // Anchors past the origin are the same SUNKs of the contig as before it.
+            if let Some(ctg_len) = circular_len {
+                read_comp
+                    .ids
+                    .iter_mut()
+                    .for_each(|id| *id = id.rem_euclid(ctg_len));
+            }
+            anchors
+                .rnames
+                .extend(std::iter::repeat_n(rname.to_owned(), read_comp.ids.len()));
+            anchors.offset_devs.extend(
+                read_comp
+                    .cpos
+                    .iter()
+                    .zip(read_proj_cpos.iter())
+                    .map(|(cpos, proj_cpos)| cpos - proj_cpos),
+            );
+            anchors.ids.extend(read_comp.ids);
+            anchors.rpos.extend(read_comp.rpos);
+            anchors.proj_cpos.extend(read_proj_cpos);
+        }
+    }
+    Ok(anchors)
 }
 
+/// Build SUNK graphs for reads assigned to a contig and get the supported intervals.
+///
+/// # Arguments
+/// * `df_read_lens`
+///     * [`DataFrame`] of read lengths with columns `[read, read_length]`. See [`crate::map_kmers::read_lengths_df`].
+/// * `asm_lens`
+///     * Contig lengths. Supported intervals are clamped to the contig and the percent supported is logged.
+/// * `state`
+///     * Run the reads and SUNKs kept by each filter, join losses, and skipped reads are recorded in.
+///
+/// # Returns
+/// * [`DataFrame`] of SUNKs in the largest component of each read.
+///     * With columns `[read, id, rpos, proj_cpos, offset_dev, component]`
+///     * `id` is the [`sunk_id`] of the SUNK group.
+///     * `proj_cpos` is the read position projected onto the contig with the read's median offset.
+///       Reads crossing the origin of a circular contig continue past its length.
+///     * `offset_dev` is the contig position of the SUNK minus `proj_cpos`.
+///     * `component` is the index of the contig component in the supported intervals, if any.
+/// * [`DataFrame`] of supported intervals.
+///     * With columns `[ctg, st, end, sunks]`
+#[allow(clippy::too_many_arguments)]
 pub fn create_sunk_graph(
     ctg: &str,
     df_read_sunks: &DataFrame,
-    read_lens: &HashMap<String, u64>,
+    df_read_lens: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    df_bad_sunks: &DataFrame,
+    params: &GraphParams,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<(DataFrame, DataFrame)> {
+    create_sunk_graph_with_store(
+        ctg,
+        df_read_sunks,
+        df_read_lens,
+        asm_lens,
+        df_bad_sunks,
+        params,
+        false,
+        None,
+        state,
+        cancel,
+    )
+}
+
+/// [`create_sunk_graph`] loading each read's anchors from `store` if built from the same read SUNKs and parameters.
+/// Otherwise, they're computed and written to `store`.
+///
+/// If `circular`, reads and components may continue past the origin of the contig. Supported intervals crossing it
+/// end past the contig length. See [`crate::circular::split_origin`].
+#[allow(clippy::too_many_arguments)]
+pub fn create_sunk_graph_with_store(
+    ctg: &str,
+    df_read_sunks: &DataFrame,
+    df_read_lens: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
     df_bad_sunks: &DataFrame,
-) -> eyre::Result<(DataFrame, DataFrame)> {
-    let lf_read_sunks = df_read_sunks
+    params: &GraphParams,
+    circular: bool,
+    store: Option<&AnchorStore>,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<(DataFrame, DataFrame)> {
+    let df_unmasked = df_read_sunks
         .clone()
         .lazy()
-        .with_column((col("ctg") + lit(":") + col("group").cast(DataType::String)).alias("id"))
+        // Positions are unsigned when computed but inferred as signed when loaded from an existing file.
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
+            col("group").cast(DataType::Int64),
+        ])
+        .with_column(sunk_id_expr().alias("id"))
         .join(
             df_bad_sunks.clone().lazy(),
             [col("id")],
@@ -287,7 +807,18 @@ pub fn create_sunk_graph(
         )
         .with_column(col("group").alias("id"))
         // Filter out bad sunks.
-        .filter(col("count").is_null());
+        .filter(col("count").is_null())
+        .collect()?;
+    filter_audit::record(
+        Stage::Filter,
+        "bad_sunks",
+        SUNK_HITS,
+        df_read_sunks.height(),
+        df_unmasked.height(),
+        state,
+    );
+    let n_unmasked_reads = df_unmasked.column("read")?.n_unique()?;
+    let lf_read_sunks = df_unmasked.lazy();
 
     let lf_multisunk = lf_read_sunks
         .clone()
@@ -307,59 +838,224 @@ pub fn create_sunk_graph(
         .sort(["read", "rpos"], Default::default())
         .unique(None, UniqueKeepStrategy::First);
 
-    let (col_reads, col_read_len): (Vec<String>, Vec<u64>) = read_lens.clone().into_iter().unzip();
-
     let df_sunk_pos_w_len = lf_sunk_pos
         .join(
-            DataFrame::new(vec![
-                Column::new("read".into(), col_reads),
-                Column::new("read_length".into(), col_read_len),
-            ])?
-            .lazy(),
+            df_read_lens.clone().lazy(),
             [col("read")],
             [col("read")],
             JoinArgs::new(JoinType::Left),
         )
+        .collect()?;
+    check_join_loss(
+        &format!("{ctg} read SUNKs x read lengths"),
+        "reads missing from the reads FASTA, ex. from a different reads file or mismatched names",
+        df_sunk_pos_w_len.height(),
+        df_sunk_pos_w_len.column("read_length")?.null_count(),
+        state,
+    );
+    let n_multi_sunk_reads = df_sunk_pos_w_len.column("read")?.n_unique()?;
+    filter_audit::record(
+        Stage::Graph,
+        "multi_sunk",
+        READS,
+        n_unmasked_reads,
+        n_multi_sunk_reads,
+        state,
+    );
+    let df_sunk_pos_w_len = df_sunk_pos_w_len
+        .lazy()
         .filter(col("read_length").gt(MIN_READ_LEN))
         .sort(["cpos", "rpos"], Default::default())
         .collect()?;
+    let n_long_reads = df_sunk_pos_w_len.column("read")?.n_unique()?;
+    filter_audit::record(
+        Stage::Graph,
+        "read_length",
+        READS,
+        n_multi_sunk_reads,
+        n_long_reads,
+        state,
+    );
 
-    let (rnames, ids): (Vec<String>, Vec<i64>) = df_sunk_pos_w_len
-        .partition_by(["read"], true)?
-        .iter()
-        .flat_map(|df_grp| {
-            let rname = df_grp
-                .column("read")
-                .unwrap()
-                .str()
-                .unwrap()
-                .first()
-                .unwrap();
-            if let Some(ids) = get_read_largest_sunk_graph_component(&df_grp, rname).unwrap() {
-                Some((vec![rname.to_owned(); ids.len()], ids))
-            } else {
-                None
+    let circular_len = asm_lens
+        .get(ctg)
+        .filter(|_| circular)
+        .map(|ctg_len| i64::try_from(*ctg_len))
+        .transpose()?;
+    let anchors = match store {
+        Some(store) => {
+            let key = AnchorStore::key(ctg, &df_sunk_pos_w_len, params, circular_len)?;
+            match store.load(ctg, key)? {
+                Some(anchors) => {
+                    log::debug!("Loaded anchors of {ctg} from {:?}.", store.path(ctg));
+                    anchors
+                }
+                None => {
+                    let anchors =
+                        read_anchors(ctg, &df_sunk_pos_w_len, params, circular_len, state, cancel)?;
+                    store.write(ctg, key, &anchors)?;
+                    anchors
+                }
             }
-        })
-        .reduce(|(mut r1, mut p1), (mut r2, mut p2)| {
-            r1.append(&mut r2);
-            p1.append(&mut p2);
-            (r1, p1)
-        })
-        .unwrap();
+        }
+        None => read_anchors(ctg, &df_sunk_pos_w_len, params, circular_len, state, cancel)?,
+    };
+    // Reads without a pair of SUNKs within the distance tolerance have no anchors.
+    filter_audit::record(
+        Stage::Graph,
+        "distance_ratio",
+        READS,
+        n_long_reads,
+        anchors.rnames.iter().unique().count(),
+        state,
+    );
+    let ReadAnchors {
+        rnames,
+        ids,
+        rpos,
+        proj_cpos,
+        offset_devs,
+    } = anchors;
 
-    let df_output_bed = get_contig_sunk_graph_components(ctg, &rnames, &ids)?;
+    let (df_output_bed, id_comps) = get_contig_sunk_graph_components(
+        ctg,
+        &rnames,
+        &ids,
+        params.min_component_sunks,
+        circular_len,
+    )?;
+    let df_output_bed = match asm_lens.get(ctg) {
+        Some(ctg_len) => {
+            // Intervals of circular contigs are already on the contig or cross its origin.
+            let (df_output_bed, df_contig_bed) = if circular {
+                let (df_split, _) = split_origin(&df_output_bed, *ctg_len)?;
+                (df_output_bed, df_split)
+            } else {
+                let df_output_bed = clamp_intervals(df_output_bed, *ctg_len)?;
+                (df_output_bed.clone(), df_output_bed)
+            };
+            let ctg_supported_bp = supported_bp(&df_contig_bed)?;
+            log::debug!(
+                "Supported {ctg_supported_bp} of {ctg_len} bp ({:.2}%) of {ctg}.",
+                ctg_supported_bp as f64 / (*ctg_len).max(1) as f64 * 100.0
+            );
+            df_output_bed
+        }
+        None => {
+            log::warn!("No assembly length for {ctg}. Supported intervals are not clamped.");
+            df_output_bed
+        }
+    };
+    let comps: Vec<Option<u64>> = ids.iter().map(|id| id_comps.get(id).copied()).collect();
     let df_output_sunks = DataFrame::new(vec![
         Column::new("read".into(), rnames),
-        Column::new("id".into(), ids),
+        Column::new(
+            "id".into(),
+            ids.iter()
+                .map(|id| sunk_id(ctg, *id))
+                .collect::<Vec<String>>(),
+        ),
+        Column::new("rpos".into(), rpos),
+        Column::new("proj_cpos".into(), proj_cpos),
+        Column::new("offset_dev".into(), offset_devs),
+        Column::new("component".into(), comps),
     ])?;
 
     Ok((df_output_sunks, df_output_bed))
 }
 
+/// Place reads with a single SUNK where it agrees with supported components.
+///
+/// Reads with one SUNK have no pairs to check, so they're dropped when building read graphs. They still carry a
+/// position, so a read is rescued if its SUNK group is in a supported contig component. Rescued reads don't add
+/// support and are counted separately.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, group]`
+/// * `df_read_lens`
+///     * [`DataFrame`] of read lengths with columns `[read, read_length]`. Reads [`MIN_READ_LEN`] or shorter are skipped.
+/// * `df_bad_sunks`
+///     * [`DataFrame`] of bad SUNK groups with columns `[id, ...]`
+/// * `df_components`
+///     * [`DataFrame`] of read SUNKs in supported components with columns `[ctg, id, component, ...]`. See
+///       [`create_sunk_graph`].
+///
+/// # Returns
+/// * [`DataFrame`] of rescued reads with columns `[ctg, read, id, rpos, cpos, component]`
+pub fn rescue_single_sunk_reads(
+    df_read_sunks: &DataFrame,
+    df_read_lens: &DataFrame,
+    df_bad_sunks: &DataFrame,
+    df_components: &DataFrame,
+) -> Result<DataFrame> {
+    let lf_supported_ids = df_components
+        .clone()
+        .lazy()
+        .filter(col("component").is_not_null())
+        .select([col("id"), col("component")])
+        .unique(None, UniqueKeepStrategy::First);
+    Ok(df_read_sunks
+        .clone()
+        .lazy()
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
+            col("group").cast(DataType::Int64),
+        ])
+        .with_column(sunk_id_expr().alias("id"))
+        .join(
+            df_bad_sunks
+                .clone()
+                .lazy()
+                .select([col("id"), col("count")]),
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .filter(col("count").is_null())
+        // Reads are graphed per contig.
+        .filter(col("id").n_unique().over(["ctg", "read"]).eq(lit(1)))
+        .join(
+            df_read_lens.clone().lazy(),
+            [col("read")],
+            [col("read")],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .filter(col("read_length").gt(MIN_READ_LEN))
+        .join(
+            lf_supported_ids,
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Inner),
+        )
+        .sort(["ctg", "cpos", "read", "rpos"], Default::default())
+        .unique_stable(
+            Some(vec!["ctg".into(), "read".into()]),
+            UniqueKeepStrategy::First,
+        )
+        .select([
+            col("ctg"),
+            col("read"),
+            col("id"),
+            col("rpos"),
+            col("cpos"),
+            col("component"),
+        ])
+        .collect()?)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::{create_sunk_graph, io::write_tsv};
+    use crate::{
+        cancel::CancelToken,
+        io::write_tsv,
+        map_kmers::read_lengths_df,
+        run_state::RunState,
+        sunk_graph::{
+            create_sunk_graph, parse_dst_tolerance, rescue_single_sunk_reads, GraphParams,
+        },
+    };
     use std::{
         collections::HashMap,
         fs::File,
@@ -370,7 +1066,16 @@ mod test {
     use polars::prelude::*;
     use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-    // #[test]
+    #[test]
+    fn test_parse_dst_tolerance() {
+        assert_eq!(parse_dst_tolerance("0.1").unwrap(), 0.1);
+        for tolerance in ["0", "-0.1", "1", "1.5", "NaN", "inf"] {
+            assert!(parse_dst_tolerance(tolerance).is_err(), "{tolerance}");
+        }
+    }
+
+    #[test]
+    #[ignore = "requires local data in ignore/test_graph"]
     fn test_run() {
         let df_read_sunks = {
             let mut df = CsvReadOptions::default()
@@ -393,14 +1098,12 @@ mod test {
                 .lines()
                 .flat_map(|l| {
                     let line = l.unwrap();
-                    let Some((x, y)) = line.trim().split("\t").collect_tuple::<(&str, &str)>()
-                    else {
-                        return None;
-                    };
+                    let (x, y) = line.trim().split("\t").collect_tuple::<(&str, &str)>()?;
                     Some((x.to_owned(), y.parse::<u64>().unwrap()))
                 })
                 .collect()
         };
+        let df_read_lens = read_lengths_df(&read_lens).unwrap();
         let df_bad_sunks: DataFrame = {
             let df = CsvReadOptions::default()
                 .with_has_header(false)
@@ -429,10 +1132,62 @@ mod test {
                     .first()
                     .map(|ctg| ctg.to_owned())
                     .unwrap();
-                let (mut df_sunks, mut df_bed) =
-                    create_sunk_graph(&contig, &df_ctg, &read_lens, &df_bad_sunks).unwrap();
+                let (mut df_sunks, mut df_bed) = create_sunk_graph(
+                    &contig,
+                    df_ctg,
+                    &df_read_lens,
+                    &HashMap::new(),
+                    &df_bad_sunks,
+                    &GraphParams::default(),
+                    &RunState::new(),
+                    &CancelToken::new(),
+                )
+                .unwrap();
                 write_tsv(&mut df_sunks, format!("{contig}_sunks.tsv")).unwrap();
                 write_tsv(&mut df_bed, format!("{contig}.bed")).unwrap();
             });
     }
+
+    #[test]
+    fn test_rescue_single_sunk_reads() {
+        // r1 has a single SUNK in a supported component, r2 one outside any, r3 two, and r4 is too short.
+        let df_read_sunks = df!(
+            "read" => ["r1", "r2", "r3", "r3", "r4"],
+            "rpos" => [10i64, 10, 10, 110, 10],
+            "ctg" => ["c1", "c1", "c1", "c1", "c1"],
+            "cpos" => [1_000i64, 5_000, 1_000, 1_100, 1_000],
+            "group" => [1i64, 5, 1, 2, 1],
+        )
+        .unwrap();
+        let read_lens = HashMap::from([
+            ("r1".to_owned(), 20_000),
+            ("r2".to_owned(), 20_000),
+            ("r3".to_owned(), 20_000),
+            ("r4".to_owned(), 5_000),
+        ]);
+        let df_bad_sunks = df!("id" => ["c1:9"], "count" => [100u32]).unwrap();
+        let df_components = df!(
+            "ctg" => ["c1", "c1"],
+            "read" => ["r5", "r5"],
+            "id" => ["c1:1", "c1:5"],
+            "component" => [Some(0u64), None],
+        )
+        .unwrap();
+        let df = rescue_single_sunk_reads(
+            &df_read_sunks,
+            &read_lengths_df(&read_lens).unwrap(),
+            &df_bad_sunks,
+            &df_components,
+        )
+        .unwrap();
+        assert_eq!(
+            df.column("read")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [Some("r1")]
+        );
+    }
 }
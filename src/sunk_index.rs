@@ -0,0 +1,403 @@
+//! Packed SUNK lookup table in a memory-mapped file shared read-only by processes on a node.
+//!
+//! Processes mapping reads on the same node, ex. several `serve-map` workers, otherwise each hold their own copy of the
+//! SUNKs searched for. The index stores SUNKs 2-bit packed and sorted so the page cache backs a single copy mapped
+//! into every process. Read k-mers are looked up by binary search so lookups don't scale with the number of SUNKs.
+//!
+//! Layout, little-endian: magic, format version `u32`, k-mer size `u32`, number of SUNKs `u64`, CRC-32 of the packed
+//! SUNKs `u32`, 4 reserved bytes, then the sorted packed SUNKs as `u64`.
+//!
+//! Within a single process, [`SunkHashIndex`] holds the packed SUNKs in a hash table instead.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::{BuildHasherDefault, Hasher},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    error::{GavisunkError, Result},
+    get_kmers::{base_code, pack_kmer, unpack_kmer},
+};
+
+/// Magic bytes at the start of a SUNK index.
+const MAGIC: &[u8; 8] = b"GVSUNKS\0";
+/// Version of the index layout.
+const FORMAT_VERSION: u32 = 2;
+/// Bytes before the packed SUNKs.
+const HEADER_LEN: usize = 32;
+/// Largest k-mer size that can be packed into a `u64` with room for the reverse complement shift.
+pub const MAX_INDEX_KMER_SIZE: usize = 31;
+
+/// Memory-mapped SUNK index.
+#[derive(Debug)]
+pub struct SunkIndex {
+    path: PathBuf,
+    mmap: Mmap,
+    kmer_size: usize,
+    len: usize,
+    /// See [`checksum`].
+    checksum: u32,
+}
+
+impl SunkIndex {
+    /// Write an index of SUNKs.
+    ///
+    /// Written to a temporary file unique to this process and renamed so processes building the same index at once
+    /// don't read a partial file.
+    ///
+    /// # Arguments
+    /// * `sunks`
+    ///     * SUNKs of the same length up to [`MAX_INDEX_KMER_SIZE`].
+    /// * `path`
+    ///     * Index path.
+    pub fn write(sunks: &[&str], path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let invalid = |reason: String| GavisunkError::InvalidSunkIndex {
+            path: path.to_owned(),
+            reason,
+        };
+        let kmer_size = sunks.first().map_or(0, |sunk| sunk.len());
+        if kmer_size > MAX_INDEX_KMER_SIZE {
+            return Err(invalid(format!(
+                "k-mer size {kmer_size} is larger than {MAX_INDEX_KMER_SIZE}"
+            )));
+        }
+        let mut packed = sunks
+            .iter()
+            .map(|sunk| {
+                pack_kmer(sunk.as_bytes())
+                    .filter(|_| sunk.len() == kmer_size)
+                    .ok_or_else(|| invalid(format!("can't pack SUNK {sunk:?}")))
+            })
+            .collect::<Result<Vec<u64>>>()?;
+        packed.sort_unstable();
+        packed.dedup();
+
+        let mut tmp_fname = path.as_os_str().to_owned();
+        tmp_fname.push(format!(".{}.tmp", std::process::id()));
+        let tmp_path = PathBuf::from(tmp_fname);
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+            writer.write_all(&(kmer_size as u32).to_le_bytes())?;
+            writer.write_all(&(packed.len() as u64).to_le_bytes())?;
+            writer.write_all(&checksum(&packed).to_le_bytes())?;
+            writer.write_all(&[0; 4])?;
+            for kmer in &packed {
+                writer.write_all(&kmer.to_le_bytes())?;
+            }
+            writer
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()?;
+        }
+        std::fs::rename(&tmp_path, path).map_err(|source| GavisunkError::Rename {
+            from: tmp_path,
+            to: path.to_owned(),
+            source,
+        })?;
+        Ok(())
+    }
+
+    /// Memory-map an index.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let invalid = |reason: &str| GavisunkError::InvalidSunkIndex {
+            path: path.to_owned(),
+            reason: reason.to_owned(),
+        };
+        let file = File::open(path)?;
+        // SAFETY: Indices are only replaced by rename, never modified in place, so the mapped file doesn't change.
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Err(invalid("not a SUNK index"));
+        }
+        let read_u32 = |st: usize| u32::from_le_bytes(mmap[st..st + 4].try_into().unwrap());
+        if read_u32(8) != FORMAT_VERSION {
+            return Err(invalid("unsupported format version"));
+        }
+        let kmer_size = read_u32(12) as usize;
+        let len = usize::try_from(u64::from_le_bytes(mmap[16..24].try_into().unwrap()))?;
+        if mmap.len() != HEADER_LEN + len * 8 {
+            return Err(invalid("truncated"));
+        }
+        let checksum = read_u32(24);
+        Ok(Self {
+            path: path.to_owned(),
+            mmap,
+            kmer_size,
+            len,
+            checksum,
+        })
+    }
+
+    /// Open an index of `sunks`, writing it first if it doesn't exist.
+    ///
+    /// # Returns
+    /// * [`GavisunkError::InvalidSunkIndex`] if an existing index wasn't built from `sunks`.
+    pub fn open_or_create(path: impl AsRef<Path>, sunks: &[&str]) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            log::info!("Writing SUNK index to {path:?}.");
+            Self::write(sunks, path)?;
+        }
+        let index = Self::open(path)?;
+        if !index.matches(sunks) {
+            return Err(GavisunkError::InvalidSunkIndex {
+                path: path.to_owned(),
+                reason: "built from other SUNKs. Remove it to rebuild it".to_owned(),
+            });
+        }
+        log::info!("Memory-mapped {} SUNKs from {path:?}.", index.len());
+        Ok(index)
+    }
+
+    /// Whether this index was built from `sunks`.
+    ///
+    /// The k-mer size, number of SUNKs, and checksum of all packed SUNKs are compared.
+    pub fn matches(&self, sunks: &[&str]) -> bool {
+        let mut packed: Vec<u64> = sunks
+            .iter()
+            .filter(|sunk| sunk.len() == self.kmer_size)
+            .filter_map(|sunk| pack_kmer(sunk.as_bytes()))
+            .collect();
+        if packed.len() != sunks.len() {
+            return false;
+        }
+        packed.sort_unstable();
+        packed.dedup();
+        packed.len() == self.len && checksum(&packed) == self.checksum
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn kmer_size(&self) -> usize {
+        self.kmer_size
+    }
+
+    /// Number of SUNKs.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Packed SUNK at a position.
+    fn get(&self, idx: usize) -> u64 {
+        let st = HEADER_LEN + idx * 8;
+        u64::from_le_bytes(self.mmap[st..st + 8].try_into().unwrap())
+    }
+
+    /// Position of a packed k-mer among the sorted SUNKs.
+    pub fn position(&self, packed: u64) -> Option<usize> {
+        let (mut lo, mut hi) = (0, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.get(mid).cmp(&packed) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// SUNK at a position. See [`SunkIndex::position`].
+    pub fn kmer(&self, idx: usize) -> String {
+        unpack_kmer(self.get(idx), self.kmer_size)
+    }
+
+    /// Find SUNKs in both orientations within a sequence.
+    ///
+    /// k-mers with bases other than `ACGT` (case-insensitive) are skipped.
+    ///
+    /// # Returns
+    /// * `(pos, idx, is_fwd)` for each hit with 1-based `pos` of the start of the k-mer in `seq` and `idx` the
+    ///   position of the SUNK. `is_fwd` is `false` if the reverse complement of the SUNK was found.
+    pub fn find(&self, seq: &[u8]) -> Vec<(usize, usize, bool)> {
+        if self.is_empty() {
+            return vec![];
+        }
+        scan(seq, self.kmer_size, |packed| self.position(packed))
+    }
+}
+
+/// CRC-32 of sorted packed SUNKs as little-endian `u64`s.
+fn checksum(packed: &[u64]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for kmer in packed {
+        hasher.update(&kmer.to_le_bytes());
+    }
+    hasher.finalize()
+}
+
+/// Look up each k-mer of a sequence in both orientations. See [`SunkIndex::find`].
+fn scan(
+    seq: &[u8],
+    kmer_size: usize,
+    position: impl Fn(u64) -> Option<usize>,
+) -> Vec<(usize, usize, bool)> {
+    if kmer_size == 0 {
+        return vec![];
+    }
+    let mask = (1u64 << (2 * kmer_size)) - 1;
+    let shift = 2 * (kmer_size as u64 - 1);
+    let (mut fwd, mut rev, mut n_valid) = (0u64, 0u64, 0usize);
+    let mut hits = vec![];
+    for (i, base) in seq.iter().enumerate() {
+        let Some(code) = base_code(*base) else {
+            n_valid = 0;
+            continue;
+        };
+        fwd = ((fwd << 2) | code) & mask;
+        rev = (rev >> 2) | ((3 - code) << shift);
+        n_valid += 1;
+        if n_valid < kmer_size {
+            continue;
+        }
+        let pos = i + 2 - kmer_size;
+        if let Some(idx) = position(fwd) {
+            hits.push((pos, idx, true));
+        }
+        if let Some(idx) = position(rev) {
+            hits.push((pos, idx, false));
+        }
+    }
+    hits
+}
+
+/// Hasher of packed k-mers. Packed k-mers are already uniform in their low bits so one multiply spreads them.
+#[derive(Debug, Default, Clone, Copy)]
+struct KmerHasher(u64);
+
+impl Hasher for KmerHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0.rotate_left(8) ^ u64::from(*byte)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        }
+    }
+
+    fn write_u64(&mut self, packed: u64) {
+        self.0 = packed.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+}
+
+/// In-memory hash table of packed SUNKs built once and shared by all threads mapping reads.
+///
+/// Each read is scanned once and each of its k-mers looked up, so mapping scales with read bases rather than read
+/// bases times SUNKs as when indexing each read and searching it for every SUNK.
+#[derive(Debug)]
+pub struct SunkHashIndex {
+    kmer_size: usize,
+    positions: HashMap<u64, usize, BuildHasherDefault<KmerHasher>>,
+}
+
+impl SunkHashIndex {
+    /// Index SUNKs by their position in `sunks`.
+    ///
+    /// # Returns
+    /// * [`None`] if the SUNKs can't be packed, ex. longer than [`MAX_INDEX_KMER_SIZE`], of different lengths, or with
+    ///   bases other than `ACGT`.
+    pub fn new(sunks: &[&str]) -> Option<Self> {
+        let kmer_size = sunks.first().map_or(0, |sunk| sunk.len());
+        if kmer_size > MAX_INDEX_KMER_SIZE {
+            return None;
+        }
+        let mut positions =
+            HashMap::with_capacity_and_hasher(sunks.len(), BuildHasherDefault::default());
+        for (idx, sunk) in sunks.iter().enumerate() {
+            if sunk.len() != kmer_size {
+                return None;
+            }
+            positions.insert(pack_kmer(sunk.as_bytes())?, idx);
+        }
+        Some(Self {
+            kmer_size,
+            positions,
+        })
+    }
+
+    pub fn kmer_size(&self) -> usize {
+        self.kmer_size
+    }
+
+    /// Number of distinct SUNKs.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Find SUNKs in both orientations within a sequence.
+    ///
+    /// # Returns
+    /// * `(pos, idx, is_fwd)` for each hit as in [`SunkIndex::find`] with `idx` the position of the SUNK in the
+    ///   `sunks` indexed. Of repeated SUNKs, the last is found.
+    pub fn find(&self, seq: &[u8]) -> Vec<(usize, usize, bool)> {
+        if self.is_empty() {
+            return vec![];
+        }
+        scan(seq, self.kmer_size, |packed| {
+            self.positions.get(&packed).copied()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SunkHashIndex, SunkIndex};
+    use crate::io::ScratchDir;
+
+    #[test]
+    fn test_sunk_index_find() {
+        let dir = ScratchDir::new_in(std::env::temp_dir(), "rs_gavisunk_test_sunk_index").unwrap();
+        let path = dir.path().join("sunks.idx");
+        let sunks = ["AACCAGTC", "TGGCATCA"];
+        let index = SunkIndex::open_or_create(&path, &sunks).unwrap();
+        assert_eq!((index.len(), index.kmer_size()), (2, 8));
+        assert!(!index.matches(&["AACCAGTC"]));
+
+        // Forward hit at 1 and reverse complement of TGGCATCA at 13.
+        let hits = index.find(b"AACCAGTCNNNNTGATGCCA");
+        assert_eq!(hits, [(1, 0, true), (13, 1, false)]);
+        assert_eq!(index.kmer(1), "TGGCATCA");
+
+        assert!(SunkIndex::open_or_create(&path, &["ACGTACGT"]).is_err());
+
+        // Same count and smallest and largest SUNK but another in between.
+        std::fs::remove_file(&path).unwrap();
+        let index =
+            SunkIndex::open_or_create(&path, &["AAAAAAAA", "ACACACAC", "TTTTTTTT"]).unwrap();
+        assert!(index.matches(&["TTTTTTTT", "ACACACAC", "AAAAAAAA"]));
+        assert!(!index.matches(&["AAAAAAAA", "AGAGAGAG", "TTTTTTTT"]));
+    }
+
+    #[test]
+    fn test_sunk_hash_index_find() {
+        let sunks = ["TGGCATCA", "aaccagtc"];
+        let index = SunkHashIndex::new(&sunks).unwrap();
+        assert_eq!((index.len(), index.kmer_size()), (2, 8));
+        // Positions are those of the SUNKs given rather than sorted.
+        let hits = index.find(b"AACCAGTCNNNNTGATGCCA");
+        assert_eq!(hits, [(1, 1, true), (13, 0, false)]);
+
+        assert!(SunkHashIndex::new(&["ACGTNCGT"]).is_none());
+        assert!(SunkHashIndex::new(&["ACGT", "ACGTA"]).is_none());
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use itertools::{iproduct, Itertools};
+use polars::prelude::*;
+use rayon::prelude::*;
+
+use crate::{
+    anchor_store::AnchorStore,
+    assign_read_ctg::assign_read_to_ctg_w_ort,
+    cancel::CancelToken,
+    error::Result,
+    filter_bad_sunks::{filter_bad_sunks, merge_extra_bad_sunks},
+    intervals::merge,
+    map_kmers::get_good_read_sunks,
+    run_state::RunState,
+    sunk_graph::{create_sunk_graph_with_store, GraphParams},
+};
+
+/// Downstream parameters varied in a sweep.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepParams {
+    /// Percentile band used to assign reads to contigs.
+    pub bandwidth: (f64, f64),
+    pub graph: GraphParams,
+}
+
+/// Build the grid of all combinations of parameters. Graph parameters that aren't varied are from `base`.
+pub fn sweep_grid(
+    bandwidths: &[(f64, f64)],
+    dst_tolerances: &[f32],
+    min_component_sunks: &[usize],
+    base: GraphParams,
+) -> Vec<SweepParams> {
+    iproduct!(bandwidths, dst_tolerances, min_component_sunks)
+        .map(
+            |(bandwidth, dst_tolerance, min_component_sunks)| SweepParams {
+                bandwidth: *bandwidth,
+                graph: GraphParams {
+                    dst_tolerance: *dst_tolerance,
+                    min_component_sunks: *min_component_sunks,
+                    ..base
+                },
+            },
+        )
+        .collect()
+}
+
+/// Supported bases, intervals, and breakpoints of supported intervals.
+///
+/// Breakpoints are gaps between supported intervals within a contig.
+fn supported_stats(dfs_bed: &[DataFrame]) -> Result<(u64, u64, u64)> {
+    let (mut supported_bp, mut n_intervals, mut n_breakpoints) = (0, 0, 0);
+    for df_bed in dfs_bed {
+        let intervals = merge(
+            df_bed
+                .column("st")?
+                .i64()?
+                .iter()
+                .zip(df_bed.column("end")?.i64()?.iter())
+                .flat_map(|(st, end)| st.zip(end))
+                .collect(),
+            0,
+        );
+        supported_bp += intervals
+            .iter()
+            .map(|(st, end)| (end - st) as u64)
+            .sum::<u64>();
+        n_intervals += intervals.len() as u64;
+        n_breakpoints += intervals.len().saturating_sub(1) as u64;
+    }
+    Ok((supported_bp, n_intervals, n_breakpoints))
+}
+
+/// Rerun the assign, filter, and graph stages across a grid of parameters, reusing mapped read SUNKs.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * Read SUNKs from [`crate::map_kmers::map_sunks_to_reads`].
+/// * `df_read_lens`
+///     * [`DataFrame`] of read lengths with columns `[read, read_length]`. See [`crate::map_kmers::read_lengths_df`].
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `df_extra_bad_sunks`
+///     * Known bad SUNKs merged with computed bad SUNKs. See [`crate::filter_bad_sunks::load_extra_bad_sunks`].
+/// * `grid`
+///     * Parameters to run. Bad SUNKs are only recomputed when the bandwidth changes.
+/// * `anchor_store`
+///     * Store of read anchors reused across `min_component_sunks`. See [`AnchorStore`].
+/// * `state`
+///     * Run join losses and skipped reads are recorded in.
+/// * `cancel`
+///     * Token checked between parameter sets and contigs.
+///
+/// # Returns
+/// * [`DataFrame`] with one row per parameter set and columns
+///   `[bandwidth_lower, bandwidth_upper, dst_tolerance, min_component_sunks, supported_bp, supported_intervals, breakpoints]`
+#[allow(clippy::too_many_arguments)]
+pub fn sweep(
+    df_read_sunks: &DataFrame,
+    df_read_lens: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    df_extra_bad_sunks: Option<&DataFrame>,
+    grid: &[SweepParams],
+    anchor_store: Option<&AnchorStore>,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<DataFrame> {
+    let dfs_ctg = df_read_sunks.partition_by(["ctg"], true)?;
+    let (mut bw_lower, mut bw_upper, mut tolerances, mut min_sunks) =
+        (vec![], vec![], vec![], vec![]);
+    let (mut supported_bps, mut n_intervals, mut n_breakpoints) = (vec![], vec![], vec![]);
+
+    for (bandwidth, params) in &grid
+        .iter()
+        .sorted_by(|a, b| a.bandwidth.partial_cmp(&b.bandwidth).unwrap())
+        .chunk_by(|params| params.bandwidth)
+    {
+        cancel.check()?;
+        let df_best_reads_asm =
+            assign_read_to_ctg_w_ort(df_read_sunks, Some(bandwidth), None, state)?;
+        let df_good_sunks_reads = get_good_read_sunks(df_read_sunks, &df_best_reads_asm, state)?;
+        let mut df_bad_sunks = filter_bad_sunks(&df_good_sunks_reads)?;
+        if let Some(df_extra) = df_extra_bad_sunks {
+            df_bad_sunks = merge_extra_bad_sunks(&df_bad_sunks, &df_good_sunks_reads, df_extra)?;
+        }
+
+        for params in params {
+            log::info!("Sweeping {params:?}");
+            let dfs_bed = dfs_ctg
+                .par_iter()
+                .map(|df_ctg| {
+                    cancel.check()?;
+                    let Some(ctg) = df_ctg.column("ctg")?.str()?.first() else {
+                        return Ok(None);
+                    };
+                    let (_, df_bed) = create_sunk_graph_with_store(
+                        ctg,
+                        df_ctg,
+                        df_read_lens,
+                        asm_lens,
+                        &df_bad_sunks,
+                        &params.graph,
+                        false,
+                        anchor_store,
+                        state,
+                        cancel,
+                    )?;
+                    Ok(Some(df_bed))
+                })
+                .collect::<Result<Vec<Option<DataFrame>>>>()?;
+            let dfs_bed: Vec<DataFrame> = dfs_bed.into_iter().flatten().collect();
+            let (supported_bp, intervals, breakpoints) = supported_stats(&dfs_bed)?;
+
+            bw_lower.push(params.bandwidth.0);
+            bw_upper.push(params.bandwidth.1);
+            tolerances.push(params.graph.dst_tolerance);
+            min_sunks.push(params.graph.min_component_sunks as u64);
+            supported_bps.push(supported_bp);
+            n_intervals.push(intervals);
+            n_breakpoints.push(breakpoints);
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("bandwidth_lower".into(), bw_lower),
+        Column::new("bandwidth_upper".into(), bw_upper),
+        Column::new("dst_tolerance".into(), tolerances),
+        Column::new("min_component_sunks".into(), min_sunks),
+        Column::new("supported_bp".into(), supported_bps),
+        Column::new("supported_intervals".into(), n_intervals),
+        Column::new("breakpoints".into(), n_breakpoints),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{supported_stats, sweep_grid};
+    use crate::sunk_graph::GraphParams;
+
+    #[test]
+    fn test_sweep_grid() {
+        let base = GraphParams {
+            max_read_sunks: None,
+            ..Default::default()
+        };
+        let grid = sweep_grid(&[(0.25, 0.75), (0.1, 0.9)], &[0.1, 0.2], &[5], base);
+        assert_eq!(grid.len(), 4);
+        assert_eq!(
+            grid.iter()
+                .map(|params| (params.bandwidth, params.graph.dst_tolerance))
+                .collect::<Vec<_>>(),
+            [
+                ((0.25, 0.75), 0.1),
+                ((0.25, 0.75), 0.2),
+                ((0.1, 0.9), 0.1),
+                ((0.1, 0.9), 0.2)
+            ]
+        );
+        // Parameters that aren't varied are from the base.
+        assert!(grid
+            .iter()
+            .all(|params| params.graph.min_component_sunks == 5
+                && params.graph.max_read_sunks.is_none()));
+    }
+
+    #[test]
+    fn test_supported_stats() {
+        // Overlapping intervals of c1 are merged into two intervals with a breakpoint between them.
+        let dfs_bed = [
+            df!("st" => [0i64, 50, 200], "end" => [100i64, 150, 300]).unwrap(),
+            df!("st" => [0i64], "end" => [1000i64]).unwrap(),
+        ];
+        assert_eq!(supported_stats(&dfs_bed).unwrap(), (1250, 3, 1));
+    }
+}
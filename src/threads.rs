@@ -0,0 +1,82 @@
+//! Coordinate rayon tasks with polars' thread pool.
+//!
+//! Polars runs each query on its own global pool. A query started from a worker of another rayon pool blocks that
+//! worker until polars' pool finishes it, so running `n` contigs concurrently on a separate pool keeps `n` extra
+//! threads alive and queues every contig's queries behind each other. Running the contigs on polars' pool instead lets
+//! nested queries run inline or be stolen by idle workers, so the CPUs are shared by both levels.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use polars::POOL;
+use rayon::prelude::*;
+
+/// Number of threads in polars' pool. Set with `POLARS_MAX_THREADS` before the first query.
+pub fn polars_threads() -> usize {
+    POOL.current_num_threads()
+}
+
+/// Call `f` on each item on polars' pool with at most `concurrency` items in progress.
+///
+/// Items are pulled in order by `concurrency` tasks, so order `items` largest first to balance them. No worker ever
+/// blocks waiting for a slot, so nested polars queries can't deadlock.
+///
+/// # Arguments
+/// * `items`
+///     * Items to process.
+/// * `concurrency`
+///     * Maximum items in progress. ex. from a memory budget. Clamped to `[1, polars_threads()]`.
+/// * `f`
+///     * Function called on each item. May run polars queries or rayon parallel iterators.
+pub fn for_each_bounded<T: Sync>(items: &[T], concurrency: usize, f: impl Fn(&T) + Sync) {
+    let next = AtomicUsize::new(0);
+    let concurrency = concurrency.clamp(1, polars_threads().max(1));
+    POOL.install(|| {
+        (0..concurrency).into_par_iter().for_each(|_| {
+            while let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) {
+                f(item);
+            }
+        })
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use polars::prelude::*;
+
+    use super::for_each_bounded;
+
+    #[test]
+    fn test_for_each_bounded() {
+        let items: Vec<i64> = (0..32).collect();
+        let (in_progress, max_in_progress) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let sums = Mutex::new(vec![]);
+        for_each_bounded(&items, 2, |item| {
+            let n = in_progress.fetch_add(1, Ordering::SeqCst) + 1;
+            max_in_progress.fetch_max(n, Ordering::SeqCst);
+            // Nested polars query.
+            let df = df!("x" => (0..1_000).map(|x| x * item).collect::<Vec<i64>>()).unwrap();
+            let sum = df
+                .lazy()
+                .select([col("x").sum()])
+                .collect()
+                .unwrap()
+                .column("x")
+                .unwrap()
+                .i64()
+                .unwrap()
+                .get(0)
+                .unwrap();
+            sums.lock().unwrap().push(sum);
+            in_progress.fetch_sub(1, Ordering::SeqCst);
+        });
+        let mut sums = sums.into_inner().unwrap();
+        sums.sort_unstable();
+        assert_eq!(sums, items.iter().map(|i| i * 499_500).collect::<Vec<_>>());
+        assert!(max_in_progress.load(Ordering::SeqCst) <= 2);
+    }
+}
@@ -0,0 +1,230 @@
+//! Supported bases as a function of read coverage.
+//!
+//! Assigned reads are downsampled to fractions of the full set and the filter and graph stages are rerun on each
+//! subset. Contigs whose supported bases are still rising at full coverage may have unsupported gaps closed by more
+//! sequencing while those that plateau likely won't.
+
+use std::collections::HashMap;
+
+use polars::prelude::*;
+use rayon::prelude::*;
+
+use crate::{
+    cancel::CancelToken,
+    error::{GavisunkError, Result},
+    filter_bad_sunks::{filter_bad_sunks, merge_extra_bad_sunks},
+    map_kmers::get_good_read_sunks,
+    run_state::RunState,
+    sunk_graph::{create_sunk_graph, supported_bp, GraphParams},
+};
+
+/// Default fractions of reads kept.
+pub const DEFAULT_FRACTIONS: [f64; 5] = [0.1, 0.25, 0.5, 0.75, 1.0];
+
+/// Parameters of a coverage titration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitrationParams {
+    /// Fractions of assigned reads kept.
+    pub fractions: Vec<f64>,
+    /// Seed of read sampling.
+    pub seed: u64,
+    pub graph: GraphParams,
+}
+
+/// Parse a fraction of reads kept in `(0, 1]`. ex. `0.25`
+pub fn parse_fraction(frac: &str) -> Result<f64> {
+    match frac.trim().parse::<f64>() {
+        Ok(value) if value > 0.0 && value <= 1.0 => Ok(value),
+        _ => Err(GavisunkError::InvalidFraction(frac.to_owned())),
+    }
+}
+
+/// SplitMix64 finalizer. See <https://prng.di.unimi.it/splitmix64.c>.
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Whether a read is kept when downsampling to `frac` of reads.
+///
+/// Reads are kept by hash so a read kept at one fraction is kept at all larger fractions with the same `seed`. The
+/// hash is fixed, the CRC-32 of the read name mixed with the seed, so samples are the same across builds and platforms.
+fn is_sampled(read: &str, seed: u64, frac: f64) -> bool {
+    let hash = splitmix64(splitmix64(seed) ^ u64::from(crc32fast::hash(read.as_bytes())));
+    frac >= 1.0 || (hash as f64 / u64::MAX as f64) < frac
+}
+
+/// Keep rows of `df` whose `read` is sampled.
+fn downsample(df: &DataFrame, seed: u64, frac: f64) -> Result<DataFrame> {
+    let mask: BooleanChunked = df
+        .column("read")?
+        .str()?
+        .iter()
+        .map(|read| read.is_some_and(|read| is_sampled(read, seed, frac)))
+        .collect();
+    Ok(df.filter(&mask)?)
+}
+
+/// Downsample assigned reads and rerun the filter and graph stages at each fraction.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * Read SUNKs from [`crate::map_kmers::map_sunks_to_reads`].
+/// * `df_best_reads_asm`
+///     * Read assignments from [`crate::assign_read_ctg::assign_read_to_ctg_w_ort`].
+/// * `df_read_lens`
+///     * [`DataFrame`] of read lengths with columns `[read, read_length]`. See [`crate::map_kmers::read_lengths_df`].
+/// * `asm_lens`
+///     * Contig lengths.
+/// * `df_extra_bad_sunks`
+///     * Known bad SUNKs merged with computed bad SUNKs. See [`crate::filter_bad_sunks::load_extra_bad_sunks`].
+/// * `params`
+///     * Fractions, seed, and graph stage parameters.
+/// * `state`
+///     * Run join losses and skipped reads are recorded in.
+/// * `cancel`
+///     * Token checked between fractions and contigs.
+///
+/// # Returns
+/// * [`DataFrame`] with one row per fraction and contig and columns `[fraction, ctg, coverage, supported_bp, supported_frac]`
+///     * `coverage` is the bases of sampled reads assigned to the contig over its length.
+#[allow(clippy::too_many_arguments)]
+pub fn titrate(
+    df_read_sunks: &DataFrame,
+    df_best_reads_asm: &DataFrame,
+    df_read_lens: &DataFrame,
+    asm_lens: &HashMap<String, u64>,
+    df_extra_bad_sunks: Option<&DataFrame>,
+    params: &TitrationParams,
+    state: &RunState,
+    cancel: &CancelToken,
+) -> Result<DataFrame> {
+    let (mut fracs, mut ctgs, mut coverages) = (vec![], vec![], vec![]);
+    let (mut supported_bps, mut supported_fracs) = (vec![], vec![]);
+    if let Some(frac) = params
+        .fractions
+        .iter()
+        .find(|frac| !(**frac > 0.0 && **frac <= 1.0))
+    {
+        return Err(GavisunkError::InvalidFraction(frac.to_string()));
+    }
+    let mut ctg_names: Vec<&String> = asm_lens.keys().collect();
+    ctg_names.sort();
+
+    for frac in &params.fractions {
+        cancel.check()?;
+        let df_best_sampled = downsample(df_best_reads_asm, params.seed, *frac)?;
+        let df_read_sunks_sampled = downsample(df_read_sunks, params.seed, *frac)?;
+        log::info!(
+            "Titrating {} of {} assigned reads ({frac}).",
+            df_best_sampled.height(),
+            df_best_reads_asm.height()
+        );
+
+        let df_read_bases = df_best_sampled
+            .select(["read", "ctg"])?
+            .lazy()
+            .join(
+                df_read_lens.clone().lazy(),
+                [col("read")],
+                [col("read")],
+                JoinArgs::new(JoinType::Inner),
+            )
+            .group_by([col("ctg")])
+            .agg([col("read_length").cast(DataType::UInt64).sum()])
+            .collect()?;
+        let ctg_read_bases: HashMap<&str, u64> = df_read_bases
+            .column("ctg")?
+            .str()?
+            .iter()
+            .zip(df_read_bases.column("read_length")?.u64()?.iter())
+            .flat_map(|(ctg, bases)| Some((ctg?, bases?)))
+            .collect();
+
+        let df_good_sunks_reads =
+            get_good_read_sunks(&df_read_sunks_sampled, &df_best_sampled, state)?;
+        let mut df_bad_sunks = filter_bad_sunks(&df_good_sunks_reads)?;
+        if let Some(df_extra) = df_extra_bad_sunks {
+            df_bad_sunks = merge_extra_bad_sunks(&df_bad_sunks, &df_good_sunks_reads, df_extra)?;
+        }
+        let ctg_supported_bp: HashMap<String, u64> = df_read_sunks_sampled
+            .partition_by(["ctg"], true)?
+            .par_iter()
+            .map(|df_ctg| {
+                cancel.check()?;
+                let Some(ctg) = df_ctg.column("ctg")?.str()?.first() else {
+                    return Ok(None);
+                };
+                let (_, df_bed) = create_sunk_graph(
+                    ctg,
+                    df_ctg,
+                    df_read_lens,
+                    asm_lens,
+                    &df_bad_sunks,
+                    &params.graph,
+                    state,
+                    cancel,
+                )?;
+                Ok(Some((ctg.to_owned(), supported_bp(&df_bed)?)))
+            })
+            .collect::<Result<Vec<Option<(String, u64)>>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        for ctg in &ctg_names {
+            let ctg_len = asm_lens[*ctg];
+            let bp = ctg_supported_bp.get(*ctg).copied().unwrap_or(0);
+            let read_bases = ctg_read_bases.get(ctg.as_str()).copied().unwrap_or(0);
+            fracs.push(*frac);
+            ctgs.push(ctg.as_str());
+            coverages.push((ctg_len > 0).then(|| read_bases as f64 / ctg_len as f64));
+            supported_bps.push(bp);
+            supported_fracs.push((ctg_len > 0).then(|| bp as f64 / ctg_len as f64));
+        }
+    }
+
+    Ok(DataFrame::new(vec![
+        Column::new("fraction".into(), fracs),
+        Column::new("ctg".into(), ctgs),
+        Column::new("coverage".into(), coverages),
+        Column::new("supported_bp".into(), supported_bps),
+        Column::new("supported_frac".into(), supported_fracs),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_sampled, parse_fraction};
+
+    #[test]
+    fn test_sampling_is_nested() {
+        let reads: Vec<String> = (0..1000).map(|i| format!("read_{i}")).collect();
+        let sampled = |frac: f64| -> Vec<&String> {
+            reads
+                .iter()
+                .filter(|read| is_sampled(read, 7, frac))
+                .collect()
+        };
+        let (quarter, half) = (sampled(0.25), sampled(0.5));
+        assert!(quarter.iter().all(|read| half.contains(read)));
+        assert!((150..350).contains(&quarter.len()));
+        assert_eq!(sampled(1.0).len(), reads.len());
+        // The hash is fixed so samples don't change between builds.
+        assert_eq!(
+            sampled(0.1)[..5],
+            ["read_14", "read_23", "read_32", "read_37", "read_43"]
+        );
+    }
+
+    #[test]
+    fn test_parse_fraction() {
+        assert_eq!(parse_fraction("0.5").unwrap(), 0.5);
+        assert_eq!(parse_fraction("1").unwrap(), 1.0);
+        for frac in ["0", "-0.1", "1.5", "NaN", "half"] {
+            assert!(parse_fraction(frac).is_err());
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use itertools::Itertools;
+use polars::prelude::*;
+
+use crate::{
+    error::{GavisunkError, Result},
+    naming::normalize_name,
+};
+
+/// Default minimum consecutive SUNKs on each contig of a jump for a translocation candidate.
+pub const DEFAULT_MIN_TRANSLOCATION_SUNKS: usize = 3;
+/// Default minimum reads jumping between the same contig positions for a translocation candidate.
+pub const DEFAULT_MIN_TRANSLOCATION_READS: usize = 2;
+/// Default minimum consecutive SUNKs on each haplotype's contig of a jump for a phase switch candidate.
+pub const DEFAULT_MIN_PHASE_SWITCH_SUNKS: usize = 3;
+
+/// Jump of a read's SUNKs from the end of a run on one contig to the start of a run on another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Jump<'a> {
+    ctg_a: &'a str,
+    ctg_b: &'a str,
+    pos_a: i64,
+    pos_b: i64,
+    read: &'a str,
+}
+
+/// Jumps between runs of at least `min_run_sunks` consecutive SUNKs on different contigs.
+///
+/// # Arguments
+/// * `anchors`
+///     * `(ctg, cpos)` of a read's SUNKs ordered by read position.
+fn read_ctg_jumps<'a>(
+    read: &'a str,
+    anchors: &[(&'a str, i64)],
+    min_run_sunks: usize,
+) -> Vec<Jump<'a>> {
+    let runs = anchors
+        .iter()
+        .chunk_by(|(ctg, _)| *ctg)
+        .into_iter()
+        .map(|(ctg, run)| {
+            let run: Vec<i64> = run.map(|(_, cpos)| *cpos).collect();
+            (ctg, run.len(), run[0], run[run.len() - 1])
+        })
+        // Drop short runs from spurious hits and then join runs on the same contig.
+        .filter(|(_, n, _, _)| *n >= min_run_sunks)
+        .coalesce(|a, b| {
+            if a.0 == b.0 {
+                Ok((a.0, a.1 + b.1, a.2, b.3))
+            } else {
+                Err((a, b))
+            }
+        })
+        .collect_vec();
+    runs.into_iter()
+        .tuple_windows()
+        .map(|((ctg_1, _, _, last_1), (ctg_2, _, first_2, _))| {
+            // Order contig pair so jumps in either direction are clustered together.
+            if ctg_1 <= ctg_2 {
+                Jump {
+                    ctg_a: ctg_1,
+                    ctg_b: ctg_2,
+                    pos_a: last_1,
+                    pos_b: first_2,
+                    read,
+                }
+            } else {
+                Jump {
+                    ctg_a: ctg_2,
+                    ctg_b: ctg_1,
+                    pos_a: first_2,
+                    pos_b: last_1,
+                    read,
+                }
+            }
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<i64>) -> i64 {
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+/// Read SUNKs ordered by read and read position with [`i64`] positions.
+fn sorted_anchors(df_read_sunks: &DataFrame) -> Result<DataFrame> {
+    Ok(df_read_sunks
+        .select(["read", "rpos", "ctg", "cpos"])?
+        .lazy()
+        .with_columns([
+            col("rpos").cast(DataType::Int64),
+            col("cpos").cast(DataType::Int64),
+        ])
+        .sort(["read", "rpos"], Default::default())
+        .collect()?)
+}
+
+/// Cluster jumps of reads between the same contig pair with both positions within `slop`.
+///
+/// # Arguments
+/// * `df`
+///     * [`DataFrame`] from [`sorted_anchors`].
+fn cluster_jumps(df: &DataFrame, min_run_sunks: usize, slop: i64) -> Result<Vec<Vec<Jump<'_>>>> {
+    let rows = df
+        .column("read")?
+        .str()?
+        .iter()
+        .zip(df.column("ctg")?.str()?.iter())
+        .zip(df.column("cpos")?.i64()?.iter())
+        .flat_map(|((read, ctg), cpos)| Some((read?, ctg?, cpos?)));
+    let mut jumps = vec![];
+    for (read, anchors) in &rows.chunk_by(|(read, _, _)| *read) {
+        let anchors: Vec<(&str, i64)> = anchors.map(|(_, ctg, cpos)| (ctg, cpos)).collect();
+        jumps.extend(read_ctg_jumps(read, &anchors, min_run_sunks));
+    }
+    jumps.sort_unstable();
+
+    let mut clusters: Vec<Vec<Jump>> = vec![];
+    for jump in jumps {
+        let cluster = clusters
+            .iter_mut()
+            .rev()
+            .take_while(|cl| {
+                let first = cl[0];
+                (first.ctg_a, first.ctg_b) == (jump.ctg_a, jump.ctg_b)
+                    && jump.pos_a - first.pos_a <= slop
+            })
+            .find(|cl| (jump.pos_b - cl[0].pos_b).abs() <= slop);
+        match cluster {
+            Some(cl) => cl.push(jump),
+            None => clusters.push(vec![jump]),
+        }
+    }
+    Ok(clusters)
+}
+
+/// Sorted, distinct reads of a cluster of jumps.
+fn cluster_reads<'a>(cl: &[Jump<'a>]) -> Vec<&'a str> {
+    cl.iter()
+        .map(|jump| jump.read)
+        .unique()
+        .sorted()
+        .collect_vec()
+}
+
+/// Detect putative false or missed joins from reads whose SUNKs jump between contigs.
+///
+/// Jumps from contig A position X to contig B position Y are clustered across reads when both positions are within `slop`.
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, ...]`
+/// * `min_run_sunks`
+///     * Minimum consecutive SUNKs on a contig for a run to be considered.
+/// * `min_reads`
+///     * Minimum supporting reads to report a candidate.
+/// * `slop`
+///     * Distance within which jump positions are clustered.
+///
+/// # Returns
+/// * [`DataFrame`] of candidates with columns `[ctg_a, pos_a, ctg_b, pos_b, n_reads, reads]`
+///     * `pos_a` and `pos_b` are median positions of the jumps. `reads` is a comma-separated list of supporting reads.
+pub fn detect_translocations(
+    df_read_sunks: &DataFrame,
+    min_run_sunks: usize,
+    min_reads: usize,
+    slop: i64,
+) -> Result<DataFrame> {
+    let df = sorted_anchors(df_read_sunks)?;
+    let (mut ctgs_a, mut pos_a, mut ctgs_b, mut pos_b, mut n_reads, mut reads) =
+        (vec![], vec![], vec![], vec![], vec![], vec![]);
+    for cl in cluster_jumps(&df, min_run_sunks, slop)? {
+        let cl_reads = cluster_reads(&cl);
+        if cl_reads.len() < min_reads {
+            continue;
+        }
+        ctgs_a.push(cl[0].ctg_a);
+        ctgs_b.push(cl[0].ctg_b);
+        pos_a.push(median(cl.iter().map(|jump| jump.pos_a).collect()));
+        pos_b.push(median(cl.iter().map(|jump| jump.pos_b).collect()));
+        n_reads.push(cl_reads.len() as u64);
+        reads.push(cl_reads.join(","));
+    }
+    log::info!("Found {} candidate translocations.", ctgs_a.len());
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg_a".into(), ctgs_a),
+        Column::new("pos_a".into(), pos_a),
+        Column::new("ctg_b".into(), ctgs_b),
+        Column::new("pos_b".into(), pos_b),
+        Column::new("n_reads".into(), n_reads),
+        Column::new("reads".into(), reads),
+    ])?)
+}
+
+/// Load the haplotype of each contig of a dual assembly, ex. `hap1` or `hap2`.
+///
+/// Loaded from a TSV of `ctg` and `haplotype` without a header. Contig names are normalized with [`normalize_name`].
+pub fn load_haplotypes(path: impl AsRef<Path>) -> Result<HashMap<String, String>> {
+    let mut haplotypes = HashMap::new();
+    let reader = BufReader::new(File::open(path)?);
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |reason: &str| {
+            GavisunkError::InvalidHaplotypeMap(format!("line {}: {reason}", line_num + 1))
+        };
+        let (ctg, hap) = line
+            .split_once('\t')
+            .ok_or_else(|| err("expected a contig and haplotype separated by a tab"))?;
+        let (ctg, hap) = (normalize_name(ctg.trim()), hap.trim());
+        if ctg.is_empty() || hap.is_empty() {
+            return Err(err("empty contig or haplotype"));
+        }
+        if haplotypes
+            .insert(ctg.to_owned(), hap.to_owned())
+            .is_some_and(|prev| prev != hap)
+        {
+            return Err(err(&format!("{ctg:?} has more than one haplotype")));
+        }
+    }
+    Ok(haplotypes)
+}
+
+/// Detect candidate phase switches of a dual assembly from reads whose SUNKs jump between haplotypes mid-read.
+///
+/// A read from one haplotype spanning a phase switch follows one haplotype's contig up to the switch and the other's
+/// after it. Jumps are clustered as in [`detect_translocations`] and only jumps between contigs of different
+/// haplotypes are kept. The read bridging both positions is what marks them as syntenic, so jumps within a haplotype
+/// are left to [`detect_translocations`].
+///
+/// # Arguments
+/// * `df_read_sunks`
+///     * [`DataFrame`] of read SUNKs with columns `[read, rpos, ctg, cpos, ...]`
+/// * `haplotypes`
+///     * Haplotype of each contig. See [`load_haplotypes`]. Contigs without one are ignored.
+/// * `min_run_sunks`
+///     * Minimum consecutive SUNKs on a contig for a run to be considered.
+/// * `min_reads`
+///     * Minimum supporting reads to report a candidate.
+/// * `slop`
+///     * Distance within which jump positions are clustered.
+///
+/// # Returns
+/// * [`DataFrame`] BED of candidates on each haplotype with columns `[ctg, st, end, haplotype, other_ctg, other_pos, n_reads, reads]`
+///     * `st` and `end` span the jump positions on `ctg`. `other_pos` is the median jump position on `other_ctg`.
+///     * Each candidate has a row on both haplotypes.
+pub fn detect_phase_switches(
+    df_read_sunks: &DataFrame,
+    haplotypes: &HashMap<String, String>,
+    min_run_sunks: usize,
+    min_reads: usize,
+    slop: i64,
+) -> Result<DataFrame> {
+    let df = sorted_anchors(df_read_sunks)?;
+    let mut rows = vec![];
+    for cl in cluster_jumps(&df, min_run_sunks, slop)? {
+        let (ctg_a, ctg_b) = (cl[0].ctg_a, cl[0].ctg_b);
+        let (Some(hap_a), Some(hap_b)) = (haplotypes.get(ctg_a), haplotypes.get(ctg_b)) else {
+            continue;
+        };
+        let cl_reads = cluster_reads(&cl);
+        if hap_a == hap_b || cl_reads.len() < min_reads {
+            continue;
+        }
+        let (pos_a, pos_b): (Vec<i64>, Vec<i64>) =
+            cl.iter().map(|jump| (jump.pos_a, jump.pos_b)).unzip();
+        let reads = cl_reads.join(",");
+        for (ctg, hap, pos, other_ctg, other_pos) in [
+            (ctg_a, hap_a, &pos_a, ctg_b, &pos_b),
+            (ctg_b, hap_b, &pos_b, ctg_a, &pos_a),
+        ] {
+            // SUNK positions are 1-based.
+            let (st, end) = pos
+                .iter()
+                .minmax()
+                .into_option()
+                .map_or((0, 0), |(min, max)| (min - 1, *max));
+            rows.push((
+                ctg,
+                st,
+                end,
+                hap.as_str(),
+                other_ctg,
+                median(other_pos.clone()),
+                cl_reads.len() as u64,
+                reads.clone(),
+            ));
+        }
+    }
+    rows.sort_unstable();
+    log::info!("Found {} candidate phase switches.", rows.len() / 2);
+
+    Ok(DataFrame::new(vec![
+        Column::new("ctg".into(), rows.iter().map(|row| row.0).collect_vec()),
+        Column::new("st".into(), rows.iter().map(|row| row.1).collect_vec()),
+        Column::new("end".into(), rows.iter().map(|row| row.2).collect_vec()),
+        Column::new(
+            "haplotype".into(),
+            rows.iter().map(|row| row.3).collect_vec(),
+        ),
+        Column::new(
+            "other_ctg".into(),
+            rows.iter().map(|row| row.4).collect_vec(),
+        ),
+        Column::new(
+            "other_pos".into(),
+            rows.iter().map(|row| row.5).collect_vec(),
+        ),
+        Column::new("n_reads".into(), rows.iter().map(|row| row.6).collect_vec()),
+        Column::new(
+            "reads".into(),
+            rows.iter().map(|row| row.7.as_str()).collect_vec(),
+        ),
+    ])?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use polars::prelude::*;
+
+    use super::detect_phase_switches;
+
+    #[test]
+    fn test_detect_phase_switches() {
+        // r1 and r2 switch from h1_ctg to h2_ctg. r3 jumps between contigs of the same haplotype.
+        let df_read_sunks = df!(
+            "read" => ["r1", "r1", "r1", "r1", "r2", "r2", "r2", "r2", "r3", "r3", "r3", "r3"],
+            "rpos" => [1i64, 100, 200, 300, 1, 100, 200, 300, 1, 100, 200, 300],
+            "ctg" => ["h1_ctg", "h1_ctg", "h2_ctg", "h2_ctg", "h1_ctg", "h1_ctg", "h2_ctg", "h2_ctg", "h1_ctg", "h1_ctg", "h1_other", "h1_other"],
+            "cpos" => [901i64, 1000, 2101, 2200, 905, 1004, 2105, 2204, 901, 1000, 5001, 5100],
+        )
+        .unwrap();
+        let haplotypes = HashMap::from([
+            ("h1_ctg".to_owned(), "hap1".to_owned()),
+            ("h1_other".to_owned(), "hap1".to_owned()),
+            ("h2_ctg".to_owned(), "hap2".to_owned()),
+        ]);
+        let df = detect_phase_switches(&df_read_sunks, &haplotypes, 2, 2, 100).unwrap();
+        assert_eq!(
+            df.select(["ctg", "st", "end", "haplotype", "other_pos", "n_reads"])
+                .unwrap(),
+            df!(
+                "ctg" => ["h1_ctg", "h2_ctg"],
+                "st" => [999i64, 2100],
+                "end" => [1004i64, 2105],
+                "haplotype" => ["hap1", "hap2"],
+                "other_pos" => [2105i64, 1004],
+                "n_reads" => [2u64, 2],
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            df.column("reads").unwrap().str().unwrap().get(0),
+            Some("r1,r2")
+        );
+    }
+}
@@ -0,0 +1,405 @@
+//! Output formats selected by file extension or `--output-formats`.
+//!
+//! Stages write final outputs with [`OutputWriters::write`], which picks a registered [`OutputWriter`] by the
+//! extension of the output path. Adding a format only needs a writer registered with [`WriterRegistry::register`].
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use polars::prelude::*;
+
+use crate::{
+    error::{GavisunkError, Result},
+    io::{sort_rows, write_atomic, write_tsv_rows_to, write_tsv_to},
+};
+
+/// Mandatory PAF columns in order. See <https://github.com/lh3/miniasm/blob/master/PAF.md>.
+pub const PAF_COLUMNS: [&str; 12] = [
+    "qname", "qlen", "qst", "qend", "strand", "tname", "tlen", "tst", "tend", "matches", "aln_len",
+    "mapq",
+];
+
+/// Writer of a [`DataFrame`] in a file format.
+pub trait OutputWriter: Send + Sync {
+    /// Name selecting the writer with `--output-formats`. ex. `parquet`
+    fn name(&self) -> &str;
+
+    /// Extensions, without a leading `.`, of output paths written in this format. ex. `["bed", "bedgraph"]`
+    fn extensions(&self) -> &[&str];
+
+    /// Write rows after `comments`, `#` comment lines, if the format has comments.
+    ///
+    /// Rows are written sorted by all columns so output is deterministic. `df` is left in its order. See
+    /// [`crate::io::sort_rows`].
+    fn write(&self, df: &mut DataFrame, writer: &mut dyn Write, comments: &str) -> Result<()>;
+}
+
+/// TSV with header. Also the fallback for unregistered extensions.
+#[derive(Debug, Clone, Copy)]
+pub struct TsvWriter;
+
+impl OutputWriter for TsvWriter {
+    fn name(&self) -> &str {
+        "tsv"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tsv"]
+    }
+
+    fn write(&self, df: &mut DataFrame, writer: &mut dyn Write, comments: &str) -> Result<()> {
+        writer.write_all(comments.as_bytes())?;
+        write_tsv_to(df, writer)
+    }
+}
+
+/// BED whose first three columns are the contig, 0-based start, and end.
+///
+/// Column names are the last `#` comment line rather than a header so BED tools read every other line as an interval.
+#[derive(Debug, Clone, Copy)]
+pub struct BedWriter;
+
+impl OutputWriter for BedWriter {
+    fn name(&self) -> &str {
+        "bed"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bed", "bedgraph", "bedpe"]
+    }
+
+    fn write(&self, df: &mut DataFrame, writer: &mut dyn Write, comments: &str) -> Result<()> {
+        let cols = df.get_columns();
+        let is_bed = cols.len() >= 3
+            && cols[0].dtype() == &DataType::String
+            && cols[1].dtype().is_integer()
+            && cols[2].dtype().is_integer();
+        if !is_bed {
+            return Err(GavisunkError::MissingColumns(
+                "contig, start, and end as the first three columns of a BED output".to_owned(),
+            ));
+        }
+        writer.write_all(comments.as_bytes())?;
+        let names: Vec<&str> = df
+            .get_column_names()
+            .iter()
+            .map(|name| name.as_str())
+            .collect();
+        writeln!(writer, "#{}", names.join("\t"))?;
+        write_tsv_rows_to(df, writer)
+    }
+}
+
+/// PAF without header or comments. Columns other than [`PAF_COLUMNS`] are written as `name:type:value` tags.
+#[derive(Debug, Clone, Copy)]
+pub struct PafWriter;
+
+impl OutputWriter for PafWriter {
+    fn name(&self) -> &str {
+        "paf"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["paf"]
+    }
+
+    fn write(&self, df: &mut DataFrame, writer: &mut dyn Write, _comments: &str) -> Result<()> {
+        let missing: Vec<&str> = PAF_COLUMNS
+            .into_iter()
+            .filter(|name| df.column(name).is_err())
+            .collect();
+        if !missing.is_empty() {
+            return Err(GavisunkError::MissingColumns(missing.join(", ")));
+        }
+        let df = sort_rows(df)?;
+        let tags: Vec<(&str, char)> = df
+            .get_columns()
+            .iter()
+            .filter(|col| !PAF_COLUMNS.contains(&col.name().as_str()))
+            .map(|col| {
+                let tag_type = match col.dtype() {
+                    dtype if dtype.is_integer() => 'i',
+                    dtype if dtype.is_float() => 'f',
+                    _ => 'Z',
+                };
+                (col.name().as_str(), tag_type)
+            })
+            .collect();
+        let values = PAF_COLUMNS
+            .iter()
+            .copied()
+            .chain(tags.iter().map(|(name, _)| *name))
+            .map(|name| Ok(df.column(name)?.cast(&DataType::String)?))
+            .collect::<Result<Vec<Column>>>()?;
+        let values = values
+            .iter()
+            .map(|col| Ok(col.str()?))
+            .collect::<Result<Vec<&StringChunked>>>()?;
+        for row in 0..df.height() {
+            let mut line: Vec<String> = values[..PAF_COLUMNS.len()]
+                .iter()
+                .map(|col| col.get(row).unwrap_or("*").to_owned())
+                .collect();
+            for ((name, tag_type), col) in tags.iter().zip(&values[PAF_COLUMNS.len()..]) {
+                if let Some(value) = col.get(row) {
+                    line.push(format!("{name}:{tag_type}:{value}"));
+                }
+            }
+            writeln!(writer, "{}", line.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+/// JSON array of row objects or, with `lines`, one row object per line. Comments are omitted.
+#[derive(Debug, Clone, Copy)]
+pub struct JsonWriter {
+    pub lines: bool,
+}
+
+impl OutputWriter for JsonWriter {
+    fn name(&self) -> &str {
+        if self.lines {
+            "ndjson"
+        } else {
+            "json"
+        }
+    }
+
+    fn extensions(&self) -> &[&str] {
+        if self.lines {
+            &["ndjson", "jsonl"]
+        } else {
+            &["json"]
+        }
+    }
+
+    fn write(&self, df: &mut DataFrame, writer: &mut dyn Write, _comments: &str) -> Result<()> {
+        let format = if self.lines {
+            JsonFormat::JsonLines
+        } else {
+            JsonFormat::Json
+        };
+        polars::prelude::JsonWriter::new(writer)
+            .with_json_format(format)
+            .finish(&mut sort_rows(df)?)?;
+        Ok(())
+    }
+}
+
+/// Parquet. Comments are omitted.
+#[cfg(feature = "parquet")]
+#[derive(Debug, Clone, Copy)]
+pub struct ParquetWriter;
+
+#[cfg(feature = "parquet")]
+impl OutputWriter for ParquetWriter {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["parquet"]
+    }
+
+    fn write(&self, df: &mut DataFrame, writer: &mut dyn Write, _comments: &str) -> Result<()> {
+        polars::prelude::ParquetWriter::new(writer).finish(&mut sort_rows(df)?)?;
+        Ok(())
+    }
+}
+
+/// Registered output writers.
+pub struct WriterRegistry {
+    writers: Vec<Box<dyn OutputWriter>>,
+}
+
+impl Default for WriterRegistry {
+    fn default() -> Self {
+        let mut registry = Self { writers: vec![] };
+        registry.register(Box::new(TsvWriter));
+        registry.register(Box::new(BedWriter));
+        registry.register(Box::new(PafWriter));
+        registry.register(Box::new(JsonWriter { lines: false }));
+        registry.register(Box::new(JsonWriter { lines: true }));
+        #[cfg(feature = "parquet")]
+        registry.register(Box::new(ParquetWriter));
+        registry
+    }
+}
+
+impl WriterRegistry {
+    /// Register a writer. It takes precedence over earlier writers with the same name or extensions.
+    pub fn register(&mut self, writer: Box<dyn OutputWriter>) {
+        self.writers.insert(0, writer);
+    }
+
+    /// Names of registered writers, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.writers.iter().map(|writer| writer.name()).collect();
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&dyn OutputWriter> {
+        self.writers
+            .iter()
+            .find(|writer| writer.name() == name)
+            .map(|writer| &**writer)
+    }
+
+    /// Writer of a path by its extension, ignoring case. [`TsvWriter`] if no writer has the extension.
+    pub fn for_path(&self, path: impl AsRef<Path>) -> &dyn OutputWriter {
+        let ext = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        self.writers
+            .iter()
+            .find(|writer| writer.extensions().contains(&ext.as_str()))
+            .map_or(&TsvWriter as &dyn OutputWriter, |writer| &**writer)
+    }
+}
+
+/// Writers of a run's final outputs.
+#[derive(Default)]
+pub struct OutputWriters {
+    registry: WriterRegistry,
+    /// Names of writers of additional copies of each output.
+    extra_formats: Vec<String>,
+}
+
+impl OutputWriters {
+    /// Writers of `registry`, also writing each output with the writers named in `extra_formats` as
+    /// `{path}.{extension}`. ex. `supported.bed.parquet`
+    ///
+    /// # Returns
+    /// * [`GavisunkError::UnknownOutputFormat`] if a name isn't registered.
+    pub fn new(registry: WriterRegistry, extra_formats: &[String]) -> Result<Self> {
+        for name in extra_formats {
+            if registry.by_name(name).is_none() {
+                return Err(GavisunkError::UnknownOutputFormat {
+                    name: name.clone(),
+                    available: registry.names().join(", "),
+                });
+            }
+        }
+        Ok(Self {
+            registry,
+            extra_formats: extra_formats.to_vec(),
+        })
+    }
+
+    /// Write a [`DataFrame`] atomically in the format of its extension, and any extra formats.
+    ///
+    /// # Arguments
+    /// * `df`
+    ///     * Output. Rows are sorted by all columns.
+    /// * `path`
+    ///     * Output path. See [`WriterRegistry::for_path`].
+    /// * `comments`
+    ///     * `#` comment lines written by formats with comments. See
+    ///       [`crate::parameters::RunMetadata::comment_header`].
+    pub fn write(&self, df: &mut DataFrame, path: impl AsRef<Path>, comments: &str) -> Result<()> {
+        let path = path.as_ref();
+        let writer = self.registry.for_path(path);
+        write_atomic(path, |file| writer.write(df, file, comments))?;
+
+        for name in &self.extra_formats {
+            let Some(writer) = self.registry.by_name(name) else {
+                continue;
+            };
+            let mut extra_fname = path.as_os_str().to_owned();
+            extra_fname.push(format!(
+                ".{}",
+                writer.extensions().first().unwrap_or(&name.as_str())
+            ));
+            write_atomic(PathBuf::from(extra_fname), |file| {
+                writer.write(df, file, comments)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use polars::prelude::*;
+
+    use super::{OutputWriter, OutputWriters, PafWriter, TsvWriter, WriterRegistry};
+
+    #[test]
+    fn test_writer_for_path() {
+        let registry = WriterRegistry::default();
+        assert_eq!(registry.for_path("supported.bed").name(), "bed");
+        assert_eq!(registry.for_path("sunk_support.bedgraph").name(), "bed");
+        assert_eq!(registry.for_path("reads.JSONL").name(), "ndjson");
+        assert_eq!(registry.for_path("summary.tsv").name(), "tsv");
+        // Unregistered extensions are written as TSV.
+        assert_eq!(registry.for_path("contig_summary.txt").name(), "tsv");
+        assert!(registry.by_name("paf").is_some());
+        assert!(OutputWriters::new(registry, &["paf".to_owned()]).is_ok());
+        assert!(OutputWriters::new(WriterRegistry::default(), &["xlsx".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn test_writer_keeps_row_order() {
+        let mut df = df!("ctg" => ["c2", "c1"], "st" => [5i64, 0]).unwrap();
+        let df_before = df.clone();
+        let mut buf = vec![];
+        TsvWriter.write(&mut df, &mut buf, "").unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "ctg\tst\nc1\t0\nc2\t5\n");
+        assert!(df.equals(&df_before));
+    }
+
+    #[test]
+    fn test_bed_writer_checks_intervals() {
+        let registry = WriterRegistry::default();
+        let mut df = df!("ctg" => ["c1"], "st" => [0i64], "end" => [10i64]).unwrap();
+        let mut buf = vec![];
+        registry
+            .for_path("a.bed")
+            .write(&mut df, &mut buf, "# comment\n")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "# comment\n#ctg\tst\tend\nc1\t0\t10\n"
+        );
+        let mut df = df!("read" => ["r1"], "len" => [10i64]).unwrap();
+        assert!(registry
+            .for_path("a.bed")
+            .write(&mut df, &mut vec![], "")
+            .is_err());
+    }
+
+    #[test]
+    fn test_paf_writer() {
+        let mut df = df!(
+            "qname" => ["r1"],
+            "qlen" => [100i64],
+            "qst" => [0i64],
+            "qend" => [90i64],
+            "strand" => ["+"],
+            "tname" => ["c1"],
+            "tlen" => [1000i64],
+            "tst" => [10i64],
+            "tend" => [100i64],
+            "matches" => [85i64],
+            "aln_len" => [90i64],
+            "mapq" => [60i64],
+            "sunks" => [Some(4i64)],
+            "label" => [None::<&str>],
+        )
+        .unwrap();
+        let mut buf = vec![];
+        PafWriter.write(&mut df, &mut buf, "# comment\n").unwrap();
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "r1\t100\t0\t90\t+\tc1\t1000\t10\t100\t85\t90\t60\tsunks:i:4\n"
+        );
+    }
+}
@@ -0,0 +1,71 @@
+"""Regenerate the self-test dataset embedded by `rs-gavisunk self-test`.
+
+Contigs are copies of one repeat unit with sparse substitutions, so SUNKs only occur at substitutions like in a
+segmental duplication. `ctg_ok` is assembled correctly. `ctg_misjoin` is missing 5 kbp of its source sequence at
+20 kbp. Reads are error-free samples of the source sequences on both strands.
+
+    python3 test/self_test/generate.py
+"""
+
+import gzip
+import os
+import random
+
+SEED = 7
+UNIT_LEN = 1_000
+SUBS_PER_UNIT = 4
+READ_LENS = (12_000, 20_000)
+COVERAGE = 8
+DELETION = (20_000, 25_000)
+# Keep the misjoin's SUNKs off the junction so reads from either side don't share a SUNK group with it.
+JUNCTION_FLANK = 50
+
+rng = random.Random(SEED)
+unit = [rng.choice("ACGT") for _ in range(UNIT_LEN)]
+
+
+def source(length: int, fixed: tuple[int, int] | None = None) -> str:
+    seq = []
+    while len(seq) < length:
+        copy = unit.copy()
+        for pos in rng.sample(range(UNIT_LEN), SUBS_PER_UNIT):
+            gpos = len(seq) + pos
+            if fixed and fixed[0] - JUNCTION_FLANK <= gpos < fixed[0] or fixed and fixed[1] <= gpos < fixed[1] + JUNCTION_FLANK:
+                continue
+            copy[pos] = rng.choice([base for base in "ACGT" if base != copy[pos]])
+        seq.extend(copy)
+    return "".join(seq[:length])
+
+
+def revcomp(seq: str) -> str:
+    return seq[::-1].translate(str.maketrans("ACGT", "TGCA"))
+
+
+def fasta(records: list[tuple[str, str]]) -> bytes:
+    lines = []
+    for name, seq in records:
+        lines.append(f">{name}")
+        lines.extend(seq[i : i + 80] for i in range(0, len(seq), 80))
+    return ("\n".join(lines) + "\n").encode()
+
+
+src_ok = source(40_000)
+src_misjoin = source(45_000, DELETION)
+asm = [("ctg_ok", src_ok), ("ctg_misjoin", src_misjoin[: DELETION[0]] + src_misjoin[DELETION[1] :])]
+
+reads = []
+for name, src in [("ctg_ok", src_ok), ("ctg_misjoin", src_misjoin)]:
+    bases = 0
+    while bases < COVERAGE * len(src):
+        length = rng.randint(*READ_LENS)
+        st = rng.randint(0, len(src) - length)
+        seq = src[st : st + length]
+        if rng.random() < 0.5:
+            seq = revcomp(seq)
+        reads.append((f"read{len(reads):03}_{name}_{st}", seq))
+        bases += length
+
+outdir = os.path.dirname(os.path.abspath(__file__))
+for fname, records in [("asm.fa.gz", asm), ("reads.fa.gz", reads)]:
+    with open(os.path.join(outdir, fname), "wb") as fh:
+        fh.write(gzip.compress(fasta(records), mtime=0))
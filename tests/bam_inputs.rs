@@ -0,0 +1,125 @@
+use std::{collections::HashMap, io::Write};
+
+use noodles::bgzf;
+use polars::prelude::*;
+use rs_gavisunk::{
+    cancel::CancelToken,
+    error::GavisunkError,
+    io::{Fasta, SeqFormat},
+    map_kmers::{map_sunks_to_read_records, map_sunks_to_reads, DedupPolicy},
+    run_state::RunState,
+};
+
+mod common;
+
+/// Unmapped BAM record of a read with the given flag.
+fn unmapped_record(name: &str, seq: &[u8], flag: u16) -> Vec<u8> {
+    let code = |base: &u8| match base {
+        b'A' => 1,
+        b'C' => 2,
+        b'G' => 4,
+        b'T' => 8,
+        _ => 15,
+    };
+    let mut rec = vec![];
+    rec.extend((-1i32).to_le_bytes());
+    rec.extend((-1i32).to_le_bytes());
+    rec.push(name.len() as u8 + 1);
+    rec.push(255);
+    rec.extend(4680u16.to_le_bytes());
+    rec.extend(0u16.to_le_bytes());
+    rec.extend(flag.to_le_bytes());
+    rec.extend((seq.len() as i32).to_le_bytes());
+    rec.extend((-1i32).to_le_bytes());
+    rec.extend((-1i32).to_le_bytes());
+    rec.extend(0i32.to_le_bytes());
+    rec.extend(name.as_bytes());
+    rec.push(0);
+    rec.extend(
+        seq.chunks(2)
+            .map(|bases| code(&bases[0]) << 4 | bases.get(1).map_or(0, code)),
+    );
+    rec.extend(std::iter::repeat(255).take(seq.len()));
+    let mut block = (rec.len() as i32).to_le_bytes().to_vec();
+    block.extend(rec);
+    block
+}
+
+#[test]
+fn unaligned_bam_streamed() {
+    let dir = common::scratch_dir("calls_bam");
+    let path = dir.path().join("calls.bam");
+    let mut writer = bgzf::Writer::new(std::fs::File::create(&path).unwrap());
+    writer.write_all(b"BAM\x01").unwrap();
+    writer.write_all(&0i32.to_le_bytes()).unwrap();
+    writer.write_all(&0i32.to_le_bytes()).unwrap();
+    writer
+        .write_all(&unmapped_record("read1", b"ACGTTGCAAGGCTTAACCGG", 4))
+        .unwrap();
+    // Secondary records are skipped.
+    writer
+        .write_all(&unmapped_record("read1", b"ACGT", 4 | 256))
+        .unwrap();
+    writer
+        .write_all(&unmapped_record("read2", b"CCGGTTAAGCCTTGCAACGT", 4))
+        .unwrap();
+    // Records without a name are skipped as malformed.
+    writer
+        .write_all(&unmapped_record("*", b"GCAAGGCTTT", 4))
+        .unwrap();
+    writer.finish().unwrap();
+    assert_eq!(SeqFormat::detect(&path).unwrap(), SeqFormat::Bam);
+    assert!(Fasta::is_streamed(&path).unwrap());
+    let state = RunState::new();
+    assert!(matches!(
+        Fasta::indexable_path(&path, false, &state),
+        Err(GavisunkError::StreamedReads(_))
+    ));
+
+    let lens = Fasta::read_lengths(&path, false, &state).unwrap();
+    assert_eq!(
+        lens,
+        HashMap::from([("read1".to_owned(), 20), ("read2".to_owned(), 20)])
+    );
+    let mut bam = Fasta::open(&path, None, false, &state).unwrap();
+    let rec = bam.fetch("read2", 3, 6).unwrap();
+    assert_eq!(rec.sequence().as_ref(), b"GGTT");
+
+    // Mapped in a batch per read without an indexed copy.
+    let df_sunks = df!(
+        "ctg" => ["ctg", "ctg"],
+        "cpos" => [11i64, 31],
+        "kmer" => ["GCAAGGCT", "TTAACCGG"],
+        "strand" => ["+", "+"],
+        "group" => [1i64, 2],
+    )
+    .unwrap();
+    let cancel = CancelToken::new();
+    let df_streamed = map_sunks_to_read_records(
+        &path,
+        &df_sunks,
+        Some(20),
+        None,
+        DedupPolicy::First,
+        None,
+        None,
+        &state,
+        &cancel,
+    )
+    .unwrap();
+    let df_in_memory = map_sunks_to_reads(
+        bam,
+        &lens,
+        &df_sunks,
+        None,
+        None,
+        DedupPolicy::First,
+        None,
+        None,
+        &state,
+        &cancel,
+    )
+    .unwrap();
+    assert_eq!(df_streamed.height(), 4);
+    assert!(df_streamed.equals(&df_in_memory));
+}
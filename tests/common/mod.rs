@@ -0,0 +1,28 @@
+//! Helpers shared by integration tests.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use rs_gavisunk::io::ScratchDir;
+
+/// Directory in the temp dir unique to this test and process. Removed with its contents when dropped.
+pub fn scratch_dir(name: &str) -> ScratchDir {
+    ScratchDir::new_in(std::env::temp_dir(), &format!("rs_gavisunk_test_{name}")).unwrap()
+}
+
+/// Write `{name}.fa` to `dir`.
+pub fn write_fasta(dir: &Path, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+    let path = dir.join(format!("{name}.fa"));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// FASTA of a single record with lines of 60 bases.
+pub fn fasta_record(name: &str, seq: &[u8]) -> Vec<u8> {
+    let mut contents = format!(">{name}\n").into_bytes();
+    for line in seq.chunks(60) {
+        contents.extend_from_slice(line);
+        contents.push(b'\n');
+    }
+    contents
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use rs_gavisunk::{
+    error::GavisunkError,
+    io::{malformed_records, Fasta},
+    run_state::RunState,
+};
+
+mod common;
+
+/// Records with descriptions, tabs, and trailing whitespace in their headers.
+const MESSY_FASTA: &str =
+    ">ctg1 length=8 circular=false\nACGTACGT\n>ctg2\tdesc\nGGGGCCCC\n>ctg3  \nTTTTAAAA\n";
+
+fn expected_lens() -> HashMap<String, u64> {
+    HashMap::from([
+        ("ctg1".to_owned(), 8),
+        ("ctg2".to_owned(), 8),
+        ("ctg3".to_owned(), 8),
+    ])
+}
+
+fn assert_fetches(fa: &mut Fasta) {
+    for (name, seq) in [
+        ("ctg1", b"CGTA"),
+        ("ctg1 length=8 circular=false", b"CGTA"),
+        ("ctg2\tdesc", b"GGCC"),
+        ("ctg3 ", b"TTAA"),
+    ] {
+        let rec = fa.fetch(name, 3, 6).unwrap();
+        assert_eq!(rec.sequence().as_ref(), seq, "{name:?}");
+    }
+    assert!(fa.fetch("ctg4", 1, 4).is_err());
+
+    // Regions outside records are rejected rather than clamped.
+    for (start, stop) in [(0, 4), (6, 3), (1, 9), (1, u64::from(u32::MAX) + 1)] {
+        assert!(
+            matches!(
+                fa.fetch("ctg1", start, stop),
+                Err(GavisunkError::InvalidFetch { .. })
+            ),
+            "{start}-{stop}"
+        );
+    }
+    assert!(fa.fetch("ctg1", 5, 4).unwrap().sequence().is_empty());
+
+    let bases = fa.fetch_bytes("ctg2\tdesc", 3, 6).unwrap();
+    assert_eq!(&*bases, b"GGCC");
+    let kmers: Vec<(usize, &[u8])> = bases.kmers(3).collect();
+    assert_eq!(kmers, [(1, &b"GGC"[..]), (2, b"GCC")]);
+    assert_eq!(bases.kmers(0).count(), 0);
+}
+
+#[test]
+fn indexed_fasta_normalizes_headers() {
+    let dir = common::scratch_dir("messy_headers");
+    let path = common::write_fasta(dir.path(), "messy_headers", MESSY_FASTA);
+    let mut fa = Fasta::new(&path).unwrap();
+    assert_eq!(fa.lengths(), expected_lens());
+    assert_fetches(&mut fa);
+}
+
+#[test]
+fn in_memory_fasta_normalizes_headers() {
+    let dir = common::scratch_dir("messy_headers_in_memory");
+    let path = common::write_fasta(dir.path(), "messy_headers", MESSY_FASTA);
+    let mut fa = Fasta::new_in_memory(&path, None, &RunState::new()).unwrap();
+    assert_eq!(fa.lengths(), expected_lens());
+    assert_fetches(&mut fa);
+}
+
+#[test]
+fn existing_index_with_full_headers() {
+    // Some tools keep the full header in the index.
+    let dir = common::scratch_dir("messy_headers_fai");
+    let path = common::write_fasta(dir.path(), "messy_headers", ">ctg1 length=8\nACGTACGT\n");
+    std::fs::write(
+        path.with_extension("fa.fai"),
+        "ctg1 length=8\t8\t15\t8\t9\n",
+    )
+    .unwrap();
+    let mut fa = Fasta::new(&path).unwrap();
+    assert_eq!(fa.lengths(), HashMap::from([("ctg1".to_owned(), 8)]));
+    let rec = fa.fetch("ctg1", 3, 6).unwrap();
+    assert_eq!(rec.sequence().as_ref(), b"CGTA");
+}
+
+#[test]
+fn in_memory_fasta_skips_malformed_records() {
+    let dir = common::scratch_dir("malformed_records");
+    let path = common::write_fasta(
+        dir.path(),
+        "malformed_records",
+        ">ctg1\nACGTACGT\n>truncated\n>ctg2\nGGGGCCCC\n",
+    );
+    let state = RunState::new();
+    let fa = Fasta::new_in_memory(&path, None, &state).unwrap();
+    assert_eq!(
+        fa.lengths(),
+        HashMap::from([("ctg1".to_owned(), 8), ("ctg2".to_owned(), 8)])
+    );
+    assert!(malformed_records(&state)
+        .iter()
+        .any(|record| record.ends_with(":truncated")));
+    // Other runs don't share the records.
+    assert!(malformed_records(&RunState::new()).is_empty());
+
+    let state = RunState::new().with_max_malformed_records(0);
+    assert!(matches!(
+        Fasta::new_in_memory(&path, None, &state),
+        Err(GavisunkError::TooManyMalformedRecords { .. })
+    ));
+}
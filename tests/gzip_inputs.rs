@@ -0,0 +1,107 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use flate2::{write::GzEncoder, Compression as GzCompression};
+use rs_gavisunk::{
+    error::GavisunkError,
+    io::{Compression, Fasta, SeqFormat},
+    run_state::RunState,
+};
+
+mod common;
+
+const FASTA: &str = ">ctg1\nACGTACGT\n>ctg2\nGGGGCCCC\n";
+const FASTQ: &str = "@read1 runid=1\nACGTACGT\n+\nIIIIIIII\n@read2\nGGGGCCCC\n+\nIIIIIIII\n";
+
+/// Write a plain gzipped FASTA to `dir`.
+fn write_gzipped_fasta(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(format!("{name}.fa.gz"));
+    let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+    encoder.write_all(FASTA.as_bytes()).unwrap();
+    std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+    path
+}
+
+#[test]
+fn detects_plain_gzip() {
+    let dir = common::scratch_dir("detect");
+    let path = write_gzipped_fasta(dir.path(), "detect");
+    assert_eq!(Compression::detect(&path).unwrap(), Compression::Gzip);
+    assert!(matches!(
+        Fasta::new(&path),
+        Err(GavisunkError::PlainGzip(_))
+    ));
+    assert!(matches!(
+        Fasta::indexable_path(&path, false, &RunState::new()),
+        Err(GavisunkError::PlainGzip(_))
+    ));
+}
+
+#[test]
+fn plain_gzip_loaded_into_memory() {
+    let dir = common::scratch_dir("in_memory");
+    let path = write_gzipped_fasta(dir.path(), "in_memory");
+    let mut fa = Fasta::open(&path, None, false, &RunState::new()).unwrap();
+    assert_eq!(fa.lengths().get("ctg2"), Some(&8));
+    let rec = fa.fetch("ctg2", 3, 6).unwrap();
+    assert_eq!(rec.sequence().as_ref(), b"GGCC");
+}
+
+#[test]
+fn uncompressed_is_indexable() {
+    let dir = common::scratch_dir("uncompressed");
+    let path = common::write_fasta(dir.path(), "uncompressed", FASTA);
+    assert_eq!(Compression::detect(&path).unwrap(), Compression::None);
+    assert_eq!(
+        Fasta::indexable_path(&path, false, &RunState::new()).unwrap(),
+        path
+    );
+}
+
+#[test]
+fn gzipped_fastq_converted_to_fasta() {
+    let dir = common::scratch_dir("fastq");
+    let path = dir.path().join("reads.fastq.gz");
+    let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+    encoder.write_all(FASTQ.as_bytes()).unwrap();
+    std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+    assert_eq!(SeqFormat::detect(&path).unwrap(), SeqFormat::Fastq);
+
+    // Converted copies are written to the copy dir rather than the working directory.
+    let state = RunState::new().with_copy_dir(dir.path());
+    let converted = Fasta::indexable_path(&path, false, &state).unwrap();
+    assert_eq!(converted.parent(), Some(dir.path()));
+    let fname = converted.file_name().unwrap().to_string_lossy();
+    assert!(fname.starts_with("converted_reads.") && fname.ends_with(".fa.gz"));
+    assert_eq!(Compression::detect(&converted).unwrap(), Compression::Bgzip);
+    let mut fa = Fasta::open(&path, None, false, &state).unwrap();
+    assert_eq!(fa.lengths().get("read1"), Some(&8));
+    let rec = fa.fetch("read2", 3, 6).unwrap();
+    assert_eq!(rec.sequence().as_ref(), b"GGCC");
+
+    let fq = Fasta::new_in_memory(&path, None, &state).unwrap();
+    assert_eq!(fq.lengths().get("read1"), Some(&8));
+}
+
+#[test]
+fn same_name_fastqs_converted_separately() {
+    let dir = common::scratch_dir("fastq_same_name");
+    let (dir_a, dir_b) = (dir.path().join("a"), dir.path().join("b"));
+    std::fs::create_dir_all(&dir_a).unwrap();
+    std::fs::create_dir_all(&dir_b).unwrap();
+    let path_a = dir_a.join("reads.fastq");
+    let path_b = dir_b.join("reads.fq");
+    std::fs::write(&path_a, FASTQ).unwrap();
+    std::fs::write(&path_b, "@read3\nACGT\n+\nIIII\n").unwrap();
+
+    let state = RunState::new().with_copy_dir(dir.path());
+    let converted_a = Fasta::convert_to_fasta(&path_a, &state).unwrap();
+    let converted_b = Fasta::convert_to_fasta(&path_b, &state).unwrap();
+    assert_ne!(converted_a, converted_b);
+    // The second input doesn't reuse the first's reads.
+    let fa = Fasta::open(&path_b, None, false, &state).unwrap();
+    assert_eq!(fa.lengths().get("read3"), Some(&4));
+    assert_eq!(fa.lengths().get("read1"), None);
+}
@@ -0,0 +1,180 @@
+use polars::prelude::*;
+use rs_gavisunk::{
+    error::GavisunkError,
+    io::{
+        load_intermediate, load_tsv, load_tsv_with_schema, write_tsv_with_comments,
+        EmptyIntermediate, IntermediateParams, INTERMEDIATE_FORMAT_VERSION, READ_SUNKS_COLUMNS,
+    },
+    run_state::RunState,
+};
+
+mod common;
+
+#[test]
+fn loads_intermediate_with_schema() {
+    let dir = common::scratch_dir("read_sunks");
+    let path = dir.path().join("read_sunks.tsv");
+    // Numeric contig names would be inferred as integers.
+    let mut df = df!(
+        "read" => ["r1", "r2"],
+        "rpos" => [10u64, 20],
+        "ctg" => ["1", "2"],
+        "cpos" => [100u64, 200],
+        "group" => [100u64, 200],
+        "strand" => ["+", "-"],
+        "hits" => [1u64, 2],
+    )
+    .unwrap();
+    write_tsv_with_comments(&mut df, &path, "# rs-gavisunk test\n").unwrap();
+
+    let df = load_tsv_with_schema(&path, READ_SUNKS_COLUMNS, &["read", "ctg", "cpos"]).unwrap();
+    assert_eq!(df.get_column_names(), ["read", "ctg", "cpos"]);
+    assert_eq!(df.column("ctg").unwrap().dtype(), &DataType::String);
+    assert_eq!(df.column("cpos").unwrap().dtype(), &DataType::Int64);
+
+    // Unexpected columns fall back to inferred types.
+    let mut df = df!("read" => ["r1"], "ctg" => ["1"]).unwrap();
+    write_tsv_with_comments(&mut df, &path, "").unwrap();
+    let df = load_tsv_with_schema(&path, READ_SUNKS_COLUMNS, &[]).unwrap();
+    assert_eq!(df.column("ctg").unwrap().dtype(), &DataType::Int64);
+}
+
+#[test]
+fn rejects_stale_intermediate_format() {
+    let dir = common::scratch_dir("stale");
+    let path = dir.path().join("stale.tsv");
+    let params = IntermediateParams {
+        kmer_size: 20,
+        assembly: "asm.fa".into(),
+        reads: "reads.fa".into(),
+        rename_map: None,
+        rename_map_crc32: None,
+        anchor_paf: None,
+        flags: Default::default(),
+    };
+    params.write(&path, 0).unwrap();
+    params.check(&path).unwrap();
+
+    // Sidecar from before intermediates were versioned.
+    let sidecar = format!("{}.params.json", path.display());
+    std::fs::write(
+        &sidecar,
+        r#"{"kmer_size": 20, "assembly": "asm.fa", "reads": "reads.fa"}"#,
+    )
+    .unwrap();
+    let err = params.check(&path).unwrap_err();
+    assert!(matches!(
+        err,
+        GavisunkError::StaleIntermediate {
+            found: 0,
+            expected: INTERMEDIATE_FORMAT_VERSION,
+            ..
+        }
+    ));
+    assert!(err.to_string().contains("--force"));
+}
+
+#[test]
+fn rejects_intermediate_with_other_flags() {
+    let dir = common::scratch_dir("flags");
+    let path = dir.path().join("flags.tsv");
+    let params = IntermediateParams {
+        kmer_size: 20,
+        assembly: "asm.fa".into(),
+        reads: "reads.fa".into(),
+        rename_map: Some("renames.tsv".into()),
+        rename_map_crc32: Some("1a2b3c4d".to_owned()),
+        anchor_paf: None,
+        flags: Default::default(),
+    }
+    .with_flag("sunk_dedup", "first");
+    params.write(&path, 0).unwrap();
+    params.check(&path).unwrap();
+
+    let err = params
+        .clone()
+        .with_flag("sunk_dedup", "all")
+        .check(&path)
+        .unwrap_err();
+    assert!(matches!(err, GavisunkError::MismatchedParams { .. }));
+    assert!(err.to_string().contains("sunk_dedup"));
+    // Flags of later stages aren't in earlier intermediates.
+    let err = params
+        .clone()
+        .with_flag("prebin", Some(8))
+        .check(&path)
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("prebin (existing: unset, current: Some(8))"));
+
+    // Rename map edited in place.
+    let edited = IntermediateParams {
+        rename_map_crc32: Some("5e6f7a8b".to_owned()),
+        ..params
+    };
+    let err = edited.check(&path).unwrap_err();
+    assert!(err.to_string().contains("rename_map contents"));
+}
+
+#[test]
+fn recomputes_empty_intermediate() {
+    let dir = common::scratch_dir("empty");
+    let path = dir.path().join("empty.tsv");
+    let params = IntermediateParams {
+        kmer_size: 20,
+        assembly: "asm.fa".into(),
+        reads: "reads.fa".into(),
+        rename_map: None,
+        rename_map_crc32: None,
+        anchor_paf: None,
+        flags: Default::default(),
+    };
+    // Header only, ex. from a crashed run.
+    let mut df = df!("id" => ["c1:1", "c1:50"], "count" => [1i64, 2]).unwrap();
+    write_tsv_with_comments(&mut df.head(Some(0)), &path, "").unwrap();
+    params.write(&path, df.height()).unwrap();
+    let state = RunState::new();
+    assert!(load_intermediate(&path, &state, || load_tsv(&path))
+        .unwrap()
+        .is_none());
+
+    // Legitimately empty.
+    params.write(&path, 0).unwrap();
+    assert!(load_intermediate(&path, &state, || load_tsv(&path))
+        .unwrap()
+        .is_some());
+
+    write_tsv_with_comments(&mut df, &path, "").unwrap();
+    params.write(&path, df.height()).unwrap();
+    std::fs::write(&path, "").unwrap();
+    let state = RunState::new().with_empty_intermediate(EmptyIntermediate::Error);
+    let err = load_intermediate(&path, &state, || load_tsv(&path)).unwrap_err();
+    assert!(matches!(err, GavisunkError::InvalidIntermediate { .. }));
+
+    // Nothing to keep.
+    let state = RunState::new().with_empty_intermediate(EmptyIntermediate::Keep);
+    assert!(load_intermediate(&path, &state, || load_tsv(&path))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn handles_unreadable_intermediate() {
+    let dir = common::scratch_dir("unreadable");
+    let path = dir.path().join("unreadable.tsv");
+    // Partially written last line.
+    std::fs::write(&path, "id\tcount\nc1:1\t1\nc1:50\t").unwrap();
+    let unreadable = || -> Result<DataFrame, GavisunkError> {
+        Err(PolarsError::ComputeError("partial last line".into()).into())
+    };
+    for policy in [EmptyIntermediate::Redo, EmptyIntermediate::Keep] {
+        let state = RunState::new().with_empty_intermediate(policy);
+        assert!(load_intermediate(&path, &state, unreadable)
+            .unwrap()
+            .is_none());
+    }
+    let state = RunState::new().with_empty_intermediate(EmptyIntermediate::Error);
+    let err = load_intermediate(&path, &state, unreadable).unwrap_err();
+    assert!(matches!(err, GavisunkError::InvalidIntermediate { .. }));
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashSet, time::Duration};
+
+use polars::prelude::*;
+use proptest::prelude::*;
+use rs_gavisunk::{
+    assign_read_ctg::assign_read_to_ctg_w_ort,
+    error::GavisunkError,
+    map_kmers::map_sunks_to_seq,
+    orientation::{infer_orientation, Orientation},
+    run_state::RunState,
+    sunk_graph::{get_read_largest_sunk_graph_component, GraphParams, TIMED_OUT, TOO_MANY_SUNKS},
+};
+
+mod common;
+
+const KMER_SIZE: usize = 20;
+
+fn revcomp(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|nt| match nt {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            _ => b'A',
+        })
+        .collect()
+}
+
+/// SUNK anchors for a read placed at `offset` on a contig in the given orientation.
+///
+/// Contig positions are spaced by `spacing` with a few bp of jitter so anchors aren't perfectly colinear.
+/// Rows are ordered by read position, as in the output of mapping.
+fn read_anchors(n_sunks: usize, spacing: i64, offset: i64, is_fwd: bool) -> DataFrame {
+    let read_len = n_sunks as i64 * spacing;
+    let mut anchors: Vec<(i64, i64)> = (0..n_sunks as i64)
+        .map(|i| {
+            let dst = i * spacing;
+            let rpos = if is_fwd { dst + 1 } else { read_len - dst };
+            (rpos, offset + dst + (i % 5))
+        })
+        .collect();
+    anchors.sort();
+    let (rpos, cpos): (Vec<i64>, Vec<i64>) = anchors.into_iter().unzip();
+    DataFrame::new(vec![
+        Column::new("read".into(), vec!["read"; n_sunks]),
+        Column::new("rpos".into(), rpos),
+        Column::new("ctg".into(), vec!["ctg"; n_sunks]),
+        Column::new("cpos".into(), cpos.clone()),
+        Column::new("id".into(), cpos),
+        Column::new(
+            "strand".into(),
+            vec![if is_fwd { "+" } else { "-" }; n_sunks],
+        ),
+    ])
+    .unwrap()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn infers_orientation_from_anchors(
+        n_sunks in 2usize..200,
+        spacing in 100i64..1_000,
+        offset in 0i64..1_000_000,
+        is_fwd in any::<bool>(),
+    ) {
+        let df = read_anchors(n_sunks, spacing, offset, is_fwd);
+        let anchors: Vec<(i64, i64)> = df
+            .column("rpos")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .zip(df.column("cpos").unwrap().i64().unwrap().into_no_null_iter())
+            .collect();
+        let (ort, confidence) = infer_orientation(&anchors).unwrap();
+        prop_assert_eq!(ort, if is_fwd { Orientation::Forward } else { Orientation::Reverse });
+        prop_assert_eq!(confidence, 1.0);
+    }
+
+    #[test]
+    fn orientation_is_robust_to_spurious_anchors(
+        n_sunks in 20usize..200,
+        spacing in 100i64..1_000,
+        offset in 0i64..1_000_000,
+        is_fwd in any::<bool>(),
+        spurious in prop::collection::vec((1i64..200_000, 0i64..2_000_000), 0..5),
+    ) {
+        let df = read_anchors(n_sunks, spacing, offset, is_fwd);
+        let mut anchors: Vec<(i64, i64)> = df
+            .column("rpos")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_no_null_iter()
+            .zip(df.column("cpos").unwrap().i64().unwrap().into_no_null_iter())
+            .collect();
+        anchors.extend(spurious);
+        let (ort, confidence) = infer_orientation(&anchors).unwrap();
+        prop_assert_eq!(ort.is_rev(), !is_fwd);
+        prop_assert!(confidence >= 0.5);
+    }
+
+    #[test]
+    fn recovers_read_orientation(
+        n_sunks in 20usize..200,
+        spacing in 100i64..1_000,
+        offset in 0i64..1_000_000,
+        is_fwd in any::<bool>(),
+    ) {
+        let df = read_anchors(n_sunks, spacing, offset, is_fwd);
+        let df_assigned = assign_read_to_ctg_w_ort(&df, None, None, &RunState::new()).unwrap();
+        let ort = df_assigned.column("ort").unwrap().str().unwrap().first();
+        prop_assert_eq!(ort, Some(if is_fwd { "+" } else { "-" }));
+    }
+
+    #[test]
+    fn consistent_read_is_single_component(
+        n_sunks in 3usize..100,
+        spacing in 100i64..1_000,
+        offset in 0i64..1_000_000,
+        is_fwd in any::<bool>(),
+    ) {
+        // Graph stage expects SUNKs ordered by contig position.
+        let df = read_anchors(n_sunks, spacing, offset, is_fwd)
+            .sort(["cpos"], Default::default())
+            .unwrap();
+        let ids: HashSet<i64> = df.column("id").unwrap().i64().unwrap().into_no_null_iter().collect();
+        let component = get_read_largest_sunk_graph_component(&df, "read", &GraphParams::default())
+            .unwrap()
+            .unwrap();
+        prop_assert_eq!(component.is_rev, !is_fwd);
+        prop_assert_eq!(component.ids.into_iter().collect::<HashSet<i64>>(), ids);
+    }
+
+    #[test]
+    fn recovers_sunk_placement_in_read(
+        seq in prop::collection::vec(prop::sample::select(b"ACGT".to_vec()), 2_000..4_000),
+        read_st in 0usize..500,
+        read_len in 1_000usize..1_500,
+        is_fwd in any::<bool>(),
+    ) {
+        let read_fwd = &seq[read_st..read_st + read_len];
+        let read = if is_fwd { read_fwd.to_vec() } else { revcomp(read_fwd) };
+        let name = if is_fwd { "placement_fwd" } else { "placement_rev" };
+        let dir = common::scratch_dir(name);
+        let fa = common::write_fasta(dir.path(), name, common::fasta_record(name, &read));
+
+        // Contig SUNKs within the read, and their expected 1-based start and strand in the read.
+        let sunks: Vec<(String, usize)> = (read_st..read_st + read_len - KMER_SIZE)
+            .step_by(97)
+            .map(|cpos| {
+                let kmer = String::from_utf8(seq[cpos..cpos + KMER_SIZE].to_vec()).unwrap();
+                let rpos = if is_fwd {
+                    cpos - read_st + 1
+                } else {
+                    read_len - (cpos - read_st) - KMER_SIZE + 1
+                };
+                (kmer, rpos)
+            })
+            .collect();
+        let kmers: Vec<&str> = sunks.iter().map(|(kmer, _)| kmer.as_str()).collect();
+        let hits = map_sunks_to_seq(&kmers, &fa, name, 1, read_len as u32).unwrap();
+
+        for (kmer, exp_rpos) in sunks.iter() {
+            prop_assert!(
+                hits.iter().any(|(_, hit, rpos, hit_is_fwd)| {
+                    *hit == kmer.as_str() && *hit_is_fwd == is_fwd && *rpos == *exp_rpos
+                }),
+                "{kmer} not found at {exp_rpos} in {} read", if is_fwd { "fwd" } else { "rev" }
+            );
+        }
+    }
+}
+
+#[test]
+fn skips_read_over_budget() {
+    let df = read_anchors(10, 100, 0, true);
+    let status =
+        |params: GraphParams| match get_read_largest_sunk_graph_component(&df, "read", &params) {
+            Err(GavisunkError::ReadBudgetExceeded { status, .. }) => Some(status),
+            res => {
+                res.unwrap();
+                None
+            }
+        };
+    assert_eq!(
+        status(GraphParams {
+            max_read_sunks: Some(5),
+            ..Default::default()
+        }),
+        Some(TOO_MANY_SUNKS)
+    );
+    assert_eq!(
+        status(GraphParams {
+            read_timeout: Some(Duration::ZERO),
+            ..Default::default()
+        }),
+        Some(TIMED_OUT)
+    );
+    assert_eq!(status(GraphParams::default()), None);
+}
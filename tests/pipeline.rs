@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use rs_gavisunk::{
+    error::GavisunkError,
+    filter_audit::filter_audit,
+    pipeline::{Config, Pipeline},
+    self_test::{contig_lengths, expectations, write_inputs},
+};
+
+mod common;
+
+#[test]
+fn runs_pipeline_on_embedded_dataset() {
+    let dir = common::scratch_dir("pipeline");
+    let (path_asm, path_reads) = (dir.path().join("asm.fa"), dir.path().join("reads.fa"));
+    write_inputs(&path_asm, &path_reads).unwrap();
+
+    let pipeline = Pipeline::new(Config::new(&path_asm, &path_reads)).unwrap();
+    let output = pipeline.run().unwrap();
+    // Filters record their counts to the pipeline's own state.
+    assert!(filter_audit(pipeline.state()).unwrap().height() > 0);
+    let df = &output.df_supported;
+    let mut supported: HashMap<&str, i64> = HashMap::new();
+    for ((ctg, st), end) in df
+        .column("ctg")
+        .unwrap()
+        .str()
+        .unwrap()
+        .iter()
+        .zip(df.column("st").unwrap().i64().unwrap().iter())
+        .zip(df.column("end").unwrap().i64().unwrap().iter())
+    {
+        *supported.entry(ctg.unwrap()).or_default() += end.unwrap() - st.unwrap();
+    }
+    let lens = contig_lengths().unwrap();
+    for expectation in expectations().unwrap() {
+        let fraction = supported
+            .get(expectation.ctg.as_str())
+            .copied()
+            .unwrap_or(0) as f64
+            / lens[&expectation.ctg] as f64;
+        assert!(
+            fraction >= expectation.min_supported_fraction,
+            "{} has {fraction:.3} supported",
+            expectation.ctg
+        );
+    }
+}
+
+#[test]
+fn rejects_invalid_config() {
+    assert!(matches!(
+        Pipeline::new(Config::new("asm.fa", "reads.fa").with_kmer_size(0)),
+        Err(GavisunkError::InvalidKmerSize(0))
+    ));
+    assert!(matches!(
+        Pipeline::new(Config::new("asm.fa", "reads.fa").with_kmer_size(33)),
+        Err(GavisunkError::InvalidKmerSize(33))
+    ));
+    assert!(matches!(
+        Pipeline::new(Config::new("asm.fa", "reads.fa").with_bandwidth((0.75, 0.25))),
+        Err(GavisunkError::InvalidBandwidth(_))
+    ));
+}